@@ -188,7 +188,8 @@ pub fn test_desc_satisfy(
                 .leaves()
                 .flat_map(|leaf| {
                     let leaf_hash = TapLeafHash::from_script(&leaf.compute_script(), LeafVersion::TapScript);
-                    leaf.miniscript().iter_pk().filter_map(move |pk| {
+                    let pks = leaf.miniscript().into_iter().flat_map(|ms| ms.iter_pk());
+                    pks.filter_map(move |pk| {
                         let i = x_only_pks.iter().position(|&x| x.to_public_key() == pk);
                         i.map(|idx| (xonly_keypairs[idx], leaf_hash))
                     })
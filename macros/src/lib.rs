@@ -0,0 +1,586 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Miniscript Macros
+//!
+//! Procedural macros which parse and check Miniscripts and descriptors at compile
+//! time, so that a malformed or ill-typed script fails the build rather than
+//! surfacing as an error at runtime.
+//!
+//! Only the checking happens at compile time. The macros expand to ordinary
+//! expressions which build the value at runtime through the typed constructors,
+//! which allocate (a Miniscript node is held in an `Arc`) and redo the type
+//! checks. The result therefore cannot be used to initialize a `const` or a
+//! `static`; use a lazily-initialized static for that instead. A `const`
+//! constructible form would need `const fn` constructors and a non-allocating
+//! representation of the AST in `miniscript` itself.
+//!
+//! These live in a separate crate because a procedural macro crate cannot be
+//! re-exported by a crate it depends on. The generated code refers to
+//! `::miniscript`, so users must depend on `miniscript` as well.
+//!
+
+// Coding conventions
+#![deny(non_upper_case_globals)]
+#![deny(non_camel_case_types)]
+#![deny(non_snake_case)]
+#![deny(unused_mut)]
+#![deny(dead_code)]
+#![deny(unused_imports)]
+#![deny(missing_docs)]
+#![deny(unsafe_code)]
+
+extern crate proc_macro;
+
+use core::fmt::Write as _;
+use core::str::FromStr;
+
+use miniscript::bitcoin::Script;
+use miniscript::descriptor::{ShInner, TapTree, WshInner};
+use miniscript::{
+    BareCtx, Descriptor, DescriptorPublicKey, Legacy, Miniscript, MiniscriptKey, ScriptContext,
+    Segwitv0, Tap, Terminal,
+};
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+
+/// Parses and typechecks a Miniscript at compile time.
+///
+/// The first argument is an optional script context, one of `Segwitv0` (the
+/// default), `Tap`, `Legacy` or `BareCtx`; the second is the Miniscript as a
+/// string literal. Keys are placeholders, and the macro expands to an expression
+/// of type `Miniscript<String, Ctx>` built with the typed constructors on
+/// `Miniscript`, without any string parsing at runtime. The keys can then be
+/// replaced with `Miniscript::translate_pk`.
+///
+/// The expression is evaluated at runtime, so it is not usable in a `const`
+/// context; see the [crate documentation](crate).
+///
+/// The script must pass all the checks done by `Miniscript::from_str`.
+///
+/// ```
+/// use core::str::FromStr;
+///
+/// use miniscript::{Miniscript, Segwitv0, Tap};
+/// use miniscript_macros::miniscript;
+///
+/// let ms = miniscript!("and_v(v:pk(A),older(144))");
+/// assert_eq!(ms, Miniscript::<String, Segwitv0>::from_str("and_v(v:pk(A),older(144))").unwrap());
+///
+/// let ms = miniscript!(Tap, "multi_a(1,A,B)");
+/// assert_eq!(ms, Miniscript::<String, Tap>::from_str("multi_a(1,A,B)").unwrap());
+/// ```
+///
+/// Invalid scripts fail to compile:
+///
+/// ```compile_fail
+/// // and_v requires its first child to be of type V
+/// let ms = miniscript_macros::miniscript!("and_v(pk(A),older(144))");
+/// ```
+///
+/// ```compile_fail
+/// // multi_a is only available in Taproot
+/// let ms = miniscript_macros::miniscript!(Segwitv0, "multi_a(1,A,B)");
+/// ```
+#[proc_macro]
+pub fn miniscript(input: TokenStream) -> TokenStream {
+    match expand_miniscript(input) {
+        Ok(ts) => ts,
+        Err((msg, span)) => compile_error(&msg, span),
+    }
+}
+
+/// Parses and checks a descriptor at compile time.
+///
+/// The descriptor may be given either directly as tokens or as a string literal.
+/// Keys must be valid `DescriptorPublicKey`s. Since `'` and `/*` mean something
+/// else to the Rust tokenizer, descriptors using hardened derivation steps or
+/// wildcards must be written as a string literal, as must hex keys which are not
+/// valid Rust tokens. Any checksum is verified, and the descriptor must
+/// pass `Descriptor::sanity_check`, which covers the context rules and resource
+/// limits of any embedded Miniscript.
+///
+/// The macro expands to an expression of type `Descriptor<DescriptorPublicKey>`
+/// built with the typed constructors on `Descriptor` and `Miniscript`, like
+/// [`miniscript!`]. Only the keys and hashes, which were checked at compile
+/// time, are converted from their string forms at runtime.
+///
+/// ```
+/// use core::str::FromStr;
+///
+/// use miniscript::{Descriptor, DescriptorPublicKey};
+/// use miniscript_macros::descriptor;
+///
+/// let desc = descriptor!(wsh(multi(2,
+///     xpub6ERApfZwUNrhLCkDtcHTcxd75RbzS1ed54G1LkBUHQVHQKqhMkhgbmJbZRkrgZw4koxb5JaHWkY4ALHY2grBGRjaDMzQLcgJvLJuZZvRcEL/0/0,
+///     xpub68NZiKmJWnxxS6aaHmn81bvJeTESw724CRDs6HbuccFQN9Ku14VQrADWgqbhhTHBaohPX4CjNLf9fq9MYo6oDaPPLPxSb7gwQN3ih19Zm4Y/0/0,
+///     xpub69H7F5d8KSRgmmdJg2KhpAK8SR3DjMwAdkxj3ZuxV27CprR9LgpeyGmXUbC6wb7ERfvrnKZjXoUmmDznezpbZb7ap6r1D3tgFxHmwMkQTPH/0/0,
+/// )));
+/// let runtime = Descriptor::<DescriptorPublicKey>::from_str(&desc.to_string()).unwrap();
+/// assert_eq!(desc, runtime);
+///
+/// let desc = descriptor!("wpkh(02e6642fd69bd211f93f7f1f36ca51a26a5290eb2dd1b0d8279a87bb0d480c8443)#mnacer79");
+/// assert!(matches!(desc, Descriptor::Wpkh(_)));
+///
+/// let desc = descriptor!("tr([d34db33f/86'/0'/0']xpub6ERApfZwUNrhLCkDtcHTcxd75RbzS1ed54G1LkBUHQVHQKqhMkhgbmJbZRkrgZw4koxb5JaHWkY4ALHY2grBGRjaDMzQLcgJvLJuZZvRcEL/0/*)");
+/// assert!(desc.has_wildcard());
+/// ```
+///
+/// Invalid descriptors fail to compile:
+///
+/// ```compile_fail
+/// // Not a key
+/// let desc = miniscript_macros::descriptor!(wsh(multi(2, A, B, C)));
+/// ```
+///
+/// ```compile_fail
+/// // Bad checksum
+/// let desc = miniscript_macros::descriptor!("wpkh(02e6642fd69bd211f93f7f1f36ca51a26a5290eb2dd1b0d8279a87bb0d480c8443)#mnacer78");
+/// ```
+#[proc_macro]
+pub fn descriptor(input: TokenStream) -> TokenStream {
+    match expand_descriptor(input) {
+        Ok(ts) => ts,
+        Err((msg, span)) => compile_error(&msg, span),
+    }
+}
+
+type Error = (String, Span);
+
+/// Unwraps the result of a constructor, whose arguments were checked at compile time.
+const EXPECT: &str = ".expect(\"checked at compile time\")";
+/// Converts a `&str` into a `String` or a `Miniscript` into an `Arc`.
+const INTO: &str = "::core::convert::Into::into";
+
+/// Removes the invisible groups which `macro_rules!` wraps around fragments.
+fn flatten(input: TokenStream) -> Vec<TokenTree> {
+    let mut ret = vec![];
+    for tt in input {
+        match tt {
+            TokenTree::Group(ref g) if g.delimiter() == Delimiter::None => {
+                ret.extend(flatten(g.stream()))
+            }
+            tt => ret.push(tt),
+        }
+    }
+    ret
+}
+
+/// Extracts the contents of a plain or raw string literal.
+fn string_literal(lit: &Literal) -> Result<String, Error> {
+    let repr = lit.to_string();
+    let err = || ("expected a string literal".to_owned(), lit.span());
+    let (s, raw) = match repr.strip_prefix('r') {
+        Some(s) => (s.trim_matches('#'), true),
+        None => (&repr[..], false),
+    };
+    let s = s
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .ok_or_else(err)?;
+    // Miniscripts never need escapes, so don't bother interpreting them.
+    if !raw && s.contains('\\') {
+        return Err(("escape sequences are not supported".to_owned(), lit.span()));
+    }
+    Ok(s.to_owned())
+}
+
+fn expand_miniscript(input: TokenStream) -> Result<TokenStream, Error> {
+    let tokens = flatten(input);
+    let (ctx, lit) = match tokens.as_slice() {
+        [TokenTree::Literal(lit)] | [TokenTree::Literal(lit), TokenTree::Punct(_)] => {
+            ("Segwitv0".to_owned(), lit)
+        }
+        [TokenTree::Ident(ctx), TokenTree::Punct(p), TokenTree::Literal(lit)]
+        | [TokenTree::Ident(ctx), TokenTree::Punct(p), TokenTree::Literal(lit), TokenTree::Punct(_)]
+            if p.as_char() == ',' =>
+        {
+            (ctx.to_string(), lit)
+        }
+        _ => {
+            return Err((
+                "expected an optional context followed by a string literal".to_owned(),
+                Span::call_site(),
+            ))
+        }
+    };
+    let s = string_literal(lit)?;
+
+    let code = match &ctx[..] {
+        "Segwitv0" => miniscript_code::<Segwitv0>(&s, &ctx),
+        "Tap" => miniscript_code::<Tap>(&s, &ctx),
+        "Legacy" => miniscript_code::<Legacy>(&s, &ctx),
+        "BareCtx" => miniscript_code::<BareCtx>(&s, &ctx),
+        _ => {
+            let span = match tokens[0] {
+                TokenTree::Ident(ref ident) => ident.span(),
+                _ => Span::call_site(),
+            };
+            return Err((
+                format!("unknown context {}; expected Segwitv0, Tap, Legacy or BareCtx", ctx),
+                span,
+            ));
+        }
+    };
+    let code = code.map_err(|msg| (msg, lit.span()))?;
+    Ok(TokenStream::from_str(&code).expect("generated code is valid Rust"))
+}
+
+/// Removes a trailing comma, which Rust allows after the last argument of a call.
+fn strip_trailing_comma(tokens: &[TokenTree]) -> &[TokenTree] {
+    match tokens.split_last() {
+        Some((TokenTree::Punct(p), rest)) if p.as_char() == ',' => rest,
+        _ => tokens,
+    }
+}
+
+/// Writes tokens back out as a string, without any whitespace or trailing commas.
+fn write_tokens(tokens: &[TokenTree], out: &mut String) {
+    for tt in strip_trailing_comma(tokens) {
+        match tt {
+            TokenTree::Group(g) => {
+                let (open, close) = match g.delimiter() {
+                    Delimiter::Parenthesis => ("(", ")"),
+                    Delimiter::Brace => ("{", "}"),
+                    Delimiter::Bracket => ("[", "]"),
+                    Delimiter::None => ("", ""),
+                };
+                out.push_str(open);
+                write_tokens(&flatten(g.stream()), out);
+                out.push_str(close);
+            }
+            TokenTree::Ident(ident) => out.push_str(&ident.to_string()),
+            TokenTree::Punct(punct) => out.push(punct.as_char()),
+            TokenTree::Literal(lit) => out.push_str(&lit.to_string()),
+        }
+    }
+}
+
+fn expand_descriptor(input: TokenStream) -> Result<TokenStream, Error> {
+    let tokens = flatten(input);
+    let (s, span) = match tokens.as_slice() {
+        [] => return Err(("expected a descriptor".to_owned(), Span::call_site())),
+        [TokenTree::Literal(lit)] | [TokenTree::Literal(lit), TokenTree::Punct(_)]
+            if lit.to_string().ends_with('"') =>
+        {
+            (string_literal(lit)?, lit.span())
+        }
+        _ => {
+            let mut s = String::new();
+            write_tokens(&tokens, &mut s);
+            (s, Span::call_site())
+        }
+    };
+
+    let desc = Descriptor::<DescriptorPublicKey>::from_str(&s)
+        .map_err(|e| (format!("invalid descriptor: {}", e), span))?;
+    desc.sanity_check()
+        .map_err(|e| (format!("invalid descriptor: {}", e), span))?;
+
+    let mut code = String::new();
+    write_descriptor(&desc, &mut code);
+    Ok(TokenStream::from_str(&code).expect("generated code is valid Rust"))
+}
+
+/// Writes an expression constructing `desc` using the typed constructors on
+/// `Descriptor`.
+fn write_descriptor(desc: &Descriptor<DescriptorPublicKey>, out: &mut String) {
+    const TY: &str = "::miniscript::Descriptor::<::miniscript::DescriptorPublicKey>";
+
+    match *desc {
+        Descriptor::Bare(ref bare) => {
+            let _ = write!(out, "{}::new_bare(", TY);
+            write_descriptor_ms(bare.as_inner(), "BareCtx", out);
+        }
+        Descriptor::Pkh(ref pkh) => {
+            let _ = write!(out, "{}::new_pkh(", TY);
+            parse_value(&pkh.as_inner().to_string(), out);
+        }
+        Descriptor::Wpkh(ref wpkh) => {
+            let _ = write!(out, "{}::new_wpkh(", TY);
+            parse_value(&wpkh.as_inner().to_string(), out);
+        }
+        Descriptor::Sh(ref sh) => match *sh.as_inner() {
+            ShInner::Wsh(ref wsh) => match *wsh.as_inner() {
+                WshInner::SortedMulti(ref smv) => {
+                    let _ = write!(out, "{}::new_sh_wsh_sortedmulti(", TY);
+                    write_sortedmulti(smv.k(), smv.pks(), out);
+                }
+                WshInner::Ms(ref ms) => {
+                    let _ = write!(out, "{}::new_sh_wsh(", TY);
+                    write_descriptor_ms(ms, "Segwitv0", out);
+                }
+            },
+            ShInner::Wpkh(ref wpkh) => {
+                let _ = write!(out, "{}::new_sh_wpkh(", TY);
+                parse_value(&wpkh.as_inner().to_string(), out);
+            }
+            ShInner::SortedMulti(ref smv) => {
+                let _ = write!(out, "{}::new_sh_sortedmulti(", TY);
+                write_sortedmulti(smv.k(), smv.pks(), out);
+            }
+            ShInner::Ms(ref ms) => {
+                let _ = write!(out, "{}::new_sh(", TY);
+                write_descriptor_ms(ms, "Legacy", out);
+            }
+        },
+        Descriptor::Wsh(ref wsh) => match *wsh.as_inner() {
+            WshInner::SortedMulti(ref smv) => {
+                let _ = write!(out, "{}::new_wsh_sortedmulti(", TY);
+                write_sortedmulti(smv.k(), smv.pks(), out);
+            }
+            WshInner::Ms(ref ms) => {
+                let _ = write!(out, "{}::new_wsh(", TY);
+                write_descriptor_ms(ms, "Segwitv0", out);
+            }
+        },
+        Descriptor::Tr(ref tr) => {
+            let _ = write!(out, "{}::new_tr(", TY);
+            parse_value(&tr.internal_key().to_string(), out);
+            match *tr.tap_tree() {
+                Some(ref tree) => {
+                    out.push_str(", ::core::option::Option::Some(");
+                    write_tap_tree(tree, out);
+                    out.push(')');
+                }
+                None => out.push_str(", ::core::option::Option::None"),
+            }
+        }
+        // Unlike the other descriptors, these are constructed infallibly.
+        Descriptor::Addr(ref addr) => {
+            let _ = write!(out, "{}::Addr(::miniscript::descriptor::Addr::new(", TY);
+            parse_value(&addr.as_inner().assume_checked_ref().to_string(), out);
+            out.push_str("))");
+            return;
+        }
+        Descriptor::Raw(ref raw) => {
+            let _ = write!(out, "{}::Raw(::miniscript::descriptor::Raw::new(", TY);
+            write_script(raw.as_inner(), out);
+            out.push_str("))");
+            return;
+        }
+    }
+    let _ = write!(out, "){}", EXPECT);
+}
+
+/// Writes an expression constructing a Taproot tree of a descriptor.
+fn write_tap_tree(tree: &TapTree<DescriptorPublicKey>, out: &mut String) {
+    const TY: &str = "::miniscript::descriptor::TapTree::<::miniscript::DescriptorPublicKey>";
+
+    match *tree {
+        TapTree::Tree { ref left, ref right, .. } => {
+            let _ = write!(out, "{}::combine(", TY);
+            write_tap_tree(left, out);
+            out.push_str(", ");
+            write_tap_tree(right, out);
+            out.push(')');
+        }
+        TapTree::Leaf(ref ms) => {
+            let _ = write!(out, "{}::Leaf({}(", TY, INTO);
+            write_descriptor_ms(ms, "Tap", out);
+            out.push_str("))");
+        }
+        TapTree::RawLeaf(ref leaf) => {
+            let _ = write!(out, "{}::RawLeaf(::miniscript::descriptor::RawTapLeaf::new(", TY);
+            write_script(leaf.script(), out);
+            let _ = write!(
+                out,
+                ", ::miniscript::bitcoin::taproot::LeafVersion::from_consensus({}){}))",
+                leaf.version().to_consensus(),
+                EXPECT,
+            );
+        }
+    }
+}
+
+/// Writes the arguments of the constructors of `sortedmulti` descriptors.
+fn write_sortedmulti(k: usize, pks: &[DescriptorPublicKey], out: &mut String) {
+    let _ = write!(out, "{}, ::miniscript::__private::Vec::from([", k);
+    for pk in pks {
+        parse_value(&pk.to_string(), out);
+        out.push_str(", ");
+    }
+    out.push_str("])");
+}
+
+/// Writes an expression constructing a Miniscript of a descriptor, in the
+/// context named `ctx`.
+fn write_descriptor_ms<Ctx: ScriptContext>(
+    ms: &Miniscript<DescriptorPublicKey, Ctx>,
+    ctx: &str,
+    out: &mut String,
+) {
+    let ty = format!(
+        "::miniscript::Miniscript::<::miniscript::DescriptorPublicKey, ::miniscript::{}>",
+        ctx
+    );
+    write_constructor(ms, &ty, parse_value, out);
+}
+
+/// Writes an expression constructing `script`.
+fn write_script(script: &Script, out: &mut String) {
+    out.push_str("::miniscript::bitcoin::ScriptBuf::from_bytes(");
+    out.push_str("::miniscript::__private::Vec::from([");
+    for byte in script.as_bytes() {
+        let _ = write!(out, "{}u8, ", byte);
+    }
+    out.push_str("]))");
+}
+
+/// Writes an expression converting `s` into a key or hash of a Miniscript with
+/// string keys.
+fn string_value(s: &str, out: &mut String) {
+    let _ = write!(out, "{}({:?})", INTO, s);
+}
+
+/// Writes an expression parsing `s`, which was checked at compile time, into a
+/// key or hash of a descriptor. The type is inferred from the constructor.
+fn parse_value(s: &str, out: &mut String) {
+    let _ = write!(out, "::core::str::FromStr::from_str({:?}){}", s, EXPECT);
+}
+
+/// Parses a Miniscript and returns the Rust code which constructs it.
+fn miniscript_code<Ctx: ScriptContext>(s: &str, ctx: &str) -> Result<String, String> {
+    let ms = Miniscript::<String, Ctx>::from_str(s)
+        .map_err(|e| format!("invalid {} miniscript: {}", ctx, e))?;
+    let ty = format!(
+        "::miniscript::Miniscript::<::miniscript::__private::String, ::miniscript::{}>",
+        ctx
+    );
+    let mut ret = String::new();
+    write_constructor(&ms, &ty, string_value, &mut ret);
+    Ok(ret)
+}
+
+/// Writes an expression constructing `ms` using the typed constructors on
+/// `Miniscript`, where `ty` is the full path of the Miniscript type and `value`
+/// writes an expression constructing a key or hash from its string form.
+fn write_constructor<Pk: MiniscriptKey, Ctx: ScriptContext>(
+    ms: &Miniscript<Pk, Ctx>,
+    ty: &str,
+    value: fn(&str, &mut String),
+    out: &mut String,
+) {
+    let children = |name: &str, subs: &[&Miniscript<Pk, Ctx>], out: &mut String| {
+        let _ = write!(out, "{}::{}(", ty, name);
+        for (i, sub) in subs.iter().enumerate() {
+            if i > 0 {
+                out.push_str(", ");
+            }
+            let _ = write!(out, "{}(", INTO);
+            write_constructor(sub, ty, value, out);
+            out.push(')');
+        }
+        let _ = write!(out, "){}", EXPECT);
+    };
+    let leaf = |name: &str, s: String, out: &mut String| {
+        let _ = write!(out, "{}::{}(", ty, name);
+        value(&s, out);
+        out.push(')');
+    };
+
+    match ms.node {
+        Terminal::True => {
+            let _ = write!(out, "{}::TRUE", ty);
+        }
+        Terminal::False => {
+            let _ = write!(out, "{}::FALSE", ty);
+        }
+        Terminal::PkK(ref pk) => leaf("pk_k", pk.to_string(), out),
+        Terminal::PkH(ref pk) => leaf("pk_h", pk.to_string(), out),
+        Terminal::RawPkH(ref hash) => {
+            // Not produced by `from_str`, but straightforward to support.
+            let _ = write!(
+                out,
+                "{}::expr_raw_pkh(<::miniscript::bitcoin::hashes::hash160::Hash as ::core::str::FromStr>::from_str({:?}){})",
+                ty,
+                hash.to_string(),
+                EXPECT,
+            );
+        }
+        Terminal::After(t) => {
+            let _ = write!(
+                out,
+                "{}::after(::miniscript::AbsLockTime::from_consensus({}){})",
+                ty,
+                t.to_consensus_u32(),
+                EXPECT,
+            );
+        }
+        Terminal::Older(t) => {
+            let _ = write!(
+                out,
+                "{}::older(::miniscript::RelLockTime::from_consensus({}){})",
+                ty,
+                t.to_consensus_u32(),
+                EXPECT,
+            );
+        }
+        Terminal::Sha256(ref h) => leaf("sha256", h.to_string(), out),
+        Terminal::Hash256(ref h) => leaf("hash256", h.to_string(), out),
+        Terminal::Ripemd160(ref h) => leaf("ripemd160", h.to_string(), out),
+        Terminal::Hash160(ref h) => leaf("hash160", h.to_string(), out),
+        Terminal::Alt(ref sub) => children("alt", &[sub], out),
+        Terminal::Swap(ref sub) => children("swap", &[sub], out),
+        Terminal::Check(ref sub) => children("check", &[sub], out),
+        Terminal::DupIf(ref sub) => children("dupif", &[sub], out),
+        Terminal::Verify(ref sub) => children("verify", &[sub], out),
+        Terminal::NonZero(ref sub) => children("nonzero", &[sub], out),
+        Terminal::ZeroNotEqual(ref sub) => children("zero_not_equal", &[sub], out),
+        Terminal::AndV(ref l, ref r) => children("and_v", &[l, r], out),
+        Terminal::AndB(ref l, ref r) => children("and_b", &[l, r], out),
+        Terminal::AndOr(ref a, ref b, ref c) => children("andor", &[a, b, c], out),
+        Terminal::OrB(ref l, ref r) => children("or_b", &[l, r], out),
+        Terminal::OrD(ref l, ref r) => children("or_d", &[l, r], out),
+        Terminal::OrC(ref l, ref r) => children("or_c", &[l, r], out),
+        Terminal::OrI(ref l, ref r) => children("or_i", &[l, r], out),
+        Terminal::Thresh(ref thresh) => {
+            let _ =
+                write!(out, "{}::thresh({}, ::miniscript::__private::Vec::from([", ty, thresh.k());
+            for sub in thresh.iter() {
+                let _ = write!(out, "{}(", INTO);
+                write_constructor(sub, ty, value, out);
+                out.push_str("), ");
+            }
+            let _ = write!(out, "])){}", EXPECT);
+        }
+        Terminal::Multi(ref thresh) => {
+            write_multi("multi", thresh.k(), thresh.iter(), ty, value, out)
+        }
+        Terminal::MultiA(ref thresh) => {
+            write_multi("multi_a", thresh.k(), thresh.iter(), ty, value, out)
+        }
+        Terminal::Ext(e) => match e {},
+    }
+}
+
+fn write_multi<'a, Pk: MiniscriptKey + 'a, I: Iterator<Item = &'a Pk>>(
+    name: &str,
+    k: usize,
+    keys: I,
+    ty: &str,
+    value: fn(&str, &mut String),
+    out: &mut String,
+) {
+    let _ = write!(out, "{}::{}({}, ::miniscript::__private::Vec::from([", ty, name, k);
+    for key in keys {
+        value(&key.to_string(), out);
+        out.push_str(", ");
+    }
+    let _ = write!(out, "])){}", EXPECT);
+}
+
+/// Expands to `compile_error!(msg)`, reported at `span`.
+fn compile_error(msg: &str, span: Span) -> TokenStream {
+    let mut lit = Literal::string(msg);
+    lit.set_span(span);
+    let mut bang = Punct::new('!', Spacing::Alone);
+    bang.set_span(span);
+    let mut body = Group::new(Delimiter::Brace, TokenTree::Literal(lit).into());
+    body.set_span(span);
+    [
+        TokenTree::Ident(Ident::new("compile_error", span)),
+        TokenTree::Punct(bang),
+        TokenTree::Group(body),
+    ]
+    .into_iter()
+    .collect()
+}
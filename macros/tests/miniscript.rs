@@ -0,0 +1,32 @@
+// SPDX-License-Identifier: CC0-1.0
+
+use core::str::FromStr;
+
+use miniscript::{BareCtx, Legacy, Miniscript, ScriptContext, Segwitv0, Tap};
+use miniscript_macros::miniscript;
+
+fn parse<Ctx: ScriptContext>(s: &str) -> Miniscript<String, Ctx> {
+    Miniscript::from_str(s).unwrap()
+}
+
+#[test]
+fn matches_runtime_parser() {
+    let ms = miniscript!("and_v(v:pk(A),or_d(pk(B),older(144)))");
+    assert_eq!(ms, parse::<Segwitv0>("and_v(v:pk(A),or_d(pk(B),older(144)))"));
+    assert_eq!(ms.ty, parse::<Segwitv0>("and_v(v:pk(A),or_d(pk(B),older(144)))").ty);
+
+    let s = "and_v(v:thresh(2,pk(A),s:pk(B),a:pkh(C)),and_v(v:sha256(2222222222222222222222222222222222222222222222222222222222222222),after(1000)))";
+    assert_eq!(miniscript!(Segwitv0, "and_v(v:thresh(2,pk(A),s:pk(B),a:pkh(C)),and_v(v:sha256(2222222222222222222222222222222222222222222222222222222222222222),after(1000)))"), parse::<Segwitv0>(s));
+
+    let s = "andor(multi(1,A,B),pkh(C),or_i(and_v(v:hash160(1111111111111111111111111111111111111111),pk(D)),and_v(v:ripemd160(3333333333333333333333333333333333333333),pk(E))))";
+    assert_eq!(miniscript!(Legacy, "andor(multi(1,A,B),pkh(C),or_i(and_v(v:hash160(1111111111111111111111111111111111111111),pk(D)),and_v(v:ripemd160(3333333333333333333333333333333333333333),pk(E))))"), parse::<Legacy>(s));
+
+    let s =
+        "and_b(pk(A),a:hash256(4444444444444444444444444444444444444444444444444444444444444444))";
+    assert_eq!(miniscript!(BareCtx, "and_b(pk(A),a:hash256(4444444444444444444444444444444444444444444444444444444444444444))"), parse::<BareCtx>(s));
+
+    assert_eq!(
+        miniscript!(Tap, r"and_v(v:multi_a(2,A,B,C),older(10))"),
+        parse::<Tap>("and_v(v:multi_a(2,A,B,C),older(10))"),
+    );
+}
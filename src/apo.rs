@@ -0,0 +1,263 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! ANYPREVOUT Keys
+//!
+//! Support for the BIP 118 public key types in Tapscript, which allow
+//! signatures that do not commit to the outpoint being spent (`SIGHASH_ANYPREVOUT`)
+//! and optionally not to the script either (`SIGHASH_ANYPREVOUTANYSCRIPT`).
+//!
+//! A BIP 118 key is written in descriptors and Miniscript as the underlying
+//! key with an `apo:` prefix, and is encoded in the script as the byte `0x01`
+//! followed by the x-only key. As a special case, the Taproot internal key may
+//! be written with an `apo_internal:` prefix, and is then encoded as the single
+//! byte `0x01`. It is the responsibility of the user to ensure that such a key
+//! is the internal key of the output.
+//!
+//! Both forms are only allowed in the `pk` and `multi_a` fragments under the
+//! [`crate::Tap`] context. Scripts containing them can be encoded and satisfied,
+//! but not parsed back from Script, and the [`crate::interpreter`] does not
+//! support them.
+//!
+
+use core::fmt;
+use core::str::FromStr;
+
+use bitcoin::secp256k1::schnorr;
+
+use crate::prelude::*;
+use crate::{MiniscriptKey, ToPublicKey};
+
+/// The script encoding of a BIP 118 key.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum ApoKind {
+    /// The byte `0x01` followed by the 32-byte x-only key.
+    Explicit,
+    /// The single byte `0x01`, standing for the Taproot internal key.
+    Internal,
+}
+
+impl ApoKind {
+    /// The length of the key in the script, including the push opcode.
+    pub fn pk_len(self) -> usize {
+        match self {
+            ApoKind::Explicit => 34,
+            ApoKind::Internal => 1,
+        }
+    }
+}
+
+/// A key which may be a BIP 118 key.
+///
+/// Keys without a prefix are ordinary BIP 340 keys, so that a single script may
+/// mix both types.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum ApoKey<Pk: MiniscriptKey> {
+    /// An ordinary BIP 340 key.
+    Plain(Pk),
+    /// A BIP 118 key, encoded as `0x01` followed by the x-only key.
+    Apo(Pk),
+    /// The Taproot internal key, used as a BIP 118 key.
+    Internal(Pk),
+}
+
+impl<Pk: MiniscriptKey> ApoKey<Pk> {
+    /// The underlying key.
+    pub fn as_inner(&self) -> &Pk {
+        match *self {
+            ApoKey::Plain(ref pk) | ApoKey::Apo(ref pk) | ApoKey::Internal(ref pk) => pk,
+        }
+    }
+
+    /// The BIP 118 encoding of the key, or `None` if it is an ordinary key.
+    pub fn kind(&self) -> Option<ApoKind> {
+        match *self {
+            ApoKey::Plain(..) => None,
+            ApoKey::Apo(..) => Some(ApoKind::Explicit),
+            ApoKey::Internal(..) => Some(ApoKind::Internal),
+        }
+    }
+}
+
+impl<Pk: MiniscriptKey> fmt::Display for ApoKey<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ApoKey::Plain(ref pk) => fmt::Display::fmt(pk, f),
+            ApoKey::Apo(ref pk) => write!(f, "apo:{}", pk),
+            ApoKey::Internal(ref pk) => write!(f, "apo_internal:{}", pk),
+        }
+    }
+}
+
+impl<Pk: MiniscriptKey + FromStr> FromStr for ApoKey<Pk> {
+    type Err = Pk::Err;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(pk) = s.strip_prefix("apo:") {
+            Pk::from_str(pk).map(ApoKey::Apo)
+        } else if let Some(pk) = s.strip_prefix("apo_internal:") {
+            Pk::from_str(pk).map(ApoKey::Internal)
+        } else {
+            Pk::from_str(s).map(ApoKey::Plain)
+        }
+    }
+}
+
+impl<Pk: MiniscriptKey> MiniscriptKey for ApoKey<Pk> {
+    type Sha256 = Pk::Sha256;
+    type Hash256 = Pk::Hash256;
+    type Ripemd160 = Pk::Ripemd160;
+    type Hash160 = Pk::Hash160;
+
+    fn is_uncompressed(&self) -> bool { self.as_inner().is_uncompressed() }
+
+    // BIP 118 keys only exist in Tapscript, so are treated as x-only keys to
+    // reject them in other contexts.
+    fn is_x_only_key(&self) -> bool { self.kind().is_some() || self.as_inner().is_x_only_key() }
+
+    fn num_der_paths(&self) -> usize { self.as_inner().num_der_paths() }
+
+    fn apo_kind(&self) -> Option<ApoKind> { self.kind() }
+}
+
+impl<Pk: ToPublicKey> ToPublicKey for ApoKey<Pk> {
+    fn to_public_key(&self) -> bitcoin::PublicKey { self.as_inner().to_public_key() }
+
+    fn to_x_only_pubkey(&self) -> bitcoin::secp256k1::XOnlyPublicKey {
+        self.as_inner().to_x_only_pubkey()
+    }
+
+    fn to_sha256(hash: &Pk::Sha256) -> bitcoin::hashes::sha256::Hash { Pk::to_sha256(hash) }
+
+    fn to_hash256(hash: &Pk::Hash256) -> crate::hash256::Hash { Pk::to_hash256(hash) }
+
+    fn to_ripemd160(hash: &Pk::Ripemd160) -> bitcoin::hashes::ripemd160::Hash {
+        Pk::to_ripemd160(hash)
+    }
+
+    fn to_hash160(hash: &Pk::Hash160) -> bitcoin::hashes::hash160::Hash { Pk::to_hash160(hash) }
+}
+
+/// The BIP 118 signature hash types.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum ApoSighashType {
+    /// `SIGHASH_ALL | SIGHASH_ANYPREVOUT`
+    AllPlusAnyPrevOut = 0x41,
+    /// `SIGHASH_NONE | SIGHASH_ANYPREVOUT`
+    NonePlusAnyPrevOut = 0x42,
+    /// `SIGHASH_SINGLE | SIGHASH_ANYPREVOUT`
+    SinglePlusAnyPrevOut = 0x43,
+    /// `SIGHASH_ALL | SIGHASH_ANYPREVOUTANYSCRIPT`
+    AllPlusAnyPrevOutAnyScript = 0xc1,
+    /// `SIGHASH_NONE | SIGHASH_ANYPREVOUTANYSCRIPT`
+    NonePlusAnyPrevOutAnyScript = 0xc2,
+    /// `SIGHASH_SINGLE | SIGHASH_ANYPREVOUTANYSCRIPT`
+    SinglePlusAnyPrevOutAnyScript = 0xc3,
+}
+
+impl ApoSighashType {
+    /// The sighash byte appended to the signature.
+    pub fn to_u8(self) -> u8 { self as u8 }
+}
+
+/// A Schnorr signature with a BIP 118 signature hash type.
+///
+/// Such signatures are only valid for BIP 118 keys. This library does not
+/// compute the BIP 118 signature hash; signers must produce the signature.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub struct ApoSignature {
+    /// The signature.
+    pub signature: schnorr::Signature,
+    /// The signature hash type, which is always explicit.
+    pub sighash_type: ApoSighashType,
+}
+
+impl ApoSignature {
+    /// Serializes the signature, followed by its sighash byte.
+    pub fn to_vec(self) -> Vec<u8> {
+        let mut ser = self.signature.as_ref().to_vec();
+        ser.push(self.sighash_type.to_u8());
+        ser
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::secp256k1::XOnlyPublicKey;
+    use bitcoin::taproot::TapLeafHash;
+
+    use super::*;
+    use crate::miniscript::context::ScriptContextError;
+    use crate::{Error, Miniscript, Satisfier, Segwitv0, Tap};
+
+    const X: &str = "cc8a4bc64d897bddc5fbc2f670f7a8ba0b386779106cf1223c6fc5d7cd6fc115";
+
+    #[test]
+    fn parse_and_check() {
+        let ms = Miniscript::<ApoKey<String>, Tap>::from_str(
+            "and_v(v:pk(apo:A),multi_a(1,B,apo_internal:C))",
+        )
+        .unwrap();
+        assert_eq!(ms.to_string(), "and_v(v:pk(apo:A),multi_a(1,B,apo_internal:C))");
+
+        assert!(matches!(
+            Miniscript::<ApoKey<String>, Segwitv0>::from_str("pk(apo:A)"),
+            Err(Error::ContextError(ScriptContextError::XOnlyKeysNotAllowed(..)))
+        ));
+        assert!(matches!(
+            Miniscript::<ApoKey<String>, Tap>::from_str("pkh(apo:A)"),
+            Err(Error::ContextError(ScriptContextError::ApoKeyHashNotAllowed(..)))
+        ));
+        Miniscript::<ApoKey<String>, Segwitv0>::from_str("pk(A)").unwrap();
+    }
+
+    #[test]
+    fn encode() {
+        type Ms = Miniscript<ApoKey<XOnlyPublicKey>, Tap>;
+
+        let ms = Ms::from_str(&format!("pk(apo:{})", X)).unwrap();
+        let script = ms.encode();
+        assert_eq!(script.len(), ms.script_size());
+        assert_eq!(script.to_hex_string(), format!("2101{}ac", X));
+
+        let ms = Ms::from_str(&format!("pk(apo_internal:{})", X)).unwrap();
+        let script = ms.encode();
+        assert_eq!(script.len(), ms.script_size());
+        assert_eq!(script.to_hex_string(), "51ac");
+
+        let ms = Ms::from_str(&format!("multi_a(1,{},apo:{},apo_internal:{})", X, X, X)).unwrap();
+        assert_eq!(ms.encode().len(), ms.script_size());
+        assert_eq!(ms.script_size(), 33 + 34 + 1 + 2 + 1 + 1 + 1);
+    }
+
+    #[test]
+    fn satisfy() {
+        struct ApoSigner(ApoSignature);
+
+        impl Satisfier<ApoKey<XOnlyPublicKey>> for ApoSigner {
+            fn lookup_apo_sig(
+                &self,
+                pk: &ApoKey<XOnlyPublicKey>,
+                _: &TapLeafHash,
+            ) -> Option<ApoSignature> {
+                pk.kind().map(|_| self.0)
+            }
+        }
+
+        let sig = ApoSignature {
+            signature: schnorr::Signature::from_slice(&[0xab; 64]).unwrap(),
+            sighash_type: ApoSighashType::AllPlusAnyPrevOutAnyScript,
+        };
+        let ms =
+            Miniscript::<ApoKey<XOnlyPublicKey>, Tap>::from_str(&format!("pk(apo_internal:{})", X))
+                .unwrap();
+        let witness = ms.satisfy(ApoSigner(sig)).unwrap();
+        assert_eq!(witness, vec![sig.to_vec()]);
+        assert_eq!(witness[0].len(), 65);
+        assert_eq!(witness[0][64], 0xc1);
+
+        // Ordinary keys cannot be satisfied with BIP 118 signatures.
+        let ms =
+            Miniscript::<ApoKey<XOnlyPublicKey>, Tap>::from_str(&format!("pk({})", X)).unwrap();
+        assert!(ms.satisfy(ApoSigner(sig)).is_err());
+    }
+}
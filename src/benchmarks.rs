@@ -218,7 +218,8 @@ deep_expression!(parse_expression_deep_h_100, 100);
 deep_expression!(parse_expression_deep_i_200, 200);
 deep_expression!(parse_expression_deep_j_300, 300);
 deep_expression!(parse_expression_deep_j_400, 400);
-// For "deep" benchmarks we hit max recursion depth and can't go farther
+deep_expression!(parse_expression_deep_k_1000, 1000);
+deep_expression!(parse_expression_deep_l_10000, 10000);
 
 balanced_segwit!(parse_descriptor_balanced_segwit_a_0, 0);
 balanced_segwit!(parse_descriptor_balanced_segwit_b_1, 1);
@@ -359,3 +360,62 @@ mod compiler_benches {
         });
     }
 }
+
+mod satisfaction_benches {
+    use bitcoin::secp256k1::schnorr;
+    use bitcoin::sighash::TapSighashType;
+    use bitcoin::taproot::{LeafVersion, Signature, TapLeafHash};
+    use bitcoin::XOnlyPublicKey;
+
+    use super::*;
+    use crate::{MapSatisfier, Miniscript, Tap};
+
+    type TapMs = Miniscript<XOnlyPublicKey, Tap>;
+
+    fn xonly_keys(n: u32) -> Vec<XOnlyPublicKey> {
+        (1..=n)
+            .map(|i| match keygen(i) {
+                DescriptorPublicKey::Single(SinglePub {
+                    key: SinglePubKey::FullKey(pk), ..
+                }) => pk.inner.x_only_public_key().0,
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    /// A satisfier with a signature for every other key of the script.
+    fn satisfier(ms: &TapMs, keys: &[XOnlyPublicKey]) -> MapSatisfier<XOnlyPublicKey> {
+        let leaf_hash = TapLeafHash::from_script(&ms.encode(), LeafVersion::TapScript);
+        let sig = Signature {
+            signature: schnorr::Signature::from_slice(&[1; 64]).unwrap(),
+            sighash_type: TapSighashType::Default,
+        };
+        let mut satisfier = MapSatisfier::new();
+        for pk in keys.iter().step_by(2) {
+            satisfier.add_tap_leaf_script_sig(*pk, leaf_hash, sig);
+        }
+        satisfier
+    }
+
+    #[bench]
+    pub fn satisfy_thresh_999(bh: &mut Bencher) {
+        let keys = xonly_keys(999);
+        let children = keys
+            .iter()
+            .enumerate()
+            .map(|(i, pk)| if i == 0 { format!("pk({})", pk) } else { format!("s:pk({})", pk) })
+            .collect::<Vec<_>>();
+        let ms = TapMs::from_str_insane(&format!("thresh(400,{})", children.join(","))).unwrap();
+        let satisfier = satisfier(&ms, &keys);
+        bh.iter(|| black_box(ms.satisfy(&satisfier)).unwrap());
+    }
+
+    #[bench]
+    pub fn satisfy_multi_a_999(bh: &mut Bencher) {
+        let keys = xonly_keys(999);
+        let keys_str = keys.iter().map(|pk| pk.to_string()).collect::<Vec<_>>();
+        let ms = TapMs::from_str_insane(&format!("multi_a(400,{})", keys_str.join(","))).unwrap();
+        let satisfier = satisfier(&ms, &keys);
+        bh.iter(|| black_box(ms.satisfy(&satisfier)).unwrap());
+    }
+}
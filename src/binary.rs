@@ -0,0 +1,796 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Binary Encoding
+//!
+//! A compact, versioned binary encoding of Miniscript and descriptor ASTs,
+//! intended for storing large numbers of descriptors in a database without
+//! paying for string parsing when loading them.
+//!
+//! The encoding is a version byte followed by the tree in pre-order, with a
+//! one-byte tag for each node. Keys and hashes are encoded as length-prefixed
+//! strings, using their `Display` and `FromStr` implementations, so that any
+//! key type which can be parsed from a string is supported. Raw scripts are
+//! encoded as length-prefixed bytes. Numbers are encoded as LEB128 varints.
+//!
+//! Decoding does not involve any string parsing of the structure, but still
+//! typechecks every node and checks the rules of the script context, so a
+//! corrupted or malicious encoding cannot produce an invalid Miniscript.
+//!
+
+use core::fmt;
+use core::str::FromStr;
+#[cfg(feature = "std")]
+use std::error;
+
+use bitcoin::taproot::{LeafVersion, TAPROOT_CONTROL_MAX_NODE_COUNT};
+use sync::Arc;
+
+use crate::descriptor::{
+    Addr, Bare, Pkh, Raw, RawTapLeaf, ShInner, TapTree, Tr, Wpkh, WshInner,
+};
+use crate::iter::TreeLike;
+use crate::prelude::*;
+use crate::{
+    AbsLockTime, Descriptor, Error, FromStrKey, Miniscript, MiniscriptKey, ParseError, RelLockTime,
+    ScriptContext, Terminal, Threshold,
+};
+
+/// The version of the binary encoding produced by this library.
+pub const VERSION: u8 = 1;
+
+/// An error decoding the binary encoding of a Miniscript or descriptor.
+///
+/// This covers only errors in the encoding itself. Once a node is decoded,
+/// errors from typechecking it are returned as the usual [`Error`] variants.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The encoding has a version this library does not understand.
+    UnsupportedVersion(u8),
+    /// The encoding ended in the middle of a node.
+    UnexpectedEnd,
+    /// A node had an unknown tag.
+    UnknownTag {
+        /// The unknown tag.
+        tag: u8,
+        /// Its position in the encoding.
+        pos: usize,
+    },
+    /// A varint did not fit in the expected integer type.
+    VarIntOverflow {
+        /// The position of the varint in the encoding.
+        pos: usize,
+    },
+    /// A string was not valid UTF-8.
+    InvalidUtf8 {
+        /// The position of the string in the encoding.
+        pos: usize,
+    },
+    /// The Taproot tree was nested more deeply than consensus allows.
+    MaxRecursionDepthExceeded,
+    /// There were bytes left over after decoding.
+    TrailingBytes {
+        /// The number of bytes left over.
+        n: usize,
+    },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::UnsupportedVersion(v) => {
+                write!(f, "unsupported binary encoding version {} (expected {})", v, VERSION)
+            }
+            DecodeError::UnexpectedEnd => f.write_str("unexpected end of binary encoding"),
+            DecodeError::UnknownTag { tag, pos } => {
+                write!(f, "unknown tag {} at position {} of binary encoding", tag, pos)
+            }
+            DecodeError::VarIntOverflow { pos } => {
+                write!(f, "varint at position {} of binary encoding is out of range", pos)
+            }
+            DecodeError::InvalidUtf8 { pos } => {
+                write!(f, "string at position {} of binary encoding is not valid UTF-8", pos)
+            }
+            DecodeError::MaxRecursionDepthExceeded => write!(
+                f,
+                "binary encoding has a Taproot tree deeper than the maximum of {}",
+                TAPROOT_CONTROL_MAX_NODE_COUNT
+            ),
+            DecodeError::TrailingBytes { n } => {
+                write!(f, "{} trailing bytes after binary encoding", n)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for DecodeError {
+    fn cause(&self) -> Option<&dyn error::Error> { None }
+}
+
+mod tag {
+    // Miniscript fragments
+    pub const FALSE: u8 = 0x00;
+    pub const TRUE: u8 = 0x01;
+    pub const PK_K: u8 = 0x02;
+    pub const PK_H: u8 = 0x03;
+    pub const RAW_PK_H: u8 = 0x04;
+    pub const AFTER: u8 = 0x05;
+    pub const OLDER: u8 = 0x06;
+    pub const SHA256: u8 = 0x07;
+    pub const HASH256: u8 = 0x08;
+    pub const RIPEMD160: u8 = 0x09;
+    pub const HASH160: u8 = 0x0a;
+    pub const ALT: u8 = 0x0b;
+    pub const SWAP: u8 = 0x0c;
+    pub const CHECK: u8 = 0x0d;
+    pub const DUP_IF: u8 = 0x0e;
+    pub const VERIFY: u8 = 0x0f;
+    pub const NON_ZERO: u8 = 0x10;
+    pub const ZERO_NOT_EQUAL: u8 = 0x11;
+    pub const AND_V: u8 = 0x12;
+    pub const AND_B: u8 = 0x13;
+    pub const AND_OR: u8 = 0x14;
+    pub const OR_B: u8 = 0x15;
+    pub const OR_D: u8 = 0x16;
+    pub const OR_C: u8 = 0x17;
+    pub const OR_I: u8 = 0x18;
+    pub const THRESH: u8 = 0x19;
+    pub const MULTI: u8 = 0x1a;
+    pub const MULTI_A: u8 = 0x1b;
+
+    // Descriptors
+    pub const BARE: u8 = 0x00;
+    pub const PKH: u8 = 0x01;
+    pub const WPKH: u8 = 0x02;
+    pub const SH: u8 = 0x03;
+    pub const SH_SORTEDMULTI: u8 = 0x04;
+    pub const SH_WPKH: u8 = 0x05;
+    pub const SH_WSH: u8 = 0x06;
+    pub const SH_WSH_SORTEDMULTI: u8 = 0x07;
+    pub const WSH: u8 = 0x08;
+    pub const WSH_SORTEDMULTI: u8 = 0x09;
+    pub const TR: u8 = 0x0a;
+    pub const ADDR: u8 = 0x0b;
+    pub const RAW: u8 = 0x0c;
+
+    // Taproot trees
+    pub const NO_TREE: u8 = 0x00;
+    pub const LEAF: u8 = 0x01;
+    pub const BRANCH: u8 = 0x02;
+    pub const RAW_LEAF: u8 = 0x03;
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn new() -> Self { Writer(vec![VERSION]) }
+
+    fn byte(&mut self, b: u8) { self.0.push(b) }
+
+    fn varint(&mut self, mut n: u64) {
+        while n >= 0x80 {
+            self.0.push((n as u8) | 0x80);
+            n >>= 7;
+        }
+        self.0.push(n as u8);
+    }
+
+    fn bytes(&mut self, b: &[u8]) {
+        self.varint(b.len() as u64);
+        self.0.extend_from_slice(b);
+    }
+
+    fn string<T: fmt::Display>(&mut self, x: &T) { self.bytes(x.to_string().as_bytes()) }
+
+    fn keys<'a, Pk: MiniscriptKey + 'a, I: ExactSizeIterator<Item = &'a Pk>>(
+        &mut self,
+        k: usize,
+        keys: I,
+    ) {
+        self.varint(k as u64);
+        self.varint(keys.len() as u64);
+        for key in keys {
+            self.string(key);
+        }
+    }
+
+    fn miniscript<Pk: MiniscriptKey, Ctx: ScriptContext>(&mut self, ms: &Miniscript<Pk, Ctx>) {
+        for node in ms.pre_order_iter() {
+            match node.node {
+                Terminal::False => self.byte(tag::FALSE),
+                Terminal::True => self.byte(tag::TRUE),
+                Terminal::PkK(ref pk) => {
+                    self.byte(tag::PK_K);
+                    self.string(pk);
+                }
+                Terminal::PkH(ref pk) => {
+                    self.byte(tag::PK_H);
+                    self.string(pk);
+                }
+                Terminal::RawPkH(ref hash) => {
+                    self.byte(tag::RAW_PK_H);
+                    self.string(hash);
+                }
+                Terminal::After(t) => {
+                    self.byte(tag::AFTER);
+                    self.varint(t.to_consensus_u32().into());
+                }
+                Terminal::Older(t) => {
+                    self.byte(tag::OLDER);
+                    self.varint(t.to_consensus_u32().into());
+                }
+                Terminal::Sha256(ref h) => {
+                    self.byte(tag::SHA256);
+                    self.string(h);
+                }
+                Terminal::Hash256(ref h) => {
+                    self.byte(tag::HASH256);
+                    self.string(h);
+                }
+                Terminal::Ripemd160(ref h) => {
+                    self.byte(tag::RIPEMD160);
+                    self.string(h);
+                }
+                Terminal::Hash160(ref h) => {
+                    self.byte(tag::HASH160);
+                    self.string(h);
+                }
+                Terminal::Alt(..) => self.byte(tag::ALT),
+                Terminal::Swap(..) => self.byte(tag::SWAP),
+                Terminal::Check(..) => self.byte(tag::CHECK),
+                Terminal::DupIf(..) => self.byte(tag::DUP_IF),
+                Terminal::Verify(..) => self.byte(tag::VERIFY),
+                Terminal::NonZero(..) => self.byte(tag::NON_ZERO),
+                Terminal::ZeroNotEqual(..) => self.byte(tag::ZERO_NOT_EQUAL),
+                Terminal::AndV(..) => self.byte(tag::AND_V),
+                Terminal::AndB(..) => self.byte(tag::AND_B),
+                Terminal::AndOr(..) => self.byte(tag::AND_OR),
+                Terminal::OrB(..) => self.byte(tag::OR_B),
+                Terminal::OrD(..) => self.byte(tag::OR_D),
+                Terminal::OrC(..) => self.byte(tag::OR_C),
+                Terminal::OrI(..) => self.byte(tag::OR_I),
+                Terminal::Thresh(ref thresh) => {
+                    self.byte(tag::THRESH);
+                    self.varint(thresh.k() as u64);
+                    self.varint(thresh.n() as u64);
+                }
+                Terminal::Multi(ref thresh) => {
+                    self.byte(tag::MULTI);
+                    self.keys(thresh.k(), thresh.iter());
+                }
+                Terminal::MultiA(ref thresh) => {
+                    self.byte(tag::MULTI_A);
+                    self.keys(thresh.k(), thresh.iter());
+                }
+                Terminal::Ext(e) => match e {},
+            }
+        }
+    }
+
+    fn tap_tree<Pk: MiniscriptKey>(&mut self, tree: &TapTree<Pk>) {
+        match *tree {
+            TapTree::Tree { ref left, ref right, .. } => {
+                self.byte(tag::BRANCH);
+                self.tap_tree(left);
+                self.tap_tree(right);
+            }
+            TapTree::Leaf(ref ms) => {
+                self.byte(tag::LEAF);
+                self.miniscript(ms);
+            }
+            TapTree::RawLeaf(ref raw) => {
+                self.byte(tag::RAW_LEAF);
+                self.bytes(raw.script().as_bytes());
+                self.byte(raw.version().to_consensus());
+            }
+        }
+    }
+}
+
+/// A Miniscript node read from the encoding, whose children are yet to be read.
+enum Node<Pk: MiniscriptKey, Ctx: ScriptContext> {
+    Leaf(Terminal<Pk, Ctx>),
+    /// A wrapper or binary/ternary combinator, identified by its tag.
+    Combinator(u8),
+    /// A `thresh` with the given `k` and `n`.
+    Thresh(usize, usize),
+}
+
+impl<Pk: MiniscriptKey, Ctx: ScriptContext> Node<Pk, Ctx> {
+    fn n_children(&self) -> usize {
+        match *self {
+            Node::Leaf(..) => 0,
+            Node::Combinator(tag) if tag < tag::AND_V => 1,
+            Node::Combinator(tag::AND_OR) => 3,
+            Node::Combinator(..) => 2,
+            Node::Thresh(_, n) => n,
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Result<Self, DecodeError> {
+        let mut ret = Reader { data, pos: 0 };
+        match ret.byte()? {
+            VERSION => Ok(ret),
+            v => Err(DecodeError::UnsupportedVersion(v)),
+        }
+    }
+
+    fn finish(self) -> Result<(), DecodeError> {
+        match self.data.len() - self.pos {
+            0 => Ok(()),
+            n => Err(DecodeError::TrailingBytes { n }),
+        }
+    }
+
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        let b = *self.data.get(self.pos).ok_or(DecodeError::UnexpectedEnd)?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn varint(&mut self) -> Result<u64, DecodeError> {
+        let start = self.pos;
+        let mut ret = 0u64;
+        for shift in (0..64).step_by(7) {
+            let b = self.byte()?;
+            let bits = u64::from(b & 0x7f);
+            if bits << shift >> shift != bits {
+                return Err(DecodeError::VarIntOverflow { pos: start });
+            }
+            ret |= bits << shift;
+            if b & 0x80 == 0 {
+                return Ok(ret);
+            }
+        }
+        Err(DecodeError::VarIntOverflow { pos: start })
+    }
+
+    fn u32(&mut self) -> Result<u32, DecodeError> {
+        let pos = self.pos;
+        u32::try_from(self.varint()?).map_err(|_| DecodeError::VarIntOverflow { pos })
+    }
+
+    fn usize(&mut self) -> Result<usize, DecodeError> {
+        let pos = self.pos;
+        usize::try_from(self.varint()?).map_err(|_| DecodeError::VarIntOverflow { pos })
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], DecodeError> {
+        let len = self.usize()?;
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.data.len())
+            .ok_or(DecodeError::UnexpectedEnd)?;
+        let ret = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(ret)
+    }
+
+    fn string<T>(&mut self) -> Result<T, Error>
+    where
+        T: FromStr,
+        <T as FromStr>::Err: crate::blanket_traits::StaticDebugAndDisplay,
+    {
+        let pos = self.pos;
+        let s = core::str::from_utf8(self.bytes()?).map_err(|_| DecodeError::InvalidUtf8 { pos })?;
+        T::from_str(s).map_err(|e| Error::Parse(ParseError::box_from_str(e)))
+    }
+
+    fn keys<Pk: FromStrKey>(&mut self) -> Result<(usize, Vec<Pk>), Error> {
+        let k = self.usize()?;
+        let n = self.usize()?;
+        // Don't trust `n` for preallocation; each key takes at least one byte.
+        let mut keys = Vec::with_capacity(n.min(self.data.len() - self.pos));
+        for _ in 0..n {
+            keys.push(self.string()?);
+        }
+        Ok((k, keys))
+    }
+
+    fn miniscript<Pk: FromStrKey, Ctx: ScriptContext>(
+        &mut self,
+    ) -> Result<Miniscript<Pk, Ctx>, Error> {
+        // First read the nodes in pre-order, tracking how many children are
+        // still expected at each level so we know where the tree ends.
+        let mut nodes = vec![];
+        let mut remaining: Vec<usize> = vec![];
+        loop {
+            let node = self.node()?;
+            match node.n_children() {
+                0 => {
+                    while let Some(n) = remaining.last_mut() {
+                        *n -= 1;
+                        if *n > 0 {
+                            break;
+                        }
+                        remaining.pop();
+                    }
+                }
+                n => {
+                    remaining.push(n);
+                }
+            }
+            nodes.push(node);
+            if remaining.is_empty() {
+                break;
+            }
+        }
+
+        // Then build the tree bottom-up. In reverse pre-order every node comes
+        // after its children, with its first child on top of the stack.
+        let mut stack = vec![];
+        for node in nodes.into_iter().rev() {
+            let mut child = || stack.pop().map(Arc::new).expect("counted children above");
+            let term = match node {
+                Node::Leaf(term) => term,
+                Node::Combinator(tag::ALT) => Terminal::Alt(child()),
+                Node::Combinator(tag::SWAP) => Terminal::Swap(child()),
+                Node::Combinator(tag::CHECK) => Terminal::Check(child()),
+                Node::Combinator(tag::DUP_IF) => Terminal::DupIf(child()),
+                Node::Combinator(tag::VERIFY) => Terminal::Verify(child()),
+                Node::Combinator(tag::NON_ZERO) => Terminal::NonZero(child()),
+                Node::Combinator(tag::ZERO_NOT_EQUAL) => Terminal::ZeroNotEqual(child()),
+                Node::Combinator(tag::AND_V) => Terminal::AndV(child(), child()),
+                Node::Combinator(tag::AND_B) => Terminal::AndB(child(), child()),
+                Node::Combinator(tag::AND_OR) => Terminal::AndOr(child(), child(), child()),
+                Node::Combinator(tag::OR_B) => Terminal::OrB(child(), child()),
+                Node::Combinator(tag::OR_D) => Terminal::OrD(child(), child()),
+                Node::Combinator(tag::OR_C) => Terminal::OrC(child(), child()),
+                Node::Combinator(tag::OR_I) => Terminal::OrI(child(), child()),
+                Node::Combinator(_) => unreachable!("only known tags are read"),
+                Node::Thresh(k, n) => {
+                    let subs = (0..n).map(|_| child()).collect();
+                    Terminal::Thresh(Threshold::new(k, subs).map_err(Error::Threshold)?)
+                }
+            };
+            stack.push(Miniscript::from_ast(term)?);
+        }
+        assert_eq!(stack.len(), 1);
+        Ok(stack.pop().unwrap())
+    }
+
+    fn node<Pk: FromStrKey, Ctx: ScriptContext>(&mut self) -> Result<Node<Pk, Ctx>, Error> {
+        let pos = self.pos;
+        let term = match self.byte()? {
+            tag::FALSE => Terminal::False,
+            tag::TRUE => Terminal::True,
+            tag::PK_K => Terminal::PkK(self.string()?),
+            tag::PK_H => Terminal::PkH(self.string()?),
+            tag::RAW_PK_H => Terminal::RawPkH(self.string()?),
+            tag::AFTER => Terminal::After(
+                AbsLockTime::from_consensus(self.u32()?).map_err(Error::AbsoluteLockTime)?,
+            ),
+            tag::OLDER => Terminal::Older(
+                RelLockTime::from_consensus(self.u32()?).map_err(Error::RelativeLockTime)?,
+            ),
+            tag::SHA256 => Terminal::Sha256(self.string()?),
+            tag::HASH256 => Terminal::Hash256(self.string()?),
+            tag::RIPEMD160 => Terminal::Ripemd160(self.string()?),
+            tag::HASH160 => Terminal::Hash160(self.string()?),
+            tag @ tag::ALT..=tag::OR_I => return Ok(Node::Combinator(tag)),
+            tag::THRESH => {
+                let k = self.usize()?;
+                let n = self.usize()?;
+                return Ok(Node::Thresh(k, n));
+            }
+            tag::MULTI => {
+                let (k, keys) = self.keys()?;
+                Terminal::Multi(Threshold::new(k, keys).map_err(Error::Threshold)?)
+            }
+            tag::MULTI_A => {
+                let (k, keys) = self.keys()?;
+                Terminal::MultiA(Threshold::new(k, keys).map_err(Error::Threshold)?)
+            }
+            tag => return Err(DecodeError::UnknownTag { tag, pos }.into()),
+        };
+        Ok(Node::Leaf(term))
+    }
+
+    fn tap_tree<Pk: FromStrKey>(&mut self, depth: usize) -> Result<TapTree<Pk>, Error> {
+        if depth > TAPROOT_CONTROL_MAX_NODE_COUNT {
+            return Err(DecodeError::MaxRecursionDepthExceeded.into());
+        }
+        let pos = self.pos;
+        match self.byte()? {
+            tag::LEAF => Ok(TapTree::Leaf(Arc::new(self.miniscript()?))),
+            tag::RAW_LEAF => {
+                let script = self.bytes()?.to_vec().into();
+                let version = LeafVersion::from_consensus(self.byte()?)
+                    .map_err(|e| Error::Parse(ParseError::box_from_str(e)))?;
+                Ok(TapTree::RawLeaf(RawTapLeaf::new(script, version)))
+            }
+            tag::BRANCH => {
+                let left = self.tap_tree(depth + 1)?;
+                let right = self.tap_tree(depth + 1)?;
+                Ok(TapTree::combine(left, right))
+            }
+            tag => Err(DecodeError::UnknownTag { tag, pos }.into()),
+        }
+    }
+}
+
+impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
+    /// Encodes the Miniscript in the compact [binary encoding](crate::binary).
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut w = Writer::new();
+        w.miniscript(self);
+        w.0
+    }
+}
+
+impl<Pk: FromStrKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
+    /// Decodes a Miniscript from the compact [binary encoding](crate::binary).
+    ///
+    /// Every node is typechecked, and must satisfy the consensus rules of the
+    /// script context, but as with [`Miniscript::from_str_insane`] the result
+    /// is not checked for sanity.
+    pub fn from_binary(data: &[u8]) -> Result<Self, Error> {
+        let mut r = Reader::new(data)?;
+        let ms = r.miniscript()?;
+        r.finish()?;
+        Ok(ms)
+    }
+}
+
+impl<Pk: MiniscriptKey> Descriptor<Pk> {
+    /// Encodes the descriptor in the compact [binary encoding](crate::binary).
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut w = Writer::new();
+        match *self {
+            Descriptor::Bare(ref bare) => {
+                w.byte(tag::BARE);
+                w.miniscript(bare.as_inner());
+            }
+            Descriptor::Pkh(ref pkh) => {
+                w.byte(tag::PKH);
+                w.string(pkh.as_inner());
+            }
+            Descriptor::Wpkh(ref wpkh) => {
+                w.byte(tag::WPKH);
+                w.string(wpkh.as_inner());
+            }
+            Descriptor::Sh(ref sh) => match *sh.as_inner() {
+                ShInner::Ms(ref ms) => {
+                    w.byte(tag::SH);
+                    w.miniscript(ms);
+                }
+                ShInner::SortedMulti(ref smv) => {
+                    w.byte(tag::SH_SORTEDMULTI);
+                    w.keys(smv.k(), smv.pks().iter());
+                }
+                ShInner::Wpkh(ref wpkh) => {
+                    w.byte(tag::SH_WPKH);
+                    w.string(wpkh.as_inner());
+                }
+                ShInner::Wsh(ref wsh) => match *wsh.as_inner() {
+                    WshInner::Ms(ref ms) => {
+                        w.byte(tag::SH_WSH);
+                        w.miniscript(ms);
+                    }
+                    WshInner::SortedMulti(ref smv) => {
+                        w.byte(tag::SH_WSH_SORTEDMULTI);
+                        w.keys(smv.k(), smv.pks().iter());
+                    }
+                },
+            },
+            Descriptor::Wsh(ref wsh) => match *wsh.as_inner() {
+                WshInner::Ms(ref ms) => {
+                    w.byte(tag::WSH);
+                    w.miniscript(ms);
+                }
+                WshInner::SortedMulti(ref smv) => {
+                    w.byte(tag::WSH_SORTEDMULTI);
+                    w.keys(smv.k(), smv.pks().iter());
+                }
+            },
+            Descriptor::Tr(ref tr) => {
+                w.byte(tag::TR);
+                w.string(tr.internal_key());
+                match *tr.tap_tree() {
+                    Some(ref tree) => w.tap_tree(tree),
+                    None => w.byte(tag::NO_TREE),
+                }
+            }
+            Descriptor::Addr(ref addr) => {
+                w.byte(tag::ADDR);
+                w.string(addr.as_inner().assume_checked_ref());
+            }
+            Descriptor::Raw(ref raw) => {
+                w.byte(tag::RAW);
+                w.bytes(raw.as_inner().as_bytes());
+            }
+        }
+        w.0
+    }
+}
+
+impl<Pk: FromStrKey> Descriptor<Pk> {
+    /// Decodes a descriptor from the compact [binary encoding](crate::binary).
+    ///
+    /// The same checks are done as when constructing the descriptor from its
+    /// parts, but as with [`Miniscript::from_binary`] the result is not checked
+    /// for sanity.
+    pub fn from_binary(data: &[u8]) -> Result<Self, Error> {
+        let mut r = Reader::new(data)?;
+        let pos = r.pos;
+        let desc = match r.byte()? {
+            tag::BARE => Descriptor::Bare(Bare::new(r.miniscript()?)?),
+            tag::PKH => Descriptor::Pkh(Pkh::new(r.string()?)?),
+            tag::WPKH => Descriptor::Wpkh(Wpkh::new(r.string()?)?),
+            tag::SH => Descriptor::new_sh(r.miniscript()?)?,
+            tag::SH_SORTEDMULTI => {
+                let (k, keys) = r.keys()?;
+                Descriptor::new_sh_sortedmulti(k, keys)?
+            }
+            tag::SH_WPKH => Descriptor::new_sh_wpkh(r.string()?)?,
+            tag::SH_WSH => Descriptor::new_sh_wsh(r.miniscript()?)?,
+            tag::SH_WSH_SORTEDMULTI => {
+                let (k, keys) = r.keys()?;
+                Descriptor::new_sh_wsh_sortedmulti(k, keys)?
+            }
+            tag::WSH => Descriptor::new_wsh(r.miniscript()?)?,
+            tag::WSH_SORTEDMULTI => {
+                let (k, keys) = r.keys()?;
+                Descriptor::new_wsh_sortedmulti(k, keys)?
+            }
+            tag::TR => {
+                let internal_key = r.string()?;
+                let tree = if r.data.get(r.pos) == Some(&tag::NO_TREE) {
+                    r.pos += 1;
+                    None
+                } else {
+                    Some(r.tap_tree(0)?)
+                };
+                Descriptor::Tr(Tr::new(internal_key, tree)?)
+            }
+            tag::ADDR => Descriptor::Addr(Addr::new(r.string()?)),
+            tag::RAW => Descriptor::Raw(Raw::new(r.bytes()?.to_vec().into())),
+            tag => return Err(DecodeError::UnknownTag { tag, pos }.into()),
+        };
+        r.finish()?;
+        Ok(desc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DescriptorPublicKey, Segwitv0, Tap};
+
+    #[test]
+    fn miniscript_roundtrip() {
+        for s in [
+            "and_v(v:pk(A),or_d(pk(B),older(144)))",
+            "andor(multi(2,A,B,C),sha256(H),and_b(pkh(D),a:after(500000000)))",
+            "thresh(2,pk(A),s:pk(B),sln:after(10),a:hash160(H))",
+            "or_i(and_v(v:ripemd160(H),pk(A)),j:and_v(vn:hash256(H),pk(B)))",
+            "t:or_c(pk(A),v:0)",
+            "1",
+        ] {
+            let ms = Miniscript::<String, Segwitv0>::from_str_insane(s).unwrap();
+            let bin = ms.to_binary();
+            let decoded = Miniscript::<String, Segwitv0>::from_binary(&bin).unwrap();
+            assert_eq!(decoded, ms);
+            assert_eq!(decoded.ty, ms.ty);
+            assert_eq!(decoded.ext, ms.ext);
+        }
+
+        let ms = Miniscript::<String, Segwitv0>::from_str("and_v(v:pk(A),older(144))").unwrap();
+        assert_eq!(
+            ms.to_binary(),
+            [
+                VERSION,
+                tag::AND_V,
+                tag::VERIFY,
+                tag::CHECK,
+                tag::PK_K,
+                1,
+                b'A',
+                tag::OLDER,
+                0x90,
+                0x01
+            ],
+        );
+
+        // Context rules are checked on decoding.
+        let ms = Miniscript::<String, Tap>::from_str("multi_a(1,A,B)").unwrap();
+        assert!(matches!(
+            Miniscript::<String, Segwitv0>::from_binary(&ms.to_binary()),
+            Err(Error::ContextError(_))
+        ));
+    }
+
+    #[test]
+    fn descriptor_roundtrip() {
+        for s in [
+            "pkh(020000000000000000000000000000000000000000000000000000000000000002)",
+            "wpkh(xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8/1/*)",
+            "sh(wpkh(020000000000000000000000000000000000000000000000000000000000000002))",
+            "sh(sortedmulti(1,020000000000000000000000000000000000000000000000000000000000000002,030000000000000000000000000000000000000000000000000000000000000003))",
+            "sh(wsh(sortedmulti(1,020000000000000000000000000000000000000000000000000000000000000002,030000000000000000000000000000000000000000000000000000000000000003)))",
+            "wsh(sortedmulti(1,020000000000000000000000000000000000000000000000000000000000000002,030000000000000000000000000000000000000000000000000000000000000003))",
+            "sh(pk(020000000000000000000000000000000000000000000000000000000000000002))",
+            "sh(wsh(pk(020000000000000000000000000000000000000000000000000000000000000002)))",
+            "wsh(or_d(pk(020000000000000000000000000000000000000000000000000000000000000002),older(1)))",
+            "pk(020000000000000000000000000000000000000000000000000000000000000002)",
+            "tr(020000000000000000000000000000000000000000000000000000000000000002)",
+            "tr(020000000000000000000000000000000000000000000000000000000000000002,{pk(030000000000000000000000000000000000000000000000000000000000000003),{0,pk(020000000000000000000000000000000000000000000000000000000000000002)}})",
+            "addr(bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4)",
+            "raw(6a0568656c6c6f)",
+            "tr(020000000000000000000000000000000000000000000000000000000000000002,{raw_leaf(51),raw_leaf(52,c2)})",
+        ] {
+            let desc = Descriptor::<DescriptorPublicKey>::from_str(s).unwrap();
+            let bin = desc.to_binary();
+            assert_eq!(Descriptor::<DescriptorPublicKey>::from_binary(&bin).unwrap(), desc);
+        }
+    }
+
+    #[test]
+    fn decode_errors() {
+        let desc = Descriptor::<String>::from_str("wsh(and_v(v:pk(A),older(144)))").unwrap();
+        let bin = desc.to_binary();
+
+        assert!(matches!(
+            Descriptor::<String>::from_binary(&[]),
+            Err(Error::Binary(DecodeError::UnexpectedEnd))
+        ));
+        let mut bad = bin.clone();
+        bad[0] = 2;
+        assert!(matches!(
+            Descriptor::<String>::from_binary(&bad),
+            Err(Error::Binary(DecodeError::UnsupportedVersion(2)))
+        ));
+        for len in 1..bin.len() {
+            assert!(matches!(
+                Descriptor::<String>::from_binary(&bin[..len]),
+                Err(Error::Binary(DecodeError::UnexpectedEnd))
+            ));
+        }
+        let mut bad = bin.clone();
+        bad.push(0);
+        assert!(matches!(
+            Descriptor::<String>::from_binary(&bad),
+            Err(Error::Binary(DecodeError::TrailingBytes { n: 1 }))
+        ));
+        let mut bad = bin.clone();
+        bad[2] = 0xff;
+        assert!(matches!(
+            Descriptor::<String>::from_binary(&bad),
+            Err(Error::Binary(DecodeError::UnknownTag { tag: 0xff, pos: 2 }))
+        ));
+        // v:v:pk(A) does not typecheck
+        let mut bad = bin.clone();
+        bad[4] = tag::VERIFY;
+        assert!(matches!(Descriptor::<String>::from_binary(&bad), Err(Error::TypeCheck(_))));
+
+        // Deep nesting is decoded without overflowing the stack.
+        let mut deep = vec![VERSION];
+        deep.extend(core::iter::repeat(tag::ALT).take(100_000));
+        assert!(matches!(
+            Miniscript::<String, Segwitv0>::from_binary(&deep),
+            Err(Error::Binary(DecodeError::UnexpectedEnd))
+        ));
+        // A huge claimed number of keys does not cause a huge allocation.
+        assert!(matches!(
+            Miniscript::<String, Segwitv0>::from_binary(&[
+                VERSION,
+                tag::MULTI,
+                1,
+                0xff,
+                0xff,
+                0xff,
+                0xff,
+                0x0f
+            ]),
+            Err(Error::Binary(DecodeError::UnexpectedEnd))
+        ));
+    }
+}
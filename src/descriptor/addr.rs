@@ -0,0 +1,86 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Address Descriptors
+//!
+//! Implementation of `addr()` descriptors, which describe the outputs paying to an
+//! address without any knowledge of their keys or scripts, e.g. to track them in
+//! watch-only wallets.
+//!
+
+use core::{fmt, mem};
+
+use bitcoin::address::NetworkUnchecked;
+use bitcoin::{Address, Network, ScriptBuf};
+
+use crate::descriptor::write_descriptor;
+use crate::expression::{self, FromTree};
+use crate::{Error, ParseDescriptorError};
+
+/// An `addr()` descriptor, paying to an address
+///
+/// Since descriptors do not depend on the network, the network of the address is
+/// only checked by [`Addr::address`].
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct Addr {
+    /// underlying address
+    address: Address<NetworkUnchecked>,
+}
+
+impl Addr {
+    /// Create a new address descriptor
+    pub fn new(address: Address<NetworkUnchecked>) -> Self { Addr { address } }
+
+    /// Get a reference to the inner address
+    pub fn as_inner(&self) -> &Address<NetworkUnchecked> { &self.address }
+
+    /// Get the inner address
+    pub fn into_inner(self) -> Address<NetworkUnchecked> { self.address }
+
+    /// Obtains the corresponding script pubkey for this descriptor.
+    pub fn script_pubkey(&self) -> ScriptBuf { self.address.assume_checked_ref().script_pubkey() }
+
+    /// Appends the script pubkey of this descriptor to `script`.
+    pub fn script_pubkey_into(&self, script: &mut ScriptBuf) {
+        let mut bytes = mem::take(script).into_bytes();
+        bytes.extend_from_slice(self.script_pubkey().as_bytes());
+        *script = ScriptBuf::from_bytes(bytes);
+    }
+
+    /// Obtains the address, if it is valid for `network`
+    pub fn address(&self, network: Network) -> Result<Address, Error> {
+        self.address
+            .clone()
+            .require_network(network)
+            .map_err(Error::AddrError)
+    }
+}
+
+impl fmt::Debug for Addr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "addr({})", self.address.assume_checked_ref())
+    }
+}
+
+impl fmt::Display for Addr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_descriptor!(f, "addr({})", self.address.assume_checked_ref())
+    }
+}
+
+impl FromTree for Addr {
+    type Error = ParseDescriptorError;
+    fn from_tree(root: expression::TreeIterItem) -> Result<Self, ParseDescriptorError> {
+        let address = root
+            .verify_terminal_parent("addr", "address")
+            .map_err(ParseDescriptorError::Parse)?;
+        Ok(Addr::new(address))
+    }
+}
+
+impl core::str::FromStr for Addr {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let top = expression::Tree::from_str(s)?;
+        Self::from_tree(top.root()).map_err(Error::from)
+    }
+}
@@ -10,9 +10,11 @@
 use core::fmt;
 
 use bitcoin::script::{self, PushBytes};
-use bitcoin::{Address, Network, ScriptBuf, Weight};
+use bitcoin::{opcodes, Address, Network, ScriptBuf, Weight};
 
-use crate::descriptor::{write_descriptor, DefiniteDescriptorKey};
+use crate::descriptor::{
+    write_descriptor, DefiniteDescriptorKey, SatisfactionSize, SatisfactionWeight,
+};
 use crate::expression::{self, FromTree};
 use crate::miniscript::context::{ScriptContext, ScriptContextError};
 use crate::miniscript::satisfy::{Placeholder, Satisfaction, Witness};
@@ -21,8 +23,8 @@ use crate::policy::{semantic, Liftable};
 use crate::prelude::*;
 use crate::util::{varint_len, witness_to_scriptsig};
 use crate::{
-    BareCtx, Error, ForEachKey, FromStrKey, Miniscript, MiniscriptKey, Satisfier, ToPublicKey,
-    TranslateErr, Translator,
+    BareCtx, Error, ForEachKey, FromStrKey, Miniscript, MiniscriptKey, ParseDescriptorError,
+    Satisfier, SigSizes, ToPublicKey, TranslateErr, Translator,
 };
 
 /// Create a Bare Descriptor. That is descriptor that is
@@ -56,10 +58,9 @@ impl<Pk: MiniscriptKey> Bare<Pk> {
     /// Computes an upper bound on the difference between a non-satisfied
     /// `TxIn`'s `segwit_weight` and a satisfied `TxIn`'s `segwit_weight`
     ///
-    /// Since this method uses `segwit_weight` instead of `legacy_weight`,
-    /// if you want to include only legacy inputs in your transaction,
-    /// you should remove 1WU from each input's `max_weight_to_satisfy`
-    /// for a more accurate estimate.
+    /// The satisfaction is entirely in the scriptSig, so the difference is
+    /// the same when measured with `legacy_weight`, and this can be used for
+    /// legacy transactions as well.
     ///
     /// Assumes all ECDSA signatures are 73 bytes, including push opcode and
     /// sighash suffix.
@@ -67,11 +68,49 @@ impl<Pk: MiniscriptKey> Bare<Pk> {
     /// # Errors
     /// When the descriptor is impossible to safisfy (ex: sh(OP_FALSE)).
     pub fn max_weight_to_satisfy(&self) -> Result<Weight, Error> {
-        let scriptsig_size = self.ms.max_satisfaction_size()?;
+        self.max_weight_to_satisfy_with_sig_sizes(&SigSizes::WORST_CASE)
+    }
+
+    /// Computes an upper bound on the difference between a non-satisfied
+    /// `TxIn`'s `segwit_weight` and a satisfied `TxIn`'s `segwit_weight`,
+    /// as [`Self::max_weight_to_satisfy`], but assuming signatures of the
+    /// given sizes.
+    ///
+    /// # Errors
+    /// When the descriptor is impossible to safisfy (ex: sh(OP_FALSE)).
+    pub fn max_weight_to_satisfy_with_sig_sizes(
+        &self,
+        sig_sizes: &SigSizes,
+    ) -> Result<Weight, Error> {
+        Ok(self.max_weight_to_satisfy_parts(sig_sizes)?.total())
+    }
+
+    /// Computes upper bounds on the weights of the scriptSig and witness
+    /// components of the difference between a non-satisfied `TxIn`'s
+    /// `segwit_weight` and a satisfied `TxIn`'s `segwit_weight`, assuming
+    /// signatures of the given sizes. Their total is given by
+    /// [`Self::max_weight_to_satisfy_with_sig_sizes`].
+    ///
+    /// # Errors
+    /// When the descriptor is impossible to safisfy (ex: sh(OP_FALSE)).
+    pub fn max_weight_to_satisfy_parts(
+        &self,
+        sig_sizes: &SigSizes,
+    ) -> Result<SatisfactionWeight, Error> {
+        let scriptsig_size = self.ms.max_satisfaction_size_with_sig_sizes(sig_sizes)?;
         // scriptSig varint difference between non-satisfied (0) and satisfied
         let scriptsig_varint_diff = varint_len(scriptsig_size) - varint_len(0);
-        Weight::from_vb((scriptsig_varint_diff + scriptsig_size) as u64)
-            .ok_or(Error::CouldNotSatisfy)
+        Ok(SatisfactionWeight::from_scriptsig_size(scriptsig_varint_diff + scriptsig_size))
+    }
+
+    /// Computes upper bounds on the sizes of the scriptSig and witness of a
+    /// satisfying `TxIn`, assuming signatures of the given sizes.
+    ///
+    /// # Errors
+    /// When the descriptor is impossible to safisfy (ex: sh(OP_FALSE)).
+    pub fn max_satisfaction_size(&self, sig_sizes: &SigSizes) -> Result<SatisfactionSize, Error> {
+        let scriptsig_size = self.ms.max_satisfaction_size_with_sig_sizes(sig_sizes)?;
+        Ok(SatisfactionSize::from_script_sig_bytes(scriptsig_size))
     }
 
     /// Computes an upper bound on the weight of a satisfying witness to the
@@ -105,6 +144,9 @@ impl<Pk: MiniscriptKey + ToPublicKey> Bare<Pk> {
     /// Obtains the corresponding script pubkey for this descriptor.
     pub fn script_pubkey(&self) -> ScriptBuf { self.ms.encode() }
 
+    /// Appends the script pubkey of this descriptor to `script`.
+    pub fn script_pubkey_into(&self, script: &mut ScriptBuf) { self.ms.encode_to(script) }
+
     /// Obtains the underlying miniscript for this descriptor.
     pub fn inner_script(&self) -> ScriptBuf { self.script_pubkey() }
 
@@ -175,10 +217,11 @@ impl<Pk: MiniscriptKey> Liftable<Pk> for Bare<Pk> {
 }
 
 impl<Pk: FromStrKey> FromTree for Bare<Pk> {
-    fn from_tree(root: expression::TreeIterItem) -> Result<Self, Error> {
+    type Error = ParseDescriptorError;
+    fn from_tree(root: expression::TreeIterItem) -> Result<Self, ParseDescriptorError> {
         let sub = Miniscript::<Pk, BareCtx>::from_tree(root)?;
-        BareCtx::top_level_checks(&sub)?;
-        Bare::new(sub)
+        BareCtx::top_level_checks(&sub).map_err(ParseDescriptorError::invalid)?;
+        Bare::new(sub).map_err(ParseDescriptorError::invalid)
     }
 }
 
@@ -186,7 +229,7 @@ impl<Pk: FromStrKey> core::str::FromStr for Bare<Pk> {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let top = expression::Tree::from_str(s)?;
-        Self::from_tree(top.root())
+        Self::from_tree(top.root()).map_err(Error::from)
     }
 }
 
@@ -222,10 +265,9 @@ impl<Pk: MiniscriptKey> Pkh<Pk> {
     /// Computes an upper bound on the difference between a non-satisfied
     /// `TxIn`'s `segwit_weight` and a satisfied `TxIn`'s `segwit_weight`
     ///
-    /// Since this method uses `segwit_weight` instead of `legacy_weight`,
-    /// if you want to include only legacy inputs in your transaction,
-    /// you should remove 1WU from each input's `max_weight_to_satisfy`
-    /// for a more accurate estimate.
+    /// The satisfaction is entirely in the scriptSig, so the difference is
+    /// the same when measured with `legacy_weight`, and this can be used for
+    /// legacy transactions as well.
     ///
     /// Assumes all ECDSA signatures are 73 bytes, including push opcode and
     /// sighash suffix.
@@ -233,11 +275,35 @@ impl<Pk: MiniscriptKey> Pkh<Pk> {
     /// # Errors
     /// When the descriptor is impossible to safisfy (ex: sh(OP_FALSE)).
     pub fn max_weight_to_satisfy(&self) -> Weight {
+        self.max_weight_to_satisfy_with_sig_sizes(&SigSizes::WORST_CASE)
+    }
+
+    /// Computes an upper bound on the difference between a non-satisfied
+    /// `TxIn`'s `segwit_weight` and a satisfied `TxIn`'s `segwit_weight`,
+    /// as [`Self::max_weight_to_satisfy`], but assuming signatures of the
+    /// given sizes.
+    pub fn max_weight_to_satisfy_with_sig_sizes(&self, sig_sizes: &SigSizes) -> Weight {
+        self.max_weight_to_satisfy_parts(sig_sizes).total()
+    }
+
+    /// Computes upper bounds on the weights of the scriptSig and witness
+    /// components of the difference between a non-satisfied `TxIn`'s
+    /// `segwit_weight` and a satisfied `TxIn`'s `segwit_weight`, assuming
+    /// signatures of the given sizes. Their total is given by
+    /// [`Self::max_weight_to_satisfy_with_sig_sizes`].
+    pub fn max_weight_to_satisfy_parts(&self, sig_sizes: &SigSizes) -> SatisfactionWeight {
         // OP_72 + <sig(71)+sigHash(1)> + OP_33 + <pubkey>
-        let scriptsig_size = 73 + BareCtx::pk_len(&self.pk);
+        let scriptsig_size = sig_sizes.ecdsa + BareCtx::pk_len(&self.pk);
         // scriptSig varint different between non-satisfied (0) and satisfied
         let scriptsig_varint_diff = varint_len(scriptsig_size) - varint_len(0);
-        Weight::from_vb((scriptsig_varint_diff + scriptsig_size) as u64).unwrap()
+        SatisfactionWeight::from_scriptsig_size(scriptsig_varint_diff + scriptsig_size)
+    }
+
+    /// Computes upper bounds on the sizes of the scriptSig and witness of a
+    /// satisfying `TxIn`, assuming signatures of the given sizes.
+    pub fn max_satisfaction_size(&self, sig_sizes: &SigSizes) -> SatisfactionSize {
+        // <sig> <pubkey>
+        SatisfactionSize::from_script_sig_bytes(sig_sizes.ecdsa + BareCtx::pk_len(&self.pk))
     }
 
     /// Computes an upper bound on the weight of a satisfying witness to the
@@ -274,6 +340,15 @@ impl<Pk: MiniscriptKey + ToPublicKey> Pkh<Pk> {
         addr.script_pubkey()
     }
 
+    /// Appends the script pubkey of this descriptor to `script`.
+    pub fn script_pubkey_into(&self, script: &mut ScriptBuf) {
+        script.push_opcode(opcodes::all::OP_DUP);
+        script.push_opcode(opcodes::all::OP_HASH160);
+        script.push_slice(self.pk.to_public_key().pubkey_hash());
+        script.push_opcode(opcodes::all::OP_EQUALVERIFY);
+        script.push_opcode(opcodes::all::OP_CHECKSIG);
+    }
+
     /// Obtains the corresponding script pubkey for this descriptor.
     pub fn address(&self, network: Network) -> Address {
         Address::p2pkh(self.pk.to_public_key(), network)
@@ -369,11 +444,12 @@ impl<Pk: MiniscriptKey> Liftable<Pk> for Pkh<Pk> {
 }
 
 impl<Pk: FromStrKey> FromTree for Pkh<Pk> {
-    fn from_tree(root: expression::TreeIterItem) -> Result<Self, Error> {
+    type Error = ParseDescriptorError;
+    fn from_tree(root: expression::TreeIterItem) -> Result<Self, ParseDescriptorError> {
         let pk = root
             .verify_terminal_parent("pkh", "public key")
-            .map_err(Error::Parse)?;
-        Pkh::new(pk).map_err(Error::ContextError)
+            .map_err(ParseDescriptorError::Parse)?;
+        Pkh::new(pk).map_err(ParseDescriptorError::Context)
     }
 }
 
@@ -381,7 +457,7 @@ impl<Pk: FromStrKey> core::str::FromStr for Pkh<Pk> {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let top = expression::Tree::from_str(s)?;
-        Self::from_tree(top.root())
+        Self::from_tree(top.root()).map_err(Error::from)
     }
 }
 
@@ -49,6 +49,8 @@ pub enum Error {
         /// The length of a valid descriptor checksum.
         expected: usize,
     },
+    /// Checksum was required but not present.
+    MissingChecksum,
     /// Checksum was invalid.
     InvalidChecksum {
         /// The checksum in the string.
@@ -67,6 +69,7 @@ impl fmt::Display for Error {
             Error::InvalidChecksumLength { actual, expected } => {
                 write!(f, "invalid checksum (length {}, expected {})", actual, expected)
             }
+            Error::MissingChecksum => f.write_str("missing checksum"),
             Error::InvalidChecksum { actual, expected } => {
                 f.write_str("invalid checksum ")?;
                 for ch in actual {
@@ -87,10 +90,48 @@ impl std::error::Error for Error {
     fn cause(&self) -> Option<&dyn std::error::Error> { None }
 }
 
-/// Helper function for `FromStr` for various descriptor types.
+/// Checks that a byte slice uses only the descriptor character set, and returns
+/// it as a string without copying it.
 ///
-/// Checks and verifies the checksum if it is present and returns the descriptor
-/// string without the checksum.
+/// The bytes are scanned twice: once to validate them as UTF-8, which borrowing
+/// them as a string requires without unsafe code, and once to check them
+/// against the character set. It does not check the checksum.
+pub fn str_from_bytes(s: &[u8]) -> Result<&str, Error> {
+    let s = utf8_from_bytes(s)?;
+    check_charset(s)?;
+    Ok(s)
+}
+
+/// Validates a byte slice as UTF-8 and returns it as a string without copying
+/// it, reporting invalid UTF-8 as an invalid descriptor character.
+///
+/// The character set is left to the caller, such as [`verify_checksum`], so
+/// that the bytes are scanned only once more.
+pub(crate) fn utf8_from_bytes(s: &[u8]) -> Result<&str, Error> {
+    core::str::from_utf8(s).map_err(|e| {
+        let pos = e.valid_up_to();
+        // Report an earlier character outside of the character set first, as
+        // a check of the whole string would.
+        let valid = core::str::from_utf8(&s[..pos]).expect("valid up to here");
+        check_charset(valid)
+            .err()
+            .unwrap_or(Error::InvalidCharacter { ch: char::REPLACEMENT_CHARACTER, pos })
+    })
+}
+
+/// Checks that a string uses only the descriptor character set.
+fn check_charset(s: &str) -> Result<(), Error> {
+    match s.char_indices().find(|&(_, ch)| !(32..127).contains(&u32::from(ch))) {
+        Some((pos, ch)) => Err(Error::InvalidCharacter { ch, pos }),
+        None => Ok(()),
+    }
+}
+
+/// Checks that a string uses only the descriptor character set, and verifies
+/// its checksum if it is present.
+///
+/// Returns the string without the checksum. This is the check done by `FromStr`
+/// for the various descriptor types, and does not otherwise parse the string.
 pub fn verify_checksum(s: &str) -> Result<&str, Error> {
     let mut last_hash_pos = s.len();
     for (pos, ch) in s.char_indices() {
@@ -127,6 +168,185 @@ pub fn verify_checksum(s: &str) -> Result<&str, Error> {
     Ok(&s[..last_hash_pos])
 }
 
+/// Verifies the checksum of a string, returning the string without it.
+///
+/// Unlike [`verify_checksum`], this requires that the checksum be present.
+pub fn strip_checksum(s: &str) -> Result<&str, Error> {
+    let stripped = verify_checksum(s)?;
+    if stripped.len() == s.len() {
+        return Err(Error::MissingChecksum);
+    }
+    Ok(stripped)
+}
+
+/// Appends a checksum to a string.
+///
+/// If the string already has a checksum, it is verified and the string is
+/// returned unchanged.
+pub fn add_checksum(s: &str) -> Result<String, Error> {
+    let stripped = verify_checksum(s)?;
+    if stripped.len() < s.len() {
+        return Ok(s.to_owned());
+    }
+
+    let mut eng = Engine::new();
+    eng.input_unchecked(s.as_bytes());
+    let mut ret = String::with_capacity(s.len() + 1 + CHECKSUM_LENGTH);
+    ret.push_str(s);
+    ret.push('#');
+    ret.extend(eng.checksum_chars().iter());
+    Ok(ret)
+}
+
+/// A single-character change which would give a string a valid checksum.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Correction {
+    /// The byte-index into the string of the character to replace.
+    pub pos: usize,
+    /// The character to replace it with.
+    pub ch: char,
+}
+
+impl Correction {
+    /// Applies the correction to the string it was computed from.
+    pub fn apply(&self, s: &str) -> String {
+        let mut ret = String::with_capacity(s.len());
+        ret.push_str(&s[..self.pos]);
+        ret.push(self.ch);
+        ret.push_str(&s[self.pos + 1..]);
+        ret
+    }
+}
+
+impl fmt::Display for Correction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "replace character at position {} with '{}'", self.pos, self.ch)
+    }
+}
+
+/// Looks for single-character corrections to a string with an invalid checksum.
+///
+/// The checksum can detect any error affecting up to four characters of a
+/// descriptor of ordinary length, and is also able to locate (and therefore
+/// correct) an error in any single character, whether it is in the descriptor
+/// itself or in the checksum. This function returns every such correction,
+/// which in practice means either none or exactly one. If none is found, the
+/// string has more than one error.
+///
+/// Returns an empty vector if the checksum is already valid, or if the string
+/// does not have a checksum of the correct length, or contains characters
+/// outside of the descriptor character set; use [`verify_checksum`] to
+/// distinguish these cases.
+pub fn suggest_corrections(s: &str) -> Vec<Correction> {
+    let mut ret = vec![];
+    if s.bytes().any(|b| !(32..127).contains(&b)) {
+        return ret;
+    }
+    let hash_pos = match s.rfind('#') {
+        Some(pos) => pos,
+        None => return ret,
+    };
+    let (payload, checksum_str) = (&s.as_bytes()[..hash_pos], &s[hash_pos + 1..]);
+    if checksum_str.len() != CHECKSUM_LENGTH {
+        return ret;
+    }
+
+    let mut eng = Engine::new();
+    eng.input_unchecked(payload);
+    let expected = eng.checksum_chars();
+
+    // Errors in the checksum itself are easy: compare with the expected checksum.
+    let mut mismatches = checksum_str
+        .chars()
+        .zip(expected.iter())
+        .enumerate()
+        .filter(|(_, (actual, expected))| actual != *expected);
+    match (mismatches.next(), mismatches.next()) {
+        (None, _) => return ret, // checksum is valid
+        (Some((idx, (_, &ch))), None) => ret.push(Correction { pos: hash_pos + 1 + idx, ch }),
+        (Some(_), Some(_)) => {}
+    }
+
+    // For errors in the payload, use the fact that the checksum is linear in
+    // the symbols fed to the engine: changing the symbol at index `i` by `d`
+    // (under XOR) changes the final residue by `d * x^k mod g`, where `k` is
+    // the number of symbols following it, including the 8 checksum symbols.
+    let target = match checksum_value(checksum_str.chars()) {
+        Some(value) => value,
+        None => return ret, // checksum contains non-bech32 characters
+    };
+    let diff = checksum_value(expected.iter().copied()).expect("valid checksum chars") ^ target;
+
+    let n_symbols = payload.len() + (payload.len() + 2) / 3;
+    let mut shifts = vec![[0u64; 5]; n_symbols];
+    let mut cur: [u64; 5] = array::from_fn(|bit| 1 << bit);
+    for _ in 0..CHECKSUM_LENGTH {
+        cur.iter_mut().for_each(|c| *c = polymod_shift(*c));
+    }
+    for entry in shifts.iter_mut().rev() {
+        *entry = cur;
+        cur.iter_mut().for_each(|c| *c = polymod_shift(*c));
+    }
+    let delta = |symbol_idx: usize, d: u64| -> u64 {
+        (0..5)
+            .filter(|bit| d & (1 << bit) != 0)
+            .fold(0, |acc, bit| acc ^ shifts[symbol_idx][bit])
+    };
+
+    for (group_idx, group) in payload.chunks(3).enumerate() {
+        let group_start = 3 * group_idx;
+        // Each complete group of three characters is followed by a symbol
+        // encoding the high bits of the three characters.
+        let cls_idx = 4 * group_idx + group.len();
+        let cls = group
+            .iter()
+            .fold(0, |acc, &ch| acc * 3 + (char_class(ch) >> 5));
+        for (offset, &old_ch) in group.iter().enumerate() {
+            let pos = group_start + offset;
+            let fe_idx = 4 * group_idx + offset;
+            let weight = 3u64.pow((group.len() - 1 - offset) as u32);
+            let old = char_class(old_ch);
+            for new_ch in (32..127u8).filter(|&ch| ch != old_ch && ch != b'#') {
+                let new = char_class(new_ch);
+                let new_cls = cls - (old >> 5) * weight + (new >> 5) * weight;
+                let d = delta(fe_idx, (old ^ new) & 31) ^ delta(cls_idx, cls ^ new_cls);
+                if d == diff {
+                    let correction = Correction { pos, ch: new_ch.into() };
+                    // Double-check by direct computation.
+                    if verify_checksum(&correction.apply(s)).is_ok() {
+                        ret.push(correction);
+                    }
+                }
+            }
+        }
+    }
+    ret
+}
+
+/// The position of a character in the descriptor character map.
+fn char_class(ch: u8) -> u64 { u64::from(CHAR_MAP[usize::from(ch) - 32]) }
+
+/// Multiplies a residue by `x` modulo the generator.
+fn polymod_shift(c: u64) -> u64 {
+    let c0 = c >> 35;
+    let mut c = (c & 0x7_ffff_ffff) << 5;
+    for (i, gen) in GEN.iter().enumerate() {
+        if c0 & (1 << i) != 0 {
+            c ^= gen;
+        }
+    }
+    c
+}
+
+/// Packs checksum characters into a 40-bit residue, if they are all valid.
+fn checksum_value<I: Iterator<Item = char>>(chars: I) -> Option<u64> {
+    chars.into_iter().try_fold(0, |acc, ch| {
+        Fe32::from_char(ch)
+            .ok()
+            .map(|fe| (acc << 5) | u64::from(fe.to_u8()))
+    })
+}
+
 /// An engine to compute a checksum from a string.
 pub struct Engine {
     inner: bech32::primitives::checksum::Engine<DescriptorChecksum>,
@@ -256,6 +476,39 @@ impl fmt::Write for Formatter<'_, '_> {
     }
 }
 
+/// A wrapper around any `fmt::Write` which checksums everything written through it.
+///
+/// This allows a descriptor string to be assembled piecewise, e.g. with `write!`,
+/// without re-scanning the result to compute its checksum.
+///
+/// Writing a character outside of the descriptor character set returns an error.
+pub struct Writer<W> {
+    inner: W,
+    eng: Engine,
+}
+
+impl<W: fmt::Write> Writer<W> {
+    /// Constructs a new `Writer`, wrapping a given `fmt::Write`.
+    pub fn new(inner: W) -> Self { Writer { inner, eng: Engine::new() } }
+
+    /// Writes `#` followed by the checksum of all data written so far, and returns
+    /// the underlying writer.
+    pub fn finish(mut self) -> Result<W, fmt::Error> {
+        self.inner.write_char('#')?;
+        for ch in self.eng.checksum_chars().iter().copied() {
+            self.inner.write_char(ch)?;
+        }
+        Ok(self.inner)
+    }
+}
+
+impl<W: fmt::Write> fmt::Write for Writer<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.eng.input(s).map_err(|_| fmt::Error)?;
+        self.inner.write_str(s)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use core::str;
@@ -337,4 +590,100 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn writer() {
+        use core::fmt::Write as _;
+
+        let mut w = Writer::new(String::new());
+        write!(w, "raw(").unwrap();
+        for byte in [0xde, 0xad, 0xbe, 0xef] {
+            write!(w, "{:02x}", byte).unwrap();
+        }
+        w.write_char(')').unwrap();
+        assert_eq!(w.finish().unwrap(), "raw(deadbeef)#89f8spxm");
+
+        let mut w = Writer::new(String::new());
+        assert!(w.write_str("raw(Ü)").is_err());
+    }
+
+    #[test]
+    fn corrections() {
+        let valid = "raw(deadbeef)#89f8spxm";
+        assert_eq!(suggest_corrections(valid), vec![]);
+        assert_eq!(suggest_corrections("raw(deadbeef)"), vec![]);
+        assert_eq!(suggest_corrections("raw(deadbeef)#89f8spx"), vec![]);
+
+        // Every single-character substitution can be corrected.
+        for pos in (0..valid.len()).filter(|&pos| pos != 13) {
+            for ch in ['0', 'x', 'A', ')', '?'] {
+                if valid.as_bytes()[pos] == ch as u8 {
+                    continue;
+                }
+                let corrupted = Correction { pos, ch }.apply(valid);
+                assert_eq!(
+                    suggest_corrections(&corrupted),
+                    vec![Correction { pos, ch: valid.as_bytes()[pos].into() }],
+                    "{}",
+                    corrupted,
+                );
+            }
+        }
+
+        // A typo in a longer descriptor.
+        let desc = "pkh(tpubD6NzVbkrYhZ4XHndKkuB8FifXm8r5FQHwrN6oZuWCz13qb93rtgKvD4PQsqC4HP4yhV3tA2fqr2RbY5mNXfM7RxXUoeABoDtsFUq2zJq6YK/44'/1'/0'/0/*)#lasegmfs";
+        let typo = desc.replacen("Xm8r", "Xn8r", 1);
+        let corrections = suggest_corrections(&typo);
+        assert_eq!(corrections, vec![Correction { pos: 33, ch: 'm' }]);
+        assert_eq!(corrections[0].apply(&typo), desc);
+
+        // Two errors cannot be corrected.
+        let typo = desc.replacen("Xm8r", "Xn9r", 1);
+        assert_eq!(suggest_corrections(&typo), vec![]);
+    }
+
+    #[test]
+    fn add_strip() {
+        assert_eq!(add_checksum("raw(deadbeef)").unwrap(), "raw(deadbeef)#89f8spxm");
+        assert_eq!(add_checksum("raw(deadbeef)#89f8spxm").unwrap(), "raw(deadbeef)#89f8spxm");
+        assert!(matches!(
+            add_checksum("raw(deadbeef)#89f8spxx"),
+            Err(Error::InvalidChecksum { .. }),
+        ));
+        assert!(matches!(
+            add_checksum("raw(Ü)"),
+            Err(Error::InvalidCharacter { ch: 'Ü', pos: 4 })
+        ));
+
+        assert_eq!(strip_checksum("raw(deadbeef)#89f8spxm").unwrap(), "raw(deadbeef)");
+        assert_eq!(strip_checksum("raw(deadbeef)"), Err(Error::MissingChecksum));
+        assert!(matches!(
+            strip_checksum("raw(deadbeef)#89f8spx"),
+            Err(Error::InvalidChecksumLength { actual: 7, expected: 8 }),
+        ));
+    }
+
+    #[test]
+    fn from_bytes() {
+        assert_eq!(str_from_bytes(b"raw(deadbeef)#89f8spxm").unwrap(), "raw(deadbeef)#89f8spxm");
+        assert_eq!(
+            str_from_bytes("raw(Ü)".as_bytes()),
+            Err(Error::InvalidCharacter { ch: 'Ü', pos: 4 })
+        );
+        assert_eq!(
+            str_from_bytes(b"raw(\xff)"),
+            Err(Error::InvalidCharacter { ch: char::REPLACEMENT_CHARACTER, pos: 4 })
+        );
+        assert_eq!(str_from_bytes(b"raw(\n)"), Err(Error::InvalidCharacter { ch: '\n', pos: 4 }));
+        // Characters are reported in order, whether or not they are valid UTF-8.
+        assert_eq!(
+            str_from_bytes(b"raw(\n\xff)"),
+            Err(Error::InvalidCharacter { ch: '\n', pos: 4 })
+        );
+        assert_eq!(
+            utf8_from_bytes(b"raw(\n\xff)"),
+            Err(Error::InvalidCharacter { ch: '\n', pos: 4 })
+        );
+        assert_eq!(utf8_from_bytes(b"raw(\n)"), Ok("raw(\n)"));
+    }
 }
@@ -1361,6 +1361,27 @@ impl Serialize for DescriptorPublicKey {
     }
 }
 
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for DefiniteDescriptorKey {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        DefiniteDescriptorKey::from_str(&s).map_err(crate::serde::de::Error::custom)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for DefiniteDescriptorKey {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
 #[cfg(test)]
 mod test {
     use core::str::FromStr;
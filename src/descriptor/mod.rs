@@ -17,32 +17,39 @@ use core::str::{self, FromStr};
 
 use bitcoin::hashes::{hash160, ripemd160, sha256};
 use bitcoin::{
-    secp256k1, Address, Network, Script, ScriptBuf, TxIn, Weight, Witness, WitnessVersion,
+    secp256k1, Address, Amount, FeeRate, Network, Script, ScriptBuf, TxIn, Weight, Witness,
+    WitnessVersion,
 };
 use sync::Arc;
 
 use crate::expression::FromTree as _;
 use crate::miniscript::decode::Terminal;
 use crate::miniscript::{satisfy, Legacy, Miniscript, Segwitv0};
-use crate::plan::{AssetProvider, Plan};
+use crate::plan::{
+    AssetProvider, AssumingProvider, MissingAssets, Plan, Plans, TapSpendPath, TapSpendPlan,
+};
 use crate::prelude::*;
 use crate::{
-    expression, hash256, BareCtx, Error, ForEachKey, FromStrKey, MiniscriptKey, ParseError,
-    Satisfier, ToPublicKey, TranslateErr, Translator,
+    expression, hash256, BareCtx, Error, ForEachKey, FromStrKey, MiniscriptKey,
+    ParseDescriptorError, ParseError, Satisfier, SigSizes, ToPublicKey, TranslateErr, Translator,
 };
 
+mod addr;
 mod bare;
+mod raw;
 mod segwitv0;
 mod sh;
 mod sortedmulti;
 mod tr;
 
 // Descriptor Exports
+pub use self::addr::Addr;
 pub use self::bare::{Bare, Pkh};
+pub use self::raw::Raw;
 pub use self::segwitv0::{Wpkh, Wsh, WshInner};
 pub use self::sh::{Sh, ShInner};
 pub use self::sortedmulti::SortedMultiVec;
-pub use self::tr::{TapTree, TapTreeIter, TapTreeIterItem, Tr};
+pub use self::tr::{RawTapLeaf, TapTree, TapTreeIter, TapTreeIterItem, Tr};
 
 pub mod checksum;
 mod key;
@@ -53,6 +60,82 @@ pub use self::key::{
     MalformedKeyDataKind, SinglePriv, SinglePub, SinglePubKey, Wildcard,
 };
 
+/// The weight of a satisfaction, split into its non-witness (scriptSig) and
+/// witness components.
+///
+/// Non-witness data counts four weight units per byte and witness data one,
+/// so sizes should be converted to weights with [`Self::from_scriptsig_size`]
+/// and [`Self::from_witness_size`] rather than summed as bytes.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub struct SatisfactionWeight {
+    /// Weight of the scriptSig data.
+    pub scriptsig: Weight,
+    /// Weight of the witness data.
+    pub witness: Weight,
+}
+
+impl SatisfactionWeight {
+    /// The weight of a satisfaction with neither scriptSig nor witness data.
+    pub const ZERO: SatisfactionWeight =
+        SatisfactionWeight { scriptsig: Weight::ZERO, witness: Weight::ZERO };
+
+    /// The weight of a satisfaction consisting of `size` bytes of scriptSig.
+    pub fn from_scriptsig_size(size: usize) -> Self {
+        SatisfactionWeight {
+            scriptsig: Weight::from_non_witness_data_size(size as u64),
+            witness: Weight::ZERO,
+        }
+    }
+
+    /// The weight of a satisfaction consisting of `size` bytes of witness.
+    pub fn from_witness_size(size: usize) -> Self {
+        SatisfactionWeight {
+            scriptsig: Weight::ZERO,
+            witness: Weight::from_witness_data_size(size as u64),
+        }
+    }
+
+    /// The total weight of the satisfaction.
+    pub fn total(&self) -> Weight { self.scriptsig + self.witness }
+}
+
+impl core::ops::Add for SatisfactionWeight {
+    type Output = SatisfactionWeight;
+
+    fn add(self, rhs: SatisfactionWeight) -> SatisfactionWeight {
+        SatisfactionWeight {
+            scriptsig: self.scriptsig + rhs.scriptsig,
+            witness: self.witness + rhs.witness,
+        }
+    }
+}
+
+/// The size of a satisfaction, split into its scriptSig and witness
+/// components.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub struct SatisfactionSize {
+    /// Size in bytes of the scriptSig, excluding its length prefix.
+    pub script_sig_bytes: usize,
+    /// Size in bytes of the witness stack elements, including their length
+    /// prefixes but excluding the prefix counting the elements.
+    pub witness_bytes: usize,
+    /// Number of elements on the witness stack.
+    pub witness_items: usize,
+}
+
+impl SatisfactionSize {
+    /// A satisfaction consisting of `bytes` bytes of scriptSig.
+    pub fn from_script_sig_bytes(bytes: usize) -> Self {
+        SatisfactionSize { script_sig_bytes: bytes, witness_bytes: 0, witness_items: 0 }
+    }
+
+    /// A satisfaction consisting of `items` witness stack elements, taking
+    /// `bytes` bytes in total.
+    pub fn from_witness(bytes: usize, items: usize) -> Self {
+        SatisfactionSize { script_sig_bytes: 0, witness_bytes: bytes, witness_items: items }
+    }
+}
+
 /// Alias type for a map of public key to secret key
 ///
 /// This map is returned whenever a descriptor that contains secrets is parsed using
@@ -76,6 +159,10 @@ pub enum Descriptor<Pk: MiniscriptKey> {
     Wsh(Wsh<Pk>),
     /// Pay-to-Taproot
     Tr(Tr<Pk>),
+    /// An address, without any knowledge of its keys or script
+    Addr(Addr),
+    /// A raw script pubkey, without any knowledge of its keys or script
+    Raw(Raw),
 }
 
 impl<Pk: MiniscriptKey> From<Bare<Pk>> for Descriptor<Pk> {
@@ -108,6 +195,16 @@ impl<Pk: MiniscriptKey> From<Tr<Pk>> for Descriptor<Pk> {
     fn from(inner: Tr<Pk>) -> Self { Descriptor::Tr(inner) }
 }
 
+impl<Pk: MiniscriptKey> From<Addr> for Descriptor<Pk> {
+    #[inline]
+    fn from(inner: Addr) -> Self { Descriptor::Addr(inner) }
+}
+
+impl<Pk: MiniscriptKey> From<Raw> for Descriptor<Pk> {
+    #[inline]
+    fn from(inner: Raw) -> Self { Descriptor::Raw(inner) }
+}
+
 /// Descriptor Type of the descriptor
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
 pub enum DescriptorType {
@@ -133,12 +230,18 @@ pub enum DescriptorType {
     ShWshSortedMulti,
     /// Tr Descriptor
     Tr,
+    /// Addr Descriptor
+    Addr,
+    /// Raw Descriptor
+    Raw,
 }
 
 impl DescriptorType {
     /// Returns the segwit version implied by the descriptor type.
     ///
     /// This will return `Some(WitnessVersion::V0)` whether it is "native" segwitv0 or "wrapped" p2sh segwit.
+    /// It returns `None` for `addr()` and `raw()` descriptors, whose type does not tell the kind
+    /// of script pubkey.
     pub fn segwit_version(&self) -> Option<WitnessVersion> {
         use self::DescriptorType::*;
         match self {
@@ -146,7 +249,7 @@ impl DescriptorType {
             Wpkh | ShWpkh | Wsh | ShWsh | ShWshSortedMulti | WshSortedMulti => {
                 Some(WitnessVersion::V0)
             }
-            Bare | Sh | Pkh | ShSortedMulti => None,
+            Bare | Sh | Pkh | ShSortedMulti | Addr | Raw => None,
         }
     }
 }
@@ -175,6 +278,22 @@ impl<Pk: MiniscriptKey> Descriptor<Pk> {
     /// Errors when uncompressed keys are supplied
     pub fn new_sh_wpkh(pk: Pk) -> Result<Self, Error> { Ok(Descriptor::Sh(Sh::new_wpkh(pk)?)) }
 
+    /// Create the descriptors a `combo()` descriptor of Bitcoin Core expands to.
+    ///
+    /// These are pk and pkh descriptors, followed by wpkh and sh-wrapped wpkh
+    /// descriptors unless the key is uncompressed.
+    /// Errors when x-only keys are supplied
+    pub fn new_combo(pk: Pk) -> Result<Vec<Self>, Error> {
+        // pkh checks the key first, so that new_pk does not panic on x-only keys
+        let pkh = Self::new_pkh(pk.clone())?;
+        let mut ret = vec![Self::new_pk(pk.clone()), pkh];
+        if !pk.is_uncompressed() {
+            ret.push(Self::new_wpkh(pk.clone())?);
+            ret.push(Self::new_sh_wpkh(pk)?);
+        }
+        Ok(ret)
+    }
+
     // Miniscripts
 
     /// Create a new sh for a given redeem script
@@ -295,6 +414,8 @@ impl<Pk: MiniscriptKey> Descriptor<Pk> {
                 WshInner::Ms(ref _ms) => DescriptorType::Wsh,
             },
             Descriptor::Tr(ref _tr) => DescriptorType::Tr,
+            Descriptor::Addr(ref _addr) => DescriptorType::Addr,
+            Descriptor::Raw(ref _raw) => DescriptorType::Raw,
         }
     }
 
@@ -315,18 +436,15 @@ impl<Pk: MiniscriptKey> Descriptor<Pk> {
             Descriptor::Wsh(ref wsh) => wsh.sanity_check(),
             Descriptor::Sh(ref sh) => sh.sanity_check(),
             Descriptor::Tr(ref tr) => tr.sanity_check(),
+            Descriptor::Addr(_) => Ok(()),
+            Descriptor::Raw(_) => Ok(()),
         }
     }
 
     /// Computes an upper bound on the difference between a non-satisfied
     /// `TxIn`'s `segwit_weight` and a satisfied `TxIn`'s `segwit_weight`
     ///
-    /// Since this method uses `segwit_weight` instead of `legacy_weight`,
-    /// if you want to include only legacy inputs in your transaction,
-    /// you should remove 1WU from each input's `max_weight_to_satisfy`
-    /// for a more accurate estimate.
-    ///
-    /// In other words, for segwit inputs or legacy inputs included in
+    /// For segwit inputs or legacy inputs included in
     /// segwit transactions, the following will hold for each input if
     /// that input was satisfied with the largest possible witness:
     /// ```ignore
@@ -355,19 +473,128 @@ impl<Pk: MiniscriptKey> Descriptor<Pk> {
     /// sighash suffix.
     ///
     /// # Errors
-    /// When the descriptor is impossible to safisfy (ex: sh(OP_FALSE)).
+    /// When the descriptor is impossible to safisfy (ex: sh(OP_FALSE)), or is an
+    /// addr() or raw() descriptor, whose script is unknown.
     pub fn max_weight_to_satisfy(&self) -> Result<Weight, Error> {
+        self.max_weight_to_satisfy_with_sig_sizes(&SigSizes::WORST_CASE)
+    }
+
+    /// Computes an upper bound on the difference between a non-satisfied
+    /// `TxIn`'s `segwit_weight` and a satisfied `TxIn`'s `segwit_weight`,
+    /// as [`Self::max_weight_to_satisfy`], but assuming signatures of the
+    /// given sizes.
+    ///
+    /// This gives exact estimates for signers which always produce smaller
+    /// signatures than the worst case, e.g. by grinding for low R values.
+    ///
+    /// ```
+    /// use std::str::FromStr;
+    ///
+    /// use miniscript::bitcoin::PublicKey;
+    /// use miniscript::{Descriptor, SigSizes};
+    ///
+    /// let desc = Descriptor::<PublicKey>::from_str(
+    ///     "wpkh(020000000000000000000000000000000000000000000000000000000000000002)",
+    /// )
+    /// .unwrap();
+    /// let worst_case = desc.max_weight_to_satisfy().unwrap();
+    /// let low_r = desc.max_weight_to_satisfy_with_sig_sizes(&SigSizes::LOW_R).unwrap();
+    /// assert_eq!(worst_case.to_wu() - low_r.to_wu(), 1);
+    /// ```
+    ///
+    /// # Errors
+    /// When the descriptor is impossible to safisfy (ex: sh(OP_FALSE)), or is an
+    /// addr() or raw() descriptor, whose script is unknown.
+    pub fn max_weight_to_satisfy_with_sig_sizes(
+        &self,
+        sig_sizes: &SigSizes,
+    ) -> Result<Weight, Error> {
+        Ok(self.max_weight_to_satisfy_parts(sig_sizes)?.total())
+    }
+
+    /// Computes upper bounds on the weights of the scriptSig and witness
+    /// components of the difference between a non-satisfied `TxIn`'s
+    /// `segwit_weight` and a satisfied `TxIn`'s `segwit_weight`, assuming
+    /// signatures of the given sizes.
+    ///
+    /// ```
+    /// use std::str::FromStr;
+    ///
+    /// use miniscript::bitcoin::{PublicKey, Weight};
+    /// use miniscript::{Descriptor, SigSizes};
+    ///
+    /// let desc = Descriptor::<PublicKey>::from_str(
+    ///     "sh(wpkh(020000000000000000000000000000000000000000000000000000000000000002))",
+    /// )
+    /// .unwrap();
+    /// let parts = desc.max_weight_to_satisfy_parts(&SigSizes::WORST_CASE).unwrap();
+    /// // The scriptSig pushes the witness program, and the witness holds the
+    /// // signature and key.
+    /// assert_eq!(parts.scriptsig, Weight::from_vb(23).unwrap());
+    /// assert_eq!(parts.witness, Weight::from_wu(73 + 34));
+    /// assert_eq!(parts.total(), desc.max_weight_to_satisfy().unwrap());
+    /// ```
+    ///
+    /// # Errors
+    /// When the descriptor is impossible to safisfy (ex: sh(OP_FALSE)), or is an
+    /// addr() or raw() descriptor, whose script is unknown.
+    pub fn max_weight_to_satisfy_parts(
+        &self,
+        sig_sizes: &SigSizes,
+    ) -> Result<SatisfactionWeight, Error> {
         let weight = match *self {
-            Descriptor::Bare(ref bare) => bare.max_weight_to_satisfy()?,
-            Descriptor::Pkh(ref pkh) => pkh.max_weight_to_satisfy(),
-            Descriptor::Wpkh(ref wpkh) => wpkh.max_weight_to_satisfy(),
-            Descriptor::Wsh(ref wsh) => wsh.max_weight_to_satisfy()?,
-            Descriptor::Sh(ref sh) => sh.max_weight_to_satisfy()?,
-            Descriptor::Tr(ref tr) => tr.max_weight_to_satisfy()?,
+            Descriptor::Bare(ref bare) => bare.max_weight_to_satisfy_parts(sig_sizes)?,
+            Descriptor::Pkh(ref pkh) => pkh.max_weight_to_satisfy_parts(sig_sizes),
+            Descriptor::Wpkh(ref wpkh) => wpkh.max_weight_to_satisfy_parts(sig_sizes),
+            Descriptor::Wsh(ref wsh) => wsh.max_weight_to_satisfy_parts(sig_sizes)?,
+            Descriptor::Sh(ref sh) => sh.max_weight_to_satisfy_parts(sig_sizes)?,
+            Descriptor::Tr(ref tr) => tr.max_weight_to_satisfy_parts(sig_sizes)?,
+            Descriptor::Addr(_) => return Err(Error::AddrNoScript),
+            Descriptor::Raw(_) => return Err(Error::RawNoScript),
         };
         Ok(weight)
     }
 
+    /// Computes upper bounds on the sizes of the scriptSig and witness of a
+    /// satisfying `TxIn`, assuming signatures of the given sizes.
+    ///
+    /// Unlike [`Self::max_weight_to_satisfy`], which gives a single weight,
+    /// this gives the sizes needed to serialize the input, which for wrapped
+    /// segwit descriptors have both scriptSig and witness components.
+    ///
+    /// ```
+    /// use std::str::FromStr;
+    ///
+    /// use miniscript::bitcoin::PublicKey;
+    /// use miniscript::{Descriptor, SatisfactionSize, SigSizes};
+    ///
+    /// let desc = Descriptor::<PublicKey>::from_str(
+    ///     "sh(wpkh(020000000000000000000000000000000000000000000000000000000000000002))",
+    /// )
+    /// .unwrap();
+    /// assert_eq!(
+    ///     desc.max_satisfaction_size(&SigSizes::WORST_CASE).unwrap(),
+    ///     SatisfactionSize { script_sig_bytes: 23, witness_bytes: 73 + 34, witness_items: 2 },
+    /// );
+    /// ```
+    ///
+    /// # Errors
+    /// When the descriptor is impossible to safisfy (ex: sh(OP_FALSE)), or is an
+    /// addr() or raw() descriptor, whose script is unknown.
+    pub fn max_satisfaction_size(&self, sig_sizes: &SigSizes) -> Result<SatisfactionSize, Error> {
+        let size = match *self {
+            Descriptor::Bare(ref bare) => bare.max_satisfaction_size(sig_sizes)?,
+            Descriptor::Pkh(ref pkh) => pkh.max_satisfaction_size(sig_sizes),
+            Descriptor::Wpkh(ref wpkh) => wpkh.max_satisfaction_size(sig_sizes),
+            Descriptor::Wsh(ref wsh) => wsh.max_satisfaction_size(sig_sizes)?,
+            Descriptor::Sh(ref sh) => sh.max_satisfaction_size(sig_sizes)?,
+            Descriptor::Tr(ref tr) => tr.max_satisfaction_size(sig_sizes)?,
+            Descriptor::Addr(_) => return Err(Error::AddrNoScript),
+            Descriptor::Raw(_) => return Err(Error::RawNoScript),
+        };
+        Ok(size)
+    }
+
     /// Computes an upper bound on the weight of a satisfying witness to the
     /// transaction.
     ///
@@ -376,7 +603,8 @@ impl<Pk: MiniscriptKey> Descriptor<Pk> {
     /// scriptSig and witness stack length.
     ///
     /// # Errors
-    /// When the descriptor is impossible to safisfy (ex: sh(OP_FALSE)).
+    /// When the descriptor is impossible to safisfy (ex: sh(OP_FALSE)), or is an
+    /// addr() or raw() descriptor, whose script is unknown.
     #[deprecated(
         since = "10.0.0",
         note = "Use max_weight_to_satisfy instead. The method to count bytes was redesigned and the results will differ from max_weight_to_satisfy. For more details check rust-bitcoin/rust-miniscript#476."
@@ -390,6 +618,8 @@ impl<Pk: MiniscriptKey> Descriptor<Pk> {
             Descriptor::Wsh(ref wsh) => wsh.max_satisfaction_weight()?,
             Descriptor::Sh(ref sh) => sh.max_satisfaction_weight()?,
             Descriptor::Tr(ref tr) => tr.max_satisfaction_weight()?,
+            Descriptor::Addr(_) => return Err(Error::AddrNoScript),
+            Descriptor::Raw(_) => return Err(Error::RawNoScript),
         };
         Ok(weight)
     }
@@ -409,6 +639,8 @@ impl<Pk: MiniscriptKey> Descriptor<Pk> {
             Descriptor::Sh(ref sh) => Descriptor::Sh(sh.translate_pk(t)?),
             Descriptor::Wsh(ref wsh) => Descriptor::Wsh(wsh.translate_pk(t)?),
             Descriptor::Tr(ref tr) => Descriptor::Tr(tr.translate_pk(t)?),
+            Descriptor::Addr(ref addr) => Descriptor::Addr(addr.clone()),
+            Descriptor::Raw(ref raw) => Descriptor::Raw(raw.clone()),
         };
         Ok(desc)
     }
@@ -420,7 +652,9 @@ impl<Pk: MiniscriptKey + ToPublicKey> Descriptor<Pk> {
     /// Some descriptors like pk() don't have an address.
     ///
     /// # Errors
-    /// For raw/bare descriptors that don't have an address.
+    /// For bare descriptors that don't have an address, for raw() descriptors whose
+    /// script pubkey has no address, and for addr() descriptors whose address is not
+    /// valid for `network`.
     pub fn address(&self, network: Network) -> Result<Address, Error> {
         match *self {
             Descriptor::Bare(_) => Err(Error::BareDescriptorAddr),
@@ -429,6 +663,8 @@ impl<Pk: MiniscriptKey + ToPublicKey> Descriptor<Pk> {
             Descriptor::Wsh(ref wsh) => Ok(wsh.address(network)),
             Descriptor::Sh(ref sh) => Ok(sh.address(network)),
             Descriptor::Tr(ref tr) => Ok(tr.address(network)),
+            Descriptor::Addr(ref addr) => addr.address(network),
+            Descriptor::Raw(ref raw) => raw.address(network),
         }
     }
 
@@ -441,6 +677,50 @@ impl<Pk: MiniscriptKey + ToPublicKey> Descriptor<Pk> {
             Descriptor::Wsh(ref wsh) => wsh.script_pubkey(),
             Descriptor::Sh(ref sh) => sh.script_pubkey(),
             Descriptor::Tr(ref tr) => tr.script_pubkey(),
+            Descriptor::Addr(ref addr) => addr.script_pubkey(),
+            Descriptor::Raw(ref raw) => raw.script_pubkey(),
+        }
+    }
+
+    /// Appends the script pubkey of this descriptor to `script`.
+    ///
+    /// This is equivalent to extending `script` with [`Descriptor::script_pubkey`],
+    /// but reuses the allocation of `script`, so that loops generating script
+    /// pubkeys for many derivation indices need not allocate on every iteration.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::str::FromStr;
+    ///
+    /// use miniscript::bitcoin::ScriptBuf;
+    /// use miniscript::{Descriptor, DescriptorPublicKey};
+    ///
+    /// let desc = Descriptor::<DescriptorPublicKey>::from_str(
+    ///     "wpkh(tpubD6NzVbkrYhZ4YqYr3amYH15zjxHvBkUUeadieW8AxTZC7aY2L8aPSk3tpW6yW1QnWzXAB7zoiaNMfwXPPz9S68ZCV4yWvkVXjdeksLskCed/0/*)",
+    /// )
+    /// .unwrap();
+    /// let mut script = ScriptBuf::with_capacity(34);
+    /// for index in 0..10 {
+    ///     let desc = desc.at_derivation_index(index).unwrap();
+    ///     // Empty the buffer, keeping its allocation.
+    ///     let mut bytes = script.into_bytes();
+    ///     bytes.clear();
+    ///     script = ScriptBuf::from_bytes(bytes);
+    ///     desc.script_pubkey_into(&mut script);
+    ///     assert_eq!(script, desc.script_pubkey());
+    /// }
+    /// ```
+    pub fn script_pubkey_into(&self, script: &mut ScriptBuf) {
+        match *self {
+            Descriptor::Bare(ref bare) => bare.script_pubkey_into(script),
+            Descriptor::Pkh(ref pkh) => pkh.script_pubkey_into(script),
+            Descriptor::Wpkh(ref wpkh) => wpkh.script_pubkey_into(script),
+            Descriptor::Wsh(ref wsh) => wsh.script_pubkey_into(script),
+            Descriptor::Sh(ref sh) => sh.script_pubkey_into(script),
+            Descriptor::Tr(ref tr) => tr.script_pubkey_into(script),
+            Descriptor::Addr(ref addr) => addr.script_pubkey_into(script),
+            Descriptor::Raw(ref raw) => raw.script_pubkey_into(script),
         }
     }
 
@@ -459,6 +739,8 @@ impl<Pk: MiniscriptKey + ToPublicKey> Descriptor<Pk> {
             Descriptor::Wsh(_) => ScriptBuf::new(),
             Descriptor::Sh(ref sh) => sh.unsigned_script_sig(),
             Descriptor::Tr(_) => ScriptBuf::new(),
+            Descriptor::Addr(_) => ScriptBuf::new(),
+            Descriptor::Raw(_) => ScriptBuf::new(),
         }
     }
 
@@ -467,7 +749,7 @@ impl<Pk: MiniscriptKey + ToPublicKey> Descriptor<Pk> {
     /// this is the redeemScript; for the others it is the witness script.
     ///
     /// # Errors
-    /// If the descriptor is a taproot descriptor.
+    /// If the descriptor is a taproot, addr() or raw() descriptor.
     pub fn explicit_script(&self) -> Result<ScriptBuf, Error> {
         match *self {
             Descriptor::Bare(ref bare) => Ok(bare.script_pubkey()),
@@ -476,6 +758,8 @@ impl<Pk: MiniscriptKey + ToPublicKey> Descriptor<Pk> {
             Descriptor::Wsh(ref wsh) => Ok(wsh.inner_script()),
             Descriptor::Sh(ref sh) => Ok(sh.inner_script()),
             Descriptor::Tr(_) => Err(Error::TrNoScriptCode),
+            Descriptor::Addr(_) => Err(Error::AddrNoScript),
+            Descriptor::Raw(_) => Err(Error::RawNoScript),
         }
     }
 
@@ -485,7 +769,7 @@ impl<Pk: MiniscriptKey + ToPublicKey> Descriptor<Pk> {
     /// serialized in the sighash when evaluating a `CHECKSIG` & co. OP code.
     ///
     /// # Errors
-    /// If the descriptor is a taproot descriptor.
+    /// If the descriptor is a taproot, addr() or raw() descriptor.
     pub fn script_code(&self) -> Result<ScriptBuf, Error> {
         match *self {
             Descriptor::Bare(ref bare) => Ok(bare.ecdsa_sighash_script_code()),
@@ -494,6 +778,8 @@ impl<Pk: MiniscriptKey + ToPublicKey> Descriptor<Pk> {
             Descriptor::Wsh(ref wsh) => Ok(wsh.ecdsa_sighash_script_code()),
             Descriptor::Sh(ref sh) => Ok(sh.ecdsa_sighash_script_code()),
             Descriptor::Tr(_) => Err(Error::TrNoScriptCode),
+            Descriptor::Addr(_) => Err(Error::AddrNoScript),
+            Descriptor::Raw(_) => Err(Error::RawNoScript),
         }
     }
 
@@ -511,6 +797,8 @@ impl<Pk: MiniscriptKey + ToPublicKey> Descriptor<Pk> {
             Descriptor::Wsh(ref wsh) => wsh.get_satisfaction(satisfier),
             Descriptor::Sh(ref sh) => sh.get_satisfaction(satisfier),
             Descriptor::Tr(ref tr) => tr.get_satisfaction(&satisfier),
+            Descriptor::Addr(_) => Err(Error::AddrNoScript),
+            Descriptor::Raw(_) => Err(Error::RawNoScript),
         }
     }
 
@@ -528,6 +816,8 @@ impl<Pk: MiniscriptKey + ToPublicKey> Descriptor<Pk> {
             Descriptor::Wsh(ref wsh) => wsh.get_satisfaction_mall(satisfier),
             Descriptor::Sh(ref sh) => sh.get_satisfaction_mall(satisfier),
             Descriptor::Tr(ref tr) => tr.get_satisfaction_mall(&satisfier),
+            Descriptor::Addr(_) => Err(Error::AddrNoScript),
+            Descriptor::Raw(_) => Err(Error::RawNoScript),
         }
     }
 
@@ -548,9 +838,26 @@ impl<Pk: MiniscriptKey + ToPublicKey> Descriptor<Pk> {
 impl Descriptor<DefiniteDescriptorKey> {
     /// Returns a plan if the provided assets are sufficient to produce a non-malleable satisfaction
     ///
-    /// If the assets aren't sufficient for generating a Plan, the descriptor is returned
+    /// This is the cheapest plan accepted by [`AssetProvider::provider_accepts_plan`]. If the
+    /// assets aren't sufficient for generating a Plan, the descriptor is returned
     #[allow(clippy::result_large_err)] // our "error type" is the original descriptor
     pub fn plan<P>(self, provider: &P) -> Result<Plan, Self>
+    where
+        P: AssetProvider<DefiniteDescriptorKey>,
+    {
+        let plan = self.plan_cheapest(provider)?;
+        if provider.provider_accepts_plan(&plan) {
+            Ok(plan)
+        } else {
+            // Look for the cheapest plan the provider accepts
+            let accepted = plan.descriptor.plans(provider, 1).next();
+            accepted.ok_or(plan.descriptor)
+        }
+    }
+
+    /// Returns the cheapest plan, whether the provider accepts it or not
+    #[allow(clippy::result_large_err)] // our "error type" is the original descriptor
+    pub(crate) fn plan_cheapest<P>(self, provider: &P) -> Result<Plan, Self>
     where
         P: AssetProvider<DefiniteDescriptorKey>,
     {
@@ -561,8 +868,18 @@ impl Descriptor<DefiniteDescriptorKey> {
             Descriptor::Wsh(ref wsh) => wsh.plan_satisfaction(provider),
             Descriptor::Sh(ref sh) => sh.plan_satisfaction(provider),
             Descriptor::Tr(ref tr) => tr.plan_satisfaction(provider),
+            Descriptor::Addr(_) => return Err(self),
+            Descriptor::Raw(_) => return Err(self),
         };
+        self.plan_from_satisfaction(satisfaction)
+    }
 
+    /// Turns a satisfaction template of this descriptor into a plan
+    #[allow(clippy::result_large_err)] // our "error type" is the original descriptor
+    fn plan_from_satisfaction(
+        self,
+        satisfaction: satisfy::Satisfaction<satisfy::Placeholder<DefiniteDescriptorKey>>,
+    ) -> Result<Plan, Self> {
         if let satisfy::Witness::Stack(stack) = satisfaction.stack {
             Ok(Plan {
                 descriptor: self,
@@ -575,9 +892,62 @@ impl Descriptor<DefiniteDescriptorKey> {
         }
     }
 
+    /// Returns a plan spending a Taproot descriptor through the chosen spending `path`
+    ///
+    /// Both the key path and the cheapest non-malleable script path are planned, and the fees
+    /// of those accepted by [`AssetProvider::provider_accepts_plan`] are computed at
+    /// `fee_rate` and reported along with the selected plan. If the descriptor is not a
+    /// Taproot descriptor, or no accepted plan satisfies `path`, the descriptor is returned
+    #[allow(clippy::result_large_err)] // our "error type" is the original descriptor
+    pub fn plan_tap_spend<P>(
+        self,
+        provider: &P,
+        path: TapSpendPath,
+        fee_rate: FeeRate,
+    ) -> Result<TapSpendPlan, Self>
+    where
+        P: AssetProvider<DefiniteDescriptorKey>,
+    {
+        let (key_spend, script_spend) = match self {
+            Descriptor::Tr(ref tr) => (tr.plan_key_spend(provider), tr.plan_script_spend(provider)),
+            _ => return Err(self),
+        };
+        let accepted = |satisfaction| {
+            self.clone()
+                .plan_from_satisfaction(satisfaction)
+                .ok()
+                .filter(|plan| provider.provider_accepts_plan(plan))
+        };
+        let key_path = accepted(key_spend);
+        let script_path = accepted(script_spend);
+        let fee = |plan: &Plan| {
+            fee_rate
+                .fee_wu(plan.satisfaction_weight())
+                .unwrap_or(Amount::MAX)
+        };
+        let key_path_fee = key_path.as_ref().map(fee);
+        let script_path_fee = script_path.as_ref().map(fee);
+
+        let plan = match path {
+            TapSpendPath::KeyPath => key_path,
+            TapSpendPath::ScriptPath => script_path,
+            TapSpendPath::Auto { max_script_path_fee } => key_path.or_else(|| {
+                script_path.filter(|_| match (script_path_fee, max_script_path_fee) {
+                    (Some(fee), Some(max)) => fee <= max,
+                    _ => true,
+                })
+            }),
+        };
+        match plan {
+            Some(plan) => Ok(TapSpendPlan { plan, key_path_fee, script_path_fee }),
+            None => Err(self),
+        }
+    }
+
     /// Returns a plan if the provided assets are sufficient to produce a malleable satisfaction
     ///
-    /// If the assets aren't sufficient for generating a Plan, the descriptor is returned
+    /// If the assets aren't sufficient for generating a Plan, or the cheapest plan is not
+    /// accepted by [`AssetProvider::provider_accepts_plan`], the descriptor is returned
     #[allow(clippy::result_large_err)] // our "error type" is the original descriptor
     pub fn plan_mall<P>(self, provider: &P) -> Result<Plan, Self>
     where
@@ -590,22 +960,63 @@ impl Descriptor<DefiniteDescriptorKey> {
             Descriptor::Wsh(ref wsh) => wsh.plan_satisfaction_mall(provider),
             Descriptor::Sh(ref sh) => sh.plan_satisfaction_mall(provider),
             Descriptor::Tr(ref tr) => tr.plan_satisfaction_mall(provider),
+            Descriptor::Addr(_) => return Err(self),
+            Descriptor::Raw(_) => return Err(self),
         };
 
-        if let satisfy::Witness::Stack(stack) = satisfaction.stack {
-            Ok(Plan {
-                descriptor: self,
-                template: stack,
-                absolute_timelock: satisfaction.absolute_timelock.map(Into::into),
-                // unwrap to be removed in a later commit
-                relative_timelock: satisfaction.relative_timelock.map(Into::into),
-            })
+        let plan = self.plan_from_satisfaction(satisfaction)?;
+        if provider.provider_accepts_plan(&plan) {
+            Ok(plan)
         } else {
-            Err(self)
+            Err(plan.descriptor)
         }
     }
+
+    /// Returns the plans which produce a non-malleable satisfaction with the provided assets,
+    /// from the cheapest and up to `limit` of them
+    ///
+    /// Each plan is the cheapest one when some of the assets used by the previous ones are
+    /// withheld, so that a plan not using a particular key or preimage can be picked. Plans which
+    /// cost more than another one using a subset of their assets are not returned.
+    pub fn plans<'a, P>(&self, provider: &'a P, limit: usize) -> Plans<'a, P>
+    where
+        P: AssetProvider<DefiniteDescriptorKey>,
+    {
+        Plans::new(self.clone(), provider, limit)
+    }
+
+    /// Returns what is missing to spend this descriptor along its most promising spending paths
+    ///
+    /// This is meant to explain why [`Descriptor::plan`] or a satisfaction failed, e.g. to tell
+    /// the user which cosigners still have to sign or how long to wait. The plans found when
+    /// assuming every signature, preimage and timelock to be available are compared, and the ones
+    /// missing the fewest items from `provider` are returned, from the cheapest. Only the 16
+    /// cheapest such plans are considered. If nothing is missing from the first one, the
+    /// descriptor can already be spent.
+    ///
+    /// Satisfiers for [`DefiniteDescriptorKey`]s are asset providers, so they can be passed too.
+    /// If the descriptor cannot be spent at all, e.g. because the key of a raw public key hash is
+    /// unknown, no plan is returned.
+    pub fn missing_assets<P>(&self, provider: &P) -> Vec<MissingAssets>
+    where
+        P: AssetProvider<DefiniteDescriptorKey>,
+    {
+        let assuming = AssumingProvider { inner: provider };
+        let candidates = self
+            .plans(&assuming, MISSING_ASSETS_CANDIDATES)
+            .map(|plan| MissingAssets::new(plan, provider))
+            .collect::<Vec<_>>();
+        let fewest = candidates.iter().map(|c| c.missing.len()).min();
+        candidates
+            .into_iter()
+            .filter(|c| Some(c.missing.len()) == fewest)
+            .collect()
+    }
 }
 
+/// How many plans [`Descriptor::missing_assets`] compares
+const MISSING_ASSETS_CANDIDATES: usize = 16;
+
 impl<Pk: MiniscriptKey> ForEachKey<Pk> for Descriptor<Pk> {
     fn for_each_key<'a, F: FnMut(&'a Pk) -> bool>(&'a self, pred: F) -> bool {
         match *self {
@@ -615,6 +1026,8 @@ impl<Pk: MiniscriptKey> ForEachKey<Pk> for Descriptor<Pk> {
             Descriptor::Wsh(ref wsh) => wsh.for_each_key(pred),
             Descriptor::Sh(ref sh) => sh.for_each_key(pred),
             Descriptor::Tr(ref tr) => tr.for_each_key(pred),
+            Descriptor::Addr(_) => true,
+            Descriptor::Raw(_) => true,
         }
     }
 }
@@ -662,6 +1075,46 @@ impl Descriptor<DescriptorPublicKey> {
         self.at_derivation_index(index)
     }
 
+    /// Plans a spend of the descriptor at each of the given derivation `indices`.
+    ///
+    /// Returns a map from each index to the plan [`Descriptor::plan`] computes for the
+    /// descriptor derived at that index. Indices for which the provider doesn't have enough
+    /// assets are left out of the map. If the descriptor has no wildcards every index yields
+    /// the same descriptor, so it is only planned once.
+    ///
+    /// # Errors
+    /// - If an index ≥ 2^31
+    /// - If the descriptor contains multi-path derivations
+    pub fn plan_at_indices<P, I>(
+        &self,
+        provider: &P,
+        indices: I,
+    ) -> Result<BTreeMap<u32, Plan>, ConversionError>
+    where
+        P: AssetProvider<DefiniteDescriptorKey>,
+        I: IntoIterator<Item = u32>,
+    {
+        let mut plans = BTreeMap::new();
+        if self.has_wildcard() {
+            for index in indices {
+                if plans.contains_key(&index) {
+                    continue;
+                }
+                if let Ok(plan) = self.at_derivation_index(index)?.plan(provider) {
+                    plans.insert(index, plan);
+                }
+            }
+        } else {
+            let mut indices = indices.into_iter().peekable();
+            if let Some(&first) = indices.peek() {
+                if let Ok(plan) = self.at_derivation_index(first)?.plan(provider) {
+                    plans.extend(indices.map(|index| (index, plan.clone())));
+                }
+            }
+        }
+        Ok(plans)
+    }
+
     /// Convert all the public keys in the descriptor to [`bitcoin::PublicKey`] by deriving them or
     /// otherwise converting them. All [`bitcoin::secp256k1::XOnlyPublicKey`]s are converted to by adding a
     /// default(0x02) y-coordinate.
@@ -713,7 +1166,7 @@ impl Descriptor<DescriptorPublicKey> {
             let (public_key, secret_key) = match DescriptorSecretKey::from_str(s) {
                 Ok(sk) => (
                     sk.to_public(secp)
-                        .map_err(|e| Error::Unexpected(e.to_string()))?,
+                        .map_err(|e| Error::Parse(ParseError::box_from_str(e)))?,
                     Some(sk),
                 ),
                 Err(_) => (
@@ -966,37 +1419,125 @@ impl Descriptor<DefiniteDescriptorKey> {
 }
 
 impl<Pk: FromStrKey> crate::expression::FromTree for Descriptor<Pk> {
+    type Error = ParseDescriptorError;
     /// Parse an expression tree into a descriptor.
-    fn from_tree(top: expression::TreeIterItem) -> Result<Descriptor<Pk>, Error> {
+    fn from_tree(top: expression::TreeIterItem) -> Result<Descriptor<Pk>, ParseDescriptorError> {
         Ok(match (top.name(), top.n_children()) {
             ("pkh", 1) => Descriptor::Pkh(Pkh::from_tree(top)?),
             ("wpkh", 1) => Descriptor::Wpkh(Wpkh::from_tree(top)?),
             ("sh", 1) => Descriptor::Sh(Sh::from_tree(top)?),
             ("wsh", 1) => Descriptor::Wsh(Wsh::from_tree(top)?),
             ("tr", _) => Descriptor::Tr(Tr::from_tree(top)?),
+            ("addr", _) => Descriptor::Addr(Addr::from_tree(top)?),
+            ("raw", _) => Descriptor::Raw(Raw::from_tree(top)?),
             _ => Descriptor::Bare(Bare::from_tree(top)?),
         })
     }
 }
 
-impl<Pk: FromStrKey> FromStr for Descriptor<Pk> {
-    type Err = Error;
-    fn from_str(s: &str) -> Result<Descriptor<Pk>, Error> {
+impl<Pk: FromStrKey> Descriptor<Pk> {
+    /// Parses a descriptor from a byte slice, such as a network buffer.
+    ///
+    /// Equivalent to [`FromStr::from_str`] on the bytes validated as UTF-8,
+    /// without copying them. See [`expression::Tree::from_bytes`] for how the
+    /// bytes are checked.
+    ///
+    /// There is no entry point taking a `Cow<'_, str>`: a `Cow` dereferences to
+    /// a `&str`, which [`FromStr::from_str`] already parses without copying.
+    pub fn from_bytes(s: &[u8]) -> Result<Self, Error> {
+        let top = expression::Tree::from_bytes(s)?;
+        Self::from_tree_checked(top.root())
+    }
+
+    /// Parses a descriptor, applying custom limits to the expression tree.
+    ///
+    /// Otherwise behaves identically to [`FromStr::from_str`].
+    pub fn parse_with_options(s: &str, options: expression::ParseOptions) -> Result<Self, Error> {
+        let top = expression::Tree::from_str_with_options(s, options)?;
+        Self::from_tree_checked(top.root())
+    }
+
+    /// Parses a descriptor using caller-provided storage for the expression tree.
+    ///
+    /// Only the expression tree is stored in `buffer`: parsing fails, rather
+    /// than allocating, if the descriptor has more expression nodes than
+    /// `buffer` can hold. The descriptor built from the tree is allocated as
+    /// usual, but its size is bounded by the size of the tree, and by
+    /// `max_keys`. The keys are counted in the expression tree, so descriptors
+    /// with more than `max_keys` keys are rejected before any of them is
+    /// parsed. Otherwise it behaves identically to [`FromStr::from_str`].
+    pub fn from_str_in<'s, const N: usize>(
+        s: &'s str,
+        buffer: &mut expression::TreeBuffer<'s, N>,
+        max_keys: usize,
+    ) -> Result<Self, Error> {
+        let root = buffer
+            .parse(s)
+            .map_err(ParseError::Tree)
+            .map_err(Error::Parse)?;
+        if root.pre_order_iter().filter(|&node| is_key_node(node)).count() > max_keys {
+            return Err(Error::TooManyKeys(max_keys));
+        }
+        Self::from_tree_checked(root)
+    }
+
+    /// Parses a `combo(KEY)` descriptor of Bitcoin Core, such as those found in
+    /// dumps of legacy wallets, into the descriptors it expands to.
+    ///
+    /// See [`Descriptor::new_combo`] for the expansion. As with [`FromStr::from_str`],
+    /// a checksum is verified if present.
+    pub fn parse_combo(s: &str) -> Result<Vec<Self>, Error> {
         let top = expression::Tree::from_str(s)?;
-        let ret = Self::from_tree(top.root())?;
+        let pk = top
+            .root()
+            .verify_toplevel("combo", 1..=1)
+            .map_err(ParseError::Tree)
+            .and_then(|child| child.verify_terminal("public key"))
+            .map_err(Error::Parse)?;
+        Self::new_combo(pk)
+    }
+
+    fn from_tree_checked(root: expression::TreeIterItem) -> Result<Self, Error> {
+        let ret = Self::from_tree(root)?;
         if let Descriptor::Tr(ref inner) = ret {
             // FIXME preserve weird/broken behavior from 12.x.
             // See https://github.com/rust-bitcoin/rust-miniscript/issues/734
             ret.sanity_check()?;
-            for item in inner.iter_scripts() {
-                item.miniscript()
-                    .ext_check(&crate::miniscript::analyzable::ExtParams::sane())?;
+            for ms in inner.leaves().filter_map(|item| item.miniscript()) {
+                ms.ext_check(&crate::miniscript::analyzable::ExtParams::sane())?;
             }
         }
         Ok(ret)
     }
 }
 
+/// Whether a node of a descriptor's expression tree is a key, judging only by
+/// its position in the tree.
+fn is_key_node(node: expression::TreeIterItem) -> bool {
+    if node.n_children() > 0 {
+        return false;
+    }
+    let parent_name = match node.parent().map(|parent| parent.name_separated(':')) {
+        Some(Ok((_, name))) => name,
+        _ => return false,
+    };
+    match parent_name {
+        "pk" | "pkh" | "wpkh" | "pk_k" | "pk_h" => true,
+        "tr" => node.is_first_child(),
+        // The first child is the threshold
+        "multi" | "multi_a" | "sortedmulti" | "sortedmulti_a" => !node.is_first_child(),
+        _ => false,
+    }
+}
+
+impl<Pk: FromStrKey> FromStr for Descriptor<Pk> {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Descriptor<Pk>, Error> {
+        let top = expression::Tree::from_str(s)?;
+        Self::from_tree_checked(top.root())
+    }
+}
+
 impl<Pk: MiniscriptKey> fmt::Debug for Descriptor<Pk> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -1006,6 +1547,8 @@ impl<Pk: MiniscriptKey> fmt::Debug for Descriptor<Pk> {
             Descriptor::Sh(ref sub) => fmt::Debug::fmt(sub, f),
             Descriptor::Wsh(ref sub) => fmt::Debug::fmt(sub, f),
             Descriptor::Tr(ref tr) => fmt::Debug::fmt(tr, f),
+            Descriptor::Addr(ref addr) => fmt::Debug::fmt(addr, f),
+            Descriptor::Raw(ref raw) => fmt::Debug::fmt(raw, f),
         }
     }
 }
@@ -1019,6 +1562,8 @@ impl<Pk: MiniscriptKey> fmt::Display for Descriptor<Pk> {
             Descriptor::Sh(ref sub) => fmt::Display::fmt(sub, f),
             Descriptor::Wsh(ref sub) => fmt::Display::fmt(sub, f),
             Descriptor::Tr(ref tr) => fmt::Display::fmt(tr, f),
+            Descriptor::Addr(ref addr) => fmt::Display::fmt(addr, f),
+            Descriptor::Raw(ref raw) => fmt::Display::fmt(raw, f),
         }
     }
 }
@@ -1110,7 +1655,7 @@ mod tests {
             StdDescriptor::from_str(&format!("sh(sortedmulti(2,{}))", &TEST_PK[3..69]))
                 .unwrap_err()
                 .to_string(),
-            "invalid threshold 2-of-1; cannot have k > n",
+            "invalid threshold 2-of-1 (position 15); cannot have k > n",
         ); //issue 202
 
         StdDescriptor::from_str(TEST_PK).unwrap();
@@ -2168,4 +2713,337 @@ pk(03f28773c2d975288bc7d1d205c3748651b075fbc6610e58cddeeddf8f19405aa8))";
         Desc::from_str(&format!("tr({},pk({}))", x_only_key, uncomp_key)).unwrap_err();
         Desc::from_str(&format!("tr({},pk({}))", x_only_key, x_only_key)).unwrap();
     }
+
+    #[test]
+    fn from_str_in() {
+        let s = "wsh(multi(2,020000000000000000000000000000000000000000000000000000000000000002,\
+                 030000000000000000000000000000000000000000000000000000000000000002,\
+                 020000000000000000000000000000000000000000000000000000000000000003))";
+        let expected = StdDescriptor::from_str(s).unwrap();
+
+        // wsh, multi, k and three keys
+        let mut buffer = expression::TreeBuffer::<6>::new();
+        assert_eq!(StdDescriptor::from_str_in(s, &mut buffer, 3).unwrap(), expected);
+        assert!(matches!(
+            StdDescriptor::from_str_in(s, &mut buffer, 2),
+            Err(Error::TooManyKeys(2)),
+        ));
+
+        // The keys are counted before the descriptor is otherwise checked
+        let bad_k = s.replace("multi(2,", "multi(4,");
+        assert!(matches!(
+            StdDescriptor::from_str_in(&bad_k, &mut buffer, 2),
+            Err(Error::TooManyKeys(2)),
+        ));
+        assert!(StdDescriptor::from_str_in(&bad_k, &mut buffer, 3).is_err());
+
+        let tr = format!(
+            "tr({},pk({}))",
+            "020000000000000000000000000000000000000000000000000000000000000002",
+            "030000000000000000000000000000000000000000000000000000000000000002",
+        );
+        let mut tr_buffer = expression::TreeBuffer::<4>::new();
+        assert!(StdDescriptor::from_str_in(&tr, &mut tr_buffer, 2).is_ok());
+        assert!(matches!(
+            StdDescriptor::from_str_in(&tr, &mut tr_buffer, 1),
+            Err(Error::TooManyKeys(1)),
+        ));
+
+        let mut buffer = expression::TreeBuffer::<5>::new();
+        assert!(matches!(
+            StdDescriptor::from_str_in(s, &mut buffer, 3),
+            Err(Error::Parse(ParseError::Tree(
+                expression::ParseTreeError::NodeCapacityExceeded { capacity: 5, .. }
+            ))),
+        ));
+    }
+
+    #[test]
+    fn parse_with_options() {
+        use expression::{ParseOptions, ParseTreeError};
+
+        let s = "wsh(multi(2,020000000000000000000000000000000000000000000000000000000000000002,\
+                 030000000000000000000000000000000000000000000000000000000000000002,\
+                 020000000000000000000000000000000000000000000000000000000000000003))";
+        let expected = StdDescriptor::from_str(s).unwrap();
+        assert_eq!(StdDescriptor::parse_with_options(s, ParseOptions::new()).unwrap(), expected);
+        assert_eq!(
+            StdDescriptor::parse_with_options(
+                s,
+                ParseOptions::new()
+                    .max_depth(2)
+                    .max_length(s.len())
+                    .max_children(4)
+            )
+            .unwrap(),
+            expected
+        );
+
+        assert!(matches!(
+            StdDescriptor::parse_with_options(s, ParseOptions::new().max_depth(1)),
+            Err(Error::Parse(ParseError::Tree(ParseTreeError::MaxRecursionDepthExceeded {
+                actual: 2,
+                maximum: 1,
+            }))),
+        ));
+        assert!(matches!(
+            StdDescriptor::parse_with_options(s, ParseOptions::new().max_length(100)),
+            Err(Error::Parse(ParseError::Tree(ParseTreeError::MaxLengthExceeded {
+                maximum: 100,
+                ..
+            }))),
+        ));
+        assert!(matches!(
+            StdDescriptor::parse_with_options(s, ParseOptions::new().max_children(3)),
+            Err(Error::Parse(ParseError::Tree(ParseTreeError::MaxChildrenExceeded {
+                pos: 146,
+                maximum: 3,
+            }))),
+        ));
+    }
+
+    #[test]
+    fn from_bytes() {
+        let s = "wpkh(020000000000000000000000000000000000000000000000000000000000000002)#d3xz2xye";
+        let buf = s.as_bytes().to_vec();
+        assert_eq!(StdDescriptor::from_bytes(&buf).unwrap(), StdDescriptor::from_str(s).unwrap());
+
+        let mut buf = buf;
+        buf[6] = 0xc3;
+        assert!(matches!(
+            StdDescriptor::from_bytes(&buf),
+            Err(Error::Parse(ParseError::Tree(expression::ParseTreeError::Checksum(
+                checksum::Error::InvalidCharacter { pos: 6, .. }
+            )))),
+        ));
+    }
+
+    #[test]
+    fn script_pubkey_into() {
+        let pk = "020000000000000000000000000000000000000000000000000000000000000002";
+        for desc in [
+            format!("pk({})", pk),
+            format!("pkh({})", pk),
+            format!("wpkh({})", pk),
+            format!("sh(wpkh({}))", pk),
+            format!("sh(multi(1,{}))", pk),
+            format!("sh(sortedmulti(1,{}))", pk),
+            format!("wsh(or_d(pk({}),older(10)))", pk),
+            format!("wsh(sortedmulti(1,{}))", pk),
+            format!("sh(wsh(or_d(pk({}),older(10))))", pk),
+            format!("tr({})", pk),
+            format!("tr({},and_v(v:pk({}),older(10)))", pk, pk),
+            "addr(bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4)".to_owned(),
+            "raw(6a0568656c6c6f)".to_owned(),
+        ] {
+            let desc = StdDescriptor::from_str(&desc).unwrap();
+            // The script pubkey is appended to the existing contents.
+            let mut script = ScriptBuf::from(vec![0x51, 0x52]);
+            desc.script_pubkey_into(&mut script);
+            let mut expected = vec![0x51, 0x52];
+            expected.extend_from_slice(desc.script_pubkey().as_bytes());
+            assert_eq!(script.as_bytes(), &expected[..], "{}", desc);
+        }
+    }
+
+    #[test]
+    fn satisfaction_size_matches_weight() {
+        use crate::util::varint_len;
+
+        let pk = "020000000000000000000000000000000000000000000000000000000000000002";
+        for desc in [
+            format!("pk({})", pk),
+            format!("pkh({})", pk),
+            format!("wpkh({})", pk),
+            format!("sh(wpkh({}))", pk),
+            format!("sh(multi(1,{}))", pk),
+            format!("wsh(or_d(pk({}),older(10)))", pk),
+            format!("sh(wsh(sortedmulti(1,{})))", pk),
+            format!("tr({})", pk),
+            format!("tr({},and_v(v:pk({}),older(10)))", pk, pk),
+        ] {
+            let desc = StdDescriptor::from_str(&desc).unwrap();
+            for sig_sizes in [SigSizes::WORST_CASE, SigSizes::LOW_R] {
+                let size = desc.max_satisfaction_size(&sig_sizes).unwrap();
+                let weight = desc.max_weight_to_satisfy_parts(&sig_sizes).unwrap();
+                assert_eq!(
+                    weight.scriptsig.to_wu() as usize,
+                    4 * (varint_len(size.script_sig_bytes) - varint_len(0) + size.script_sig_bytes),
+                    "{}",
+                    desc,
+                );
+                assert_eq!(
+                    weight.witness.to_wu() as usize,
+                    varint_len(size.witness_items) - varint_len(0) + size.witness_bytes,
+                    "{}",
+                    desc,
+                );
+            }
+        }
+
+        let desc = StdDescriptor::from_str(&format!("sh(wsh(pk({})))", pk)).unwrap();
+        assert_eq!(
+            desc.max_satisfaction_size(&SigSizes::LOW_R).unwrap(),
+            SatisfactionSize { script_sig_bytes: 35, witness_bytes: 72 + 1 + 35, witness_items: 2 },
+        );
+    }
+
+    #[test]
+    fn max_weight_to_satisfy_matches_satisfaction() {
+        // A signature whose R value has its high bit set, so that it takes the
+        // largest standard size: 71 bytes, 73 with push opcode and sighash.
+        let mut compact = [0x11; 64];
+        compact[0] = 0x80;
+        let sig = bitcoin::ecdsa::Signature {
+            signature: secp256k1::ecdsa::Signature::from_compact(&compact).unwrap(),
+            sighash_type: EcdsaSighashType::All,
+        };
+        assert_eq!(sig.to_vec().len(), 72);
+
+        let pks = [
+            "020000000000000000000000000000000000000000000000000000000000000002",
+            "03a1b2c3d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5f60718293a4b5c6d7e8f90",
+        ];
+        let mut sigs = BTreeMap::new();
+        for pk in pks {
+            sigs.insert(PublicKey::from_str(pk).unwrap(), sig);
+        }
+
+        for desc in [
+            format!("pk({})", pks[0]),
+            format!("pkh({})", pks[0]),
+            format!("wpkh({})", pks[0]),
+            format!("sh(wpkh({}))", pks[0]),
+            format!("sh(multi(2,{},{}))", pks[0], pks[1]),
+            format!("sh(sortedmulti(1,{},{}))", pks[0], pks[1]),
+            format!("sh(and_v(v:pk({}),pk({})))", pks[0], pks[1]),
+            format!("wsh(multi(2,{},{}))", pks[0], pks[1]),
+            format!("sh(wsh(and_v(v:pk({}),pk({}))))", pks[0], pks[1]),
+        ] {
+            let desc = StdDescriptor::from_str(&desc).unwrap();
+            let mut txin = TxIn::default();
+            desc.satisfy(&mut txin, &sigs).unwrap();
+
+            let max_weight = desc.max_weight_to_satisfy().unwrap();
+            assert_eq!(
+                txin.segwit_weight() - TxIn::default().segwit_weight(),
+                max_weight,
+                "{}",
+                desc
+            );
+            if txin.witness.is_empty() {
+                // Pre-segwit inputs have the same weight in legacy transactions.
+                assert_eq!(
+                    txin.legacy_weight() - TxIn::default().legacy_weight(),
+                    max_weight,
+                    "{}",
+                    desc
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn addr_descriptor() {
+        use crate::policy::Liftable as _;
+
+        let s = "addr(bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4)";
+        let desc = StdDescriptor::from_str(s).unwrap();
+        assert_eq!(desc.desc_type(), DescriptorType::Addr);
+        assert_eq!(desc.to_string(), checksum::add_checksum(s).unwrap());
+        assert_eq!(
+            desc.script_pubkey(),
+            ScriptBuf::from_hex("0014751e76e8199196d454941c45d1b3a323f1433bd6").unwrap(),
+        );
+
+        let address = desc.address(Network::Bitcoin).unwrap();
+        assert_eq!(address.to_string(), "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4");
+        assert!(matches!(desc.address(Network::Testnet), Err(Error::AddrError(_))));
+
+        // Without the script nothing can be satisfied, estimated or lifted.
+        assert!(matches!(desc.explicit_script(), Err(Error::AddrNoScript)));
+        assert!(matches!(desc.max_weight_to_satisfy(), Err(Error::AddrNoScript)));
+        assert!(matches!(desc.lift(), Err(Error::AddrNoScript)));
+        let sigs = BTreeMap::<PublicKey, bitcoin::ecdsa::Signature>::new();
+        assert!(matches!(desc.get_satisfaction(&sigs), Err(Error::AddrNoScript)));
+
+        // The address is checked when parsing.
+        let bad_checksum = "addr(bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t5)";
+        assert!(StdDescriptor::from_str(bad_checksum).is_err());
+        assert!(StdDescriptor::from_str("addr()").is_err());
+    }
+
+    #[test]
+    fn raw_descriptor() {
+        use crate::policy::Liftable as _;
+
+        // A raw P2WPKH script pubkey has the same address as the addr() descriptor.
+        let s = "raw(0014751e76e8199196d454941c45d1b3a323f1433bd6)";
+        let desc = StdDescriptor::from_str(s).unwrap();
+        assert_eq!(desc.desc_type(), DescriptorType::Raw);
+        assert_eq!(desc.to_string(), checksum::add_checksum(s).unwrap());
+        assert_eq!(StdDescriptor::from_str(&desc.to_string()).unwrap(), desc);
+        let addr =
+            StdDescriptor::from_str("addr(bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4)").unwrap();
+        assert_eq!(desc.script_pubkey(), addr.script_pubkey());
+        assert_eq!(
+            desc.address(Network::Bitcoin).unwrap(),
+            addr.address(Network::Bitcoin).unwrap(),
+        );
+
+        // Non-standard scripts have no address, and nothing can be satisfied,
+        // estimated or lifted without knowing how to spend the script.
+        let desc = StdDescriptor::from_str("raw(6a0568656c6c6f)").unwrap();
+        assert_eq!(desc.script_pubkey().as_bytes(), b"\x6a\x05hello");
+        assert!(matches!(desc.address(Network::Bitcoin), Err(Error::RawAddr(_))));
+        assert!(matches!(desc.explicit_script(), Err(Error::RawNoScript)));
+        assert!(matches!(desc.max_weight_to_satisfy(), Err(Error::RawNoScript)));
+        assert!(matches!(desc.lift(), Err(Error::RawNoScript)));
+
+        assert!(StdDescriptor::from_str("raw(6a0)").is_err());
+        assert!(StdDescriptor::from_str("raw(xx)").is_err());
+        assert!(StdDescriptor::from_str("raw(6a,6a)").is_err());
+    }
+
+    #[test]
+    fn combo() {
+        let pk = "020000000000000000000000000000000000000000000000000000000000000002";
+        let descs = StdDescriptor::parse_combo(&format!("combo({})", pk)).unwrap();
+        let expected = [
+            format!("pk({})", pk),
+            format!("pkh({})", pk),
+            format!("wpkh({})", pk),
+            format!("sh(wpkh({}))", pk),
+        ];
+        assert_eq!(descs.len(), expected.len());
+        for (desc, exp) in descs.iter().zip(expected.iter()) {
+            assert_eq!(*desc, StdDescriptor::from_str(exp).unwrap());
+        }
+
+        // Checksums are verified.
+        let s = checksum::add_checksum(&format!("combo({})", pk)).unwrap();
+        assert_eq!(StdDescriptor::parse_combo(&s).unwrap(), descs);
+        let bad = s.replace('#', "0#");
+        assert!(StdDescriptor::parse_combo(&bad).is_err());
+
+        // Uncompressed keys only expand to the legacy descriptors.
+        let pk = "04a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd5b8dec5235a0fa8722476c7709c02559e3aa73aa03918ba2d492eea75abea235";
+        let descs = StdDescriptor::parse_combo(&format!("combo({})", pk)).unwrap();
+        assert_eq!(descs.len(), 2);
+        assert_eq!(descs[1].desc_type(), DescriptorType::Pkh);
+
+        // Extended keys are always compressed.
+        let xpub = "xpub6ERApfZwUNrhLCkDtcHTcxd75RbzS1ed54G1LkBUHQVHQKqhMkhgbmJbZRkrgZw4koxb5JaHWkY4ALHY2grBGRjaDMzQLcgJvLJuZZvRcEL/1/*";
+        let descs =
+            Descriptor::<DescriptorPublicKey>::parse_combo(&format!("combo({})", xpub)).unwrap();
+        assert_eq!(descs.len(), 4);
+        assert!(descs.iter().all(Descriptor::has_wildcard));
+
+        // X-only keys cannot be used.
+        let xonly = "0000000000000000000000000000000000000000000000000000000000000002";
+        let res = Descriptor::<DescriptorPublicKey>::parse_combo(&format!("combo({})", xonly));
+        assert!(res.is_err());
+        assert!(StdDescriptor::parse_combo(&format!("pk({})", pk)).is_err());
+        assert!(StdDescriptor::parse_combo("combo()").is_err());
+    }
 }
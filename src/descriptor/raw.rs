@@ -0,0 +1,80 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Raw Descriptors
+//!
+//! Implementation of `raw()` descriptors, which wrap an arbitrary scriptPubKey
+//! given as hex, e.g. for outputs in wallet dumps that no other descriptor can
+//! express.
+//!
+
+use core::{fmt, mem};
+
+use bitcoin::{Address, Network, Script, ScriptBuf};
+
+use crate::descriptor::write_descriptor;
+use crate::expression::{self, FromTree};
+use crate::prelude::*;
+use crate::{Error, ParseDescriptorError, ParseError};
+
+/// A `raw()` descriptor, paying to an arbitrary script pubkey
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct Raw {
+    /// underlying script pubkey
+    script: ScriptBuf,
+}
+
+impl Raw {
+    /// Create a new raw descriptor
+    pub fn new(script: ScriptBuf) -> Self { Raw { script } }
+
+    /// Get a reference to the inner script pubkey
+    pub fn as_inner(&self) -> &Script { &self.script }
+
+    /// Get the inner script pubkey
+    pub fn into_inner(self) -> ScriptBuf { self.script }
+
+    /// Obtains the corresponding script pubkey for this descriptor.
+    pub fn script_pubkey(&self) -> ScriptBuf { self.script.clone() }
+
+    /// Appends the script pubkey of this descriptor to `script`.
+    pub fn script_pubkey_into(&self, script: &mut ScriptBuf) {
+        let mut bytes = mem::take(script).into_bytes();
+        bytes.extend_from_slice(self.script.as_bytes());
+        *script = ScriptBuf::from_bytes(bytes);
+    }
+
+    /// Obtains the address of the script pubkey, if it has a standard one.
+    pub fn address(&self, network: Network) -> Result<Address, Error> {
+        Address::from_script(&self.script, network).map_err(Error::RawAddr)
+    }
+}
+
+impl fmt::Debug for Raw {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "raw({:x})", self.script) }
+}
+
+impl fmt::Display for Raw {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_descriptor!(f, "raw({:x})", self.script)
+    }
+}
+
+impl FromTree for Raw {
+    type Error = ParseDescriptorError;
+    fn from_tree(root: expression::TreeIterItem) -> Result<Self, ParseDescriptorError> {
+        let hex: String = root
+            .verify_terminal_parent("raw", "script hex")
+            .map_err(ParseDescriptorError::Parse)?;
+        let script = ScriptBuf::from_hex(&hex)
+            .map_err(|e| ParseDescriptorError::Parse(ParseError::box_from_str(e)))?;
+        Ok(Raw::new(script))
+    }
+}
+
+impl core::str::FromStr for Raw {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let top = expression::Tree::from_str(s)?;
+        Self::from_tree(top.root()).map_err(Error::from)
+    }
+}
@@ -8,20 +8,22 @@
 use core::convert::TryFrom;
 use core::fmt;
 
-use bitcoin::{Address, Network, ScriptBuf, Weight};
+use bitcoin::{opcodes, Address, Network, ScriptBuf, Weight};
 
 use super::SortedMultiVec;
-use crate::descriptor::{write_descriptor, DefiniteDescriptorKey};
+use crate::descriptor::{
+    write_descriptor, DefiniteDescriptorKey, SatisfactionSize, SatisfactionWeight,
+};
 use crate::expression::{self, FromTree};
 use crate::miniscript::context::{ScriptContext, ScriptContextError};
 use crate::miniscript::satisfy::{Placeholder, Satisfaction, Witness};
 use crate::plan::AssetProvider;
 use crate::policy::{semantic, Liftable};
 use crate::prelude::*;
-use crate::util::varint_len;
+use crate::util::{varint_len, wrap_in_p2wsh};
 use crate::{
-    Error, ForEachKey, FromStrKey, Miniscript, MiniscriptKey, Satisfier, Segwitv0, ToPublicKey,
-    TranslateErr, Translator,
+    Error, ForEachKey, FromStrKey, Miniscript, MiniscriptKey, ParseDescriptorError, Satisfier,
+    Segwitv0, SigSizes, ToPublicKey, TranslateErr, Translator,
 };
 /// A Segwitv0 wsh descriptor
 #[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
@@ -73,25 +75,78 @@ impl<Pk: MiniscriptKey> Wsh<Pk> {
     /// # Errors
     /// When the descriptor is impossible to safisfy (ex: sh(OP_FALSE)).
     pub fn max_weight_to_satisfy(&self) -> Result<Weight, Error> {
+        self.max_weight_to_satisfy_with_sig_sizes(&SigSizes::WORST_CASE)
+    }
+
+    /// Computes an upper bound on the difference between a non-satisfied
+    /// `TxIn`'s `segwit_weight` and a satisfied `TxIn`'s `segwit_weight`,
+    /// as [`Self::max_weight_to_satisfy`], but assuming signatures of the
+    /// given sizes.
+    ///
+    /// # Errors
+    /// When the descriptor is impossible to safisfy (ex: sh(OP_FALSE)).
+    pub fn max_weight_to_satisfy_with_sig_sizes(
+        &self,
+        sig_sizes: &SigSizes,
+    ) -> Result<Weight, Error> {
+        Ok(self.max_weight_to_satisfy_parts(sig_sizes)?.total())
+    }
+
+    /// Computes upper bounds on the weights of the scriptSig and witness
+    /// components of the difference between a non-satisfied `TxIn`'s
+    /// `segwit_weight` and a satisfied `TxIn`'s `segwit_weight`, assuming
+    /// signatures of the given sizes. Their total is given by
+    /// [`Self::max_weight_to_satisfy_with_sig_sizes`].
+    ///
+    /// # Errors
+    /// When the descriptor is impossible to safisfy (ex: sh(OP_FALSE)).
+    pub fn max_weight_to_satisfy_parts(
+        &self,
+        sig_sizes: &SigSizes,
+    ) -> Result<SatisfactionWeight, Error> {
         let (redeem_script_size, max_sat_elems, max_sat_size) = match self.inner {
             WshInner::SortedMulti(ref smv) => (
                 smv.script_size(),
                 smv.max_satisfaction_witness_elements(),
-                smv.max_satisfaction_size(),
+                smv.max_satisfaction_size_with_sig_sizes(sig_sizes),
             ),
             WshInner::Ms(ref ms) => (
                 ms.script_size(),
                 ms.max_satisfaction_witness_elements()?,
-                ms.max_satisfaction_size()?,
+                ms.max_satisfaction_size_with_sig_sizes(sig_sizes)?,
             ),
         };
         // stack size varint difference between non-satisfied (0) and satisfied
         // `max_sat_elems` is inclusive of the "witness script" (redeem script)
         let stack_varint_diff = varint_len(max_sat_elems) - varint_len(0);
 
-        Ok(Weight::from_wu(
-            (stack_varint_diff + varint_len(redeem_script_size) + redeem_script_size + max_sat_size)
-                as u64,
+        Ok(SatisfactionWeight::from_witness_size(
+            stack_varint_diff + varint_len(redeem_script_size) + redeem_script_size + max_sat_size,
+        ))
+    }
+
+    /// Computes upper bounds on the sizes of the scriptSig and witness of a
+    /// satisfying `TxIn`, assuming signatures of the given sizes.
+    ///
+    /// # Errors
+    /// When the descriptor is impossible to safisfy (ex: sh(OP_FALSE)).
+    pub fn max_satisfaction_size(&self, sig_sizes: &SigSizes) -> Result<SatisfactionSize, Error> {
+        let (redeem_script_size, max_sat_elems, max_sat_size) = match self.inner {
+            WshInner::SortedMulti(ref smv) => (
+                smv.script_size(),
+                smv.max_satisfaction_witness_elements(),
+                smv.max_satisfaction_size_with_sig_sizes(sig_sizes),
+            ),
+            WshInner::Ms(ref ms) => (
+                ms.script_size(),
+                ms.max_satisfaction_witness_elements()?,
+                ms.max_satisfaction_size_with_sig_sizes(sig_sizes)?,
+            ),
+        };
+        // `max_sat_elems` is inclusive of the "witness script" (redeem script)
+        Ok(SatisfactionSize::from_witness(
+            max_sat_size + varint_len(redeem_script_size) + redeem_script_size,
+            max_sat_elems,
         ))
     }
 
@@ -145,6 +200,16 @@ impl<Pk: MiniscriptKey + ToPublicKey> Wsh<Pk> {
     /// Obtains the corresponding script pubkey for this descriptor.
     pub fn script_pubkey(&self) -> ScriptBuf { self.inner_script().to_p2wsh() }
 
+    /// Appends the script pubkey of this descriptor to `script`.
+    pub fn script_pubkey_into(&self, script: &mut ScriptBuf) {
+        let start = script.len();
+        match self.inner {
+            WshInner::SortedMulti(ref smv) => smv.encode_to(script),
+            WshInner::Ms(ref ms) => ms.encode_to(script),
+        }
+        wrap_in_p2wsh(script, start);
+    }
+
     /// Obtains the corresponding script pubkey for this descriptor.
     pub fn address(&self, network: Network) -> Address {
         match self.inner {
@@ -247,17 +312,18 @@ impl<Pk: MiniscriptKey> Liftable<Pk> for Wsh<Pk> {
 }
 
 impl<Pk: FromStrKey> crate::expression::FromTree for Wsh<Pk> {
-    fn from_tree(top: expression::TreeIterItem) -> Result<Self, Error> {
+    type Error = ParseDescriptorError;
+    fn from_tree(top: expression::TreeIterItem) -> Result<Self, ParseDescriptorError> {
         let top = top
             .verify_toplevel("wsh", 1..=1)
             .map_err(From::from)
-            .map_err(Error::Parse)?;
+            .map_err(ParseDescriptorError::Parse)?;
 
         if top.name() == "sortedmulti" {
             return Ok(Wsh { inner: WshInner::SortedMulti(SortedMultiVec::from_tree(top)?) });
         }
         let sub = Miniscript::from_tree(top)?;
-        Segwitv0::top_level_checks(&sub)?;
+        Segwitv0::top_level_checks(&sub).map_err(ParseDescriptorError::invalid)?;
         Ok(Wsh { inner: WshInner::Ms(sub) })
     }
 }
@@ -284,7 +350,7 @@ impl<Pk: FromStrKey> core::str::FromStr for Wsh<Pk> {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let top = expression::Tree::from_str(s)?;
-        Wsh::<Pk>::from_tree(top.root())
+        Wsh::<Pk>::from_tree(top.root()).map_err(Error::from)
     }
 }
 
@@ -339,11 +405,35 @@ impl<Pk: MiniscriptKey> Wpkh<Pk> {
     /// Assumes all ec-signatures are 73 bytes, including push opcode and
     /// sighash suffix.
     pub fn max_weight_to_satisfy(&self) -> Weight {
+        self.max_weight_to_satisfy_with_sig_sizes(&SigSizes::WORST_CASE)
+    }
+
+    /// Computes an upper bound on the difference between a non-satisfied
+    /// `TxIn`'s `segwit_weight` and a satisfied `TxIn`'s `segwit_weight`,
+    /// as [`Self::max_weight_to_satisfy`], but assuming signatures of the
+    /// given sizes.
+    pub fn max_weight_to_satisfy_with_sig_sizes(&self, sig_sizes: &SigSizes) -> Weight {
+        self.max_weight_to_satisfy_parts(sig_sizes).total()
+    }
+
+    /// Computes upper bounds on the weights of the scriptSig and witness
+    /// components of the difference between a non-satisfied `TxIn`'s
+    /// `segwit_weight` and a satisfied `TxIn`'s `segwit_weight`, assuming
+    /// signatures of the given sizes. Their total is given by
+    /// [`Self::max_weight_to_satisfy_with_sig_sizes`].
+    pub fn max_weight_to_satisfy_parts(&self, sig_sizes: &SigSizes) -> SatisfactionWeight {
         // stack items: <varint(sig+sigHash)> <sig(71)+sigHash(1)> <varint(pubkey)> <pubkey>
-        let stack_items_size = 73 + Segwitv0::pk_len(&self.pk);
+        let stack_items_size = sig_sizes.ecdsa + Segwitv0::pk_len(&self.pk);
         // stackLen varint difference between non-satisfied (0) and satisfied
         let stack_varint_diff = varint_len(2) - varint_len(0);
-        Weight::from_wu((stack_varint_diff + stack_items_size) as u64)
+        SatisfactionWeight::from_witness_size(stack_varint_diff + stack_items_size)
+    }
+
+    /// Computes upper bounds on the sizes of the scriptSig and witness of a
+    /// satisfying `TxIn`, assuming signatures of the given sizes.
+    pub fn max_satisfaction_size(&self, sig_sizes: &SigSizes) -> SatisfactionSize {
+        // stack items: <sig> <pubkey>
+        SatisfactionSize::from_witness(sig_sizes.ecdsa + Segwitv0::pk_len(&self.pk), 2)
     }
 
     /// Computes an upper bound on the weight of a satisfying witness to the
@@ -382,6 +472,16 @@ impl<Pk: MiniscriptKey + ToPublicKey> Wpkh<Pk> {
         addr.script_pubkey()
     }
 
+    /// Appends the script pubkey of this descriptor to `script`.
+    pub fn script_pubkey_into(&self, script: &mut ScriptBuf) {
+        let pk = self.pk.to_public_key();
+        let compressed = bitcoin::key::CompressedPublicKey::try_from(pk)
+            .expect("wpkh descriptors have compressed keys");
+
+        script.push_opcode(opcodes::OP_0);
+        script.push_slice(compressed.wpubkey_hash());
+    }
+
     /// Obtains the corresponding script pubkey for this descriptor.
     pub fn address(&self, network: Network) -> Address {
         let pk = self.pk.to_public_key();
@@ -483,11 +583,12 @@ impl<Pk: MiniscriptKey> Liftable<Pk> for Wpkh<Pk> {
 }
 
 impl<Pk: FromStrKey> crate::expression::FromTree for Wpkh<Pk> {
-    fn from_tree(top: expression::TreeIterItem) -> Result<Self, Error> {
+    type Error = ParseDescriptorError;
+    fn from_tree(top: expression::TreeIterItem) -> Result<Self, ParseDescriptorError> {
         let pk = top
             .verify_terminal_parent("wpkh", "public key")
-            .map_err(Error::Parse)?;
-        Wpkh::new(pk).map_err(Error::ContextError)
+            .map_err(ParseDescriptorError::Parse)?;
+        Wpkh::new(pk).map_err(ParseDescriptorError::Context)
     }
 }
 
@@ -495,7 +596,7 @@ impl<Pk: FromStrKey> core::str::FromStr for Wpkh<Pk> {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let top = expression::Tree::from_str(s)?;
-        Self::from_tree(top.root())
+        Self::from_tree(top.root()).map_err(Error::from)
     }
 }
 
@@ -14,17 +14,19 @@ use bitcoin::script::PushBytes;
 use bitcoin::{script, Address, Network, ScriptBuf, Weight};
 
 use super::{SortedMultiVec, Wpkh, Wsh};
-use crate::descriptor::{write_descriptor, DefiniteDescriptorKey};
+use crate::descriptor::{
+    write_descriptor, DefiniteDescriptorKey, SatisfactionSize, SatisfactionWeight,
+};
 use crate::expression::{self, FromTree};
 use crate::miniscript::context::ScriptContext;
 use crate::miniscript::satisfy::{Placeholder, Satisfaction};
 use crate::plan::AssetProvider;
 use crate::policy::{semantic, Liftable};
 use crate::prelude::*;
-use crate::util::{varint_len, witness_to_scriptsig};
+use crate::util::{varint_len, witness_to_scriptsig, wrap_in_p2sh};
 use crate::{
-    push_opcode_size, Error, ForEachKey, FromStrKey, Legacy, Miniscript, MiniscriptKey, Satisfier,
-    Segwitv0, ToPublicKey, TranslateErr, Translator,
+    push_opcode_size, Error, ForEachKey, FromStrKey, Legacy, Miniscript, MiniscriptKey,
+    ParseDescriptorError, Satisfier, Segwitv0, SigSizes, ToPublicKey, TranslateErr, Translator,
 };
 
 /// A Legacy p2sh Descriptor
@@ -81,11 +83,12 @@ impl<Pk: MiniscriptKey> fmt::Display for Sh<Pk> {
 }
 
 impl<Pk: FromStrKey> crate::expression::FromTree for Sh<Pk> {
-    fn from_tree(top: expression::TreeIterItem) -> Result<Self, Error> {
+    type Error = ParseDescriptorError;
+    fn from_tree(top: expression::TreeIterItem) -> Result<Self, ParseDescriptorError> {
         let top = top
             .verify_toplevel("sh", 1..=1)
             .map_err(From::from)
-            .map_err(Error::Parse)?;
+            .map_err(ParseDescriptorError::Parse)?;
 
         let inner = match top.name() {
             "wsh" => ShInner::Wsh(Wsh::from_tree(top)?),
@@ -93,7 +96,7 @@ impl<Pk: FromStrKey> crate::expression::FromTree for Sh<Pk> {
             "sortedmulti" => ShInner::SortedMulti(SortedMultiVec::from_tree(top)?),
             _ => {
                 let sub = Miniscript::from_tree(top)?;
-                Legacy::top_level_checks(&sub)?;
+                Legacy::top_level_checks(&sub).map_err(ParseDescriptorError::invalid)?;
                 ShInner::Ms(sub)
             }
         };
@@ -105,7 +108,7 @@ impl<Pk: FromStrKey> core::str::FromStr for Sh<Pk> {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let top = expression::Tree::from_str(s)?;
-        Self::from_tree(top.root())
+        Self::from_tree(top.root()).map_err(Error::from)
     }
 }
 
@@ -169,10 +172,9 @@ impl<Pk: MiniscriptKey> Sh<Pk> {
     /// Computes an upper bound on the difference between a non-satisfied
     /// `TxIn`'s `segwit_weight` and a satisfied `TxIn`'s `segwit_weight`
     ///
-    /// Since this method uses `segwit_weight` instead of `legacy_weight`,
-    /// if you want to include only legacy inputs in your transaction,
-    /// you should remove 1WU from each input's `max_weight_to_satisfy`
-    /// for a more accurate estimate.
+    /// When the descriptor does not wrap segwit, the satisfaction is entirely
+    /// in the scriptSig, so the difference is the same when measured with
+    /// `legacy_weight`, and this can be used for legacy transactions as well.
     ///
     /// Assumes all ec-signatures are 73 bytes, including push opcode and
     /// sighash suffix.
@@ -180,31 +182,61 @@ impl<Pk: MiniscriptKey> Sh<Pk> {
     /// # Errors
     /// When the descriptor is impossible to safisfy (ex: sh(OP_FALSE)).
     pub fn max_weight_to_satisfy(&self) -> Result<Weight, Error> {
+        self.max_weight_to_satisfy_with_sig_sizes(&SigSizes::WORST_CASE)
+    }
+
+    /// Computes an upper bound on the difference between a non-satisfied
+    /// `TxIn`'s `segwit_weight` and a satisfied `TxIn`'s `segwit_weight`,
+    /// as [`Self::max_weight_to_satisfy`], but assuming signatures of the
+    /// given sizes.
+    ///
+    /// # Errors
+    /// When the descriptor is impossible to safisfy (ex: sh(OP_FALSE)).
+    pub fn max_weight_to_satisfy_with_sig_sizes(
+        &self,
+        sig_sizes: &SigSizes,
+    ) -> Result<Weight, Error> {
+        Ok(self.max_weight_to_satisfy_parts(sig_sizes)?.total())
+    }
+
+    /// Computes upper bounds on the weights of the scriptSig and witness
+    /// components of the difference between a non-satisfied `TxIn`'s
+    /// `segwit_weight` and a satisfied `TxIn`'s `segwit_weight`, assuming
+    /// signatures of the given sizes. Their total is given by
+    /// [`Self::max_weight_to_satisfy_with_sig_sizes`].
+    ///
+    /// # Errors
+    /// When the descriptor is impossible to safisfy (ex: sh(OP_FALSE)).
+    pub fn max_weight_to_satisfy_parts(
+        &self,
+        sig_sizes: &SigSizes,
+    ) -> Result<SatisfactionWeight, Error> {
         let (scriptsig_size, witness_size) = match self.inner {
             // add weighted script sig, len byte stays the same
             ShInner::Wsh(ref wsh) => {
                 // scriptSig: OP_34 <OP_0 OP_32 <32-byte-hash>>
                 let scriptsig_size = 1 + 1 + 1 + 32;
-                let witness_size = wsh.max_weight_to_satisfy()?;
+                let witness_size = wsh.max_weight_to_satisfy_parts(sig_sizes)?.witness;
                 (scriptsig_size, witness_size)
             }
             ShInner::SortedMulti(ref smv) => {
                 let ss = smv.script_size();
                 let ps = push_opcode_size(ss);
-                let scriptsig_size = ps + ss + smv.max_satisfaction_size();
+                let scriptsig_size = ps + ss + smv.max_satisfaction_size_with_sig_sizes(sig_sizes);
                 (scriptsig_size, Weight::ZERO)
             }
             // add weighted script sig, len byte stays the same
             ShInner::Wpkh(ref wpkh) => {
                 // scriptSig: OP_22 <OP_0 OP_20 <20-byte-hash>>
                 let scriptsig_size = 1 + 1 + 1 + 20;
-                let witness_size = wpkh.max_weight_to_satisfy();
+                let witness_size = wpkh.max_weight_to_satisfy_parts(sig_sizes).witness;
                 (scriptsig_size, witness_size)
             }
             ShInner::Ms(ref ms) => {
                 let ss = ms.script_size();
                 let ps = push_opcode_size(ss);
-                let scriptsig_size = ps + ss + ms.max_satisfaction_size()?;
+                let scriptsig_size =
+                    ps + ss + ms.max_satisfaction_size_with_sig_sizes(sig_sizes)?;
                 (scriptsig_size, Weight::ZERO)
             }
         };
@@ -212,11 +244,51 @@ impl<Pk: MiniscriptKey> Sh<Pk> {
         // scriptSigLen varint difference between non-satisfied (0) and satisfied
         let scriptsig_varint_diff = varint_len(scriptsig_size) - varint_len(0);
 
-        let wu = Weight::from_vb((scriptsig_varint_diff + scriptsig_size) as u64);
-        match wu {
-            Some(w) => Ok(w + witness_size),
-            None => Err(Error::CouldNotSatisfy),
-        }
+        Ok(SatisfactionWeight {
+            scriptsig: Weight::from_non_witness_data_size(
+                (scriptsig_varint_diff + scriptsig_size) as u64,
+            ),
+            witness: witness_size,
+        })
+    }
+
+    /// Computes upper bounds on the sizes of the scriptSig and witness of a
+    /// satisfying `TxIn`, assuming signatures of the given sizes.
+    ///
+    /// # Errors
+    /// When the descriptor is impossible to safisfy (ex: sh(OP_FALSE)).
+    pub fn max_satisfaction_size(&self, sig_sizes: &SigSizes) -> Result<SatisfactionSize, Error> {
+        let size = match self.inner {
+            ShInner::Wsh(ref wsh) => {
+                // scriptSig: OP_34 <OP_0 OP_32 <32-byte-hash>>
+                SatisfactionSize {
+                    script_sig_bytes: 1 + 1 + 1 + 32,
+                    ..wsh.max_satisfaction_size(sig_sizes)?
+                }
+            }
+            ShInner::SortedMulti(ref smv) => {
+                let ss = smv.script_size();
+                let ps = push_opcode_size(ss);
+                SatisfactionSize::from_script_sig_bytes(
+                    ps + ss + smv.max_satisfaction_size_with_sig_sizes(sig_sizes),
+                )
+            }
+            ShInner::Wpkh(ref wpkh) => {
+                // scriptSig: OP_22 <OP_0 OP_20 <20-byte-hash>>
+                SatisfactionSize {
+                    script_sig_bytes: 1 + 1 + 1 + 20,
+                    ..wpkh.max_satisfaction_size(sig_sizes)
+                }
+            }
+            ShInner::Ms(ref ms) => {
+                let ss = ms.script_size();
+                let ps = push_opcode_size(ss);
+                SatisfactionSize::from_script_sig_bytes(
+                    ps + ss + ms.max_satisfaction_size_with_sig_sizes(sig_sizes)?,
+                )
+            }
+        };
+        Ok(size)
     }
 
     /// Computes an upper bound on the weight of a satisfying witness to the
@@ -280,6 +352,18 @@ impl<Pk: MiniscriptKey + ToPublicKey> Sh<Pk> {
         }
     }
 
+    /// Appends the script pubkey of this descriptor to `script`.
+    pub fn script_pubkey_into(&self, script: &mut ScriptBuf) {
+        let start = script.len();
+        match self.inner {
+            ShInner::Wsh(ref wsh) => wsh.script_pubkey_into(script),
+            ShInner::Wpkh(ref wpkh) => wpkh.script_pubkey_into(script),
+            ShInner::SortedMulti(ref smv) => smv.encode_to(script),
+            ShInner::Ms(ref ms) => ms.encode_to(script),
+        }
+        wrap_in_p2sh(script, start);
+    }
+
     /// Obtains the corresponding address for this descriptor.
     pub fn address(&self, network: Network) -> Address {
         let addr = self.address_fallible(network);
@@ -19,8 +19,8 @@ use crate::plan::AssetProvider;
 use crate::prelude::*;
 use crate::sync::Arc;
 use crate::{
-    expression, policy, script_num_size, Error, ForEachKey, Miniscript, MiniscriptKey, Satisfier,
-    Threshold, ToPublicKey, TranslateErr, Translator,
+    expression, policy, script_num_size, Error, ForEachKey, Miniscript, MiniscriptKey,
+    ParseDescriptorError, Satisfier, SigSizes, Threshold, ToPublicKey, TranslateErr, Translator,
 };
 
 /// Contents of a "sortedmulti" descriptor
@@ -41,7 +41,7 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> SortedMultiVec<Pk, Ctx> {
         // This would check all the consensus rules for p2sh/p2wsh and
         // even tapscript in future
         Ctx::check_local_validity(&ms)?;
-        if let Terminal::Multi(inner) = ms.node {
+        if let Terminal::Multi(inner) = ms.into_inner() {
             self.inner = inner;
             Ok(self)
         } else {
@@ -59,20 +59,22 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> SortedMultiVec<Pk, Ctx> {
     }
 
     /// Parse an expression tree into a SortedMultiVec
-    pub fn from_tree(tree: expression::TreeIterItem) -> Result<Self, Error>
+    pub fn from_tree(tree: expression::TreeIterItem) -> Result<Self, ParseDescriptorError>
     where
         Pk: FromStrKey,
     {
         tree.verify_toplevel("sortedmulti", 1..)
             .map_err(From::from)
-            .map_err(Error::Parse)?;
+            .map_err(ParseDescriptorError::Parse)?;
 
         let ret = Self {
-            inner: tree
-                .verify_threshold(|sub| sub.verify_terminal("public_key").map_err(Error::Parse))?,
+            inner: tree.verify_threshold(|sub| {
+                sub.verify_terminal("public_key")
+                    .map_err(ParseDescriptorError::Parse)
+            })?,
             phantom: PhantomData,
         };
-        ret.constructor_check()
+        ret.constructor_check().map_err(ParseDescriptorError::invalid)
     }
 
     /// This will panic if fpk returns an uncompressed key when
@@ -149,6 +151,15 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> SortedMultiVec<Pk, Ctx> {
             .into_script()
     }
 
+    /// Encode as a Bitcoin script, appending it to `script`.
+    pub fn encode_to(&self, script: &mut script::ScriptBuf)
+    where
+        Pk: ToPublicKey,
+    {
+        let builder = script::Builder::from(core::mem::take(script).into_bytes());
+        *script = self.sorted_node().encode(builder).into_script();
+    }
+
     /// Attempt to produce a satisfying witness for the
     /// witness script represented by the parse tree
     pub fn satisfy<S>(&self, satisfier: S) -> Result<Vec<Vec<u8>>, Error>
@@ -202,7 +213,15 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> SortedMultiVec<Pk, Ctx> {
     /// All signatures are assumed to be 73 bytes in size, including the
     /// length prefix (segwit) or push opcode (pre-segwit) and sighash
     /// postfix.
-    pub fn max_satisfaction_size(&self) -> usize { 1 + 73 * self.k() }
+    pub fn max_satisfaction_size(&self) -> usize {
+        self.max_satisfaction_size_with_sig_sizes(&SigSizes::WORST_CASE)
+    }
+
+    /// Maximum size, in bytes, of a satisfying witness, assuming signatures
+    /// of the given sizes.
+    pub fn max_satisfaction_size_with_sig_sizes(&self, sig_sizes: &SigSizes) -> usize {
+        1 + sig_sizes.ecdsa * self.k()
+    }
 }
 
 impl<Pk: MiniscriptKey, Ctx: ScriptContext> policy::Liftable<Pk> for SortedMultiVec<Pk, Ctx> {
@@ -5,14 +5,14 @@ use core::{cmp, fmt, hash};
 #[cfg(not(test))] // https://github.com/rust-lang/rust/issues/121684
 use bitcoin::secp256k1;
 use bitcoin::taproot::{
-    LeafVersion, TaprootBuilder, TaprootSpendInfo, TAPROOT_CONTROL_BASE_SIZE,
+    TaprootBuilder, TaprootSpendInfo, TAPROOT_CONTROL_BASE_SIZE,
     TAPROOT_CONTROL_MAX_NODE_COUNT, TAPROOT_CONTROL_NODE_SIZE,
 };
 use bitcoin::{opcodes, Address, Network, ScriptBuf, Weight};
 use sync::Arc;
 
 use super::checksum;
-use crate::descriptor::DefiniteDescriptorKey;
+use crate::descriptor::{DefiniteDescriptorKey, SatisfactionSize, SatisfactionWeight};
 use crate::expression::{self, FromTree};
 use crate::miniscript::satisfy::{Placeholder, Satisfaction, SchnorrSigType, Witness};
 use crate::miniscript::Miniscript;
@@ -22,13 +22,13 @@ use crate::policy::Liftable;
 use crate::prelude::*;
 use crate::util::{varint_len, witness_size};
 use crate::{
-    Error, ForEachKey, FromStrKey, MiniscriptKey, ParseError, Satisfier, ScriptContext, Tap,
-    Threshold, ToPublicKey, TranslateErr, Translator,
+    Error, ForEachKey, FromStrKey, MiniscriptKey, ParseDescriptorError, ParseError, Satisfier,
+    ScriptContext, SigSizes, Tap, Threshold, ToPublicKey, TranslateErr, Translator,
 };
 
 mod taptree;
 
-pub use self::taptree::{TapTree, TapTreeIter, TapTreeIterItem};
+pub use self::taptree::{RawTapLeaf, TapTree, TapTreeIter, TapTreeIterItem};
 
 /// A taproot descriptor
 pub struct Tr<Pk: MiniscriptKey> {
@@ -159,9 +159,8 @@ impl<Pk: MiniscriptKey> Tr<Pk> {
         } else {
             let mut builder = TaprootBuilder::new();
             for leaf in self.leaves() {
-                let script = leaf.miniscript().encode();
                 builder = builder
-                    .add_leaf(leaf.depth(), script)
+                    .add_leaf_with_ver(leaf.depth(), leaf.compute_script(), leaf.leaf_version())
                     .expect("Computing spend data on a valid Tree should always succeed");
             }
             // Assert builder cannot error here because we have a well formed descriptor
@@ -177,8 +176,8 @@ impl<Pk: MiniscriptKey> Tr<Pk> {
 
     /// Checks whether the descriptor is safe.
     pub fn sanity_check(&self) -> Result<(), Error> {
-        for leaf in self.leaves() {
-            leaf.miniscript().sanity_check()?;
+        for ms in self.leaves().filter_map(|leaf| leaf.miniscript()) {
+            ms.sanity_check()?;
         }
         Ok(())
     }
@@ -187,20 +186,52 @@ impl<Pk: MiniscriptKey> Tr<Pk> {
     /// `TxIn`'s `segwit_weight` and a satisfied `TxIn`'s `segwit_weight`
     ///
     /// Assumes all Schnorr signatures are 66 bytes, including push opcode and
-    /// sighash suffix.
+    /// sighash suffix. Raw leaves are not considered, since they cannot be
+    /// satisfied by this library.
     ///
     /// # Errors
     /// When the descriptor is impossible to safisfy (ex: sh(OP_FALSE)).
     pub fn max_weight_to_satisfy(&self) -> Result<Weight, Error> {
+        self.max_weight_to_satisfy_with_sig_sizes(&SigSizes::WORST_CASE)
+    }
+
+    /// Computes an upper bound on the difference between a non-satisfied
+    /// `TxIn`'s `segwit_weight` and a satisfied `TxIn`'s `segwit_weight`,
+    /// as [`Self::max_weight_to_satisfy`], but assuming signatures of the
+    /// given sizes.
+    ///
+    /// # Errors
+    /// When the descriptor is impossible to safisfy (ex: sh(OP_FALSE)).
+    pub fn max_weight_to_satisfy_with_sig_sizes(
+        &self,
+        sig_sizes: &SigSizes,
+    ) -> Result<Weight, Error> {
+        Ok(self.max_weight_to_satisfy_parts(sig_sizes)?.total())
+    }
+
+    /// Computes upper bounds on the weights of the scriptSig and witness
+    /// components of the difference between a non-satisfied `TxIn`'s
+    /// `segwit_weight` and a satisfied `TxIn`'s `segwit_weight`, assuming
+    /// signatures of the given sizes. Their total is given by
+    /// [`Self::max_weight_to_satisfy_with_sig_sizes`].
+    ///
+    /// # Errors
+    /// When the descriptor is impossible to safisfy (ex: sh(OP_FALSE)).
+    pub fn max_weight_to_satisfy_parts(
+        &self,
+        sig_sizes: &SigSizes,
+    ) -> Result<SatisfactionWeight, Error> {
         let tree = match self.tap_tree() {
             None => {
                 // key spend path
                 // item: varint(sig+sigHash) + <sig(64)+sigHash(1)>
-                let item_sig_size = 1 + 65;
+                let item_sig_size = sig_sizes.schnorr;
                 // 1 stack item
                 let stack_varint_diff = varint_len(1) - varint_len(0);
 
-                return Ok(Weight::from_wu((stack_varint_diff + item_sig_size) as u64));
+                return Ok(SatisfactionWeight::from_witness_size(
+                    stack_varint_diff + item_sig_size,
+                ));
             }
             // script path spend..
             Some(tree) => tree,
@@ -209,9 +240,10 @@ impl<Pk: MiniscriptKey> Tr<Pk> {
         let wu = tree
             .leaves()
             .filter_map(|leaf| {
-                let script_size = leaf.miniscript().script_size();
-                let max_sat_elems = leaf.miniscript().max_satisfaction_witness_elements().ok()?;
-                let max_sat_size = leaf.miniscript().max_satisfaction_size().ok()?;
+                let ms = leaf.miniscript()?;
+                let script_size = ms.script_size();
+                let max_sat_elems = ms.max_satisfaction_witness_elements().ok()?;
+                let max_sat_size = ms.max_satisfaction_size_with_sig_sizes(sig_sizes).ok()?;
                 let control_block_size = control_block_len(leaf.depth());
 
                 // stack varint difference (+1 for ctrl block, witness script already included)
@@ -232,7 +264,49 @@ impl<Pk: MiniscriptKey> Tr<Pk> {
             .max()
             .ok_or(Error::ImpossibleSatisfaction)?;
 
-        Ok(Weight::from_wu(wu as u64))
+        Ok(SatisfactionWeight::from_witness_size(wu))
+    }
+
+    /// Computes upper bounds on the sizes of the scriptSig and witness of a
+    /// satisfying `TxIn`, assuming signatures of the given sizes.
+    ///
+    /// For script path spends, each component is bounded separately over all
+    /// leaves, so the bounds may come from different leaves.
+    ///
+    /// # Errors
+    /// When the descriptor is impossible to safisfy (ex: sh(OP_FALSE)).
+    pub fn max_satisfaction_size(&self, sig_sizes: &SigSizes) -> Result<SatisfactionSize, Error> {
+        let tree = match self.tap_tree() {
+            // key spend path: <sig>
+            None => return Ok(SatisfactionSize::from_witness(sig_sizes.schnorr, 1)),
+            // script path spend..
+            Some(tree) => tree,
+        };
+
+        tree.leaves()
+            .filter_map(|leaf| {
+                let ms = leaf.miniscript()?;
+                let script_size = ms.script_size();
+                let max_sat_elems = ms.max_satisfaction_witness_elements().ok()?;
+                let max_sat_size = ms.max_satisfaction_size_with_sig_sizes(sig_sizes).ok()?;
+                let control_block_size = control_block_len(leaf.depth());
+
+                Some(SatisfactionSize::from_witness(
+                    max_sat_size
+                        + varint_len(script_size)
+                        + script_size
+                        + varint_len(control_block_size)
+                        + control_block_size,
+                    // +1 for ctrl block, witness script already included
+                    max_sat_elems + 1,
+                ))
+            })
+            .reduce(|a, b| SatisfactionSize {
+                script_sig_bytes: 0,
+                witness_bytes: cmp::max(a.witness_bytes, b.witness_bytes),
+                witness_items: cmp::max(a.witness_items, b.witness_items),
+            })
+            .ok_or(Error::ImpossibleSatisfaction)
     }
 
     /// Computes an upper bound on the weight of a satisfying witness to the
@@ -259,9 +333,10 @@ impl<Pk: MiniscriptKey> Tr<Pk> {
 
         tree.leaves()
             .filter_map(|leaf| {
-                let script_size = leaf.miniscript().script_size();
-                let max_sat_elems = leaf.miniscript().max_satisfaction_witness_elements().ok()?;
-                let max_sat_size = leaf.miniscript().max_satisfaction_size().ok()?;
+                let ms = leaf.miniscript()?;
+                let script_size = ms.script_size();
+                let max_sat_elems = ms.max_satisfaction_witness_elements().ok()?;
+                let max_sat_size = ms.max_satisfaction_size().ok()?;
                 let control_block_size = control_block_len(leaf.depth());
                 Some(
                     // scriptSig len byte
@@ -311,6 +386,13 @@ impl<Pk: MiniscriptKey + ToPublicKey> Tr<Pk> {
             .into_script()
     }
 
+    /// Appends the script pubkey of this descriptor to `script`.
+    pub fn script_pubkey_into(&self, script: &mut ScriptBuf) {
+        let output_key = self.spend_info().output_key();
+        script.push_opcode(opcodes::all::OP_PUSHNUM_1);
+        script.push_slice(output_key.serialize());
+    }
+
     /// Obtains the corresponding address for this descriptor.
     pub fn address(&self, network: Network) -> Address {
         let spend_info = self.spend_info();
@@ -325,8 +407,7 @@ impl<Pk: MiniscriptKey + ToPublicKey> Tr<Pk> {
         S: Satisfier<Pk>,
     {
         let satisfaction = best_tap_spend(self, satisfier, false /* allow_mall */)
-            .try_completing(satisfier)
-            .expect("the same satisfier should manage to complete the template");
+            .complete(satisfier);
         if let Witness::Stack(stack) = satisfaction.stack {
             Ok((stack, ScriptBuf::new()))
         } else {
@@ -345,8 +426,7 @@ impl<Pk: MiniscriptKey + ToPublicKey> Tr<Pk> {
         S: Satisfier<Pk>,
     {
         let satisfaction = best_tap_spend(self, satisfier, true /* allow_mall */)
-            .try_completing(satisfier)
-            .expect("the same satisfier should manage to complete the template");
+            .complete(satisfier);
         if let Witness::Stack(stack) = satisfaction.stack {
             Ok((stack, ScriptBuf::new()))
         } else {
@@ -377,6 +457,34 @@ impl Tr<DefiniteDescriptorKey> {
     {
         best_tap_spend(self, provider, true /* allow_mall */)
     }
+
+    /// Returns a plan spending the key path, if the provided assets allow it
+    pub fn plan_key_spend<P>(
+        &self,
+        provider: &P,
+    ) -> Satisfaction<Placeholder<DefiniteDescriptorKey>>
+    where
+        P: AssetProvider<DefiniteDescriptorKey>,
+    {
+        tap_key_spend(self, provider).unwrap_or(Satisfaction {
+            stack: Witness::Unavailable,
+            has_sig: false,
+            relative_timelock: None,
+            absolute_timelock: None,
+        })
+    }
+
+    /// Returns a plan spending the cheapest script path for which the provided assets are
+    /// sufficient to produce a non-malleable satisfaction, even if the key path is available
+    pub fn plan_script_spend<P>(
+        &self,
+        provider: &P,
+    ) -> Satisfaction<Placeholder<DefiniteDescriptorKey>>
+    where
+        P: AssetProvider<DefiniteDescriptorKey>,
+    {
+        best_tap_script_spend(self, provider, false /* allow_mall */)
+    }
 }
 
 impl<Pk: FromStrKey> core::str::FromStr for Tr<Pk> {
@@ -384,12 +492,13 @@ impl<Pk: FromStrKey> core::str::FromStr for Tr<Pk> {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let expr_tree = expression::Tree::from_str(s)?;
-        Self::from_tree(expr_tree.root())
+        Self::from_tree(expr_tree.root()).map_err(Error::from)
     }
 }
 
 impl<Pk: FromStrKey> crate::expression::FromTree for Tr<Pk> {
-    fn from_tree(root: expression::TreeIterItem) -> Result<Self, Error> {
+    type Error = ParseDescriptorError;
+    fn from_tree(root: expression::TreeIterItem) -> Result<Self, ParseDescriptorError> {
         use crate::expression::{Parens, ParseTreeError};
 
         struct TreeStack<'s, Pk: MiniscriptKey> {
@@ -421,17 +530,17 @@ impl<Pk: FromStrKey> crate::expression::FromTree for Tr<Pk> {
 
         root.verify_toplevel("tr", 1..=2)
             .map_err(From::from)
-            .map_err(Error::Parse)?;
+            .map_err(ParseDescriptorError::Parse)?;
 
         let mut root_children = root.children();
         let internal_key: Pk = root_children
             .next()
             .unwrap() // `verify_toplevel` above checked that first child existed
             .verify_terminal("internal key")
-            .map_err(Error::Parse)?;
+            .map_err(ParseDescriptorError::Parse)?;
 
         let tap_tree = match root_children.next() {
-            None => return Tr::new(internal_key, None),
+            None => return Tr::new(internal_key, None).map_err(ParseDescriptorError::invalid),
             Some(tree) => tree,
         };
 
@@ -442,26 +551,33 @@ impl<Pk: FromStrKey> crate::expression::FromTree for Tr<Pk> {
         while let Some(node) = tap_tree_iter.next() {
             if node.parens() == Parens::Curly {
                 if !node.name().is_empty() {
-                    return Err(Error::Parse(ParseError::Tree(ParseTreeError::IncorrectName {
-                        actual: node.name().to_owned(),
-                        expected: "",
-                    })));
+                    return Err(ParseDescriptorError::Parse(ParseError::Tree(
+                        ParseTreeError::IncorrectName {
+                            actual: node.name().to_owned(),
+                            expected: "",
+                        },
+                    )));
                 }
                 node.verify_n_children("taptree branch", 2..=2)
                     .map_err(From::from)
-                    .map_err(Error::Parse)?;
+                    .map_err(ParseDescriptorError::Parse)?;
+            } else if node.name() == "raw_leaf" {
+                let raw = RawTapLeaf::from_tree(node)?;
+                tree_stack.push(node.parent().unwrap(), TapTree::RawLeaf(raw));
+                tap_tree_iter.skip_descendants();
             } else {
                 let script = Miniscript::from_tree(node)?;
                 // FIXME hack for https://github.com/rust-bitcoin/rust-miniscript/issues/734
                 if script.ty.corr.base != crate::miniscript::types::Base::B {
-                    return Err(Error::NonTopLevel(format!("{:?}", script)));
+                    let e = Error::NonTopLevel(format!("{:?}", script));
+                    return Err(ParseDescriptorError::invalid(e));
                 };
 
                 tree_stack.push(node.parent().unwrap(), TapTree::Leaf(Arc::new(script)));
                 tap_tree_iter.skip_descendants();
             }
         }
-        Tr::new(internal_key, tree_stack.pop_final())
+        Tr::new(internal_key, tree_stack.pop_final()).map_err(ParseDescriptorError::invalid)
     }
 }
 
@@ -503,7 +619,8 @@ impl<Pk: MiniscriptKey> ForEachKey<Pk> for Tr<Pk> {
     fn for_each_key<'a, F: FnMut(&'a Pk) -> bool>(&'a self, mut pred: F) -> bool {
         let script_keys_res = self
             .leaves()
-            .all(|leaf| leaf.miniscript().for_each_key(&mut pred));
+            .filter_map(|leaf| leaf.miniscript())
+            .all(|ms| ms.for_each_key(&mut pred));
         script_keys_res && pred(&self.internal_key)
     }
 }
@@ -513,76 +630,98 @@ fn control_block_len(depth: u8) -> usize {
     TAPROOT_CONTROL_BASE_SIZE + (depth as usize) * TAPROOT_CONTROL_NODE_SIZE
 }
 
-// Helper function to get a script spend satisfaction
-// try script spend
+// Helper function to get a satisfaction, preferring the key spend path
 fn best_tap_spend<Pk, P>(
     desc: &Tr<Pk>,
     provider: &P,
     allow_mall: bool,
 ) -> Satisfaction<Placeholder<Pk>>
+where
+    Pk: ToPublicKey,
+    P: AssetProvider<Pk>,
+{
+    tap_key_spend(desc, provider)
+        .unwrap_or_else(|| best_tap_script_spend(desc, provider, allow_mall))
+}
+
+// Helper function to get the key spend satisfaction
+fn tap_key_spend<Pk, P>(desc: &Tr<Pk>, provider: &P) -> Option<Satisfaction<Placeholder<Pk>>>
+where
+    Pk: ToPublicKey,
+    P: AssetProvider<Pk>,
+{
+    let size = provider.provider_lookup_tap_key_spend_sig(&desc.internal_key)?;
+    Some(Satisfaction {
+        stack: Witness::Stack(vec![Placeholder::SchnorrSigPk(
+            desc.internal_key.clone(),
+            SchnorrSigType::KeySpend { merkle_root: desc.spend_info().merkle_root() },
+            size,
+        )]),
+        has_sig: true,
+        absolute_timelock: None,
+        relative_timelock: None,
+    })
+}
+
+// Helper function to get the cheapest script spend satisfaction
+fn best_tap_script_spend<Pk, P>(
+    desc: &Tr<Pk>,
+    provider: &P,
+    allow_mall: bool,
+) -> Satisfaction<Placeholder<Pk>>
 where
     Pk: ToPublicKey,
     P: AssetProvider<Pk>,
 {
     let spend_info = desc.spend_info();
-    // First try the key spend path
-    if let Some(size) = provider.provider_lookup_tap_key_spend_sig(&desc.internal_key) {
-        Satisfaction {
-            stack: Witness::Stack(vec![Placeholder::SchnorrSigPk(
-                desc.internal_key.clone(),
-                SchnorrSigType::KeySpend { merkle_root: spend_info.merkle_root() },
-                size,
-            )]),
-            has_sig: true,
-            absolute_timelock: None,
-            relative_timelock: None,
-        }
-    } else {
-        // Since we have the complete descriptor we can ignore the satisfier. We don't use the control block
-        // map (lookup_control_block) from the satisfier here.
-        let mut min_satisfaction = Satisfaction {
-            stack: Witness::Unavailable,
-            has_sig: false,
-            relative_timelock: None,
-            absolute_timelock: None,
-        };
-        let mut min_wit_len = None;
-        for leaf in desc.leaves() {
-            let mut satisfaction = if allow_mall {
-                match leaf.miniscript().build_template(provider) {
-                    s @ Satisfaction { stack: Witness::Stack(_), .. } => s,
-                    _ => continue, // No witness for this script in tr descriptor, look for next one
-                }
-            } else {
-                match leaf.miniscript().build_template_mall(provider) {
-                    s @ Satisfaction { stack: Witness::Stack(_), .. } => s,
-                    _ => continue, // No witness for this script in tr descriptor, look for next one
-                }
-            };
-            let wit = match satisfaction {
-                Satisfaction { stack: Witness::Stack(ref mut wit), .. } => wit,
-                _ => unreachable!(),
-            };
-
-            let leaf_script = (leaf.compute_script(), LeafVersion::TapScript);
-            let control_block = spend_info
-                .control_block(&leaf_script)
-                .expect("Control block must exist in script map for every known leaf");
-
-            wit.push(Placeholder::TapScript(leaf_script.0));
-            wit.push(Placeholder::TapControlBlock(control_block));
-
-            let wit_size = witness_size(wit);
-            if min_wit_len.is_some() && Some(wit_size) > min_wit_len {
-                continue;
-            } else {
-                min_satisfaction = satisfaction;
-                min_wit_len = Some(wit_size);
+    // Since we have the complete descriptor we can ignore the satisfier. We don't use the control block
+    // map (lookup_control_block) from the satisfier here.
+    let mut min_satisfaction = Satisfaction {
+        stack: Witness::Unavailable,
+        has_sig: false,
+        relative_timelock: None,
+        absolute_timelock: None,
+    };
+    let mut min_wit_len = None;
+    // Raw leaves are opaque, so only Miniscript leaves can be satisfied
+    for (leaf, ms) in desc
+        .leaves()
+        .filter_map(|leaf| leaf.miniscript().map(|ms| (leaf, ms)))
+    {
+        let mut satisfaction = if allow_mall {
+            match ms.build_template_mall(provider) {
+                s @ Satisfaction { stack: Witness::Stack(_), .. } => s,
+                _ => continue, // No witness for this script in tr descriptor, look for next one
             }
-        }
+        } else {
+            match ms.build_template(provider) {
+                s @ Satisfaction { stack: Witness::Stack(_), .. } => s,
+                _ => continue, // No witness for this script in tr descriptor, look for next one
+            }
+        };
+        let wit = match satisfaction {
+            Satisfaction { stack: Witness::Stack(ref mut wit), .. } => wit,
+            _ => unreachable!(),
+        };
+
+        let leaf_script = (leaf.compute_script(), leaf.leaf_version());
+        let control_block = spend_info
+            .control_block(&leaf_script)
+            .expect("Control block must exist in script map for every known leaf");
 
-        min_satisfaction
+        wit.push(Placeholder::TapScript(leaf_script.0));
+        wit.push(Placeholder::TapControlBlock(control_block));
+
+        let wit_size = witness_size(wit);
+        if min_wit_len.is_some() && Some(wit_size) > min_wit_len {
+            continue;
+        } else {
+            min_satisfaction = satisfaction;
+            min_wit_len = Some(wit_size);
+        }
     }
+
+    min_satisfaction
 }
 
 #[cfg(test)]
@@ -621,4 +760,65 @@ mod tests {
         let tr = Tr::<String>::from_str(&desc).unwrap();
         assert_eq!(tr.tap_tree().as_ref().unwrap().height(), 2);
     }
+
+    #[test]
+    fn raw_leaf() {
+        use bitcoin::taproot::LeafVersion;
+        use bitcoin::PublicKey;
+
+        use crate::policy::LiftError;
+
+        let pk = "020000000000000000000000000000000000000000000000000000000000000002";
+        let desc = format!("tr({},{{raw_leaf(51),{{pk({}),raw_leaf(52,c2)}}}})", pk, pk);
+        let tr = Tr::<PublicKey>::from_str(&desc).unwrap();
+        assert_eq!(format!("{:#}", tr), desc);
+        assert_eq!(crate::Descriptor::<PublicKey>::from_str(&desc).unwrap(), tr.clone().into());
+
+        let version = LeafVersion::from_consensus(0xc2).unwrap();
+        let leaves = tr.leaves().collect::<Vec<_>>();
+        assert_eq!(leaves.len(), 3);
+        assert!(leaves[0].miniscript().is_none());
+        assert_eq!(leaves[0].compute_script(), ScriptBuf::from(vec![0x51]));
+        assert_eq!(leaves[0].leaf_version(), LeafVersion::TapScript);
+        assert!(leaves[1].raw_leaf().is_none());
+        assert_eq!(leaves[2].raw_leaf().unwrap().script().as_bytes(), [0x52]);
+        assert_eq!(leaves[2].leaf_version(), version);
+
+        // Raw leaves are committed to with their leaf version.
+        let secp = secp256k1::Secp256k1::verification_only();
+        let expected = TaprootBuilder::new()
+            .add_leaf(1, ScriptBuf::from(vec![0x51]))
+            .unwrap()
+            .add_leaf(2, leaves[1].compute_script())
+            .unwrap()
+            .add_leaf_with_ver(2, ScriptBuf::from(vec![0x52]), version)
+            .unwrap()
+            .finalize(&secp, PublicKey::from_str(pk).unwrap().to_x_only_pubkey())
+            .unwrap();
+        assert_eq!(tr.spend_info().output_key(), expected.output_key());
+
+        // Only the Miniscript leaf can be satisfied, and its keys are the only ones in the tree.
+        let ms_only = format!("tr({},{{0,{{pk({}),0}}}})", pk, pk);
+        let ms_only = Tr::<PublicKey>::from_str(&ms_only).unwrap();
+        assert_eq!(tr.max_weight_to_satisfy().unwrap(), ms_only.max_weight_to_satisfy().unwrap());
+        let mut n_keys = 0;
+        tr.for_each_key(|_| {
+            n_keys += 1;
+            true
+        });
+        assert_eq!(n_keys, 2);
+        assert!(matches!(tr.lift(), Err(Error::LiftError(LiftError::RawDescriptorLift))));
+
+        for bad in [
+            "raw_leaf(5)",
+            "raw_leaf(51,c1)",
+            "raw_leaf(51,50)",
+            "raw_leaf(51,c)",
+            "raw_leaf(51,c0,c0)",
+            "raw_leaf(pk(K))",
+        ] {
+            let desc = format!("tr(K,{{{},pk(K)}})", bad);
+            assert!(Tr::<String>::from_str(&desc).is_err(), "{}", bad);
+        }
+    }
 }
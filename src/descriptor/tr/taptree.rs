@@ -2,13 +2,19 @@
 
 use core::{cmp, fmt};
 
+use bitcoin::hex::FromHex;
 use bitcoin::taproot::{LeafVersion, TapLeafHash};
+use bitcoin::{Script, ScriptBuf};
 
+use crate::expression;
 use crate::miniscript::context::Tap;
-use crate::policy::{Liftable, Semantic};
-use crate::prelude::Vec;
+use crate::policy::{LiftError, Liftable, Semantic};
+use crate::prelude::*;
 use crate::sync::Arc;
-use crate::{Miniscript, MiniscriptKey, Threshold, ToPublicKey, TranslateErr, Translator};
+use crate::{
+    Miniscript, MiniscriptKey, ParseDescriptorError, ParseError, Threshold, ToPublicKey,
+    TranslateErr, Translator,
+};
 
 /// A Taproot Tree representation.
 // Hidden leaves are not yet supported in descriptor spec. Conceptually, it should
@@ -29,6 +35,78 @@ pub enum TapTree<Pk: MiniscriptKey> {
     // in adding a LeafVersion with Leaf type here. All Miniscripts right now
     // are of Leafversion::default
     Leaf(Arc<Miniscript<Pk, Tap>>),
+    /// A taproot leaf with a script which is not Miniscript, possibly of another leaf version
+    RawLeaf(RawTapLeaf),
+}
+
+/// A taproot leaf whose script is not Miniscript, written `raw_leaf(HEX)`, or
+/// `raw_leaf(HEX,VERSION)` for leaf versions other than tapscript.
+///
+/// The script is opaque to this library: it is committed to in the taproot
+/// output, but it is never satisfied, and it has no keys or semantic policy.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct RawTapLeaf {
+    /// The script of the leaf
+    script: ScriptBuf,
+    /// The leaf version
+    version: LeafVersion,
+}
+
+impl RawTapLeaf {
+    /// Create a new raw leaf with the given script and leaf version
+    pub fn new(script: ScriptBuf, version: LeafVersion) -> Self { RawTapLeaf { script, version } }
+
+    /// Get a reference to the script of the leaf
+    pub fn script(&self) -> &Script { &self.script }
+
+    /// Get the leaf version
+    pub fn version(&self) -> LeafVersion { self.version }
+}
+
+impl fmt::Debug for RawTapLeaf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { fmt::Display::fmt(self, f) }
+}
+
+impl fmt::Display for RawTapLeaf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.version {
+            LeafVersion::TapScript => write!(f, "raw_leaf({:x})", self.script),
+            version => write!(f, "raw_leaf({:x},{:02x})", self.script, version.to_consensus()),
+        }
+    }
+}
+
+impl expression::FromTree for RawTapLeaf {
+    type Error = ParseDescriptorError;
+    fn from_tree(root: expression::TreeIterItem) -> Result<Self, ParseDescriptorError> {
+        root.verify_toplevel("raw_leaf", 1..=2)
+            .map_err(ParseError::Tree)
+            .map_err(ParseDescriptorError::Parse)?;
+
+        let mut children = root.children();
+
+        let hex: String = children
+            .next()
+            .unwrap() // `verify_toplevel` above checked that first child existed
+            .verify_terminal("script hex")
+            .map_err(ParseDescriptorError::Parse)?;
+        let script = ScriptBuf::from_hex(&hex)
+            .map_err(|e| ParseDescriptorError::Parse(ParseError::box_from_str(e)))?;
+
+        let version = match children.next() {
+            None => LeafVersion::TapScript,
+            Some(child) => {
+                let hex: String = child
+                    .verify_terminal("leaf version")
+                    .map_err(ParseDescriptorError::Parse)?;
+                let [byte] = <[u8; 1]>::from_hex(&hex)
+                    .map_err(|e| ParseDescriptorError::Parse(ParseError::box_from_str(e)))?;
+                LeafVersion::from_consensus(byte)
+                    .map_err(|e| ParseDescriptorError::Parse(ParseError::box_from_str(e)))?
+            }
+        };
+        Ok(RawTapLeaf::new(script, version))
+    }
 }
 
 impl<Pk: MiniscriptKey> TapTree<Pk> {
@@ -42,14 +120,14 @@ impl<Pk: MiniscriptKey> TapTree<Pk> {
     pub fn height(&self) -> usize {
         match *self {
             TapTree::Tree { left: _, right: _, height } => height,
-            TapTree::Leaf(..) => 0,
+            TapTree::Leaf(..) | TapTree::RawLeaf(..) => 0,
         }
     }
 
     /// Iterates over all the leaves of the tree in depth-first preorder.
     ///
-    /// The yielded elements include the Miniscript for each leave as well as its depth
-    /// in the tree, which is the data required by PSBT (BIP 371).
+    /// The yielded elements include the Miniscript or raw script for each leave as well
+    /// as its depth in the tree, which is the data required by PSBT (BIP 371).
     pub fn leaves(&self) -> TapTreeIter<Pk> { TapTreeIter::from_tree(self) }
 
     // Helper function to translate keys
@@ -67,6 +145,7 @@ impl<Pk: MiniscriptKey> TapTree<Pk> {
                 height: *height,
             },
             TapTree::Leaf(ref ms) => TapTree::Leaf(Arc::new(ms.translate_pk(t)?)),
+            TapTree::RawLeaf(ref raw) => TapTree::RawLeaf(raw.clone()),
         };
         Ok(frag)
     }
@@ -80,6 +159,7 @@ impl<Pk: MiniscriptKey> Liftable<Pk> for TapTree<Pk> {
                     Threshold::or(Arc::new(lift_helper(left)?), Arc::new(lift_helper(right)?)),
                 )),
                 TapTree::Leaf(ref leaf) => leaf.lift(),
+                TapTree::RawLeaf(_) => Err(crate::Error::LiftError(LiftError::RawDescriptorLift)),
             }
         }
 
@@ -95,6 +175,7 @@ impl<Pk: MiniscriptKey> fmt::Display for TapTree<Pk> {
                 write!(f, "{{{},{}}}", *left, *right)
             }
             TapTree::Leaf(ref script) => write!(f, "{}", *script),
+            TapTree::RawLeaf(ref raw) => write!(f, "{}", raw),
         }
     }
 }
@@ -106,6 +187,7 @@ impl<Pk: MiniscriptKey> fmt::Debug for TapTree<Pk> {
                 write!(f, "{{{:?},{:?}}}", *left, *right)
             }
             TapTree::Leaf(ref script) => write!(f, "{:?}", *script),
+            TapTree::RawLeaf(ref raw) => write!(f, "{:?}", raw),
         }
     }
 }
@@ -149,7 +231,12 @@ where
                     self.stack.push((depth + 1, right));
                     self.stack.push((depth + 1, left));
                 }
-                TapTree::Leaf(ref ms) => return Some(TapTreeIterItem { node: ms, depth }),
+                TapTree::Leaf(ref ms) => {
+                    return Some(TapTreeIterItem { node: TapLeaf::Miniscript(ms), depth })
+                }
+                TapTree::RawLeaf(ref raw) => {
+                    return Some(TapTreeIterItem { node: TapLeaf::Raw(raw), depth })
+                }
             }
         }
         None
@@ -162,17 +249,38 @@ where
 /// then the iterator will yield nothing.
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct TapTreeIterItem<'tr, Pk: MiniscriptKey> {
-    node: &'tr Arc<Miniscript<Pk, Tap>>,
+    node: TapLeaf<'tr, Pk>,
     depth: u8,
 }
 
+/// The contents of a leaf yielded by [`TapTreeIter`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum TapLeaf<'tr, Pk: MiniscriptKey> {
+    Miniscript(&'tr Arc<Miniscript<Pk, Tap>>),
+    Raw(&'tr RawTapLeaf),
+}
+
 impl<'tr, Pk: MiniscriptKey> TapTreeIterItem<'tr, Pk> {
-    /// The Tapscript in the leaf.
+    /// The Tapscript in the leaf, or `None` for a raw leaf.
     ///
     /// To obtain a [`bitcoin::Script`] from this node, call [`Miniscript::encode`]
-    /// on the returned value.
+    /// on the returned value, or use [`Self::compute_script`] for any leaf.
+    #[inline]
+    pub fn miniscript(&self) -> Option<&'tr Arc<Miniscript<Pk, Tap>>> {
+        match self.node {
+            TapLeaf::Miniscript(ms) => Some(ms),
+            TapLeaf::Raw(_) => None,
+        }
+    }
+
+    /// The raw leaf, or `None` for a Miniscript leaf.
     #[inline]
-    pub fn miniscript(&self) -> &'tr Arc<Miniscript<Pk, Tap>> { self.node }
+    pub fn raw_leaf(&self) -> Option<&'tr RawTapLeaf> {
+        match self.node {
+            TapLeaf::Miniscript(_) => None,
+            TapLeaf::Raw(raw) => Some(raw),
+        }
+    }
 
     /// The depth of this leaf.
     ///
@@ -182,12 +290,16 @@ impl<'tr, Pk: MiniscriptKey> TapTreeIterItem<'tr, Pk> {
 
     /// The Tapleaf version of this leaf.
     ///
-    /// This function returns a constant value, since there is only one version in use
-    /// on the Bitcoin network; however, it may be useful to use this method in case
-    /// you wish to be forward-compatible with future versions supported by this
-    /// library.
+    /// This is always [`LeafVersion::TapScript`] for Miniscript leaves, since there
+    /// is only one version in use on the Bitcoin network; only raw leaves may have
+    /// other versions.
     #[inline]
-    pub fn leaf_version(&self) -> LeafVersion { LeafVersion::TapScript }
+    pub fn leaf_version(&self) -> LeafVersion {
+        match self.node {
+            TapLeaf::Miniscript(_) => LeafVersion::TapScript,
+            TapLeaf::Raw(raw) => raw.version,
+        }
+    }
 }
 
 impl<Pk: ToPublicKey> TapTreeIterItem<'_, Pk> {
@@ -195,7 +307,12 @@ impl<Pk: ToPublicKey> TapTreeIterItem<'_, Pk> {
     ///
     /// This function is potentially expensive.
     #[inline]
-    pub fn compute_script(&self) -> bitcoin::ScriptBuf { self.node.encode() }
+    pub fn compute_script(&self) -> bitcoin::ScriptBuf {
+        match self.node {
+            TapLeaf::Miniscript(ms) => ms.encode(),
+            TapLeaf::Raw(raw) => raw.script.clone(),
+        }
+    }
 
     /// Computes the [`TapLeafHash`] of the leaf.
     ///
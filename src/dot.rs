@@ -0,0 +1,253 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Graphviz Output
+//!
+//! Rendering of descriptors and Miniscripts as Graphviz graphs in the DOT
+//! language, for visual review of complicated scripts.
+//!
+
+use core::fmt::{self, Write as _};
+
+use crate::descriptor::{ShInner, SortedMultiVec, TapTree, WshInner};
+use crate::iter::TreeLike;
+use crate::prelude::*;
+use crate::{Descriptor, Miniscript, MiniscriptKey, ScriptContext, Terminal};
+
+/// Builds a DOT graph, numbering nodes in the order they are added.
+struct Graph {
+    out: String,
+    next_id: usize,
+}
+
+impl Graph {
+    fn new() -> Self {
+        Graph {
+            out: String::from("digraph {\n  ordering=out;\n  node [fontname=\"monospace\"];\n"),
+            next_id: 0,
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.out.push_str("}\n");
+        self.out
+    }
+
+    /// Adds a node and returns its ID.
+    fn node<L: fmt::Display>(&mut self, label: L, shape: &str) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        let label = label.to_string().replace('\\', "\\\\").replace('"', "\\\"");
+        writeln!(self.out, "  n{} [label=\"{}\", shape={}];", id, label, shape)
+            .expect("writing to string");
+        id
+    }
+
+    fn edge(&mut self, from: usize, to: usize) {
+        writeln!(self.out, "  n{} -> n{};", from, to).expect("writing to string");
+    }
+
+    /// Adds a node for a descriptor wrapper such as `wsh`.
+    fn wrapper(&mut self, label: &str) -> usize { self.node(label, "box, style=bold") }
+
+    /// Adds a Miniscript, returning the ID of its root.
+    fn miniscript<Pk: MiniscriptKey, Ctx: ScriptContext>(
+        &mut self,
+        ms: &Miniscript<Pk, Ctx>,
+    ) -> usize {
+        // IDs of the nodes added so far, indexed by post-order index.
+        let mut ids = vec![];
+        for item in ms.post_order_iter() {
+            let id = match item.node.node {
+                Terminal::Alt(..) => self.node("a:", "ellipse"),
+                Terminal::Swap(..) => self.node("s:", "ellipse"),
+                Terminal::Check(..) => self.node("c:", "ellipse"),
+                Terminal::DupIf(..) => self.node("d:", "ellipse"),
+                Terminal::Verify(..) => self.node("v:", "ellipse"),
+                Terminal::NonZero(..) => self.node("j:", "ellipse"),
+                Terminal::ZeroNotEqual(..) => self.node("n:", "ellipse"),
+                Terminal::AndV(..) => self.node("and_v", "ellipse"),
+                Terminal::AndB(..) => self.node("and_b", "ellipse"),
+                Terminal::AndOr(..) => self.node("andor", "ellipse"),
+                Terminal::OrB(..) => self.node("or_b", "ellipse"),
+                Terminal::OrD(..) => self.node("or_d", "ellipse"),
+                Terminal::OrC(..) => self.node("or_c", "ellipse"),
+                Terminal::OrI(..) => self.node("or_i", "ellipse"),
+                Terminal::Thresh(ref thresh) => {
+                    self.node(format_args!("thresh({})", thresh.k()), "ellipse")
+                }
+                // Leaves, including keys, hashes and timelocks, are shown in full.
+                _ => self.node(item.node, "box"),
+            };
+            for &child in &item.child_indices {
+                self.edge(id, ids[child]);
+            }
+            ids.push(id);
+        }
+        *ids.last().expect("non-empty tree")
+    }
+
+    fn sortedmulti<Pk: MiniscriptKey, Ctx: ScriptContext>(
+        &mut self,
+        smv: &SortedMultiVec<Pk, Ctx>,
+    ) -> usize {
+        self.node(smv, "box")
+    }
+
+    fn wsh_inner<Pk: MiniscriptKey>(&mut self, inner: &WshInner<Pk>) -> usize {
+        let id = self.wrapper("wsh");
+        let child = match *inner {
+            WshInner::SortedMulti(ref smv) => self.sortedmulti(smv),
+            WshInner::Ms(ref ms) => self.miniscript(ms),
+        };
+        self.edge(id, child);
+        id
+    }
+
+    /// Adds a Taproot tree, with an unlabeled point for each branch.
+    fn tap_tree<Pk: MiniscriptKey>(&mut self, tree: &TapTree<Pk>) -> usize {
+        match *tree {
+            TapTree::Tree { ref left, ref right, .. } => {
+                let id = self.node("", "point");
+                let left = self.tap_tree(left);
+                self.edge(id, left);
+                let right = self.tap_tree(right);
+                self.edge(id, right);
+                id
+            }
+            TapTree::Leaf(ref ms) => self.miniscript(ms),
+            TapTree::RawLeaf(ref raw) => self.node(raw, "box"),
+        }
+    }
+}
+
+impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
+    /// Renders the Miniscript as a Graphviz graph in the DOT language.
+    ///
+    /// Every fragment, including wrappers, is a separate node, with its
+    /// children in order. Leaf fragments, which contain the keys, hashes and
+    /// timelocks, are drawn as boxes.
+    pub fn to_dot(&self) -> String {
+        let mut graph = Graph::new();
+        graph.miniscript(self);
+        graph.finish()
+    }
+}
+
+impl<Pk: MiniscriptKey> Descriptor<Pk> {
+    /// Renders the descriptor as a Graphviz graph in the DOT language.
+    ///
+    /// Descriptor wrappers such as `sh` and `wsh` are drawn as bold boxes
+    /// above the Miniscripts they contain, which are drawn as for
+    /// [`Miniscript::to_dot`]. For Taproot descriptors, the internal key is
+    /// shown in the root node and each branch of the tree is drawn as a point.
+    pub fn to_dot(&self) -> String {
+        let mut graph = Graph::new();
+        match *self {
+            Descriptor::Bare(ref bare) => {
+                let id = graph.wrapper("bare");
+                let child = graph.miniscript(bare.as_inner());
+                graph.edge(id, child);
+            }
+            Descriptor::Pkh(ref pkh) => {
+                graph.wrapper(&format!("pkh({})", pkh.as_inner()));
+            }
+            Descriptor::Wpkh(ref wpkh) => {
+                graph.wrapper(&format!("wpkh({})", wpkh.as_inner()));
+            }
+            Descriptor::Addr(ref addr) => {
+                graph.wrapper(&format!("addr({})", addr.as_inner().assume_checked_ref()));
+            }
+            Descriptor::Raw(ref raw) => {
+                graph.wrapper(&format!("raw({:x})", raw.as_inner()));
+            }
+            Descriptor::Sh(ref sh) => {
+                let id = graph.wrapper("sh");
+                let child = match *sh.as_inner() {
+                    ShInner::Wsh(ref wsh) => graph.wsh_inner(wsh.as_inner()),
+                    ShInner::Wpkh(ref wpkh) => graph.wrapper(&format!("wpkh({})", wpkh.as_inner())),
+                    ShInner::SortedMulti(ref smv) => graph.sortedmulti(smv),
+                    ShInner::Ms(ref ms) => graph.miniscript(ms),
+                };
+                graph.edge(id, child);
+            }
+            Descriptor::Wsh(ref wsh) => {
+                graph.wsh_inner(wsh.as_inner());
+            }
+            Descriptor::Tr(ref tr) => {
+                let id = graph.wrapper(&format!("tr({})", tr.internal_key()));
+                if let Some(tree) = tr.tap_tree() {
+                    let child = graph.tap_tree(tree);
+                    graph.edge(id, child);
+                }
+            }
+        }
+        graph.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use super::*;
+    use crate::Segwitv0;
+
+    #[test]
+    fn miniscript() {
+        let ms = Miniscript::<String, Segwitv0>::from_str("and_v(v:pk(A),older(144))").unwrap();
+        assert_eq!(
+            ms.to_dot(),
+            "digraph {
+  ordering=out;
+  node [fontname=\"monospace\"];
+  n0 [label=\"pk_k(A)\", shape=box];
+  n1 [label=\"c:\", shape=ellipse];
+  n1 -> n0;
+  n2 [label=\"v:\", shape=ellipse];
+  n2 -> n1;
+  n3 [label=\"older(144)\", shape=box];
+  n4 [label=\"and_v\", shape=ellipse];
+  n4 -> n2;
+  n4 -> n3;
+}
+"
+        );
+
+        // Labels are escaped.
+        let ms = Miniscript::<String, Segwitv0>::from_str("pk(\"\\)").unwrap();
+        assert!(ms
+            .to_dot()
+            .contains("[label=\"pk_k(\\\"\\\\)\", shape=box]"));
+    }
+
+    #[test]
+    fn descriptor() {
+        let desc = Descriptor::<String>::from_str("sh(wsh(thresh(2,pk(A),s:pk(B),sln:older(10))))")
+            .unwrap();
+        let dot = desc.to_dot();
+        assert!(dot.contains("n0 [label=\"sh\", shape=box, style=bold];"));
+        assert!(dot.contains("n1 [label=\"wsh\", shape=box, style=bold];"));
+        assert!(dot.contains("n0 -> n1;"));
+        assert!(dot.contains("[label=\"thresh(2)\", shape=ellipse];"));
+        assert!(dot.contains("[label=\"older(10)\", shape=box];"));
+
+        let desc = Descriptor::<String>::from_str("tr(K,{pk(A),{pk(B),pk(C)}})").unwrap();
+        let dot = desc.to_dot();
+        assert!(dot.starts_with(
+            "digraph {
+  ordering=out;
+  node [fontname=\"monospace\"];
+  n0 [label=\"tr(K)\", shape=box, style=bold];
+  n1 [label=\"\", shape=point];
+"
+        ));
+        assert_eq!(dot.matches("shape=point").count(), 2);
+        assert_eq!(dot.matches("shape=box]").count(), 3);
+        assert!(dot.ends_with("  n0 -> n1;\n}\n"));
+
+        let desc = Descriptor::<String>::from_str("wpkh(A)").unwrap();
+        assert!(desc
+            .to_dot()
+            .contains("n0 [label=\"wpkh(A)\", shape=box, style=bold];"));
+    }
+}
@@ -8,9 +8,11 @@ use core::fmt;
 use std::error;
 
 use crate::blanket_traits::StaticDebugAndDisplay;
+use crate::miniscript::context::ScriptContextError;
+use crate::miniscript::types;
 use crate::primitives::absolute_locktime::AbsLockTimeError;
 use crate::primitives::relative_locktime::RelLockTimeError;
-use crate::Box;
+use crate::{Box, Error, ParseThresholdError, ThresholdError};
 
 /// An error parsing a Miniscript object (policy, descriptor or miniscript)
 /// from a string.
@@ -70,3 +72,177 @@ impl error::Error for ParseError {
         }
     }
 }
+
+/// An error parsing a Miniscript from an expression tree.
+#[derive(Debug)]
+pub enum ParseMiniscriptError {
+    /// The tree is not a well-formed Miniscript expression.
+    Parse(ParseError),
+    /// Invalid threshold in a `thresh`, `multi` or `multi_a` fragment.
+    ParseThreshold(ParseThresholdError),
+    /// Encountered a wrapper character that we don't recognize.
+    UnknownWrapper(char),
+    /// A fragment does not typecheck.
+    TypeCheck(types::Error),
+    /// A fragment is not valid in the script context.
+    Context(ScriptContextError),
+    /// An extension fragment failed to parse.
+    Extension(Box<Error>),
+}
+
+impl fmt::Display for ParseMiniscriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseMiniscriptError::Parse(ref e) => e.fmt(f),
+            ParseMiniscriptError::ParseThreshold(ref e) => e.fmt(f),
+            ParseMiniscriptError::UnknownWrapper(ch) => write!(f, "unknown wrapper «{}:»", ch),
+            ParseMiniscriptError::TypeCheck(ref e) => write!(f, "typecheck: {}", e),
+            ParseMiniscriptError::Context(ref e) => e.fmt(f),
+            ParseMiniscriptError::Extension(ref e) => e.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for ParseMiniscriptError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ParseMiniscriptError::Parse(ref e) => Some(e),
+            ParseMiniscriptError::ParseThreshold(ref e) => Some(e),
+            ParseMiniscriptError::UnknownWrapper(_) => None,
+            ParseMiniscriptError::TypeCheck(ref e) => Some(e),
+            ParseMiniscriptError::Context(ref e) => Some(e),
+            ParseMiniscriptError::Extension(ref e) => Some(e),
+        }
+    }
+}
+
+impl From<ParseThresholdError> for ParseMiniscriptError {
+    fn from(e: ParseThresholdError) -> Self { Self::ParseThreshold(e) }
+}
+
+impl From<ParseMiniscriptError> for Error {
+    fn from(e: ParseMiniscriptError) -> Self {
+        match e {
+            ParseMiniscriptError::Parse(e) => Error::Parse(e),
+            ParseMiniscriptError::ParseThreshold(e) => Error::ParseThreshold(e),
+            ParseMiniscriptError::UnknownWrapper(ch) => Error::UnknownWrapper(ch),
+            ParseMiniscriptError::TypeCheck(e) => Error::from(e),
+            ParseMiniscriptError::Context(e) => Error::ContextError(e),
+            ParseMiniscriptError::Extension(e) => *e,
+        }
+    }
+}
+
+/// An error parsing a descriptor from an expression tree.
+#[derive(Debug)]
+pub enum ParseDescriptorError {
+    /// The tree is not a well-formed descriptor expression.
+    Parse(ParseError),
+    /// Invalid threshold in a `sortedmulti` descriptor.
+    ParseThreshold(ParseThresholdError),
+    /// A script of the descriptor failed to parse.
+    Miniscript(ParseMiniscriptError),
+    /// The key of a single-key descriptor is not valid in its script context.
+    Context(ScriptContextError),
+    /// The descriptor parsed, but was rejected by the checks of its constructor,
+    /// such as the top-level checks of its script context or the depth limit
+    /// of a Taproot tree.
+    Invalid(Box<Error>),
+}
+
+impl fmt::Display for ParseDescriptorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseDescriptorError::Parse(ref e) => e.fmt(f),
+            ParseDescriptorError::ParseThreshold(ref e) => e.fmt(f),
+            ParseDescriptorError::Miniscript(ref e) => e.fmt(f),
+            ParseDescriptorError::Context(ref e) => e.fmt(f),
+            ParseDescriptorError::Invalid(ref e) => e.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for ParseDescriptorError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ParseDescriptorError::Parse(ref e) => Some(e),
+            ParseDescriptorError::ParseThreshold(ref e) => Some(e),
+            ParseDescriptorError::Miniscript(ref e) => Some(e),
+            ParseDescriptorError::Context(ref e) => Some(e),
+            ParseDescriptorError::Invalid(ref e) => Some(e),
+        }
+    }
+}
+
+impl ParseDescriptorError {
+    /// Wraps an error returned by the constructor of a descriptor.
+    pub(crate) fn invalid(e: Error) -> Self { ParseDescriptorError::Invalid(Box::new(e)) }
+}
+
+impl From<ParseMiniscriptError> for ParseDescriptorError {
+    fn from(e: ParseMiniscriptError) -> Self { Self::Miniscript(e) }
+}
+
+impl From<ParseThresholdError> for ParseDescriptorError {
+    fn from(e: ParseThresholdError) -> Self { Self::ParseThreshold(e) }
+}
+
+impl From<ParseDescriptorError> for Error {
+    fn from(e: ParseDescriptorError) -> Self {
+        match e {
+            ParseDescriptorError::Parse(e) => Error::Parse(e),
+            ParseDescriptorError::ParseThreshold(e) => Error::ParseThreshold(e),
+            ParseDescriptorError::Miniscript(e) => Error::from(e),
+            ParseDescriptorError::Context(e) => Error::ContextError(e),
+            ParseDescriptorError::Invalid(e) => *e,
+        }
+    }
+}
+
+/// An error parsing a concrete or semantic policy from an expression tree.
+#[derive(Debug)]
+pub enum ParsePolicyError {
+    /// The tree is not a well-formed policy expression.
+    Parse(ParseError),
+    /// Invalid threshold in a `thresh` fragment.
+    ParseThreshold(ParseThresholdError),
+    /// Invalid threshold for an `and` or `or` fragment.
+    Threshold(ThresholdError),
+}
+
+impl fmt::Display for ParsePolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParsePolicyError::Parse(ref e) => e.fmt(f),
+            ParsePolicyError::ParseThreshold(ref e) => e.fmt(f),
+            ParsePolicyError::Threshold(ref e) => e.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for ParsePolicyError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ParsePolicyError::Parse(ref e) => Some(e),
+            ParsePolicyError::ParseThreshold(ref e) => Some(e),
+            ParsePolicyError::Threshold(ref e) => Some(e),
+        }
+    }
+}
+
+impl From<ParseThresholdError> for ParsePolicyError {
+    fn from(e: ParseThresholdError) -> Self { Self::ParseThreshold(e) }
+}
+
+impl From<ParsePolicyError> for Error {
+    fn from(e: ParsePolicyError) -> Self {
+        match e {
+            ParsePolicyError::Parse(e) => Error::Parse(e),
+            ParsePolicyError::ParseThreshold(e) => Error::ParseThreshold(e),
+            ParsePolicyError::Threshold(e) => Error::Threshold(e),
+        }
+    }
+}
@@ -13,13 +13,27 @@ use crate::ThresholdError;
 pub enum ParseTreeError {
     /// Error validating the checksum or character set.
     Checksum(checksum::Error),
-    /// Expression tree had depth exceeding our hard cap.
+    /// Expression tree had depth exceeding the configured maximum.
     MaxRecursionDepthExceeded {
         /// The depth of the tree that was attempted to be parsed.
         actual: usize,
         /// The maximum depth.
         maximum: u32,
     },
+    /// Expression string was longer than the configured maximum.
+    MaxLengthExceeded {
+        /// The length of the string, in bytes.
+        actual: usize,
+        /// The maximum length.
+        maximum: usize,
+    },
+    /// A node had more children than the configured maximum.
+    MaxChildrenExceeded {
+        /// The byte-index into the string of the first child over the limit.
+        pos: usize,
+        /// The maximum number of children.
+        maximum: usize,
+    },
     /// After a close-paren, the only valid next characters are close-parens and commas. Got
     /// something else.
     ExpectedParenOrComma {
@@ -95,6 +109,13 @@ pub enum ParseTreeError {
         /// The name that was not recognized.
         name: String,
     },
+    /// The tree had more nodes than a fixed-capacity buffer could hold.
+    NodeCapacityExceeded {
+        /// The number of nodes the buffer can hold.
+        capacity: usize,
+        /// The byte-index into the string of the first node which did not fit.
+        pos: usize,
+    },
 }
 
 impl From<checksum::Error> for ParseTreeError {
@@ -106,7 +127,13 @@ impl fmt::Display for ParseTreeError {
         match self {
             ParseTreeError::Checksum(ref e) => e.fmt(f),
             ParseTreeError::MaxRecursionDepthExceeded { actual, maximum } => {
-                write!(f, "maximum recursion depth exceeded (max {}, got {})", maximum, actual)
+                write!(f, "maximum nesting depth exceeded (max {}, got {})", maximum, actual)
+            }
+            ParseTreeError::MaxLengthExceeded { actual, maximum } => {
+                write!(f, "maximum length exceeded (max {}, got {})", maximum, actual)
+            }
+            ParseTreeError::MaxChildrenExceeded { pos, maximum } => {
+                write!(f, "too many children (position {}); maximum is {}", pos, maximum)
             }
             ParseTreeError::ExpectedParenOrComma { ch, pos } => {
                 write!(
@@ -167,6 +194,9 @@ impl fmt::Display for ParseTreeError {
                 write!(f, "trailing data `{}...` (position {})", ch, pos)
             }
             ParseTreeError::UnknownName { name } => write!(f, "unrecognized name '{}'", name),
+            ParseTreeError::NodeCapacityExceeded { capacity, pos } => {
+                write!(f, "too many nodes (position {}); buffer holds at most {}", pos, capacity)
+            }
         }
     }
 }
@@ -176,6 +206,8 @@ impl std::error::Error for ParseTreeError {
         match self {
             ParseTreeError::Checksum(ref e) => Some(e),
             ParseTreeError::MaxRecursionDepthExceeded { .. }
+            | ParseTreeError::MaxLengthExceeded { .. }
+            | ParseTreeError::MaxChildrenExceeded { .. }
             | ParseTreeError::ExpectedParenOrComma { .. }
             | ParseTreeError::UnmatchedOpenParen { .. }
             | ParseTreeError::UnmatchedCloseParen { .. }
@@ -185,7 +217,8 @@ impl std::error::Error for ParseTreeError {
             | ParseTreeError::IncorrectNumberOfChildren { .. }
             | ParseTreeError::MultipleSeparators { .. }
             | ParseTreeError::TrailingCharacter { .. }
-            | ParseTreeError::UnknownName { .. } => None,
+            | ParseTreeError::UnknownName { .. }
+            | ParseTreeError::NodeCapacityExceeded { .. } => None,
         }
     }
 }
@@ -232,7 +265,26 @@ pub enum ParseThresholdError {
     /// A n-of-n threshold was used in a context it was not allowed.
     IllegalAnd,
     /// Failed to parse the threshold value.
-    ParseK(ParseNumError),
+    ParseK {
+        /// The underlying number parsing error.
+        error: ParseNumError,
+        /// The byte-index into the string of the threshold value.
+        pos: usize,
+    },
+    /// The threshold value was zero.
+    ZeroK {
+        /// The byte-index into the string of the threshold value.
+        pos: usize,
+    },
+    /// The threshold value exceeded the number of children.
+    KExceedsN {
+        /// The threshold value.
+        k: usize,
+        /// The number of children.
+        n: usize,
+        /// The byte-index into the string of the threshold value.
+        pos: usize,
+    },
     /// Threshold parameters were invalid.
     Threshold(ThresholdError),
 }
@@ -250,7 +302,13 @@ impl fmt::Display for ParseThresholdError {
             IllegalAnd => f.write_str(
                 "n-of-n thresholds not allowed here; please use an 'and' fragment instead",
             ),
-            ParseK(ref x) => write!(f, "failed to parse threshold value: {}", x),
+            ParseK { ref error, pos } => {
+                write!(f, "failed to parse threshold value (position {}): {}", pos, error)
+            }
+            ZeroK { pos } => write!(f, "threshold value must be positive (position {})", pos),
+            KExceedsN { k, n, pos } => {
+                write!(f, "invalid threshold {}-of-{} (position {}); cannot have k > n", k, n, pos)
+            }
             Threshold(ref e) => e.fmt(f),
         }
     }
@@ -262,8 +320,13 @@ impl std::error::Error for ParseThresholdError {
         use ParseThresholdError::*;
 
         match *self {
-            NoChildren | KNotTerminal | IllegalOr | IllegalAnd => None,
-            ParseK(ref e) => Some(e),
+            NoChildren
+            | KNotTerminal
+            | IllegalOr
+            | IllegalAnd
+            | ZeroK { .. }
+            | KExceedsN { .. } => None,
+            ParseK { ref error, .. } => Some(error),
             Threshold(ref e) => Some(e),
         }
     }
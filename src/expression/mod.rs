@@ -26,15 +26,17 @@
 //!
 
 mod error;
+mod token;
 
-use core::ops;
 use core::str::FromStr;
+use core::{fmt, ops};
 
 pub use self::error::{ParseNumError, ParseThresholdError, ParseTreeError};
+pub use self::token::{tokenize, Token, TokenKind, Tokens};
 use crate::blanket_traits::StaticDebugAndDisplay;
 use crate::descriptor::checksum::verify_checksum;
 use crate::prelude::*;
-use crate::{AbsLockTime, Error, ParseError, RelLockTime, Threshold, MAX_RECURSION_DEPTH};
+use crate::{AbsLockTime, Error, ParseError, RelLockTime, Threshold};
 
 /// Allowed characters are descriptor strings.
 pub const INPUT_CHARSET: &str = "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
@@ -44,7 +46,7 @@ pub const INPUT_CHARSET: &str = "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVW
 /// Users of the public API will always interact with this using the
 /// wrapper type [`TreeIterItem`] which also contains a reference to
 /// the whole tree.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 struct TreeNode<'s> {
     name: &'s str,
     name_pos: usize,
@@ -69,6 +71,16 @@ impl TreeNode<'_> {
             right_sibling_idx: None,
         }
     }
+
+    /// The opening parenthesis of a node with children, and its position.
+    fn open_paren(&self) -> (char, usize) {
+        let ch = if self.parens == Parens::Curly {
+            '{'
+        } else {
+            '('
+        };
+        (ch, self.name_pos + self.name.len())
+    }
 }
 
 /// An iterator over the nodes of a tree, in pre-order.
@@ -133,8 +145,8 @@ impl DoubleEndedIterator for PreOrderIter<'_> {
 impl ExactSizeIterator for PreOrderIter<'_> {
     // The inner `RangeInclusive` does not impl ExactSizeIterator because the
     // range 0..=usize::MAX would have length usize::MAX + 1. But we know
-    // that our range is limited by the `n_nodes` variable returned by
-    // `parse_pre_check`, and if THAT didn't overflow then this won't either.
+    // that our range indexes into a slice of nodes, whose length cannot
+    // exceed usize::MAX, so this won't overflow.
 }
 
 /// A tree node, as yielded from an iterator.
@@ -174,8 +186,16 @@ pub enum Parens {
 
 /// A trait for extracting a structure from a Tree representation in token form
 pub trait FromTree: Sized {
+    /// The error returned when the tree does not represent a valid `Self`.
+    ///
+    /// Descriptors, Miniscripts and policies use [`crate::ParseDescriptorError`],
+    /// [`crate::ParseMiniscriptError`] and [`crate::ParsePolicyError`]
+    /// respectively. Every error converts into the top-level [`Error`], so that
+    /// parsers can be composed with `?`.
+    type Error: fmt::Debug + fmt::Display + Into<Error>;
+
     /// Extract a structure from Tree representation
-    fn from_tree(root: TreeIterItem) -> Result<Self, Error>;
+    fn from_tree(root: TreeIterItem) -> Result<Self, Self::Error>;
 }
 
 impl<'s> TreeIterItem<'s> {
@@ -454,8 +474,21 @@ impl<'s> TreeIterItem<'s> {
             return Err(ParseThresholdError::KNotTerminal.into());
         }
 
-        let k = parse_num(kchild.name()).map_err(ParseThresholdError::ParseK)? as usize;
-        Threshold::new(k, vec![(); self.n_children() - 1])
+        let pos = kchild.name_pos();
+        let k = parse_num(kchild.name())
+            .map_err(|error| ParseThresholdError::ParseK { error, pos })? as usize;
+        // Check k against the arity before doing anything else, so that the
+        // error can point at the offending value. An empty threshold is left to
+        // `Threshold::new`, which reports it independently of k.
+        let n = self.n_children() - 1;
+        if n > 0 {
+            if k == 0 {
+                return Err(ParseThresholdError::ZeroK { pos }.into());
+            } else if k > n {
+                return Err(ParseThresholdError::KExceedsN { k, n, pos }.into());
+            }
+        }
+        Threshold::new(k, vec![(); n])
             .map_err(ParseThresholdError::Threshold)
             .map_err(From::from)
             .and_then(|thresh| thresh.translate_by_index(|_| map_child(child_iter.next().unwrap())))
@@ -484,6 +517,64 @@ impl<'s> TreeIterItem<'s> {
     }
 }
 
+/// Limits applied when parsing an expression tree.
+///
+/// By default no limits are applied: trees are parsed without recursion, and
+/// their size is bounded by the length of the string. Applications parsing
+/// untrusted input may wish to set limits. Note that the descriptor and
+/// Miniscript types apply their own limits after parsing, so raising a limit
+/// here does not allow parsing anything those types would otherwise reject.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub struct ParseOptions {
+    /// The maximum nesting depth of the tree.
+    pub max_depth: u32,
+    /// The maximum length of the string, in bytes, including any checksum.
+    pub max_length: usize,
+    /// The maximum number of children of any single node.
+    pub max_children: usize,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self { Self::new() }
+}
+
+impl ParseOptions {
+    /// Create a new ParseOptions with the default limits.
+    pub const fn new() -> Self {
+        ParseOptions { max_depth: u32::MAX, max_length: usize::MAX, max_children: usize::MAX }
+    }
+
+    /// Builder that sets the maximum nesting depth.
+    pub const fn max_depth(mut self, max_depth: u32) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Builder that sets the maximum string length.
+    pub const fn max_length(mut self, max_length: usize) -> Self {
+        self.max_length = max_length;
+        self
+    }
+
+    /// Builder that sets the maximum number of children of a node.
+    pub const fn max_children(mut self, max_children: usize) -> Self {
+        self.max_children = max_children;
+        self
+    }
+
+    /// Checks the length limit, then the checksum, returning the string
+    /// without the checksum.
+    fn verify_length_and_checksum(self, s: &str) -> Result<&str, ParseTreeError> {
+        if s.len() > self.max_length {
+            return Err(ParseTreeError::MaxLengthExceeded {
+                actual: s.len(),
+                maximum: self.max_length,
+            });
+        }
+        verify_checksum(s).map_err(From::from)
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 /// A parsed expression tree. See module-level documentation for syntax.
 pub struct Tree<'s> {
@@ -502,179 +593,349 @@ impl<'a> Tree<'a> {
         TreeIterItem { nodes: &self.nodes, index: 0 }
     }
 
-    /// Check that a string is a well-formed expression string, with optional
-    /// checksum.
+    /// Parses a tree from a string
+    #[allow(clippy::should_implement_trait)] // Cannot use std::str::FromStr because of lifetimes.
+    pub fn from_str(s: &'a str) -> Result<Self, Error> {
+        Self::from_str_with_options(s, ParseOptions::new())
+    }
+
+    /// Parses a tree from a string, with custom limits.
+    pub fn from_str_with_options(s: &'a str, options: ParseOptions) -> Result<Self, Error> {
+        Self::from_str_inner(s, options)
+            .map_err(From::from)
+            .map_err(Error::Parse)
+    }
+
+    /// Parses a tree from a byte slice, such as a network buffer.
     ///
-    /// Returns the string with the checksum removed, the maximum depth, and the
-    /// number of nodes in the tree.
-    fn parse_pre_check(s: &str) -> Result<(&str, usize, usize), ParseTreeError> {
-        // First, scan through string to make sure it is well-formed.
-        // Do ASCII/checksum check first; after this we can use .bytes().enumerate() rather
-        // than .char_indices(), which is *significantly* faster.
-        let s = verify_checksum(s)?;
-
-        let mut n_nodes = 1;
-        let mut max_depth = 0;
-        let mut open_paren_stack = Vec::with_capacity(128);
-        for (pos, ch) in s.bytes().enumerate() {
-            if ch == b'(' || ch == b'{' {
-                open_paren_stack.push((ch, pos));
-                if max_depth < open_paren_stack.len() {
-                    max_depth = open_paren_stack.len();
+    /// The tree borrows from the bytes without copying them. The bytes are
+    /// validated as UTF-8 and then checked against the descriptor character
+    /// set along with the checksum, so they are scanned twice before parsing,
+    /// as a string would be after the caller's own UTF-8 validation.
+    pub fn from_bytes(s: &'a [u8]) -> Result<Self, Error> {
+        Self::from_bytes_with_options(s, ParseOptions::new())
+    }
+
+    /// Parses a tree from a byte slice, with custom limits.
+    pub fn from_bytes_with_options(s: &'a [u8], options: ParseOptions) -> Result<Self, Error> {
+        // Check the length before scanning anything.
+        if s.len() > options.max_length {
+            return Err(Error::Parse(ParseError::Tree(ParseTreeError::MaxLengthExceeded {
+                actual: s.len(),
+                maximum: options.max_length,
+            })));
+        }
+        let s = crate::descriptor::checksum::utf8_from_bytes(s)
+            .map_err(ParseTreeError::from)
+            .map_err(ParseError::Tree)
+            .map_err(Error::Parse)?;
+        Self::from_str_with_options(s, options)
+    }
+
+    fn from_str_inner(s: &'a str, options: ParseOptions) -> Result<Self, ParseTreeError> {
+        let s = options.verify_length_and_checksum(s)?;
+
+        // Every node other than the root starts immediately after an opening
+        // paren or a comma, so this bounds the number of nodes from above, and
+        // is exact for well-formed input.
+        let max_nodes = 1 + s
+            .bytes()
+            .filter(|&ch| ch == b'(' || ch == b'{' || ch == b',')
+            .count();
+        let mut nodes = vec![TreeNode::null(0); max_nodes];
+
+        let mut arena = NodeArena { nodes: &mut nodes, len: 0, options };
+        arena.parse(s)?;
+        let len = arena.len;
+        debug_assert_eq!(len, max_nodes);
+        nodes.truncate(len);
+
+        Ok(Tree { nodes })
+    }
+}
+
+/// Caller-provided, fixed-capacity storage for a parsed expression tree.
+///
+/// [`Tree::from_str`] makes a single allocation, sized to the number of nodes
+/// in the input. On targets where heap allocation is unavailable or must be
+/// tightly bounded, a `TreeBuffer` can be placed on the stack or in static
+/// memory instead, and parsing will fail with
+/// [`ParseTreeError::NodeCapacityExceeded`] rather than allocate when the input
+/// has more than `N` nodes.
+///
+/// The buffer may be reused across parses; each call to [`TreeBuffer::parse`]
+/// overwrites the previous contents.
+#[derive(Debug)]
+pub struct TreeBuffer<'s, const N: usize> {
+    nodes: [TreeNode<'s>; N],
+    len: usize,
+}
+
+impl<const N: usize> Default for TreeBuffer<'_, N> {
+    fn default() -> Self { Self::new() }
+}
+
+impl<'s, const N: usize> TreeBuffer<'s, N> {
+    /// Constructs a new empty buffer which can hold up to `N` nodes.
+    pub fn new() -> Self { TreeBuffer { nodes: [TreeNode::null(0); N], len: 0 } }
+
+    /// The maximum number of nodes that this buffer can hold.
+    pub fn capacity(&self) -> usize { N }
+
+    /// The number of nodes in the most recently parsed tree.
+    pub fn len(&self) -> usize { self.len }
+
+    /// Whether the buffer currently holds no tree.
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+
+    /// Parses a string into the buffer, returning the root of the tree.
+    ///
+    /// Accepts exactly the same strings, and returns the same errors, as
+    /// [`Tree::from_str`], except that input which does not fit in the buffer
+    /// fails with [`ParseTreeError::NodeCapacityExceeded`].
+    pub fn parse(&mut self, s: &'s str) -> Result<TreeIterItem<'_>, ParseTreeError> {
+        self.parse_with_options(s, ParseOptions::new())
+    }
+
+    /// Parses a string into the buffer, with custom limits.
+    pub fn parse_with_options(
+        &mut self,
+        s: &'s str,
+        options: ParseOptions,
+    ) -> Result<TreeIterItem<'_>, ParseTreeError> {
+        self.len = 0;
+        let s = options.verify_length_and_checksum(s)?;
+        let mut arena = NodeArena { nodes: &mut self.nodes, len: 0, options };
+        arena.parse(s)?;
+        self.len = arena.len;
+        Ok(TreeIterItem { nodes: &self.nodes[..self.len], index: 0 })
+    }
+}
+
+/// A structural piece of an expression string.
+///
+/// This is the only place where an expression string is split into pieces;
+/// both the tree parser and [`tokenize`] are built on it, so that they always
+/// agree on where names and parentheses are.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Lexeme<'s> {
+    /// The (possibly empty) text between two structural characters, or between
+    /// a structural character and either end of the string.
+    Name { pos: usize, text: &'s str },
+    /// A `(` or `{`.
+    Open { pos: usize, ch: u8 },
+    /// A `,`.
+    Comma { pos: usize },
+    /// A `)` or `}`.
+    Close { pos: usize, ch: u8 },
+}
+
+/// An iterator over the lexemes of an expression string.
+///
+/// Never fails: every byte of the input belongs to exactly one lexeme, and it
+/// is up to the consumer to decide whether the sequence is well-formed. A
+/// [`Lexeme::Name`] is yielded at the start of the string and after every
+/// structural character, so names and structural characters alternate.
+#[derive(Clone, Debug)]
+struct Lexemes<'s> {
+    s: &'s str,
+    pos: usize,
+    name_next: bool,
+}
+
+impl<'s> Lexemes<'s> {
+    fn new(s: &'s str) -> Self { Lexemes { s, pos: 0, name_next: true } }
+}
+
+impl<'s> Iterator for Lexemes<'s> {
+    type Item = Lexeme<'s>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let bytes = self.s.as_bytes();
+        let pos = self.pos;
+        if self.name_next {
+            self.name_next = false;
+            // Structural characters are ASCII, so this is always a char boundary.
+            self.pos = bytes[pos..]
+                .iter()
+                .position(|ch| b"(){},".contains(ch))
+                .map_or(bytes.len(), |len| pos + len);
+            return Some(Lexeme::Name { pos, text: &self.s[pos..self.pos] });
+        }
+
+        let ch = *bytes.get(pos)?;
+        self.pos += 1;
+        self.name_next = true;
+        Some(match ch {
+            b'(' | b'{' => Lexeme::Open { pos, ch },
+            b',' => Lexeme::Comma { pos },
+            _ => Lexeme::Close { pos, ch },
+        })
+    }
+}
+
+/// A flat, pre-order array of tree nodes being filled in by the parser.
+///
+/// Children are located through index links rather than owned allocations,
+/// so the whole tree lives in a single slice, which may be a vector sized
+/// up front by [`Tree::from_str`] or the fixed array of a [`TreeBuffer`].
+struct NodeArena<'a, 's> {
+    nodes: &'a mut [TreeNode<'s>],
+    len: usize,
+    options: ParseOptions,
+}
+
+impl<'s> NodeArena<'_, 's> {
+    /// Parses a checksum-stripped, ASCII-validated string into the arena.
+    ///
+    /// Validates the string in the same pass, using the parent links of the
+    /// nodes themselves as the stack of open parentheses.
+    fn parse(&mut self, s: &'s str) -> Result<(), ParseTreeError> {
+        let mut depth = 0;
+        let mut parent: Option<usize> = None;
+        // Position and text of the name of the node currently being read, if any.
+        let mut current_name: Option<(usize, &'s str)> = None;
+        // Whether the last structural character was a closing paren, in which
+        // case no name may follow it.
+        let mut after_close = false;
+        for lexeme in Lexemes::new(s) {
+            match lexeme {
+                Lexeme::Name { pos, text } => {
+                    if !after_close {
+                        current_name = Some((pos, text));
+                    }
                 }
-            } else if ch == b')' || ch == b'}' {
-                if let Some((open_ch, open_pos)) = open_paren_stack.pop() {
-                    if (open_ch == b'(' && ch == b'}') || (open_ch == b'{' && ch == b')') {
+                Lexeme::Open { ch, .. } => {
+                    let (name_pos, name) =
+                        current_name.take().expect("'(' only occurs after a node name");
+                    let idx = self.push(parent, name, name_pos)?;
+                    self.nodes[idx].parens = if ch == b'(' {
+                        Parens::Round
+                    } else {
+                        Parens::Curly
+                    };
+
+                    depth += 1;
+                    if u32::try_from(depth).unwrap_or(u32::MAX) > self.options.max_depth {
+                        return Err(ParseTreeError::MaxRecursionDepthExceeded {
+                            actual: depth,
+                            maximum: self.options.max_depth,
+                        });
+                    }
+                    parent = Some(idx);
+                    after_close = false;
+                }
+                Lexeme::Comma { pos } => {
+                    if parent.is_none() {
+                        // We consider commas outside of the tree to be "trailing characters"
+                        return Err(ParseTreeError::TrailingCharacter { ch: ',', pos });
+                    }
+                    if let Some((name_pos, name)) = current_name.take() {
+                        self.push(parent, name, name_pos)?;
+                    }
+                    after_close = false;
+                }
+                Lexeme::Close { pos, ch } => {
+                    let open_idx = match parent {
+                        Some(idx) => idx,
+                        None => {
+                            return Err(ParseTreeError::UnmatchedCloseParen { ch: ch.into(), pos })
+                        }
+                    };
+                    let open = self.nodes[open_idx];
+                    let (open_ch, open_pos) = open.open_paren();
+                    if (open_ch == '(' && ch == b'}') || (open_ch == '{' && ch == b')') {
                         return Err(ParseTreeError::MismatchedParens {
-                            open_ch: open_ch.into(),
+                            open_ch,
                             open_pos,
                             close_ch: ch.into(),
                             close_pos: pos,
                         });
                     }
+                    if let Some((name_pos, name)) = current_name.take() {
+                        self.push(parent, name, name_pos)?;
+                    }
 
-                    if let Some(&(paren_ch, paren_pos)) = open_paren_stack.last() {
-                        // not last paren; this should not be the end of the string,
-                        // and the next character should be a , ) or }.
-                        if pos == s.len() - 1 {
-                            return Err(ParseTreeError::UnmatchedOpenParen {
-                                ch: paren_ch.into(),
-                                pos: paren_pos,
-                            });
-                        } else {
+                    match open.parent_idx {
+                        Some(grandparent_idx) => {
+                            // not last paren; this should not be the end of the string,
+                            // and the next character should be a , ) or }.
+                            if pos == s.len() - 1 {
+                                let (ch, pos) = self.nodes[grandparent_idx].open_paren();
+                                return Err(ParseTreeError::UnmatchedOpenParen { ch, pos });
+                            }
                             let next_byte = s.as_bytes()[pos + 1];
                             if next_byte != b')' && next_byte != b'}' && next_byte != b',' {
                                 return Err(ParseTreeError::ExpectedParenOrComma {
                                     ch: next_byte.into(),
                                     pos: pos + 1,
                                 });
-                                //
                             }
                         }
-                    } else {
-                        // last paren; this SHOULD be the end of the string
-                        if pos < s.len() - 1 {
-                            return Err(ParseTreeError::TrailingCharacter {
-                                ch: s.as_bytes()[pos + 1].into(),
-                                pos: pos + 1,
-                            });
+                        None => {
+                            // last paren; this SHOULD be the end of the string
+                            if pos < s.len() - 1 {
+                                return Err(ParseTreeError::TrailingCharacter {
+                                    ch: s.as_bytes()[pos + 1].into(),
+                                    pos: pos + 1,
+                                });
+                            }
                         }
                     }
-                } else {
-                    // In practice, this is only hit if there are no open parens at all.
-                    // If there are open parens, like in "())", then on the first ), we
-                    // would have returned TrailingCharacter in the previous clause.
-                    //
-                    // From a user point of view, UnmatchedCloseParen would probably be
-                    // a clearer error to get, but it complicates the parser to do this,
-                    // and "TralingCharacter" is technically correct, so we leave it for
-                    // now.
-                    return Err(ParseTreeError::UnmatchedCloseParen { ch: ch.into(), pos });
-                }
 
-                n_nodes += 1;
-            } else if ch == b',' {
-                if open_paren_stack.is_empty() {
-                    // We consider commas outside of the tree to be "trailing characters"
-                    return Err(ParseTreeError::TrailingCharacter { ch: ch.into(), pos });
+                    depth -= 1;
+                    parent = open.parent_idx;
+                    after_close = true;
                 }
-
-                n_nodes += 1;
             }
         }
         // Catch "early end of string"
-        if let Some((ch, pos)) = open_paren_stack.pop() {
-            return Err(ParseTreeError::UnmatchedOpenParen { ch: ch.into(), pos });
+        if let Some(idx) = parent {
+            let (ch, pos) = self.nodes[idx].open_paren();
+            return Err(ParseTreeError::UnmatchedOpenParen { ch, pos });
         }
-
-        // FIXME should be able to remove this once we eliminate all recursion
-        // in the library.
-        if u32::try_from(max_depth).unwrap_or(u32::MAX) > MAX_RECURSION_DEPTH {
-            return Err(ParseTreeError::MaxRecursionDepthExceeded {
-                actual: max_depth,
-                maximum: MAX_RECURSION_DEPTH,
-            });
+        if let Some((name_pos, name)) = current_name {
+            self.push(None, name, name_pos)?;
         }
 
-        Ok((s, max_depth, n_nodes))
-    }
-
-    /// Parses a tree from a string
-    #[allow(clippy::should_implement_trait)] // Cannot use std::str::FromStr because of lifetimes.
-    pub fn from_str(s: &'a str) -> Result<Self, Error> {
-        Self::from_str_inner(s)
-            .map_err(From::from)
-            .map_err(Error::Parse)
+        Ok(())
     }
 
-    fn from_str_inner(s: &'a str) -> Result<Self, ParseTreeError> {
-        fn new_node<'a>(nodes: &mut [TreeNode<'a>], stack: &[usize], pos: usize) -> TreeNode<'a> {
-            let parent_idx = stack.last().copied();
-            if let Some(idx) = parent_idx {
-                nodes[idx].n_children += 1;
-                nodes[idx].last_child_idx = Some(nodes.len());
-            }
-
-            let mut new = TreeNode::null(nodes.len());
-            new.name_pos = pos;
-            new.parent_idx = parent_idx;
-            new
+    /// Appends a node as the last child of `parent`, returning its index.
+    fn push(
+        &mut self,
+        parent: Option<usize>,
+        name: &'s str,
+        name_pos: usize,
+    ) -> Result<usize, ParseTreeError> {
+        let idx = self.len;
+        if idx == self.nodes.len() {
+            return Err(ParseTreeError::NodeCapacityExceeded {
+                capacity: self.nodes.len(),
+                pos: name_pos,
+            });
         }
 
-        // First, scan through string to make sure it is well-formed.
-        let (s, max_depth, n_nodes) = Self::parse_pre_check(s)?;
-
-        let mut nodes = Vec::with_capacity(n_nodes);
-
-        // Now, knowing it is sane and well-formed, we can easily parse it forward,
-        // as the string serialization lists all the nodes in pre-order.
-        let mut parent_stack = Vec::with_capacity(max_depth);
-        let mut current_node = Some(TreeNode::null(0));
-        for (pos, ch) in s.bytes().enumerate() {
-            if ch == b'(' || ch == b'{' {
-                let mut current = current_node.expect("'(' only occurs after a node name");
-                current.name = &s[current.name_pos..pos];
-                current.parens = match ch {
-                    b'(' => Parens::Round,
-                    b'{' => Parens::Curly,
-                    _ => unreachable!(),
-                };
-                parent_stack.push(nodes.len());
-                nodes.push(current);
-
-                current_node = Some(new_node(&mut nodes, &parent_stack, pos + 1));
-            } else if ch == b',' {
-                if let Some(mut current) = current_node {
-                    current.name = &s[current.name_pos..pos];
-                    nodes.push(current);
-                }
-
-                if let Some(last_sib_idx) =
-                    parent_stack.last().and_then(|n| nodes[*n].last_child_idx)
-                {
-                    nodes[last_sib_idx].right_sibling_idx = Some(nodes.len());
-                }
-                current_node = Some(new_node(&mut nodes, &parent_stack, pos + 1));
-            } else if ch == b')' || ch == b'}' {
-                if let Some(mut current) = current_node {
-                    current.name = &s[current.name_pos..pos];
-                    nodes.push(current);
-                }
-
-                current_node = None;
-                parent_stack.pop();
+        let mut new = TreeNode::null(idx);
+        new.name = name;
+        new.name_pos = name_pos;
+        new.parent_idx = parent;
+        if let Some(parent_idx) = parent {
+            if self.nodes[parent_idx].n_children == self.options.max_children {
+                return Err(ParseTreeError::MaxChildrenExceeded {
+                    pos: name_pos,
+                    maximum: self.options.max_children,
+                });
             }
+            if let Some(last_sib_idx) = self.nodes[parent_idx].last_child_idx {
+                self.nodes[last_sib_idx].right_sibling_idx = Some(idx);
+            }
+            self.nodes[parent_idx].n_children += 1;
+            self.nodes[parent_idx].last_child_idx = Some(idx);
         }
-        if let Some(mut current) = current_node {
-            current.name = &s[current.name_pos..];
-            nodes.push(current);
-        }
-
-        assert_eq!(parent_stack.capacity(), max_depth);
-        assert_eq!(nodes.capacity(), n_nodes);
-        assert_eq!(nodes.len(), nodes.capacity());
-
-        Ok(Tree { nodes })
+        self.nodes[idx] = new;
+        self.len += 1;
+        Ok(idx)
     }
 }
 
@@ -913,4 +1174,162 @@ mod tests {
                 .into_tree()
         );
     }
+
+    #[test]
+    fn tree_buffer() {
+        for s in [
+            "",
+            "thresh",
+            "thresh()",
+            "thresh(,)",
+            "a{b(c),d}",
+            "wsh(t:or_c(pk(A),v:pkh(B)))",
+            "multi(2,A,B,C)#abcdefgh",
+            "thresh,",
+            "thresh()thresh()",
+            "thresh(a()b)",
+            "a(",
+            "a(b(c)",
+            ")",
+            "x(y))",
+            "x(y)}",
+            "x{y)",
+            "a(b,c)(",
+        ] {
+            let mut buffer = TreeBuffer::<16>::new();
+            match (Tree::from_str(s), buffer.parse(s)) {
+                (Ok(tree), Ok(root)) => assert_eq!(tree.nodes, root.nodes, "{}", s),
+                (Err(Error::Parse(ParseError::Tree(e1))), Err(e2)) => assert_eq!(e1, e2, "{}", s),
+                (x, y) => panic!("{}: mismatched results {:?} vs {:?}", s, x, y.map(|r| r.name())),
+            }
+        }
+
+        // Buffer may be reused.
+        let mut buffer = TreeBuffer::<3>::new();
+        assert_eq!(buffer.parse("a(b,c)").unwrap().n_children(), 2);
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(
+            buffer.parse("a(b,c,d)").err(),
+            Some(ParseTreeError::NodeCapacityExceeded { capacity: 3, pos: 6 }),
+        );
+        assert_eq!(buffer.parse("x").unwrap().name(), "x");
+
+        let mut buffer = TreeBuffer::<0>::new();
+        assert_eq!(
+            buffer.parse("").err(),
+            Some(ParseTreeError::NodeCapacityExceeded { capacity: 0, pos: 0 }),
+        );
+    }
+
+    #[test]
+    fn from_tree_custom_error() {
+        // A list of names, which can fail only with a tree error.
+        #[derive(Debug, PartialEq, Eq)]
+        struct Names(Vec<String>);
+
+        impl FromTree for Names {
+            type Error = ParseTreeError;
+            fn from_tree(root: TreeIterItem) -> Result<Self, ParseTreeError> {
+                root.verify_n_children("names", 1..)?;
+                root.children()
+                    .map(|child| {
+                        child.verify_n_children("name", 0..=0)?;
+                        Ok(child.name().to_owned())
+                    })
+                    .collect::<Result<_, _>>()
+                    .map(Names)
+            }
+        }
+
+        let tree = Tree::from_str("names(a,b)").unwrap();
+        assert_eq!(Names::from_tree(tree.root()), Ok(Names(vec!["a".into(), "b".into()])));
+        let tree = Tree::from_str("names(a,b(c))").unwrap();
+        assert!(matches!(
+            Names::from_tree(tree.root()),
+            Err(ParseTreeError::IncorrectNumberOfChildren { description: "name", .. }),
+        ));
+    }
+
+    #[test]
+    fn from_tree_specific_errors() {
+        use crate::policy::Semantic;
+        use crate::{
+            Descriptor, Miniscript, ParseDescriptorError, ParseMiniscriptError, ParsePolicyError,
+            Segwitv0,
+        };
+
+        let tree = |s| Tree::from_str(s).unwrap();
+        // The first child of `and_v` must be of type V.
+        assert!(matches!(
+            Miniscript::<String, Segwitv0>::from_tree(tree("and_v(pk(A),pk(B))").root()),
+            Err(ParseMiniscriptError::TypeCheck(_)),
+        ));
+        assert!(matches!(
+            Miniscript::<String, Segwitv0>::from_tree(tree("x:pk(A)").root()),
+            Err(ParseMiniscriptError::UnknownWrapper('x')),
+        ));
+        assert!(matches!(
+            Descriptor::<String>::from_tree(tree("wsh(and_v(pk(A),pk(B)))").root()),
+            Err(ParseDescriptorError::Miniscript(ParseMiniscriptError::TypeCheck(_))),
+        ));
+        // `pk_k` is a valid Miniscript, but not of type B.
+        assert!(matches!(
+            Descriptor::<String>::from_tree(tree("wsh(pk_k(A))").root()),
+            Err(ParseDescriptorError::Invalid(_)),
+        ));
+        let thresh = tree("thresh(1,pk(A),pk(B))");
+        assert!(matches!(
+            Semantic::<String>::from_tree(thresh.root()),
+            Err(ParsePolicyError::ParseThreshold(ParseThresholdError::IllegalOr)),
+        ));
+
+        // The specific errors convert into the top-level error.
+        let error: Error = Semantic::<String>::from_tree(thresh.root()).unwrap_err().into();
+        assert!(matches!(error, Error::ParseThreshold(ParseThresholdError::IllegalOr)));
+    }
+
+    #[test]
+    fn parse_tree_depth() {
+        let nested = |depth: usize| "a(".repeat(depth) + &")".repeat(depth);
+
+        let s = nested(100_000);
+        let tree = Tree::from_str(&s).unwrap();
+        // The single allocation is sized exactly.
+        assert_eq!(tree.nodes.len(), 100_001);
+        assert_eq!(tree.nodes.capacity(), tree.nodes.len());
+
+        let options = ParseOptions::new().max_depth(402);
+        Tree::from_str_with_options(&nested(402), options).unwrap();
+        assert!(matches!(
+            Tree::from_str_with_options(&nested(403), options).unwrap_err(),
+            Error::Parse(ParseError::Tree(ParseTreeError::MaxRecursionDepthExceeded {
+                actual: 403,
+                maximum: 402,
+            })),
+        ));
+    }
+
+    #[test]
+    fn verify_threshold_errors() {
+        fn thresh(s: &str) -> Result<Threshold<(), 0>, ParseThresholdError> {
+            Tree::from_str(s)
+                .unwrap()
+                .root()
+                .verify_threshold(|_| Ok::<_, ParseThresholdError>(()))
+        }
+
+        assert_eq!(thresh("thresh(2,a,b,c)").unwrap().k(), 2);
+        assert_eq!(thresh("thresh").unwrap_err(), ParseThresholdError::NoChildren);
+        assert_eq!(thresh("thresh(1(),a)").unwrap_err(), ParseThresholdError::KNotTerminal);
+        assert!(matches!(
+            thresh("thresh(a,b)").unwrap_err(),
+            ParseThresholdError::ParseK { error: ParseNumError::InvalidLeadingDigit('a'), pos: 7 },
+        ));
+        assert_eq!(thresh("thresh(0,a)").unwrap_err(), ParseThresholdError::ZeroK { pos: 7 });
+        assert_eq!(
+            thresh("thresh(3,a,b)").unwrap_err(),
+            ParseThresholdError::KExceedsN { k: 3, n: 2, pos: 7 },
+        );
+        assert!(matches!(thresh("thresh(0)").unwrap_err(), ParseThresholdError::Threshold(_)));
+    }
 }
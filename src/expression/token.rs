@@ -0,0 +1,261 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Expression Tokenizer
+//!
+//! Splits an expression string into lexical tokens, each carrying its byte span
+//! in the original string. The structure of the string is split by the same
+//! lexer that [`super::Tree::from_str`] uses, so the tokens always agree with
+//! the parser about where nodes begin and end. Unlike the parser this never
+//! fails: malformed input simply yields tokens which a parser would reject,
+//! which makes it suitable for syntax highlighting and similar tooling that must
+//! cope with incomplete input.
+//!
+
+use core::ops;
+
+use super::{Lexeme, Lexemes};
+
+/// The kind of a lexical token in an expression string.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    /// The name of a node which has children, e.g. `wsh` or `and_v`.
+    Fragment,
+    /// Wrapper characters preceding a `:`, e.g. `vc` in `vc:pk_k(...)`.
+    Wrappers,
+    /// A probability preceding an `@` in a policy, e.g. `9` in `9@pk(...)`.
+    Probability,
+    /// A `:` or `@` separator.
+    Separator,
+    /// A terminal consisting only of decimal digits, e.g. a threshold or timelock.
+    Number,
+    /// Any other terminal, typically a key or a hash.
+    Terminal,
+    /// `(`
+    OpenParen,
+    /// `)`
+    CloseParen,
+    /// `{`
+    OpenBrace,
+    /// `}`
+    CloseBrace,
+    /// `,`
+    Comma,
+    /// A `#` followed by the checksum, which extends to the end of the string.
+    Checksum,
+    /// A character outside of the descriptor character set.
+    Invalid,
+}
+
+/// A lexical token in an expression string.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Token<'s> {
+    /// The kind of token.
+    pub kind: TokenKind,
+    /// The text of the token.
+    pub text: &'s str,
+    /// The byte range of the token in the original string.
+    pub span: ops::Range<usize>,
+}
+
+/// An iterator over the tokens of an expression string.
+///
+/// Constructed by [`tokenize`].
+#[derive(Clone, Debug)]
+pub struct Tokens<'s> {
+    s: &'s str,
+    /// Lexemes of the part of the string before the checksum.
+    lexemes: Lexemes<'s>,
+    /// The part of the current name which has not been yielded yet.
+    name: ops::Range<usize>,
+    /// The start of the checksum, or the end of the string once it is yielded.
+    checksum_pos: usize,
+}
+
+/// Splits an expression string into tokens.
+///
+/// Every byte of the input belongs to exactly one token, and tokens are yielded
+/// in order, so the input can be reconstructed by concatenating them.
+pub fn tokenize(s: &str) -> Tokens<'_> {
+    let checksum_pos = s.find('#').unwrap_or(s.len());
+    Tokens { s, lexemes: Lexemes::new(&s[..checksum_pos]), name: 0..0, checksum_pos }
+}
+
+fn is_valid(ch: char) -> bool { (32..127).contains(&u32::from(ch)) }
+
+impl<'s> Tokens<'s> {
+    /// Splits the next token off the front of the current name.
+    ///
+    /// Names are further divided into wrappers or probabilities and their
+    /// separators, runs of invalid characters, and the remaining text.
+    fn next_in_name(&mut self) -> (TokenKind, usize) {
+        let rest = &self.s[self.name.clone()];
+        let ch = rest.chars().next().expect("name is non-empty");
+        if ch == ':' || ch == '@' {
+            return (TokenKind::Separator, 1);
+        }
+        if !is_valid(ch) {
+            return (TokenKind::Invalid, ch.len_utf8());
+        }
+
+        let run_len = rest.find(|ch| !is_valid(ch)).unwrap_or(rest.len());
+        let run = &rest[..run_len];
+        match run.find([':', '@']) {
+            Some(sep_pos) if run.as_bytes()[sep_pos] == b':' => (TokenKind::Wrappers, sep_pos),
+            Some(sep_pos) => (TokenKind::Probability, sep_pos),
+            None if run_len == rest.len()
+                && self.s[self.name.end..].starts_with(['(', '{']) =>
+            {
+                (TokenKind::Fragment, run_len)
+            }
+            None if run.bytes().all(|b| b.is_ascii_digit()) => (TokenKind::Number, run_len),
+            None => (TokenKind::Terminal, run_len),
+        }
+    }
+}
+
+impl<'s> Iterator for Tokens<'s> {
+    type Item = Token<'s>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (kind, span) = loop {
+            if !self.name.is_empty() {
+                let (kind, len) = self.next_in_name();
+                let span = self.name.start..self.name.start + len;
+                self.name.start += len;
+                break (kind, span);
+            }
+
+            match self.lexemes.next() {
+                Some(Lexeme::Name { pos, text }) => self.name = pos..pos + text.len(),
+                Some(Lexeme::Open { pos, ch }) => {
+                    let kind =
+                        if ch == b'(' { TokenKind::OpenParen } else { TokenKind::OpenBrace };
+                    break (kind, pos..pos + 1);
+                }
+                Some(Lexeme::Comma { pos }) => break (TokenKind::Comma, pos..pos + 1),
+                Some(Lexeme::Close { pos, ch }) => {
+                    let kind =
+                        if ch == b')' { TokenKind::CloseParen } else { TokenKind::CloseBrace };
+                    break (kind, pos..pos + 1);
+                }
+                None if self.checksum_pos < self.s.len() => {
+                    let span = self.checksum_pos..self.s.len();
+                    self.checksum_pos = self.s.len();
+                    break (TokenKind::Checksum, span);
+                }
+                None => return None,
+            }
+        };
+        Some(Token { kind, text: &self.s[span.clone()], span })
+    }
+}
+
+impl core::iter::FusedIterator for Tokens<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::*;
+
+    fn kinds(s: &str) -> Vec<(TokenKind, &str)> {
+        tokenize(s).map(|tok| (tok.kind, tok.text)).collect()
+    }
+
+    #[test]
+    fn tokenize_descriptor() {
+        use TokenKind::*;
+
+        assert_eq!(kinds(""), vec![]);
+        assert_eq!(
+            kinds("wsh(and_v(v:pk(A),older(144)))#abcdefgh"),
+            vec![
+                (Fragment, "wsh"),
+                (OpenParen, "("),
+                (Fragment, "and_v"),
+                (OpenParen, "("),
+                (Wrappers, "v"),
+                (Separator, ":"),
+                (Fragment, "pk"),
+                (OpenParen, "("),
+                (Terminal, "A"),
+                (CloseParen, ")"),
+                (Comma, ","),
+                (Fragment, "older"),
+                (OpenParen, "("),
+                (Number, "144"),
+                (CloseParen, ")"),
+                (CloseParen, ")"),
+                (CloseParen, ")"),
+                (Checksum, "#abcdefgh"),
+            ],
+        );
+        assert_eq!(
+            kinds("tr(K,{pk(A),9@pk(B)})"),
+            vec![
+                (Fragment, "tr"),
+                (OpenParen, "("),
+                (Terminal, "K"),
+                (Comma, ","),
+                (OpenBrace, "{"),
+                (Fragment, "pk"),
+                (OpenParen, "("),
+                (Terminal, "A"),
+                (CloseParen, ")"),
+                (Comma, ","),
+                (Probability, "9"),
+                (Separator, "@"),
+                (Fragment, "pk"),
+                (OpenParen, "("),
+                (Terminal, "B"),
+                (CloseParen, ")"),
+                (CloseBrace, "}"),
+                (CloseParen, ")"),
+            ],
+        );
+    }
+
+    #[test]
+    fn tokenize_agrees_with_tree() {
+        let s = "tr(K,{and_v(v:pk(A),older(144)),9@multi_a(1,B,C)})";
+        let tree = super::super::Tree::from_str(s).unwrap();
+        let tokens = tokenize(s).collect::<Vec<_>>();
+        // The tokens starting within each node name make up exactly that name.
+        for node in tree.root().pre_order_iter() {
+            let span = node.name_pos()..node.name_pos() + node.name().len();
+            let name = tokens
+                .iter()
+                .filter(|tok| span.contains(&tok.span.start))
+                .map(|tok| tok.text)
+                .collect::<String>();
+            assert_eq!(name, node.name());
+        }
+    }
+
+    #[test]
+    fn tokenize_malformed() {
+        use TokenKind::*;
+
+        let s = "pk([d34db33f/44'/0'/0']xpub/1/*),,é)(:";
+        let tokens = tokenize(s).collect::<Vec<_>>();
+        // Tokens cover the input exactly.
+        assert_eq!(tokens.iter().map(|tok| tok.text).collect::<String>(), s);
+        for tok in &tokens {
+            assert_eq!(&s[tok.span.clone()], tok.text);
+        }
+        assert_eq!(
+            kinds(s),
+            vec![
+                (Fragment, "pk"),
+                (OpenParen, "("),
+                (Terminal, "[d34db33f/44'/0'/0']xpub/1/*"),
+                (CloseParen, ")"),
+                (Comma, ","),
+                (Comma, ","),
+                (Invalid, "é"),
+                (CloseParen, ")"),
+                (OpenParen, "("),
+                (Separator, ":"),
+            ],
+        );
+    }
+}
@@ -0,0 +1,350 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! FROST Threshold Signatures
+//!
+//! Support for satisfying Taproot keys which are the group keys of a threshold
+//! Schnorr (FROST) signing group. Descriptors see such a key as an ordinary
+//! x-only key, while satisfying it takes signature shares from a threshold of
+//! the participants.
+//!
+//! The FROST protocol itself is left to downstream libraries, which implement
+//! [`FrostGroup`] to aggregate the shares of their participants. A
+//! [`FrostSatisfier`] then collects the shares of each signing session and
+//! provides the aggregated signatures to the satisfaction, after checking them
+//! against the key of the descriptor. With the `test-utils` feature,
+//! `MockFrostGroup` is a test double producing signatures with the group
+//! secret key.
+//!
+
+use core::fmt;
+
+use bitcoin::hashes::Hash;
+#[cfg(any(test, feature = "test-utils"))]
+use bitcoin::key::Keypair;
+use bitcoin::key::TapTweak;
+#[cfg(any(test, feature = "test-utils"))]
+use bitcoin::secp256k1::SecretKey;
+use bitcoin::secp256k1::{self, schnorr, Secp256k1, XOnlyPublicKey};
+use bitcoin::sighash::TapSighashType;
+use bitcoin::taproot::{self, TapLeafHash};
+use bitcoin::TapSighash;
+
+use crate::miniscript::satisfy::SchnorrSigType;
+use crate::prelude::*;
+use crate::{MiniscriptKey, Satisfier, ToPublicKey};
+
+/// A FROST signing group, able to aggregate the signature shares of its participants
+pub trait FrostGroup {
+    /// The signature share of a participant
+    type Share: Clone + fmt::Debug;
+    /// Error returned when the shares cannot be aggregated
+    type Error: fmt::Debug;
+
+    /// The group key, without any Taproot tweak
+    fn group_key(&self) -> XOnlyPublicKey;
+
+    /// The number of participants needed to sign
+    fn threshold(&self) -> usize;
+
+    /// Aggregates signature shares of `sighash` into a signature
+    ///
+    /// For a key spend, the signature is by the group key tweaked with the merkle root of the
+    /// spend, as the group key is the internal key of the output. For a script spend, it is by
+    /// the untweaked group key.
+    fn aggregate(
+        &self,
+        sighash: &TapSighash,
+        spend: &SchnorrSigType,
+        shares: &[Self::Share],
+    ) -> Result<schnorr::Signature, Self::Error>;
+}
+
+/// The signature shares of a signing session of a [`FrostGroup`]
+#[derive(Clone, Debug)]
+pub struct FrostSession<G: FrostGroup> {
+    /// The signing group
+    pub group: G,
+    /// Whether the session signs for a key spend or a script spend
+    pub spend: SchnorrSigType,
+    /// The signed sighash
+    pub sighash: TapSighash,
+    /// The sighash type of the final signature
+    pub sighash_type: TapSighashType,
+    /// The signature shares gathered so far
+    pub shares: Vec<G::Share>,
+}
+
+impl<G: FrostGroup> FrostSession<G> {
+    /// Creates a session without any signature share
+    pub fn new(
+        group: G,
+        spend: SchnorrSigType,
+        sighash: TapSighash,
+        sighash_type: TapSighashType,
+    ) -> Self {
+        FrostSession { group, spend, sighash, sighash_type, shares: vec![] }
+    }
+
+    /// Adds the signature share of a participant
+    pub fn add_share(&mut self, share: G::Share) { self.shares.push(share); }
+
+    /// The key the final signature is checked against
+    pub fn signing_key(&self) -> XOnlyPublicKey {
+        let group_key = self.group.group_key();
+        match self.spend {
+            SchnorrSigType::KeySpend { merkle_root } => {
+                let secp = Secp256k1::verification_only();
+                group_key
+                    .tap_tweak(&secp, merkle_root)
+                    .0
+                    .to_x_only_public_key()
+            }
+            SchnorrSigType::ScriptSpend { .. } => group_key,
+        }
+    }
+
+    /// Aggregates the shares into the final signature, once there are enough of them
+    pub fn signature(&self) -> Result<taproot::Signature, FrostError<G::Error>> {
+        if self.shares.len() < self.group.threshold() {
+            return Err(FrostError::NotEnoughShares {
+                threshold: self.group.threshold(),
+                shares: self.shares.len(),
+            });
+        }
+        let signature = self
+            .group
+            .aggregate(&self.sighash, &self.spend, &self.shares)
+            .map_err(FrostError::Group)?;
+        let secp = Secp256k1::verification_only();
+        let msg = secp256k1::Message::from_digest(self.sighash.to_byte_array());
+        secp.verify_schnorr(&signature, &msg, &self.signing_key())
+            .map_err(|_| FrostError::InvalidSignature)?;
+        Ok(taproot::Signature { signature, sighash_type: self.sighash_type })
+    }
+}
+
+/// An error producing the signature of a [`FrostSession`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FrostError<E> {
+    /// Fewer shares than the threshold of the group were gathered
+    NotEnoughShares {
+        /// The threshold of the group
+        threshold: usize,
+        /// The number of shares gathered
+        shares: usize,
+    },
+    /// The group failed to aggregate the shares
+    Group(E),
+    /// The aggregated signature is not valid for the signing key
+    InvalidSignature,
+}
+
+impl<E: fmt::Display> fmt::Display for FrostError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FrostError::NotEnoughShares { threshold, shares } => {
+                write!(f, "{} shares gathered, {} needed", shares, threshold)
+            }
+            FrostError::Group(ref e) => e.fmt(f),
+            FrostError::InvalidSignature => f.write_str("invalid aggregated signature"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: fmt::Debug + fmt::Display> std::error::Error for FrostError<E> {}
+
+/// A satisfier providing the signatures of FROST signing sessions
+///
+/// Sessions are matched to the keys of the descriptor by their group key. Key spend sessions
+/// provide the Taproot key spend signature. As other signatures are not provided, the satisfier
+/// is meant to be paired with another one in a tuple or a [`crate::SatisfierChain`].
+#[derive(Clone, Debug)]
+pub struct FrostSatisfier<G: FrostGroup> {
+    /// The signing sessions
+    pub sessions: Vec<FrostSession<G>>,
+}
+
+impl<G: FrostGroup> FrostSatisfier<G> {
+    /// Creates a satisfier without any session
+    pub fn new() -> Self { FrostSatisfier { sessions: vec![] } }
+
+    /// Adds a signing session
+    pub fn add_session(&mut self, session: FrostSession<G>) { self.sessions.push(session); }
+}
+
+impl<G: FrostGroup> Default for FrostSatisfier<G> {
+    fn default() -> Self { FrostSatisfier::new() }
+}
+
+impl<Pk: MiniscriptKey + ToPublicKey, G: FrostGroup> Satisfier<Pk> for FrostSatisfier<G> {
+    fn lookup_tap_key_spend_sig(&self) -> Option<taproot::Signature> {
+        self.sessions
+            .iter()
+            .filter(|session| matches!(session.spend, SchnorrSigType::KeySpend { .. }))
+            .find_map(|session| session.signature().ok())
+    }
+
+    fn lookup_tap_leaf_script_sig(
+        &self,
+        pk: &Pk,
+        leaf_hash: &TapLeafHash,
+    ) -> Option<taproot::Signature> {
+        let key = pk.to_x_only_pubkey();
+        self.sessions
+            .iter()
+            .filter(|session| {
+                session.group.group_key() == key
+                    && session.spend == SchnorrSigType::ScriptSpend { leaf_hash: *leaf_hash }
+            })
+            .find_map(|session| session.signature().ok())
+    }
+}
+
+#[cfg(any(test, feature = "test-utils"))]
+/// A [`FrostGroup`] signing with the group secret key, for tests
+///
+/// Participants are numbered from 1, and their shares are just their numbers. Once a threshold
+/// of distinct participants provided a share, the group signs like a trusted dealer would.
+#[derive(Debug, Clone)]
+pub struct MockFrostGroup {
+    secret: SecretKey,
+    threshold: usize,
+    participants: u32,
+    secp: Secp256k1<secp256k1::All>,
+}
+
+#[cfg(any(test, feature = "test-utils"))]
+impl MockFrostGroup {
+    /// Creates a group of `participants` with the group secret key `secret`
+    pub fn new(secret: SecretKey, threshold: usize, participants: u32) -> Self {
+        MockFrostGroup { secret, threshold, participants, secp: Secp256k1::new() }
+    }
+}
+
+#[cfg(any(test, feature = "test-utils"))]
+/// Error returned by a [`MockFrostGroup`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MockFrostError {
+    /// A share is from a participant outside of the group
+    UnknownParticipant(u32),
+    /// A participant provided several shares
+    DuplicateShare(u32),
+}
+
+#[cfg(any(test, feature = "test-utils"))]
+impl fmt::Display for MockFrostError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MockFrostError::UnknownParticipant(i) => write!(f, "unknown participant {}", i),
+            MockFrostError::DuplicateShare(i) => write!(f, "duplicate share of participant {}", i),
+        }
+    }
+}
+
+#[cfg(all(feature = "std", any(test, feature = "test-utils")))]
+impl std::error::Error for MockFrostError {}
+
+#[cfg(any(test, feature = "test-utils"))]
+impl FrostGroup for MockFrostGroup {
+    type Share = u32;
+    type Error = MockFrostError;
+
+    fn group_key(&self) -> XOnlyPublicKey { self.secret.x_only_public_key(&self.secp).0 }
+
+    fn threshold(&self) -> usize { self.threshold }
+
+    fn aggregate(
+        &self,
+        sighash: &TapSighash,
+        spend: &SchnorrSigType,
+        shares: &[u32],
+    ) -> Result<schnorr::Signature, MockFrostError> {
+        for (i, &share) in shares.iter().enumerate() {
+            if share == 0 || share > self.participants {
+                return Err(MockFrostError::UnknownParticipant(share));
+            }
+            if shares[..i].contains(&share) {
+                return Err(MockFrostError::DuplicateShare(share));
+            }
+        }
+        let keypair = Keypair::from_secret_key(&self.secp, &self.secret);
+        let keypair = match *spend {
+            SchnorrSigType::KeySpend { merkle_root } => {
+                keypair.tap_tweak(&self.secp, merkle_root).to_keypair()
+            }
+            SchnorrSigType::ScriptSpend { .. } => keypair,
+        };
+        let msg = secp256k1::Message::from_digest(sighash.to_byte_array());
+        Ok(self.secp.sign_schnorr_no_aux_rand(&msg, &keypair))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use bitcoin::taproot::LeafVersion;
+
+    use super::*;
+    use crate::{Descriptor, MapSatisfier, Tap};
+
+    #[test]
+    fn frost_satisfier() {
+        let secret = SecretKey::from_slice(&[3; 32]).unwrap();
+        let group = MockFrostGroup::new(secret, 2, 3);
+        let group_key = group.group_key();
+        let sighash = TapSighash::from_byte_array([5; 32]);
+
+        // A key spend of an output with the group key as internal key
+        let desc = Descriptor::<XOnlyPublicKey>::from_str(&format!("tr({})", group_key)).unwrap();
+        let spend = SchnorrSigType::KeySpend { merkle_root: None };
+        let mut session = FrostSession::new(group.clone(), spend, sighash, TapSighashType::All);
+        session.add_share(1);
+        assert_eq!(
+            session.signature(),
+            Err(FrostError::NotEnoughShares { threshold: 2, shares: 1 })
+        );
+        session.add_share(1);
+        assert_eq!(
+            session.signature(),
+            Err(FrostError::Group(MockFrostError::DuplicateShare(1)))
+        );
+        session.shares[1] = 3;
+        let sig = session.signature().unwrap();
+        let mut satisfier = FrostSatisfier::new();
+        satisfier.add_session(session);
+        let (witness, _) = desc.get_satisfaction(&satisfier).unwrap();
+        assert_eq!(witness, vec![sig.to_vec()]);
+
+        // A script spend, along with an ordinary key
+        let other = XOnlyPublicKey::from_str(
+            "500a2b48b0f66c8183cc0d6645ab21cc19c7fad8a33ff04d41c3ece54b0bc1c5",
+        )
+        .unwrap();
+        let ms = crate::Miniscript::<XOnlyPublicKey, Tap>::from_str(&format!(
+            "and_v(v:pk({}),pk({}))",
+            group_key, other
+        ))
+        .unwrap();
+        let leaf_hash = TapLeafHash::from_script(&ms.encode(), LeafVersion::TapScript);
+        let spend = SchnorrSigType::ScriptSpend { leaf_hash };
+        let mut session = FrostSession::new(group, spend, sighash, TapSighashType::Default);
+        session.add_share(2);
+        session.add_share(3);
+        let sig = session.signature().unwrap();
+        let mut satisfier = FrostSatisfier::new();
+        satisfier.add_session(session);
+        let other_sig = taproot::Signature {
+            signature: schnorr::Signature::from_slice(&[1; 64]).unwrap(),
+            sighash_type: TapSighashType::Default,
+        };
+        let mut map = MapSatisfier::new();
+        assert!(ms.satisfy((&satisfier, &map)).is_err());
+        map.add_tap_leaf_script_sig(other, leaf_hash, other_sig);
+        assert_eq!(
+            ms.satisfy((&satisfier, &map)).unwrap(),
+            vec![other_sig.to_vec(), sig.to_vec()]
+        );
+    }
+}
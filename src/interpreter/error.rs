@@ -12,6 +12,7 @@ use bitcoin::secp256k1;
 use bitcoin::{absolute, relative, taproot};
 
 use super::BitcoinKey;
+use crate::miniscript::decode::UnspendableScript;
 use crate::prelude::*;
 
 /// Detailed Error type for Interpreter
@@ -30,10 +31,25 @@ pub enum Error {
     ControlBlockParse(taproot::TaprootError),
     /// Tap control block(merkle proofs + tweak) verification error
     ControlBlockVerificationError,
+    /// The interpreter and libbitcoinconsensus disagree on the validity of a spend
+    #[cfg(feature = "consensus-verify")]
+    ConsensusDiscrepancy(ConsensusDiscrepancy),
     /// General Interpreter error.
     CouldNotEvaluate,
+    /// The scriptPubKey of the spent output is not that of the descriptor
+    /// expected for the input
+    DescriptorMismatch,
+    /// A descriptor (or `None`) must be provided for every input of the
+    /// transaction: number of inputs, number of descriptors
+    DescriptorsLengthMismatch(usize, usize),
+    /// Spend of a Taproot leaf with a future version, rejected as the
+    /// interpreter was asked to discourage upgradable Taproot versions
+    DiscouragedUpgradableTaprootVersion(taproot::FutureLeafVersion),
     /// ECDSA Signature related error
     EcdsaSig(bitcoin::ecdsa::Error),
+    /// An error which occurred while evaluating the script, with the state of
+    /// the evaluation at that point
+    Evaluation(Box<EvaluationError>),
     /// We expected a push (including a `OP_1` but no other numeric pushes)
     ExpectedPush,
     /// The preimage to the hash function must be exactly 32 bytes.
@@ -79,6 +95,9 @@ pub enum Error {
     /// The Public Key hash check for the given pubkey. This occurs in `PkH`
     /// node when the given key does not match to Hash in script.
     PkHashVerifyFail(hash160::Hash),
+    /// A spent output must be provided for every input of the transaction:
+    /// number of inputs, number of spent outputs
+    PrevoutsLengthMismatch(usize, usize),
     /// Parse Error while parsing a `stack::Element::Push` as a Pubkey. Both
     /// 33 byte and 65 bytes are supported.
     PubkeyParseError,
@@ -97,10 +116,14 @@ pub enum Error {
     /// Errors in signature hash calculations
     SighashError(bitcoin::sighash::InvalidSighashTypeError),
     /// Taproot Annex Unsupported
+    ///
+    /// No longer returned, since the interpreter supports the annex.
     TapAnnexUnsupported,
     /// An uncompressed public key was encountered in a context where it is
     /// disallowed (e.g. in a Segwit script or p2wpkh output)
     UncompressedPubkey,
+    /// The spent output is provably unspendable, such as a null data output
+    UnspendableOutput(UnspendableScript),
     /// Got `stack::Element::Satisfied` or `stack::Element::Dissatisfied` when the
     /// interpreter was expecting `stack::Element::Push`
     UnexpectedStackBoolean,
@@ -115,6 +138,16 @@ pub enum Error {
     VerifyFailed,
 }
 
+impl Error {
+    /// The error, stripped of the evaluation context attached to it, if any
+    pub fn without_context(&self) -> &Error {
+        match *self {
+            Error::Evaluation(ref e) => &e.error,
+            ref e => e,
+        }
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -131,7 +164,19 @@ impl fmt::Display for Error {
             Error::ControlBlockVerificationError => {
                 f.write_str("Control block verification failed")
             }
+            #[cfg(feature = "consensus-verify")]
+            Error::ConsensusDiscrepancy(ref e) => fmt::Display::fmt(e, f),
+            Error::DescriptorMismatch => {
+                f.write_str("spent scriptpubkey did not match the expected descriptor")
+            }
+            Error::DescriptorsLengthMismatch(inputs, descs) => {
+                write!(f, "transaction has {} inputs but {} descriptors were given", inputs, descs)
+            }
+            Error::DiscouragedUpgradableTaprootVersion(version) => {
+                write!(f, "spend of discouraged future leaf version {:#04x}", version)
+            }
             Error::EcdsaSig(ref s) => write!(f, "Ecdsa sig error: {}", s),
+            Error::Evaluation(ref e) => fmt::Display::fmt(e, f),
             Error::ExpectedPush => f.write_str("expected push in script"),
             Error::CouldNotEvaluate => f.write_str("Interpreter Error: Could not evaluate"),
             Error::HashPreimageLengthMismatch => f.write_str("Hash preimage should be 32 bytes"),
@@ -159,6 +204,11 @@ impl fmt::Display for Error {
             }
             Error::PkEvaluationError(ref key) => write!(f, "Incorrect Signature for pk {}", key),
             Error::PkHashVerifyFail(ref hash) => write!(f, "Pubkey Hash check failed {}", hash),
+            Error::PrevoutsLengthMismatch(inputs, prevouts) => write!(
+                f,
+                "transaction has {} inputs but {} spent outputs were given",
+                inputs, prevouts
+            ),
             Error::PubkeyParseError => f.write_str("could not parse pubkey"),
             Error::XOnlyPublicKeyParseError => f.write_str("could not parse x-only pubkey"),
             Error::RelativeLockTimeNotMet(n) => {
@@ -175,6 +225,9 @@ impl fmt::Display for Error {
             Error::UncompressedPubkey => {
                 f.write_str("uncompressed pubkey in non-legacy descriptor")
             }
+            Error::UnspendableOutput(ref script) => {
+                write!(f, "spend of provably unspendable output: {}", script)
+            }
             Error::UnexpectedStackBoolean => {
                 f.write_str("Expected Stack Push operation, found stack bool")
             }
@@ -198,6 +251,9 @@ impl error::Error for Error {
             | CannotInferTrDescriptors
             | ControlBlockVerificationError
             | CouldNotEvaluate
+            | DescriptorMismatch
+            | DescriptorsLengthMismatch(_, _)
+            | DiscouragedUpgradableTaprootVersion(_)
             | ExpectedPush
             | HashPreimageLengthMismatch
             | IncorrectPubkeyHash
@@ -213,6 +269,7 @@ impl error::Error for Error {
             | MultiSigEvaluationError
             | NonEmptyWitness
             | NonEmptyScriptSig
+            | PrevoutsLengthMismatch(_, _)
             | PubkeyParseError
             | XOnlyPublicKeyParseError
             | PkEvaluationError(_)
@@ -222,12 +279,16 @@ impl error::Error for Error {
             | ScriptSatisfactionError
             | TapAnnexUnsupported
             | UncompressedPubkey
+            | UnspendableOutput(_)
             | UnexpectedStackBoolean
             | UnexpectedStackEnd
             | UnexpectedStackElementPush
             | VerifyFailed => None,
             ControlBlockParse(e) => Some(e),
+            #[cfg(feature = "consensus-verify")]
+            ConsensusDiscrepancy(e) => Some(e),
             EcdsaSig(e) => Some(e),
+            Evaluation(e) => Some(&e.error),
             Miniscript(e) => Some(e),
             Secp(e) => Some(e),
             SchnorrSig(e) => Some(e),
@@ -261,6 +322,93 @@ impl From<crate::Error> for Error {
     fn from(e: crate::Error) -> Error { Error::Miniscript(e) }
 }
 
+/// An error which occurred while evaluating a script, with the context needed
+/// to understand why the witness was rejected
+#[derive(Debug)]
+pub struct EvaluationError {
+    /// The error itself
+    pub error: Error,
+    /// The fragment being evaluated when the error occurred, if any
+    ///
+    /// Errors raised once the whole script has been evaluated, such as the
+    /// script not being satisfied, are not attributed to a fragment.
+    pub fragment: Option<String>,
+    /// Index of the fragment in a pre-order traversal of the script
+    pub fragment_index: Option<usize>,
+    /// Offset of the encoding of the fragment in the script, in bytes
+    pub script_position: Option<usize>,
+    /// The elements at the top of the stack when the error occurred, from
+    /// the top down
+    pub stack_top: Vec<Vec<u8>>,
+}
+
+impl fmt::Display for EvaluationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.error, f)?;
+        if let Some(ref fragment) = self.fragment {
+            write!(f, " in fragment {}", fragment)?;
+        }
+        if let Some(index) = self.fragment_index {
+            write!(f, " (#{}", index)?;
+            if let Some(position) = self.script_position {
+                write!(f, ", at script offset {}", position)?;
+            }
+            f.write_str(")")?;
+        }
+        f.write_str("; stack top: [")?;
+        for (i, elem) in self.stack_top.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            if elem.is_empty() {
+                f.write_str("<empty>")?;
+            } else {
+                write!(f, "{}", elem.as_hex())?;
+            }
+        }
+        f.write_str("]")
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for EvaluationError {
+    fn cause(&self) -> Option<&dyn error::Error> { Some(&self.error) }
+}
+
+/// A spend on which the interpreter and libbitcoinconsensus disagree
+#[cfg(feature = "consensus-verify")]
+#[derive(Debug)]
+pub enum ConsensusDiscrepancy {
+    /// The interpreter accepted the spend, but libbitcoinconsensus rejected it
+    ConsensusRejected(bitcoin::consensus::validation::BitcoinconsensusError),
+    /// libbitcoinconsensus accepted the spend, but the interpreter rejected it
+    InterpreterRejected(Box<Error>),
+}
+
+#[cfg(feature = "consensus-verify")]
+impl fmt::Display for ConsensusDiscrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ConsensusDiscrepancy::ConsensusRejected(ref e) => {
+                write!(f, "interpreter accepted a spend which libbitcoinconsensus rejected: {}", e)
+            }
+            ConsensusDiscrepancy::InterpreterRejected(ref e) => {
+                write!(f, "interpreter rejected a spend which libbitcoinconsensus accepted: {}", e)
+            }
+        }
+    }
+}
+
+#[cfg(all(feature = "consensus-verify", feature = "std"))]
+impl error::Error for ConsensusDiscrepancy {
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            ConsensusDiscrepancy::ConsensusRejected(ref e) => Some(e),
+            ConsensusDiscrepancy::InterpreterRejected(ref e) => Some(e),
+        }
+    }
+}
+
 /// A type of representing which keys errored during interpreter checksig evaluation
 // Note that we can't use BitcoinKey because it is not public
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -2,11 +2,12 @@
 // SPDX-License-Identifier: CC0-1.0
 
 use bitcoin::hashes::{hash160, sha256, Hash};
-use bitcoin::taproot::{ControlBlock, TAPROOT_ANNEX_PREFIX};
+use bitcoin::taproot::{ControlBlock, FutureLeafVersion, LeafVersion, TAPROOT_ANNEX_PREFIX};
 use bitcoin::Witness;
 
 use super::{stack, BitcoinKey, Error, Stack};
 use crate::miniscript::context::{NoChecks, ScriptContext, SigType};
+use crate::miniscript::decode::UnspendableScript;
 use crate::prelude::*;
 use crate::{BareCtx, ExtParams, Legacy, Miniscript, Segwitv0, Tap, ToPublicKey, Translator};
 
@@ -81,6 +82,9 @@ pub(super) enum Inner {
     PublicKey(super::BitcoinKey, PubkeyType),
     /// The script being evaluated is an actual script
     Script(Miniscript<super::BitcoinKey, NoChecks>, ScriptType),
+    /// The spend is a Taproot script spend of a leaf with a future version,
+    /// which is unconditionally valid
+    FutureLeaf(FutureLeafVersion),
 }
 
 // The `Script` returned by this method is always generated/cloned ... when
@@ -200,10 +204,10 @@ pub(super) fn from_txdata<'txin>(
                 .unwrap_or(false);
             let has_annex = has_annex && (wit_stack.len() >= 2);
             if has_annex {
-                // Annex is non-standard, bitcoin consensus rules ignore it.
-                // Our sighash structure and signature verification
-                // does not support annex, return error
-                return Err(Error::TapAnnexUnsupported);
+                // The annex is not part of the witness stack seen by the script,
+                // it is only committed to by signatures. The interpreter gets
+                // it from the witness directly.
+                wit_stack.pop();
             }
             match wit_stack.len() {
                 0 => Err(Error::UnexpectedStackEnd),
@@ -219,10 +223,22 @@ pub(super) fn from_txdata<'txin>(
                     let tap_script = wit_stack.pop().ok_or(Error::UnexpectedStackEnd)?;
                     let ctrl_blk =
                         ControlBlock::decode(ctrl_blk).map_err(Error::ControlBlockParse)?;
-                    let tap_script = script_from_stack_elem::<Tap>(&tap_script)?;
-                    let ms = tap_script.to_no_checks_ms();
                     // Creating new contexts is cheap
                     let secp = bitcoin::secp256k1::Secp256k1::verification_only();
+                    if let LeafVersion::Future(version) = ctrl_blk.leaf_version {
+                        // The leaf script is not executed, so it need not be Miniscript
+                        let leaf_script = match tap_script {
+                            stack::Element::Push(sl) => bitcoin::ScriptBuf::from_bytes(sl.to_vec()),
+                            stack::Element::Satisfied => bitcoin::ScriptBuf::from_bytes(vec![1]),
+                            stack::Element::Dissatisfied => bitcoin::ScriptBuf::new(),
+                        };
+                        if !ctrl_blk.verify_taproot_commitment(&secp, output_key, &leaf_script) {
+                            return Err(Error::ControlBlockVerificationError);
+                        }
+                        return Ok((Inner::FutureLeaf(version), wit_stack, Some(leaf_script)));
+                    }
+                    let tap_script = script_from_stack_elem::<Tap>(&tap_script)?;
+                    let ms = tap_script.to_no_checks_ms();
                     let tap_script = tap_script.encode();
                     if ctrl_blk.verify_taproot_commitment(&secp, output_key, &tap_script) {
                         Ok((
@@ -325,7 +341,9 @@ pub(super) fn from_txdata<'txin>(
         }
     // ** bare script **
     } else {
-        if wit_stack.is_empty() {
+        if let Some(unspendable) = UnspendableScript::from_script_pubkey(spk) {
+            Err(Error::UnspendableOutput(unspendable))
+        } else if wit_stack.is_empty() {
             // Bare script parsed in BareCtx
             let miniscript = Miniscript::<bitcoin::PublicKey, BareCtx>::parse_with_ext(
                 spk,
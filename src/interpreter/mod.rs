@@ -8,23 +8,29 @@
 //! assuming that the spent coin was descriptor controlled.
 //!
 
-use core::fmt;
 use core::str::FromStr;
+use core::{cmp, fmt};
 
 use bitcoin::hashes::{hash160, ripemd160, sha256, Hash};
 use bitcoin::{absolute, relative, secp256k1, sighash, taproot, Sequence, TxOut, Witness};
 
+use crate::iter::TreeLike;
+use crate::miniscript::analyzable::ExtParams;
 use crate::miniscript::context::{NoChecks, SigType};
-use crate::miniscript::ScriptContext;
+use crate::miniscript::{BareCtx, Legacy, ScriptContext, Segwitv0, Tap};
 use crate::prelude::*;
-use crate::{hash256, Descriptor, Miniscript, Terminal, ToPublicKey};
+use crate::util::varint_len;
+use crate::{hash256, script_num_size, Descriptor, Miniscript, Terminal, ToPublicKey};
 
 mod error;
 mod inner;
 mod stack;
 
-pub use self::error::Error;
+#[cfg(feature = "consensus-verify")]
+pub use self::error::ConsensusDiscrepancy;
+pub use self::error::{Error, EvaluationError};
 use self::error::PkEvalErrInner;
+pub use self::stack::Element;
 use self::stack::Stack;
 use crate::MiniscriptKey;
 
@@ -35,8 +41,155 @@ pub struct Interpreter<'txin> {
     /// For non-Taproot spends, the scriptCode; for Taproot script-spends, this
     /// is the leaf script; for key-spends it is `None`.
     script_code: Option<bitcoin::ScriptBuf>,
+    /// For Taproot spends, the annex of the witness, if any.
+    annex: Option<&'txin [u8]>,
     sequence: Sequence,
     lock_time: absolute::LockTime,
+    flags: VerifyFlags,
+    chain: Option<ChainContext>,
+    /// For Taproot script-spends, the control block of the spent leaf.
+    control_block: Option<taproot::ControlBlock>,
+}
+
+/// Script verification flags of the [`Interpreter`]
+///
+/// Miniscript assumes that witnesses follow the standardness rules of Bitcoin
+/// Core, which the interpreter checks by default. These flags allow relaxing
+/// them, so that spends which are valid per consensus but not standard (such as
+/// those found in historical blocks) can be interpreted, or making the checks
+/// stricter. Rules which are enforced by consensus in Tapscript are always
+/// checked for Taproot spends, whatever the flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VerifyFlags {
+    /// Require the argument of `OP_IF` and `OP_NOTIF` to be either empty or
+    /// `0x01` (`SCRIPT_VERIFY_MINIMALIF`)
+    pub minimal_if: bool,
+    /// Require the extra element consumed by `OP_CHECKMULTISIG` to be empty
+    /// (`SCRIPT_VERIFY_NULLDUMMY`)
+    pub null_dummy: bool,
+    /// Require signatures failing verification to be empty
+    /// (`SCRIPT_VERIFY_NULLFAIL`)
+    pub null_fail: bool,
+    /// Reject spends of Taproot leaves with a future version
+    /// (`SCRIPT_VERIFY_DISCOURAGE_UPGRADABLE_TAPROOT_VERSION`)
+    pub discourage_upgradable_taproot_version: bool,
+    /// Require ECDSA signatures to be strictly DER-encoded
+    /// (`SCRIPT_VERIFY_DERSIG`)
+    ///
+    /// Without it, signatures are parsed as Bitcoin Core did before BIP 66,
+    /// accepting lax DER encodings, and their S value is normalized, so that
+    /// the spends of historical blocks can be verified.
+    pub der_sig: bool,
+}
+
+impl VerifyFlags {
+    /// No flags: only the rules enforced by consensus are checked
+    ///
+    /// Note that `SCRIPT_VERIFY_NULLDUMMY` is enforced by consensus since the
+    /// activation of segwit, so should be set when validating later blocks.
+    /// Likewise, `SCRIPT_VERIFY_DERSIG` is enforced since the activation of
+    /// BIP 66.
+    pub const NONE: VerifyFlags = VerifyFlags {
+        minimal_if: false,
+        null_dummy: false,
+        null_fail: false,
+        discourage_upgradable_taproot_version: false,
+        der_sig: false,
+    };
+
+    /// All flags, as used by Bitcoin Core to validate new transactions
+    pub const STANDARD: VerifyFlags = VerifyFlags {
+        minimal_if: true,
+        null_dummy: true,
+        null_fail: true,
+        discourage_upgradable_taproot_version: true,
+        der_sig: true,
+    };
+}
+
+impl Default for VerifyFlags {
+    /// The flags used by [`Interpreter::from_txdata`]
+    ///
+    /// These are the standardness rules Miniscript relies on; spends of
+    /// Taproot leaves with a future version are accepted.
+    fn default() -> Self {
+        VerifyFlags { discourage_upgradable_taproot_version: false, ..VerifyFlags::STANDARD }
+    }
+}
+
+/// The state of the chain a spend is to be included in
+///
+/// Without it, the interpreter only checks that the `nLockTime` and `nSequence`
+/// of the spending transaction satisfy the `after` and `older` fragments of
+/// the script. Given the chain context, it also checks that the timelocks have
+/// actually expired for a transaction included in the block following the tip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChainContext {
+    /// Height of the tip of the chain
+    pub tip_height: absolute::Height,
+    /// Median time past of the tip of the chain
+    pub tip_mtp: absolute::Time,
+    /// Height of the block the spent output was confirmed in
+    pub utxo_confirmation_height: absolute::Height,
+    /// Median time past of the block preceding the one the spent output was
+    /// confirmed in, from which BIP 68 time-based relative lock times count
+    pub utxo_mtp: absolute::Time,
+}
+
+impl ChainContext {
+    /// Whether an absolute lock time has expired for a transaction included in
+    /// the block following the tip
+    ///
+    /// Per BIP 113, time-based lock times are compared to the median time past
+    /// of the tip rather than to the timestamp of the block.
+    pub fn is_absolute_lock_time_met(&self, n: absolute::LockTime) -> bool {
+        match n {
+            absolute::LockTime::Blocks(height) => height <= self.tip_height,
+            absolute::LockTime::Seconds(time) => time < self.tip_mtp,
+        }
+    }
+
+    /// Whether a relative lock time has expired, per BIP 68, for a transaction
+    /// spending the output and included in the block following the tip
+    pub fn is_relative_lock_time_met(&self, n: relative::LockTime) -> bool {
+        match n {
+            relative::LockTime::Blocks(height) => {
+                u64::from(self.utxo_confirmation_height.to_consensus_u32())
+                    + u64::from(height.value())
+                    <= u64::from(self.tip_height.to_consensus_u32()) + 1
+            }
+            relative::LockTime::Time(time) => {
+                u64::from(self.utxo_mtp.to_consensus_u32()) + u64::from(time.value()) * 512
+                    <= u64::from(self.tip_mtp.to_consensus_u32())
+            }
+        }
+    }
+}
+
+/// The leaf of the Taproot tree executed by a script-spend
+///
+/// See [`Interpreter::tap_leaf`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TapLeafSpend {
+    /// The leaf script
+    pub script: bitcoin::ScriptBuf,
+    /// The control block of the spend, committing to the leaf version and
+    /// proving the inclusion of the leaf in the tree
+    pub control_block: taproot::ControlBlock,
+}
+
+impl TapLeafSpend {
+    /// The version of the leaf
+    pub fn leaf_version(&self) -> taproot::LeafVersion { self.control_block.leaf_version }
+
+    /// The hash of the leaf, as committed to by signatures in the leaf
+    pub fn leaf_hash(&self) -> taproot::TapLeafHash {
+        taproot::TapLeafHash::from_script(&self.script, self.leaf_version())
+    }
+
+    /// The depth of the leaf in the Taproot tree, that is the length of its
+    /// merkle path
+    pub fn depth(&self) -> usize { self.control_block.merkle_branch.len() }
 }
 
 // A type representing functions for checking signatures that accept both
@@ -69,37 +222,48 @@ impl KeySigPair {
             KeySigPair::Schnorr(pk, sig) => Some((*pk, *sig)),
         }
     }
+
+    /// The public part of the nonce of the signature: the `r` value of an
+    /// ECDSA signature, or the x-coordinate of the `R` point of a Schnorr one
+    ///
+    /// Two different signatures sharing it reveal the private key used for
+    /// both if it is the same.
+    pub fn nonce(&self) -> [u8; 32] {
+        let mut nonce = [0; 32];
+        match self {
+            KeySigPair::Ecdsa(_, sig) => {
+                nonce.copy_from_slice(&sig.signature.serialize_compact()[..32])
+            }
+            KeySigPair::Schnorr(_, sig) => {
+                nonce.copy_from_slice(&sig.signature.serialize()[..32])
+            }
+        }
+        nonce
+    }
 }
 
-// Internally used enum for different types of bitcoin keys
-// Even though we implement MiniscriptKey for BitcoinKey, we make sure that there
-// are little mis-use
-// - The only constructors for this are only called in from_txdata that take care
-//   using the correct enum variant
-// - This does not implement ToPublicKey to avoid context dependant encoding/decoding of 33/32
-//   byte keys. This allows us to keep a single NoChecks context instead of a context for
-//   for NoChecksSchnorr/NoChecksEcdsa.
+/// A key of a script run by the interpreter
+///
+/// Scripts of every context are interpreted in the `NoChecks` context, so
+/// that its keys may be full keys, from pre-Taproot spends, or x-only keys,
+/// from Tapscript spends. When decoding scripts, 32-byte keys are parsed as
+/// x-only keys and others as full keys.
+///
+/// X-only keys are displayed, and converted to full keys, with a `0x02`
+/// prefix; their hash is the one of their 32-byte serialization when computed
+/// for [`SigType::Schnorr`].
 // Long term TODO: There really should be not be any need for Miniscript<Pk: MiniscriptKey> struct
 // to have the Pk: MiniscriptKey bound. The bound should be on all of it's methods. That would
 // require changing Miniscript struct to three generics Miniscript<Pk, Pkh, Ctx> and bound on
 // all of the methods of Miniscript to ensure that Pkh = Pk::Hash
 #[derive(Hash, Eq, Ord, PartialEq, PartialOrd, Clone, Copy, Debug)]
-enum BitcoinKey {
-    // Full key
+pub enum BitcoinKey {
+    /// Full key, used by pre-Taproot scripts
     Fullkey(bitcoin::PublicKey),
-    // Xonly key
+    /// X-only key, used by Tapscript
     XOnlyPublicKey(bitcoin::key::XOnlyPublicKey),
 }
 
-impl BitcoinKey {
-    fn to_pubkeyhash(self, sig_type: SigType) -> hash160::Hash {
-        match self {
-            BitcoinKey::Fullkey(pk) => pk.to_pubkeyhash(sig_type),
-            BitcoinKey::XOnlyPublicKey(pk) => pk.to_pubkeyhash(sig_type),
-        }
-    }
-}
-
 // Displayed in full 33 byte representation. X-only keys are displayed with 0x02 prefix
 impl fmt::Display for BitcoinKey {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -130,6 +294,37 @@ impl MiniscriptKey for BitcoinKey {
             BitcoinKey::XOnlyPublicKey(_) => false,
         }
     }
+
+    fn is_x_only_key(&self) -> bool {
+        match *self {
+            BitcoinKey::Fullkey(_) => false,
+            BitcoinKey::XOnlyPublicKey(_) => true,
+        }
+    }
+}
+
+impl ToPublicKey for BitcoinKey {
+    fn to_public_key(&self) -> bitcoin::PublicKey {
+        match self {
+            BitcoinKey::Fullkey(pk) => *pk,
+            BitcoinKey::XOnlyPublicKey(pk) => pk.to_public_key(),
+        }
+    }
+
+    fn to_x_only_pubkey(&self) -> bitcoin::key::XOnlyPublicKey {
+        match self {
+            BitcoinKey::Fullkey(pk) => pk.to_x_only_pubkey(),
+            BitcoinKey::XOnlyPublicKey(pk) => *pk,
+        }
+    }
+
+    fn to_sha256(hash: &sha256::Hash) -> sha256::Hash { *hash }
+
+    fn to_hash256(hash: &hash256::Hash) -> hash256::Hash { *hash }
+
+    fn to_ripemd160(hash: &ripemd160::Hash) -> ripemd160::Hash { *hash }
+
+    fn to_hash160(hash: &hash160::Hash) -> hash160::Hash { *hash }
 }
 
 impl<'txin> Interpreter<'txin> {
@@ -145,9 +340,91 @@ impl<'txin> Interpreter<'txin> {
         witness: &'txin Witness,
         sequence: Sequence,            // CSV, relative lock time.
         lock_time: absolute::LockTime, // CLTV, absolute lock time.
+    ) -> Result<Self, Error> {
+        Interpreter::from_txdata_with_flags(
+            spk,
+            script_sig,
+            witness,
+            sequence,
+            lock_time,
+            VerifyFlags::default(),
+        )
+    }
+
+    /// Same as [`Interpreter::from_txdata`], but with custom script
+    /// verification flags
+    pub fn from_txdata_with_flags(
+        spk: &bitcoin::ScriptBuf,
+        script_sig: &'txin bitcoin::Script,
+        witness: &'txin Witness,
+        sequence: Sequence,
+        lock_time: absolute::LockTime,
+        flags: VerifyFlags,
     ) -> Result<Self, Error> {
         let (inner, stack, script_code) = inner::from_txdata(spk, script_sig, witness)?;
-        Ok(Interpreter { inner, stack, script_code, sequence, lock_time })
+        if let inner::Inner::FutureLeaf(version) = inner {
+            if flags.discourage_upgradable_taproot_version {
+                return Err(Error::DiscouragedUpgradableTaprootVersion(version));
+            }
+        }
+        let annex = if spk.is_p2tr() {
+            witness.taproot_annex()
+        } else {
+            None
+        };
+        let control_block = match inner {
+            inner::Inner::Script(_, inner::ScriptType::Tr) | inner::Inner::FutureLeaf(_) => witness
+                .taproot_control_block()
+                .and_then(|ctrl_blk| taproot::ControlBlock::decode(ctrl_blk).ok()),
+            inner::Inner::PublicKey(..) | inner::Inner::Script(..) => None,
+        };
+        Ok(Interpreter {
+            inner,
+            stack,
+            script_code,
+            annex,
+            sequence,
+            lock_time,
+            flags,
+            chain: None,
+            control_block,
+        })
+    }
+
+    /// The script verification flags of the interpreter
+    pub fn flags(&self) -> VerifyFlags { self.flags }
+
+    /// Sets the state of the chain the spend is evaluated against
+    ///
+    /// The `after` and `older` fragments of the script are then only satisfied
+    /// if their timelocks have expired in this chain, in addition to being
+    /// satisfied by the `nLockTime` and `nSequence` of the transaction.
+    pub fn with_chain_context(mut self, chain: ChainContext) -> Self {
+        self.chain = Some(chain);
+        self
+    }
+
+    /// The state of the chain the spend is evaluated against, if set
+    pub fn chain_context(&self) -> Option<ChainContext> { self.chain }
+
+    /// The BIP-341 annex of a Taproot spend, including its `0x50` prefix
+    ///
+    /// The annex is not part of the stack the script is executed against, but
+    /// is committed to by the signatures of the spend, which
+    /// [`Interpreter::verify_sig`] takes into account.
+    pub fn annex(&self) -> Option<&'txin [u8]> { self.annex }
+
+    /// The leaf executed by a Taproot script-spend
+    ///
+    /// This is the leaf whose commitment in the output key was checked when
+    /// creating the interpreter, including leaves with a future version.
+    pub fn tap_leaf(&self) -> Option<TapLeafSpend> {
+        match (&self.script_code, &self.control_block) {
+            (Some(script), Some(control_block)) => {
+                Some(TapLeafSpend { script: script.clone(), control_block: control_block.clone() })
+            }
+            _ => None,
+        }
     }
 
     /// Same as [`Interpreter::iter`], but allows for a custom verification function.
@@ -169,13 +446,35 @@ impl<'txin> Interpreter<'txin> {
             } else {
                 vec![]
             },
+            script: if let inner::Inner::Script(ref script, _) = self.inner {
+                Some(script)
+            } else {
+                None
+            },
             // Cloning the references to elements of stack should be fine as it allows
             // call interpreter.iter() without mutating interpreter
             stack: self.stack.clone(),
+            trace: None,
+            input: self.stack.as_slice().to_vec(),
+            n_input: self.stack.n_input(),
+            current_node: None,
+            explained: vec![],
+            annex: self.annex,
             sequence: self.sequence,
             lock_time: self.lock_time,
             has_errored: false,
+            future_leaf: self.future_leaf_version().is_some(),
+            flags: self.flags,
+            chain: self.chain,
+            assume_timelocks: false,
             sig_type: self.sig_type(),
+            report: ExecutionReport {
+                op_count: match self.inner {
+                    inner::Inner::Script(ref script, _) => script.ext.ops.count,
+                    _ => 0,
+                },
+                ..ExecutionReport::default()
+            },
         }
     }
 
@@ -188,8 +487,6 @@ impl<'txin> Interpreter<'txin> {
     /// - Insufficient sighash information is present
     /// - sighash single without corresponding output
     // TODO: Create a good first isse to change this to error
-    // TODO: Requires refactor to remove the script_code logic in order to use the new sighash API.
-    #[allow(deprecated)] // For segwit_signature_hash
     pub fn verify_sig<C: secp256k1::Verification, T: Borrow<TxOut>>(
         &self,
         secp: &secp256k1::Secp256k1<C>,
@@ -198,6 +495,30 @@ impl<'txin> Interpreter<'txin> {
         prevouts: &sighash::Prevouts<T>,
         sig: &KeySigPair,
     ) -> bool {
+        let mut cache = sighash::SighashCache::new(tx);
+        self.verify_sig_with_cache(secp, &mut cache, input_idx, prevouts, sig)
+    }
+
+    /// Same as [`Interpreter::verify_sig`], but computes the sighash with the
+    /// given cache of the spending transaction
+    ///
+    /// The cache keeps the parts of the segwit and taproot sighashes which are
+    /// common to all inputs, so sharing it when verifying several inputs of a
+    /// transaction avoids hashing the whole transaction again for each of them.
+    // TODO: Requires refactor to remove the script_code logic in order to use the new sighash API.
+    pub fn verify_sig_with_cache<C, T, R>(
+        &self,
+        secp: &secp256k1::Secp256k1<C>,
+        cache: &mut sighash::SighashCache<R>,
+        input_idx: usize,
+        prevouts: &sighash::Prevouts<T>,
+        sig: &KeySigPair,
+    ) -> bool
+    where
+        C: secp256k1::Verification,
+        T: Borrow<TxOut>,
+        R: Borrow<bitcoin::Transaction>,
+    {
         fn get_prevout<'u, T: Borrow<TxOut>>(
             prevouts: &'u sighash::Prevouts<'u, T>,
             input_index: usize,
@@ -213,7 +534,6 @@ impl<'txin> Interpreter<'txin> {
                 sighash::Prevouts::All(prevouts) => prevouts.get(input_index),
             }
         }
-        let mut cache = bitcoin::sighash::SighashCache::new(tx);
         match sig {
             KeySigPair::Ecdsa(key, ecdsa_sig) => {
                 let script_pubkey = self.script_code.as_ref().expect("Legacy have script code");
@@ -247,10 +567,15 @@ impl<'txin> Interpreter<'txin> {
                 success.unwrap_or(false) // unwrap_or checks for errors, while success would have checksig results
             }
             KeySigPair::Schnorr(xpk, schnorr_sig) => {
+                let annex = self
+                    .annex
+                    .map(|annex| sighash::Annex::new(annex).expect("annex has the annex prefix"));
                 let sighash_msg = if self.is_taproot_v1_key_spend() {
-                    cache.taproot_key_spend_signature_hash(
+                    cache.taproot_signature_hash(
                         input_idx,
                         prevouts,
+                        annex,
+                        None,
                         schnorr_sig.sighash_type,
                     )
                 } else if self.is_taproot_v1_script_spend() {
@@ -262,10 +587,12 @@ impl<'txin> Interpreter<'txin> {
                         tap_script,
                         taproot::LeafVersion::TapScript,
                     );
-                    cache.taproot_script_spend_signature_hash(
+                    // No OP_CODESEPARATOR is ever executed by Miniscript.
+                    cache.taproot_signature_hash(
                         input_idx,
                         prevouts,
-                        leaf_hash,
+                        annex,
+                        Some((leaf_hash, 0xFFFFFFFF)),
                         schnorr_sig.sighash_type,
                     )
                 } else {
@@ -310,11 +637,61 @@ impl<'txin> Interpreter<'txin> {
         self.iter_custom(Box::new(move |sig| self.verify_sig(secp, tx, input_idx, prevouts, sig)))
     }
 
+    /// Same as [`Interpreter::iter`], but computes sighashes with the given
+    /// cache of the spending transaction
+    ///
+    /// See [`Interpreter::verify_sig_with_cache`].
+    pub fn iter_with_cache<'iter, C, T, R>(
+        &'iter self,
+        secp: &'iter secp256k1::Secp256k1<C>,
+        cache: &'iter mut sighash::SighashCache<R>,
+        input_idx: usize,
+        prevouts: &'iter sighash::Prevouts<T>,
+    ) -> Iter<'txin, 'iter>
+    where
+        C: secp256k1::Verification,
+        T: Borrow<TxOut>,
+        R: Borrow<bitcoin::Transaction>,
+    {
+        self.iter_custom(Box::new(move |sig| {
+            self.verify_sig_with_cache(secp, cache, input_idx, prevouts, sig)
+        }))
+    }
+
     /// Creates an iterator over the satisfied spending conditions without checking signatures
+    ///
+    /// See also [`Interpreter::iter_unverified`], which marks the returned
+    /// constraints as such.
     pub fn iter_assume_sigs<'iter>(&'iter self) -> Iter<'txin, 'iter> {
         self.iter_custom(Box::new(|_| true))
     }
 
+    /// Creates an iterator over the satisfied spending conditions, treating
+    /// all well-formed signatures as valid
+    ///
+    /// This is much faster than [`Interpreter::iter`], since no signature is
+    /// verified and no sighash computed, and is enough to find out which
+    /// spending path of the script was taken. The returned constraints are
+    /// wrapped in [`Unverified`] to prevent mistaking them for verified ones.
+    pub fn iter_unverified<'iter>(&'iter self) -> UnverifiedIter<'txin, 'iter> {
+        UnverifiedIter(self.iter_assume_sigs())
+    }
+
+    /// Annotates the elements of the witness with the fragments which consumed
+    /// them, without checking signatures
+    ///
+    /// Fails if the spend is not satisfied. See [`Iter::explain_witness`],
+    /// which can be used with [`Interpreter::iter`] to check signatures.
+    pub fn explain_witness<'iter>(
+        &'iter self,
+    ) -> Result<Vec<ExplainedElement<'iter>>, Error> {
+        let mut iter = self.iter_assume_sigs();
+        for res in &mut iter {
+            res?;
+        }
+        Ok(iter.explain_witness())
+    }
+
     /// Outputs a "descriptor" string which reproduces the spent coins
     ///
     /// This may not represent the original descriptor used to produce the transaction,
@@ -350,6 +727,9 @@ impl<'txin> Interpreter<'txin> {
                 // rawnode and raw leaf.
                 format!("tr(hidden_paths_not_yet_supported,{})", ms)
             }
+            inner::Inner::FutureLeaf(version) => {
+                format!("tr(hidden_paths_not_yet_supported,future_leaf_version_{:#04x})", version)
+            }
         }
     }
 
@@ -366,6 +746,7 @@ impl<'txin> Interpreter<'txin> {
             inner::Inner::Script(_, inner::ScriptType::Wsh) => false,
             inner::Inner::Script(_, inner::ScriptType::ShWsh) => false, // lol "sorta"
             inner::Inner::Script(_, inner::ScriptType::Tr) => false,
+            inner::Inner::FutureLeaf(_) => false,
         }
     }
 
@@ -382,6 +763,7 @@ impl<'txin> Interpreter<'txin> {
             inner::Inner::Script(_, inner::ScriptType::Wsh) => true,
             inner::Inner::Script(_, inner::ScriptType::ShWsh) => true, // lol "sorta"
             inner::Inner::Script(_, inner::ScriptType::Tr) => false,
+            inner::Inner::FutureLeaf(_) => false,
         }
     }
 
@@ -398,6 +780,7 @@ impl<'txin> Interpreter<'txin> {
             inner::Inner::Script(_, inner::ScriptType::Wsh) => false,
             inner::Inner::Script(_, inner::ScriptType::ShWsh) => false,
             inner::Inner::Script(_, inner::ScriptType::Tr) => false,
+            inner::Inner::FutureLeaf(_) => false,
         }
     }
 
@@ -414,6 +797,20 @@ impl<'txin> Interpreter<'txin> {
             inner::Inner::Script(_, inner::ScriptType::Wsh) => false,
             inner::Inner::Script(_, inner::ScriptType::ShWsh) => false,
             inner::Inner::Script(_, inner::ScriptType::Tr) => true,
+            inner::Inner::FutureLeaf(_) => false,
+        }
+    }
+
+    /// The version of the spent leaf, if this is a taproot script spend of a
+    /// leaf with a version other than tapscript
+    ///
+    /// Such spends are valid per consensus whatever the leaf script and the
+    /// witness, so the interpreter does not execute them: iterating over them
+    /// returns no constraints.
+    pub fn future_leaf_version(&self) -> Option<taproot::FutureLeafVersion> {
+        match self.inner {
+            inner::Inner::FutureLeaf(version) => Some(version),
+            inner::Inner::PublicKey(..) | inner::Inner::Script(..) => None,
         }
     }
 
@@ -422,6 +819,7 @@ impl<'txin> Interpreter<'txin> {
         match self.inner {
             inner::Inner::PublicKey(_, inner::PubkeyType::Tr) => SigType::Schnorr,
             inner::Inner::Script(_, inner::ScriptType::Tr) => SigType::Schnorr,
+            inner::Inner::FutureLeaf(_) => SigType::Schnorr,
             inner::Inner::PublicKey(_, inner::PubkeyType::Pk)
             | inner::Inner::PublicKey(_, inner::PubkeyType::Pkh)
             | inner::Inner::PublicKey(_, inner::PubkeyType::Wpkh)
@@ -442,6 +840,580 @@ impl<'txin> Interpreter<'txin> {
     pub fn inferred_descriptor(&self) -> Result<Descriptor<bitcoin::PublicKey>, crate::Error> {
         Descriptor::from_str(&self.inferred_descriptor_string())
     }
+
+    /// The template of the spent output
+    pub fn template(&self) -> SpendTemplate {
+        match self.inner {
+            inner::Inner::PublicKey(_, inner::PubkeyType::Pk) => SpendTemplate::Pk,
+            inner::Inner::PublicKey(_, inner::PubkeyType::Pkh) => SpendTemplate::Pkh,
+            inner::Inner::PublicKey(_, inner::PubkeyType::Wpkh) => SpendTemplate::Wpkh,
+            inner::Inner::PublicKey(_, inner::PubkeyType::ShWpkh) => SpendTemplate::ShWpkh,
+            inner::Inner::PublicKey(_, inner::PubkeyType::Tr) => SpendTemplate::TrKeySpend,
+            inner::Inner::Script(_, inner::ScriptType::Bare) => SpendTemplate::Bare,
+            inner::Inner::Script(_, inner::ScriptType::Sh) => SpendTemplate::Sh,
+            inner::Inner::Script(_, inner::ScriptType::Wsh) => SpendTemplate::Wsh,
+            inner::Inner::Script(_, inner::ScriptType::ShWsh) => SpendTemplate::ShWsh,
+            inner::Inner::Script(_, inner::ScriptType::Tr) => SpendTemplate::TrScriptSpend,
+            inner::Inner::FutureLeaf(_) => SpendTemplate::TrFutureLeafSpend,
+        }
+    }
+
+    /// Classifies the spent output, reconstructing its descriptor and the
+    /// executed Miniscript where possible
+    ///
+    /// See [`ClassifiedSpend`] for what can be reconstructed for each template.
+    pub fn classify(&self) -> ClassifiedSpend {
+        let template = self.template();
+        let descriptor = match template {
+            SpendTemplate::TrKeySpend
+            | SpendTemplate::TrScriptSpend
+            | SpendTemplate::TrFutureLeafSpend => None,
+            _ => self.inferred_descriptor().ok(),
+        };
+        // The script code of a script spend is the executed script, which
+        // the interpreter already parsed as Miniscript without context checks.
+        let script = self.script_code.as_deref();
+        let ext = ExtParams::allow_all();
+        let miniscript = match (template, script) {
+            (SpendTemplate::Bare, Some(script)) => Miniscript::parse_with_ext(script, &ext)
+                .ok()
+                .map(SpentMiniscript::Bare),
+            (SpendTemplate::Sh, Some(script)) => Miniscript::parse_with_ext(script, &ext)
+                .ok()
+                .map(SpentMiniscript::Legacy),
+            (SpendTemplate::Wsh, Some(script)) | (SpendTemplate::ShWsh, Some(script)) => {
+                Miniscript::parse_with_ext(script, &ext)
+                    .ok()
+                    .map(SpentMiniscript::Segwitv0)
+            }
+            (SpendTemplate::TrScriptSpend, Some(script)) => {
+                Miniscript::parse_with_ext(script, &ext)
+                    .ok()
+                    .map(SpentMiniscript::Tap)
+            }
+            _ => None,
+        };
+        ClassifiedSpend { template, descriptor, miniscript }
+    }
+}
+
+/// Template of a spent output, as recognized by the [`Interpreter`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SpendTemplate {
+    /// Pay to public key
+    Pk,
+    /// Pay to public key hash
+    Pkh,
+    /// Pay to witness public key hash
+    Wpkh,
+    /// Pay to witness public key hash, wrapped in pay to script hash
+    ShWpkh,
+    /// A bare script
+    Bare,
+    /// Pay to script hash
+    Sh,
+    /// Pay to witness script hash
+    Wsh,
+    /// Pay to witness script hash, wrapped in pay to script hash
+    ShWsh,
+    /// Pay to taproot, spent with the key path
+    TrKeySpend,
+    /// Pay to taproot, spent with a script path
+    TrScriptSpend,
+    /// Pay to taproot, spent with a script path whose leaf has a future
+    /// version
+    TrFutureLeafSpend,
+}
+
+impl fmt::Display for SpendTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match *self {
+            SpendTemplate::Pk => "p2pk",
+            SpendTemplate::Pkh => "p2pkh",
+            SpendTemplate::Wpkh => "p2wpkh",
+            SpendTemplate::ShWpkh => "p2sh-p2wpkh",
+            SpendTemplate::Bare => "bare",
+            SpendTemplate::Sh => "p2sh",
+            SpendTemplate::Wsh => "p2wsh",
+            SpendTemplate::ShWsh => "p2sh-p2wsh",
+            SpendTemplate::TrKeySpend => "p2tr key path",
+            SpendTemplate::TrScriptSpend => "p2tr script path",
+            SpendTemplate::TrFutureLeafSpend => "p2tr future leaf version script path",
+        })
+    }
+}
+
+/// Miniscript executed by the spend of an output, in the script context of
+/// the output
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SpentMiniscript {
+    /// A bare script
+    Bare(Miniscript<bitcoin::PublicKey, BareCtx>),
+    /// The redeem script of a pay to script hash output
+    Legacy(Miniscript<bitcoin::PublicKey, Legacy>),
+    /// The witness script of a (possibly wrapped) pay to witness script hash
+    /// output
+    Segwitv0(Miniscript<bitcoin::PublicKey, Segwitv0>),
+    /// The leaf script of a taproot script path spend
+    Tap(Miniscript<bitcoin::key::XOnlyPublicKey, Tap>),
+}
+
+impl fmt::Display for SpentMiniscript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            SpentMiniscript::Bare(ref ms) => fmt::Display::fmt(ms, f),
+            SpentMiniscript::Legacy(ref ms) => fmt::Display::fmt(ms, f),
+            SpentMiniscript::Segwitv0(ref ms) => fmt::Display::fmt(ms, f),
+            SpentMiniscript::Tap(ref ms) => fmt::Display::fmt(ms, f),
+        }
+    }
+}
+
+/// Classification of the spend of an output, from its scriptPubKey and the
+/// spending scriptSig and witness
+///
+/// The descriptor is reconstructed for all non-taproot templates, as by
+/// [`Interpreter::inferred_descriptor`], unless it fails the sanity checks
+/// of descriptor parsing. It is never reconstructed for taproot spends, since
+/// neither the internal key of a key path spend nor the other leaves of a
+/// script path spend are revealed.
+///
+/// The Miniscript is reconstructed for all script templates, including
+/// taproot script path spends, even if it is not sane.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClassifiedSpend {
+    /// The template of the spent output
+    pub template: SpendTemplate,
+    /// The descriptor of the spent output, if it could be reconstructed
+    pub descriptor: Option<Descriptor<bitcoin::PublicKey>>,
+    /// The Miniscript executed by the spend, if it could be reconstructed
+    pub miniscript: Option<SpentMiniscript>,
+}
+
+impl ClassifiedSpend {
+    /// Classifies the spend of an output from its scriptPubKey and the
+    /// spending scriptSig and witness
+    ///
+    /// Fails if the spend cannot be interpreted as that of a
+    /// Miniscript-structured output. Signatures are not checked.
+    pub fn from_txdata(
+        spk: &bitcoin::ScriptBuf,
+        script_sig: &bitcoin::Script,
+        witness: &Witness,
+    ) -> Result<Self, Error> {
+        let interpreter = Interpreter::from_txdata(
+            spk,
+            script_sig,
+            witness,
+            Sequence::MAX,
+            absolute::LockTime::ZERO,
+        )?;
+        Ok(interpreter.classify())
+    }
+}
+
+/// Verifies every input of a transaction
+///
+/// `prevouts` must contain the outputs spent by the transaction, in the order
+/// of its inputs. Signatures are checked against the sighashes of the
+/// transaction, which for taproot spends commit to all of the spent outputs.
+///
+/// Returns, for each input, the constraints satisfied by its spend, or the
+/// error with which its interpretation failed. Fails as a whole only if the
+/// number of spent outputs does not match the number of inputs.
+///
+/// With the `consensus-verify` feature, every non-taproot spend is additionally
+/// verified with libbitcoinconsensus, and fails with
+/// `Error::ConsensusDiscrepancy` if exactly one of the interpreter and
+/// libbitcoinconsensus rejects it. Taproot spends are not cross-checked, as
+/// libbitcoinconsensus cannot verify them without all of the spent outputs.
+pub fn verify_transaction<C: secp256k1::Verification>(
+    secp: &secp256k1::Secp256k1<C>,
+    tx: &bitcoin::Transaction,
+    prevouts: &[TxOut],
+) -> Result<Vec<Result<Vec<SatisfiedConstraint>, Error>>, Error> {
+    verify_transaction_inner(secp, tx, prevouts, &vec![None; tx.input.len()])
+}
+
+/// Same as [`verify_transaction`], but additionally checks that the outputs
+/// spent by the transaction are those of the given descriptors
+///
+/// `descriptors` must contain an entry for every input of the transaction;
+/// inputs for which it is `None` are verified without such a check. An input
+/// whose spent output does not match its descriptor fails with
+/// [`Error::DescriptorMismatch`].
+pub fn verify_transaction_with_descriptors<C, Pk>(
+    secp: &secp256k1::Secp256k1<C>,
+    tx: &bitcoin::Transaction,
+    prevouts: &[TxOut],
+    descriptors: &[Option<Descriptor<Pk>>],
+) -> Result<Vec<Result<Vec<SatisfiedConstraint>, Error>>, Error>
+where
+    C: secp256k1::Verification,
+    Pk: ToPublicKey,
+{
+    if descriptors.len() != tx.input.len() {
+        return Err(Error::DescriptorsLengthMismatch(tx.input.len(), descriptors.len()));
+    }
+    let expected_spks = descriptors
+        .iter()
+        .map(|desc| desc.as_ref().map(Descriptor::script_pubkey))
+        .collect::<Vec<_>>();
+    verify_transaction_inner(secp, tx, prevouts, &expected_spks)
+}
+
+/// Verifies that a scriptSig and witness satisfy a descriptor, without a
+/// spending transaction
+///
+/// This is meant for tests and signing services, which have a witness to
+/// check but no transaction to put it in. Signatures are checked with
+/// `verify_sig`, which would typically verify them against a known sighash,
+/// or are all assumed valid if it is `None`. Since there is no transaction,
+/// timelocks are not checked and are returned as satisfied.
+///
+/// Returns the constraints satisfied by the spend. Fails with
+/// [`Error::IncorrectWScriptHash`] or similar errors if the witness is for a
+/// different descriptor.
+pub fn verify_witness<Pk: ToPublicKey>(
+    descriptor: &Descriptor<Pk>,
+    script_sig: &bitcoin::Script,
+    witness: &Witness,
+    mut verify_sig: Option<&mut dyn FnMut(&KeySigPair) -> bool>,
+) -> Result<Vec<SatisfiedConstraint>, Error> {
+    let interpreter = Interpreter::from_txdata(
+        &descriptor.script_pubkey(),
+        script_sig,
+        witness,
+        Sequence::ZERO,
+        absolute::LockTime::ZERO,
+    )?;
+    let mut iter = interpreter
+        .iter_custom(Box::new(move |sig| verify_sig.as_mut().map_or(true, |verify| verify(sig))));
+    iter.assume_timelocks = true;
+    iter.collect()
+}
+
+/// Rebuilds the canonical scriptSig and witness of a spend of a descriptor
+///
+/// The spend is re-satisfied using exactly the signatures, hash preimages and
+/// timelocks it reveals, so the result is the witness this crate produces
+/// from the same data with [`Descriptor::get_satisfaction`], falling back to
+/// [`Descriptor::get_satisfaction_mall`] if the spend only allows a malleable
+/// satisfaction. Taproot script spends are kept on the same leaf. Signatures
+/// are not checked, and any annex is dropped.
+///
+/// Since the satisfaction of a script is a deterministic function of the
+/// assets available, this maps every valid spend using the same data to the
+/// same witness, e.g. to keep the txids of a tree of pre-signed legacy
+/// transactions stable.
+pub fn canonicalize_witness<Pk: ToPublicKey>(
+    descriptor: &Descriptor<Pk>,
+    script_sig: &bitcoin::Script,
+    witness: &Witness,
+) -> Result<(bitcoin::ScriptBuf, Witness), Error> {
+    let interpreter = Interpreter::from_txdata(
+        &descriptor.script_pubkey(),
+        script_sig,
+        witness,
+        Sequence::ZERO,
+        absolute::LockTime::ZERO,
+    )?;
+    let mut iter = interpreter.iter_assume_sigs();
+    iter.assume_timelocks = true;
+    let revealed = iter.collect::<Result<RevealedData, _>>()?;
+    let satisfier = RevealedSatisfier {
+        revealed: &revealed,
+        key_spend: interpreter.is_taproot_v1_key_spend(),
+        leaf_hash: interpreter.tap_leaf().map(|leaf| leaf.leaf_hash()),
+    };
+    let (stack, script_sig) = descriptor
+        .get_satisfaction(&satisfier)
+        .or_else(|_| descriptor.get_satisfaction_mall(&satisfier))
+        .map_err(Error::Miniscript)?;
+    Ok((script_sig, Witness::from_slice(&stack)))
+}
+
+/// A satisfier with exactly the data revealed by a spend
+struct RevealedSatisfier<'a> {
+    revealed: &'a RevealedData,
+    /// Whether the spend is a Taproot key spend
+    key_spend: bool,
+    /// The leaf of a Taproot script spend
+    leaf_hash: Option<taproot::TapLeafHash>,
+}
+
+impl RevealedSatisfier<'_> {
+    fn ecdsa_sigs(
+        &self,
+    ) -> impl Iterator<Item = (bitcoin::PublicKey, bitcoin::ecdsa::Signature)> + '_ {
+        self.revealed.signatures.iter().filter_map(KeySigPair::as_ecdsa)
+    }
+
+    /// The schnorr signatures of the spent leaf, if it is `leaf_hash`
+    fn schnorr_sigs(
+        &self,
+        leaf_hash: &taproot::TapLeafHash,
+    ) -> impl Iterator<Item = (bitcoin::key::XOnlyPublicKey, taproot::Signature)> + '_ {
+        let leaf_sigs = !self.key_spend && self.leaf_hash == Some(*leaf_hash);
+        self.revealed
+            .signatures
+            .iter()
+            .filter(move |_| leaf_sigs)
+            .filter_map(KeySigPair::as_schnorr)
+    }
+
+    fn preimage(&self, hash: HashLockType) -> Option<[u8; 32]> {
+        self.revealed
+            .preimages
+            .iter()
+            .find(|(revealed, _)| *revealed == hash)
+            .map(|(_, preimage)| *preimage)
+    }
+}
+
+impl<Pk: ToPublicKey> crate::Satisfier<Pk> for RevealedSatisfier<'_> {
+    fn lookup_ecdsa_sig(&self, pk: &Pk) -> Option<bitcoin::ecdsa::Signature> {
+        let pk = pk.to_public_key();
+        self.ecdsa_sigs().find(|(key, _)| *key == pk).map(|(_, sig)| sig)
+    }
+
+    fn lookup_tap_key_spend_sig(&self) -> Option<taproot::Signature> {
+        if !self.key_spend {
+            return None;
+        }
+        self.revealed
+            .signatures
+            .iter()
+            .find_map(KeySigPair::as_schnorr)
+            .map(|(_, sig)| sig)
+    }
+
+    fn lookup_tap_leaf_script_sig(
+        &self,
+        pk: &Pk,
+        leaf_hash: &taproot::TapLeafHash,
+    ) -> Option<taproot::Signature> {
+        let pk = pk.to_x_only_pubkey();
+        self.schnorr_sigs(leaf_hash)
+            .find(|(key, _)| *key == pk)
+            .map(|(_, sig)| sig)
+    }
+
+    fn lookup_raw_pkh_pk(&self, hash: &hash160::Hash) -> Option<bitcoin::PublicKey> {
+        crate::Satisfier::<Pk>::lookup_raw_pkh_ecdsa_sig(self, hash).map(|(pk, _)| pk)
+    }
+
+    fn lookup_raw_pkh_ecdsa_sig(
+        &self,
+        hash: &hash160::Hash,
+    ) -> Option<(bitcoin::PublicKey, bitcoin::ecdsa::Signature)> {
+        self.ecdsa_sigs()
+            .find(|(pk, _)| hash160::Hash::hash(&pk.to_bytes()) == *hash)
+    }
+
+    fn lookup_raw_pkh_tap_leaf_script_sig(
+        &self,
+        (hash, leaf_hash): &(hash160::Hash, taproot::TapLeafHash),
+    ) -> Option<(bitcoin::key::XOnlyPublicKey, taproot::Signature)> {
+        self.schnorr_sigs(leaf_hash)
+            .find(|(pk, _)| hash160::Hash::hash(&pk.serialize()) == *hash)
+    }
+
+    fn lookup_sha256(&self, hash: &Pk::Sha256) -> Option<[u8; 32]> {
+        self.preimage(HashLockType::Sha256(Pk::to_sha256(hash)))
+    }
+
+    fn lookup_hash256(&self, hash: &Pk::Hash256) -> Option<[u8; 32]> {
+        self.preimage(HashLockType::Hash256(Pk::to_hash256(hash)))
+    }
+
+    fn lookup_ripemd160(&self, hash: &Pk::Ripemd160) -> Option<[u8; 32]> {
+        self.preimage(HashLockType::Ripemd160(Pk::to_ripemd160(hash)))
+    }
+
+    fn lookup_hash160(&self, hash: &Pk::Hash160) -> Option<[u8; 32]> {
+        self.preimage(HashLockType::Hash160(Pk::to_hash160(hash)))
+    }
+
+    fn check_older(&self, n: relative::LockTime) -> bool {
+        self.revealed
+            .relative_timelocks
+            .iter()
+            .any(|&revealed| n.is_implied_by(revealed))
+    }
+
+    fn check_after(&self, n: absolute::LockTime) -> bool {
+        self.revealed
+            .absolute_timelocks
+            .iter()
+            .any(|&revealed| n.is_implied_by(revealed))
+    }
+}
+
+fn verify_transaction_inner<C: secp256k1::Verification>(
+    secp: &secp256k1::Secp256k1<C>,
+    tx: &bitcoin::Transaction,
+    prevouts: &[TxOut],
+    expected_spks: &[Option<bitcoin::ScriptBuf>],
+) -> Result<Vec<Result<Vec<SatisfiedConstraint>, Error>>, Error> {
+    if prevouts.len() != tx.input.len() {
+        return Err(Error::PrevoutsLengthMismatch(tx.input.len(), prevouts.len()));
+    }
+    let all_prevouts = sighash::Prevouts::All(prevouts);
+    let mut cache = sighash::SighashCache::new(tx);
+    #[cfg(feature = "consensus-verify")]
+    let serialized_tx = bitcoin::consensus::encode::serialize(tx);
+    let results = tx
+        .input
+        .iter()
+        .zip(prevouts)
+        .zip(expected_spks)
+        .enumerate()
+        .map(|(input_idx, ((txin, prevout), expected_spk))| {
+            if let Some(ref expected_spk) = *expected_spk {
+                if *expected_spk != prevout.script_pubkey {
+                    return Err(Error::DescriptorMismatch);
+                }
+            }
+            let interpreted = Interpreter::from_txdata(
+                &prevout.script_pubkey,
+                &txin.script_sig,
+                &txin.witness,
+                txin.sequence,
+                tx.lock_time,
+            )
+            .and_then(|interpreter| {
+                interpreter
+                    .iter_with_cache(secp, &mut cache, input_idx, &all_prevouts)
+                    .collect::<Result<Vec<_>, _>>()
+            });
+            #[cfg(feature = "consensus-verify")]
+            if !prevout.script_pubkey.is_p2tr() {
+                let spk = &prevout.script_pubkey;
+                let verified = spk.verify(input_idx, prevout.value, &serialized_tx);
+                return cross_check_consensus(interpreted, verified);
+            }
+            interpreted
+        })
+        .collect();
+    Ok(results)
+}
+
+/// Compares the outcome of interpreting a spend with the outcome of verifying
+/// it with libbitcoinconsensus, failing if exactly one of them rejected it
+#[cfg(feature = "consensus-verify")]
+pub(crate) fn cross_check_consensus<T>(
+    interpreted: Result<T, Error>,
+    verified: Result<(), bitcoin::consensus::validation::BitcoinconsensusError>,
+) -> Result<T, Error> {
+    match (interpreted, verified) {
+        (Ok(_), Err(e)) => {
+            Err(Error::ConsensusDiscrepancy(ConsensusDiscrepancy::ConsensusRejected(e)))
+        }
+        (Err(e), Ok(())) => {
+            Err(Error::ConsensusDiscrepancy(ConsensusDiscrepancy::InterpreterRejected(Box::new(e))))
+        }
+        (interpreted, _) => interpreted,
+    }
+}
+
+/// A signature reused across the inputs of a transaction, as found by
+/// [`find_signature_reuse`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SignatureReuse {
+    /// The same signature appears in two inputs
+    Duplicate {
+        /// The signature, with the key it is for
+        key_sig: KeySigPair,
+        /// The indices of the inputs
+        inputs: (usize, usize),
+    },
+    /// Two different signatures share their nonce, which reveals the private
+    /// key if they are for the same key
+    NonceReuse {
+        /// The index of the input of the first signature, and the signature
+        first: (usize, KeySigPair),
+        /// The index of the input of the second signature, and the signature
+        second: (usize, KeySigPair),
+    },
+}
+
+/// Finds the signatures reused across the inputs of a signed transaction
+///
+/// This is meant for audit tooling: signatures appearing in several inputs,
+/// or sharing their nonce, are a sign of a faulty signer or of a compromised
+/// key. Nonce reuse is also reported for signatures within the same input.
+///
+/// The signatures are extracted with the interpreter, without verifying
+/// them, and inputs which fail to be interpreted are only searched up to the
+/// failure. `prevouts` must contain the outputs spent by the transaction, in
+/// the order of its inputs.
+pub fn find_signature_reuse(
+    tx: &bitcoin::Transaction,
+    prevouts: &[TxOut],
+) -> Result<Vec<SignatureReuse>, Error> {
+    if prevouts.len() != tx.input.len() {
+        return Err(Error::PrevoutsLengthMismatch(tx.input.len(), prevouts.len()));
+    }
+    let mut by_nonce: BTreeMap<[u8; 32], Vec<(usize, KeySigPair)>> = BTreeMap::new();
+    for (input_idx, (txin, prevout)) in tx.input.iter().zip(prevouts).enumerate() {
+        let interpreter = match Interpreter::from_txdata(
+            &prevout.script_pubkey,
+            &txin.script_sig,
+            &txin.witness,
+            txin.sequence,
+            tx.lock_time,
+        ) {
+            Ok(interpreter) => interpreter,
+            Err(_) => continue,
+        };
+        let key_sigs = interpreter
+            .iter_assume_sigs()
+            .map_while(Result::ok)
+            .filter_map(|constraint| match constraint {
+                SatisfiedConstraint::PublicKey { key_sig }
+                | SatisfiedConstraint::PublicKeyHash { key_sig, .. } => Some(key_sig),
+                _ => None,
+            });
+        for key_sig in key_sigs {
+            by_nonce
+                .entry(key_sig.nonce())
+                .or_default()
+                .push((input_idx, key_sig));
+        }
+    }
+
+    let mut reuses = vec![];
+    for sigs in by_nonce.values() {
+        for (i, &first) in sigs.iter().enumerate() {
+            for &second in &sigs[i + 1..] {
+                if first.1 != second.1 {
+                    reuses.push(SignatureReuse::NonceReuse { first, second });
+                } else if first.0 != second.0 {
+                    reuses.push(SignatureReuse::Duplicate {
+                        key_sig: first.1,
+                        inputs: (first.0, second.0),
+                    });
+                }
+            }
+        }
+    }
+    Ok(reuses)
+}
+
+/// A value obtained by the interpreter without verifying signatures
+///
+/// It is only valid if the signatures it contains are, which must be checked
+/// separately, e.g. with [`Interpreter::verify_sig`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Unverified<T>(T);
+
+impl<T> Unverified<T> {
+    /// Accessor for the value, whose signatures have not been verified
+    pub fn as_unverified(&self) -> &T { &self.0 }
+
+    /// Unwraps the value, assuming that its signatures are valid
+    pub fn assume_verified(self) -> T { self.0 }
 }
 
 /// Type of HashLock used for SatisfiedConstraint structure
@@ -493,6 +1465,49 @@ pub enum SatisfiedConstraint {
     },
 }
 
+/// The data revealed by a spend, gathered from its satisfied constraints
+///
+/// This can be collected from an [`Iter`] in a single pass, e.g. by software
+/// watching the chain for the preimages revealed by a counterparty:
+/// `interpreter.iter(..).collect::<Result<RevealedData, _>>()`.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct RevealedData {
+    /// Signatures, with the key they are for
+    pub signatures: Vec<KeySigPair>,
+    /// Hash preimages, with the hash they are for
+    pub preimages: Vec<(HashLockType, [u8; 32])>,
+    /// Absolute timelocks the spend satisfies
+    pub absolute_timelocks: Vec<absolute::LockTime>,
+    /// Relative timelocks the spend satisfies
+    pub relative_timelocks: Vec<relative::LockTime>,
+}
+
+impl Extend<SatisfiedConstraint> for RevealedData {
+    fn extend<I: IntoIterator<Item = SatisfiedConstraint>>(&mut self, iter: I) {
+        for constraint in iter {
+            match constraint {
+                SatisfiedConstraint::PublicKey { key_sig }
+                | SatisfiedConstraint::PublicKeyHash { key_sig, .. } => {
+                    self.signatures.push(key_sig)
+                }
+                SatisfiedConstraint::HashLock { hash, preimage } => {
+                    self.preimages.push((hash, preimage))
+                }
+                SatisfiedConstraint::AbsoluteTimelock { n } => self.absolute_timelocks.push(n),
+                SatisfiedConstraint::RelativeTimelock { n } => self.relative_timelocks.push(n),
+            }
+        }
+    }
+}
+
+impl FromIterator<SatisfiedConstraint> for RevealedData {
+    fn from_iter<I: IntoIterator<Item = SatisfiedConstraint>>(iter: I) -> Self {
+        let mut data = RevealedData::default();
+        data.extend(iter);
+        data
+    }
+}
+
 ///This is used by the interpreter to know which evaluation state a AstemElem is.
 ///This is required because whenever a same node(for eg. OrB) appears on the stack, we don't
 ///know if the left child has been evaluated or not. And based on the result on
@@ -508,6 +1523,9 @@ struct NodeEvaluationState<'intp> {
     n_satisfied: usize,
 }
 
+/// Maximum number of stack elements included in the context of an error
+const MAX_ERROR_STACK_ELEMENTS: usize = 5;
+
 /// Iterator over all the constraints satisfied by a completed scriptPubKey
 /// and witness stack
 ///
@@ -518,16 +1536,222 @@ struct NodeEvaluationState<'intp> {
 /// been used.
 ///
 /// In case the script is actually dissatisfied, this may return several values
-/// before ultimately returning an error.
+/// before ultimately returning an error. This error is an [`Error::Evaluation`],
+/// recording where in the script evaluation failed.
 pub struct Iter<'intp, 'txin: 'intp> {
     verify_sig: Box<dyn FnMut(&KeySigPair) -> bool + 'intp>,
     public_key: Option<&'intp BitcoinKey>,
     state: Vec<NodeEvaluationState<'intp>>,
+    /// The script being evaluated, if any
+    script: Option<&'intp Miniscript<BitcoinKey, NoChecks>>,
     stack: Stack<'txin>,
+    #[allow(clippy::type_complexity)]
+    trace: Option<Box<dyn FnMut(&TraceStep<'_, 'txin>) + 'intp>>,
+    /// The stack the script is executed against, as it was created
+    input: Vec<Element<'txin>>,
+    /// Number of elements of `input` not yet attributed to a fragment
+    n_input: usize,
+    /// The fragment being evaluated, if any
+    current_node: Option<&'intp Miniscript<BitcoinKey, NoChecks>>,
+    /// The elements of `input` attributed to a fragment, with the fragment
+    /// and their role
+    #[allow(clippy::type_complexity)]
+    explained: Vec<(usize, Option<&'intp Miniscript<BitcoinKey, NoChecks>>, WitnessRole)>,
+    annex: Option<&'txin [u8]>,
     sequence: Sequence,
     lock_time: absolute::LockTime,
     has_errored: bool,
+    /// Whether this is a spend of a leaf with a future version, which is
+    /// unconditionally valid
+    future_leaf: bool,
+    flags: VerifyFlags,
+    chain: Option<ChainContext>,
+    /// Whether timelocks are treated as satisfied, for spends verified
+    /// without a transaction
+    assume_timelocks: bool,
     sig_type: SigType,
+    /// The resources used so far, except for the witness size
+    report: ExecutionReport,
+}
+
+impl<'intp, 'txin> Iter<'intp, 'txin> {
+    /// The BIP-341 annex of the Taproot spend being iterated over, if any
+    ///
+    /// See [`Interpreter::annex`].
+    pub fn annex(&self) -> Option<&'txin [u8]> { self.annex }
+
+    /// Sets a hook called at every step of the evaluation of the script
+    ///
+    /// The hook is called before each fragment is evaluated and, for fragments
+    /// which combine the results of their children, again after each of them
+    /// has been evaluated. This allows producing an execution trace of the
+    /// spend, e.g. to debug why it fails.
+    /// It is not called for spends of bare public keys, which have no script.
+    pub fn with_trace(mut self, trace: Box<dyn FnMut(&TraceStep<'_, 'txin>) + 'intp>) -> Self {
+        self.trace = Some(trace);
+        self
+    }
+
+    /// The elements of the stack the script is executed against, annotated
+    /// with the fragments which consumed them, from bottom to top
+    ///
+    /// Only covers the elements consumed so far, so this should be called
+    /// once the iterator has been exhausted. For a successful spend, every
+    /// element of the stack has then been consumed.
+    pub fn explain_witness(&self) -> Vec<ExplainedElement<'txin>> {
+        let mut explained = self
+            .explained
+            .iter()
+            .map(|&(index, node, role)| ExplainedElement {
+                index,
+                element: self.input[index],
+                fragment: node.map(ToString::to_string),
+                role,
+            })
+            .collect::<Vec<_>>();
+        explained.sort_by_key(|elem| elem.index);
+        explained
+    }
+
+    /// The resources used by the execution of the script
+    ///
+    /// Only covers the execution so far, so this should be called once the
+    /// iterator has been exhausted.
+    pub fn execution_report(&self) -> ExecutionReport {
+        let witness_size = self.input[self.stack.n_input()..]
+            .iter()
+            .map(|elem| {
+                let len = match *elem {
+                    stack::Element::Push(v) => v.len(),
+                    stack::Element::Satisfied => 1,
+                    stack::Element::Dissatisfied => 0,
+                };
+                varint_len(len) + len
+            })
+            .sum();
+        ExecutionReport { witness_size, ..self.report }
+    }
+}
+
+/// The resources used by the execution of a script by the interpreter, as
+/// returned by [`Iter::execution_report`]
+///
+/// For a successful spend of a Miniscript, each of these is bounded by the
+/// worst case predicted by the [`ExtData`](crate::miniscript::types::ExtData)
+/// of the Miniscript, as documented for each field.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ExecutionReport {
+    /// Number of non-push opcodes counted against the limit on opcodes per
+    /// script, which includes the keys of executed `OP_CHECKMULTISIG`s
+    ///
+    /// This is bounded by `ops.count + ops.sat`. Spends of public keys, which
+    /// have no Miniscript, count no opcodes.
+    pub op_count: usize,
+    /// Maximum number of elements on the stack during the execution
+    ///
+    /// This is bounded by `stack_elem_count_sat + exec_stack_elem_count_sat`.
+    /// The interpreter replaces the elements consumed by each fragment by a
+    /// single result, so this can be lower than the depth reached by Script.
+    pub max_stack_size: usize,
+    /// Serialized size of the stack elements consumed, including their
+    /// length prefixes, in bytes
+    ///
+    /// This is bounded by the first element of `max_sat_size`.
+    pub witness_size: usize,
+    /// Number of signature checks with a non-empty signature, each of which
+    /// consumes part of the Tapscript sigops budget
+    ///
+    /// This is bounded by the `sigops` returned by
+    /// [`Tap::sigops_budget`](crate::miniscript::Tap::sigops_budget).
+    pub sigops: usize,
+}
+
+/// The part an element of the witness plays in the satisfaction of a script
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum WitnessRole {
+    /// A signature
+    Signature,
+    /// A public key, revealed for a `pk_h` fragment
+    PublicKey,
+    /// The preimage of a hash
+    Preimage,
+    /// The extra element consumed by `OP_CHECKMULTISIG`
+    MultisigDummy,
+    /// The choice of a branch, by an `or_i` fragment or a `d:` wrapper
+    BranchSelector,
+    /// The dissatisfaction of a fragment
+    Dissatisfaction,
+}
+
+/// An element of the stack the script is executed against, annotated with
+/// the fragment which consumed it, as returned by [`Iter::explain_witness`]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ExplainedElement<'txin> {
+    /// The position of the element in the stack, from the bottom
+    ///
+    /// This is its position in the witness (or the scriptSig for legacy
+    /// spends), not counting the elements which are not part of the stack
+    /// the script is executed against, such as the witness script.
+    pub index: usize,
+    /// The element
+    pub element: Element<'txin>,
+    /// The fragment which consumed the element, or `None` for spends of bare
+    /// public keys, which have no script
+    ///
+    /// Keys are displayed as in [`Interpreter::inferred_descriptor_string`].
+    pub fragment: Option<String>,
+    /// The part the element plays in the satisfaction of the script
+    pub role: WitnessRole,
+}
+
+/// A step of the evaluation of a script by the interpreter, as passed to the
+/// hook set with [`Iter::with_trace`]
+pub struct TraceStep<'a, 'txin> {
+    node: &'a Miniscript<BitcoinKey, NoChecks>,
+    n_evaluated: usize,
+    stack: &'a Stack<'txin>,
+}
+
+impl<'a, 'txin> TraceStep<'a, 'txin> {
+    /// The fragment being evaluated
+    ///
+    /// Keys are displayed as in [`Interpreter::inferred_descriptor_string`].
+    pub fn fragment(&self) -> impl fmt::Display + 'a { self.node }
+
+    /// The number of children of the fragment which have been evaluated
+    ///
+    /// This is zero when the evaluation of the fragment starts.
+    pub fn n_evaluated(&self) -> usize { self.n_evaluated }
+
+    /// The stack, from bottom to top
+    ///
+    /// The interpreter executes the script against a single stack, made of the
+    /// remaining witness elements on which the results of evaluated fragments
+    /// are pushed.
+    pub fn stack(&self) -> &'a [Element<'txin>] { self.stack.as_slice() }
+}
+
+/// Iterator over the constraints satisfied by a spend, treating all
+/// well-formed signatures as valid
+///
+/// See [`Interpreter::iter_unverified`].
+pub struct UnverifiedIter<'intp, 'txin: 'intp>(Iter<'intp, 'txin>);
+
+impl<'intp, 'txin: 'intp> UnverifiedIter<'intp, 'txin> {
+    /// See [`Iter::explain_witness`].
+    pub fn explain_witness(&self) -> Vec<ExplainedElement<'txin>> { self.0.explain_witness() }
+
+    /// See [`Iter::execution_report`].
+    pub fn execution_report(&self) -> ExecutionReport { self.0.execution_report() }
+}
+
+impl<'intp, 'txin: 'intp> Iterator for UnverifiedIter<'intp, 'txin>
+where
+    NoChecks: ScriptContext,
+{
+    type Item = Result<Unverified<SatisfiedConstraint>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> { self.0.next().map(|res| res.map(Unverified)) }
 }
 
 ///Iterator for Iter
@@ -542,11 +1766,22 @@ where
             // Stop yielding values after the first error
             None
         } else {
-            let res = self.iter_next();
-            if let Some(Err(_)) = res {
-                self.has_errored = true;
+            match self.iter_next() {
+                Some(Err(error)) => {
+                    self.has_errored = true;
+                    Some(Err(self.with_context(error)))
+                }
+                res => {
+                    if let Some(Ok(
+                        SatisfiedConstraint::PublicKey { .. }
+                        | SatisfiedConstraint::PublicKeyHash { .. },
+                    )) = res
+                    {
+                        self.report.sigops += 1;
+                    }
+                    res
+                }
             }
-            res
         }
     }
 }
@@ -566,9 +1801,133 @@ where
             .push(NodeEvaluationState { node, n_evaluated, n_satisfied })
     }
 
+    /// Helper function to attach the state of the evaluation to an error
+    fn with_context(&self, error: Error) -> Error {
+        let location = match (self.script, self.current_node) {
+            (Some(script), Some(node)) => locate_fragment(script, node, &mut 0, 0),
+            _ => None,
+        };
+        let stack_top = self
+            .stack
+            .as_slice()
+            .iter()
+            .rev()
+            .take(MAX_ERROR_STACK_ELEMENTS)
+            .map(|elem| match *elem {
+                stack::Element::Push(v) => v.to_vec(),
+                stack::Element::Satisfied => vec![1],
+                stack::Element::Dissatisfied => vec![],
+            })
+            .collect();
+        Error::Evaluation(Box::new(EvaluationError {
+            error,
+            fragment: self.current_node.map(|node| node.to_string()),
+            fragment_index: location.map(|(index, _)| index),
+            script_position: location.map(|(_, position)| position),
+            stack_top,
+        }))
+    }
+
+    /// Helper function to pop the argument of an `OP_IF` from the stack
+    ///
+    /// Unless `OP_IF` arguments are required to be minimal, as they are in
+    /// Tapscript, any element is interpreted as Script would cast it to a
+    /// boolean.
+    fn pop_if_argument(&mut self) -> Option<stack::Element<'txin>> {
+        match self.stack.pop() {
+            Some(stack::Element::Push(v))
+                if !self.flags.minimal_if && self.sig_type == SigType::Ecdsa =>
+            {
+                if cast_to_bool(v) {
+                    Some(stack::Element::Satisfied)
+                } else {
+                    Some(stack::Element::Dissatisfied)
+                }
+            }
+            elem => elem,
+        }
+    }
+
+    /// Helper function to turn the failed verification of a non-empty ECDSA
+    /// signature into a dissatisfaction when NULLFAIL is not enforced
+    fn null_fail(
+        &mut self,
+        res: Option<Result<SatisfiedConstraint, Error>>,
+    ) -> Option<Result<SatisfiedConstraint, Error>> {
+        match res {
+            Some(Err(Error::InvalidEcdsaSignature(_))) if !self.flags.null_fail => {
+                self.report.sigops += 1;
+                self.stack.push(stack::Element::Dissatisfied);
+                None
+            }
+            res => res,
+        }
+    }
+
+    /// Whether the element is valid as the extra element consumed by
+    /// `OP_CHECKMULTISIG`
+    fn is_multi_dummy(&self, elem: &stack::Element<'txin>) -> bool {
+        *elem == stack::Element::Dissatisfied || !self.flags.null_dummy
+    }
+
+    /// Helper function to attribute the elements of the stack input consumed
+    /// since the last call to the fragment being evaluated
+    fn explain_consumed(&mut self) {
+        let n_input = self.stack.n_input();
+        for index in (n_input..self.n_input).rev() {
+            let element = self.input[index];
+            let role = match (self.current_node.map(|ms| &ms.node), element) {
+                // The public key is popped first, then the signature
+                (Some(Terminal::PkH(_) | Terminal::RawPkH(_)), _) if index + 1 == self.n_input => {
+                    WitnessRole::PublicKey
+                }
+                (
+                    Some(
+                        Terminal::Sha256(_)
+                        | Terminal::Hash256(_)
+                        | Terminal::Ripemd160(_)
+                        | Terminal::Hash160(_),
+                    ),
+                    _,
+                ) => {
+                    if self.stack.last() == Some(&stack::Element::Satisfied) {
+                        WitnessRole::Preimage
+                    } else {
+                        WitnessRole::Dissatisfaction
+                    }
+                }
+                (Some(Terminal::OrI(..) | Terminal::DupIf(_)), _) => WitnessRole::BranchSelector,
+                // The extra element is the last one consumed
+                (Some(Terminal::Multi(_)), stack::Element::Dissatisfied) if index == n_input => {
+                    WitnessRole::MultisigDummy
+                }
+                (_, stack::Element::Push(_)) => WitnessRole::Signature,
+                _ => WitnessRole::Dissatisfaction,
+            };
+            self.explained.push((index, self.current_node, role));
+        }
+        self.n_input = n_input;
+    }
+
+    /// Helper function to record the size of the stack in the report
+    fn update_max_stack_size(&mut self) {
+        let size = self.stack.as_slice().len();
+        self.report.max_stack_size = cmp::max(self.report.max_stack_size, size);
+    }
+
     /// Helper function to step the iterator
     fn iter_next(&mut self) -> Option<Result<SatisfiedConstraint, Error>> {
         while let Some(node_state) = self.state.pop() {
+            self.update_max_stack_size();
+            self.explain_consumed();
+            self.current_node = Some(node_state.node);
+            if let Some(ref mut trace) = self.trace {
+                trace(&TraceStep {
+                    node: node_state.node,
+                    n_evaluated: node_state.n_evaluated,
+                    stack: &self.stack,
+                });
+            }
             //non-empty stack
             match node_state.node.node {
                 Terminal::True => {
@@ -584,7 +1943,8 @@ where
                 Terminal::PkK(ref pk) => {
                     debug_assert_eq!(node_state.n_evaluated, 0);
                     debug_assert_eq!(node_state.n_satisfied, 0);
-                    let res = self.stack.evaluate_pk(&mut self.verify_sig, *pk);
+                    let res = self.stack.evaluate_pk(&mut self.verify_sig, *pk, self.flags);
+                    let res = self.null_fail(res);
                     if res.is_some() {
                         return res;
                     }
@@ -596,7 +1956,9 @@ where
                         &mut self.verify_sig,
                         pk.to_pubkeyhash(self.sig_type),
                         self.sig_type,
+                        self.flags,
                     );
+                    let res = self.null_fail(res);
                     if res.is_some() {
                         return res;
                     }
@@ -604,9 +1966,13 @@ where
                 Terminal::RawPkH(ref pkh) => {
                     debug_assert_eq!(node_state.n_evaluated, 0);
                     debug_assert_eq!(node_state.n_satisfied, 0);
-                    let res = self
-                        .stack
-                        .evaluate_pkh(&mut self.verify_sig, *pkh, self.sig_type);
+                    let res = self.stack.evaluate_pkh(
+                        &mut self.verify_sig,
+                        *pkh,
+                        self.sig_type,
+                        self.flags,
+                    );
+                    let res = self.null_fail(res);
                     if res.is_some() {
                         return res;
                     }
@@ -614,9 +1980,18 @@ where
                 Terminal::After(ref n) => {
                     debug_assert_eq!(node_state.n_evaluated, 0);
                     debug_assert_eq!(node_state.n_satisfied, 0);
-                    let res = self
-                        .stack
-                        .evaluate_after(&absolute::LockTime::from(*n), self.lock_time);
+                    let n = absolute::LockTime::from(*n);
+                    let res = if self.assume_timelocks {
+                        self.stack.push(stack::Element::Satisfied);
+                        Some(Ok(SatisfiedConstraint::AbsoluteTimelock { n }))
+                    } else {
+                        self.stack.evaluate_after(&n, self.lock_time)
+                    };
+                    if let (Some(Ok(_)), Some(chain)) = (&res, self.chain) {
+                        if !chain.is_absolute_lock_time_met(n) {
+                            return Some(Err(Error::AbsoluteLockTimeNotMet(n)));
+                        }
+                    }
                     if res.is_some() {
                         return res;
                     }
@@ -624,7 +1999,18 @@ where
                 Terminal::Older(ref n) => {
                     debug_assert_eq!(node_state.n_evaluated, 0);
                     debug_assert_eq!(node_state.n_satisfied, 0);
-                    let res = self.stack.evaluate_older(&(*n).into(), self.sequence);
+                    let n = relative::LockTime::from(*n);
+                    let res = if self.assume_timelocks {
+                        self.stack.push(stack::Element::Satisfied);
+                        Some(Ok(SatisfiedConstraint::RelativeTimelock { n }))
+                    } else {
+                        self.stack.evaluate_older(&n, self.sequence)
+                    };
+                    if let (Some(Ok(_)), Some(chain)) = (&res, self.chain) {
+                        if !chain.is_relative_lock_time_met(n) {
+                            return Some(Err(Error::RelativeLockTimeNotMet(n)));
+                        }
+                    }
                     if res.is_some() {
                         return res;
                     }
@@ -661,24 +2047,26 @@ where
                         return res;
                     }
                 }
-                Terminal::Alt(ref sub) | Terminal::Swap(ref sub) | Terminal::Check(ref sub) => {
-                    debug_assert_eq!(node_state.n_evaluated, 0);
-                    debug_assert_eq!(node_state.n_satisfied, 0);
-                    self.push_evaluation_state(sub, 0, 0);
-                }
-                Terminal::DupIf(ref sub) if node_state.n_evaluated == 0 => match self.stack.pop() {
-                    Some(stack::Element::Dissatisfied) => {
-                        self.stack.push(stack::Element::Dissatisfied);
-                    }
-                    Some(stack::Element::Satisfied) => {
-                        self.push_evaluation_state(node_state.node, 1, 1);
-                        self.push_evaluation_state(sub, 0, 0);
-                    }
-                    Some(stack::Element::Push(_v)) => {
-                        return Some(Err(Error::UnexpectedStackElementPush))
-                    }
-                    None => return Some(Err(Error::UnexpectedStackEnd)),
-                },
+                Terminal::Alt(ref sub) | Terminal::Swap(ref sub) | Terminal::Check(ref sub) => {
+                    debug_assert_eq!(node_state.n_evaluated, 0);
+                    debug_assert_eq!(node_state.n_satisfied, 0);
+                    self.push_evaluation_state(sub, 0, 0);
+                }
+                Terminal::DupIf(ref sub) if node_state.n_evaluated == 0 => {
+                    match self.pop_if_argument() {
+                        Some(stack::Element::Dissatisfied) => {
+                            self.stack.push(stack::Element::Dissatisfied);
+                        }
+                        Some(stack::Element::Satisfied) => {
+                            self.push_evaluation_state(node_state.node, 1, 1);
+                            self.push_evaluation_state(sub, 0, 0);
+                        }
+                        Some(stack::Element::Push(_v)) => {
+                            return Some(Err(Error::UnexpectedStackElementPush))
+                        }
+                        None => return Some(Err(Error::UnexpectedStackEnd)),
+                    }
+                }
                 Terminal::DupIf(ref _sub) if node_state.n_evaluated == 1 => {
                     self.stack.push(stack::Element::Satisfied);
                 }
@@ -708,7 +2096,7 @@ where
                     debug_assert_eq!(node_state.n_evaluated, 0);
                     debug_assert_eq!(node_state.n_satisfied, 0);
                     match self.stack.last() {
-                        Some(&stack::Element::Dissatisfied) => (),
+                        Some(&stack::Element::Dissatisfied) => self.stack.consume_last(),
                         Some(_) => self.push_evaluation_state(sub, 0, 0),
                         None => return Some(Err(Error::UnexpectedStackEnd)),
                     }
@@ -798,7 +2186,7 @@ where
                     }
                 }
                 Terminal::AndOr(_, ref left, ref right) | Terminal::OrI(ref left, ref right) => {
-                    match self.stack.pop() {
+                    match self.pop_if_argument() {
                         Some(stack::Element::Satisfied) => self.push_evaluation_state(left, 0, 0),
                         Some(stack::Element::Dissatisfied) => {
                             self.push_evaluation_state(right, 0, 0)
@@ -880,6 +2268,7 @@ where
                         match self.stack.evaluate_pk(
                             &mut self.verify_sig,
                             thresh.data()[node_state.n_evaluated],
+                            self.flags,
                         ) {
                             Some(Ok(x)) => {
                                 self.push_evaluation_state(
@@ -908,6 +2297,7 @@ where
                     }
                 }
                 Terminal::Multi(ref thresh) if node_state.n_evaluated == 0 => {
+                    self.report.op_count += thresh.n();
                     let len = self.stack.len();
                     if len < thresh.k() + 1 {
                         return Some(Err(Error::InsufficientSignaturesMultiSig));
@@ -918,12 +2308,14 @@ where
                             Some(&stack::Element::Dissatisfied) => {
                                 //Remove the extra zero from multi-sig check
                                 let sigs = self.stack.split_off(len - (thresh.k() + 1));
-                                let nonsat = sigs
+                                let nonsat = sigs[1..]
                                     .iter()
-                                    .map(|sig| *sig == stack::Element::Dissatisfied)
-                                    .filter(|empty| *empty)
+                                    .filter(|sig| **sig == stack::Element::Dissatisfied)
                                     .count();
-                                if nonsat == thresh.k() + 1 {
+                                // Without NULLFAIL, the other signatures may be
+                                // anything as the empty one never verifies
+                                let sigs_ok = nonsat == thresh.k() || !self.flags.null_fail;
+                                if sigs_ok && self.is_multi_dummy(&sigs[0]) {
                                     self.stack.push(stack::Element::Dissatisfied);
                                 } else {
                                     return Some(Err(Error::MissingExtraZeroMultiSig));
@@ -934,6 +2326,7 @@ where
                                 match self.stack.evaluate_multi(
                                     &mut self.verify_sig,
                                     &thresh.data()[thresh.n() - 1],
+                                    self.flags,
                                 ) {
                                     Some(Ok(x)) => {
                                         self.push_evaluation_state(
@@ -957,17 +2350,33 @@ where
                 Terminal::Multi(ref thresh) => {
                     if node_state.n_satisfied == thresh.k() {
                         //multi-sig bug: Pop extra 0
-                        if let Some(stack::Element::Dissatisfied) = self.stack.pop() {
-                            self.stack.push(stack::Element::Satisfied);
-                        } else {
-                            return Some(Err(Error::MissingExtraZeroMultiSig));
+                        match self.stack.pop() {
+                            Some(ref dummy) if self.is_multi_dummy(dummy) => {
+                                self.stack.push(stack::Element::Satisfied)
+                            }
+                            _ => return Some(Err(Error::MissingExtraZeroMultiSig)),
                         }
                     } else if node_state.n_evaluated == thresh.n() {
-                        return Some(Err(Error::MultiSigEvaluationError));
+                        if self.flags.null_fail {
+                            return Some(Err(Error::MultiSigEvaluationError));
+                        }
+                        // Without NULLFAIL, signatures failing verification
+                        // make the check fail instead of the script
+                        let len = self.stack.len();
+                        let n_left = thresh.k() - node_state.n_satisfied + 1;
+                        if len < n_left {
+                            return Some(Err(Error::UnexpectedStackEnd));
+                        }
+                        let sigs = self.stack.split_off(len - n_left);
+                        if !self.is_multi_dummy(&sigs[0]) {
+                            return Some(Err(Error::MissingExtraZeroMultiSig));
+                        }
+                        self.stack.push(stack::Element::Dissatisfied);
                     } else {
                         match self.stack.evaluate_multi(
                             &mut self.verify_sig,
                             &thresh.data()[thresh.n() - node_state.n_evaluated - 1],
+                            self.flags,
                         ) {
                             Some(Ok(x)) => {
                                 self.push_evaluation_state(
@@ -992,11 +2401,16 @@ where
             };
         }
 
+        self.update_max_stack_size();
+        self.explain_consumed();
+        self.current_node = None;
         //state empty implies that either the execution has terminated or we have a
         //Pk based descriptor
         if let Some(pk) = self.public_key {
-            if let Some(stack::Element::Push(sig)) = self.stack.pop() {
-                if let Ok(key_sig) = verify_sersig(&mut self.verify_sig, pk, sig) {
+            let sig = self.stack.pop();
+            self.explain_consumed();
+            if let Some(stack::Element::Push(sig)) = sig {
+                if let Ok(key_sig) = verify_sersig(&mut self.verify_sig, pk, sig, self.flags) {
                     //Signature check successful, set public_key to None to
                     //terminate the next() function in the subsequent call
                     self.public_key = None;
@@ -1010,8 +2424,11 @@ where
             }
         } else {
             //All the script has been executed.
-            //Check that the stack must contain exactly 1 satisfied element
-            if self.stack.pop() == Some(stack::Element::Satisfied) && self.stack.is_empty() {
+            //Check that the stack must contain exactly 1 satisfied element,
+            //unless the leaf has a future version and was not executed
+            if self.future_leaf
+                || (self.stack.pop() == Some(stack::Element::Satisfied) && self.stack.is_empty())
+            {
                 None
             } else {
                 Some(Err(Error::ScriptSatisfactionError))
@@ -1020,15 +2437,124 @@ where
     }
 }
 
+/// Helper function to find a fragment of a script, returning its index in a
+/// pre-order traversal of the script and the offset of its encoding
+fn locate_fragment(
+    ms: &Miniscript<BitcoinKey, NoChecks>,
+    target: &Miniscript<BitcoinKey, NoChecks>,
+    index: &mut usize,
+    position: usize,
+) -> Option<(usize, usize)> {
+    if core::ptr::eq(ms, target) {
+        return Some((*index, position));
+    }
+    *index += 1;
+    // Offsets of the encodings of the children in the encoding of the fragment
+    let children: Vec<(&Miniscript<BitcoinKey, NoChecks>, usize)> = match ms.node {
+        Terminal::Alt(ref x) | Terminal::Swap(ref x) => vec![(x, 1)],
+        Terminal::Check(ref x) | Terminal::Verify(ref x) | Terminal::ZeroNotEqual(ref x) => {
+            vec![(x, 0)]
+        }
+        Terminal::DupIf(ref x) => vec![(x, 2)],
+        Terminal::NonZero(ref x) => vec![(x, 3)],
+        Terminal::AndV(ref x, ref y)
+        | Terminal::AndB(ref x, ref y)
+        | Terminal::OrB(ref x, ref y) => vec![(x, 0), (y, encoded_len(x))],
+        Terminal::AndOr(ref x, ref y, ref z) => {
+            // X NOTIF Z ELSE Y ENDIF
+            vec![(x, 0), (y, encoded_len(x) + encoded_len(z) + 2), (z, encoded_len(x) + 1)]
+        }
+        Terminal::OrD(ref x, ref z) => vec![(x, 0), (z, encoded_len(x) + 2)],
+        Terminal::OrC(ref x, ref z) => vec![(x, 0), (z, encoded_len(x) + 1)],
+        Terminal::OrI(ref x, ref z) => vec![(x, 1), (z, encoded_len(x) + 2)],
+        Terminal::Thresh(ref thresh) => {
+            // X1 X2 ADD X3 ADD ... Xn ADD k EQUAL
+            let mut offset = 0;
+            let mut children = vec![];
+            for (i, sub) in thresh.iter().enumerate() {
+                children.push((&**sub, offset));
+                offset += encoded_len(sub) + usize::from(i > 0);
+            }
+            children
+        }
+        _ => vec![],
+    };
+    children
+        .into_iter()
+        .find_map(|(child, offset)| locate_fragment(child, target, index, position + offset))
+}
+
+/// Helper function to compute the length of the encoding of a fragment
+///
+/// Unlike [`Miniscript::script_size`], this supports `NoChecks` fragments,
+/// whose keys may be either full or x-only.
+fn encoded_len(ms: &Miniscript<BitcoinKey, NoChecks>) -> usize {
+    let pk_len = |pk: &BitcoinKey| match *pk {
+        BitcoinKey::Fullkey(pk) if !pk.compressed => 66,
+        BitcoinKey::Fullkey(_) => 34,
+        BitcoinKey::XOnlyPublicKey(_) => 33,
+    };
+    ms.pre_order_iter()
+        .map(|ms| match ms.node {
+            Terminal::AndV(..) => 0,
+            Terminal::True
+            | Terminal::False
+            | Terminal::Swap(..)
+            | Terminal::Check(..)
+            | Terminal::ZeroNotEqual(..)
+            | Terminal::AndB(..)
+            | Terminal::OrB(..) => 1,
+            Terminal::Alt(..) | Terminal::OrC(..) => 2,
+            Terminal::DupIf(..) | Terminal::AndOr(..) | Terminal::OrD(..) | Terminal::OrI(..) => 3,
+            Terminal::NonZero(..) => 4,
+            Terminal::PkH(..) | Terminal::RawPkH(..) => 24,
+            Terminal::Ripemd160(..) | Terminal::Hash160(..) => 21 + 6,
+            Terminal::Sha256(..) | Terminal::Hash256(..) => 33 + 6,
+            Terminal::PkK(ref pk) => pk_len(pk),
+            Terminal::After(n) => script_num_size(n.to_consensus_u32() as usize) + 1,
+            Terminal::Older(n) => script_num_size(n.to_consensus_u32() as usize) + 1,
+            Terminal::Verify(ref sub) => usize::from(!sub.ext.has_free_verify),
+            Terminal::Thresh(ref thresh) => script_num_size(thresh.k()) + 1 + thresh.n() - 1,
+            Terminal::Multi(ref thresh) => {
+                script_num_size(thresh.k())
+                    + 1
+                    + script_num_size(thresh.n())
+                    + thresh.iter().map(pk_len).sum::<usize>()
+            }
+            Terminal::MultiA(ref thresh) => {
+                script_num_size(thresh.k())
+                    + 1
+                    + thresh.iter().map(pk_len).sum::<usize>()
+                    + thresh.n()
+            }
+            Terminal::Ext(..) => ms.ext.pk_cost,
+        })
+        .sum()
+}
+
+/// Helper function to cast a stack element to a boolean the way Script does:
+/// any element other than a (possibly negative) zero is true
+fn cast_to_bool(v: &[u8]) -> bool {
+    match v.split_last() {
+        Some((&last, rest)) => rest.iter().any(|&b| b != 0) || (last != 0 && last != 0x80),
+        None => false,
+    }
+}
+
 /// Helper function to verify serialized signature
 fn verify_sersig<'txin>(
     verify_sig: &mut Box<dyn FnMut(&KeySigPair) -> bool + 'txin>,
     pk: &BitcoinKey,
     sigser: &[u8],
+    flags: VerifyFlags,
 ) -> Result<KeySigPair, Error> {
     match pk {
         BitcoinKey::Fullkey(pk) => {
-            let ecdsa_sig = bitcoin::ecdsa::Signature::from_slice(sigser)?;
+            let ecdsa_sig = if flags.der_sig {
+                bitcoin::ecdsa::Signature::from_slice(sigser)?
+            } else {
+                ecdsa_sig_from_slice_lax(sigser)?
+            };
             let key_sig_pair = KeySigPair::Ecdsa(*pk, ecdsa_sig);
             if verify_sig(&key_sig_pair) {
                 Ok(key_sig_pair)
@@ -1048,6 +2574,17 @@ fn verify_sersig<'txin>(
     }
 }
 
+/// Parses an ECDSA signature as Bitcoin Core did before BIP 66, normalizing
+/// its S value so that it can be verified by libsecp256k1
+fn ecdsa_sig_from_slice_lax(sl: &[u8]) -> Result<bitcoin::ecdsa::Signature, bitcoin::ecdsa::Error> {
+    let (sighash_type, sig) = sl.split_last().ok_or(bitcoin::ecdsa::Error::EmptySignature)?;
+    let sighash_type = sighash::EcdsaSighashType::from_standard(*sighash_type as u32)?;
+    let mut signature = secp256k1::ecdsa::Signature::from_der_lax(sig)
+        .map_err(bitcoin::ecdsa::Error::Secp256k1)?;
+    signature.normalize_s();
+    Ok(bitcoin::ecdsa::Signature { signature, sighash_type })
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -1056,6 +2593,7 @@ mod tests {
     use super::inner::ToNoChecks;
     use super::*;
     use crate::miniscript::analyzable::ExtParams;
+    use crate::miniscript::decode::UnspendableScript;
 
     #[allow(clippy::type_complexity)]
     fn setup_keys_sigs(
@@ -1135,13 +2673,25 @@ mod tests {
         ) -> Iter<'elem, 'txin> {
             Iter {
                 verify_sig: verify_fn,
+                input: stack.as_slice().to_vec(),
+                n_input: stack.n_input(),
+                current_node: None,
+                explained: vec![],
                 stack,
+                trace: None,
                 public_key: None,
                 state: vec![NodeEvaluationState { node: ms, n_evaluated: 0, n_satisfied: 0 }],
+                script: Some(ms),
+                annex: None,
                 sequence: Sequence::from_height(1002),
                 lock_time: absolute::LockTime::from_height(1002).unwrap(),
                 has_errored: false,
+                future_leaf: false,
+                flags: VerifyFlags::default(),
+                chain: None,
+                assume_timelocks: false,
                 sig_type: SigType::Ecdsa,
+                report: ExecutionReport { op_count: ms.ext.ops.count, ..Default::default() },
             }
         }
 
@@ -1554,6 +3104,1116 @@ mod tests {
         assert!(multi_a_error.is_err());
     }
 
+    #[test]
+    fn classify_spends() {
+        let (pks, der_sigs, _, _, secp, xpks, _, ser_schnorr_sigs) = setup_keys_sigs(2);
+        let empty_script = bitcoin::ScriptBuf::new();
+        let empty_wit = Witness::new();
+
+        // pkh
+        let spk = bitcoin::ScriptBuf::new_p2pkh(&pks[0].pubkey_hash());
+        let script_sig = bitcoin::script::Builder::new()
+            .push_slice(<&bitcoin::script::PushBytes>::try_from(&der_sigs[0][..]).unwrap())
+            .push_key(&pks[0])
+            .into_script();
+        let spend = ClassifiedSpend::from_txdata(&spk, &script_sig, &empty_wit).unwrap();
+        assert_eq!(spend.template, SpendTemplate::Pkh);
+        assert_eq!(spend.descriptor, Some(Descriptor::new_pkh(pks[0]).unwrap()));
+        assert_eq!(spend.miniscript, None);
+
+        // wsh and sh-wsh
+        let ms = Miniscript::<bitcoin::PublicKey, Segwitv0>::from_str(&format!(
+            "and_v(v:pk({}),older(10))",
+            pks[1]
+        ))
+        .unwrap();
+        let witness = Witness::from_slice(&[der_sigs[1].clone(), ms.encode().into_bytes()]);
+        let wsh = Descriptor::new_wsh(ms.clone()).unwrap();
+        let spend =
+            ClassifiedSpend::from_txdata(&wsh.script_pubkey(), &empty_script, &witness).unwrap();
+        assert_eq!(spend.template, SpendTemplate::Wsh);
+        assert_eq!(spend.descriptor, Some(wsh));
+        assert_eq!(spend.miniscript, Some(SpentMiniscript::Segwitv0(ms.clone())));
+
+        let sh_wsh = Descriptor::new_sh_wsh(ms.clone()).unwrap();
+        let spend = ClassifiedSpend::from_txdata(
+            &sh_wsh.script_pubkey(),
+            &sh_wsh.unsigned_script_sig(),
+            &witness,
+        )
+        .unwrap();
+        assert_eq!(spend.template, SpendTemplate::ShWsh);
+        assert_eq!(spend.descriptor, Some(sh_wsh));
+        assert_eq!(spend.miniscript, Some(SpentMiniscript::Segwitv0(ms)));
+
+        // taproot script and key path spends
+        let ms =
+            Miniscript::<bitcoin::key::XOnlyPublicKey, Tap>::from_str(&format!("pk({})", xpks[1]))
+                .unwrap();
+        let leaf = (ms.encode(), taproot::LeafVersion::TapScript);
+        let spend_info = taproot::TaprootBuilder::new()
+            .add_leaf(0, leaf.0.clone())
+            .unwrap()
+            .finalize(&secp, xpks[0])
+            .unwrap();
+        let control_block = spend_info.control_block(&leaf).unwrap();
+        let spk = bitcoin::ScriptBuf::new_p2tr_tweaked(spend_info.output_key());
+        let witness = Witness::from_slice(&[
+            ser_schnorr_sigs[1].clone(),
+            leaf.0.into_bytes(),
+            control_block.serialize(),
+        ]);
+        let spend = ClassifiedSpend::from_txdata(&spk, &empty_script, &witness).unwrap();
+        assert_eq!(spend.template, SpendTemplate::TrScriptSpend);
+        assert_eq!(spend.descriptor, None);
+        assert_eq!(spend.miniscript, Some(SpentMiniscript::Tap(ms)));
+
+        let witness = Witness::from_slice(&[ser_schnorr_sigs[0].clone()]);
+        let spend = ClassifiedSpend::from_txdata(&spk, &empty_script, &witness).unwrap();
+        assert_eq!(spend.template, SpendTemplate::TrKeySpend);
+        assert_eq!(spend.descriptor, None);
+        assert_eq!(spend.miniscript, None);
+
+        // not miniscript
+        let spk = bitcoin::ScriptBuf::new_op_return([1, 2, 3]);
+        assert!(matches!(
+            ClassifiedSpend::from_txdata(&spk, &empty_script, &empty_wit),
+            Err(Error::UnspendableOutput(UnspendableScript::NullData(data)))
+                if data == [vec![1, 2, 3]]
+        ));
+        let spk = bitcoin::ScriptBuf::new_p2wsh(&bitcoin::WScriptHash::all_zeros());
+        assert!(ClassifiedSpend::from_txdata(&spk, &empty_script, &empty_wit).is_err());
+    }
+
+    #[test]
+    fn taproot_annex() {
+        use bitcoin::key::TapTweak;
+
+        let secp = Secp256k1::new();
+        let keypair = bitcoin::key::Keypair::from_seckey_slice(&secp, &[1; 32]).unwrap();
+        let (internal_key, _) = keypair.x_only_public_key();
+        let spk = bitcoin::ScriptBuf::new_p2tr(&secp, internal_key, None);
+        let prevouts =
+            [TxOut { value: bitcoin::Amount::from_sat(100_000), script_pubkey: spk.clone() }];
+        let tx = bitcoin::Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![bitcoin::TxIn::default()],
+            output: vec![TxOut::NULL],
+        };
+        let annex = [0x50, 0xaa, 0xbb];
+
+        let sign = |annex: Option<sighash::Annex>| {
+            let sighash = sighash::SighashCache::new(&tx)
+                .taproot_signature_hash(
+                    0,
+                    &sighash::Prevouts::All(&prevouts),
+                    annex,
+                    None,
+                    sighash::TapSighashType::Default,
+                )
+                .unwrap();
+            let msg = secp256k1::Message::from_digest(sighash.to_byte_array());
+            let keypair = keypair.tap_tweak(&secp, None).to_keypair();
+            secp.sign_schnorr_no_aux_rand(&msg, &keypair)
+                .serialize()
+                .to_vec()
+        };
+        let verify = |witness: &Witness| {
+            let interpreter = Interpreter::from_txdata(
+                &spk,
+                bitcoin::Script::new(),
+                witness,
+                Sequence::MAX,
+                absolute::LockTime::ZERO,
+            )
+            .unwrap();
+            assert!(interpreter.is_taproot_v1_key_spend());
+            let prevouts = sighash::Prevouts::All(&prevouts);
+            let iter = interpreter.iter(&secp, &tx, 0, &prevouts);
+            assert_eq!(iter.annex(), interpreter.annex());
+            iter.collect::<Result<Vec<_>, _>>()
+                .map(|constraints| constraints.len())
+        };
+
+        // The annex is committed to by the signature, but not part of the stack
+        let sig = sign(Some(sighash::Annex::new(&annex).unwrap()));
+        let witness = Witness::from_slice(&[sig.clone(), annex.to_vec()]);
+        assert_eq!(verify(&witness).unwrap(), 1);
+
+        // Signatures not committing to the annex present do not verify, and
+        // vice versa
+        let witness = Witness::from_slice(&[sign(None), annex.to_vec()]);
+        assert!(verify(&witness).is_err());
+        let witness = Witness::from_slice(&[sig]);
+        assert!(verify(&witness).is_err());
+        let witness = Witness::from_slice(&[sign(None)]);
+        assert_eq!(verify(&witness).unwrap(), 1);
+
+        // Only taproot spends have an annex
+        let witness = Witness::from_slice(&[vec![1], annex.to_vec()]);
+        let wsh = bitcoin::ScriptBuf::new_p2wsh(&bitcoin::WScriptHash::all_zeros());
+        let err = Interpreter::from_txdata(
+            &wsh,
+            bitcoin::Script::new(),
+            &witness,
+            Sequence::MAX,
+            absolute::LockTime::ZERO,
+        );
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn uncompressed_keys() {
+        use bitcoin::consensus::encode::deserialize_hex;
+        use bitcoin::script::{Builder, PushBytesBuf};
+
+        let secp = Secp256k1::new();
+
+        // Mainnet transaction f4184fc5..., in block 170, spending the P2PK
+        // output of the coinbase of block 9 to an uncompressed key
+        let tx: bitcoin::Transaction = deserialize_hex(
+            "0100000001c997a5e56e104102fa209c6a852dd90660a20b2d9c352423edce25857fcd3704000000\
+             004847304402204e45e16932b8af514961a1d3a1a25fdf3f4f7732e9d624c6c61548ab5fb8cd4102\
+             20181522ec8eca07de4860a4acdd12909d831cc56cbbac4622082221a8768d1d0901ffffffff0200\
+             ca9a3b00000000434104ae1a62fe09c5f51b13905f07f06b99a2f7159b2225f374cd378d71302fa2\
+             8414e7aab37397f554a7df5f142c21c1b7303b8a0626f1baded5c72a704f7e6cd84cac00286bee00\
+             00000043410411db93e1dcdb8a016b49840f8c53bc1eb68a382e97b1482ecad7b148a6909a5cb2e0\
+             eaddfb84ccf9744464f82e160bfa9b8b64f9d4c03f999b8643f656b412a3ac00000000",
+        )
+        .unwrap();
+        assert_eq!(
+            tx.compute_txid().to_string(),
+            "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16"
+        );
+        // The spent output pays to the same key as the change output
+        let prevout = TxOut {
+            value: bitcoin::Amount::from_sat(50_0000_0000),
+            script_pubkey: tx.output[1].script_pubkey.clone(),
+        };
+        let results = verify_transaction(&secp, &tx, core::slice::from_ref(&prevout)).unwrap();
+        match results[0].as_ref().unwrap()[..] {
+            [SatisfiedConstraint::PublicKey { key_sig: KeySigPair::Ecdsa(pk, _) }] => {
+                assert!(!pk.compressed);
+                assert_eq!(bitcoin::ScriptBuf::new_p2pk(&pk), prevout.script_pubkey);
+            }
+            ref constraints => panic!("unexpected constraints {:?}", constraints),
+        }
+
+        // Legacy and bare spends with uncompressed keys
+        let sks = [[1; 32], [2; 32]].map(|sk| secp256k1::SecretKey::from_slice(&sk).unwrap());
+        let pks = sks.map(|sk| bitcoin::PublicKey {
+            compressed: false,
+            inner: secp256k1::PublicKey::from_secret_key(&secp, &sk),
+        });
+        let multi = Miniscript::<bitcoin::PublicKey, BareCtx>::from_str(&format!(
+            "multi(1,{},{})",
+            pks[0], pks[1]
+        ))
+        .unwrap()
+        .encode();
+        let or_d = Miniscript::<bitcoin::PublicKey, Legacy>::from_str(&format!(
+            "or_d(pk({}),pkh({}))",
+            pks[1], pks[0]
+        ))
+        .unwrap()
+        .encode();
+        let spends = [
+            (bitcoin::ScriptBuf::new_p2pkh(&pks[0].pubkey_hash()), None),
+            (multi.clone(), None),
+            (bitcoin::ScriptBuf::new_p2sh(&multi.script_hash()), Some(multi)),
+            (bitcoin::ScriptBuf::new_p2sh(&or_d.script_hash()), Some(or_d)),
+        ];
+        for (spk, redeem_script) in spends {
+            let mut tx = bitcoin::Transaction {
+                version: bitcoin::transaction::Version::ONE,
+                lock_time: absolute::LockTime::ZERO,
+                input: vec![bitcoin::TxIn::default()],
+                output: vec![TxOut::NULL],
+            };
+            let script_code = redeem_script.as_ref().unwrap_or(&spk);
+            let sighash = sighash::SighashCache::new(&tx)
+                .legacy_signature_hash(0, script_code, 1)
+                .unwrap();
+            let msg = secp256k1::Message::from_digest(sighash.to_byte_array());
+            let sig = bitcoin::ecdsa::Signature::sighash_all(secp.sign_ecdsa(&msg, &sks[0]));
+            let builder = if spk.is_p2pkh() {
+                Builder::new().push_slice(sig.serialize()).push_key(&pks[0])
+            } else if let Some(ref redeem_script) = redeem_script {
+                let builder = if redeem_script.is_multisig() {
+                    Builder::new().push_int(0).push_slice(sig.serialize())
+                } else {
+                    // Dissatisfy the `pk` and satisfy the `pkh` of the `or_d`
+                    Builder::new().push_slice(sig.serialize()).push_key(&pks[0]).push_int(0)
+                };
+                builder.push_slice(PushBytesBuf::try_from(redeem_script.to_bytes()).unwrap())
+            } else {
+                Builder::new().push_int(0).push_slice(sig.serialize())
+            };
+            tx.input[0].script_sig = builder.into_script();
+
+            let prevout = TxOut { value: bitcoin::Amount::from_sat(10_000), script_pubkey: spk };
+            let results = verify_transaction(&secp, &tx, &[prevout]).unwrap();
+            match results[0].as_ref().unwrap()[..] {
+                [SatisfiedConstraint::PublicKey { key_sig }]
+                | [SatisfiedConstraint::PublicKeyHash { key_sig, .. }] => {
+                    assert_eq!(key_sig, KeySigPair::Ecdsa(pks[0], sig))
+                }
+                ref constraints => panic!("unexpected constraints {:?}", constraints),
+            }
+        }
+    }
+
+    #[test]
+    fn verify_tx() {
+        use bitcoin::key::TapTweak;
+
+        let secp = Secp256k1::new();
+        let keypairs = [[1; 32], [2; 32]]
+            .map(|sk| bitcoin::key::Keypair::from_seckey_slice(&secp, &sk).unwrap());
+        let internal_keys = keypairs.map(|keypair| keypair.x_only_public_key().0);
+        let mut prevouts = internal_keys
+            .iter()
+            .map(|&internal_key| TxOut {
+                value: bitcoin::Amount::from_sat(100_000),
+                script_pubkey: bitcoin::ScriptBuf::new_p2tr(&secp, internal_key, None),
+            })
+            .collect::<Vec<_>>();
+        let mut tx = bitcoin::Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![bitcoin::TxIn::default(); 2],
+            output: vec![TxOut::NULL],
+        };
+        for (input_idx, keypair) in keypairs.iter().enumerate() {
+            let sighash = sighash::SighashCache::new(&tx)
+                .taproot_key_spend_signature_hash(
+                    input_idx,
+                    &sighash::Prevouts::All(&prevouts),
+                    sighash::TapSighashType::Default,
+                )
+                .unwrap();
+            let msg = secp256k1::Message::from_digest(sighash.to_byte_array());
+            let keypair = keypair.tap_tweak(&secp, None).to_keypair();
+            let sig = secp.sign_schnorr_no_aux_rand(&msg, &keypair);
+            tx.input[input_idx].witness = Witness::from_slice(&[sig.serialize()]);
+        }
+
+        let results = verify_transaction(&secp, &tx, &prevouts).unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|res| res.as_ref().unwrap().len() == 1));
+
+        let descriptors = internal_keys
+            .map(|internal_key| Some(Descriptor::new_tr(internal_key, None).unwrap()));
+        let results =
+            verify_transaction_with_descriptors(&secp, &tx, &prevouts, &descriptors).unwrap();
+        assert!(results.iter().all(Result::is_ok));
+        let swapped = [descriptors[1].clone(), None];
+        let results =
+            verify_transaction_with_descriptors(&secp, &tx, &prevouts, &swapped).unwrap();
+        assert!(matches!(results[0], Err(Error::DescriptorMismatch)));
+        assert!(results[1].is_ok());
+        assert!(matches!(
+            verify_transaction_with_descriptors(&secp, &tx, &prevouts, &descriptors[..1]),
+            Err(Error::DescriptorsLengthMismatch(2, 1))
+        ));
+
+        assert!(matches!(
+            verify_transaction(&secp, &tx, &prevouts[..1]),
+            Err(Error::PrevoutsLengthMismatch(2, 1))
+        ));
+        // Taproot signatures commit to the amounts of all spent outputs
+        prevouts[1].value = bitcoin::Amount::from_sat(50_000);
+        let results = verify_transaction(&secp, &tx, &prevouts).unwrap();
+        assert!(results.iter().all(Result::is_err));
+    }
+
+    #[test]
+    #[cfg(feature = "consensus-verify")]
+    fn consensus_cross_check() {
+        use bitcoin::opcodes::all::{OP_NOP, OP_PUSHNUM_1};
+
+        let secp = Secp256k1::new();
+        let sk = secp256k1::SecretKey::from_slice(&[1; 32]).unwrap();
+        let pk = bitcoin::PublicKey::new(secp256k1::PublicKey::from_secret_key(&secp, &sk));
+        let p2wpkh = bitcoin::ScriptBuf::new_p2wpkh(&pk.wpubkey_hash().unwrap());
+        // Consensus-valid, but not a miniscript
+        let nop = bitcoin::script::Builder::new()
+            .push_opcode(OP_NOP)
+            .push_opcode(OP_PUSHNUM_1)
+            .into_script();
+        let prevouts = [p2wpkh.clone(), p2wpkh, nop].map(|script_pubkey| TxOut {
+            value: bitcoin::Amount::from_sat(100_000),
+            script_pubkey,
+        });
+        let mut tx = bitcoin::Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![bitcoin::TxIn::default(); 3],
+            output: vec![TxOut::NULL],
+        };
+        let sighash = sighash::SighashCache::new(&tx)
+            .p2wpkh_signature_hash(
+                0,
+                &prevouts[0].script_pubkey,
+                prevouts[0].value,
+                sighash::EcdsaSighashType::All,
+            )
+            .unwrap();
+        let msg = secp256k1::Message::from_digest(sighash.to_byte_array());
+        let sig = bitcoin::ecdsa::Signature::sighash_all(secp.sign_ecdsa(&msg, &sk));
+        tx.input[0].witness = Witness::p2wpkh(&sig, &pk.inner);
+        // Break the second spend by signing the wrong message
+        let msg = secp256k1::Message::from_digest([0xab; 32]);
+        let sig = bitcoin::ecdsa::Signature::sighash_all(secp.sign_ecdsa(&msg, &sk));
+        tx.input[1].witness = Witness::p2wpkh(&sig, &pk.inner);
+
+        let results = verify_transaction(&secp, &tx, &prevouts).unwrap();
+        assert!(results[0].is_ok());
+        // Both reject the broken spend, which is no discrepancy
+        assert!(matches!(results[1], Err(ref e) if !matches!(e, Error::ConsensusDiscrepancy(_))));
+        assert!(matches!(
+            results[2],
+            Err(Error::ConsensusDiscrepancy(ConsensusDiscrepancy::InterpreterRejected(_)))
+        ));
+
+        // Libbitcoinconsensus rejecting a spend the interpreter accepted
+        let serialized_tx = bitcoin::consensus::encode::serialize(&tx);
+        let rejected = prevouts[1]
+            .script_pubkey
+            .verify(1, prevouts[1].value, &serialized_tx)
+            .unwrap_err();
+        assert!(matches!(
+            cross_check_consensus(Ok(()), Err(rejected)),
+            Err(Error::ConsensusDiscrepancy(ConsensusDiscrepancy::ConsensusRejected(_)))
+        ));
+        assert!(cross_check_consensus(Ok(()), Ok(())).is_ok());
+    }
+
+    #[test]
+    fn signature_reuse() {
+        let (pks, ..) = setup_keys_sigs(2);
+        let sig = |r: u8, s: u8| {
+            let mut compact = [r; 64];
+            compact[32..].copy_from_slice(&[s; 32]);
+            bitcoin::ecdsa::Signature::sighash_all(
+                secp256k1::ecdsa::Signature::from_compact(&compact).unwrap(),
+            )
+        };
+        let (sig_a, sig_b, sig_c) = (sig(1, 2), sig(1, 3), sig(4, 2));
+        let spends = [(pks[0], sig_a), (pks[0], sig_a), (pks[1], sig_b), (pks[1], sig_c)];
+        let prevouts = spends
+            .iter()
+            .map(|(pk, _)| TxOut {
+                value: bitcoin::Amount::from_sat(100_000),
+                script_pubkey: bitcoin::ScriptBuf::new_p2wpkh(&pk.wpubkey_hash().unwrap()),
+            })
+            .collect::<Vec<_>>();
+        let tx = bitcoin::Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: spends
+                .iter()
+                .map(|(pk, sig)| bitcoin::TxIn {
+                    witness: Witness::p2wpkh(sig, &pk.inner),
+                    ..Default::default()
+                })
+                .collect(),
+            output: vec![TxOut::NULL],
+        };
+
+        assert_eq!(
+            find_signature_reuse(&tx, &prevouts).unwrap(),
+            vec![
+                SignatureReuse::Duplicate {
+                    key_sig: KeySigPair::Ecdsa(pks[0], sig_a),
+                    inputs: (0, 1),
+                },
+                SignatureReuse::NonceReuse {
+                    first: (0, KeySigPair::Ecdsa(pks[0], sig_a)),
+                    second: (2, KeySigPair::Ecdsa(pks[1], sig_b)),
+                },
+                SignatureReuse::NonceReuse {
+                    first: (1, KeySigPair::Ecdsa(pks[0], sig_a)),
+                    second: (2, KeySigPair::Ecdsa(pks[1], sig_b)),
+                },
+            ]
+        );
+        assert!(matches!(
+            find_signature_reuse(&tx, &prevouts[..1]),
+            Err(Error::PrevoutsLengthMismatch(4, 1))
+        ));
+    }
+
+    #[test]
+    fn verify_witness() {
+        let (pks, der_sigs, ecdsa_sigs, msg, secp, ..) = setup_keys_sigs(2);
+        let desc = Descriptor::<bitcoin::PublicKey>::from_str(&format!(
+            "wsh(and_v(v:pk({}),older(144)))",
+            pks[0]
+        ))
+        .unwrap();
+        let script = desc.explicit_script().unwrap().into_bytes();
+        let mut verify_ecdsa = |pksig: &KeySigPair| {
+            let (pk, sig) = pksig.as_ecdsa().unwrap();
+            secp.verify_ecdsa(&msg, &sig.signature, &pk.inner).is_ok()
+        };
+        let witness = |sig: &[u8]| Witness::from_slice(&[sig.to_vec(), script.clone()]);
+        let no_script_sig = bitcoin::Script::new();
+
+        // Timelocks are not checked
+        assert_eq!(
+            super::verify_witness(
+                &desc,
+                no_script_sig,
+                &witness(&der_sigs[0]),
+                Some(&mut verify_ecdsa)
+            )
+            .unwrap(),
+            vec![
+                SatisfiedConstraint::PublicKey {
+                    key_sig: KeySigPair::Ecdsa(pks[0], ecdsa_sigs[0])
+                },
+                SatisfiedConstraint::RelativeTimelock { n: relative::LockTime::from_height(144) },
+            ]
+        );
+        // Signatures are only checked if a callback is given
+        let wrong_sig = witness(&der_sigs[1]);
+        assert!(
+            super::verify_witness(&desc, no_script_sig, &wrong_sig, Some(&mut verify_ecdsa))
+                .is_err()
+        );
+        assert!(super::verify_witness(&desc, no_script_sig, &wrong_sig, None).is_ok());
+
+        // The witness must be for the descriptor
+        let other =
+            Descriptor::<bitcoin::PublicKey>::from_str(&format!("wpkh({})", pks[0])).unwrap();
+        let witness = witness(&der_sigs[0]);
+        assert!(super::verify_witness(&other, no_script_sig, &witness, None).is_err());
+    }
+
+    #[test]
+    fn canonicalize_witness() {
+        let (pks, der_sigs, ..) = setup_keys_sigs(3);
+        let desc = Descriptor::<bitcoin::PublicKey>::from_str(&format!(
+            "wsh(or_b(pk({}),s:pk({})))",
+            pks[0], pks[1]
+        ))
+        .unwrap();
+        let script = desc.explicit_script().unwrap().into_bytes();
+        let witness = |stack: &[&[u8]]| {
+            let mut stack = stack.iter().map(|elem| elem.to_vec()).collect::<Vec<_>>();
+            stack.push(script.clone());
+            Witness::from_slice(&stack)
+        };
+        let no_script_sig = bitcoin::Script::new();
+        let canonical = witness(&[&[], &der_sigs[0]]);
+
+        // A spend with both signatures is canonicalized to one with the left one
+        let spend = witness(&[&der_sigs[1], &der_sigs[0]]);
+        assert_eq!(
+            super::canonicalize_witness(&desc, no_script_sig, &spend).unwrap(),
+            (bitcoin::ScriptBuf::new(), canonical.clone())
+        );
+        // Canonical witnesses are left as they are
+        assert_eq!(
+            super::canonicalize_witness(&desc, no_script_sig, &canonical).unwrap(),
+            (bitcoin::ScriptBuf::new(), canonical.clone())
+        );
+        let spend = witness(&[&der_sigs[1], &[]]);
+        assert_eq!(
+            super::canonicalize_witness(&desc, no_script_sig, &spend).unwrap().1,
+            spend
+        );
+
+        // The witness must be for the descriptor
+        let other =
+            Descriptor::<bitcoin::PublicKey>::from_str(&format!("wpkh({})", pks[2])).unwrap();
+        assert!(super::canonicalize_witness(&other, no_script_sig, &canonical).is_err());
+    }
+
+    #[test]
+    fn explain_witness() {
+        let (pks, der_sigs, ..) = setup_keys_sigs(4);
+        let preimage = [0xab; 32];
+        let hash = sha256::Hash::hash(&preimage);
+        let or_i = format!(
+            "or_i(and_v(v:sha256({}),pk({})),multi(1,{},{}))",
+            hash, pks[1], pks[2], pks[3]
+        );
+        let ms = Miniscript::<bitcoin::PublicKey, Segwitv0>::from_str(&format!(
+            "and_v(v:pkh({}),{})",
+            pks[0], or_i
+        ))
+        .unwrap();
+        let spk = bitcoin::ScriptBuf::new_p2wsh(&ms.encode().wscript_hash());
+        let explain = |witness: &[Vec<u8>]| {
+            let mut witness = witness.to_vec();
+            witness.push(ms.encode().into_bytes());
+            let witness = Witness::from_slice(&witness);
+            let interpreter = Interpreter::from_txdata(
+                &spk,
+                bitcoin::Script::new(),
+                &witness,
+                Sequence::ZERO,
+                absolute::LockTime::ZERO,
+            )
+            .unwrap();
+            interpreter
+                .explain_witness()
+                .unwrap()
+                .into_iter()
+                .map(|elem| (elem.index, elem.fragment.unwrap(), elem.role))
+                .collect::<Vec<_>>()
+        };
+
+        // Decoded from the script, keys of `pkh` fragments are not known
+        let pkh = format!("expr_raw_pk_h({})", pks[0].pubkey_hash());
+        let explained = explain(&[
+            der_sigs[1].clone(),
+            preimage.to_vec(),
+            vec![1],
+            der_sigs[0].clone(),
+            pks[0].to_bytes(),
+        ]);
+        assert_eq!(
+            explained,
+            vec![
+                (0, format!("pk_k({})", pks[1]), WitnessRole::Signature),
+                (1, format!("sha256({})", hash), WitnessRole::Preimage),
+                (2, or_i.clone(), WitnessRole::BranchSelector),
+                (3, pkh.clone(), WitnessRole::Signature),
+                (4, pkh.clone(), WitnessRole::PublicKey),
+            ]
+        );
+
+        let multi = format!("multi(1,{},{})", pks[2], pks[3]);
+        let explained =
+            explain(&[vec![], der_sigs[3].clone(), vec![], der_sigs[0].clone(), pks[0].to_bytes()]);
+        assert_eq!(
+            explained,
+            vec![
+                (0, multi.clone(), WitnessRole::MultisigDummy),
+                (1, multi, WitnessRole::Signature),
+                (2, or_i, WitnessRole::BranchSelector),
+                (3, pkh.clone(), WitnessRole::Signature),
+                (4, pkh, WitnessRole::PublicKey),
+            ]
+        );
+    }
+
+    #[test]
+    fn execution_report() {
+        let (pks, der_sigs, ..) = setup_keys_sigs(4);
+        let preimage = [0xab; 32];
+        let hash = sha256::Hash::hash(&preimage);
+        let ms = Miniscript::<bitcoin::PublicKey, Segwitv0>::from_str(&format!(
+            "and_v(v:pkh({}),or_i(and_v(v:sha256({}),pk({})),multi(1,{},{})))",
+            pks[0], hash, pks[1], pks[2], pks[3]
+        ))
+        .unwrap();
+        let spk = bitcoin::ScriptBuf::new_p2wsh(&ms.encode().wscript_hash());
+        let report = |witness: &[Vec<u8>]| {
+            let mut witness = witness.to_vec();
+            witness.push(ms.encode().into_bytes());
+            let witness = Witness::from_slice(&witness);
+            let interpreter = Interpreter::from_txdata(
+                &spk,
+                bitcoin::Script::new(),
+                &witness,
+                Sequence::ZERO,
+                absolute::LockTime::ZERO,
+            )
+            .unwrap();
+            let mut iter = interpreter.iter_assume_sigs();
+            assert!(iter.by_ref().all(|res| res.is_ok()));
+            iter.execution_report()
+        };
+        let fits = |report: ExecutionReport| {
+            report.op_count <= ms.ext.ops.count + ms.ext.ops.sat.unwrap()
+                && report.max_stack_size
+                    <= ms.ext.stack_elem_count_sat.unwrap()
+                        + ms.ext.exec_stack_elem_count_sat.unwrap()
+                && report.witness_size <= ms.ext.max_sat_size.unwrap().0
+        };
+
+        let witness = [
+            der_sigs[1].clone(),
+            preimage.to_vec(),
+            vec![1],
+            der_sigs[0].clone(),
+            pks[0].to_bytes(),
+        ];
+        let sha256_branch = report(&witness);
+        assert_eq!(
+            sha256_branch,
+            ExecutionReport {
+                op_count: ms.ext.ops.count,
+                max_stack_size: 5,
+                witness_size: witness.iter().map(|elem| 1 + elem.len()).sum(),
+                sigops: 2,
+            }
+        );
+        assert!(fits(sha256_branch));
+
+        // The keys of the executed `OP_CHECKMULTISIG` count as opcodes
+        let multi_branch =
+            report(&[vec![], der_sigs[3].clone(), vec![], der_sigs[0].clone(), pks[0].to_bytes()]);
+        assert_eq!(multi_branch.op_count, ms.ext.ops.count + 2);
+        assert_eq!(multi_branch.sigops, 2);
+        assert!(fits(multi_branch));
+    }
+
+    #[test]
+    fn iter_unverified() {
+        let (pks, der_sigs, ecdsa_sigs, ..) = setup_keys_sigs(2);
+        let ms = Miniscript::<bitcoin::PublicKey, Segwitv0>::from_str(&format!(
+            "and_v(v:pk({}),older(10))",
+            pks[0]
+        ))
+        .unwrap();
+        let spk = bitcoin::ScriptBuf::new_p2wsh(&ms.encode().wscript_hash());
+        let interpret = |sig: &[u8]| {
+            let witness = Witness::from_slice(&[sig.to_vec(), ms.encode().into_bytes()]);
+            let interpreter = Interpreter::from_txdata(
+                &spk,
+                bitcoin::Script::new(),
+                &witness,
+                Sequence::from_height(10),
+                absolute::LockTime::ZERO,
+            )
+            .unwrap();
+            interpreter
+                .iter_unverified()
+                .map(|res| res.map(Unverified::assume_verified))
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        // A well-formed signature by another key is not rejected
+        assert_eq!(
+            interpret(&der_sigs[1]).unwrap(),
+            vec![
+                SatisfiedConstraint::PublicKey {
+                    key_sig: KeySigPair::Ecdsa(pks[0], ecdsa_sigs[1])
+                },
+                SatisfiedConstraint::RelativeTimelock { n: relative::LockTime::from_height(10) },
+            ]
+        );
+        assert!(interpret(&der_sigs[1][1..]).is_err());
+    }
+
+    #[test]
+    fn no_checks_keys() {
+        let (pks, _, _, _, _, xpks, ..) = setup_keys_sigs(2);
+        // Keys of pre-Taproot and Tapscript spends decode to the same context
+        let script = bitcoin::script::Builder::new()
+            .push_key(&pks[0])
+            .push_opcode(bitcoin::opcodes::all::OP_CHECKSIGVERIFY)
+            .push_slice(xpks[1].serialize())
+            .push_opcode(bitcoin::opcodes::all::OP_CHECKSIG)
+            .into_script();
+        let ms = Miniscript::<BitcoinKey, NoChecks>::parse_insane(&script).unwrap();
+        let keys = ms.iter_pk().collect::<Vec<_>>();
+        assert_eq!(keys, vec![BitcoinKey::Fullkey(pks[0]), BitcoinKey::XOnlyPublicKey(xpks[1])]);
+        assert!(!keys[0].is_x_only_key() && keys[1].is_x_only_key());
+        assert_eq!(keys[1].to_x_only_pubkey(), xpks[1]);
+        assert_eq!(keys[1].to_public_key(), xpks[1].to_public_key());
+        assert_eq!(
+            keys[1].to_pubkeyhash(SigType::Schnorr),
+            hash160::Hash::hash(&xpks[1].serialize())
+        );
+    }
+
+    #[test]
+    fn tap_leaf() {
+        let secp = Secp256k1::new();
+        let (_, _, _, _, _, xpks, _, ser_schnorr_sigs) = setup_keys_sigs(4);
+        let leaves = (1..4)
+            .map(|i| {
+                let ms = Miniscript::<bitcoin::key::XOnlyPublicKey, Tap>::from_str(&format!(
+                    "pk({})",
+                    xpks[i]
+                ))
+                .unwrap();
+                ms.encode()
+            })
+            .collect::<Vec<_>>();
+        let spend_info = taproot::TaprootBuilder::new()
+            .add_leaf(1, leaves[0].clone())
+            .unwrap()
+            .add_leaf(2, leaves[1].clone())
+            .unwrap()
+            .add_leaf(2, leaves[2].clone())
+            .unwrap()
+            .finalize(&secp, xpks[0])
+            .unwrap();
+        let spk = bitcoin::ScriptBuf::new_p2tr_tweaked(spend_info.output_key());
+        let interpret = |witness: &Witness| {
+            Interpreter::from_txdata(
+                &spk,
+                bitcoin::Script::new(),
+                witness,
+                Sequence::MAX,
+                absolute::LockTime::ZERO,
+            )
+            .unwrap()
+            .tap_leaf()
+        };
+
+        for (i, (script, depth)) in leaves.iter().zip([1, 2, 2]).enumerate() {
+            let control_block = spend_info
+                .control_block(&(script.clone(), taproot::LeafVersion::TapScript))
+                .unwrap();
+            let witness = Witness::from_slice(&[
+                ser_schnorr_sigs[i + 1].clone(),
+                script.to_bytes(),
+                control_block.serialize(),
+            ]);
+            let leaf = interpret(&witness).unwrap();
+            assert_eq!(&leaf.script, script);
+            assert_eq!(leaf.control_block, control_block);
+            assert_eq!(leaf.leaf_version(), taproot::LeafVersion::TapScript);
+            assert_eq!(
+                leaf.leaf_hash(),
+                taproot::TapLeafHash::from_script(script, taproot::LeafVersion::TapScript)
+            );
+            assert_eq!(leaf.depth(), depth);
+        }
+
+        // Key spends have no leaf
+        let witness = Witness::from_slice(&[ser_schnorr_sigs[0].clone()]);
+        assert_eq!(interpret(&witness), None);
+    }
+
+    #[test]
+    fn future_leaf_version() {
+        let secp = Secp256k1::new();
+        let (_, _, _, _, _, xpks, ..) = setup_keys_sigs(1);
+        let version = taproot::LeafVersion::from_consensus(0xc2).unwrap();
+        // Not a valid Miniscript, nor even a valid script
+        let leaf_script = bitcoin::ScriptBuf::from_bytes(vec![0xff, 0x4c]);
+        let spend_info = taproot::TaprootBuilder::new()
+            .add_leaf_with_ver(0, leaf_script.clone(), version)
+            .unwrap()
+            .finalize(&secp, xpks[0])
+            .unwrap();
+        let spk = bitcoin::ScriptBuf::new_p2tr_tweaked(spend_info.output_key());
+        let control_block = spend_info.control_block(&(leaf_script.clone(), version)).unwrap();
+        let witness =
+            Witness::from_slice(&[vec![0xaa], leaf_script.to_bytes(), control_block.serialize()]);
+
+        let interpreter = Interpreter::from_txdata(
+            &spk,
+            bitcoin::Script::new(),
+            &witness,
+            Sequence::MAX,
+            absolute::LockTime::ZERO,
+        )
+        .unwrap();
+        assert_eq!(
+            interpreter.future_leaf_version().map(taproot::LeafVersion::Future),
+            Some(version)
+        );
+        assert_eq!(interpreter.template(), SpendTemplate::TrFutureLeafSpend);
+        let leaf = interpreter.tap_leaf().unwrap();
+        assert_eq!((&leaf.script, leaf.leaf_version()), (&leaf_script, version));
+        assert!(!interpreter.is_taproot_v1_script_spend());
+        assert_eq!(interpreter.iter_assume_sigs().count(), 0);
+
+        // The leaf must still be committed to by the output key
+        let witness = Witness::from_slice(&[vec![0x51], control_block.serialize()]);
+        let err = Interpreter::from_txdata(
+            &spk,
+            bitcoin::Script::new(),
+            &witness,
+            Sequence::MAX,
+            absolute::LockTime::ZERO,
+        );
+        assert!(matches!(err, Err(Error::ControlBlockVerificationError)));
+
+        let witness =
+            Witness::from_slice(&[vec![0xaa], leaf_script.to_bytes(), control_block.serialize()]);
+        let err = Interpreter::from_txdata_with_flags(
+            &spk,
+            bitcoin::Script::new(),
+            &witness,
+            Sequence::MAX,
+            absolute::LockTime::ZERO,
+            VerifyFlags::STANDARD,
+        );
+        assert!(matches!(err, Err(Error::DiscouragedUpgradableTaprootVersion(_))));
+    }
+
+    #[test]
+    fn evaluation_error_context() {
+        let (pks, der_sigs, ..) = setup_keys_sigs(2);
+        let hash = sha256::Hash::hash(&[0xab; 32]);
+        let ms = Miniscript::<bitcoin::PublicKey, Segwitv0>::from_str(&format!(
+            "and_v(v:pk({}),or_i(pk({}),sha256({})))",
+            pks[0], pks[1], hash
+        ))
+        .unwrap();
+        let script = ms.encode();
+        let spk = bitcoin::ScriptBuf::new_p2wsh(&script.wscript_hash());
+        let evaluate = |witness: &[Vec<u8>]| {
+            let mut witness = witness.to_vec();
+            witness.push(script.to_bytes());
+            let witness = Witness::from_slice(&witness);
+            let interpreter = Interpreter::from_txdata(
+                &spk,
+                bitcoin::Script::new(),
+                &witness,
+                Sequence::ZERO,
+                absolute::LockTime::ZERO,
+            )
+            .unwrap();
+            let err = interpreter.iter_assume_sigs().find_map(Result::err);
+            match err {
+                Some(Error::Evaluation(e)) => *e,
+                e => panic!("expected an evaluation error, got {:?}", e),
+            }
+        };
+
+        // Non-minimal branch selector of the `or_i`
+        let err = evaluate(&[vec![], vec![0x02], der_sigs[0].clone()]);
+        assert!(matches!(err.error, Error::UnexpectedStackElementPush));
+        assert_eq!(err.fragment, Some(format!("or_i(pk({}),sha256({}))", pks[1], hash)));
+        assert_eq!(err.fragment_index, Some(4));
+        assert_eq!(err.script_position, Some(35));
+        assert_eq!(script.as_bytes()[35], bitcoin::opcodes::all::OP_IF.to_u8());
+        assert_eq!(err.stack_top, vec![vec![]]);
+
+        // Preimage of the wrong length
+        let err = evaluate(&[vec![0xab; 31], vec![], der_sigs[0].clone()]);
+        assert!(matches!(err.error, Error::HashPreimageLengthMismatch));
+        assert_eq!(err.fragment, Some(format!("sha256({})", hash)));
+        assert_eq!(err.fragment_index, Some(7));
+        assert_eq!(err.script_position, Some(72));
+        assert_eq!(script.as_bytes()[72], bitcoin::opcodes::all::OP_SIZE.to_u8());
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Hash preimage should be 32 bytes in fragment sha256({}) \
+                 (#7, at script offset 72); stack top: []",
+                hash
+            )
+        );
+    }
+
+    #[test]
+    fn revealed_data() {
+        let (pks, der_sigs, ecdsa_sigs, ..) = setup_keys_sigs(2);
+        let preimage = [0xab; 32];
+        let hash = hash160::Hash::hash(&preimage);
+        let ms = Miniscript::<bitcoin::PublicKey, Segwitv0>::from_str(&format!(
+            "and_v(v:pkh({}),and_v(v:hash160({}),and_v(v:older(10),after(100))))",
+            pks[0], hash
+        ))
+        .unwrap();
+        let spk = bitcoin::ScriptBuf::new_p2wsh(&ms.encode().wscript_hash());
+        let witness = Witness::from_slice(&[
+            preimage.to_vec(),
+            der_sigs[0].clone(),
+            pks[0].to_bytes(),
+            ms.encode().into_bytes(),
+        ]);
+        let interpreter = Interpreter::from_txdata(
+            &spk,
+            bitcoin::Script::new(),
+            &witness,
+            Sequence::from_height(10),
+            absolute::LockTime::from_height(100).unwrap(),
+        )
+        .unwrap();
+        let data = interpreter
+            .iter_assume_sigs()
+            .collect::<Result<RevealedData, _>>()
+            .unwrap();
+        assert_eq!(
+            data,
+            RevealedData {
+                signatures: vec![KeySigPair::Ecdsa(pks[0], ecdsa_sigs[0])],
+                preimages: vec![(HashLockType::Hash160(hash), preimage)],
+                absolute_timelocks: vec![absolute::LockTime::from_height(100).unwrap()],
+                relative_timelocks: vec![relative::LockTime::from_height(10)],
+            }
+        );
+    }
+
+    #[test]
+    fn chain_context() {
+        let evaluate = |ms: &str, sequence: Sequence, chain: Option<ChainContext>| {
+            let ms = Miniscript::<bitcoin::PublicKey, Segwitv0>::from_str_insane(ms).unwrap();
+            let spk = bitcoin::ScriptBuf::new_p2wsh(&ms.encode().wscript_hash());
+            let witness = Witness::from_slice(&[ms.encode().into_bytes()]);
+            let mut interpreter = Interpreter::from_txdata(
+                &spk,
+                bitcoin::Script::new(),
+                &witness,
+                sequence,
+                absolute::LockTime::from_height(100).unwrap(),
+            )
+            .unwrap();
+            if let Some(chain) = chain {
+                interpreter = interpreter.with_chain_context(chain);
+            }
+            let res = interpreter.iter_assume_sigs().collect::<Result<Vec<_>, _>>();
+            res.map_err(|e| e.without_context().to_string())
+        };
+        let chain = |tip_height, utxo_confirmation_height, tip_mtp, utxo_mtp| {
+            Some(ChainContext {
+                tip_height: absolute::Height::from_consensus(tip_height).unwrap(),
+                tip_mtp: absolute::Time::from_consensus(tip_mtp).unwrap(),
+                utxo_confirmation_height: absolute::Height::from_consensus(
+                    utxo_confirmation_height,
+                )
+                .unwrap(),
+                utxo_mtp: absolute::Time::from_consensus(utxo_mtp).unwrap(),
+            })
+        };
+        let t = 1_700_000_000;
+
+        let ms = "and_v(v:after(100),older(10))";
+        let sequence = Sequence::from_height(10);
+        assert_eq!(evaluate(ms, sequence, None).unwrap().len(), 2);
+        // A lock time of 100 is final in the block at height 101, 10 blocks
+        // after the one at height 91
+        assert_eq!(evaluate(ms, sequence, chain(100, 91, t, t)).unwrap().len(), 2);
+        assert_eq!(
+            evaluate(ms, sequence, chain(99, 80, t, t)),
+            Err(Error::AbsoluteLockTimeNotMet(absolute::LockTime::from_height(100).unwrap())
+                .to_string())
+        );
+        assert_eq!(
+            evaluate(ms, sequence, chain(100, 92, t, t)),
+            Err(Error::RelativeLockTimeNotMet(relative::LockTime::from_height(10)).to_string())
+        );
+
+        // Time-based relative lock times count from the median time past of
+        // the block preceding the confirmation
+        let ms = "older(4194306)";
+        let sequence = Sequence::from_512_second_intervals(2);
+        assert_eq!(evaluate(ms, sequence, chain(99, 90, t + 1024, t)).unwrap().len(), 1);
+        assert!(evaluate(ms, sequence, chain(99, 90, t + 1023, t)).is_err());
+    }
+
+    #[test]
+    fn verify_flags() {
+        let (pks, der_sigs, ecdsa_sigs, msg, secp, ..) = setup_keys_sigs(4);
+        let run = |ms: &str, witness: &[Vec<u8>], flags: VerifyFlags| {
+            let ms = Miniscript::<bitcoin::PublicKey, Segwitv0>::from_str(ms).unwrap();
+            let spk = bitcoin::ScriptBuf::new_p2wsh(&ms.encode().wscript_hash());
+            let mut witness = witness.to_vec();
+            witness.push(ms.encode().into_bytes());
+            let witness = Witness::from_slice(&witness);
+            let interpreter = Interpreter::from_txdata_with_flags(
+                &spk,
+                bitcoin::Script::new(),
+                &witness,
+                Sequence::ZERO,
+                absolute::LockTime::ZERO,
+                flags,
+            )
+            .unwrap();
+            let verify_sig = Box::new(|key_sig: &KeySigPair| match key_sig {
+                KeySigPair::Ecdsa(pk, sig) => {
+                    secp.verify_ecdsa(&msg, &sig.signature, &pk.inner).is_ok()
+                }
+                KeySigPair::Schnorr(..) => false,
+            });
+            interpreter.iter_custom(verify_sig).collect::<Result<Vec<_>, _>>()
+        };
+
+        // Non-minimal `OP_IF` arguments
+        let or_i = format!("or_i(pk({}),pk({}))", pks[1], pks[2]);
+        for (selector, i) in [(vec![0x02], 1), (vec![0x00, 0x80], 2)] {
+            let witness = [der_sigs[i].clone(), selector];
+            let err = run(&or_i, &witness, VerifyFlags::default()).unwrap_err();
+            assert!(matches!(err.without_context(), Error::UnexpectedStackElementPush));
+            let constraints = run(&or_i, &witness, VerifyFlags::NONE).unwrap();
+            let key_sig = KeySigPair::Ecdsa(pks[i], ecdsa_sigs[i]);
+            assert_eq!(constraints, vec![SatisfiedConstraint::PublicKey { key_sig }]);
+        }
+
+        // Non-empty `OP_CHECKMULTISIG` dummy
+        let multi = format!("multi(1,{},{})", pks[2], pks[3]);
+        let witness = [vec![0x05], der_sigs[2].clone()];
+        let err = run(&multi, &witness, VerifyFlags::default()).unwrap_err();
+        assert!(matches!(err.without_context(), Error::MissingExtraZeroMultiSig));
+        assert_eq!(run(&multi, &witness, VerifyFlags::NONE).unwrap().len(), 1);
+
+        // Non-empty signatures failing verification
+        let or_d = format!("or_d(pk({}),pk({}))", pks[0], pks[1]);
+        let witness = [der_sigs[1].clone(), der_sigs[3].clone()];
+        let err = run(&or_d, &witness, VerifyFlags::default()).unwrap_err();
+        assert!(matches!(err.without_context(), Error::InvalidEcdsaSignature(pk) if *pk == pks[0]));
+        assert_eq!(run(&or_d, &witness, VerifyFlags::NONE).unwrap().len(), 1);
+
+        let or_d = format!("or_d(multi(1,{},{}),pk({}))", pks[2], pks[0], pks[1]);
+        let witness = [der_sigs[1].clone(), vec![], der_sigs[3].clone()];
+        let err = run(&or_d, &witness, VerifyFlags::default()).unwrap_err();
+        assert!(matches!(err.without_context(), Error::MultiSigEvaluationError));
+        assert_eq!(run(&or_d, &witness, VerifyFlags::NONE).unwrap().len(), 1);
+
+        // Signature whose R is not minimally encoded, as found before BIP 66
+        let sig = &der_sigs[0];
+        let mut lax_sig = vec![0x30, sig[1] + 1, 0x02, sig[3] + 1, 0x00];
+        lax_sig.extend_from_slice(&sig[4..]);
+        let pk = format!("pk({})", pks[0]);
+        let err = run(&pk, &[lax_sig.clone()], VerifyFlags::default()).unwrap_err();
+        assert!(matches!(err.without_context(), Error::EcdsaSig(_)));
+        let flags = VerifyFlags { der_sig: false, ..VerifyFlags::default() };
+        let key_sig = KeySigPair::Ecdsa(pks[0], ecdsa_sigs[0]);
+        assert_eq!(
+            run(&pk, &[lax_sig], flags).unwrap(),
+            vec![SatisfiedConstraint::PublicKey { key_sig }]
+        );
+    }
+
+    #[test]
+    fn trace() {
+        let (pks, der_sigs, ..) = setup_keys_sigs(1);
+        let ms = Miniscript::<bitcoin::PublicKey, Segwitv0>::from_str(&format!(
+            "and_v(v:pk({}),older(10))",
+            pks[0]
+        ))
+        .unwrap();
+        let spk = bitcoin::ScriptBuf::new_p2wsh(&ms.encode().wscript_hash());
+        let witness = Witness::from_slice(&[der_sigs[0].clone(), ms.encode().into_bytes()]);
+        let interpreter = Interpreter::from_txdata(
+            &spk,
+            bitcoin::Script::new(),
+            &witness,
+            Sequence::from_height(10),
+            absolute::LockTime::ZERO,
+        )
+        .unwrap();
+
+        let mut steps = vec![];
+        let constraints = interpreter
+            .iter_assume_sigs()
+            .with_trace(Box::new(|step| {
+                steps.push((step.fragment().to_string(), step.n_evaluated(), step.stack().to_vec()))
+            }))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(constraints.len(), 2);
+
+        let sig = Element::Push(&der_sigs[0]);
+        assert_eq!(
+            steps,
+            vec![
+                (ms.to_string(), 0, vec![sig]),
+                (format!("v:pk({})", pks[0]), 0, vec![sig]),
+                (format!("pk({})", pks[0]), 0, vec![sig]),
+                (format!("pk_k({})", pks[0]), 0, vec![sig]),
+                // verify pops the result of its child
+                (format!("v:pk({})", pks[0]), 1, vec![Element::Satisfied]),
+                ("older(10)".to_owned(), 0, vec![]),
+            ]
+        );
+    }
+
     // By design there is no support for parse a miniscript with BitcoinKey
     // because it does not implement FromStr
     fn no_checks_ms(ms: &str) -> Miniscript<BitcoinKey, NoChecks> {
@@ -3,12 +3,16 @@
 
 //! Interpreter stack
 
+use core::cmp;
+
 use bitcoin::blockdata::{opcodes, script};
 use bitcoin::hashes::{hash160, ripemd160, sha256, Hash};
 use bitcoin::{absolute, relative, Sequence};
 
 use super::error::PkEvalErrInner;
-use super::{verify_sersig, BitcoinKey, Error, HashLockType, KeySigPair, SatisfiedConstraint};
+use super::{
+    verify_sersig, BitcoinKey, Error, HashLockType, KeySigPair, SatisfiedConstraint, VerifyFlags,
+};
 use crate::hash256;
 use crate::miniscript::context::SigType;
 use crate::prelude::*;
@@ -72,11 +76,18 @@ impl<'txin> Element<'txin> {
 
 /// Stack Data structure representing the stack input to Miniscript. This Stack
 /// is created from the combination of ScriptSig and Witness stack.
+///
+/// Also keeps track of the number of elements at the bottom of the stack which
+/// are still the ones it was created from, rather than results of evaluated
+/// fragments.
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Default, Hash)]
-pub struct Stack<'txin>(Vec<Element<'txin>>);
+pub struct Stack<'txin>(Vec<Element<'txin>>, usize);
 
 impl<'txin> From<Vec<Element<'txin>>> for Stack<'txin> {
-    fn from(v: Vec<Element<'txin>>) -> Self { Stack(v) }
+    fn from(v: Vec<Element<'txin>>) -> Self {
+        let n_input = v.len();
+        Stack(v, n_input)
+    }
 }
 
 impl<'txin> Stack<'txin> {
@@ -87,18 +98,36 @@ impl<'txin> Stack<'txin> {
     pub fn len(&mut self) -> usize { self.0.len() }
 
     /// Removes the top stack element, if the stack is nonempty
-    pub fn pop(&mut self) -> Option<Element<'txin>> { self.0.pop() }
+    pub fn pop(&mut self) -> Option<Element<'txin>> {
+        let elem = self.0.pop();
+        self.1 = cmp::min(self.1, self.0.len());
+        elem
+    }
 
     /// Pushes an element onto the top of the stack
     pub fn push(&mut self, elem: Element<'txin>) { self.0.push(elem); }
 
     /// Returns a new stack representing the top `k` elements of the stack,
     /// removing these elements from the original
-    pub fn split_off(&mut self, k: usize) -> Vec<Element<'txin>> { self.0.split_off(k) }
+    pub fn split_off(&mut self, k: usize) -> Vec<Element<'txin>> {
+        self.1 = cmp::min(self.1, k);
+        self.0.split_off(k)
+    }
 
     /// Returns a reference to the top stack element, if the stack is nonempty
     pub fn last(&self) -> Option<&Element<'txin>> { self.0.last() }
 
+    /// The elements of the stack, from bottom to top
+    pub fn as_slice(&self) -> &[Element<'txin>] { &self.0 }
+
+    /// Number of elements at the bottom of the stack which have not been
+    /// consumed since its creation
+    pub fn n_input(&self) -> usize { self.1 }
+
+    /// Marks the top stack element as consumed, if it was not already, while
+    /// leaving it on the stack as the result of a fragment
+    pub fn consume_last(&mut self) { self.1 = cmp::min(self.1, self.0.len().saturating_sub(1)); }
+
     /// Helper function to evaluate a Pk Node which takes the
     /// top of the stack as input signature and validates it.
     /// Sat: If the signature witness is correct, 1 is pushed
@@ -109,6 +138,7 @@ impl<'txin> Stack<'txin> {
         &mut self,
         verify_sig: &mut Box<dyn FnMut(&KeySigPair) -> bool + 'intp>,
         pk: BitcoinKey,
+        flags: VerifyFlags,
     ) -> Option<Result<SatisfiedConstraint, Error>> {
         if let Some(sigser) = self.pop() {
             match sigser {
@@ -117,7 +147,7 @@ impl<'txin> Stack<'txin> {
                     None
                 }
                 Element::Push(sigser) => {
-                    let key_sig = verify_sersig(verify_sig, &pk, sigser);
+                    let key_sig = verify_sersig(verify_sig, &pk, sigser, flags);
                     match key_sig {
                         Ok(key_sig) => {
                             self.push(Element::Satisfied);
@@ -144,6 +174,7 @@ impl<'txin> Stack<'txin> {
         verify_sig: &mut Box<dyn FnMut(&KeySigPair) -> bool + 'intp>,
         pkh: hash160::Hash,
         sig_type: SigType,
+        flags: VerifyFlags,
     ) -> Option<Result<SatisfiedConstraint, Error>> {
         // Parse a bitcoin key from witness data slice depending on hash context
         // when we encounter a pkh(hash)
@@ -171,7 +202,7 @@ impl<'txin> Stack<'txin> {
                                 None
                             }
                             Element::Push(sigser) => {
-                                let key_sig = verify_sersig(verify_sig, &pk, sigser);
+                                let key_sig = verify_sersig(verify_sig, &pk, sigser, flags);
                                 match key_sig {
                                     Ok(key_sig) => {
                                         self.push(Element::Satisfied);
@@ -357,22 +388,20 @@ impl<'txin> Stack<'txin> {
         &mut self,
         verify_sig: &mut Box<dyn FnMut(&KeySigPair) -> bool + 'intp>,
         pk: &'intp BitcoinKey,
+        flags: VerifyFlags,
     ) -> Option<Result<SatisfiedConstraint, Error>> {
-        if let Some(witness_sig) = self.pop() {
-            if let Element::Push(sigser) = witness_sig {
-                let key_sig = verify_sersig(verify_sig, pk, sigser);
-                match key_sig {
-                    Ok(key_sig) => Some(Ok(SatisfiedConstraint::PublicKey { key_sig })),
-                    Err(..) => {
-                        self.push(witness_sig);
-                        None
-                    }
+        // Only pop the signature if it is for this key, so that it is not
+        // counted as consumed otherwise.
+        match self.last() {
+            Some(&Element::Push(sigser)) => match verify_sersig(verify_sig, pk, sigser, flags) {
+                Ok(key_sig) => {
+                    self.pop();
+                    Some(Ok(SatisfiedConstraint::PublicKey { key_sig }))
                 }
-            } else {
-                Some(Err(Error::UnexpectedStackBoolean))
-            }
-        } else {
-            Some(Err(Error::UnexpectedStackEnd))
+                Err(..) => None,
+            },
+            Some(_) => Some(Err(Error::UnexpectedStackBoolean)),
+            None => Some(Err(Error::UnexpectedStackEnd)),
         }
     }
 }
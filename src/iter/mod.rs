@@ -15,10 +15,12 @@ pub use tree::{
 };
 
 use crate::sync::Arc;
-use crate::{Miniscript, MiniscriptKey, ScriptContext, Terminal};
+use crate::{Extension, Miniscript, MiniscriptKey, ScriptContext, Terminal};
 
-impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext> TreeLike for &'a Miniscript<Pk, Ctx> {
-    type NaryChildren = &'a [Arc<Miniscript<Pk, Ctx>>];
+impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> TreeLike
+    for &'a Miniscript<Pk, Ctx, Ext>
+{
+    type NaryChildren = &'a [Arc<Miniscript<Pk, Ctx, Ext>>];
 
     fn nary_len(tc: &Self::NaryChildren) -> usize { tc.len() }
     fn nary_index(tc: Self::NaryChildren, idx: usize) -> Self { Arc::as_ref(&tc[idx]) }
@@ -27,7 +29,9 @@ impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext> TreeLike for &'a Miniscript<Pk,
         use Terminal::*;
         match self.node {
             PkK(..) | PkH(..) | RawPkH(..) | After(..) | Older(..) | Sha256(..) | Hash256(..)
-            | Ripemd160(..) | Hash160(..) | True | False | Multi(..) | MultiA(..) => Tree::Nullary,
+            | Ripemd160(..) | Hash160(..) | True | False | Multi(..) | MultiA(..) | Ext(..) => {
+                Tree::Nullary
+            }
             Alt(ref sub)
             | Swap(ref sub)
             | Check(ref sub)
@@ -47,8 +51,10 @@ impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext> TreeLike for &'a Miniscript<Pk,
     }
 }
 
-impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext> TreeLike for &'a Arc<Miniscript<Pk, Ctx>> {
-    type NaryChildren = &'a [Arc<Miniscript<Pk, Ctx>>];
+impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> TreeLike
+    for &'a Arc<Miniscript<Pk, Ctx, Ext>>
+{
+    type NaryChildren = &'a [Arc<Miniscript<Pk, Ctx, Ext>>];
 
     fn nary_len(tc: &Self::NaryChildren) -> usize { tc.len() }
     fn nary_index(tc: Self::NaryChildren, idx: usize) -> Self { &tc[idx] }
@@ -57,7 +63,9 @@ impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext> TreeLike for &'a Arc<Miniscript<
         use Terminal::*;
         match self.node {
             PkK(..) | PkH(..) | RawPkH(..) | After(..) | Older(..) | Sha256(..) | Hash256(..)
-            | Ripemd160(..) | Hash160(..) | True | False | Multi(..) | MultiA(..) => Tree::Nullary,
+            | Ripemd160(..) | Hash160(..) | True | False | Multi(..) | MultiA(..) | Ext(..) => {
+                Tree::Nullary
+            }
             Alt(ref sub)
             | Swap(ref sub)
             | Check(ref sub)
@@ -77,8 +85,10 @@ impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext> TreeLike for &'a Arc<Miniscript<
     }
 }
 
-impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext> TreeLike for &'a Terminal<Pk, Ctx> {
-    type NaryChildren = &'a [Arc<Miniscript<Pk, Ctx>>];
+impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> TreeLike
+    for &'a Terminal<Pk, Ctx, Ext>
+{
+    type NaryChildren = &'a [Arc<Miniscript<Pk, Ctx, Ext>>];
 
     fn nary_len(tc: &Self::NaryChildren) -> usize { tc.len() }
     fn nary_index(tc: Self::NaryChildren, idx: usize) -> Self { tc[idx].as_inner() }
@@ -87,7 +97,9 @@ impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext> TreeLike for &'a Terminal<Pk, Ct
         use Terminal::*;
         match self {
             PkK(..) | PkH(..) | RawPkH(..) | After(..) | Older(..) | Sha256(..) | Hash256(..)
-            | Ripemd160(..) | Hash160(..) | True | False | Multi(..) | MultiA(..) => Tree::Nullary,
+            | Ripemd160(..) | Hash160(..) | True | False | Multi(..) | MultiA(..) | Ext(..) => {
+                Tree::Nullary
+            }
             Alt(ref sub)
             | Swap(ref sub)
             | Check(ref sub)
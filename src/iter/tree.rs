@@ -171,41 +171,42 @@ impl<T: TreeLike> Iterator for PostOrderIter<T> {
     type Item = PostOrderIterItem<T>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let mut current = self.stack.pop()?;
-
-        if !current.processed {
-            current.processed = true;
-
-            // When we first encounter an item, it is completely unknown; it is
-            // nominally the next item to be yielded, but it might have children,
-            // and if so, they come first
-            let current_stack_idx = self.stack.len();
-            let n_children = current.elem.n_children();
-            self.stack.push(current);
-            for idx in (0..n_children).rev() {
-                self.stack.push(IterStackItem::unprocessed(
-                    self.stack[current_stack_idx].elem.nth_child(idx).unwrap(),
-                    Some(current_stack_idx),
-                ));
-            }
-            self.next()
-        } else {
-            // The second time we encounter an item, we have dealt with its children,
-            // updated the child indices for this item, and are now ready to yield it
-            // rather than putting it back in the stack.
-            //
-            // Before yielding though, we must the item's parent's child indices with
-            // this item's index.
-            if let Some(idx) = current.parent_stack_idx {
-                self.stack[idx].child_indices.push(self.index);
-            }
+        loop {
+            let mut current = self.stack.pop()?;
+
+            if !current.processed {
+                current.processed = true;
+
+                // When we first encounter an item, it is completely unknown; it is
+                // nominally the next item to be yielded, but it might have children,
+                // and if so, they come first
+                let current_stack_idx = self.stack.len();
+                let n_children = current.elem.n_children();
+                self.stack.push(current);
+                for idx in (0..n_children).rev() {
+                    self.stack.push(IterStackItem::unprocessed(
+                        self.stack[current_stack_idx].elem.nth_child(idx).unwrap(),
+                        Some(current_stack_idx),
+                    ));
+                }
+            } else {
+                // The second time we encounter an item, we have dealt with its children,
+                // updated the child indices for this item, and are now ready to yield it
+                // rather than putting it back in the stack.
+                //
+                // Before yielding though, we must the item's parent's child indices with
+                // this item's index.
+                if let Some(idx) = current.parent_stack_idx {
+                    self.stack[idx].child_indices.push(self.index);
+                }
 
-            self.index += 1;
-            Some(PostOrderIterItem {
-                node: current.elem,
-                index: self.index - 1,
-                child_indices: current.child_indices,
-            })
+                self.index += 1;
+                return Some(PostOrderIterItem {
+                    node: current.elem,
+                    index: self.index - 1,
+                    child_indices: current.child_indices,
+                });
+            }
         }
     }
 }
@@ -0,0 +1,1236 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! JSON AST
+//!
+//! Conversion of Miniscripts, descriptors and policies to and from a JSON
+//! representation of their abstract syntax tree, so that tools written in
+//! other languages can consume them without reimplementing the parser.
+//!
+//! Every node is a JSON object with a `kind` field naming the fragment, plus
+//! fields depending on the kind:
+//!
+//! | Kind | Fields |
+//! |---|---|
+//! | *Miniscript* | |
+//! | `false`, `true` | |
+//! | `pk_k`, `pk_h` | `key` |
+//! | `expr_raw_pkh`, `sha256`, `hash256`, `ripemd160`, `hash160` | `hash` |
+//! | `after`, `older` | `value`: the consensus encoding of the timelock |
+//! | `a`, `s`, `c`, `d`, `v`, `j`, `n` | `children`: the wrapped node |
+//! | `and_v`, `and_b`, `andor`, `or_b`, `or_c`, `or_d`, `or_i` | `children` |
+//! | `thresh` | `k`, `children` |
+//! | `multi`, `multi_a` | `k`, `keys` |
+//! | *Descriptors* | |
+//! | `bare` | `script`: a Miniscript |
+//! | `pkh`, `wpkh` | `key` |
+//! | `sh` | `inner`: a Miniscript, or a `sortedmulti`, `wpkh` or `wsh` node |
+//! | `wsh` | `inner`: a Miniscript or a `sortedmulti` node |
+//! | `sortedmulti` | `k`, `keys` |
+//! | `tr` | `internal_key`, `tree`: `null` or a Taproot tree node |
+//! | `leaf` | `script`: a Miniscript |
+//! | `raw_leaf` | `script`: the leaf script as hex, `version`: the leaf version |
+//! | `branch` | `left`, `right`: Taproot tree nodes |
+//! | `addr` | `address` |
+//! | `raw` | `script`: the script pubkey as hex |
+//! | *Policies* | |
+//! | `unsatisfiable`, `trivial` | |
+//! | `pk` | `key` |
+//! | `after`, `older`, `sha256`, `hash256`, `ripemd160`, `hash160` | as for Miniscript |
+//! | `and` | `children` (concrete policies only) |
+//! | `or` | `children`: objects with a `weight` and a `policy` (concrete policies only) |
+//! | `thresh` | `k`, `children` |
+//!
+//! Keys and hashes are strings, in the same format as in descriptors; `k`,
+//! `weight`, timelock values and leaf versions are numbers; and `children`
+//! and `keys` are arrays. Miniscript nodes also have a `type` field giving their type and
+//! type properties, e.g. `"B/onduesm"`. This is informational only and is
+//! ignored when importing, since the type is recomputed.
+//!
+//! Importing does the same checks as parsing the equivalent string, except
+//! that Miniscripts are not checked for sanity.
+//!
+
+use core::fmt;
+use core::str::FromStr;
+#[cfg(feature = "std")]
+use std::error;
+
+use bitcoin::taproot::LeafVersion;
+use bitcoin::ScriptBuf;
+use sync::Arc;
+
+use crate::descriptor::{
+    Addr, Bare, Pkh, Raw, RawTapLeaf, ShInner, SortedMultiVec, TapTree, Tr, Wpkh, WshInner,
+};
+use crate::iter::TreeLike;
+use crate::policy::{concrete, semantic};
+use crate::prelude::*;
+use crate::{
+    AbsLockTime, Descriptor, Error, FromStrKey, Miniscript, MiniscriptKey, ParseError, RelLockTime,
+    ScriptContext, Terminal, Threshold,
+};
+
+/// Maximum nesting depth of a Miniscript in a JSON document.
+///
+/// Unlike Miniscripts, [`Value`]s are dropped recursively, so their depth
+/// must be bounded.
+// https://github.com/sipa/miniscript/pull/5 for discussion on this number
+const MAX_MINISCRIPT_DEPTH: usize = 402;
+
+/// Maximum nesting depth of a JSON document.
+///
+/// Every Miniscript node uses two levels, one for the node object and one for
+/// its `children` array, and a descriptor needs a few more around the script.
+const MAX_DEPTH: usize = 2 * MAX_MINISCRIPT_DEPTH + 8;
+
+/// A JSON value.
+///
+/// Numbers are restricted to non-negative integers, which is all the AST
+/// uses. Object fields are kept in order.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Value {
+    /// `null`
+    Null,
+    /// `true` or `false`
+    Bool(bool),
+    /// A non-negative integer.
+    Number(u64),
+    /// A string.
+    String(String),
+    /// An array.
+    Array(Vec<Value>),
+    /// An object.
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Looks up a field of an object, returning `None` if the value is not an
+    /// object or has no such field.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match *self {
+            Value::Object(ref fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// The value as a string, if it is one.
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::String(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The value as a number, if it is one.
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Value::Number(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    /// The value as an array, if it is one.
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match *self {
+            Value::Array(ref arr) => Some(arr),
+            _ => None,
+        }
+    }
+}
+
+fn write_str_escaped(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for ch in s.chars() {
+        match ch {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            ch if (ch as u32) < 0x20 => write!(f, "\\u{:04x}", ch as u32)?,
+            ch => fmt::Write::write_char(f, ch)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Null => f.write_str("null"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::String(ref s) => write_str_escaped(f, s),
+            Value::Array(ref arr) => {
+                f.write_str("[")?;
+                for (i, v) in arr.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    v.fmt(f)?;
+                }
+                f.write_str("]")
+            }
+            Value::Object(ref fields) => {
+                f.write_str("{")?;
+                for (i, (k, v)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_str_escaped(f, k)?;
+                    f.write_str(":")?;
+                    v.fmt(f)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+impl FromStr for Value {
+    type Err = JsonError;
+
+    fn from_str(s: &str) -> Result<Self, JsonError> {
+        let mut parser = Parser { data: s.as_bytes(), pos: 0 };
+        let ret = parser.value()?;
+        parser.skip_whitespace();
+        if parser.pos < parser.data.len() {
+            return Err(JsonError::Syntax { pos: parser.pos });
+        }
+        Ok(ret)
+    }
+}
+
+/// An array or object whose contents are being parsed.
+enum Container {
+    Array(Vec<Value>),
+    /// An object, with the key of the value currently being parsed.
+    Object(Vec<(String, Value)>, String),
+}
+
+struct Parser<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.data.get(self.pos) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Result<u8, JsonError> {
+        self.skip_whitespace();
+        self.data
+            .get(self.pos)
+            .copied()
+            .ok_or(JsonError::Syntax { pos: self.pos })
+    }
+
+    fn expect(&mut self, b: u8) -> Result<(), JsonError> {
+        if self.peek()? != b {
+            return Err(JsonError::Syntax { pos: self.pos });
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn literal(&mut self, lit: &str, val: Value) -> Result<Value, JsonError> {
+        if !self.data[self.pos..].starts_with(lit.as_bytes()) {
+            return Err(JsonError::Syntax { pos: self.pos });
+        }
+        self.pos += lit.len();
+        Ok(val)
+    }
+
+    fn key(&mut self) -> Result<String, JsonError> {
+        if self.peek()? != b'"' {
+            return Err(JsonError::Syntax { pos: self.pos });
+        }
+        let key = self.string()?;
+        self.expect(b':')?;
+        Ok(key)
+    }
+
+    fn value(&mut self) -> Result<Value, JsonError> {
+        // Arrays and objects currently being parsed, innermost last. Parsing is
+        // done without recursion so that the depth limit, rather than the
+        // size of the stack, bounds the nesting.
+        let mut stack = vec![];
+        loop {
+            let mut value = match self.peek()? {
+                b'n' => self.literal("null", Value::Null)?,
+                b't' => self.literal("true", Value::Bool(true))?,
+                b'f' => self.literal("false", Value::Bool(false))?,
+                b'"' => Value::String(self.string()?),
+                b'0'..=b'9' | b'-' => self.number()?,
+                open @ (b'[' | b'{') => {
+                    self.pos += 1;
+                    if open == b'[' && self.peek()? == b']' {
+                        self.pos += 1;
+                        Value::Array(vec![])
+                    } else if open == b'{' && self.peek()? == b'}' {
+                        self.pos += 1;
+                        Value::Object(vec![])
+                    } else {
+                        if stack.len() >= MAX_DEPTH {
+                            return Err(JsonError::MaxRecursionDepthExceeded);
+                        }
+                        stack.push(match open {
+                            b'[' => Container::Array(vec![]),
+                            _ => Container::Object(vec![], self.key()?),
+                        });
+                        continue;
+                    }
+                }
+                _ => return Err(JsonError::Syntax { pos: self.pos }),
+            };
+
+            // Add the complete value to its parent, closing any containers
+            // which are thereby completed.
+            loop {
+                match stack.last_mut() {
+                    None => return Ok(value),
+                    Some(Container::Array(ref mut arr)) => {
+                        arr.push(value);
+                        match self.peek()? {
+                            b',' => self.pos += 1,
+                            b']' => {
+                                self.pos += 1;
+                                value = Value::Array(core::mem::take(arr));
+                                stack.pop();
+                                continue;
+                            }
+                            _ => return Err(JsonError::Syntax { pos: self.pos }),
+                        }
+                    }
+                    Some(Container::Object(ref mut fields, ref mut key)) => {
+                        fields.push((core::mem::take(key), value));
+                        match self.peek()? {
+                            b',' => {
+                                self.pos += 1;
+                                *key = self.key()?;
+                            }
+                            b'}' => {
+                                self.pos += 1;
+                                value = Value::Object(core::mem::take(fields));
+                                stack.pop();
+                                continue;
+                            }
+                            _ => return Err(JsonError::Syntax { pos: self.pos }),
+                        }
+                    }
+                }
+                break;
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, JsonError> {
+        let start = self.pos;
+        while let Some(b'0'..=b'9' | b'-' | b'+' | b'.' | b'e' | b'E') = self.data.get(self.pos) {
+            self.pos += 1;
+        }
+        let s = core::str::from_utf8(&self.data[start..self.pos]).expect("ASCII");
+        // Leading zeros are not valid JSON, but "0" itself is.
+        if s.len() > 1 && s.starts_with('0') {
+            return Err(JsonError::Syntax { pos: start });
+        }
+        if !s.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(JsonError::UnsupportedNumber { pos: start });
+        }
+        u64::from_str(s)
+            .map(Value::Number)
+            .map_err(|_| JsonError::UnsupportedNumber { pos: start })
+    }
+
+    fn hex4(&mut self) -> Result<u32, JsonError> {
+        let hex = self
+            .data
+            .get(self.pos..self.pos + 4)
+            .and_then(|h| core::str::from_utf8(h).ok())
+            .and_then(|h| u32::from_str_radix(h, 16).ok())
+            .ok_or(JsonError::Syntax { pos: self.pos })?;
+        self.pos += 4;
+        Ok(hex)
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.pos += 1; // opening quote
+        let mut ret = String::new();
+        loop {
+            let start = self.pos;
+            while let Some(&b) = self.data.get(self.pos) {
+                if b == b'"' || b == b'\\' || b < 0x20 {
+                    break;
+                }
+                self.pos += 1;
+            }
+            // Splitting at ASCII characters cannot break UTF-8.
+            ret.push_str(core::str::from_utf8(&self.data[start..self.pos]).expect("input is str"));
+
+            let pos = self.pos;
+            match self.data.get(pos) {
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(ret);
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let esc = *self.data.get(self.pos).ok_or(JsonError::Syntax { pos })?;
+                    self.pos += 1;
+                    ret.push(match esc {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let mut c = self.hex4()?;
+                            if (0xd800..0xdc00).contains(&c)
+                                && self.data[self.pos..].starts_with(b"\\u")
+                            {
+                                self.pos += 2;
+                                let lo = self.hex4()?;
+                                if !(0xdc00..0xe000).contains(&lo) {
+                                    return Err(JsonError::Syntax { pos });
+                                }
+                                c = 0x10000 + ((c - 0xd800) << 10) + (lo - 0xdc00);
+                            }
+                            char::from_u32(c).ok_or(JsonError::Syntax { pos })?
+                        }
+                        _ => return Err(JsonError::Syntax { pos }),
+                    });
+                }
+                _ => return Err(JsonError::Syntax { pos }),
+            }
+        }
+    }
+}
+
+/// An error parsing a JSON document or interpreting it as an AST.
+///
+/// Errors in the contents of a well-formed AST, such as invalid keys or
+/// Miniscripts which do not typecheck, are returned as the usual [`Error`]
+/// variants.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum JsonError {
+    /// The document is not valid JSON.
+    Syntax {
+        /// The byte position of the error.
+        pos: usize,
+    },
+    /// The document contains a number which is not a non-negative integer
+    /// fitting in 64 bits.
+    UnsupportedNumber {
+        /// The byte position of the number.
+        pos: usize,
+    },
+    /// The document is nested too deeply.
+    MaxRecursionDepthExceeded,
+    /// A node is not an object.
+    ExpectedObject,
+    /// A node is missing a required field.
+    MissingField(&'static str),
+    /// A field has the wrong JSON type.
+    WrongType {
+        /// The name of the field.
+        field: &'static str,
+        /// The expected type.
+        expected: &'static str,
+    },
+    /// A numeric field is out of range.
+    OutOfRange(&'static str),
+    /// A node has an unknown or unexpected kind.
+    UnknownKind(String),
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            JsonError::Syntax { pos } => write!(f, "invalid JSON at position {}", pos),
+            JsonError::UnsupportedNumber { pos } => {
+                write!(f, "JSON number at position {} is not a non-negative 64-bit integer", pos)
+            }
+            JsonError::MaxRecursionDepthExceeded => {
+                write!(f, "JSON is nested more deeply than the maximum of {}", MAX_DEPTH)
+            }
+            JsonError::ExpectedObject => f.write_str("JSON AST node is not an object"),
+            JsonError::MissingField(field) => write!(f, "JSON AST node has no `{}` field", field),
+            JsonError::WrongType { field, expected } => {
+                write!(f, "JSON AST field `{}` should be {}", field, expected)
+            }
+            JsonError::OutOfRange(field) => write!(f, "JSON AST field `{}` is out of range", field),
+            JsonError::UnknownKind(ref kind) => {
+                write!(f, "unexpected JSON AST node kind `{}`", kind)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for JsonError {
+    fn cause(&self) -> Option<&dyn error::Error> { None }
+}
+
+fn string<T: fmt::Display>(x: &T) -> Value { Value::String(x.to_string()) }
+
+fn keys<'a, Pk: MiniscriptKey + 'a, I: Iterator<Item = &'a Pk>>(keys: I) -> Value {
+    Value::Array(keys.map(string).collect())
+}
+
+fn node<const N: usize>(kind: &str, fields: [(&str, Value); N]) -> Value {
+    let mut obj = Vec::with_capacity(N + 1);
+    obj.push(("kind".to_owned(), Value::String(kind.to_owned())));
+    obj.extend(fields.into_iter().map(|(k, v)| (k.to_owned(), v)));
+    Value::Object(obj)
+}
+
+fn kind(value: &Value) -> Result<&str, JsonError> {
+    if !matches!(value, Value::Object(..)) {
+        return Err(JsonError::ExpectedObject);
+    }
+    field(value, "kind")?
+        .as_str()
+        .ok_or(JsonError::WrongType { field: "kind", expected: "a string" })
+}
+
+fn field<'a>(value: &'a Value, name: &'static str) -> Result<&'a Value, JsonError> {
+    value.get(name).ok_or(JsonError::MissingField(name))
+}
+
+fn num_field(value: &Value, name: &'static str) -> Result<u64, JsonError> {
+    field(value, name)?
+        .as_u64()
+        .ok_or(JsonError::WrongType { field: name, expected: "a number" })
+}
+
+fn usize_field(value: &Value, name: &'static str) -> Result<usize, JsonError> {
+    usize::try_from(num_field(value, name)?).map_err(|_| JsonError::OutOfRange(name))
+}
+
+fn u32_field(value: &Value, name: &'static str) -> Result<u32, JsonError> {
+    u32::try_from(num_field(value, name)?).map_err(|_| JsonError::OutOfRange(name))
+}
+
+fn array_field<'a>(value: &'a Value, name: &'static str) -> Result<&'a [Value], JsonError> {
+    field(value, name)?
+        .as_array()
+        .ok_or(JsonError::WrongType { field: name, expected: "an array" })
+}
+
+fn parse_field<T>(value: &Value, name: &'static str) -> Result<T, Error>
+where
+    T: FromStr,
+    <T as FromStr>::Err: crate::blanket_traits::StaticDebugAndDisplay,
+{
+    parse_str(field(value, name)?, name)
+}
+
+fn parse_str<T>(value: &Value, name: &'static str) -> Result<T, Error>
+where
+    T: FromStr,
+    <T as FromStr>::Err: crate::blanket_traits::StaticDebugAndDisplay,
+{
+    let s = value
+        .as_str()
+        .ok_or(JsonError::WrongType { field: name, expected: "a string" })?;
+    T::from_str(s).map_err(|e| Error::Parse(ParseError::box_from_str(e)))
+}
+
+fn script_field(value: &Value, name: &'static str) -> Result<ScriptBuf, Error> {
+    let hex: String = parse_field(value, name)?;
+    ScriptBuf::from_hex(&hex).map_err(|e| Error::Parse(ParseError::box_from_str(e)))
+}
+
+fn key_list<Pk: FromStrKey>(value: &Value) -> Result<Vec<Pk>, Error> {
+    array_field(value, "keys")?
+        .iter()
+        .map(|key| parse_str(key, "keys"))
+        .collect()
+}
+
+fn after(value: &Value) -> Result<AbsLockTime, Error> {
+    AbsLockTime::from_consensus(u32_field(value, "value")?).map_err(Error::AbsoluteLockTime)
+}
+
+fn older(value: &Value) -> Result<RelLockTime, Error> {
+    RelLockTime::from_consensus(u32_field(value, "value")?).map_err(Error::RelativeLockTime)
+}
+
+/// Builds a tree bottom-up from a JSON AST, without recursing.
+///
+/// `children` returns the child nodes of a node, and `convert` constructs a
+/// node from its JSON and its already-converted children.
+fn convert_tree<'a, T, C, F>(root: &'a Value, mut children: C, mut convert: F) -> Result<T, Error>
+where
+    C: FnMut(&'a Value) -> Result<Vec<&'a Value>, Error>,
+    F: FnMut(&'a Value, Vec<T>) -> Result<T, Error>,
+{
+    // In reverse pre-order every node comes after its children, with its
+    // first child on top of the stack.
+    let mut pre_order = vec![];
+    let mut to_visit = vec![root];
+    while let Some(value) = to_visit.pop() {
+        let subs = children(value)?;
+        pre_order.push((value, subs.len()));
+        to_visit.extend(subs.into_iter().rev());
+    }
+
+    let mut stack = vec![];
+    for (value, n) in pre_order.into_iter().rev() {
+        let subs = (0..n).map(|_| stack.pop().unwrap()).collect();
+        stack.push(convert(value, subs)?);
+    }
+    assert_eq!(stack.len(), 1);
+    Ok(stack.pop().unwrap())
+}
+
+impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
+    /// Converts the Miniscript to a [JSON AST](crate::json).
+    pub fn to_json(&self) -> Value {
+        let mut stack: Vec<Value> = vec![];
+        for item in self.post_order_iter() {
+            let children = stack.split_off(stack.len() - item.child_indices.len());
+            let children = || ("children", Value::Array(children));
+            let mut value = match item.node.node {
+                Terminal::False => node("false", []),
+                Terminal::True => node("true", []),
+                Terminal::PkK(ref pk) => node("pk_k", [("key", string(pk))]),
+                Terminal::PkH(ref pk) => node("pk_h", [("key", string(pk))]),
+                Terminal::RawPkH(ref hash) => node("expr_raw_pkh", [("hash", string(hash))]),
+                Terminal::After(t) => {
+                    node("after", [("value", Value::Number(t.to_consensus_u32().into()))])
+                }
+                Terminal::Older(t) => {
+                    node("older", [("value", Value::Number(t.to_consensus_u32().into()))])
+                }
+                Terminal::Sha256(ref h) => node("sha256", [("hash", string(h))]),
+                Terminal::Hash256(ref h) => node("hash256", [("hash", string(h))]),
+                Terminal::Ripemd160(ref h) => node("ripemd160", [("hash", string(h))]),
+                Terminal::Hash160(ref h) => node("hash160", [("hash", string(h))]),
+                Terminal::Alt(..) => node("a", [children()]),
+                Terminal::Swap(..) => node("s", [children()]),
+                Terminal::Check(..) => node("c", [children()]),
+                Terminal::DupIf(..) => node("d", [children()]),
+                Terminal::Verify(..) => node("v", [children()]),
+                Terminal::NonZero(..) => node("j", [children()]),
+                Terminal::ZeroNotEqual(..) => node("n", [children()]),
+                Terminal::AndV(..) => node("and_v", [children()]),
+                Terminal::AndB(..) => node("and_b", [children()]),
+                Terminal::AndOr(..) => node("andor", [children()]),
+                Terminal::OrB(..) => node("or_b", [children()]),
+                Terminal::OrD(..) => node("or_d", [children()]),
+                Terminal::OrC(..) => node("or_c", [children()]),
+                Terminal::OrI(..) => node("or_i", [children()]),
+                Terminal::Thresh(ref thresh) => {
+                    node("thresh", [("k", Value::Number(thresh.k() as u64)), children()])
+                }
+                Terminal::Multi(ref thresh) => node(
+                    "multi",
+                    [
+                        ("k", Value::Number(thresh.k() as u64)),
+                        ("keys", keys(thresh.iter())),
+                    ],
+                ),
+                Terminal::MultiA(ref thresh) => node(
+                    "multi_a",
+                    [
+                        ("k", Value::Number(thresh.k() as u64)),
+                        ("keys", keys(thresh.iter())),
+                    ],
+                ),
+                Terminal::Ext(e) => match e {},
+            };
+            if let Value::Object(ref mut fields) = value {
+                fields.insert(1, ("type".to_owned(), string(&item.node.ty)));
+            }
+            stack.push(value);
+        }
+        assert_eq!(stack.len(), 1);
+        stack.pop().unwrap()
+    }
+}
+
+impl<Pk: FromStrKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
+    /// Constructs a Miniscript from a [JSON AST](crate::json).
+    ///
+    /// Every node is typechecked, and must satisfy the consensus rules of the
+    /// script context, but as with [`Miniscript::from_str_insane`] the result
+    /// is not checked for sanity.
+    pub fn from_json(value: &Value) -> Result<Self, Error> {
+        convert_tree(
+            value,
+            |value| match kind(value)? {
+                "a" | "s" | "c" | "d" | "v" | "j" | "n" | "and_v" | "and_b" | "andor" | "or_b"
+                | "or_c" | "or_d" | "or_i" | "thresh" => {
+                    Ok(array_field(value, "children")?.iter().collect())
+                }
+                _ => Ok(vec![]),
+            },
+            |value, subs: Vec<Arc<Self>>| {
+                let expected = match kind(value)? {
+                    "a" | "s" | "c" | "d" | "v" | "j" | "n" => Some((1, "an array of 1 node")),
+                    "and_v" | "and_b" | "or_b" | "or_c" | "or_d" | "or_i" => {
+                        Some((2, "an array of 2 nodes"))
+                    }
+                    "andor" => Some((3, "an array of 3 nodes")),
+                    _ => None,
+                };
+                if let Some((n, expected)) = expected {
+                    if subs.len() != n {
+                        return Err(JsonError::WrongType { field: "children", expected }.into());
+                    }
+                }
+                let mut subs = subs.into_iter();
+                let mut child = || subs.next().unwrap();
+                let term = match kind(value)? {
+                    "false" => Terminal::False,
+                    "true" => Terminal::True,
+                    "pk_k" => Terminal::PkK(parse_field(value, "key")?),
+                    "pk_h" => Terminal::PkH(parse_field(value, "key")?),
+                    "expr_raw_pkh" => Terminal::RawPkH(parse_field(value, "hash")?),
+                    "after" => Terminal::After(after(value)?),
+                    "older" => Terminal::Older(older(value)?),
+                    "sha256" => Terminal::Sha256(parse_field(value, "hash")?),
+                    "hash256" => Terminal::Hash256(parse_field(value, "hash")?),
+                    "ripemd160" => Terminal::Ripemd160(parse_field(value, "hash")?),
+                    "hash160" => Terminal::Hash160(parse_field(value, "hash")?),
+                    "a" => Terminal::Alt(child()),
+                    "s" => Terminal::Swap(child()),
+                    "c" => Terminal::Check(child()),
+                    "d" => Terminal::DupIf(child()),
+                    "v" => Terminal::Verify(child()),
+                    "j" => Terminal::NonZero(child()),
+                    "n" => Terminal::ZeroNotEqual(child()),
+                    "and_v" => Terminal::AndV(child(), child()),
+                    "and_b" => Terminal::AndB(child(), child()),
+                    "andor" => Terminal::AndOr(child(), child(), child()),
+                    "or_b" => Terminal::OrB(child(), child()),
+                    "or_c" => Terminal::OrC(child(), child()),
+                    "or_d" => Terminal::OrD(child(), child()),
+                    "or_i" => Terminal::OrI(child(), child()),
+                    "thresh" => Terminal::Thresh(
+                        Threshold::new(usize_field(value, "k")?, subs.collect())
+                            .map_err(Error::Threshold)?,
+                    ),
+                    "multi" => Terminal::Multi(
+                        Threshold::new(usize_field(value, "k")?, key_list(value)?)
+                            .map_err(Error::Threshold)?,
+                    ),
+                    "multi_a" => Terminal::MultiA(
+                        Threshold::new(usize_field(value, "k")?, key_list(value)?)
+                            .map_err(Error::Threshold)?,
+                    ),
+                    x => return Err(JsonError::UnknownKind(x.to_owned()).into()),
+                };
+                Miniscript::from_ast(term).map(Arc::new)
+            },
+        )
+        .map(|ms| Arc::try_unwrap(ms).unwrap_or_else(|ms| Miniscript::clone(&ms)))
+    }
+}
+
+fn sortedmulti_to_json<Pk: MiniscriptKey, Ctx: ScriptContext>(
+    smv: &SortedMultiVec<Pk, Ctx>,
+) -> Value {
+    node(
+        "sortedmulti",
+        [
+            ("k", Value::Number(smv.k() as u64)),
+            ("keys", keys(smv.pks().iter())),
+        ],
+    )
+}
+
+fn tap_tree_to_json<Pk: MiniscriptKey>(tree: &TapTree<Pk>) -> Value {
+    match *tree {
+        TapTree::Tree { ref left, ref right, .. } => node(
+            "branch",
+            [
+                ("left", tap_tree_to_json(left)),
+                ("right", tap_tree_to_json(right)),
+            ],
+        ),
+        TapTree::Leaf(ref ms) => node("leaf", [("script", ms.to_json())]),
+        TapTree::RawLeaf(ref raw) => node(
+            "raw_leaf",
+            [
+                ("script", Value::String(format!("{:x}", raw.script()))),
+                ("version", Value::Number(raw.version().to_consensus().into())),
+            ],
+        ),
+    }
+}
+
+fn tap_tree_from_json<Pk: FromStrKey>(value: &Value) -> Result<TapTree<Pk>, Error> {
+    convert_tree(
+        value,
+        |value| match kind(value)? {
+            "branch" => Ok(vec![field(value, "left")?, field(value, "right")?]),
+            _ => Ok(vec![]),
+        },
+        |value, subs: Vec<TapTree<Pk>>| match kind(value)? {
+            "leaf" => Ok(TapTree::Leaf(Arc::new(Miniscript::from_json(field(value, "script")?)?))),
+            "raw_leaf" => {
+                let version = u8::try_from(num_field(value, "version")?)
+                    .map_err(|_| JsonError::OutOfRange("version"))?;
+                let version = LeafVersion::from_consensus(version)
+                    .map_err(|e| Error::Parse(ParseError::box_from_str(e)))?;
+                Ok(TapTree::RawLeaf(RawTapLeaf::new(script_field(value, "script")?, version)))
+            }
+            "branch" => {
+                let mut subs = subs.into_iter();
+                Ok(TapTree::combine(subs.next().unwrap(), subs.next().unwrap()))
+            }
+            x => Err(JsonError::UnknownKind(x.to_owned()).into()),
+        },
+    )
+}
+
+impl<Pk: MiniscriptKey> Descriptor<Pk> {
+    /// Converts the descriptor to a [JSON AST](crate::json).
+    pub fn to_json(&self) -> Value {
+        match *self {
+            Descriptor::Bare(ref bare) => node("bare", [("script", bare.as_inner().to_json())]),
+            Descriptor::Pkh(ref pkh) => node("pkh", [("key", string(pkh.as_inner()))]),
+            Descriptor::Wpkh(ref wpkh) => node("wpkh", [("key", string(wpkh.as_inner()))]),
+            Descriptor::Sh(ref sh) => {
+                let inner = match *sh.as_inner() {
+                    ShInner::Ms(ref ms) => ms.to_json(),
+                    ShInner::SortedMulti(ref smv) => sortedmulti_to_json(smv),
+                    ShInner::Wpkh(ref wpkh) => node("wpkh", [("key", string(wpkh.as_inner()))]),
+                    ShInner::Wsh(ref wsh) => {
+                        let inner = match *wsh.as_inner() {
+                            WshInner::Ms(ref ms) => ms.to_json(),
+                            WshInner::SortedMulti(ref smv) => sortedmulti_to_json(smv),
+                        };
+                        node("wsh", [("inner", inner)])
+                    }
+                };
+                node("sh", [("inner", inner)])
+            }
+            Descriptor::Wsh(ref wsh) => {
+                let inner = match *wsh.as_inner() {
+                    WshInner::Ms(ref ms) => ms.to_json(),
+                    WshInner::SortedMulti(ref smv) => sortedmulti_to_json(smv),
+                };
+                node("wsh", [("inner", inner)])
+            }
+            Descriptor::Tr(ref tr) => node(
+                "tr",
+                [
+                    ("internal_key", string(tr.internal_key())),
+                    ("tree", tr.tap_tree().as_ref().map_or(Value::Null, tap_tree_to_json)),
+                ],
+            ),
+            Descriptor::Addr(ref addr) => {
+                node("addr", [("address", string(addr.as_inner().assume_checked_ref()))])
+            }
+            Descriptor::Raw(ref raw) => {
+                node("raw", [("script", Value::String(format!("{:x}", raw.as_inner())))])
+            }
+        }
+    }
+}
+
+impl<Pk: FromStrKey> Descriptor<Pk> {
+    /// Constructs a descriptor from a [JSON AST](crate::json).
+    ///
+    /// The same checks are done as when constructing the descriptor from its
+    /// parts, but as with [`Miniscript::from_json`] the result is not checked
+    /// for sanity.
+    pub fn from_json(value: &Value) -> Result<Self, Error> {
+        match kind(value)? {
+            "bare" => {
+                Ok(Descriptor::Bare(Bare::new(Miniscript::from_json(field(value, "script")?)?)?))
+            }
+            "pkh" => Ok(Descriptor::Pkh(Pkh::new(parse_field(value, "key")?)?)),
+            "wpkh" => Ok(Descriptor::Wpkh(Wpkh::new(parse_field(value, "key")?)?)),
+            "sh" => {
+                let inner = field(value, "inner")?;
+                match kind(inner)? {
+                    "sortedmulti" => {
+                        Descriptor::new_sh_sortedmulti(usize_field(inner, "k")?, key_list(inner)?)
+                    }
+                    "wpkh" => Descriptor::new_sh_wpkh(parse_field(inner, "key")?),
+                    "wsh" => {
+                        let inner = field(inner, "inner")?;
+                        match kind(inner)? {
+                            "sortedmulti" => Descriptor::new_sh_wsh_sortedmulti(
+                                usize_field(inner, "k")?,
+                                key_list(inner)?,
+                            ),
+                            _ => Descriptor::new_sh_wsh(Miniscript::from_json(inner)?),
+                        }
+                    }
+                    _ => Descriptor::new_sh(Miniscript::from_json(inner)?),
+                }
+            }
+            "wsh" => {
+                let inner = field(value, "inner")?;
+                match kind(inner)? {
+                    "sortedmulti" => {
+                        Descriptor::new_wsh_sortedmulti(usize_field(inner, "k")?, key_list(inner)?)
+                    }
+                    _ => Descriptor::new_wsh(Miniscript::from_json(inner)?),
+                }
+            }
+            "tr" => {
+                let internal_key = parse_field(value, "internal_key")?;
+                let tree = match *field(value, "tree")? {
+                    Value::Null => None,
+                    ref tree => Some(tap_tree_from_json(tree)?),
+                };
+                Ok(Descriptor::Tr(Tr::new(internal_key, tree)?))
+            }
+            "addr" => Ok(Descriptor::Addr(Addr::new(parse_field(value, "address")?))),
+            "raw" => Ok(Descriptor::Raw(Raw::new(script_field(value, "script")?))),
+            x => Err(JsonError::UnknownKind(x.to_owned()).into()),
+        }
+    }
+}
+
+/// Converts the leaves common to both kinds of policy.
+macro_rules! policy_leaf_to_json {
+    ($policy:expr, $($extra:tt)*) => {
+        match *$policy {
+            Policy::Unsatisfiable => node("unsatisfiable", []),
+            Policy::Trivial => node("trivial", []),
+            Policy::Key(ref pk) => node("pk", [("key", string(pk))]),
+            Policy::After(t) => node("after", [("value", Value::Number(t.to_consensus_u32().into()))]),
+            Policy::Older(t) => node("older", [("value", Value::Number(t.to_consensus_u32().into()))]),
+            Policy::Sha256(ref h) => node("sha256", [("hash", string(h))]),
+            Policy::Hash256(ref h) => node("hash256", [("hash", string(h))]),
+            Policy::Ripemd160(ref h) => node("ripemd160", [("hash", string(h))]),
+            Policy::Hash160(ref h) => node("hash160", [("hash", string(h))]),
+            $($extra)*
+        }
+    };
+}
+
+/// Converts the leaves common to both kinds of policy.
+macro_rules! policy_leaf_from_json {
+    ($value:expr, $($extra:tt)*) => {
+        match kind($value)? {
+            "unsatisfiable" => Policy::Unsatisfiable,
+            "trivial" => Policy::Trivial,
+            "pk" => Policy::Key(parse_field($value, "key")?),
+            "after" => Policy::After(after($value)?),
+            "older" => Policy::Older(older($value)?),
+            "sha256" => Policy::Sha256(parse_field($value, "hash")?),
+            "hash256" => Policy::Hash256(parse_field($value, "hash")?),
+            "ripemd160" => Policy::Ripemd160(parse_field($value, "hash")?),
+            "hash160" => Policy::Hash160(parse_field($value, "hash")?),
+            $($extra)*
+            x => return Err(JsonError::UnknownKind(x.to_owned()).into()),
+        }
+    };
+}
+
+impl<Pk: MiniscriptKey> semantic::Policy<Pk> {
+    /// Converts the policy to a [JSON AST](crate::json).
+    pub fn to_json(&self) -> Value {
+        use semantic::Policy;
+
+        let mut stack: Vec<Value> = vec![];
+        for item in self.post_order_iter() {
+            let children = stack.split_off(stack.len() - item.child_indices.len());
+            stack.push(policy_leaf_to_json!(item.node,
+                Policy::Thresh(ref thresh) => node(
+                    "thresh",
+                    [("k", Value::Number(thresh.k() as u64)), ("children", Value::Array(children))],
+                ),
+            ));
+        }
+        assert_eq!(stack.len(), 1);
+        stack.pop().unwrap()
+    }
+}
+
+impl<Pk: FromStrKey> semantic::Policy<Pk> {
+    /// Constructs a policy from a [JSON AST](crate::json).
+    pub fn from_json(value: &Value) -> Result<Self, Error> {
+        use semantic::Policy;
+
+        convert_tree(
+            value,
+            |value| match kind(value)? {
+                "thresh" => Ok(array_field(value, "children")?.iter().collect()),
+                _ => Ok(vec![]),
+            },
+            |value, subs| {
+                Ok(Arc::new(policy_leaf_from_json!(value,
+                    "thresh" => Policy::Thresh(
+                        Threshold::new(usize_field(value, "k")?, subs).map_err(Error::Threshold)?,
+                    ),
+                )))
+            },
+        )
+        .map(|policy| Arc::try_unwrap(policy).unwrap_or_else(|policy| Policy::clone(&policy)))
+    }
+}
+
+impl<Pk: MiniscriptKey> concrete::Policy<Pk> {
+    /// Converts the policy to a [JSON AST](crate::json).
+    pub fn to_json(&self) -> Value {
+        use concrete::Policy;
+
+        let mut stack: Vec<Value> = vec![];
+        for item in self.post_order_iter() {
+            let children = stack.split_off(stack.len() - item.child_indices.len());
+            stack.push(policy_leaf_to_json!(item.node,
+                Policy::And(..) => node("and", [("children", Value::Array(children))]),
+                Policy::Or(ref subs) => {
+                    let children = subs
+                        .iter()
+                        .zip(children)
+                        .map(|((weight, _), policy)| {
+                            Value::Object(vec![
+                                ("weight".to_owned(), Value::Number(*weight as u64)),
+                                ("policy".to_owned(), policy),
+                            ])
+                        })
+                        .collect();
+                    node("or", [("children", Value::Array(children))])
+                }
+                Policy::Thresh(ref thresh) => node(
+                    "thresh",
+                    [("k", Value::Number(thresh.k() as u64)), ("children", Value::Array(children))],
+                ),
+            ));
+        }
+        assert_eq!(stack.len(), 1);
+        stack.pop().unwrap()
+    }
+}
+
+impl<Pk: FromStrKey> concrete::Policy<Pk> {
+    /// Constructs a policy from a [JSON AST](crate::json).
+    ///
+    /// As when parsing a policy from a string, `and` and `or` must have exactly
+    /// two children, and timelocks must not be mixed in a way that cannot be
+    /// satisfied.
+    pub fn from_json(value: &Value) -> Result<Self, Error> {
+        use concrete::{Policy, PolicyError};
+
+        let policy = convert_tree(
+            value,
+            |value| match kind(value)? {
+                "and" | "thresh" => Ok(array_field(value, "children")?.iter().collect()),
+                "or" => array_field(value, "children")?
+                    .iter()
+                    .map(|child| field(child, "policy").map_err(Error::from))
+                    .collect(),
+                _ => Ok(vec![]),
+            },
+            |value, subs| {
+                Ok(Arc::new(policy_leaf_from_json!(value,
+                    "and" => {
+                        if subs.len() != 2 {
+                            return Err(Error::ConcretePolicy(PolicyError::NonBinaryArgAnd));
+                        }
+                        Policy::And(subs)
+                    }
+                    "or" => {
+                        if subs.len() != 2 {
+                            return Err(Error::ConcretePolicy(PolicyError::NonBinaryArgOr));
+                        }
+                        let weights = array_field(value, "children")?
+                            .iter()
+                            .map(|child| usize_field(child, "weight"))
+                            .collect::<Result<Vec<_>, _>>()?;
+                        Policy::Or(weights.into_iter().zip(subs).collect())
+                    }
+                    "thresh" => Policy::Thresh(
+                        Threshold::new(usize_field(value, "k")?, subs).map_err(Error::Threshold)?,
+                    ),
+                )))
+            },
+        )?;
+        let policy = Arc::try_unwrap(policy).unwrap_or_else(|policy| Policy::clone(&policy));
+        policy.check_timelocks().map_err(Error::ConcretePolicy)?;
+        Ok(policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DescriptorPublicKey, Segwitv0, Tap};
+
+    fn json(s: &str) -> Value { Value::from_str(s).unwrap() }
+
+    #[test]
+    fn value_roundtrip() {
+        let s = r#"{"a":[1,true,false,null,"x\"y\\z\n\u0001"],"b":{},"c":[]}"#;
+        let value = json(s);
+        assert_eq!(value.to_string(), s);
+        assert_eq!(value.get("a").and_then(Value::as_array).map(<[_]>::len), Some(5));
+
+        assert_eq!(json(" [ 0 , 18446744073709551615 ] ").to_string(), "[0,18446744073709551615]");
+        assert_eq!(json(r#""😀\/""#), Value::String("\u{1f600}/".to_owned()));
+
+        for (s, err) in [
+            ("", JsonError::Syntax { pos: 0 }),
+            ("[1,]", JsonError::Syntax { pos: 3 }),
+            ("{\"a\" 1}", JsonError::Syntax { pos: 5 }),
+            ("[1] x", JsonError::Syntax { pos: 4 }),
+            ("\"abc", JsonError::Syntax { pos: 4 }),
+            ("01", JsonError::Syntax { pos: 0 }),
+            ("-1", JsonError::UnsupportedNumber { pos: 0 }),
+            ("1.5", JsonError::UnsupportedNumber { pos: 0 }),
+            ("18446744073709551616", JsonError::UnsupportedNumber { pos: 0 }),
+        ] {
+            assert_eq!(Value::from_str(s), Err(err), "{}", s);
+        }
+        let deep = "[".repeat(100_000);
+        assert_eq!(Value::from_str(&deep), Err(JsonError::MaxRecursionDepthExceeded));
+    }
+
+    #[test]
+    fn miniscript() {
+        let ms = Miniscript::<String, Segwitv0>::from_str("and_v(v:pk(A),older(144))").unwrap();
+        let value = ms.to_json();
+        assert_eq!(
+            value.to_string(),
+            r#"{"kind":"and_v","type":"B/onfsm","children":[{"kind":"v","type":"V/onfsm","children":[{"kind":"c","type":"B/onduesm","children":[{"kind":"pk_k","type":"K/onduesm","key":"A"}]}]},{"kind":"older","type":"B/zfm","value":144}]}"#
+        );
+
+        for s in [
+            "andor(multi(2,A,B,C),sha256(H),and_b(pkh(D),a:after(500000000)))",
+            "thresh(2,pk(A),s:pk(B),sln:after(10),a:hash160(H))",
+            "or_i(and_v(v:ripemd160(H),pk(A)),j:and_v(vn:hash256(H),pk(B)))",
+            "t:or_c(pk(A),v:0)",
+        ] {
+            let ms = Miniscript::<String, Segwitv0>::from_str_insane(s).unwrap();
+            let value = json(&ms.to_json().to_string());
+            assert_eq!(Miniscript::<String, Segwitv0>::from_json(&value).unwrap(), ms);
+        }
+
+        // The `type` field is optional and ignored.
+        let value = json(r#"{"kind":"c","type":"X","children":[{"kind":"pk_k","key":"A"}]}"#);
+        assert_eq!(
+            Miniscript::<String, Tap>::from_json(&value)
+                .unwrap()
+                .to_string(),
+            "pk(A)"
+        );
+
+        for (s, err) in [
+            ("[]", JsonError::ExpectedObject),
+            (r#"{"key":"A"}"#, JsonError::MissingField("kind")),
+            (r#"{"kind":"pk"}"#, JsonError::UnknownKind("pk".to_owned())),
+            (
+                r#"{"kind":"older","value":"1"}"#,
+                JsonError::WrongType { field: "value", expected: "a number" },
+            ),
+            (r#"{"kind":"older","value":4294967296}"#, JsonError::OutOfRange("value")),
+            (
+                r#"{"kind":"c","children":[]}"#,
+                JsonError::WrongType { field: "children", expected: "an array of 1 node" },
+            ),
+        ] {
+            match Miniscript::<String, Segwitv0>::from_json(&json(s)) {
+                Err(Error::Json(e)) => assert_eq!(e, err, "{}", s),
+                res => panic!("{}: unexpected result {:?}", s, res),
+            }
+        }
+
+        // Typechecking and context rules are applied.
+        let value = json(r#"{"kind":"v","children":[{"kind":"pk_k","key":"A"}]}"#);
+        assert!(matches!(
+            Miniscript::<String, Segwitv0>::from_json(&value),
+            Err(Error::TypeCheck(_))
+        ));
+        let value = Miniscript::<String, Tap>::from_str("multi_a(1,A,B)")
+            .unwrap()
+            .to_json();
+        assert!(matches!(
+            Miniscript::<String, Segwitv0>::from_json(&value),
+            Err(Error::ContextError(_))
+        ));
+    }
+
+    #[test]
+    fn descriptor() {
+        for s in [
+            "pkh(020000000000000000000000000000000000000000000000000000000000000002)",
+            "wpkh(xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8/1/*)",
+            "sh(wpkh(020000000000000000000000000000000000000000000000000000000000000002))",
+            "sh(sortedmulti(1,020000000000000000000000000000000000000000000000000000000000000002,030000000000000000000000000000000000000000000000000000000000000003))",
+            "sh(wsh(sortedmulti(1,020000000000000000000000000000000000000000000000000000000000000002,030000000000000000000000000000000000000000000000000000000000000003)))",
+            "wsh(sortedmulti(1,020000000000000000000000000000000000000000000000000000000000000002,030000000000000000000000000000000000000000000000000000000000000003))",
+            "sh(pk(020000000000000000000000000000000000000000000000000000000000000002))",
+            "sh(wsh(pk(020000000000000000000000000000000000000000000000000000000000000002)))",
+            "wsh(or_d(pk(020000000000000000000000000000000000000000000000000000000000000002),older(1)))",
+            "pk(020000000000000000000000000000000000000000000000000000000000000002)",
+            "tr(020000000000000000000000000000000000000000000000000000000000000002)",
+            "tr(020000000000000000000000000000000000000000000000000000000000000002,{pk(030000000000000000000000000000000000000000000000000000000000000003),{0,pk(020000000000000000000000000000000000000000000000000000000000000002)}})",
+
+            "addr(bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4)",
+            "raw(6a0568656c6c6f)",
+            "tr(020000000000000000000000000000000000000000000000000000000000000002,{raw_leaf(51),raw_leaf(52,c2)})",
+        ] {
+            let desc = Descriptor::<DescriptorPublicKey>::from_str(s).unwrap();
+            let value = json(&desc.to_json().to_string());
+            assert_eq!(Descriptor::<DescriptorPublicKey>::from_json(&value).unwrap(), desc);
+        }
+
+        let desc = Descriptor::<String>::from_str("tr(K,{pk(A),pk(B)})").unwrap();
+        assert_eq!(
+            desc.to_json().to_string(),
+            r#"{"kind":"tr","internal_key":"K","tree":{"kind":"branch","left":{"kind":"leaf","script":{"kind":"c","type":"B/onduesm","children":[{"kind":"pk_k","type":"K/onduesm","key":"A"}]}},"right":{"kind":"leaf","script":{"kind":"c","type":"B/onduesm","children":[{"kind":"pk_k","type":"K/onduesm","key":"B"}]}}}}"#
+        );
+    }
+
+    #[test]
+    fn policy() {
+        for s in [
+            "or(99@pk(A),1@and(pk(B),older(10)))",
+            "thresh(2,pk(A),sha256(H),after(100))",
+            "and(UNSATISFIABLE,or(TRIVIAL,hash160(H)))",
+        ] {
+            let policy = concrete::Policy::<String>::from_str(s).unwrap();
+            let value = json(&policy.to_json().to_string());
+            assert_eq!(concrete::Policy::<String>::from_json(&value).unwrap(), policy);
+
+            let policy = semantic::Policy::<String>::from_str(
+                s.replace("99@", "").replace("1@", "").as_str(),
+            );
+            if let Ok(policy) = policy {
+                let value = json(&policy.to_json().to_string());
+                assert_eq!(semantic::Policy::<String>::from_json(&value).unwrap(), policy);
+            }
+        }
+
+        let policy = concrete::Policy::<String>::from_str("or(3@pk(A),pk(B))").unwrap();
+        assert_eq!(
+            policy.to_json().to_string(),
+            r#"{"kind":"or","children":[{"weight":3,"policy":{"kind":"pk","key":"A"}},{"weight":1,"policy":{"kind":"pk","key":"B"}}]}"#
+        );
+        let policy = semantic::Policy::<String>::from_str("or(pk(A),pk(B))").unwrap();
+        assert_eq!(
+            policy.to_json().to_string(),
+            r#"{"kind":"thresh","k":1,"children":[{"kind":"pk","key":"A"},{"kind":"pk","key":"B"}]}"#
+        );
+
+        let value = json(r#"{"kind":"and","children":[{"kind":"pk","key":"A"}]}"#);
+        assert!(matches!(
+            concrete::Policy::<String>::from_json(&value),
+            Err(Error::ConcretePolicy(concrete::PolicyError::NonBinaryArgAnd))
+        ));
+        let value = json(
+            r#"{"kind":"and","children":[{"kind":"after","value":100},{"kind":"after","value":500000001}]}"#,
+        );
+        assert!(matches!(
+            concrete::Policy::<String>::from_json(&value),
+            Err(Error::ConcretePolicy(concrete::PolicyError::HeightTimelockCombination))
+        ));
+        assert!(matches!(semantic::Policy::<String>::from_json(&value), Err(Error::Json(_))));
+    }
+}
@@ -110,23 +110,32 @@ mod macros;
 #[macro_use]
 mod pub_macros;
 
+#[cfg(feature = "apo")]
+pub mod apo;
 #[cfg(bench)]
 mod benchmarks;
+pub mod binary;
 mod blanket_traits;
 pub mod descriptor;
+mod dot;
 mod error;
 pub mod expression;
+pub mod frost;
 pub mod interpreter;
 pub mod iter;
+#[cfg(feature = "json")]
+pub mod json;
 pub mod miniscript;
 pub mod plan;
 pub mod policy;
 mod primitives;
 pub mod psbt;
+#[cfg(feature = "unstable-fragments")]
+pub mod unstable;
 
-#[cfg(test)]
-mod test_utils;
-mod util;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+pub mod util;
 
 use core::{fmt, hash, str};
 
@@ -135,14 +144,23 @@ use bitcoin::hex::DisplayHex;
 use bitcoin::{script, Opcode};
 
 pub use crate::blanket_traits::FromStrKey;
-pub use crate::descriptor::{DefiniteDescriptorKey, Descriptor, DescriptorPublicKey};
-pub use crate::error::ParseError;
+pub use crate::descriptor::{
+    DefiniteDescriptorKey, Descriptor, DescriptorPublicKey, SatisfactionSize, SatisfactionWeight,
+};
+pub use crate::error::{ParseDescriptorError, ParseError, ParseMiniscriptError, ParsePolicyError};
 pub use crate::expression::{ParseNumError, ParseThresholdError, ParseTreeError};
 pub use crate::interpreter::Interpreter;
 pub use crate::miniscript::analyzable::{AnalysisError, ExtParams};
-pub use crate::miniscript::context::{BareCtx, Legacy, ScriptContext, Segwitv0, SigType, Tap};
+pub use crate::miniscript::context::{
+    BareCtx, ContextParams, CustomCtx, Legacy, ScriptContext, Segwitv0, SigType, Tap,
+};
 pub use crate::miniscript::decode::Terminal;
-pub use crate::miniscript::satisfy::{Preimage32, Satisfier};
+pub use crate::miniscript::extensions::{Extension, NoExt};
+pub use crate::miniscript::satisfy::{
+    KeyPreferences, LazyPreimageSatisfier, MapSatisfier, MapSignature, Preimage32, PreimageFetcher,
+    PreimageHash, Satisfier, SatisfierChain, TxSatisfier,
+};
+pub use crate::miniscript::types::SigSizes;
 pub use crate::miniscript::{hash256, Miniscript};
 use crate::prelude::*;
 pub use crate::primitives::absolute_locktime::{AbsLockTime, AbsLockTimeError};
@@ -163,6 +181,11 @@ pub trait MiniscriptKey: Clone + Eq + Ord + fmt::Debug + fmt::Display + hash::Ha
     /// in BIP389 multipath descriptors.
     fn num_der_paths(&self) -> usize { 0 }
 
+    /// Returns the BIP 118 encoding of the key if it is an ANYPREVOUT key.
+    /// Defaults to `None`.
+    #[cfg(feature = "apo")]
+    fn apo_kind(&self) -> Option<apo::ApoKind> { None }
+
     /// The associated [`bitcoin::hashes::sha256::Hash`] for this [`MiniscriptKey`], used in the
     /// sha256 fragment.
     type Sha256: Clone + Eq + Ord + fmt::Display + fmt::Debug + hash::Hash;
@@ -426,6 +449,10 @@ pub enum Error {
     /// While parsing backward, hit beginning of script
     UnexpectedStart,
     /// Got something we were not expecting
+    #[deprecated(
+        since = "TBD",
+        note = "no longer returned by the library; match on the typed parse and decode errors instead"
+    )]
     Unexpected(String),
     /// Encountered a wrapping character that we don't recognize
     UnknownWrapper(char),
@@ -450,7 +477,7 @@ pub enum Error {
     LiftError(policy::LiftError),
     /// Forward script context related errors
     ContextError(miniscript::context::ScriptContextError),
-    /// Recursion depth exceeded when parsing policy/miniscript from string
+    /// The Taproot tree is nested more deeply than consensus allows
     MaxRecursiveDepthExceeded,
     /// Anything but c:pk(key) (P2PK), c:pk_h(key) (P2PKH), and thresh_m(k,...)
     /// up to n=3 is invalid by standardness (bare)
@@ -465,6 +492,12 @@ pub enum Error {
     PubKeyCtxError(miniscript::decode::KeyParseError, &'static str),
     /// No script code for Tr descriptors
     TrNoScriptCode,
+    /// Addr descriptors only know the scriptPubKey of their address
+    AddrNoScript,
+    /// Raw descriptors only know their scriptPubKey
+    RawNoScript,
+    /// The scriptPubKey of a raw descriptor has no address
+    RawAddr(bitcoin::address::FromScriptError),
     /// At least two BIP389 key expressions in the descriptor contain tuples of
     /// derivation indexes of different lengths.
     MultipathDescLenMismatch,
@@ -478,6 +511,20 @@ pub enum Error {
     ParseThreshold(ParseThresholdError),
     /// Invalid expression tree.
     Parse(ParseError),
+    /// Error decoding a Miniscript from Script.
+    Decode(miniscript::decode::DecodeError),
+    /// Error decoding a Miniscript from Script, at the given position in the
+    /// script.
+    AtScriptPosition(miniscript::decode::ScriptPosition, Box<Error>),
+    /// Descriptor contained more keys than the caller-specified maximum.
+    TooManyKeys(usize),
+    /// No node exists at the given path in a Miniscript
+    NoSuchChild(Vec<usize>),
+    /// Error decoding the binary encoding of a Miniscript or descriptor.
+    Binary(binary::DecodeError),
+    /// Error converting a JSON AST.
+    #[cfg(feature = "json")]
+    Json(json::JsonError),
 }
 
 #[doc(hidden)] // will be removed when we remove Error
@@ -485,9 +532,6 @@ impl From<ParseThresholdError> for Error {
     fn from(e: ParseThresholdError) -> Self { Self::ParseThreshold(e) }
 }
 
-// https://github.com/sipa/miniscript/pull/5 for discussion on this number
-const MAX_RECURSION_DEPTH: u32 = 402;
-
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -500,6 +544,7 @@ impl fmt::Display for Error {
             Error::AddrError(ref e) => fmt::Display::fmt(e, f),
             Error::AddrP2shError(ref e) => fmt::Display::fmt(e, f),
             Error::UnexpectedStart => f.write_str("unexpected start of script"),
+            #[allow(deprecated)]
             Error::Unexpected(ref s) => write!(f, "unexpected «{}»", s),
             Error::UnknownWrapper(ch) => write!(f, "unknown wrapper «{}:»", ch),
             Error::NonTopLevel(ref s) => write!(f, "non-T miniscript: {}", s),
@@ -515,8 +560,8 @@ impl fmt::Display for Error {
             Error::LiftError(ref e) => fmt::Display::fmt(e, f),
             Error::MaxRecursiveDepthExceeded => write!(
                 f,
-                "Recursive depth over {} not permitted",
-                MAX_RECURSION_DEPTH
+                "Taproot tree depth over {} not permitted",
+                bitcoin::taproot::TAPROOT_CONTROL_MAX_NODE_COUNT
             ),
             Error::NonStandardBareScript => write!(
                 f,
@@ -531,18 +576,29 @@ impl fmt::Display for Error {
                 write!(f, "Pubkey error: {} under {} scriptcontext", pk, ctx)
             }
             Error::TrNoScriptCode => write!(f, "No script code for Tr descriptors"),
+            Error::AddrNoScript => write!(f, "Addr descriptors only know their scriptPubKey"),
+            Error::RawNoScript => write!(f, "Raw descriptors only know their scriptPubKey"),
+            Error::RawAddr(ref e) => write!(f, "Raw descriptor has no address: {}", e),
             Error::MultipathDescLenMismatch => write!(f, "At least two BIP389 key expressions in the descriptor contain tuples of derivation indexes of different lengths"),
             Error::AbsoluteLockTime(ref e) => e.fmt(f),
             Error::RelativeLockTime(ref e) => e.fmt(f),
             Error::Threshold(ref e) => e.fmt(f),
             Error::ParseThreshold(ref e) => e.fmt(f),
             Error::Parse(ref e) => e.fmt(f),
+            Error::Decode(ref e) => e.fmt(f),
+            Error::AtScriptPosition(ref pos, ref e) => write!(f, "{} ({})", e, pos),
+            Error::TooManyKeys(max) => write!(f, "descriptor has more than {} keys", max),
+            Error::NoSuchChild(ref path) => write!(f, "no node at path {:?}", path),
+            Error::Binary(ref e) => e.fmt(f),
+            #[cfg(feature = "json")]
+            Error::Json(ref e) => e.fmt(f),
         }
     }
 }
 
 #[cfg(feature = "std")]
 impl std::error::Error for Error {
+    #[allow(deprecated)] // for Unexpected
     fn cause(&self) -> Option<&dyn std::error::Error> {
         use self::Error::*;
 
@@ -563,10 +619,15 @@ impl std::error::Error for Error {
             | ImpossibleSatisfaction
             | BareDescriptorAddr
             | TrNoScriptCode
-            | MultipathDescLenMismatch => None,
+            | AddrNoScript
+            | RawNoScript
+            | MultipathDescLenMismatch
+            | TooManyKeys(_)
+            | NoSuchChild(_) => None,
             Script(e) => Some(e),
             AddrError(e) => Some(e),
             AddrP2shError(e) => Some(e),
+            RawAddr(e) => Some(e),
             Secp(e) => Some(e),
             #[cfg(feature = "compiler")]
             CompilerError(e) => Some(e),
@@ -580,15 +641,46 @@ impl std::error::Error for Error {
             Threshold(e) => Some(e),
             ParseThreshold(e) => Some(e),
             Parse(e) => Some(e),
+            Decode(e) => Some(e),
+            AtScriptPosition(_, e) => Some(&**e),
+            Binary(e) => Some(e),
+            #[cfg(feature = "json")]
+            Json(e) => Some(e),
         }
     }
 }
 
+#[doc(hidden)]
+impl From<ParseError> for Error {
+    fn from(e: ParseError) -> Error { Error::Parse(e) }
+}
+
+#[doc(hidden)]
+impl From<ParseTreeError> for Error {
+    fn from(e: ParseTreeError) -> Error { Error::Parse(ParseError::Tree(e)) }
+}
+
 #[doc(hidden)]
 impl From<miniscript::types::Error> for Error {
     fn from(e: miniscript::types::Error) -> Error { Error::TypeCheck(e.to_string()) }
 }
 
+#[doc(hidden)]
+impl From<miniscript::decode::DecodeError> for Error {
+    fn from(e: miniscript::decode::DecodeError) -> Error { Error::Decode(e) }
+}
+
+#[doc(hidden)]
+impl From<binary::DecodeError> for Error {
+    fn from(e: binary::DecodeError) -> Error { Error::Binary(e) }
+}
+
+#[doc(hidden)]
+#[cfg(feature = "json")]
+impl From<json::JsonError> for Error {
+    fn from(e: json::JsonError) -> Error { Error::Json(e) }
+}
+
 #[doc(hidden)]
 impl From<policy::LiftError> for Error {
     fn from(e: policy::LiftError) -> Error { Error::LiftError(e) }
@@ -711,6 +803,14 @@ mod tests {
     }
 }
 
+/// Re-exports used by the code generated by the `miniscript-macros` crate.
+///
+/// This is not part of the public API and may change at any time.
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::prelude::{String, Vec};
+}
+
 #[allow(unused_imports)] // this is an internal prelude module; not all imports are used with every feature combination
 mod prelude {
     // Mutex implementation from LDK
@@ -9,8 +9,16 @@ use core::fmt;
 #[cfg(feature = "std")]
 use std::error;
 
+use bitcoin::hashes::hash160;
+
+use crate::iter::TreeLike;
+use crate::miniscript::context::ScriptContextError;
+use crate::miniscript::limits::{Limits, ResourceUsage};
 use crate::prelude::*;
-use crate::{Miniscript, MiniscriptKey, ScriptContext, Terminal};
+use crate::sync::Arc;
+use crate::{
+    AbsLockTime, Extension, Miniscript, MiniscriptKey, NoExt, RelLockTime, ScriptContext, Terminal,
+};
 
 /// Params for parsing miniscripts that either non-sane or non-specified(experimental) in the spec.
 /// Used as a parameter [`Miniscript::from_str_ext`] and [`Miniscript::parse_with_ext`].
@@ -143,6 +151,8 @@ pub enum AnalysisError {
     Malleable,
     /// Contains partial descriptor raw pkh
     ContainsRawPkh,
+    /// Miniscript has more spending paths than the given limit
+    TooManyPaths,
 }
 
 impl fmt::Display for AnalysisError {
@@ -162,6 +172,9 @@ impl fmt::Display for AnalysisError {
             }
             AnalysisError::Malleable => f.write_str("Miniscript is malleable"),
             AnalysisError::ContainsRawPkh => f.write_str("Miniscript contains raw pkh"),
+            AnalysisError::TooManyPaths => {
+                f.write_str("Miniscript has more spending paths than the given limit")
+            }
         }
     }
 }
@@ -177,12 +190,13 @@ impl error::Error for AnalysisError {
             | BranchExceedResouceLimits
             | HeightTimelockCombination
             | Malleable
-            | ContainsRawPkh => None,
+            | ContainsRawPkh
+            | TooManyPaths => None,
         }
     }
 }
 
-impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
+impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> Miniscript<Pk, Ctx, Ext> {
     /// Whether all spend paths of miniscript require a signature
     pub fn requires_sig(&self) -> bool { self.ty.mall.safe }
 
@@ -194,6 +208,37 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
     // failed. But doing so may require returning a collection of errors
     pub fn within_resource_limits(&self) -> bool { Ctx::check_local_validity(self).is_ok() }
 
+    /// Checks that no spend path of the miniscript exceeds the given resource
+    /// limits, for use on chains whose limits differ from those of Bitcoin.
+    pub fn check_resource_limits(&self, limits: &Limits) -> Result<(), ScriptContextError> {
+        Ctx::check_local_validity_with_limits(self, limits)
+    }
+
+    /// Reports the worst-case value of each resource limit of the script
+    /// context, such as the script size or the opcode count, along with the
+    /// limit itself, so that callers can see how close the miniscript is to
+    /// exceeding them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use miniscript::{Miniscript, Segwitv0};
+    ///
+    /// let ms = "and_v(v:pk(A),pk(B))".parse::<Miniscript<String, Segwitv0>>().unwrap();
+    /// let usage = ms.resource_usage();
+    /// let op_count = usage.op_count.unwrap();
+    /// assert_eq!((op_count.used, op_count.limit), (2, 201));
+    /// assert_eq!(op_count.margin(), Some(199));
+    /// assert!(usage.fits());
+    /// ```
+    pub fn resource_usage(&self) -> ResourceUsage { Ctx::resource_usage(self) }
+
+    /// Reports the worst-case value of each resource limit of the script
+    /// context, using the given resource limits rather than those of Bitcoin.
+    pub fn resource_usage_with_limits(&self, limits: &Limits) -> ResourceUsage {
+        Ctx::resource_usage_with_limits(self, limits)
+    }
+
     /// Whether the miniscript contains a combination of timelocks
     pub fn has_mixed_timelocks(&self) -> bool { self.ext.timelock_info.contains_unspendable_path() }
 
@@ -256,4 +301,450 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
             Ok(())
         }
     }
+
+    /// Enumerates the spending paths of the miniscript and reports which of
+    /// them are malleable, which fragments make them so, and whether the
+    /// conditions of a malleable path also suffice for a non-malleable one.
+    ///
+    /// A spending path is a choice of branches of the disjunctions and
+    /// thresholds of the miniscript, identified by the keys, hash preimages
+    /// and timelocks it requires. The report is conservative: a path is
+    /// considered malleable if it satisfies a fragment that the type system
+    /// cannot prove non-malleable, or if it executes a fragment which is
+    /// malleable in the script context, such as `or_i` in [`crate::Legacy`].
+    ///
+    /// The number of paths can be exponential in the size of the miniscript;
+    /// if it exceeds `max_paths`, [`AnalysisError::TooManyPaths`] is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use miniscript::miniscript::analyzable::MalleabilityCause;
+    /// use miniscript::{Legacy, Miniscript};
+    ///
+    /// let ms = "c:or_i(pk_k(A),pk_k(B))".parse::<Miniscript<String, Legacy>>().unwrap();
+    /// let report = ms.malleability_report(100).unwrap();
+    /// assert_eq!(report.paths.len(), 2);
+    /// assert!(report.paths.iter().all(|path| path.is_malleable()));
+    /// assert_eq!(report.fragments[0].fragment.to_string(), "or_i(pk_k(A),pk_k(B))");
+    /// assert!(matches!(report.fragments[0].cause, MalleabilityCause::Context(_)));
+    /// ```
+    pub fn malleability_report(
+        &self,
+        max_paths: usize,
+    ) -> Result<MalleabilityReport<'_, Pk, Ctx, Ext>, AnalysisError> {
+        let mut conditions = ConditionSet::default();
+        let mut fragments = vec![];
+        let mut non_mall = vec![];
+        // Paths through each node, and the context-malleable fragments executed
+        // when dissatisfying it.
+        let mut sats: Vec<Vec<RawPath>> = vec![];
+        let mut dissats: Vec<BTreeSet<usize>> = vec![];
+        for item in self.post_order_iter() {
+            let node = item.node;
+            let child_sat = |n: usize| &sats[item.child_indices[n]];
+            let child_dissat = |n: usize| &dissats[item.child_indices[n]];
+
+            let mut sat = match node.node {
+                Terminal::True | Terminal::Ext(_) => vec![RawPath::default()],
+                Terminal::False => vec![],
+                Terminal::PkK(ref pk) | Terminal::PkH(ref pk) => {
+                    vec![RawPath::single(
+                        conditions.insert(PathCondition::Key(pk.clone())),
+                    )]
+                }
+                Terminal::RawPkH(ref hash) => {
+                    vec![RawPath::single(
+                        conditions.insert(PathCondition::KeyHash(*hash)),
+                    )]
+                }
+                Terminal::After(t) => {
+                    vec![RawPath::single(conditions.insert(PathCondition::After(t)))]
+                }
+                Terminal::Older(t) => {
+                    vec![RawPath::single(conditions.insert(PathCondition::Older(t)))]
+                }
+                Terminal::Sha256(ref h) => {
+                    vec![RawPath::single(
+                        conditions.insert(PathCondition::Sha256(h.clone())),
+                    )]
+                }
+                Terminal::Hash256(ref h) => {
+                    vec![RawPath::single(
+                        conditions.insert(PathCondition::Hash256(h.clone())),
+                    )]
+                }
+                Terminal::Ripemd160(ref h) => {
+                    vec![RawPath::single(
+                        conditions.insert(PathCondition::Ripemd160(h.clone())),
+                    )]
+                }
+                Terminal::Hash160(ref h) => {
+                    vec![RawPath::single(
+                        conditions.insert(PathCondition::Hash160(h.clone())),
+                    )]
+                }
+                Terminal::Multi(ref thresh) => {
+                    let ids = thresh
+                        .iter()
+                        .map(|pk| conditions.insert(PathCondition::Key(pk.clone())))
+                        .collect::<Vec<_>>();
+                    multi_paths(&ids, thresh.k(), max_paths)?
+                }
+                Terminal::MultiA(ref thresh) => {
+                    let ids = thresh
+                        .iter()
+                        .map(|pk| conditions.insert(PathCondition::Key(pk.clone())))
+                        .collect::<Vec<_>>();
+                    multi_paths(&ids, thresh.k(), max_paths)?
+                }
+                Terminal::Alt(..)
+                | Terminal::Swap(..)
+                | Terminal::Check(..)
+                | Terminal::DupIf(..)
+                | Terminal::Verify(..)
+                | Terminal::NonZero(..)
+                | Terminal::ZeroNotEqual(..) => child_sat(0).clone(),
+                Terminal::AndV(..) | Terminal::AndB(..) => {
+                    product(child_sat(0), child_sat(1), max_paths)?
+                }
+                Terminal::OrB(..) => {
+                    let mut paths = with_sources(child_sat(0), child_dissat(1));
+                    paths.extend(with_sources(child_sat(1), child_dissat(0)));
+                    paths
+                }
+                Terminal::OrD(..) | Terminal::OrC(..) => {
+                    let mut paths = child_sat(0).clone();
+                    paths.extend(with_sources(child_sat(1), child_dissat(0)));
+                    paths
+                }
+                Terminal::OrI(..) => {
+                    let mut paths = child_sat(0).clone();
+                    paths.extend(child_sat(1).iter().cloned());
+                    paths
+                }
+                Terminal::AndOr(..) => {
+                    let mut paths = product(child_sat(0), child_sat(1), max_paths)?;
+                    paths.extend(with_sources(child_sat(2), child_dissat(0)));
+                    paths
+                }
+                Terminal::Thresh(ref thresh) => {
+                    let mut paths = vec![];
+                    for_each_subset(thresh.n(), thresh.k(), |chosen| {
+                        let mut subset_paths = vec![RawPath::default()];
+                        let mut next = 0;
+                        for i in 0..thresh.n() {
+                            if chosen.get(next) == Some(&i) {
+                                subset_paths = product(&subset_paths, child_sat(i), max_paths)?;
+                                next += 1;
+                            } else {
+                                subset_paths = with_sources(&subset_paths, child_dissat(i));
+                            }
+                        }
+                        paths.extend(subset_paths);
+                        if paths.len() > max_paths {
+                            return Err(AnalysisError::TooManyPaths);
+                        }
+                        Ok(())
+                    })?;
+                    paths
+                }
+            };
+            if sat.len() > max_paths {
+                return Err(AnalysisError::TooManyPaths);
+            }
+
+            let mut dissat = BTreeSet::new();
+            for &child in &item.child_indices {
+                dissat.extend(dissats[child].iter().copied());
+            }
+            if let Err(e) = context_check(&node.node) {
+                let id = fragments.len();
+                fragments.push(MalleableFragment {
+                    fragment: node,
+                    cause: MalleabilityCause::Context(e),
+                });
+                dissat.insert(id);
+                for path in &mut sat {
+                    path.sources.insert(id);
+                }
+            }
+            let children_non_mall = item.child_indices.iter().all(|&child| non_mall[child]);
+            if !node.ty.mall.non_malleable && children_non_mall {
+                let id = fragments.len();
+                fragments
+                    .push(MalleableFragment { fragment: node, cause: MalleabilityCause::Type });
+                for path in &mut sat {
+                    path.sources.insert(id);
+                }
+            }
+
+            non_mall.push(node.ty.mall.non_malleable);
+            sats.push(sat);
+            dissats.push(dissat);
+        }
+
+        let mut seen = BTreeSet::new();
+        let raw_paths = sats
+            .pop()
+            .expect("post-order iterator yields the root")
+            .into_iter()
+            .filter(|path| seen.insert(path.clone()))
+            .collect::<Vec<_>>();
+        let paths = raw_paths
+            .iter()
+            .map(|path| SpendPath {
+                conditions: path
+                    .conditions
+                    .iter()
+                    .map(|&id| conditions.conditions[id].clone())
+                    .collect(),
+                malleable_fragments: path.sources.iter().copied().collect(),
+                non_malleable_alternative: if path.sources.is_empty() {
+                    None
+                } else {
+                    raw_paths.iter().position(|alt| {
+                        alt.sources.is_empty() && alt.conditions.is_subset(&path.conditions)
+                    })
+                },
+            })
+            .collect();
+        Ok(MalleabilityReport { fragments, paths })
+    }
+}
+
+/// A condition which must be met to spend through a path of a miniscript, as
+/// reported by [`Miniscript::malleability_report`].
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum PathCondition<Pk: MiniscriptKey> {
+    /// A signature for the key.
+    Key(Pk),
+    /// A signature for the key with the given hash, from a raw `pkh` fragment.
+    KeyHash(hash160::Hash),
+    /// A SHA256 preimage.
+    Sha256(Pk::Sha256),
+    /// A HASH256 preimage.
+    Hash256(Pk::Hash256),
+    /// A RIPEMD160 preimage.
+    Ripemd160(Pk::Ripemd160),
+    /// A HASH160 preimage.
+    Hash160(Pk::Hash160),
+    /// An absolute timelock.
+    After(AbsLockTime),
+    /// A relative timelock.
+    Older(RelLockTime),
+}
+
+/// Why a fragment makes the satisfactions through it malleable.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum MalleabilityCause {
+    /// The type system cannot prove the fragment non-malleable although its
+    /// children are, e.g. a disjunction in which no branch requires a signature.
+    Type,
+    /// The fragment is malleable in the script context, e.g. `or_i` in
+    /// [`crate::Legacy`], where `MINIMALIF` is not a consensus rule.
+    Context(ScriptContextError),
+}
+
+/// A fragment which makes the satisfactions through it malleable.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MalleableFragment<'a, Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> {
+    /// The fragment.
+    pub fragment: &'a Miniscript<Pk, Ctx, Ext>,
+    /// Why the fragment is malleable.
+    pub cause: MalleabilityCause,
+}
+
+/// The malleability of a spending path of a miniscript.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SpendPath<Pk: MiniscriptKey> {
+    /// The conditions which must be met to spend through the path.
+    pub conditions: Vec<PathCondition<Pk>>,
+    /// Indices in [`MalleabilityReport::fragments`] of the fragments which
+    /// make the path malleable.
+    pub malleable_fragments: Vec<usize>,
+    /// For a malleable path, the index in [`MalleabilityReport::paths`] of a
+    /// non-malleable path whose conditions are a subset of this one's, if any.
+    pub non_malleable_alternative: Option<usize>,
+}
+
+impl<Pk: MiniscriptKey> SpendPath<Pk> {
+    /// Whether a third party may be able to change the satisfaction of this
+    /// path, and thereby the witness txid of the spending transaction.
+    pub fn is_malleable(&self) -> bool { !self.malleable_fragments.is_empty() }
+}
+
+/// Malleability of each spending path of a miniscript, as returned by
+/// [`Miniscript::malleability_report`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MalleabilityReport<'a, Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> {
+    /// The fragments which make some path malleable.
+    pub fragments: Vec<MalleableFragment<'a, Pk, Ctx, Ext>>,
+    /// The spending paths.
+    pub paths: Vec<SpendPath<Pk>>,
+}
+
+impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> MalleabilityReport<'_, Pk, Ctx, Ext> {
+    /// Whether no spending path is malleable.
+    pub fn is_non_malleable(&self) -> bool { self.paths.iter().all(|path| !path.is_malleable()) }
+
+    /// Iterator over the malleable spending paths.
+    pub fn malleable_paths(&self) -> impl Iterator<Item = &SpendPath<Pk>> {
+        self.paths.iter().filter(|path| path.is_malleable())
+    }
+}
+
+/// Conditions seen so far, numbered in order of first appearance.
+struct ConditionSet<Pk: MiniscriptKey> {
+    conditions: Vec<PathCondition<Pk>>,
+    ids: BTreeMap<PathCondition<Pk>, usize>,
+}
+
+impl<Pk: MiniscriptKey> Default for ConditionSet<Pk> {
+    fn default() -> Self { ConditionSet { conditions: vec![], ids: BTreeMap::new() } }
+}
+
+impl<Pk: MiniscriptKey> ConditionSet<Pk> {
+    fn insert(&mut self, cond: PathCondition<Pk>) -> usize {
+        if let Some(&id) = self.ids.get(&cond) {
+            return id;
+        }
+        let id = self.conditions.len();
+        self.conditions.push(cond.clone());
+        self.ids.insert(cond, id);
+        id
+    }
+}
+
+/// A spending path, as condition ids and the ids of the malleable fragments
+/// it goes through.
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+struct RawPath {
+    conditions: BTreeSet<usize>,
+    sources: BTreeSet<usize>,
+}
+
+impl RawPath {
+    fn single(condition: usize) -> Self {
+        RawPath { conditions: core::iter::once(condition).collect(), sources: BTreeSet::new() }
+    }
+}
+
+/// Paths which take both a path of `left` and one of `right`.
+fn product(
+    left: &[RawPath],
+    right: &[RawPath],
+    max_paths: usize,
+) -> Result<Vec<RawPath>, AnalysisError> {
+    if left.len().saturating_mul(right.len()) > max_paths {
+        return Err(AnalysisError::TooManyPaths);
+    }
+    let mut ret = Vec::with_capacity(left.len() * right.len());
+    for l in left {
+        for r in right {
+            let mut path = l.clone();
+            path.conditions.extend(r.conditions.iter().copied());
+            path.sources.extend(r.sources.iter().copied());
+            ret.push(path);
+        }
+    }
+    Ok(ret)
+}
+
+/// Adds the given malleable fragments to each of the paths.
+fn with_sources(paths: &[RawPath], sources: &BTreeSet<usize>) -> Vec<RawPath> {
+    paths
+        .iter()
+        .map(|path| {
+            let mut path = path.clone();
+            path.sources.extend(sources.iter().copied());
+            path
+        })
+        .collect()
+}
+
+/// Paths of a `multi` or `multi_a` fragment, one per `k`-subset of its keys.
+fn multi_paths(keys: &[usize], k: usize, max_paths: usize) -> Result<Vec<RawPath>, AnalysisError> {
+    let mut paths = vec![];
+    for_each_subset(keys.len(), k, |chosen| {
+        if paths.len() == max_paths {
+            return Err(AnalysisError::TooManyPaths);
+        }
+        let conditions = chosen.iter().map(|&i| keys[i]).collect();
+        paths.push(RawPath { conditions, sources: BTreeSet::new() });
+        Ok(())
+    })?;
+    Ok(paths)
+}
+
+/// Calls `f` with each `k`-element subset of `0..n`, in lexicographic order.
+fn for_each_subset<E, F>(n: usize, k: usize, mut f: F) -> Result<(), E>
+where
+    F: FnMut(&[usize]) -> Result<(), E>,
+{
+    if k > n {
+        return Ok(());
+    }
+    let mut chosen = (0..k).collect::<Vec<_>>();
+    loop {
+        f(&chosen)?;
+        // Find the last index which can still be advanced.
+        let mut i = k;
+        loop {
+            if i == 0 {
+                return Ok(());
+            }
+            i -= 1;
+            if chosen[i] < n - k + i {
+                break;
+            }
+        }
+        chosen[i] += 1;
+        for j in i + 1..k {
+            chosen[j] = chosen[j - 1] + 1;
+        }
+    }
+}
+
+/// Checks whether the fragment is malleable in its script context.
+///
+/// [`ScriptContext::check_terminal_non_malleable`] only looks at the fragment
+/// itself, so its children are replaced by `1` to check fragments which may
+/// contain extensions.
+fn context_check<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension>(
+    frag: &Terminal<Pk, Ctx, Ext>,
+) -> Result<(), ScriptContextError> {
+    let t = || Arc::new(Miniscript::<Pk, Ctx, NoExt>::TRUE);
+    let frag = match *frag {
+        Terminal::True => Terminal::True,
+        Terminal::False => Terminal::False,
+        Terminal::PkK(ref pk) => Terminal::PkK(pk.clone()),
+        Terminal::PkH(ref pk) => Terminal::PkH(pk.clone()),
+        Terminal::RawPkH(ref hash) => Terminal::RawPkH(*hash),
+        Terminal::After(t) => Terminal::After(t),
+        Terminal::Older(t) => Terminal::Older(t),
+        Terminal::Sha256(ref h) => Terminal::Sha256(h.clone()),
+        Terminal::Hash256(ref h) => Terminal::Hash256(h.clone()),
+        Terminal::Ripemd160(ref h) => Terminal::Ripemd160(h.clone()),
+        Terminal::Hash160(ref h) => Terminal::Hash160(h.clone()),
+        Terminal::Alt(..) => Terminal::Alt(t()),
+        Terminal::Swap(..) => Terminal::Swap(t()),
+        Terminal::Check(..) => Terminal::Check(t()),
+        Terminal::DupIf(..) => Terminal::DupIf(t()),
+        Terminal::Verify(..) => Terminal::Verify(t()),
+        Terminal::NonZero(..) => Terminal::NonZero(t()),
+        Terminal::ZeroNotEqual(..) => Terminal::ZeroNotEqual(t()),
+        Terminal::AndV(..) => Terminal::AndV(t(), t()),
+        Terminal::AndB(..) => Terminal::AndB(t(), t()),
+        Terminal::AndOr(..) => Terminal::AndOr(t(), t(), t()),
+        Terminal::OrB(..) => Terminal::OrB(t(), t()),
+        Terminal::OrD(..) => Terminal::OrD(t(), t()),
+        Terminal::OrC(..) => Terminal::OrC(t(), t()),
+        Terminal::OrI(..) => Terminal::OrI(t(), t()),
+        Terminal::Thresh(ref thresh) => Terminal::Thresh(thresh.map_ref(|_| t())),
+        Terminal::Multi(ref thresh) => Terminal::Multi(thresh.clone()),
+        Terminal::MultiA(ref thresh) => Terminal::MultiA(thresh.clone()),
+        Terminal::Ext(_) => return Ok(()),
+    };
+    Ctx::check_terminal_non_malleable(&frag)
 }
@@ -12,33 +12,53 @@ use bitcoin::{absolute, opcodes, script};
 
 use crate::miniscript::context::SigType;
 use crate::miniscript::ScriptContext;
+use crate::prelude::*;
 use crate::util::MsKeyBuilder;
-use crate::{Miniscript, MiniscriptKey, Terminal, ToPublicKey};
+use crate::{Extension, MiniscriptKey, Terminal, ToPublicKey};
 
-/// Helper trait to add a `push_astelem` method to `script::Builder`
-trait PushAstElem<Pk: MiniscriptKey, Ctx: ScriptContext> {
-    fn push_astelem(self, ast: &Miniscript<Pk, Ctx>) -> Self
-    where
-        Pk: ToPublicKey;
+/// An item still to be encoded by [`Terminal::encode`].
+enum EncodeItem<'a, Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> {
+    Term(&'a Terminal<Pk, Ctx, Ext>),
+    Opcode(opcodes::Opcode),
+    Int(i64),
+    Verify,
 }
 
-impl<Pk: MiniscriptKey, Ctx: ScriptContext> PushAstElem<Pk, Ctx> for script::Builder {
-    fn push_astelem(self, ast: &Miniscript<Pk, Ctx>) -> Self
+impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> Terminal<Pk, Ctx, Ext> {
+    /// Encode the element as a fragment of Bitcoin Script. The inverse
+    /// function, from Script to an AST element, is implemented in the
+    /// `parse` module.
+    pub fn encode(&self, mut builder: script::Builder) -> script::Builder
     where
         Pk: ToPublicKey,
     {
-        ast.node.encode(self)
+        // Items still to be encoded, the next one last. Children are expanded
+        // onto the stack rather than encoded recursively.
+        let mut stack = vec![EncodeItem::Term(self)];
+        while let Some(item) = stack.pop() {
+            builder = match item {
+                EncodeItem::Term(term) => term.encode_node(builder, &mut stack),
+                EncodeItem::Opcode(op) => builder.push_opcode(op),
+                EncodeItem::Int(n) => builder.push_int(n),
+                EncodeItem::Verify => builder.push_verify(),
+            };
+        }
+        builder
     }
-}
 
-impl<Pk: MiniscriptKey, Ctx: ScriptContext> Terminal<Pk, Ctx> {
-    /// Encode the element as a fragment of Bitcoin Script. The inverse
-    /// function, from Script to an AST element, is implemented in the
-    /// `parse` module.
-    pub fn encode(&self, mut builder: script::Builder) -> script::Builder
+    /// Encodes the opcodes of this node which come before its first child,
+    /// and pushes its children and the remaining opcodes onto the stack, in
+    /// reverse order.
+    fn encode_node<'a>(
+        &'a self,
+        mut builder: script::Builder,
+        stack: &mut Vec<EncodeItem<'a, Pk, Ctx, Ext>>,
+    ) -> script::Builder
     where
         Pk: ToPublicKey,
     {
+        use EncodeItem::{Opcode as Op, Term};
+
         match *self {
             Terminal::PkK(ref pk) => builder.push_ms_key::<_, Ctx>(pk),
             Terminal::PkH(ref pk) => builder
@@ -87,70 +107,108 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Terminal<Pk, Ctx> {
                 .push_opcode(opcodes::all::OP_EQUAL),
             Terminal::True => builder.push_opcode(opcodes::OP_TRUE),
             Terminal::False => builder.push_opcode(opcodes::OP_FALSE),
-            Terminal::Alt(ref sub) => builder
-                .push_opcode(opcodes::all::OP_TOALTSTACK)
-                .push_astelem(sub)
-                .push_opcode(opcodes::all::OP_FROMALTSTACK),
-            Terminal::Swap(ref sub) => builder.push_opcode(opcodes::all::OP_SWAP).push_astelem(sub),
-            Terminal::Check(ref sub) => builder
-                .push_astelem(sub)
-                .push_opcode(opcodes::all::OP_CHECKSIG),
-            Terminal::DupIf(ref sub) => builder
-                .push_opcode(opcodes::all::OP_DUP)
-                .push_opcode(opcodes::all::OP_IF)
-                .push_astelem(sub)
-                .push_opcode(opcodes::all::OP_ENDIF),
-            Terminal::Verify(ref sub) => builder.push_astelem(sub).push_verify(),
-            Terminal::NonZero(ref sub) => builder
-                .push_opcode(opcodes::all::OP_SIZE)
-                .push_opcode(opcodes::all::OP_0NOTEQUAL)
-                .push_opcode(opcodes::all::OP_IF)
-                .push_astelem(sub)
-                .push_opcode(opcodes::all::OP_ENDIF),
-            Terminal::ZeroNotEqual(ref sub) => builder
-                .push_astelem(sub)
-                .push_opcode(opcodes::all::OP_0NOTEQUAL),
-            Terminal::AndV(ref left, ref right) => builder.push_astelem(left).push_astelem(right),
-            Terminal::AndB(ref left, ref right) => builder
-                .push_astelem(left)
-                .push_astelem(right)
-                .push_opcode(opcodes::all::OP_BOOLAND),
-            Terminal::AndOr(ref a, ref b, ref c) => builder
-                .push_astelem(a)
-                .push_opcode(opcodes::all::OP_NOTIF)
-                .push_astelem(c)
-                .push_opcode(opcodes::all::OP_ELSE)
-                .push_astelem(b)
-                .push_opcode(opcodes::all::OP_ENDIF),
-            Terminal::OrB(ref left, ref right) => builder
-                .push_astelem(left)
-                .push_astelem(right)
-                .push_opcode(opcodes::all::OP_BOOLOR),
-            Terminal::OrD(ref left, ref right) => builder
-                .push_astelem(left)
-                .push_opcode(opcodes::all::OP_IFDUP)
-                .push_opcode(opcodes::all::OP_NOTIF)
-                .push_astelem(right)
-                .push_opcode(opcodes::all::OP_ENDIF),
-            Terminal::OrC(ref left, ref right) => builder
-                .push_astelem(left)
-                .push_opcode(opcodes::all::OP_NOTIF)
-                .push_astelem(right)
-                .push_opcode(opcodes::all::OP_ENDIF),
-            Terminal::OrI(ref left, ref right) => builder
-                .push_opcode(opcodes::all::OP_IF)
-                .push_astelem(left)
-                .push_opcode(opcodes::all::OP_ELSE)
-                .push_astelem(right)
-                .push_opcode(opcodes::all::OP_ENDIF),
+            Terminal::Alt(ref sub) => {
+                stack.extend([Op(opcodes::all::OP_FROMALTSTACK), Term(&sub.node)]);
+                builder.push_opcode(opcodes::all::OP_TOALTSTACK)
+            }
+            Terminal::Swap(ref sub) => {
+                stack.push(Term(&sub.node));
+                builder.push_opcode(opcodes::all::OP_SWAP)
+            }
+            Terminal::Check(ref sub) => {
+                stack.extend([Op(opcodes::all::OP_CHECKSIG), Term(&sub.node)]);
+                builder
+            }
+            Terminal::DupIf(ref sub) => {
+                stack.extend([Op(opcodes::all::OP_ENDIF), Term(&sub.node)]);
+                builder
+                    .push_opcode(opcodes::all::OP_DUP)
+                    .push_opcode(opcodes::all::OP_IF)
+            }
+            Terminal::Verify(ref sub) => {
+                stack.extend([EncodeItem::Verify, Term(&sub.node)]);
+                builder
+            }
+            Terminal::NonZero(ref sub) => {
+                stack.extend([Op(opcodes::all::OP_ENDIF), Term(&sub.node)]);
+                builder
+                    .push_opcode(opcodes::all::OP_SIZE)
+                    .push_opcode(opcodes::all::OP_0NOTEQUAL)
+                    .push_opcode(opcodes::all::OP_IF)
+            }
+            Terminal::ZeroNotEqual(ref sub) => {
+                stack.extend([Op(opcodes::all::OP_0NOTEQUAL), Term(&sub.node)]);
+                builder
+            }
+            Terminal::AndV(ref left, ref right) => {
+                stack.extend([Term(&right.node), Term(&left.node)]);
+                builder
+            }
+            Terminal::AndB(ref left, ref right) => {
+                stack.extend([
+                    Op(opcodes::all::OP_BOOLAND),
+                    Term(&right.node),
+                    Term(&left.node),
+                ]);
+                builder
+            }
+            Terminal::AndOr(ref a, ref b, ref c) => {
+                stack.extend([
+                    Op(opcodes::all::OP_ENDIF),
+                    Term(&b.node),
+                    Op(opcodes::all::OP_ELSE),
+                    Term(&c.node),
+                    Op(opcodes::all::OP_NOTIF),
+                    Term(&a.node),
+                ]);
+                builder
+            }
+            Terminal::OrB(ref left, ref right) => {
+                stack.extend([
+                    Op(opcodes::all::OP_BOOLOR),
+                    Term(&right.node),
+                    Term(&left.node),
+                ]);
+                builder
+            }
+            Terminal::OrD(ref left, ref right) => {
+                stack.extend([
+                    Op(opcodes::all::OP_ENDIF),
+                    Term(&right.node),
+                    Op(opcodes::all::OP_NOTIF),
+                    Op(opcodes::all::OP_IFDUP),
+                    Term(&left.node),
+                ]);
+                builder
+            }
+            Terminal::OrC(ref left, ref right) => {
+                stack.extend([
+                    Op(opcodes::all::OP_ENDIF),
+                    Term(&right.node),
+                    Op(opcodes::all::OP_NOTIF),
+                    Term(&left.node),
+                ]);
+                builder
+            }
+            Terminal::OrI(ref left, ref right) => {
+                stack.extend([
+                    Op(opcodes::all::OP_ENDIF),
+                    Term(&right.node),
+                    Op(opcodes::all::OP_ELSE),
+                    Term(&left.node),
+                ]);
+                builder.push_opcode(opcodes::all::OP_IF)
+            }
             Terminal::Thresh(ref thresh) => {
-                builder = builder.push_astelem(&thresh.data()[0]);
-                for sub in &thresh.data()[1..] {
-                    builder = builder.push_astelem(sub).push_opcode(opcodes::all::OP_ADD);
+                stack.extend([
+                    Op(opcodes::all::OP_EQUAL),
+                    EncodeItem::Int(thresh.k() as i64),
+                ]);
+                for sub in thresh.data()[1..].iter().rev() {
+                    stack.extend([Op(opcodes::all::OP_ADD), Term(&sub.node)]);
                 }
+                stack.push(Term(&thresh.data()[0].node));
                 builder
-                    .push_int(thresh.k() as i64)
-                    .push_opcode(opcodes::all::OP_EQUAL)
             }
             Terminal::Multi(ref thresh) => {
                 debug_assert!(Ctx::sig_type() == SigType::Ecdsa);
@@ -175,6 +233,7 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Terminal<Pk, Ctx> {
                     .push_int(thresh.k() as i64)
                     .push_opcode(opcodes::all::OP_NUMEQUAL)
             }
+            Terminal::Ext(ref ext) => ext.push_to_builder(builder),
         }
     }
 }
@@ -0,0 +1,269 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Miniscript Builder
+//!
+//! A fluent interface for constructing Miniscripts programmatically, e.g.
+//!
+//! ```
+//! use miniscript::miniscript::builder::Builder;
+//! use miniscript::{Miniscript, RelLockTime, Segwitv0};
+//!
+//! let ms: Miniscript<String, Segwitv0> = Builder::pk("A".to_owned())
+//!     .and(Builder::pk("B".to_owned()).or(Builder::older(RelLockTime::from_height(144))))
+//!     .build()
+//!     .unwrap();
+//! assert_eq!(ms.to_string(), "and_v(v:pk(A),or_d(pk(B),older(144)))");
+//! ```
+//!
+//! Every node is typechecked as soon as it is added, using the typed constructors
+//! on [`Miniscript`]. The first error encountered is carried through the rest of
+//! the chain and returned by [`Builder::build`].
+//!
+
+use sync::Arc;
+
+use crate::miniscript::types::{self, Base};
+use crate::prelude::*;
+use crate::{AbsLockTime, Error, Miniscript, MiniscriptKey, RelLockTime, ScriptContext};
+
+/// A partially-constructed Miniscript.
+#[derive(Debug)]
+pub struct Builder<Pk: MiniscriptKey, Ctx: ScriptContext> {
+    inner: Result<Arc<Miniscript<Pk, Ctx>>, Error>,
+}
+
+impl<Pk: MiniscriptKey, Ctx: ScriptContext> From<Miniscript<Pk, Ctx>> for Builder<Pk, Ctx> {
+    fn from(ms: Miniscript<Pk, Ctx>) -> Self { Builder { inner: Ok(Arc::new(ms)) } }
+}
+
+impl<Pk: MiniscriptKey, Ctx: ScriptContext> From<Arc<Miniscript<Pk, Ctx>>> for Builder<Pk, Ctx> {
+    fn from(ms: Arc<Miniscript<Pk, Ctx>>) -> Self { Builder { inner: Ok(ms) } }
+}
+
+impl<Pk: MiniscriptKey, Ctx: ScriptContext> Builder<Pk, Ctx> {
+    /// Starts a builder with the `pk` fragment.
+    pub fn pk(pk: Pk) -> Self { Miniscript::pk(pk).into() }
+
+    /// Starts a builder with the `pkh` fragment.
+    pub fn pkh(pk: Pk) -> Self { Miniscript::pkh(pk).into() }
+
+    /// Starts a builder with the `pk_k` fragment.
+    pub fn pk_k(pk: Pk) -> Self { Miniscript::pk_k(pk).into() }
+
+    /// Starts a builder with the `pk_h` fragment.
+    pub fn pk_h(pk: Pk) -> Self { Miniscript::pk_h(pk).into() }
+
+    /// Starts a builder with the `after` fragment.
+    pub fn after(time: AbsLockTime) -> Self { Miniscript::after(time).into() }
+
+    /// Starts a builder with the `older` fragment.
+    pub fn older(time: RelLockTime) -> Self { Miniscript::older(time).into() }
+
+    /// Starts a builder with the `sha256` fragment.
+    pub fn sha256(hash: Pk::Sha256) -> Self { Miniscript::sha256(hash).into() }
+
+    /// Starts a builder with the `hash256` fragment.
+    pub fn hash256(hash: Pk::Hash256) -> Self { Miniscript::hash256(hash).into() }
+
+    /// Starts a builder with the `ripemd160` fragment.
+    pub fn ripemd160(hash: Pk::Ripemd160) -> Self { Miniscript::ripemd160(hash).into() }
+
+    /// Starts a builder with the `hash160` fragment.
+    pub fn hash160(hash: Pk::Hash160) -> Self { Miniscript::hash160(hash).into() }
+
+    /// Starts a builder with the `multi` fragment.
+    pub fn multi(k: usize, keys: Vec<Pk>) -> Self { Miniscript::multi(k, keys).into() }
+
+    /// Starts a builder with the `multi_a` fragment.
+    pub fn multi_a(k: usize, keys: Vec<Pk>) -> Self { Miniscript::multi_a(k, keys).into() }
+
+    /// Starts a builder with a `thresh` of the given builders.
+    pub fn thresh(k: usize, subs: Vec<Self>) -> Self {
+        subs.into_iter()
+            .map(|sub| sub.inner)
+            .collect::<Result<Vec<_>, _>>()
+            .and_then(|subs| Miniscript::thresh(k, subs))
+            .into()
+    }
+
+    /// The type of the Miniscript built so far, or `None` if there was an error.
+    pub fn ty(&self) -> Option<types::Type> { self.inner.as_ref().ok().map(|ms| ms.ty) }
+
+    /// The first error encountered while building, if any.
+    pub fn error(&self) -> Option<&Error> { self.inner.as_ref().err() }
+
+    /// Finishes building, returning the Miniscript or the first error encountered.
+    pub fn build(self) -> Result<Miniscript<Pk, Ctx>, Error> {
+        self.inner
+            .map(|ms| Arc::try_unwrap(ms).unwrap_or_else(|arc| Miniscript::clone(&arc)))
+    }
+
+    fn map<F>(self, f: F) -> Self
+    where
+        F: FnOnce(Arc<Miniscript<Pk, Ctx>>) -> Result<Miniscript<Pk, Ctx>, Error>,
+    {
+        self.inner.and_then(f).into()
+    }
+
+    fn combine<F>(self, other: Self, f: F) -> Self
+    where
+        F: FnOnce(
+            Arc<Miniscript<Pk, Ctx>>,
+            Arc<Miniscript<Pk, Ctx>>,
+        ) -> Result<Miniscript<Pk, Ctx>, Error>,
+    {
+        self.inner
+            .and_then(|left| other.inner.and_then(|right| f(left, right)))
+            .into()
+    }
+
+    /// Applies the `a:` wrapper.
+    pub fn wrap_a(self) -> Self { self.map(Miniscript::alt) }
+
+    /// Applies the `s:` wrapper.
+    pub fn wrap_s(self) -> Self { self.map(Miniscript::swap) }
+
+    /// Applies the `c:` wrapper.
+    pub fn wrap_c(self) -> Self { self.map(Miniscript::check) }
+
+    /// Applies the `d:` wrapper.
+    pub fn wrap_d(self) -> Self { self.map(Miniscript::dupif) }
+
+    /// Applies the `v:` wrapper.
+    pub fn wrap_v(self) -> Self { self.map(Miniscript::verify) }
+
+    /// Applies the `j:` wrapper.
+    pub fn wrap_j(self) -> Self { self.map(Miniscript::nonzero) }
+
+    /// Applies the `n:` wrapper.
+    pub fn wrap_n(self) -> Self { self.map(Miniscript::zero_not_equal) }
+
+    /// Applies the `t:` wrapper, which is an alias for `and_v(X,1)`.
+    pub fn wrap_t(self) -> Self { self.and_v(Miniscript::TRUE.into()) }
+
+    /// Applies the `u:` wrapper, which is an alias for `or_i(X,0)`.
+    pub fn wrap_u(self) -> Self { self.or_i(Miniscript::FALSE.into()) }
+
+    /// Applies the `l:` wrapper, which is an alias for `or_i(0,X)`.
+    pub fn wrap_l(self) -> Self { Builder::from(Miniscript::FALSE).or_i(self) }
+
+    /// Combines with `other` using `and_v`.
+    pub fn and_v(self, other: Self) -> Self { self.combine(other, Miniscript::and_v) }
+
+    /// Combines with `other` using `and_b`.
+    pub fn and_b(self, other: Self) -> Self { self.combine(other, Miniscript::and_b) }
+
+    /// Combines with `other` using `and_n`.
+    pub fn and_n(self, other: Self) -> Self { self.combine(other, Miniscript::and_n) }
+
+    /// Combines with `other` using `or_b`.
+    pub fn or_b(self, other: Self) -> Self { self.combine(other, Miniscript::or_b) }
+
+    /// Combines with `other` using `or_c`.
+    pub fn or_c(self, other: Self) -> Self { self.combine(other, Miniscript::or_c) }
+
+    /// Combines with `other` using `or_d`.
+    pub fn or_d(self, other: Self) -> Self { self.combine(other, Miniscript::or_d) }
+
+    /// Combines with `other` using `or_i`.
+    pub fn or_i(self, other: Self) -> Self { self.combine(other, Miniscript::or_i) }
+
+    /// Combines with `then` and `els` using `andor`, with `self` as the condition.
+    pub fn andor(self, then: Self, els: Self) -> Self {
+        self.combine(then, |cond, then| {
+            els.inner.and_then(|els| Miniscript::andor(cond, then, els))
+        })
+    }
+
+    /// Requires both `self` and `other` to be satisfied.
+    ///
+    /// This is `and_v(v:self,other)`, omitting the `v:` wrapper if `self` is
+    /// already of type V.
+    pub fn and(self, other: Self) -> Self {
+        let left = match self.ty() {
+            Some(ty) if ty.corr.base == Base::V => self,
+            _ => self.wrap_v(),
+        };
+        left.and_v(other)
+    }
+
+    /// Requires either `self` or `other` to be satisfied.
+    ///
+    /// This is `or_d(self,other)` if `self` can be used as the first child of
+    /// `or_d`, i.e. has type B and the properties d and u, and otherwise
+    /// `or_i(self,other)`.
+    pub fn or(self, other: Self) -> Self {
+        match self.ty() {
+            Some(ty) if ty.corr.base == Base::B && ty.corr.dissatisfiable && ty.corr.unit => {
+                self.or_d(other)
+            }
+            _ => self.or_i(other),
+        }
+    }
+}
+
+impl<Pk: MiniscriptKey, Ctx: ScriptContext> From<Result<Miniscript<Pk, Ctx>, Error>>
+    for Builder<Pk, Ctx>
+{
+    fn from(res: Result<Miniscript<Pk, Ctx>, Error>) -> Self {
+        Builder { inner: res.map(Arc::new) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use super::*;
+    use crate::{Segwitv0, Tap};
+
+    type B = Builder<String, Segwitv0>;
+
+    fn pk(k: &str) -> B { Builder::pk(k.to_owned()) }
+
+    #[test]
+    fn build() {
+        let older = || B::older(RelLockTime::from_height(144));
+
+        let ms = pk("A").and(pk("B").or(older())).build().unwrap();
+        let expected =
+            Miniscript::<String, Segwitv0>::from_str("and_v(v:pk(A),or_d(pk(B),older(144)))")
+                .unwrap();
+        assert_eq!(ms, expected);
+        assert_eq!(ms.ty, expected.ty);
+        assert_eq!(ms.ext, expected.ext);
+
+        // `or` falls back to or_i when or_d is not possible.
+        let ms = older().or(pk("A")).build().unwrap();
+        assert_eq!(ms.to_string(), "or_i(older(144),pk(A))");
+
+        let ms = B::thresh(2, vec![pk("A"), pk("B").wrap_s(), B::pkh("C".to_owned()).wrap_a()])
+            .wrap_v()
+            .and(pk("D").wrap_v().wrap_t())
+            .build()
+            .unwrap();
+        assert_eq!(ms.to_string(), "and_v(v:thresh(2,pk(A),s:pk(B),a:pkh(C)),tv:pk(D))");
+
+        let ms = pk("A").andor(pk("B"), older().wrap_l()).build().unwrap();
+        assert_eq!(ms.to_string(), "andor(pk(A),pk(B),l:older(144))");
+    }
+
+    #[test]
+    fn errors() {
+        // The first error is carried through to the end.
+        let builder = pk("A").and_v(pk("B"));
+        assert!(builder.ty().is_none());
+        assert!(matches!(builder.error(), Some(Error::TypeCheck(_))));
+        let builder = builder.wrap_v().and(pk("C"));
+        assert!(matches!(builder.build(), Err(Error::TypeCheck(_))));
+
+        assert!(matches!(B::thresh(3, vec![pk("A"), pk("B")]).build(), Err(Error::Threshold(_))));
+
+        let keys = vec!["A".to_owned(), "B".to_owned()];
+        assert!(matches!(
+            Builder::<String, Tap>::multi(1, keys.clone()).build(),
+            Err(Error::ContextError(_))
+        ));
+        Builder::<String, Tap>::multi_a(1, keys).build().unwrap();
+    }
+}
@@ -1,22 +1,23 @@
 // Written in 2019 by Sanket Kanjalkar and Andrew Poelstra
 // SPDX-License-Identifier: CC0-1.0
 
-use core::{fmt, hash};
+use core::marker::PhantomData;
+use core::{cmp, fmt, hash};
 #[cfg(feature = "std")]
 use std::error;
 
 use bitcoin::hashes::{hash160, ripemd160, sha256};
-use bitcoin::Weight;
+use bitcoin::taproot::{TAPROOT_CONTROL_BASE_SIZE, TAPROOT_CONTROL_NODE_SIZE};
 
 use super::decode::ParseableKey;
-use crate::miniscript::limits::{
-    MAX_OPS_PER_SCRIPT, MAX_SCRIPTSIG_SIZE, MAX_SCRIPT_ELEMENT_SIZE, MAX_SCRIPT_SIZE,
-    MAX_STACK_SIZE, MAX_STANDARD_P2WSH_SCRIPT_SIZE, MAX_STANDARD_P2WSH_STACK_ITEMS,
-};
-use crate::miniscript::types;
+use crate::descriptor::SatisfactionWeight;
+use crate::iter::TreeLike;
+use crate::miniscript::limits::{LimitUsage, Limits, ResourceUsage, SigopsBudget};
+use crate::miniscript::satisfy::Placeholder;
+use crate::miniscript::types::{self, ExtData, SigSizes};
 use crate::prelude::*;
-use crate::util::witness_to_scriptsig;
-use crate::{hash256, Error, ForEachKey, Miniscript, MiniscriptKey, Terminal};
+use crate::util::{varint_len, witness_to_scriptsig, ItemSize};
+use crate::{hash256, Error, Extension, ForEachKey, Miniscript, MiniscriptKey, Terminal};
 
 /// Error for Script Context
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -64,8 +65,14 @@ pub enum ScriptContextError {
     TaprootMultiDisabled,
     /// Stack size exceeded in script execution
     StackSizeLimitExceeded { actual: usize, limit: usize },
+    /// The signature checks of at least one satisfaction can use more than
+    /// the Tapscript sigops budget of its input.
+    SigopsBudgetExceeded { used: usize, available: usize },
     /// MultiA is only allowed in post tapscript
     MultiANotAllowed,
+    /// BIP 118 keys cannot be used in key hash fragments
+    #[cfg(feature = "apo")]
+    ApoKeyHashNotAllowed(String),
 }
 
 #[cfg(feature = "std")]
@@ -89,7 +96,10 @@ impl error::Error for ScriptContextError {
             | ImpossibleSatisfaction
             | TaprootMultiDisabled
             | StackSizeLimitExceeded { .. }
+            | SigopsBudgetExceeded { .. }
             | MultiANotAllowed => None,
+            #[cfg(feature = "apo")]
+            ApoKeyHashNotAllowed(_) => None,
         }
     }
 }
@@ -160,9 +170,18 @@ impl fmt::Display for ScriptContextError {
                     actual, limit
                 )
             }
+            ScriptContextError::SigopsBudgetExceeded { used, available } => write!(
+                f,
+                "Signature checks can use {} of the sigops budget, but only {} is available",
+                used, available
+            ),
             ScriptContextError::MultiANotAllowed => {
                 write!(f, "Multi a(CHECKSIGADD) only allowed post tapscript")
             }
+            #[cfg(feature = "apo")]
+            ScriptContextError::ApoKeyHashNotAllowed(ref pk) => {
+                write!(f, "ANYPREVOUT key {} cannot be used in a key hash", pk)
+            }
         }
     }
 }
@@ -198,7 +217,16 @@ where
     /// Check whether the given satisfaction is valid under the ScriptContext
     /// For example, segwit satisfactions may fail if the witness len is more
     /// 3600 or number of stack elements are more than 100.
-    fn check_witness(_witness: &[Vec<u8>]) -> Result<(), ScriptContextError> {
+    fn check_witness(witness: &[Vec<u8>]) -> Result<(), ScriptContextError> {
+        Self::check_witness_with_limits(witness, &Limits::BITCOIN)
+    }
+
+    /// Check whether the given satisfaction is valid under the ScriptContext,
+    /// using the given resource limits rather than those of Bitcoin.
+    fn check_witness_with_limits(
+        _witness: &[Vec<u8>],
+        _limits: &Limits,
+    ) -> Result<(), ScriptContextError> {
         // Only really need to do this for segwitv0 and legacy
         // Bare is already restrcited by standardness rules
         // and would reach these limits.
@@ -212,7 +240,36 @@ where
     fn check_pk<Pk: MiniscriptKey>(pk: &Pk) -> Result<(), ScriptContextError>;
 
     /// Depending on script context, the size of a satifaction witness may slightly differ.
-    fn max_satisfaction_size<Pk: MiniscriptKey>(ms: &Miniscript<Pk, Self>) -> Option<usize>;
+    fn max_satisfaction_size<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Option<usize>;
+
+    /// The size of a satisfaction witness, as given by [`Self::max_satisfaction_size`],
+    /// assuming signatures of the given sizes rather than the worst case.
+    ///
+    /// Defaults to the size of the witness stack, which is right for segwit
+    /// contexts.
+    fn max_satisfaction_size_with_sig_sizes<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+        sig_sizes: &SigSizes,
+    ) -> Option<usize> {
+        ExtData::for_sig_sizes(ms, sig_sizes)
+            .max_sat_size
+            .map(|x| x.0)
+    }
+
+    /// The weight of a satisfaction, as given by
+    /// [`Self::max_satisfaction_size_with_sig_sizes`], counted as witness or
+    /// scriptSig data depending on where the context places satisfactions.
+    ///
+    /// Defaults to witness data, which is right for segwit contexts.
+    fn max_satisfaction_weight<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+        sig_sizes: &SigSizes,
+    ) -> Option<SatisfactionWeight> {
+        Self::max_satisfaction_size_with_sig_sizes(ms, sig_sizes)
+            .map(SatisfactionWeight::from_witness_size)
+    }
     /// Depending on script Context, some of the Terminals might not
     /// be valid under the current consensus rules.
     /// Or some of the script resource limits may have been exceeded.
@@ -223,8 +280,17 @@ where
     /// In LegacyP2SH context, scripts above 520 bytes are invalid.
     /// Post Tapscript upgrade, this would have to consider other nodes.
     /// This does *NOT* recursively check the miniscript fragments.
-    fn check_global_consensus_validity<Pk: MiniscriptKey>(
-        _ms: &Miniscript<Pk, Self>,
+    fn check_global_consensus_validity<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Result<(), ScriptContextError> {
+        Self::check_global_consensus_validity_with_limits(ms, &Limits::BITCOIN)
+    }
+
+    /// Check the global consensus rules, using the given resource limits rather
+    /// than those of Bitcoin.
+    fn check_global_consensus_validity_with_limits<Pk: MiniscriptKey, Ext: Extension>(
+        _ms: &Miniscript<Pk, Self, Ext>,
+        _limits: &Limits,
     ) -> Result<(), ScriptContextError> {
         Ok(())
     }
@@ -237,8 +303,17 @@ where
     /// scripts over 3600 bytes are invalid.
     /// Post Tapscript upgrade, this would have to consider other nodes.
     /// This does *NOT* recursively check the miniscript fragments.
-    fn check_global_policy_validity<Pk: MiniscriptKey>(
-        _ms: &Miniscript<Pk, Self>,
+    fn check_global_policy_validity<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Result<(), ScriptContextError> {
+        Self::check_global_policy_validity_with_limits(ms, &Limits::BITCOIN)
+    }
+
+    /// Check the global policy rules, using the given resource limits rather
+    /// than those of Bitcoin.
+    fn check_global_policy_validity_with_limits<Pk: MiniscriptKey, Ext: Extension>(
+        _ms: &Miniscript<Pk, Self, Ext>,
+        _limits: &Limits,
     ) -> Result<(), ScriptContextError> {
         Ok(())
     }
@@ -247,8 +322,17 @@ where
     /// It is possible that some paths of miniscript may exceed resource limits
     /// and our current satisfier and lifting analysis would not work correctly.
     /// For example, satisfaction path(Legacy/Segwitv0) may require more than 201 opcodes.
-    fn check_local_consensus_validity<Pk: MiniscriptKey>(
-        _ms: &Miniscript<Pk, Self>,
+    fn check_local_consensus_validity<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Result<(), ScriptContextError> {
+        Self::check_local_consensus_validity_with_limits(ms, &Limits::BITCOIN)
+    }
+
+    /// Check the consensus rules at satisfaction time, using the given resource limits rather
+    /// than those of Bitcoin.
+    fn check_local_consensus_validity_with_limits<Pk: MiniscriptKey, Ext: Extension>(
+        _ms: &Miniscript<Pk, Self, Ext>,
+        _limits: &Limits,
     ) -> Result<(), ScriptContextError> {
         Ok(())
     }
@@ -258,36 +342,83 @@ where
     /// and our current satisfier and lifting analysis would not work correctly.
     /// For example, satisfaction path in Legacy context scriptSig more
     /// than 1650 bytes
-    fn check_local_policy_validity<Pk: MiniscriptKey>(
-        _ms: &Miniscript<Pk, Self>,
+    fn check_local_policy_validity<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Result<(), ScriptContextError> {
+        Self::check_local_policy_validity_with_limits(ms, &Limits::BITCOIN)
+    }
+
+    /// Check the policy rules at satisfaction time, using the given resource limits rather
+    /// than those of Bitcoin.
+    fn check_local_policy_validity_with_limits<Pk: MiniscriptKey, Ext: Extension>(
+        _ms: &Miniscript<Pk, Self, Ext>,
+        _limits: &Limits,
     ) -> Result<(), ScriptContextError> {
         Ok(())
     }
 
     /// Check the consensus + policy(if not disabled) rules that are not based
     /// satisfaction
-    fn check_global_validity<Pk: MiniscriptKey>(
-        ms: &Miniscript<Pk, Self>,
+    fn check_global_validity<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Result<(), ScriptContextError> {
+        Self::check_global_validity_with_limits(ms, &Limits::BITCOIN)
+    }
+
+    /// Check the consensus + policy rules that are not based on satisfaction,
+    /// using the given resource limits rather than those of Bitcoin.
+    fn check_global_validity_with_limits<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+        limits: &Limits,
     ) -> Result<(), ScriptContextError> {
-        Self::check_global_consensus_validity(ms)?;
-        Self::check_global_policy_validity(ms)?;
+        Self::check_global_consensus_validity_with_limits(ms, limits)?;
+        Self::check_global_policy_validity_with_limits(ms, limits)?;
         Ok(())
     }
 
     /// Check the consensus + policy(if not disabled) rules including the
     /// ones for satisfaction
-    fn check_local_validity<Pk: MiniscriptKey>(
-        ms: &Miniscript<Pk, Self>,
+    fn check_local_validity<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Result<(), ScriptContextError> {
+        Self::check_local_validity_with_limits(ms, &Limits::BITCOIN)
+    }
+
+    /// Check the consensus + policy rules including the ones for satisfaction,
+    /// using the given resource limits rather than those of Bitcoin.
+    fn check_local_validity_with_limits<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+        limits: &Limits,
     ) -> Result<(), ScriptContextError> {
-        Self::check_global_consensus_validity(ms)?;
-        Self::check_global_policy_validity(ms)?;
-        Self::check_local_consensus_validity(ms)?;
-        Self::check_local_policy_validity(ms)?;
+        Self::check_global_consensus_validity_with_limits(ms, limits)?;
+        Self::check_global_policy_validity_with_limits(ms, limits)?;
+        Self::check_local_consensus_validity_with_limits(ms, limits)?;
+        Self::check_local_policy_validity_with_limits(ms, limits)?;
         Ok(())
     }
 
+    /// Reports the worst-case usage of each resource limit of the context by
+    /// the Miniscript and its satisfactions.
+    fn resource_usage<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> ResourceUsage {
+        Self::resource_usage_with_limits(ms, &Limits::BITCOIN)
+    }
+
+    /// Reports the worst-case usage of each resource limit of the context by
+    /// the Miniscript and its satisfactions, using the given resource limits
+    /// rather than those of Bitcoin.
+    fn resource_usage_with_limits<Pk: MiniscriptKey, Ext: Extension>(
+        _ms: &Miniscript<Pk, Self, Ext>,
+        _limits: &Limits,
+    ) -> ResourceUsage {
+        ResourceUsage::default()
+    }
+
     /// Check whether the top-level is type B
-    fn top_level_type_check<Pk: MiniscriptKey>(ms: &Miniscript<Pk, Self>) -> Result<(), Error> {
+    fn top_level_type_check<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Result<(), Error> {
         if ms.ty.corr.base != types::Base::B {
             return Err(Error::NonTopLevel(format!("{:?}", ms)));
         }
@@ -325,7 +456,9 @@ where
     }
 
     /// Other top level checks that are context specific
-    fn other_top_level_checks<Pk: MiniscriptKey>(_ms: &Miniscript<Pk, Self>) -> Result<(), Error> {
+    fn other_top_level_checks<Pk: MiniscriptKey, Ext: Extension>(
+        _ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Result<(), Error> {
         Ok(())
     }
 
@@ -338,7 +471,9 @@ where
     // that are only applicable at the top-level
     // We can also combine the top-level check for Base::B here
     // even though it does not depend on context, but helps in cleaner code
-    fn top_level_checks<Pk: MiniscriptKey>(ms: &Miniscript<Pk, Self>) -> Result<(), Error> {
+    fn top_level_checks<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Result<(), Error> {
         Self::top_level_type_check(ms)?;
         Self::other_top_level_checks(ms)
     }
@@ -397,21 +532,25 @@ impl ScriptContext for Legacy {
         }
     }
 
-    fn check_witness(witness: &[Vec<u8>]) -> Result<(), ScriptContextError> {
+    fn check_witness_with_limits(
+        witness: &[Vec<u8>],
+        limits: &Limits,
+    ) -> Result<(), ScriptContextError> {
         // In future, we could avoid by having a function to count only
         // len of script instead of converting it.
         let script_sig = witness_to_scriptsig(witness);
-        if script_sig.len() > MAX_SCRIPTSIG_SIZE {
+        if script_sig.len() > limits.max_scriptsig_size {
             return Err(ScriptContextError::MaxScriptSigSizeExceeded {
                 actual: script_sig.len(),
-                limit: MAX_SCRIPTSIG_SIZE,
+                limit: limits.max_scriptsig_size,
             });
         }
         Ok(())
     }
 
-    fn check_global_consensus_validity<Pk: MiniscriptKey>(
-        ms: &Miniscript<Pk, Self>,
+    fn check_global_consensus_validity_with_limits<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+        limits: &Limits,
     ) -> Result<(), ScriptContextError> {
         // 1. Check the node first, throw an error on the language itself
         let node_checked = match ms.node {
@@ -428,9 +567,9 @@ impl ScriptContext for Legacy {
         // 2. After fragment and param check, validate the script size finally
         match node_checked {
             Ok(_) => {
-                if ms.ext.pk_cost > MAX_SCRIPT_ELEMENT_SIZE {
+                if ms.ext.pk_cost > limits.max_script_element_size {
                     Err(ScriptContextError::MaxRedeemScriptSizeExceeded {
-                        max: MAX_SCRIPT_ELEMENT_SIZE,
+                        max: limits.max_script_element_size,
                         got: ms.ext.pk_cost,
                     })
                 } else {
@@ -441,44 +580,78 @@ impl ScriptContext for Legacy {
         }
     }
 
-    fn check_local_consensus_validity<Pk: MiniscriptKey>(
-        ms: &Miniscript<Pk, Self>,
+    fn check_local_consensus_validity_with_limits<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+        limits: &Limits,
     ) -> Result<(), ScriptContextError> {
         match ms.ext.ops.op_count() {
             None => Err(ScriptContextError::ImpossibleSatisfaction),
-            Some(op_count) if op_count > MAX_OPS_PER_SCRIPT => {
+            Some(op_count) if op_count > limits.max_ops_per_script => {
                 Err(ScriptContextError::MaxOpCountExceeded {
                     actual: op_count,
-                    limit: MAX_OPS_PER_SCRIPT,
+                    limit: limits.max_ops_per_script,
                 })
             }
             _ => Ok(()),
         }
     }
 
-    fn check_local_policy_validity<Pk: MiniscriptKey>(
-        ms: &Miniscript<Pk, Self>,
+    fn check_local_policy_validity_with_limits<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+        limits: &Limits,
     ) -> Result<(), ScriptContextError> {
         // Legacy scripts permit upto 1000 stack elements, 520 bytes consensus limits
         // on P2SH size, it is not possible to reach the 1000 elements limit and hence
         // we do not check it.
         match ms.max_satisfaction_size() {
             Err(_e) => Err(ScriptContextError::ImpossibleSatisfaction),
-            Ok(size) if size > MAX_SCRIPTSIG_SIZE => {
+            Ok(size) if size > limits.max_scriptsig_size => {
                 Err(ScriptContextError::MaxScriptSigSizeExceeded {
                     actual: size,
-                    limit: MAX_SCRIPTSIG_SIZE,
+                    limit: limits.max_scriptsig_size,
                 })
             }
             _ => Ok(()),
         }
     }
 
-    fn max_satisfaction_size<Pk: MiniscriptKey>(ms: &Miniscript<Pk, Self>) -> Option<usize> {
+    fn resource_usage_with_limits<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+        limits: &Limits,
+    ) -> ResourceUsage {
+        ResourceUsage {
+            script_size: usage(Some(ms.ext.pk_cost), limits.max_script_element_size),
+            op_count: usage(ms.ext.ops.op_count(), limits.max_ops_per_script),
+            scriptsig_size: usage(Self::max_satisfaction_size(ms), limits.max_scriptsig_size),
+            ..ResourceUsage::default()
+        }
+    }
+
+    fn max_satisfaction_size<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Option<usize> {
         // The scriptSig cost is the second element of the tuple
         ms.ext.max_sat_size.map(|x| x.1)
     }
 
+    fn max_satisfaction_size_with_sig_sizes<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+        sig_sizes: &SigSizes,
+    ) -> Option<usize> {
+        // The scriptSig cost is the second element of the tuple
+        ExtData::for_sig_sizes(ms, sig_sizes)
+            .max_sat_size
+            .map(|x| x.1)
+    }
+
+    fn max_satisfaction_weight<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+        sig_sizes: &SigSizes,
+    ) -> Option<SatisfactionWeight> {
+        Self::max_satisfaction_size_with_sig_sizes(ms, sig_sizes)
+            .map(SatisfactionWeight::from_scriptsig_size)
+    }
+
     fn pk_len<Pk: MiniscriptKey>(pk: &Pk) -> usize {
         if pk.is_uncompressed() {
             66
@@ -515,18 +688,22 @@ impl ScriptContext for Segwitv0 {
         }
     }
 
-    fn check_witness(witness: &[Vec<u8>]) -> Result<(), ScriptContextError> {
-        if witness.len() > MAX_STANDARD_P2WSH_STACK_ITEMS {
+    fn check_witness_with_limits(
+        witness: &[Vec<u8>],
+        limits: &Limits,
+    ) -> Result<(), ScriptContextError> {
+        if witness.len() > limits.max_standard_p2wsh_stack_items {
             return Err(ScriptContextError::MaxWitnessItemsExceeded {
                 actual: witness.len(),
-                limit: MAX_STANDARD_P2WSH_STACK_ITEMS,
+                limit: limits.max_standard_p2wsh_stack_items,
             });
         }
         Ok(())
     }
 
-    fn check_global_consensus_validity<Pk: MiniscriptKey>(
-        ms: &Miniscript<Pk, Self>,
+    fn check_global_consensus_validity_with_limits<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+        limits: &Limits,
     ) -> Result<(), ScriptContextError> {
         // 1. Check the node first, throw an error on the language itself
         let node_checked = match ms.node {
@@ -543,9 +720,9 @@ impl ScriptContext for Segwitv0 {
         // 2. After fragment and param check, validate the script size finally
         match node_checked {
             Ok(_) => {
-                if ms.ext.pk_cost > MAX_SCRIPT_SIZE {
+                if ms.ext.pk_cost > limits.max_script_size {
                     Err(ScriptContextError::MaxWitnessScriptSizeExceeded {
-                        max: MAX_SCRIPT_SIZE,
+                        max: limits.max_script_size,
                         got: ms.ext.pk_cost,
                     })
                 } else {
@@ -556,35 +733,38 @@ impl ScriptContext for Segwitv0 {
         }
     }
 
-    fn check_local_consensus_validity<Pk: MiniscriptKey>(
-        ms: &Miniscript<Pk, Self>,
+    fn check_local_consensus_validity_with_limits<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+        limits: &Limits,
     ) -> Result<(), ScriptContextError> {
         match ms.ext.ops.op_count() {
             None => Err(ScriptContextError::ImpossibleSatisfaction),
-            Some(op_count) if op_count > MAX_OPS_PER_SCRIPT => {
+            Some(op_count) if op_count > limits.max_ops_per_script => {
                 Err(ScriptContextError::MaxOpCountExceeded {
                     actual: op_count,
-                    limit: MAX_OPS_PER_SCRIPT,
+                    limit: limits.max_ops_per_script,
                 })
             }
             _ => Ok(()),
         }
     }
 
-    fn check_global_policy_validity<Pk: MiniscriptKey>(
-        ms: &Miniscript<Pk, Self>,
+    fn check_global_policy_validity_with_limits<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+        limits: &Limits,
     ) -> Result<(), ScriptContextError> {
-        if ms.ext.pk_cost > MAX_STANDARD_P2WSH_SCRIPT_SIZE {
+        if ms.ext.pk_cost > limits.max_standard_p2wsh_script_size {
             return Err(ScriptContextError::MaxWitnessScriptSizeExceeded {
-                max: MAX_STANDARD_P2WSH_SCRIPT_SIZE,
+                max: limits.max_standard_p2wsh_script_size,
                 got: ms.ext.pk_cost,
             });
         }
         Ok(())
     }
 
-    fn check_local_policy_validity<Pk: MiniscriptKey>(
-        ms: &Miniscript<Pk, Self>,
+    fn check_local_policy_validity_with_limits<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+        limits: &Limits,
     ) -> Result<(), ScriptContextError> {
         // We don't need to know if this is actually a p2wsh as the standard satisfaction for
         // other Segwitv0 defined programs all require (much) less than 100 elements.
@@ -592,21 +772,30 @@ impl ScriptContext for Segwitv0 {
         match ms.max_satisfaction_witness_elements() {
             // No possible satisfactions
             Err(_e) => Err(ScriptContextError::ImpossibleSatisfaction),
-            Ok(max_witness_items) if max_witness_items > MAX_STANDARD_P2WSH_STACK_ITEMS => {
+            Ok(max_witness_items) if max_witness_items > limits.max_standard_p2wsh_stack_items => {
                 Err(ScriptContextError::MaxWitnessItemsExceeded {
                     actual: max_witness_items,
-                    limit: MAX_STANDARD_P2WSH_STACK_ITEMS,
+                    limit: limits.max_standard_p2wsh_stack_items,
                 })
             }
             _ => Ok(()),
         }
     }
 
-    fn max_satisfaction_size<Pk: MiniscriptKey>(ms: &Miniscript<Pk, Self>) -> Option<usize> {
+    fn max_satisfaction_size<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Option<usize> {
         // The witness stack cost is the first element of the tuple
         ms.ext.max_sat_size.map(|x| x.0)
     }
 
+    fn resource_usage_with_limits<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+        limits: &Limits,
+    ) -> ResourceUsage {
+        segwitv0_resource_usage(ms, limits)
+    }
+
     fn pk_len<Pk: MiniscriptKey>(_pk: &Pk) -> usize { 34 }
 
     fn name_str() -> &'static str { "Segwitv0" }
@@ -618,6 +807,102 @@ impl ScriptContext for Segwitv0 {
 #[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub enum Tap {}
 
+impl Tap {
+    /// Computes the worst-case usage of the Tapscript sigops budget by the
+    /// satisfactions of the given Miniscript.
+    ///
+    /// Every signature is at least 64 bytes, which adds more to the budget
+    /// than its check consumes, so Miniscripts made of the standard fragments
+    /// always fit. Signature checks done by extension fragments are not
+    /// counted.
+    pub fn sigops_budget<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> SigopsBudget {
+        // The maximum number of non-empty signatures in a satisfaction and in
+        // a dissatisfaction of each node.
+        let mut sigs: Vec<(usize, usize)> = vec![];
+        for data in ms.post_order_iter() {
+            let child = |n: usize| sigs[data.child_indices[n]];
+            let node_sigs = match data.node.node {
+                Terminal::Check(..) => (child(0).0 + 1, child(0).1),
+                Terminal::Multi(ref thresh) => (thresh.k(), 0),
+                Terminal::MultiA(ref thresh) => (thresh.k(), 0),
+                Terminal::Alt(..)
+                | Terminal::Swap(..)
+                | Terminal::DupIf(..)
+                | Terminal::Verify(..)
+                | Terminal::NonZero(..)
+                | Terminal::ZeroNotEqual(..) => child(0),
+                Terminal::AndV(..) | Terminal::AndB(..) => {
+                    let (l, r) = (child(0), child(1));
+                    (l.0 + r.0, cmp::max(l.0 + r.1, l.1 + cmp::max(r.0, r.1)))
+                }
+                Terminal::AndOr(..) => {
+                    let (a, b, c) = (child(0), child(1), child(2));
+                    (cmp::max(a.0 + b.0, a.1 + c.0), cmp::max(a.0 + b.1, a.1 + c.1))
+                }
+                Terminal::OrB(..) | Terminal::OrD(..) | Terminal::OrC(..) | Terminal::OrI(..) => {
+                    let (l, r) = (child(0), child(1));
+                    (cmp::max(l.0 + r.1, l.1 + r.0), l.1 + r.1)
+                }
+                Terminal::Thresh(ref thresh) => {
+                    let subs: Vec<_> = (0..thresh.n()).map(child).collect();
+                    let dissat: usize = subs.iter().map(|s| s.1).sum();
+                    // Satisfy the k subs gaining the most signatures.
+                    let mut gains: Vec<_> =
+                        subs.iter().map(|s| s.0 as isize - s.1 as isize).collect();
+                    gains.sort_unstable_by(|a, b| b.cmp(a));
+                    let gain: isize = gains.iter().take(thresh.k()).sum();
+                    ((dissat as isize + gain) as usize, dissat)
+                }
+                _ => (0, 0),
+            };
+            sigs.push(node_sigs);
+        }
+        let sigops = sigs.last().map(|s| s.0).unwrap_or(0);
+
+        // The witness contains at least the script, a 33-byte control block
+        // and a 64-byte signature for each signature check.
+        let script_size = ms.script_size();
+        let witness_size = varint_len(sigops + 2)
+            + varint_len(script_size)
+            + script_size
+            + 1
+            + 33
+            + sigops * (1 + 64);
+        SigopsBudget::new(sigops, witness_size)
+    }
+
+    /// Computes the usage of the Tapscript sigops budget by the given
+    /// satisfaction of a Miniscript, such as one built by
+    /// [`Miniscript::build_template`], when spending it as a leaf at the given
+    /// depth of a Taproot tree.
+    ///
+    /// Signature checks done by extension fragments are not counted.
+    pub fn satisfaction_sigops_budget<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+        satisfaction: &[Placeholder<Pk>],
+        leaf_depth: u8,
+    ) -> SigopsBudget {
+        let sigops = satisfaction
+            .iter()
+            .filter(|item| item.is_script_spend_sig())
+            .count();
+        // The witness is the satisfaction followed by the script and the
+        // control block.
+        let script_size = ms.script_size();
+        let control_block_size =
+            TAPROOT_CONTROL_BASE_SIZE + TAPROOT_CONTROL_NODE_SIZE * leaf_depth as usize;
+        let witness_size = varint_len(satisfaction.len() + 2)
+            + satisfaction.iter().map(ItemSize::size).sum::<usize>()
+            + varint_len(script_size)
+            + script_size
+            + varint_len(control_block_size)
+            + control_block_size;
+        SigopsBudget::new(sigops, witness_size)
+    }
+}
+
 impl ScriptContext for Tap {
     type Key = bitcoin::secp256k1::XOnlyPublicKey;
     fn check_terminal_non_malleable<Pk: MiniscriptKey>(
@@ -637,19 +922,23 @@ impl ScriptContext for Tap {
         }
     }
 
-    fn check_witness(witness: &[Vec<u8>]) -> Result<(), ScriptContextError> {
+    fn check_witness_with_limits(
+        witness: &[Vec<u8>],
+        limits: &Limits,
+    ) -> Result<(), ScriptContextError> {
         // Note that tapscript has a 1000 limit compared to 100 of segwitv0
-        if witness.len() > MAX_STACK_SIZE {
+        if witness.len() > limits.max_stack_size {
             return Err(ScriptContextError::MaxWitnessItemsExceeded {
                 actual: witness.len(),
-                limit: MAX_STACK_SIZE,
+                limit: limits.max_stack_size,
             });
         }
         Ok(())
     }
 
-    fn check_global_consensus_validity<Pk: MiniscriptKey>(
-        ms: &Miniscript<Pk, Self>,
+    fn check_global_consensus_validity_with_limits<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+        limits: &Limits,
     ) -> Result<(), ScriptContextError> {
         // 1. Check the node first, throw an error on the language itself
         let node_checked = match ms.node {
@@ -661,6 +950,10 @@ impl ScriptContext for Tap {
                 Ok(())
             }
             Terminal::Multi(..) => Err(ScriptContextError::TaprootMultiDisabled),
+            #[cfg(feature = "apo")]
+            Terminal::PkH(ref pk) if pk.apo_kind().is_some() => {
+                Err(ScriptContextError::ApoKeyHashNotAllowed(pk.to_string()))
+            }
             _ => Ok(()),
         };
         // 2. After fragment and param check, validate the script size finally
@@ -671,9 +964,9 @@ impl ScriptContext for Tap {
                 // When the transaction sizes get close to block limits,
                 // some guarantees are not easy to satisfy because of knapsack
                 // constraints
-                if ms.ext.pk_cost as u64 > Weight::MAX_BLOCK.to_wu() {
+                if ms.ext.pk_cost > limits.max_block_weight {
                     Err(ScriptContextError::MaxWitnessScriptSizeExceeded {
-                        max: Weight::MAX_BLOCK.to_wu() as usize,
+                        max: limits.max_block_weight,
                         got: ms.ext.pk_cost,
                     })
                 } else {
@@ -684,51 +977,76 @@ impl ScriptContext for Tap {
         }
     }
 
-    fn check_local_consensus_validity<Pk: MiniscriptKey>(
-        ms: &Miniscript<Pk, Self>,
+    fn check_local_consensus_validity_with_limits<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+        limits: &Limits,
     ) -> Result<(), ScriptContextError> {
         // Taproot introduces the concept of sigops budget.
-        // All valid miniscripts satisfy the sigops constraint
         // Whenever we add new fragment that uses pk(pk() or multi based on checksigadd)
         // miniscript typing rules ensure that pk when executed successfully has it's
         // own unique signature. That is, there is no way to re-use signatures from one CHECKSIG
         // to another checksig. In other words, for each successfully executed checksig
         // will have it's corresponding 64 bytes signature.
         // sigops budget = witness_script.len() + witness.size() + 50
-        // Each signature will cover it's own cost(64 > 50) and thus will will never exceed the budget
+        // Each signature will cover it's own cost(64 > 50), but we check the worst case
+        // anyway so that the numbers reported by `Tap::sigops_budget` are enforced.
+        let budget = Self::sigops_budget(ms);
+        if !budget.fits() {
+            return Err(ScriptContextError::SigopsBudgetExceeded {
+                used: budget.used,
+                available: budget.available,
+            });
+        }
         if let (Some(s), Some(h)) = (ms.ext.exec_stack_elem_count_sat, ms.ext.stack_elem_count_sat)
         {
-            if s + h > MAX_STACK_SIZE {
+            if s + h > limits.max_stack_size {
                 return Err(ScriptContextError::StackSizeLimitExceeded {
                     actual: s + h,
-                    limit: MAX_STACK_SIZE,
+                    limit: limits.max_stack_size,
                 });
             }
         }
         Ok(())
     }
 
-    fn check_global_policy_validity<Pk: MiniscriptKey>(
-        _ms: &Miniscript<Pk, Self>,
+    fn check_global_policy_validity_with_limits<Pk: MiniscriptKey, Ext: Extension>(
+        _ms: &Miniscript<Pk, Self, Ext>,
+        _limits: &Limits,
     ) -> Result<(), ScriptContextError> {
         // No script rules, rules are subject to entire tx rules
         Ok(())
     }
 
-    fn check_local_policy_validity<Pk: MiniscriptKey>(
-        _ms: &Miniscript<Pk, Self>,
+    fn check_local_policy_validity_with_limits<Pk: MiniscriptKey, Ext: Extension>(
+        _ms: &Miniscript<Pk, Self, Ext>,
+        _limits: &Limits,
     ) -> Result<(), ScriptContextError> {
         Ok(())
     }
 
-    fn max_satisfaction_size<Pk: MiniscriptKey>(ms: &Miniscript<Pk, Self>) -> Option<usize> {
+    fn max_satisfaction_size<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Option<usize> {
         // The witness stack cost is the first element of the tuple
         ms.ext.max_sat_size.map(|x| x.0)
     }
 
+    fn resource_usage_with_limits<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+        limits: &Limits,
+    ) -> ResourceUsage {
+        tap_resource_usage(ms, limits)
+    }
+
     fn sig_type() -> SigType { SigType::Schnorr }
 
-    fn pk_len<Pk: MiniscriptKey>(_pk: &Pk) -> usize { 33 }
+    fn pk_len<Pk: MiniscriptKey>(_pk: &Pk) -> usize {
+        #[cfg(feature = "apo")]
+        if let Some(kind) = _pk.apo_kind() {
+            return kind.pk_len();
+        }
+        33
+    }
 
     fn name_str() -> &'static str { "TapscriptCtx" }
 }
@@ -761,8 +1079,9 @@ impl ScriptContext for BareCtx {
         }
     }
 
-    fn check_global_consensus_validity<Pk: MiniscriptKey>(
-        ms: &Miniscript<Pk, Self>,
+    fn check_global_consensus_validity_with_limits<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+        limits: &Limits,
     ) -> Result<(), ScriptContextError> {
         // 1. Check the node first, throw an error on the language itself
         let node_checked = match ms.node {
@@ -779,9 +1098,9 @@ impl ScriptContext for BareCtx {
         // 2. After fragment and param check, validate the script size finally
         match node_checked {
             Ok(_) => {
-                if ms.ext.pk_cost > MAX_SCRIPT_SIZE {
+                if ms.ext.pk_cost > limits.max_script_size {
                     Err(ScriptContextError::MaxBareScriptSizeExceeded {
-                        max: MAX_SCRIPT_SIZE,
+                        max: limits.max_script_size,
                         got: ms.ext.pk_cost,
                     })
                 } else {
@@ -792,22 +1111,25 @@ impl ScriptContext for BareCtx {
         }
     }
 
-    fn check_local_consensus_validity<Pk: MiniscriptKey>(
-        ms: &Miniscript<Pk, Self>,
+    fn check_local_consensus_validity_with_limits<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+        limits: &Limits,
     ) -> Result<(), ScriptContextError> {
         match ms.ext.ops.op_count() {
             None => Err(ScriptContextError::ImpossibleSatisfaction),
-            Some(op_count) if op_count > MAX_OPS_PER_SCRIPT => {
+            Some(op_count) if op_count > limits.max_ops_per_script => {
                 Err(ScriptContextError::MaxOpCountExceeded {
                     actual: op_count,
-                    limit: MAX_OPS_PER_SCRIPT,
+                    limit: limits.max_ops_per_script,
                 })
             }
             _ => Ok(()),
         }
     }
 
-    fn other_top_level_checks<Pk: MiniscriptKey>(ms: &Miniscript<Pk, Self>) -> Result<(), Error> {
+    fn other_top_level_checks<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Result<(), Error> {
         match &ms.node {
             Terminal::Check(ref ms) => match &ms.node {
                 Terminal::RawPkH(_pkh) => Ok(()),
@@ -819,11 +1141,42 @@ impl ScriptContext for BareCtx {
         }
     }
 
-    fn max_satisfaction_size<Pk: MiniscriptKey>(ms: &Miniscript<Pk, Self>) -> Option<usize> {
+    fn resource_usage_with_limits<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+        limits: &Limits,
+    ) -> ResourceUsage {
+        ResourceUsage {
+            script_size: usage(Some(ms.ext.pk_cost), limits.max_script_size),
+            op_count: usage(ms.ext.ops.op_count(), limits.max_ops_per_script),
+            scriptsig_size: usage(Self::max_satisfaction_size(ms), limits.max_scriptsig_size),
+            ..ResourceUsage::default()
+        }
+    }
+
+    fn max_satisfaction_size<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Option<usize> {
         // The witness stack cost is the first element of the tuple
         ms.ext.max_sat_size.map(|x| x.1)
     }
 
+    fn max_satisfaction_size_with_sig_sizes<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+        sig_sizes: &SigSizes,
+    ) -> Option<usize> {
+        ExtData::for_sig_sizes(ms, sig_sizes)
+            .max_sat_size
+            .map(|x| x.1)
+    }
+
+    fn max_satisfaction_weight<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+        sig_sizes: &SigSizes,
+    ) -> Option<SatisfactionWeight> {
+        Self::max_satisfaction_size_with_sig_sizes(ms, sig_sizes)
+            .map(SatisfactionWeight::from_scriptsig_size)
+    }
+
     fn pk_len<Pk: MiniscriptKey>(pk: &Pk) -> usize {
         if pk.is_uncompressed() {
             66
@@ -837,16 +1190,17 @@ impl ScriptContext for BareCtx {
     fn sig_type() -> SigType { SigType::Ecdsa }
 }
 
-/// "No Checks Ecdsa" Context
+/// "No Checks" Context
 ///
 /// Used by the "satisified constraints" iterator, which is intended to read
 /// scripts off of the blockchain without doing any sanity checks on them.
+/// Its keys are [`BitcoinKey`](crate::interpreter::BitcoinKey)s, so that it
+/// can represent scripts of both pre-Taproot and Taproot spends.
 /// This context should *NOT* be used unless you know what you are doing.
 #[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub enum NoChecks {}
 impl ScriptContext for NoChecks {
-    // todo: When adding support for interpreter, we need a enum with all supported keys here
-    type Key = bitcoin::PublicKey;
+    type Key = crate::interpreter::BitcoinKey;
     fn check_terminal_non_malleable<Pk: MiniscriptKey>(
         _frag: &Terminal<Pk, Self>,
     ) -> Result<(), ScriptContextError> {
@@ -856,31 +1210,44 @@ impl ScriptContext for NoChecks {
     // No checks in NoChecks
     fn check_pk<Pk: MiniscriptKey>(_pk: &Pk) -> Result<(), ScriptContextError> { Ok(()) }
 
-    fn check_global_policy_validity<Pk: MiniscriptKey>(
-        _ms: &Miniscript<Pk, Self>,
+    fn check_global_policy_validity_with_limits<Pk: MiniscriptKey, Ext: Extension>(
+        _ms: &Miniscript<Pk, Self, Ext>,
+        _limits: &Limits,
     ) -> Result<(), ScriptContextError> {
         Ok(())
     }
 
-    fn check_global_consensus_validity<Pk: MiniscriptKey>(
-        _ms: &Miniscript<Pk, Self>,
+    fn check_global_consensus_validity_with_limits<Pk: MiniscriptKey, Ext: Extension>(
+        _ms: &Miniscript<Pk, Self, Ext>,
+        _limits: &Limits,
     ) -> Result<(), ScriptContextError> {
         Ok(())
     }
 
-    fn check_local_policy_validity<Pk: MiniscriptKey>(
-        _ms: &Miniscript<Pk, Self>,
+    fn check_local_policy_validity_with_limits<Pk: MiniscriptKey, Ext: Extension>(
+        _ms: &Miniscript<Pk, Self, Ext>,
+        _limits: &Limits,
     ) -> Result<(), ScriptContextError> {
         Ok(())
     }
 
-    fn check_local_consensus_validity<Pk: MiniscriptKey>(
-        _ms: &Miniscript<Pk, Self>,
+    fn check_local_consensus_validity_with_limits<Pk: MiniscriptKey, Ext: Extension>(
+        _ms: &Miniscript<Pk, Self, Ext>,
+        _limits: &Limits,
     ) -> Result<(), ScriptContextError> {
         Ok(())
     }
 
-    fn max_satisfaction_size<Pk: MiniscriptKey>(_ms: &Miniscript<Pk, Self>) -> Option<usize> {
+    fn max_satisfaction_size<Pk: MiniscriptKey, Ext: Extension>(
+        _ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Option<usize> {
+        panic!("Tried to compute a satisfaction size bound on a no-checks ecdsa miniscript")
+    }
+
+    fn max_satisfaction_size_with_sig_sizes<Pk: MiniscriptKey, Ext: Extension>(
+        _ms: &Miniscript<Pk, Self, Ext>,
+        _sig_sizes: &SigSizes,
+    ) -> Option<usize> {
         panic!("Tried to compute a satisfaction size bound on a no-checks ecdsa miniscript")
     }
 
@@ -893,53 +1260,378 @@ impl ScriptContext for NoChecks {
         "NochecksEcdsa"
     }
 
-    fn check_witness(_witness: &[Vec<u8>]) -> Result<(), ScriptContextError> {
+    fn check_witness_with_limits(
+        _witness: &[Vec<u8>],
+        _limits: &Limits,
+    ) -> Result<(), ScriptContextError> {
         // Only really need to do this for segwitv0 and legacy
         // Bare is already restrcited by standardness rules
         // and would reach these limits.
         Ok(())
     }
 
-    fn check_global_validity<Pk: MiniscriptKey>(
-        ms: &Miniscript<Pk, Self>,
+    fn top_level_type_check<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Result<(), Error> {
+        if ms.ty.corr.base != types::Base::B {
+            return Err(Error::NonTopLevel(format!("{:?}", ms)));
+        }
+        Ok(())
+    }
+
+    fn other_top_level_checks<Pk: MiniscriptKey, Ext: Extension>(
+        _ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn top_level_checks<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Result<(), Error> {
+        Self::top_level_type_check(ms)?;
+        Self::other_top_level_checks(ms)
+    }
+
+    fn sig_type() -> SigType { SigType::Ecdsa }
+}
+
+/// Parameters of a user-defined script context
+///
+/// The built-in contexts are sealed, as the rest of the library relies on
+/// their exact rules. Users who need to check Miniscripts against different
+/// rules, for example those of a sidechain or of a test network with relaxed
+/// resource limits, may instead implement this trait and use the resulting
+/// [`CustomCtx`]. The rules of the custom context follow those of
+/// [`Segwitv0`] when [`ContextParams::Key`] is an ECDSA key and those of
+/// [`Tap`] when it is an x-only key, with the resource limits taken from
+/// [`ContextParams::LIMITS`].
+///
+/// # Examples
+///
+/// ```
+/// use miniscript::miniscript::limits::Limits;
+/// use miniscript::{ContextParams, CustomCtx, Miniscript};
+///
+/// #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// enum RelaxedParams {}
+///
+/// impl ContextParams for RelaxedParams {
+///     type Key = bitcoin::PublicKey;
+///     const NAME: &'static str = "Relaxed";
+///     const LIMITS: Limits = Limits { max_ops_per_script: 1000, ..Limits::BITCOIN };
+/// }
+///
+/// let ms = "pk(020000000000000000000000000000000000000000000000000000000000000002)"
+///     .parse::<Miniscript<bitcoin::PublicKey, CustomCtx<RelaxedParams>>>()
+///     .unwrap();
+/// ms.sanity_check().unwrap();
+/// ```
+pub trait ContextParams:
+    fmt::Debug + Clone + Ord + PartialOrd + Eq + PartialEq + hash::Hash + 'static
+{
+    /// The consensus key of the context, which also determines its signature type
+    type Key: ParseableKey
+        + MiniscriptKey<
+            Sha256 = sha256::Hash,
+            Hash256 = hash256::Hash,
+            Ripemd160 = ripemd160::Hash,
+            Hash160 = hash160::Hash,
+        >;
+
+    /// Name of the context, used in error messages
+    const NAME: &'static str;
+
+    /// Resource limits of the context
+    const LIMITS: Limits = Limits::BITCOIN;
+
+    /// Checks whether a key is allowed in the context
+    ///
+    /// By default uncompressed keys are never allowed, and x-only keys are
+    /// only allowed in Schnorr contexts.
+    fn check_pk<Pk: MiniscriptKey>(pk: &Pk) -> Result<(), ScriptContextError> {
+        if pk.is_uncompressed() {
+            Err(ScriptContextError::UncompressedKeysNotAllowed)
+        } else if pk.is_x_only_key() && Self::Key::SIG_TYPE == SigType::Ecdsa {
+            Err(ScriptContextError::XOnlyKeysNotAllowed(pk.to_string(), Self::NAME))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Checks whether a fragment is non-malleable in the context
+    ///
+    /// By default no fragment is considered malleable.
+    fn check_terminal_non_malleable<Pk: MiniscriptKey>(
+        _frag: &Terminal<Pk, CustomCtx<Self>>,
     ) -> Result<(), ScriptContextError> {
-        Self::check_global_consensus_validity(ms)?;
-        Self::check_global_policy_validity(ms)?;
         Ok(())
     }
+}
+
+/// User-defined ScriptContext
+///
+/// See [`ContextParams`] for how to define the rules of the context.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum CustomCtx<P: ContextParams> {
+    #[doc(hidden)]
+    _Unreachable(core::convert::Infallible, PhantomData<P>),
+}
+
+impl<P: ContextParams> ScriptContext for CustomCtx<P> {
+    type Key = P::Key;
+
+    fn check_terminal_non_malleable<Pk: MiniscriptKey>(
+        frag: &Terminal<Pk, Self>,
+    ) -> Result<(), ScriptContextError> {
+        P::check_terminal_non_malleable(frag)
+    }
+
+    fn check_pk<Pk: MiniscriptKey>(pk: &Pk) -> Result<(), ScriptContextError> { P::check_pk(pk) }
+
+    fn check_witness(witness: &[Vec<u8>]) -> Result<(), ScriptContextError> {
+        Self::check_witness_with_limits(witness, &P::LIMITS)
+    }
 
-    fn check_local_validity<Pk: MiniscriptKey>(
-        ms: &Miniscript<Pk, Self>,
+    fn check_witness_with_limits(
+        witness: &[Vec<u8>],
+        limits: &Limits,
     ) -> Result<(), ScriptContextError> {
-        Self::check_global_consensus_validity(ms)?;
-        Self::check_global_policy_validity(ms)?;
-        Self::check_local_consensus_validity(ms)?;
-        Self::check_local_policy_validity(ms)?;
+        let limit = match Self::sig_type() {
+            SigType::Ecdsa => limits.max_standard_p2wsh_stack_items,
+            SigType::Schnorr => limits.max_stack_size,
+        };
+        if witness.len() > limit {
+            return Err(ScriptContextError::MaxWitnessItemsExceeded {
+                actual: witness.len(),
+                limit,
+            });
+        }
         Ok(())
     }
 
-    fn top_level_type_check<Pk: MiniscriptKey>(ms: &Miniscript<Pk, Self>) -> Result<(), Error> {
-        if ms.ty.corr.base != types::Base::B {
-            return Err(Error::NonTopLevel(format!("{:?}", ms)));
+    fn check_global_consensus_validity<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Result<(), ScriptContextError> {
+        Self::check_global_consensus_validity_with_limits(ms, &P::LIMITS)
+    }
+
+    fn check_global_consensus_validity_with_limits<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+        limits: &Limits,
+    ) -> Result<(), ScriptContextError> {
+        // 1. Check the node first, throw an error on the language itself
+        match (&ms.node, Self::sig_type()) {
+            (Terminal::PkK(ref pk), _) => Self::check_pk(pk)?,
+            (Terminal::Multi(ref thresh), SigType::Ecdsa) => {
+                for pk in thresh.iter() {
+                    Self::check_pk(pk)?;
+                }
+            }
+            (Terminal::MultiA(ref thresh), SigType::Schnorr) => {
+                for pk in thresh.iter() {
+                    Self::check_pk(pk)?;
+                }
+            }
+            (Terminal::Multi(..), SigType::Schnorr) => {
+                return Err(ScriptContextError::TaprootMultiDisabled)
+            }
+            (Terminal::MultiA(..), SigType::Ecdsa) => {
+                return Err(ScriptContextError::MultiANotAllowed)
+            }
+            #[cfg(feature = "apo")]
+            (Terminal::PkH(ref pk), SigType::Schnorr) if pk.apo_kind().is_some() => {
+                return Err(ScriptContextError::ApoKeyHashNotAllowed(pk.to_string()))
+            }
+            _ => {}
+        }
+        // 2. After fragment and param check, validate the script size finally
+        let max = match Self::sig_type() {
+            SigType::Ecdsa => limits.max_script_size,
+            SigType::Schnorr => limits.max_block_weight,
+        };
+        if ms.ext.pk_cost > max {
+            return Err(ScriptContextError::MaxWitnessScriptSizeExceeded {
+                max,
+                got: ms.ext.pk_cost,
+            });
         }
         Ok(())
     }
 
-    fn other_top_level_checks<Pk: MiniscriptKey>(_ms: &Miniscript<Pk, Self>) -> Result<(), Error> {
+    fn check_global_policy_validity<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Result<(), ScriptContextError> {
+        Self::check_global_policy_validity_with_limits(ms, &P::LIMITS)
+    }
+
+    fn check_global_policy_validity_with_limits<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+        limits: &Limits,
+    ) -> Result<(), ScriptContextError> {
+        if Self::sig_type() == SigType::Ecdsa
+            && ms.ext.pk_cost > limits.max_standard_p2wsh_script_size
+        {
+            return Err(ScriptContextError::MaxWitnessScriptSizeExceeded {
+                max: limits.max_standard_p2wsh_script_size,
+                got: ms.ext.pk_cost,
+            });
+        }
         Ok(())
     }
 
-    fn top_level_checks<Pk: MiniscriptKey>(ms: &Miniscript<Pk, Self>) -> Result<(), Error> {
-        Self::top_level_type_check(ms)?;
-        Self::other_top_level_checks(ms)
+    fn check_local_consensus_validity<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Result<(), ScriptContextError> {
+        Self::check_local_consensus_validity_with_limits(ms, &P::LIMITS)
     }
 
-    fn sig_type() -> SigType { SigType::Ecdsa }
+    fn check_local_consensus_validity_with_limits<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+        limits: &Limits,
+    ) -> Result<(), ScriptContextError> {
+        match Self::sig_type() {
+            SigType::Ecdsa => match ms.ext.ops.op_count() {
+                None => Err(ScriptContextError::ImpossibleSatisfaction),
+                Some(op_count) if op_count > limits.max_ops_per_script => {
+                    Err(ScriptContextError::MaxOpCountExceeded {
+                        actual: op_count,
+                        limit: limits.max_ops_per_script,
+                    })
+                }
+                _ => Ok(()),
+            },
+            SigType::Schnorr => {
+                if let (Some(s), Some(h)) =
+                    (ms.ext.exec_stack_elem_count_sat, ms.ext.stack_elem_count_sat)
+                {
+                    if s + h > limits.max_stack_size {
+                        return Err(ScriptContextError::StackSizeLimitExceeded {
+                            actual: s + h,
+                            limit: limits.max_stack_size,
+                        });
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn check_local_policy_validity<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Result<(), ScriptContextError> {
+        Self::check_local_policy_validity_with_limits(ms, &P::LIMITS)
+    }
+
+    fn check_local_policy_validity_with_limits<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+        limits: &Limits,
+    ) -> Result<(), ScriptContextError> {
+        if Self::sig_type() == SigType::Schnorr {
+            return Ok(());
+        }
+        match ms.max_satisfaction_witness_elements() {
+            // No possible satisfactions
+            Err(_e) => Err(ScriptContextError::ImpossibleSatisfaction),
+            Ok(max_witness_items) if max_witness_items > limits.max_standard_p2wsh_stack_items => {
+                Err(ScriptContextError::MaxWitnessItemsExceeded {
+                    actual: max_witness_items,
+                    limit: limits.max_standard_p2wsh_stack_items,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn check_global_validity<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Result<(), ScriptContextError> {
+        Self::check_global_validity_with_limits(ms, &P::LIMITS)
+    }
+
+    fn check_local_validity<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Result<(), ScriptContextError> {
+        Self::check_local_validity_with_limits(ms, &P::LIMITS)
+    }
+
+    fn resource_usage<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> ResourceUsage {
+        Self::resource_usage_with_limits(ms, &P::LIMITS)
+    }
+
+    fn resource_usage_with_limits<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+        limits: &Limits,
+    ) -> ResourceUsage {
+        match Self::sig_type() {
+            SigType::Ecdsa => segwitv0_resource_usage(ms, limits),
+            SigType::Schnorr => tap_resource_usage(ms, limits),
+        }
+    }
+
+    fn max_satisfaction_size<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Option<usize> {
+        // The witness stack cost is the first element of the tuple
+        ms.ext.max_sat_size.map(|x| x.0)
+    }
+
+    fn pk_len<Pk: MiniscriptKey>(pk: &Pk) -> usize {
+        match Self::sig_type() {
+            SigType::Ecdsa => 34,
+            SigType::Schnorr => Tap::pk_len(pk),
+        }
+    }
+
+    fn name_str() -> &'static str { P::NAME }
+
+    fn sig_type() -> SigType { P::Key::SIG_TYPE }
+}
+
+/// Pairs a worst-case value, if any, with its limit.
+fn usage(used: Option<usize>, limit: usize) -> Option<LimitUsage> {
+    used.map(|used| LimitUsage { used, limit })
+}
+
+/// Resource usage under the rules of [`Segwitv0`], shared with ECDSA custom
+/// contexts.
+fn segwitv0_resource_usage<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension>(
+    ms: &Miniscript<Pk, Ctx, Ext>,
+    limits: &Limits,
+) -> ResourceUsage {
+    // The standardness limit on the script size is smaller than the consensus one.
+    ResourceUsage {
+        script_size: usage(Some(ms.ext.pk_cost), limits.max_standard_p2wsh_script_size),
+        op_count: usage(ms.ext.ops.op_count(), limits.max_ops_per_script),
+        witness_items: usage(
+            ms.max_satisfaction_witness_elements().ok(),
+            limits.max_standard_p2wsh_stack_items,
+        ),
+        ..ResourceUsage::default()
+    }
+}
+
+/// Resource usage under the rules of [`Tap`], shared with Schnorr custom
+/// contexts.
+fn tap_resource_usage<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension>(
+    ms: &Miniscript<Pk, Ctx, Ext>,
+    limits: &Limits,
+) -> ResourceUsage {
+    let stack_size = match (ms.ext.exec_stack_elem_count_sat, ms.ext.stack_elem_count_sat) {
+        (Some(s), Some(h)) => Some(s + h),
+        _ => None,
+    };
+    ResourceUsage {
+        script_size: usage(Some(ms.ext.pk_cost), limits.max_block_weight),
+        stack_size: usage(stack_size, limits.max_stack_size),
+        ..ResourceUsage::default()
+    }
 }
 
 /// Private Mod to prevent downstream from implementing this public trait
 mod private {
-    use super::{BareCtx, Legacy, NoChecks, Segwitv0, Tap};
+    use super::{BareCtx, ContextParams, CustomCtx, Legacy, NoChecks, Segwitv0, Tap};
 
     pub trait Sealed {}
 
@@ -949,4 +1641,5 @@ mod private {
     impl Sealed for Segwitv0 {}
     impl Sealed for Tap {}
     impl Sealed for NoChecks {}
+    impl<P: ContextParams> Sealed for CustomCtx<P> {}
 }
@@ -10,14 +10,20 @@ use core::{fmt, mem};
 use std::error;
 
 use bitcoin::hashes::{hash160, ripemd160, sha256, Hash};
+use bitcoin::hex::DisplayHex;
 use sync::Arc;
 
 use crate::iter::TreeLike;
-use crate::miniscript::lex::{Token as Tk, TokenIter};
-use crate::miniscript::limits::{MAX_PUBKEYS_IN_CHECKSIGADD, MAX_PUBKEYS_PER_MULTISIG};
+use crate::miniscript::context::SigType;
+use crate::miniscript::extensions::{Extension, NoExt};
+use crate::miniscript::lex::{OwnedToken, Token as Tk, TokenIter};
+use crate::miniscript::limits::{
+    MAX_PUBKEYS_IN_CHECKSIGADD, MAX_PUBKEYS_PER_MULTISIG, MAX_SCRIPT_SIZE,
+};
 use crate::miniscript::ScriptContext;
 use crate::prelude::*;
 use crate::primitives::threshold;
+use crate::util::pushed_number;
 #[cfg(doc)]
 use crate::Descriptor;
 use crate::{
@@ -26,23 +32,43 @@ use crate::{
 
 /// Trait for parsing keys from byte slices
 pub trait ParseableKey: Sized + ToPublicKey + private::Sealed {
+    /// The signature algorithm used with keys of this type
+    const SIG_TYPE: SigType;
+
     /// Parse a key from slice
     fn from_slice(sl: &[u8]) -> Result<Self, KeyParseError>;
 }
 
 impl ParseableKey for bitcoin::PublicKey {
+    const SIG_TYPE: SigType = SigType::Ecdsa;
+
     fn from_slice(sl: &[u8]) -> Result<Self, KeyParseError> {
         bitcoin::PublicKey::from_slice(sl).map_err(KeyParseError::FullKeyParseError)
     }
 }
 
 impl ParseableKey for bitcoin::secp256k1::XOnlyPublicKey {
+    const SIG_TYPE: SigType = SigType::Schnorr;
+
     fn from_slice(sl: &[u8]) -> Result<Self, KeyParseError> {
         bitcoin::secp256k1::XOnlyPublicKey::from_slice(sl)
             .map_err(KeyParseError::XonlyKeyParseError)
     }
 }
 
+impl ParseableKey for crate::interpreter::BitcoinKey {
+    // Only used by the `NoChecks` context, whose signature type is nominal
+    const SIG_TYPE: SigType = SigType::Ecdsa;
+
+    fn from_slice(sl: &[u8]) -> Result<Self, KeyParseError> {
+        if sl.len() == 32 {
+            ParseableKey::from_slice(sl).map(Self::XOnlyPublicKey)
+        } else {
+            ParseableKey::from_slice(sl).map(Self::Fullkey)
+        }
+    }
+}
+
 /// Decoding error while parsing keys
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum KeyParseError {
@@ -71,6 +97,121 @@ impl error::Error for KeyParseError {
     }
 }
 
+/// Position of an opcode in a script, such as the one at which decoding a
+/// Miniscript from it failed.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ScriptPosition {
+    /// The byte-index into the script of the opcode.
+    pub byte_offset: usize,
+    /// The index of the opcode among those of the script, counting each push
+    /// as a single opcode.
+    pub opcode_index: usize,
+}
+
+impl fmt::Display for ScriptPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at opcode {}, byte {}", self.opcode_index, self.byte_offset)
+    }
+}
+
+/// Error decoding a Miniscript from Script.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DecodeError {
+    /// Encountered a token which cannot occur at this point in the script.
+    UnexpectedToken(OwnedToken),
+    /// The script is provably unspendable, so cannot be a Miniscript.
+    Unspendable(UnspendableScript),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnexpectedToken(ref tok) => write!(f, "unexpected token «{}»", tok),
+            DecodeError::Unspendable(ref script) => {
+                write!(f, "provably unspendable script: {}", script)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for DecodeError {
+    fn cause(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            DecodeError::UnexpectedToken(..) | DecodeError::Unspendable(..) => None,
+        }
+    }
+}
+
+/// A provably unspendable script
+///
+/// Such scripts fail whatever the stack they are run with, and are used by
+/// outputs carrying data rather than value.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum UnspendableScript {
+    /// `OP_RETURN` followed by pushes only, as in null data outputs, with the
+    /// pushed data
+    NullData(Vec<Vec<u8>>),
+    /// `OP_RETURN` followed by anything other than pushes
+    OpReturn,
+    /// A scriptPubKey larger than the maximum script size, with its size
+    Oversized(usize),
+}
+
+impl UnspendableScript {
+    /// Classifies a scriptPubKey as provably unspendable, using the same rules
+    /// as Bitcoin Core
+    ///
+    /// That is, it starts with `OP_RETURN` or is larger than `MAX_SCRIPT_SIZE`.
+    /// Returns `None` for any other scriptPubKey, even if it cannot be spent.
+    pub fn from_script_pubkey(spk: &bitcoin::Script) -> Option<Self> {
+        if spk.len() > MAX_SCRIPT_SIZE {
+            Some(UnspendableScript::Oversized(spk.len()))
+        } else {
+            Self::from_op_return(spk)
+        }
+    }
+
+    /// Classifies a script starting with `OP_RETURN`, which is unspendable
+    /// whatever its context.
+    pub(crate) fn from_op_return(script: &bitcoin::Script) -> Option<Self> {
+        if !script.is_op_return() {
+            return None;
+        }
+        let data = script
+            .instructions()
+            .skip(1)
+            .map(|ins| match ins.ok()? {
+                bitcoin::script::Instruction::PushBytes(push) => Some(push.as_bytes().to_vec()),
+                bitcoin::script::Instruction::Op(op) => pushed_number(op),
+            })
+            .collect::<Option<Vec<_>>>();
+        Some(data.map_or(UnspendableScript::OpReturn, UnspendableScript::NullData))
+    }
+}
+
+impl fmt::Display for UnspendableScript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnspendableScript::NullData(ref data) => {
+                f.write_str("null data output")?;
+                for push in data {
+                    if push.is_empty() {
+                        f.write_str(" <empty>")?;
+                    } else {
+                        write!(f, " {:x}", push.as_hex())?;
+                    }
+                }
+                Ok(())
+            }
+            UnspendableScript::OpReturn => f.write_str("OP_RETURN output"),
+            UnspendableScript::Oversized(size) => {
+                write!(f, "script of {} bytes, larger than the maximum script size", size)
+            }
+        }
+    }
+}
+
 /// Private Mod to prevent downstream from implementing this public trait
 mod private {
 
@@ -79,6 +220,7 @@ mod private {
     // Implement for those same types, but no others.
     impl Sealed for bitcoin::PublicKey {}
     impl Sealed for bitcoin::secp256k1::XOnlyPublicKey {}
+    impl Sealed for crate::interpreter::BitcoinKey {}
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -116,7 +258,7 @@ enum NonTerm {
 ///
 /// The average user should always use the [`Descriptor`] APIs. Advanced users who want deal
 /// with Miniscript ASTs should use the [`Miniscript`] APIs.
-pub enum Terminal<Pk: MiniscriptKey, Ctx: ScriptContext> {
+pub enum Terminal<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension = NoExt> {
     /// `1`
     True,
     /// `0`
@@ -148,45 +290,93 @@ pub enum Terminal<Pk: MiniscriptKey, Ctx: ScriptContext> {
     Hash160(Pk::Hash160),
     // Wrappers
     /// `TOALTSTACK [E] FROMALTSTACK`
-    Alt(Arc<Miniscript<Pk, Ctx>>),
+    Alt(Arc<Miniscript<Pk, Ctx, Ext>>),
     /// `SWAP [E1]`
-    Swap(Arc<Miniscript<Pk, Ctx>>),
+    Swap(Arc<Miniscript<Pk, Ctx, Ext>>),
     /// `[Kt]/[Ke] CHECKSIG`
-    Check(Arc<Miniscript<Pk, Ctx>>),
+    Check(Arc<Miniscript<Pk, Ctx, Ext>>),
     /// `DUP IF [V] ENDIF`
-    DupIf(Arc<Miniscript<Pk, Ctx>>),
+    DupIf(Arc<Miniscript<Pk, Ctx, Ext>>),
     /// `[T] VERIFY`
-    Verify(Arc<Miniscript<Pk, Ctx>>),
+    Verify(Arc<Miniscript<Pk, Ctx, Ext>>),
     /// `SIZE 0NOTEQUAL IF [Fn] ENDIF`
-    NonZero(Arc<Miniscript<Pk, Ctx>>),
+    NonZero(Arc<Miniscript<Pk, Ctx, Ext>>),
     /// `[X] 0NOTEQUAL`
-    ZeroNotEqual(Arc<Miniscript<Pk, Ctx>>),
+    ZeroNotEqual(Arc<Miniscript<Pk, Ctx, Ext>>),
     // Conjunctions
     /// `[V] [T]/[V]/[F]/[Kt]`
-    AndV(Arc<Miniscript<Pk, Ctx>>, Arc<Miniscript<Pk, Ctx>>),
+    AndV(Arc<Miniscript<Pk, Ctx, Ext>>, Arc<Miniscript<Pk, Ctx, Ext>>),
     /// `[E] [W] BOOLAND`
-    AndB(Arc<Miniscript<Pk, Ctx>>, Arc<Miniscript<Pk, Ctx>>),
+    AndB(Arc<Miniscript<Pk, Ctx, Ext>>, Arc<Miniscript<Pk, Ctx, Ext>>),
     /// `[various] NOTIF [various] ELSE [various] ENDIF`
-    AndOr(Arc<Miniscript<Pk, Ctx>>, Arc<Miniscript<Pk, Ctx>>, Arc<Miniscript<Pk, Ctx>>),
+    AndOr(
+        Arc<Miniscript<Pk, Ctx, Ext>>,
+        Arc<Miniscript<Pk, Ctx, Ext>>,
+        Arc<Miniscript<Pk, Ctx, Ext>>,
+    ),
     // Disjunctions
     /// `[E] [W] BOOLOR`
-    OrB(Arc<Miniscript<Pk, Ctx>>, Arc<Miniscript<Pk, Ctx>>),
+    OrB(Arc<Miniscript<Pk, Ctx, Ext>>, Arc<Miniscript<Pk, Ctx, Ext>>),
     /// `[E] IFDUP NOTIF [T]/[E] ENDIF`
-    OrD(Arc<Miniscript<Pk, Ctx>>, Arc<Miniscript<Pk, Ctx>>),
+    OrD(Arc<Miniscript<Pk, Ctx, Ext>>, Arc<Miniscript<Pk, Ctx, Ext>>),
     /// `[E] NOTIF [V] ENDIF`
-    OrC(Arc<Miniscript<Pk, Ctx>>, Arc<Miniscript<Pk, Ctx>>),
+    OrC(Arc<Miniscript<Pk, Ctx, Ext>>, Arc<Miniscript<Pk, Ctx, Ext>>),
     /// `IF [various] ELSE [various] ENDIF`
-    OrI(Arc<Miniscript<Pk, Ctx>>, Arc<Miniscript<Pk, Ctx>>),
+    OrI(Arc<Miniscript<Pk, Ctx, Ext>>, Arc<Miniscript<Pk, Ctx, Ext>>),
     // Thresholds
     /// `[E] ([W] ADD)* k EQUAL`
-    Thresh(Threshold<Arc<Miniscript<Pk, Ctx>>, 0>),
+    Thresh(Threshold<Arc<Miniscript<Pk, Ctx, Ext>>, 0>),
     /// `k (<key>)* n CHECKMULTISIG`
     Multi(Threshold<Pk, MAX_PUBKEYS_PER_MULTISIG>),
     /// `<key> CHECKSIG (<key> CHECKSIGADD)*(n-1) k NUMEQUAL`
     MultiA(Threshold<Pk, MAX_PUBKEYS_IN_CHECKSIGADD>),
+    /// An extension fragment
+    Ext(Ext),
 }
 
-impl<Pk: MiniscriptKey, Ctx: ScriptContext> Clone for Terminal<Pk, Ctx> {
+impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> Terminal<Pk, Ctx, Ext> {
+    /// Returns a copy of this fragment with its `n`th child replaced, sharing all
+    /// other children, or `None` if there is no such child.
+    pub(crate) fn with_nth_child(
+        &self,
+        n: usize,
+        child: Arc<Miniscript<Pk, Ctx, Ext>>,
+    ) -> Option<Self> {
+        let new = match (n, self) {
+            (0, Terminal::Alt(..)) => Terminal::Alt(child),
+            (0, Terminal::Swap(..)) => Terminal::Swap(child),
+            (0, Terminal::Check(..)) => Terminal::Check(child),
+            (0, Terminal::DupIf(..)) => Terminal::DupIf(child),
+            (0, Terminal::Verify(..)) => Terminal::Verify(child),
+            (0, Terminal::NonZero(..)) => Terminal::NonZero(child),
+            (0, Terminal::ZeroNotEqual(..)) => Terminal::ZeroNotEqual(child),
+            (0, Terminal::AndV(_, r)) => Terminal::AndV(child, Arc::clone(r)),
+            (0, Terminal::AndB(_, r)) => Terminal::AndB(child, Arc::clone(r)),
+            (0, Terminal::OrB(_, r)) => Terminal::OrB(child, Arc::clone(r)),
+            (0, Terminal::OrD(_, r)) => Terminal::OrD(child, Arc::clone(r)),
+            (0, Terminal::OrC(_, r)) => Terminal::OrC(child, Arc::clone(r)),
+            (0, Terminal::OrI(_, r)) => Terminal::OrI(child, Arc::clone(r)),
+            (1, Terminal::AndV(l, _)) => Terminal::AndV(Arc::clone(l), child),
+            (1, Terminal::AndB(l, _)) => Terminal::AndB(Arc::clone(l), child),
+            (1, Terminal::OrB(l, _)) => Terminal::OrB(Arc::clone(l), child),
+            (1, Terminal::OrD(l, _)) => Terminal::OrD(Arc::clone(l), child),
+            (1, Terminal::OrC(l, _)) => Terminal::OrC(Arc::clone(l), child),
+            (1, Terminal::OrI(l, _)) => Terminal::OrI(Arc::clone(l), child),
+            (0, Terminal::AndOr(_, b, c)) => Terminal::AndOr(child, Arc::clone(b), Arc::clone(c)),
+            (1, Terminal::AndOr(a, _, c)) => Terminal::AndOr(Arc::clone(a), child, Arc::clone(c)),
+            (2, Terminal::AndOr(a, b, _)) => Terminal::AndOr(Arc::clone(a), Arc::clone(b), child),
+            (n, Terminal::Thresh(thresh)) if n < thresh.n() => {
+                let mut thresh = thresh.map_ref(Arc::clone);
+                thresh.data_mut()[n] = child;
+                Terminal::Thresh(thresh)
+            }
+            _ => return None,
+        };
+        Some(new)
+    }
+}
+
+impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> Clone for Terminal<Pk, Ctx, Ext> {
     /// We implement clone as a "deep clone" which reconstructs the entire tree.
     ///
     /// If users just want to clone Arcs they can use Arc::clone themselves.
@@ -242,11 +432,12 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Clone for Terminal<Pk, Ctx> {
             }
             Terminal::Multi(ref thresh) => Terminal::Multi(thresh.clone()),
             Terminal::MultiA(ref thresh) => Terminal::MultiA(thresh.clone()),
+            Terminal::Ext(ref ext) => Terminal::Ext(ext.clone()),
         }
     }
 }
 
-impl<Pk: MiniscriptKey, Ctx: ScriptContext> PartialEq for Terminal<Pk, Ctx> {
+impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> PartialEq for Terminal<Pk, Ctx, Ext> {
     fn eq(&self, other: &Self) -> bool {
         for (me, you) in self.pre_order_iter().zip(other.pre_order_iter()) {
             match (me, you) {
@@ -261,6 +452,7 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> PartialEq for Terminal<Pk, Ctx> {
                 (Terminal::Hash160(h1), Terminal::Hash160(h2)) if h1 != h2 => return false,
                 (Terminal::Multi(th1), Terminal::Multi(th2)) if th1 != th2 => return false,
                 (Terminal::MultiA(th1), Terminal::MultiA(th2)) if th1 != th2 => return false,
+                (Terminal::Ext(e1), Terminal::Ext(e2)) if e1 != e2 => return false,
                 _ => {
                     if mem::discriminant(me) != mem::discriminant(you) {
                         return false;
@@ -271,9 +463,11 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> PartialEq for Terminal<Pk, Ctx> {
         true
     }
 }
-impl<Pk: MiniscriptKey, Ctx: ScriptContext> Eq for Terminal<Pk, Ctx> {}
+impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> Eq for Terminal<Pk, Ctx, Ext> {}
 
-impl<Pk: MiniscriptKey, Ctx: ScriptContext> core::hash::Hash for Terminal<Pk, Ctx> {
+impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> core::hash::Hash
+    for Terminal<Pk, Ctx, Ext>
+{
     fn hash<H: core::hash::Hasher>(&self, hasher: &mut H) {
         for term in self.pre_order_iter() {
             mem::discriminant(term).hash(hasher);
@@ -294,6 +488,7 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> core::hash::Hash for Terminal<Pk, Ct
                 }
                 Terminal::Multi(th) => th.hash(hasher),
                 Terminal::MultiA(th) => th.hash(hasher),
+                Terminal::Ext(e) => e.hash(hasher),
                 _ => {}
             }
         }
@@ -309,7 +504,9 @@ macro_rules! match_token {
             $(
                 Some($first) => match_token!($tokens $(,$rest)* => $sub,),
             )*
-            Some(other) => return Err(Error::Unexpected(other.to_string())),
+            Some(other) => {
+                return Err(Error::Decode(DecodeError::UnexpectedToken(other.to_owned_token())))
+            }
             None => return Err(Error::UnexpectedStart),
         }
     };
@@ -317,14 +514,16 @@ macro_rules! match_token {
 
 ///Vec representing terminals stack while decoding.
 #[derive(Debug)]
-struct TerminalStack<Pk: MiniscriptKey, Ctx: ScriptContext>(Vec<Miniscript<Pk, Ctx>>);
+struct TerminalStack<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension>(
+    Vec<Miniscript<Pk, Ctx, Ext>>,
+);
 
-impl<Pk: MiniscriptKey, Ctx: ScriptContext> TerminalStack<Pk, Ctx> {
+impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> TerminalStack<Pk, Ctx, Ext> {
     ///Wrapper around self.0.pop()
-    fn pop(&mut self) -> Option<Miniscript<Pk, Ctx>> { self.0.pop() }
+    fn pop(&mut self) -> Option<Miniscript<Pk, Ctx, Ext>> { self.0.pop() }
 
     ///reduce, type check and push a 0-arg node
-    fn reduce0(&mut self, ms: Terminal<Pk, Ctx>) -> Result<(), Error> {
+    fn reduce0(&mut self, ms: Terminal<Pk, Ctx, Ext>) -> Result<(), Error> {
         let ms = Miniscript::from_ast(ms)?;
         self.0.push(ms);
         Ok(())
@@ -333,7 +532,7 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> TerminalStack<Pk, Ctx> {
     ///reduce, type check and push a 1-arg node
     fn reduce1<F>(&mut self, wrap: F) -> Result<(), Error>
     where
-        F: FnOnce(Arc<Miniscript<Pk, Ctx>>) -> Terminal<Pk, Ctx>,
+        F: FnOnce(Arc<Miniscript<Pk, Ctx, Ext>>) -> Terminal<Pk, Ctx, Ext>,
     {
         let top = self.pop().unwrap();
         let wrapped_ms = wrap(Arc::new(top));
@@ -344,7 +543,10 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> TerminalStack<Pk, Ctx> {
     ///reduce, type check and push a 2-arg node
     fn reduce2<F>(&mut self, wrap: F) -> Result<(), Error>
     where
-        F: FnOnce(Arc<Miniscript<Pk, Ctx>>, Arc<Miniscript<Pk, Ctx>>) -> Terminal<Pk, Ctx>,
+        F: FnOnce(
+            Arc<Miniscript<Pk, Ctx, Ext>>,
+            Arc<Miniscript<Pk, Ctx, Ext>>,
+        ) -> Terminal<Pk, Ctx, Ext>,
     {
         let left = self.pop().unwrap();
         let right = self.pop().unwrap();
@@ -356,10 +558,19 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> TerminalStack<Pk, Ctx> {
 }
 
 /// Parse a script fragment into an `Miniscript`
+///
+/// If the tokens carry their positions in the script, errors are reported
+/// as [`Error::AtScriptPosition`], at the last token read before failing.
+pub fn parse<Ctx: ScriptContext, Ext: Extension>(
+    tokens: &mut TokenIter,
+) -> Result<Miniscript<Ctx::Key, Ctx, Ext>, Error> {
+    parse_tokens(tokens).map_err(|e| tokens.error_at_position(e))
+}
+
 #[allow(unreachable_patterns)]
-pub fn parse<Ctx: ScriptContext>(
+fn parse_tokens<Ctx: ScriptContext, Ext: Extension>(
     tokens: &mut TokenIter,
-) -> Result<Miniscript<Ctx::Key, Ctx>, Error> {
+) -> Result<Miniscript<Ctx::Key, Ctx, Ext>, Error> {
     let mut non_term = Vec::with_capacity(tokens.len());
     let mut term = TerminalStack(Vec::with_capacity(tokens.len()));
 
@@ -369,6 +580,10 @@ pub fn parse<Ctx: ScriptContext>(
     loop {
         match non_term.pop() {
             Some(NonTerm::Expression) => {
+                if let Some(ext) = Ext::from_token_iter(tokens) {
+                    term.reduce0(Terminal::Ext(ext))?;
+                    continue;
+                }
                 match_token!(
                     tokens,
                     // pubkey
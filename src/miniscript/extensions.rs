@@ -0,0 +1,249 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Extension Fragments
+//!
+//! Miniscript may be extended with additional, user-defined fragments, for
+//! example to experiment with proposed opcodes or with the opcodes of other
+//! chains, without forking this library. An extension is a type implementing
+//! [`Extension`], given as the third type parameter of [`Miniscript`] and
+//! [`Terminal`]. Its values appear in the AST as [`Terminal::Ext`] leaves.
+//!
+//! The default extension, [`NoExt`], has no values, and is the only one
+//! supported by descriptors, policies and the interpreter.
+//!
+
+use core::{fmt, hash};
+
+use bitcoin::script;
+
+use crate::expression::TreeIterItem;
+use crate::miniscript::lex::TokenIter;
+use crate::miniscript::satisfy::{Placeholder, Satisfaction};
+use crate::miniscript::types::extra_props::ExtData;
+use crate::miniscript::types::Type;
+use crate::plan::AssetProvider;
+use crate::{Error, ToPublicKey};
+#[cfg(doc)]
+use crate::{Miniscript, Terminal};
+
+/// A user-defined leaf fragment.
+///
+/// Parsing, typing, encoding, decoding and satisfaction of the fragment are
+/// delegated to this trait; the fragment may then be combined with ordinary
+/// Miniscript fragments, subject to the usual type system rules.
+///
+/// Extension fragments do not contain keys, so are unaffected by key
+/// translation.
+pub trait Extension: Clone + Eq + Ord + fmt::Debug + fmt::Display + hash::Hash {
+    /// The correctness and malleability type of the fragment.
+    fn type_check(&self) -> Type;
+
+    /// The extra properties of the fragment, such as its script size and
+    /// the sizes of its (dis)satisfactions.
+    fn extra_props(&self) -> ExtData;
+
+    /// Parses the fragment from an expression tree node whose name, without
+    /// any wrappers, is `name`.
+    ///
+    /// Returns `None` if `name` is not the name of an extension fragment. The
+    /// children of the node, if any, are arguments of the fragment, and are
+    /// not parsed as Miniscript.
+    fn from_name_tree(name: &str, node: TreeIterItem) -> Option<Result<Self, Error>>;
+
+    /// Encodes the fragment as Bitcoin Script.
+    fn push_to_builder(&self, builder: script::Builder) -> script::Builder;
+
+    /// Decodes the fragment from the end of a token stream.
+    ///
+    /// The tokens are read backward, as by the Script parser. Returns `None`
+    /// if the tokens at the end of the stream are not an encoding of the
+    /// fragment, in which case none of them may have been consumed.
+    fn from_token_iter(tokens: &mut TokenIter) -> Option<Self>;
+
+    /// Produces a satisfaction of the fragment.
+    fn satisfy<Pk, P>(&self, provider: &P) -> Satisfaction<Placeholder<Pk>>
+    where
+        Pk: ToPublicKey,
+        P: AssetProvider<Pk>;
+
+    /// Produces a dissatisfaction of the fragment.
+    fn dissatisfy<Pk, P>(&self, provider: &P) -> Satisfaction<Placeholder<Pk>>
+    where
+        Pk: ToPublicKey,
+        P: AssetProvider<Pk>;
+}
+
+/// The empty extension, which has no fragments.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum NoExt {}
+
+impl fmt::Display for NoExt {
+    fn fmt(&self, _: &mut fmt::Formatter) -> fmt::Result { match *self {} }
+}
+
+impl Extension for NoExt {
+    fn type_check(&self) -> Type { match *self {} }
+
+    fn extra_props(&self) -> ExtData { match *self {} }
+
+    fn from_name_tree(_: &str, _: TreeIterItem) -> Option<Result<Self, Error>> { None }
+
+    fn push_to_builder(&self, _: script::Builder) -> script::Builder { match *self {} }
+
+    fn from_token_iter(_: &mut TokenIter) -> Option<Self> { None }
+
+    fn satisfy<Pk, P>(&self, _: &P) -> Satisfaction<Placeholder<Pk>>
+    where
+        Pk: ToPublicKey,
+        P: AssetProvider<Pk>,
+    {
+        match *self {}
+    }
+
+    fn dissatisfy<Pk, P>(&self, _: &P) -> Satisfaction<Placeholder<Pk>>
+    where
+        Pk: ToPublicKey,
+        P: AssetProvider<Pk>,
+    {
+        match *self {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::PublicKey;
+
+    use super::*;
+    use crate::miniscript::lex::Token;
+    use crate::miniscript::satisfy::Witness;
+    use crate::miniscript::types::extra_props::{OpLimits, TimelockInfo};
+    use crate::{script_num_size, Miniscript, Segwitv0, Terminal};
+
+    /// `<n> OP_DROP OP_1`, which behaves as `1` but commits to some data.
+    #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+    struct Mark(u32);
+
+    impl fmt::Display for Mark {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "mark({})", self.0) }
+    }
+
+    impl Extension for Mark {
+        fn type_check(&self) -> Type { Type::TRUE }
+
+        fn extra_props(&self) -> ExtData {
+            ExtData {
+                pk_cost: script_num_size(self.0 as usize) + 2,
+                ops: OpLimits::new(1, Some(0), None),
+                ..ExtData::TRUE
+            }
+        }
+
+        fn from_name_tree(name: &str, node: TreeIterItem) -> Option<Result<Self, Error>> {
+            if name != "mark" {
+                return None;
+            }
+            Some(
+                node.verify_terminal_parent("mark", "number")
+                    .map(Mark)
+                    .map_err(Error::Parse),
+            )
+        }
+
+        fn push_to_builder(&self, builder: script::Builder) -> script::Builder {
+            builder
+                .push_int(self.0.into())
+                .push_opcode(bitcoin::opcodes::all::OP_DROP)
+                .push_opcode(bitcoin::opcodes::OP_TRUE)
+        }
+
+        fn from_token_iter(tokens: &mut TokenIter) -> Option<Self> {
+            let taken: Vec<_> = tokens.take(3).collect();
+            let ret = match taken[..] {
+                [Token::Num(1), Token::Drop, Token::Num(n)] => Some(Mark(n)),
+                _ => None,
+            };
+            if ret.is_none() {
+                for tok in taken.into_iter().rev() {
+                    tokens.un_next(tok);
+                }
+            }
+            ret
+        }
+
+        fn satisfy<Pk, P>(&self, _: &P) -> Satisfaction<Placeholder<Pk>>
+        where
+            Pk: ToPublicKey,
+            P: AssetProvider<Pk>,
+        {
+            Satisfaction {
+                stack: Witness::Stack(vec![]),
+                has_sig: false,
+                absolute_timelock: None,
+                relative_timelock: None,
+            }
+        }
+
+        fn dissatisfy<Pk, P>(&self, _: &P) -> Satisfaction<Placeholder<Pk>>
+        where
+            Pk: ToPublicKey,
+            P: AssetProvider<Pk>,
+        {
+            Satisfaction {
+                stack: Witness::Impossible,
+                has_sig: false,
+                absolute_timelock: None,
+                relative_timelock: None,
+            }
+        }
+    }
+
+    type MarkMs = Miniscript<PublicKey, Segwitv0, Mark>;
+
+    #[test]
+    fn parse_and_display() {
+        let ms = MarkMs::from_str_insane("and_b(mark(7),a:mark(300))").unwrap();
+        assert_eq!(ms.to_string(), "and_b(mark(7),a:mark(300))");
+        assert!(matches!(ms.node, Terminal::AndB(ref l, _) if l.node == Terminal::Ext(Mark(7))));
+        assert_eq!(ms.ext.timelock_info, TimelockInfo::new());
+
+        // Extensions may not be used where their type is not allowed.
+        assert!(MarkMs::from_str_insane("a:mark(7)").is_err());
+        assert!(MarkMs::from_str_insane("mark(x)").is_err());
+        assert!(MarkMs::from_str_insane("mark(1,2)").is_err());
+        // The default extension knows no fragments.
+        assert!(Miniscript::<PublicKey, Segwitv0>::from_str_insane("mark(7)").is_err());
+    }
+
+    #[test]
+    fn encode_decode() {
+        for s in [
+            "and_b(mark(7),a:mark(300))",
+            "and_v(v:pk(020000000000000000000000000000000000000000000000000000000000000002),mark(1))",
+            "or_i(mark(16),and_v(v:1,mark(17)))",
+        ] {
+            let ms = MarkMs::from_str_insane(s).unwrap();
+            let script = ms.encode();
+            assert_eq!(script.len(), ms.script_size());
+            assert_eq!(MarkMs::parse_insane(&script).unwrap(), ms);
+        }
+        assert_eq!(
+            MarkMs::from_str_insane("mark(7)")
+                .unwrap()
+                .encode()
+                .to_hex_string(),
+            "577551"
+        );
+    }
+
+    #[test]
+    fn satisfy() {
+        let ms = MarkMs::from_str_insane("and_b(mark(7),a:mark(300))").unwrap();
+        assert_eq!(ms.satisfy(()).unwrap(), Vec::<Vec<u8>>::new());
+
+        let ms = MarkMs::from_str_insane("or_i(0,mark(8))").unwrap();
+        assert_eq!(ms.satisfy(()).unwrap(), vec![vec![]]);
+
+        // An extension which cannot be dissatisfied cannot be used as the left child of or_d.
+        assert!(MarkMs::from_str_insane("or_d(mark(7),mark(8))").is_err());
+    }
+}
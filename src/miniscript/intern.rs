@@ -0,0 +1,205 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Miniscript Interning
+//!
+//! Hash-consing of Miniscript subtrees, so that structurally identical
+//! subtrees share a single allocation. This saves memory when holding many
+//! descriptors with repeated sub-scripts, and lets equal subtrees be compared
+//! with [`Arc::ptr_eq`] rather than by walking both trees.
+//!
+
+use sync::Arc;
+
+use crate::descriptor::TapTree;
+use crate::iter::TreeLike;
+use crate::prelude::*;
+use crate::{Miniscript, MiniscriptKey, ScriptContext, Tap, Terminal};
+
+/// A set of interned Miniscript subtrees.
+///
+/// Every subtree returned by [`Interner::intern`] is shared with all
+/// structurally identical subtrees previously passed through the same
+/// interner, including subtrees of other Miniscripts.
+#[derive(Debug)]
+pub struct Interner<Pk: MiniscriptKey, Ctx: ScriptContext> {
+    nodes: BTreeSet<Arc<Miniscript<Pk, Ctx>>>,
+}
+
+impl<Pk: MiniscriptKey, Ctx: ScriptContext> Default for Interner<Pk, Ctx> {
+    fn default() -> Self { Self::new() }
+}
+
+impl<Pk: MiniscriptKey, Ctx: ScriptContext> Interner<Pk, Ctx> {
+    /// Constructs a new empty interner.
+    pub fn new() -> Self { Interner { nodes: BTreeSet::new() } }
+
+    /// The number of distinct subtrees held by the interner.
+    pub fn len(&self) -> usize { self.nodes.len() }
+
+    /// Whether the interner holds no subtrees.
+    pub fn is_empty(&self) -> bool { self.nodes.is_empty() }
+
+    /// Removes all subtrees from the interner.
+    ///
+    /// Previously-interned Miniscripts remain valid, but will no longer be
+    /// shared with ones interned afterward.
+    pub fn clear(&mut self) { self.nodes.clear() }
+
+    /// Returns the shared copy of `node`, inserting it if it is new.
+    fn insert(&mut self, node: Miniscript<Pk, Ctx>) -> Arc<Miniscript<Pk, Ctx>> {
+        if let Some(existing) = self.nodes.get(&node) {
+            return Arc::clone(existing);
+        }
+        let node = Arc::new(node);
+        self.nodes.insert(Arc::clone(&node));
+        node
+    }
+
+    /// Interns a Miniscript, returning a copy in which every subtree is shared
+    /// with any identical subtree seen by this interner.
+    ///
+    /// The result compares equal to `ms`. Type information is copied rather
+    /// than recomputed.
+    pub fn intern(&mut self, ms: &Miniscript<Pk, Ctx>) -> Arc<Miniscript<Pk, Ctx>> {
+        // Interned nodes are compared by walking their subtrees, so this is
+        // quadratic in the depth of the tree; but Miniscripts are shallow.
+        let mut stack = vec![];
+        for item in ms.rtl_post_order_iter() {
+            let new_term = match item.node.node {
+                Terminal::PkK(ref p) => Terminal::PkK(p.clone()),
+                Terminal::PkH(ref p) => Terminal::PkH(p.clone()),
+                Terminal::RawPkH(ref hash) => Terminal::RawPkH(*hash),
+                Terminal::After(ref n) => Terminal::After(*n),
+                Terminal::Older(ref n) => Terminal::Older(*n),
+                Terminal::Sha256(ref x) => Terminal::Sha256(x.clone()),
+                Terminal::Hash256(ref x) => Terminal::Hash256(x.clone()),
+                Terminal::Ripemd160(ref x) => Terminal::Ripemd160(x.clone()),
+                Terminal::Hash160(ref x) => Terminal::Hash160(x.clone()),
+                Terminal::True => Terminal::True,
+                Terminal::False => Terminal::False,
+                Terminal::Alt(..) => Terminal::Alt(stack.pop().unwrap()),
+                Terminal::Swap(..) => Terminal::Swap(stack.pop().unwrap()),
+                Terminal::Check(..) => Terminal::Check(stack.pop().unwrap()),
+                Terminal::DupIf(..) => Terminal::DupIf(stack.pop().unwrap()),
+                Terminal::Verify(..) => Terminal::Verify(stack.pop().unwrap()),
+                Terminal::NonZero(..) => Terminal::NonZero(stack.pop().unwrap()),
+                Terminal::ZeroNotEqual(..) => Terminal::ZeroNotEqual(stack.pop().unwrap()),
+                Terminal::AndV(..) => Terminal::AndV(stack.pop().unwrap(), stack.pop().unwrap()),
+                Terminal::AndB(..) => Terminal::AndB(stack.pop().unwrap(), stack.pop().unwrap()),
+                Terminal::AndOr(..) => Terminal::AndOr(
+                    stack.pop().unwrap(),
+                    stack.pop().unwrap(),
+                    stack.pop().unwrap(),
+                ),
+                Terminal::OrB(..) => Terminal::OrB(stack.pop().unwrap(), stack.pop().unwrap()),
+                Terminal::OrD(..) => Terminal::OrD(stack.pop().unwrap(), stack.pop().unwrap()),
+                Terminal::OrC(..) => Terminal::OrC(stack.pop().unwrap(), stack.pop().unwrap()),
+                Terminal::OrI(..) => Terminal::OrI(stack.pop().unwrap(), stack.pop().unwrap()),
+                Terminal::Thresh(ref thresh) => {
+                    Terminal::Thresh(thresh.map_ref(|_| stack.pop().unwrap()))
+                }
+                Terminal::Multi(ref thresh) => Terminal::Multi(thresh.clone()),
+                Terminal::MultiA(ref thresh) => Terminal::MultiA(thresh.clone()),
+                Terminal::Ext(e) => match e {},
+            };
+
+            let node = Miniscript::from_components_unchecked(new_term, item.node.ty, item.node.ext);
+            stack.push(self.insert(node));
+        }
+
+        assert_eq!(stack.len(), 1);
+        stack.pop().unwrap()
+    }
+}
+
+impl<Pk: MiniscriptKey> Interner<Pk, Tap> {
+    /// Interns every leaf of a Taproot tree, so that identical leaves, and
+    /// identical fragments within different leaves, share memory.
+    pub fn intern_tap_tree(&mut self, tree: &TapTree<Pk>) -> TapTree<Pk> {
+        match *tree {
+            TapTree::Tree { ref left, ref right, height } => TapTree::Tree {
+                left: Arc::new(self.intern_tap_tree(left)),
+                right: Arc::new(self.intern_tap_tree(right)),
+                height,
+            },
+            TapTree::Leaf(ref ms) => TapTree::Leaf(self.intern(ms)),
+            TapTree::RawLeaf(ref raw) => TapTree::RawLeaf(raw.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use super::*;
+    use crate::{Descriptor, Segwitv0};
+
+    type Ms = Miniscript<String, Segwitv0>;
+
+    #[test]
+    fn intern() {
+        let mut interner = Interner::new();
+        assert!(interner.is_empty());
+
+        let ms1 = Ms::from_str("or_d(multi(2,A,B,C),and_v(v:pk(D),older(10)))").unwrap();
+        let ms2 = Ms::from_str("andor(pk(E),older(10),multi(2,A,B,C))").unwrap();
+        let int1 = interner.intern(&ms1);
+        assert_eq!(*int1, ms1);
+        assert_eq!(int1.ty, ms1.ty);
+        assert_eq!(int1.ext, ms1.ext);
+        // or_d, multi, and_v, v:pk, pk, pk_k, older
+        assert_eq!(interner.len(), 7);
+
+        let int2 = interner.intern(&ms2);
+        assert_eq!(*int2, ms2);
+        // andor, pk, pk_k, plus the shared multi and older
+        assert_eq!(interner.len(), 10);
+
+        match (&int1.node, &int2.node) {
+            (Terminal::OrD(multi1, and_v), Terminal::AndOr(_, older2, multi2)) => {
+                assert!(Arc::ptr_eq(multi1, multi2));
+                match and_v.node {
+                    Terminal::AndV(_, ref older1) => assert!(Arc::ptr_eq(older1, older2)),
+                    _ => unreachable!(),
+                }
+            }
+            _ => unreachable!(),
+        }
+
+        // Interning again gives the same pointer.
+        assert!(Arc::ptr_eq(&interner.intern(&ms1), &int1));
+        assert_eq!(interner.len(), 10);
+
+        interner.clear();
+        assert!(!Arc::ptr_eq(&interner.intern(&ms1), &int1));
+    }
+
+    #[test]
+    fn intern_tap_tree() {
+        let desc = Descriptor::<String>::from_str(
+            "tr(K,{{and_v(v:pk(A),multi_a(2,B,C,D)),and_v(v:pk(E),multi_a(2,B,C,D))},multi_a(2,B,C,D)})",
+        )
+        .unwrap();
+        let tree = match desc {
+            Descriptor::Tr(ref tr) => tr.tap_tree().clone().unwrap(),
+            _ => unreachable!(),
+        };
+
+        let mut interner = Interner::new();
+        let interned = interner.intern_tap_tree(&tree);
+        assert_eq!(interned, tree);
+
+        let leaves = interned
+            .leaves()
+            .map(|item| item.miniscript().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(leaves.len(), 3);
+        for leaf in &leaves[..2] {
+            match leaf.node {
+                Terminal::AndV(_, ref multi) => assert!(Arc::ptr_eq(multi, leaves[2])),
+                _ => unreachable!(),
+            }
+        }
+    }
+}
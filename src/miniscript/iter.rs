@@ -10,24 +10,36 @@ use core::ops::Deref;
 use sync::Arc;
 
 use super::decode::Terminal;
-use super::{Miniscript, MiniscriptKey, ScriptContext};
+use super::{Extension, Miniscript, MiniscriptKey, NoExt, ScriptContext};
 use crate::prelude::*;
 
 /// Iterator-related extensions for [Miniscript]
-impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
+impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> Miniscript<Pk, Ctx, Ext> {
     /// Creates a new [Iter] iterator that will iterate over all [Miniscript] items within
     /// AST by traversing its branches. For the specific algorithm please see
     /// [Iter::next] function.
-    pub fn iter(&self) -> Iter<Pk, Ctx> { Iter::new(self) }
+    pub fn iter(&self) -> Iter<Pk, Ctx, Ext> { Iter::new(self) }
 
     /// Creates a new [PkIter] iterator that will iterate over all plain public keys (and not
     /// key hash values) present in [Miniscript] items within AST by traversing all its branches.
     /// For the specific algorithm please see [PkIter::next] function.
-    pub fn iter_pk(&self) -> PkIter<Pk, Ctx> { PkIter::new(self) }
+    pub fn iter_pk(&self) -> PkIter<Pk, Ctx, Ext> { PkIter::new(self) }
+
+    /// Creates a new [PathIter] iterator that will iterate over all [Miniscript] items within
+    /// AST in the same order as [Miniscript::iter], along with the path to each of them.
+    pub fn iter_with_paths(&self) -> PathIter<'_, Pk, Ctx, Ext> { PathIter::new(self) }
+
+    /// Returns the node at the given path, if any.
+    ///
+    /// A path is a list of child indices, as used by [Miniscript::get_nth_child], leading
+    /// from `self` to the node. The empty path denotes `self`.
+    pub fn get_at_path(&self, path: &[usize]) -> Option<&Miniscript<Pk, Ctx, Ext>> {
+        path.iter().try_fold(self, |node, &n| node.get_nth_child(n))
+    }
 
     /// Enumerates all child nodes of the current AST node (`self`) and returns a `Vec` referencing
     /// them.
-    pub fn branches(&self) -> Vec<&Miniscript<Pk, Ctx>> {
+    pub fn branches(&self) -> Vec<&Miniscript<Pk, Ctx, Ext>> {
         match self.node {
             Terminal::PkK(_) | Terminal::PkH(_) | Terminal::RawPkH(_) | Terminal::Multi(_) => {
                 vec![]
@@ -57,7 +69,7 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
     }
 
     /// Returns child node with given index, if any
-    pub fn get_nth_child(&self, n: usize) -> Option<&Miniscript<Pk, Ctx>> {
+    pub fn get_nth_child(&self, n: usize) -> Option<&Miniscript<Pk, Ctx, Ext>> {
         match (n, &self.node) {
             (0, Terminal::Alt(node))
             | (0, Terminal::Swap(node))
@@ -104,22 +116,24 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
 
 /// Iterator for traversing all [Miniscript] miniscript AST references starting from some specific
 /// node which constructs the iterator via [Miniscript::iter] method.
-pub struct Iter<'a, Pk: MiniscriptKey, Ctx: ScriptContext> {
-    next: Option<&'a Miniscript<Pk, Ctx>>,
+pub struct Iter<'a, Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension = NoExt> {
+    next: Option<&'a Miniscript<Pk, Ctx, Ext>>,
     // Here we store vec of path elements, where each element is a tuple, consisting of:
     // 1. Miniscript node on the path
     // 2. Index of the current branch
-    path: Vec<(&'a Miniscript<Pk, Ctx>, usize)>,
+    path: Vec<(&'a Miniscript<Pk, Ctx, Ext>, usize)>,
 }
 
-impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext> Iter<'a, Pk, Ctx> {
-    fn new(miniscript: &'a Miniscript<Pk, Ctx>) -> Self {
+impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> Iter<'a, Pk, Ctx, Ext> {
+    fn new(miniscript: &'a Miniscript<Pk, Ctx, Ext>) -> Self {
         Iter { next: Some(miniscript), path: vec![] }
     }
 }
 
-impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext> Iterator for Iter<'a, Pk, Ctx> {
-    type Item = &'a Miniscript<Pk, Ctx>;
+impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> Iterator
+    for Iter<'a, Pk, Ctx, Ext>
+{
+    type Item = &'a Miniscript<Pk, Ctx, Ext>;
 
     /// First, the function returns `self`, then the first child of the self (if any),
     /// then proceeds to the child of the child — down to a leaf of the tree in its first branch.
@@ -161,22 +175,71 @@ impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext> Iterator for Iter<'a, Pk, Ctx> {
     }
 }
 
+/// A [Miniscript] node yielded by [PathIter], along with its position in the tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathItem<'a, Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension = NoExt> {
+    path: Vec<usize>,
+    node: &'a Miniscript<Pk, Ctx, Ext>,
+}
+
+impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> PathItem<'a, Pk, Ctx, Ext> {
+    /// The child indices leading from the root to this node, which can be passed to
+    /// [Miniscript::get_at_path] or [Miniscript::replace_subtree].
+    pub fn path(&self) -> &[usize] { &self.path }
+
+    /// The depth of this node, which is zero for the root.
+    pub fn depth(&self) -> usize { self.path.len() }
+
+    /// The node itself.
+    pub fn node(&self) -> &'a Miniscript<Pk, Ctx, Ext> { self.node }
+}
+
+/// Iterator for traversing all [Miniscript] AST references along with their paths, which
+/// constructs the iterator via [Miniscript::iter_with_paths] method.
+pub struct PathIter<'a, Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension = NoExt> {
+    stack: Vec<PathItem<'a, Pk, Ctx, Ext>>,
+}
+
+impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> PathIter<'a, Pk, Ctx, Ext> {
+    fn new(miniscript: &'a Miniscript<Pk, Ctx, Ext>) -> Self {
+        PathIter { stack: vec![PathItem { path: vec![], node: miniscript }] }
+    }
+}
+
+impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> Iterator
+    for PathIter<'a, Pk, Ctx, Ext>
+{
+    type Item = PathItem<'a, Pk, Ctx, Ext>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.stack.pop()?;
+        let branches = item.node.branches();
+        for (n, child) in branches.into_iter().enumerate().rev() {
+            let mut path = Vec::with_capacity(item.path.len() + 1);
+            path.extend_from_slice(&item.path);
+            path.push(n);
+            self.stack.push(PathItem { path, node: child });
+        }
+        Some(item)
+    }
+}
+
 /// Iterator for traversing all [MiniscriptKey]'s in AST starting from some specific node which
 /// constructs the iterator via [Miniscript::iter_pk] method.
-pub struct PkIter<'a, Pk: MiniscriptKey, Ctx: ScriptContext> {
-    node_iter: Iter<'a, Pk, Ctx>,
-    curr_node: Option<&'a Miniscript<Pk, Ctx>>,
+pub struct PkIter<'a, Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension = NoExt> {
+    node_iter: Iter<'a, Pk, Ctx, Ext>,
+    curr_node: Option<&'a Miniscript<Pk, Ctx, Ext>>,
     key_index: usize,
 }
 
-impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext> PkIter<'a, Pk, Ctx> {
-    fn new(miniscript: &'a Miniscript<Pk, Ctx>) -> Self {
+impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> PkIter<'a, Pk, Ctx, Ext> {
+    fn new(miniscript: &'a Miniscript<Pk, Ctx, Ext>) -> Self {
         let mut iter = Iter::new(miniscript);
         PkIter { curr_node: iter.next(), node_iter: iter, key_index: 0 }
     }
 }
 
-impl<Pk: MiniscriptKey, Ctx: ScriptContext> Iterator for PkIter<'_, Pk, Ctx> {
+impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> Iterator for PkIter<'_, Pk, Ctx, Ext> {
     type Item = Pk;
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -345,4 +408,42 @@ pub mod test {
             assert_eq!(ms.iter_pk().collect::<Vec<bitcoin::PublicKey>>(), k);
         })
     }
+
+    #[test]
+    fn iter_with_paths() {
+        use core::str::FromStr;
+
+        let ms = Miniscript::<String, Segwitv0>::from_str(
+            "or_d(pk(A),thresh(2,pk(B),s:pk(C),a:pkh(D)))",
+        )
+        .unwrap();
+        let items = ms.iter_with_paths().collect::<Vec<_>>();
+        // Same order as `iter`.
+        assert!(items.iter().map(|item| item.node()).eq(ms.iter()));
+        assert_eq!(
+            items
+                .iter()
+                .map(|item| (item.path(), item.depth(), item.node().to_string()))
+                .collect::<Vec<_>>(),
+            vec![
+                (&[][..], 0, "or_d(pk(A),thresh(2,pk(B),s:pk(C),a:pkh(D)))".to_owned()),
+                (&[0], 1, "pk(A)".to_owned()),
+                (&[0, 0], 2, "pk_k(A)".to_owned()),
+                (&[1], 1, "thresh(2,pk(B),s:pk(C),a:pkh(D))".to_owned()),
+                (&[1, 0], 2, "pk(B)".to_owned()),
+                (&[1, 0, 0], 3, "pk_k(B)".to_owned()),
+                (&[1, 1], 2, "s:pk(C)".to_owned()),
+                (&[1, 1, 0], 3, "pk(C)".to_owned()),
+                (&[1, 1, 0, 0], 4, "pk_k(C)".to_owned()),
+                (&[1, 2], 2, "a:pkh(D)".to_owned()),
+                (&[1, 2, 0], 3, "pkh(D)".to_owned()),
+                (&[1, 2, 0, 0], 4, "pk_h(D)".to_owned()),
+            ],
+        );
+        for item in &items {
+            assert_eq!(ms.get_at_path(item.path()), Some(item.node()));
+        }
+        assert_eq!(ms.get_at_path(&[0, 1]), None);
+        assert_eq!(ms.get_at_path(&[1, 3]), None);
+    }
 }
@@ -9,13 +9,18 @@ use core::fmt;
 
 use bitcoin::blockdata::{opcodes, script};
 
+use super::decode::ScriptPosition;
 use super::Error;
 use crate::prelude::*;
 
-/// Atom of a tokenized version of a script
+/// Atom of a tokenized version of a script, generic over the type used to
+/// hold pushed data.
+///
+/// Users will normally interact with this through the [`Token`] alias, which
+/// borrows from the script being decoded, or [`OwnedToken`], which does not.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 #[allow(missing_docs)]
-pub enum Token<'s> {
+pub enum GenericToken<B> {
     BoolAnd,
     BoolOr,
     Add,
@@ -43,240 +48,499 @@ pub enum Token<'s> {
     Hash160,
     Sha256,
     Hash256,
+    Cat,
+    CheckTemplateVerify,
     Num(u32),
-    Hash20(&'s [u8]),
-    Bytes32(&'s [u8]),
-    Bytes33(&'s [u8]),
-    Bytes65(&'s [u8]),
+    Hash20(B),
+    Bytes32(B),
+    Bytes33(B),
+    Bytes65(B),
 }
 
-impl fmt::Display for Token<'_> {
+/// Atom of a tokenized version of a script, borrowing from the script.
+pub type Token<'s> = GenericToken<&'s [u8]>;
+
+/// Atom of a tokenized version of a script, which owns any pushed data.
+///
+/// Used in error types, which cannot borrow from the script.
+pub type OwnedToken = GenericToken<Vec<u8>>;
+
+impl Token<'_> {
+    /// Copies any pushed data out of the script, yielding an [`OwnedToken`].
+    pub fn to_owned_token(&self) -> OwnedToken {
+        match *self {
+            Token::BoolAnd => OwnedToken::BoolAnd,
+            Token::BoolOr => OwnedToken::BoolOr,
+            Token::Add => OwnedToken::Add,
+            Token::Equal => OwnedToken::Equal,
+            Token::NumEqual => OwnedToken::NumEqual,
+            Token::CheckSig => OwnedToken::CheckSig,
+            Token::CheckSigAdd => OwnedToken::CheckSigAdd,
+            Token::CheckMultiSig => OwnedToken::CheckMultiSig,
+            Token::CheckSequenceVerify => OwnedToken::CheckSequenceVerify,
+            Token::CheckLockTimeVerify => OwnedToken::CheckLockTimeVerify,
+            Token::FromAltStack => OwnedToken::FromAltStack,
+            Token::ToAltStack => OwnedToken::ToAltStack,
+            Token::Drop => OwnedToken::Drop,
+            Token::Dup => OwnedToken::Dup,
+            Token::If => OwnedToken::If,
+            Token::IfDup => OwnedToken::IfDup,
+            Token::NotIf => OwnedToken::NotIf,
+            Token::Else => OwnedToken::Else,
+            Token::EndIf => OwnedToken::EndIf,
+            Token::ZeroNotEqual => OwnedToken::ZeroNotEqual,
+            Token::Size => OwnedToken::Size,
+            Token::Swap => OwnedToken::Swap,
+            Token::Verify => OwnedToken::Verify,
+            Token::Ripemd160 => OwnedToken::Ripemd160,
+            Token::Hash160 => OwnedToken::Hash160,
+            Token::Sha256 => OwnedToken::Sha256,
+            Token::Hash256 => OwnedToken::Hash256,
+            Token::Cat => OwnedToken::Cat,
+            Token::CheckTemplateVerify => OwnedToken::CheckTemplateVerify,
+            Token::Num(n) => OwnedToken::Num(n),
+            Token::Hash20(b) => OwnedToken::Hash20(b.to_vec()),
+            Token::Bytes32(b) => OwnedToken::Bytes32(b.to_vec()),
+            Token::Bytes33(b) => OwnedToken::Bytes33(b.to_vec()),
+            Token::Bytes65(b) => OwnedToken::Bytes65(b.to_vec()),
+        }
+    }
+}
+
+impl<B: AsRef<[u8]> + fmt::Debug> fmt::Display for GenericToken<B> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Token::Num(n) => write!(f, "#{}", n),
-            Token::Hash20(b) | Token::Bytes33(b) | Token::Bytes32(b) | Token::Bytes65(b) => {
-                for ch in b {
+            GenericToken::Num(n) => write!(f, "#{}", n),
+            GenericToken::Hash20(ref b)
+            | GenericToken::Bytes33(ref b)
+            | GenericToken::Bytes32(ref b)
+            | GenericToken::Bytes65(ref b) => {
+                for ch in b.as_ref() {
                     write!(f, "{:02x}", *ch)?;
                 }
                 Ok(())
             }
-            x => write!(f, "{:?}", x),
+            ref x => write!(f, "{:?}", x),
+        }
+    }
+}
+
+/// A consensus-valid but non-minimal encoding, which Miniscript never produces.
+///
+/// These are accepted by [`lex_lenient`] instead of being rejected.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum NonMinimalKind {
+    /// Data pushed with a larger opcode than necessary, e.g. a number pushed as
+    /// data rather than with `OP_1` to `OP_16`, or a small push using `OP_PUSHDATA1`.
+    Push,
+    /// A number encoded with more bytes than necessary.
+    Number,
+    /// A separate `OP_VERIFY` following an opcode which has a `VERIFY` form.
+    Verify,
+}
+
+impl fmt::Display for NonMinimalKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NonMinimalKind::Push => f.write_str("non-minimal push"),
+            NonMinimalKind::Number => f.write_str("non-minimal number"),
+            NonMinimalKind::Verify => f.write_str("non-minimal verify"),
         }
     }
 }
 
+/// A non-minimal encoding found by [`lex_lenient`], and where it occurs.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct NonMinimalEncoding {
+    /// The position in the script of the non-minimally encoded opcode.
+    pub position: ScriptPosition,
+    /// The kind of non-minimal encoding.
+    pub kind: NonMinimalKind,
+}
+
+impl fmt::Display for NonMinimalEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.kind, self.position)
+    }
+}
+
 #[derive(Debug, Clone)]
 /// Iterator that goes through a vector of tokens backward (our parser wants to read
 /// backward and this is more efficient anyway since we can use `Vec::pop()`).
-pub struct TokenIter<'s>(Vec<Token<'s>>);
+pub struct TokenIter<'s> {
+    tokens: Vec<Token<'s>>,
+    /// Positions in the script of the tokens, if known.
+    positions: Option<Vec<ScriptPosition>>,
+    /// Position in the script of the most recently consumed token.
+    last_position: Option<ScriptPosition>,
+}
 
 impl<'s> TokenIter<'s> {
     /// Create a new TokenIter
-    pub fn new(v: Vec<Token<'s>>) -> TokenIter<'s> { TokenIter(v) }
+    pub fn new(v: Vec<Token<'s>>) -> TokenIter<'s> {
+        TokenIter { tokens: v, positions: None, last_position: None }
+    }
+
+    /// Create a new TokenIter over tokens with known positions in the script,
+    /// as returned by [`lex_with_positions`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is not exactly one position per token.
+    pub fn with_positions(tokens: Vec<Token<'s>>, positions: Vec<ScriptPosition>) -> Self {
+        assert_eq!(tokens.len(), positions.len());
+        TokenIter { tokens, positions: Some(positions), last_position: None }
+    }
 
     /// Look at the top at Iterator
-    pub fn peek(&self) -> Option<&'s Token> { self.0.last() }
+    pub fn peek(&self) -> Option<&'s Token> { self.tokens.last() }
 
     /// Push a value to the iterator
     /// This will be first value consumed by popun_
-    pub fn un_next(&mut self, tok: Token<'s>) { self.0.push(tok) }
+    pub fn un_next(&mut self, tok: Token<'s>) {
+        self.tokens.push(tok);
+        if let Some(ref mut positions) = self.positions {
+            positions.push(self.last_position.unwrap_or_default());
+        }
+    }
 
     /// The len of the iterator
-    pub fn len(&self) -> usize { self.0.len() }
+    pub fn len(&self) -> usize { self.tokens.len() }
 
     /// Returns true if iterator is empty.
-    pub fn is_empty(&self) -> bool { self.0.is_empty() }
+    pub fn is_empty(&self) -> bool { self.tokens.is_empty() }
+
+    /// The position in the script of the most recently consumed token, if
+    /// positions are known.
+    ///
+    /// Since the script is read backward, this is the leftmost position read
+    /// so far. If no token has been consumed, this is the end of the script.
+    pub fn position(&self) -> Option<ScriptPosition> {
+        self.positions.as_ref()?;
+        Some(self.last_position.unwrap_or_default())
+    }
+
+    /// Attaches the current position, if known, to an error.
+    pub(crate) fn error_at_position(&self, e: Error) -> Error {
+        match (self.position(), e) {
+            (_, e @ Error::AtScriptPosition(..)) => e,
+            (Some(pos), e) => Error::AtScriptPosition(pos, Box::new(e)),
+            (None, e) => e,
+        }
+    }
 }
 
 impl<'s> Iterator for TokenIter<'s> {
     type Item = Token<'s>;
 
-    fn next(&mut self) -> Option<Token<'s>> { self.0.pop() }
+    fn next(&mut self) -> Option<Token<'s>> {
+        let tok = self.tokens.pop()?;
+        if let Some(ref mut positions) = self.positions {
+            self.last_position = positions.pop();
+        }
+        Some(tok)
+    }
+}
+
+/// A token lexed from a script by a [`Lexer`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LexedToken<'s> {
+    /// The token.
+    pub token: Token<'s>,
+    /// The position in the script of the opcode the token was lexed from.
+    /// Opcodes with a `VERIFY` form are lexed into two tokens, which share it.
+    pub position: ScriptPosition,
+    /// The non-minimal encodings of the opcode the token was lexed from, which
+    /// are only accepted when lexing leniently. They are given with the first
+    /// token of the opcode only.
+    pub non_minimal: &'static [NonMinimalKind],
+}
+
+/// Streaming lexer, yielding the tokens of a script in order.
+///
+/// Unlike [`lex`], the lexer does not allocate, so it can be used to cheaply
+/// check whether a script may be a Miniscript, e.g. when scanning the outputs
+/// of whole blocks, before decoding it. Lexing stops after the first error.
+#[derive(Debug, Clone)]
+pub struct Lexer<'s> {
+    script: &'s script::Script,
+    instructions: script::Instructions<'s>,
+    lenient: bool,
+    opcode_index: usize,
+    /// The most recently yielded token.
+    last: Option<Token<'s>>,
+    /// The second token of an opcode lexed into two.
+    pending: Option<LexedToken<'s>>,
+    has_errored: bool,
+}
+
+impl<'s> Lexer<'s> {
+    /// Creates a lexer which rejects non-minimal encodings.
+    pub fn new(script: &'s script::Script) -> Self {
+        Lexer {
+            script,
+            instructions: script.instructions_minimal(),
+            lenient: false,
+            opcode_index: 0,
+            last: None,
+            pending: None,
+            has_errored: false,
+        }
+    }
+
+    /// Creates a lexer which accepts consensus-valid encodings which are not
+    /// minimal, as [`lex_lenient`] does, and reports them in
+    /// [`LexedToken::non_minimal`].
+    pub fn lenient(script: &'s script::Script) -> Self {
+        Lexer { instructions: script.instructions(), lenient: true, ..Lexer::new(script) }
+    }
+
+    /// Lexes the next instruction, returning its first token and keeping its
+    /// second one, if any, pending.
+    fn lex_next(
+        &mut self,
+        ins: Result<script::Instruction<'s>, script::Error>,
+        position: ScriptPosition,
+    ) -> Result<LexedToken<'s>, Error> {
+        let ins = ins.map_err(Error::Script)?;
+        let (token, second, kind) = lex_instruction(ins, self.last.as_ref(), self.lenient)?;
+        let non_minimal_push = match ins {
+            script::Instruction::PushBytes(bytes) if self.lenient => {
+                let len =
+                    self.script.len() - self.instructions.as_script().len() - position.byte_offset;
+                len > minimal_push_len(bytes.as_bytes())
+            }
+            _ => false,
+        };
+        self.pending = second.map(|token| LexedToken { token, position, non_minimal: &[] });
+        Ok(LexedToken { token, position, non_minimal: non_minimal_kinds(non_minimal_push, kind) })
+    }
+}
+
+impl<'s> Iterator for Lexer<'s> {
+    type Item = Result<LexedToken<'s>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let lexed = match self.pending.take() {
+            Some(lexed) => lexed,
+            None => {
+                if self.has_errored {
+                    return None;
+                }
+                let byte_offset = self.script.len() - self.instructions.as_script().len();
+                let position = ScriptPosition { byte_offset, opcode_index: self.opcode_index };
+                let ins = self.instructions.next()?;
+                self.opcode_index += 1;
+                match self.lex_next(ins, position) {
+                    Ok(lexed) => lexed,
+                    Err(e) => {
+                        self.has_errored = true;
+                        return Some(Err(Error::AtScriptPosition(position, Box::new(e))));
+                    }
+                }
+            }
+        };
+        self.last = Some(lexed.token);
+        Some(Ok(lexed))
+    }
 }
 
 /// Tokenize a script
 pub fn lex(script: &'_ script::Script) -> Result<Vec<Token<'_>>, Error> {
-    let mut ret = Vec::with_capacity(script.len());
+    Lexer::new(script)
+        .map(|lexed| lexed.map(|lexed| lexed.token))
+        .collect()
+}
 
-    for ins in script.instructions_minimal() {
-        match ins.map_err(Error::Script)? {
-            script::Instruction::Op(opcodes::all::OP_BOOLAND) => {
-                ret.push(Token::BoolAnd);
-            }
-            script::Instruction::Op(opcodes::all::OP_BOOLOR) => {
-                ret.push(Token::BoolOr);
-            }
-            script::Instruction::Op(opcodes::all::OP_EQUAL) => {
-                ret.push(Token::Equal);
-            }
-            script::Instruction::Op(opcodes::all::OP_EQUALVERIFY) => {
-                ret.push(Token::Equal);
-                ret.push(Token::Verify);
-            }
-            script::Instruction::Op(opcodes::all::OP_NUMEQUAL) => {
-                ret.push(Token::NumEqual);
-            }
-            script::Instruction::Op(opcodes::all::OP_NUMEQUALVERIFY) => {
-                ret.push(Token::NumEqual);
-                ret.push(Token::Verify);
-            }
-            script::Instruction::Op(opcodes::all::OP_CHECKSIG) => {
-                ret.push(Token::CheckSig);
-            }
-            script::Instruction::Op(opcodes::all::OP_CHECKSIGVERIFY) => {
-                ret.push(Token::CheckSig);
-                ret.push(Token::Verify);
-            }
-            // Change once the opcode name is updated
-            script::Instruction::Op(opcodes::all::OP_CHECKSIGADD) => {
-                ret.push(Token::CheckSigAdd);
-            }
-            script::Instruction::Op(opcodes::all::OP_CHECKMULTISIG) => {
-                ret.push(Token::CheckMultiSig);
-            }
-            script::Instruction::Op(opcodes::all::OP_CHECKMULTISIGVERIFY) => {
-                ret.push(Token::CheckMultiSig);
-                ret.push(Token::Verify);
-            }
-            script::Instruction::Op(opcodes::all::OP_CSV) => {
-                ret.push(Token::CheckSequenceVerify);
-            }
-            script::Instruction::Op(opcodes::all::OP_CLTV) => {
-                ret.push(Token::CheckLockTimeVerify);
-            }
-            script::Instruction::Op(opcodes::all::OP_FROMALTSTACK) => {
-                ret.push(Token::FromAltStack);
-            }
-            script::Instruction::Op(opcodes::all::OP_TOALTSTACK) => {
-                ret.push(Token::ToAltStack);
-            }
-            script::Instruction::Op(opcodes::all::OP_DROP) => {
-                ret.push(Token::Drop);
-            }
-            script::Instruction::Op(opcodes::all::OP_DUP) => {
-                ret.push(Token::Dup);
-            }
-            script::Instruction::Op(opcodes::all::OP_ADD) => {
-                ret.push(Token::Add);
-            }
-            script::Instruction::Op(opcodes::all::OP_IF) => {
-                ret.push(Token::If);
-            }
-            script::Instruction::Op(opcodes::all::OP_IFDUP) => {
-                ret.push(Token::IfDup);
-            }
-            script::Instruction::Op(opcodes::all::OP_NOTIF) => {
-                ret.push(Token::NotIf);
-            }
-            script::Instruction::Op(opcodes::all::OP_ELSE) => {
-                ret.push(Token::Else);
-            }
-            script::Instruction::Op(opcodes::all::OP_ENDIF) => {
-                ret.push(Token::EndIf);
-            }
-            script::Instruction::Op(opcodes::all::OP_0NOTEQUAL) => {
-                ret.push(Token::ZeroNotEqual);
-            }
-            script::Instruction::Op(opcodes::all::OP_SIZE) => {
-                ret.push(Token::Size);
-            }
-            script::Instruction::Op(opcodes::all::OP_SWAP) => {
-                ret.push(Token::Swap);
-            }
-            script::Instruction::Op(opcodes::all::OP_VERIFY) => {
-                match ret.last() {
-                    Some(op @ &Token::Equal)
-                    | Some(op @ &Token::CheckSig)
-                    | Some(op @ &Token::CheckMultiSig) => {
-                        return Err(Error::NonMinimalVerify(format!("{:?}", op)))
+/// Tokenize a script, returning the position in the script of each token.
+///
+/// Errors are reported as [`Error::AtScriptPosition`], at the opcode which
+/// could not be tokenized.
+pub fn lex_with_positions(
+    script: &'_ script::Script,
+) -> Result<(Vec<Token<'_>>, Vec<ScriptPosition>), Error> {
+    lex_inner(Lexer::new(script), None)
+}
+
+/// Tokenize a script, accepting consensus-valid encodings which are not
+/// minimal, such as those of some historic scripts.
+///
+/// Returns the tokens and their positions as [`lex_with_positions`] does,
+/// along with every non-minimal encoding encountered. Since Miniscript always
+/// encodes minimally, re-encoding a Miniscript decoded from these tokens
+/// yields a different script whenever the latter is non-empty.
+#[allow(clippy::type_complexity)]
+pub fn lex_lenient(
+    script: &'_ script::Script,
+) -> Result<(Vec<Token<'_>>, Vec<ScriptPosition>, Vec<NonMinimalEncoding>), Error> {
+    let mut non_minimal = vec![];
+    let (tokens, positions) = lex_inner(Lexer::lenient(script), Some(&mut non_minimal))?;
+    Ok((tokens, positions, non_minimal))
+}
+
+/// Collects the tokens of a lexer and their positions, recording non-minimal
+/// encodings in `non_minimal` if given.
+fn lex_inner<'s>(
+    lexer: Lexer<'s>,
+    mut non_minimal: Option<&mut Vec<NonMinimalEncoding>>,
+) -> Result<(Vec<Token<'s>>, Vec<ScriptPosition>), Error> {
+    let mut tokens = vec![];
+    let mut positions = vec![];
+    for lexed in lexer {
+        let lexed = lexed?;
+        if let Some(ref mut non_minimal) = non_minimal {
+            non_minimal.extend(
+                lexed
+                    .non_minimal
+                    .iter()
+                    .map(|&kind| NonMinimalEncoding { position: lexed.position, kind }),
+            );
+        }
+        tokens.push(lexed.token);
+        positions.push(lexed.position);
+    }
+    Ok((tokens, positions))
+}
+
+/// The non-minimal encodings of an opcode, given whether it is a non-minimal
+/// push and the kind of non-minimal encoding found by [`lex_instruction`].
+fn non_minimal_kinds(push: bool, kind: Option<NonMinimalKind>) -> &'static [NonMinimalKind] {
+    match (push, kind) {
+        (false, None) => &[],
+        (true, None) | (_, Some(NonMinimalKind::Push)) => &[NonMinimalKind::Push],
+        (false, Some(NonMinimalKind::Number)) => &[NonMinimalKind::Number],
+        (false, Some(NonMinimalKind::Verify)) => &[NonMinimalKind::Verify],
+        (true, Some(NonMinimalKind::Number)) => &[NonMinimalKind::Push, NonMinimalKind::Number],
+        (true, Some(NonMinimalKind::Verify)) => &[NonMinimalKind::Push, NonMinimalKind::Verify],
+    }
+}
+
+/// The length of the shortest encoding of a push of `data`.
+fn minimal_push_len(data: &[u8]) -> usize {
+    match data.len() {
+        0 => 1,
+        1 if (1..=16).contains(&data[0]) || data[0] == 0x81 => 1,
+        n @ 1..=75 => 1 + n,
+        n @ 76..=0xff => 2 + n,
+        n @ 0x100..=0xffff => 3 + n,
+        n => 5 + n,
+    }
+}
+
+/// Tokenizes a single instruction into one or two tokens, given the token
+/// preceding it.
+///
+/// If `lenient`, also returns the kind of non-minimal encoding of the
+/// instruction, if any, rather than rejecting it. Non-minimal pushes are not
+/// detected here.
+#[allow(clippy::type_complexity)]
+fn lex_instruction<'s>(
+    ins: script::Instruction<'s>,
+    last: Option<&Token<'s>>,
+    lenient: bool,
+) -> Result<(Token<'s>, Option<Token<'s>>, Option<NonMinimalKind>), Error> {
+    let mut non_minimal = None;
+    let (first, second) = match ins {
+        script::Instruction::Op(opcodes::all::OP_BOOLAND) => (Token::BoolAnd, None),
+        script::Instruction::Op(opcodes::all::OP_BOOLOR) => (Token::BoolOr, None),
+        script::Instruction::Op(opcodes::all::OP_EQUAL) => (Token::Equal, None),
+        script::Instruction::Op(opcodes::all::OP_EQUALVERIFY) => {
+            (Token::Equal, Some(Token::Verify))
+        }
+        script::Instruction::Op(opcodes::all::OP_NUMEQUAL) => (Token::NumEqual, None),
+        script::Instruction::Op(opcodes::all::OP_NUMEQUALVERIFY) => {
+            (Token::NumEqual, Some(Token::Verify))
+        }
+        script::Instruction::Op(opcodes::all::OP_CHECKSIG) => (Token::CheckSig, None),
+        script::Instruction::Op(opcodes::all::OP_CHECKSIGVERIFY) => {
+            (Token::CheckSig, Some(Token::Verify))
+        }
+        // Change once the opcode name is updated
+        script::Instruction::Op(opcodes::all::OP_CHECKSIGADD) => (Token::CheckSigAdd, None),
+        script::Instruction::Op(opcodes::all::OP_CHECKMULTISIG) => (Token::CheckMultiSig, None),
+        script::Instruction::Op(opcodes::all::OP_CHECKMULTISIGVERIFY) => {
+            (Token::CheckMultiSig, Some(Token::Verify))
+        }
+        script::Instruction::Op(opcodes::all::OP_CSV) => (Token::CheckSequenceVerify, None),
+        script::Instruction::Op(opcodes::all::OP_CLTV) => (Token::CheckLockTimeVerify, None),
+        script::Instruction::Op(opcodes::all::OP_FROMALTSTACK) => (Token::FromAltStack, None),
+        script::Instruction::Op(opcodes::all::OP_TOALTSTACK) => (Token::ToAltStack, None),
+        script::Instruction::Op(opcodes::all::OP_DROP) => (Token::Drop, None),
+        script::Instruction::Op(opcodes::all::OP_DUP) => (Token::Dup, None),
+        script::Instruction::Op(opcodes::all::OP_ADD) => (Token::Add, None),
+        script::Instruction::Op(opcodes::all::OP_IF) => (Token::If, None),
+        script::Instruction::Op(opcodes::all::OP_IFDUP) => (Token::IfDup, None),
+        script::Instruction::Op(opcodes::all::OP_NOTIF) => (Token::NotIf, None),
+        script::Instruction::Op(opcodes::all::OP_ELSE) => (Token::Else, None),
+        script::Instruction::Op(opcodes::all::OP_ENDIF) => (Token::EndIf, None),
+        script::Instruction::Op(opcodes::all::OP_0NOTEQUAL) => (Token::ZeroNotEqual, None),
+        script::Instruction::Op(opcodes::all::OP_SIZE) => (Token::Size, None),
+        script::Instruction::Op(opcodes::all::OP_SWAP) => (Token::Swap, None),
+        script::Instruction::Op(opcodes::all::OP_VERIFY) => {
+            match last {
+                Some(op @ &Token::Equal)
+                | Some(op @ &Token::CheckSig)
+                | Some(op @ &Token::CheckMultiSig) => {
+                    if !lenient {
+                        return Err(Error::NonMinimalVerify(format!("{:?}", op)));
                     }
-                    _ => {}
+                    non_minimal = Some(NonMinimalKind::Verify);
                 }
-                ret.push(Token::Verify);
-            }
-            script::Instruction::Op(opcodes::all::OP_RIPEMD160) => {
-                ret.push(Token::Ripemd160);
+                _ => {}
             }
-            script::Instruction::Op(opcodes::all::OP_HASH160) => {
-                ret.push(Token::Hash160);
-            }
-            script::Instruction::Op(opcodes::all::OP_SHA256) => {
-                ret.push(Token::Sha256);
-            }
-            script::Instruction::Op(opcodes::all::OP_HASH256) => {
-                ret.push(Token::Hash256);
-            }
-            script::Instruction::PushBytes(bytes) => {
-                match bytes.len() {
-                    20 => ret.push(Token::Hash20(bytes.as_bytes())),
-                    32 => ret.push(Token::Bytes32(bytes.as_bytes())),
-                    33 => ret.push(Token::Bytes33(bytes.as_bytes())),
-                    65 => ret.push(Token::Bytes65(bytes.as_bytes())),
-                    _ => {
-                        // check minimality of the number
-                        match script::read_scriptint(bytes.as_bytes()) {
-                            Ok(v) if v >= 0 => {
-                                ret.push(Token::Num(v as u32));
-                            }
-                            Ok(_) => return Err(Error::InvalidPush(bytes.to_owned().into())),
-                            Err(e) => return Err(Error::Script(e)),
+            (Token::Verify, None)
+        }
+        script::Instruction::Op(opcodes::all::OP_RIPEMD160) => (Token::Ripemd160, None),
+        script::Instruction::Op(opcodes::all::OP_HASH160) => (Token::Hash160, None),
+        script::Instruction::Op(opcodes::all::OP_SHA256) => (Token::Sha256, None),
+        script::Instruction::Op(opcodes::all::OP_HASH256) => (Token::Hash256, None),
+        // Opcodes of proposed soft forks, only used by the unstable fragments.
+        #[cfg(feature = "unstable-fragments")]
+        script::Instruction::Op(opcodes::all::OP_CAT) => (Token::Cat, None),
+        #[cfg(feature = "unstable-fragments")]
+        script::Instruction::Op(opcodes::all::OP_NOP4) => (Token::CheckTemplateVerify, None),
+        script::Instruction::PushBytes(bytes) => {
+            match bytes.len() {
+                20 => (Token::Hash20(bytes.as_bytes()), None),
+                32 => (Token::Bytes32(bytes.as_bytes()), None),
+                33 => (Token::Bytes33(bytes.as_bytes()), None),
+                65 => (Token::Bytes65(bytes.as_bytes()), None),
+                _ => {
+                    // check minimality of the number
+                    let v = match script::read_scriptint(bytes.as_bytes()) {
+                        Ok(v) => v,
+                        Err(script::Error::NonMinimalPush) if lenient => {
+                            non_minimal = Some(NonMinimalKind::Number);
+                            script::read_scriptint_non_minimal(bytes.as_bytes())
+                                .map_err(Error::Script)?
                         }
+                        Err(e) => return Err(Error::Script(e)),
+                    };
+                    if v < 0 {
+                        return Err(Error::InvalidPush(bytes.to_owned().into()));
                     }
+                    (Token::Num(v as u32), None)
                 }
             }
-            script::Instruction::Op(opcodes::all::OP_PUSHBYTES_0) => {
-                ret.push(Token::Num(0));
-            }
-            script::Instruction::Op(opcodes::all::OP_PUSHNUM_1) => {
-                ret.push(Token::Num(1));
-            }
-            script::Instruction::Op(opcodes::all::OP_PUSHNUM_2) => {
-                ret.push(Token::Num(2));
-            }
-            script::Instruction::Op(opcodes::all::OP_PUSHNUM_3) => {
-                ret.push(Token::Num(3));
-            }
-            script::Instruction::Op(opcodes::all::OP_PUSHNUM_4) => {
-                ret.push(Token::Num(4));
-            }
-            script::Instruction::Op(opcodes::all::OP_PUSHNUM_5) => {
-                ret.push(Token::Num(5));
-            }
-            script::Instruction::Op(opcodes::all::OP_PUSHNUM_6) => {
-                ret.push(Token::Num(6));
-            }
-            script::Instruction::Op(opcodes::all::OP_PUSHNUM_7) => {
-                ret.push(Token::Num(7));
-            }
-            script::Instruction::Op(opcodes::all::OP_PUSHNUM_8) => {
-                ret.push(Token::Num(8));
-            }
-            script::Instruction::Op(opcodes::all::OP_PUSHNUM_9) => {
-                ret.push(Token::Num(9));
-            }
-            script::Instruction::Op(opcodes::all::OP_PUSHNUM_10) => {
-                ret.push(Token::Num(10));
-            }
-            script::Instruction::Op(opcodes::all::OP_PUSHNUM_11) => {
-                ret.push(Token::Num(11));
-            }
-            script::Instruction::Op(opcodes::all::OP_PUSHNUM_12) => {
-                ret.push(Token::Num(12));
-            }
-            script::Instruction::Op(opcodes::all::OP_PUSHNUM_13) => {
-                ret.push(Token::Num(13));
-            }
-            script::Instruction::Op(opcodes::all::OP_PUSHNUM_14) => {
-                ret.push(Token::Num(14));
-            }
-            script::Instruction::Op(opcodes::all::OP_PUSHNUM_15) => {
-                ret.push(Token::Num(15));
-            }
-            script::Instruction::Op(opcodes::all::OP_PUSHNUM_16) => {
-                ret.push(Token::Num(16));
-            }
-            script::Instruction::Op(op) => return Err(Error::InvalidOpcode(op)),
-        };
-    }
-    Ok(ret)
+        }
+        script::Instruction::Op(opcodes::all::OP_PUSHBYTES_0) => (Token::Num(0), None),
+        script::Instruction::Op(opcodes::all::OP_PUSHNUM_1) => (Token::Num(1), None),
+        script::Instruction::Op(opcodes::all::OP_PUSHNUM_2) => (Token::Num(2), None),
+        script::Instruction::Op(opcodes::all::OP_PUSHNUM_3) => (Token::Num(3), None),
+        script::Instruction::Op(opcodes::all::OP_PUSHNUM_4) => (Token::Num(4), None),
+        script::Instruction::Op(opcodes::all::OP_PUSHNUM_5) => (Token::Num(5), None),
+        script::Instruction::Op(opcodes::all::OP_PUSHNUM_6) => (Token::Num(6), None),
+        script::Instruction::Op(opcodes::all::OP_PUSHNUM_7) => (Token::Num(7), None),
+        script::Instruction::Op(opcodes::all::OP_PUSHNUM_8) => (Token::Num(8), None),
+        script::Instruction::Op(opcodes::all::OP_PUSHNUM_9) => (Token::Num(9), None),
+        script::Instruction::Op(opcodes::all::OP_PUSHNUM_10) => (Token::Num(10), None),
+        script::Instruction::Op(opcodes::all::OP_PUSHNUM_11) => (Token::Num(11), None),
+        script::Instruction::Op(opcodes::all::OP_PUSHNUM_12) => (Token::Num(12), None),
+        script::Instruction::Op(opcodes::all::OP_PUSHNUM_13) => (Token::Num(13), None),
+        script::Instruction::Op(opcodes::all::OP_PUSHNUM_14) => (Token::Num(14), None),
+        script::Instruction::Op(opcodes::all::OP_PUSHNUM_15) => (Token::Num(15), None),
+        script::Instruction::Op(opcodes::all::OP_PUSHNUM_16) => (Token::Num(16), None),
+        script::Instruction::Op(op) => return Err(Error::InvalidOpcode(op)),
+    };
+    Ok((first, second, non_minimal))
 }
@@ -36,3 +36,167 @@ pub const MAX_PUBKEYS_PER_MULTISIG: usize = 20;
 /// Maximum pubkeys in a CHECKSIGADD construction.
 // https://github.com/bitcoin/bitcoin/blob/99b06b7f1d4194fb8036b90e5308101645f968e7/src/script/script.h#L36
 pub const MAX_PUBKEYS_IN_CHECKSIGADD: usize = 999;
+/// Tapscript sigops budget available to every input, in addition to the
+/// size of its witness, see BIP 342.
+pub const VALIDATION_WEIGHT_OFFSET: usize = 50;
+/// Tapscript sigops budget consumed by each signature check with a non-empty
+/// signature, see BIP 342.
+pub const VALIDATION_WEIGHT_PER_SIGOP_PASSED: usize = 50;
+
+/// Resource limits used when checking Miniscripts and their satisfactions.
+///
+/// The checks done by [`ScriptContext`](super::context::ScriptContext) normally
+/// use the limits of Bitcoin, given by [`Limits::BITCOIN`]. Chains with
+/// different consensus or policy limits, such as signet forks, can construct
+/// their own `Limits` and pass them to the `*_with_limits` checks, e.g.
+///
+/// ```
+/// use miniscript::miniscript::limits::Limits;
+///
+/// let limits = Limits { max_ops_per_script: 1000, ..Limits::BITCOIN };
+/// ```
+///
+/// Parsing always checks the Bitcoin limits which apply to the script itself,
+/// such as its size. Scripts exceeding the limits on satisfactions, such as the
+/// opcode count, can be parsed with [`ExtParams::insane`](super::analyzable::ExtParams::insane)
+/// and then checked with [`Miniscript::check_resource_limits`](super::Miniscript::check_resource_limits).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Limits {
+    /// Maximum operations per script.
+    pub max_ops_per_script: usize,
+    /// Maximum p2wsh initial stack items.
+    pub max_standard_p2wsh_stack_items: usize,
+    /// Maximum script size allowed by consensus rules.
+    pub max_script_size: usize,
+    /// Maximum p2wsh script size allowed by standardness rules.
+    pub max_standard_p2wsh_script_size: usize,
+    /// Maximum script element size allowed by consensus rules.
+    pub max_script_element_size: usize,
+    /// Maximum script sig size allowed by standardness rules.
+    pub max_scriptsig_size: usize,
+    /// Maximum items during stack execution.
+    pub max_stack_size: usize,
+    /// Maximum weight of a block.
+    pub max_block_weight: usize,
+}
+
+impl Limits {
+    /// The limits of the Bitcoin network.
+    pub const BITCOIN: Limits = Limits {
+        max_ops_per_script: MAX_OPS_PER_SCRIPT,
+        max_standard_p2wsh_stack_items: MAX_STANDARD_P2WSH_STACK_ITEMS,
+        max_script_size: MAX_SCRIPT_SIZE,
+        max_standard_p2wsh_script_size: MAX_STANDARD_P2WSH_SCRIPT_SIZE,
+        max_script_element_size: MAX_SCRIPT_ELEMENT_SIZE,
+        max_scriptsig_size: MAX_SCRIPTSIG_SIZE,
+        max_stack_size: MAX_STACK_SIZE,
+        max_block_weight: MAX_BLOCK_WEIGHT,
+    };
+}
+
+impl Default for Limits {
+    fn default() -> Self { Limits::BITCOIN }
+}
+
+/// Worst-case usage of the Tapscript sigops budget by the satisfactions of a
+/// Miniscript, as returned by [`Tap::sigops_budget`](super::context::Tap::sigops_budget).
+///
+/// BIP 342 gives every input a budget of [`VALIDATION_WEIGHT_OFFSET`] plus the
+/// serialized size of its witness, and charges
+/// [`VALIDATION_WEIGHT_PER_SIGOP_PASSED`] for every signature check with a
+/// non-empty signature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SigopsBudget {
+    /// Maximum number of non-empty signatures checked by a satisfaction.
+    pub sigops: usize,
+    /// Budget consumed by these signature checks.
+    pub used: usize,
+    /// Smallest budget available to a satisfaction with this many signatures.
+    pub available: usize,
+}
+
+impl SigopsBudget {
+    /// The budget usage of an input with a witness of the given serialized
+    /// size, checking the given number of non-empty signatures.
+    pub fn new(sigops: usize, witness_size: usize) -> Self {
+        SigopsBudget {
+            sigops,
+            used: sigops * VALIDATION_WEIGHT_PER_SIGOP_PASSED,
+            available: VALIDATION_WEIGHT_OFFSET + witness_size,
+        }
+    }
+
+    /// Whether the satisfactions fit within the budget.
+    pub fn fits(&self) -> bool { self.used <= self.available }
+
+    /// The budget left over by the worst-case satisfaction, if it fits.
+    pub fn remaining(&self) -> Option<usize> { self.available.checked_sub(self.used) }
+}
+
+/// Worst-case usage of a single resource limit, as reported in a
+/// [`ResourceUsage`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LimitUsage {
+    /// Worst-case value reached by the Miniscript or its satisfactions.
+    pub used: usize,
+    /// Limit applying to this value in the script context.
+    pub limit: usize,
+}
+
+impl LimitUsage {
+    /// Whether the worst-case value is within the limit.
+    pub fn fits(&self) -> bool { self.used <= self.limit }
+
+    /// How much the worst-case value may grow before exceeding the limit, if
+    /// it fits.
+    pub fn margin(&self) -> Option<usize> { self.limit.checked_sub(self.used) }
+}
+
+/// Worst-case usage of each resource limit by a Miniscript and its
+/// satisfactions, as returned by
+/// [`Miniscript::resource_usage`](super::Miniscript::resource_usage).
+///
+/// Limits which do not apply in the script context of the Miniscript are
+/// `None`, as are those on satisfactions if the Miniscript cannot be
+/// satisfied. Where both a consensus and a standardness limit apply to the
+/// same value, the smaller one is reported.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ResourceUsage {
+    /// Size of the script, in bytes.
+    pub script_size: Option<LimitUsage>,
+    /// Number of non-push opcodes executed by a satisfaction.
+    pub op_count: Option<LimitUsage>,
+    /// Number of witness elements of a satisfaction, including the witness
+    /// script.
+    pub witness_items: Option<LimitUsage>,
+    /// Number of stack elements during the execution of a satisfaction.
+    pub stack_size: Option<LimitUsage>,
+    /// Size of the scriptSig of a satisfaction, in bytes.
+    pub scriptsig_size: Option<LimitUsage>,
+}
+
+impl ResourceUsage {
+    /// Iterates over the limits applying to the Miniscript, along with the
+    /// name of the field reporting each.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, LimitUsage)> {
+        [
+            ("script_size", self.script_size),
+            ("op_count", self.op_count),
+            ("witness_items", self.witness_items),
+            ("stack_size", self.stack_size),
+            ("scriptsig_size", self.scriptsig_size),
+        ]
+        .into_iter()
+        .filter_map(|(name, usage)| usage.map(|usage| (name, usage)))
+    }
+
+    /// Whether every applying limit fits.
+    pub fn fits(&self) -> bool { self.iter().all(|(_, usage)| usage.fits()) }
+
+    /// The applying limit with the smallest margin, which is the first to be
+    /// exceeded as the Miniscript grows. Limits which are already exceeded
+    /// have no margin and come first.
+    pub fn tightest(&self) -> Option<(&'static str, LimitUsage)> {
+        self.iter().min_by_key(|(_, usage)| usage.margin())
+    }
+}
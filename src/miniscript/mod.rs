@@ -19,16 +19,20 @@ use bitcoin::script;
 use bitcoin::taproot::{LeafVersion, TapLeafHash};
 
 use self::analyzable::ExtParams;
-pub use self::context::{BareCtx, Legacy, Segwitv0, Tap};
+pub use self::context::{BareCtx, ContextParams, CustomCtx, Legacy, Segwitv0, Tap};
+pub use self::extensions::{Extension, NoExt};
 use crate::iter::TreeLike;
 use crate::prelude::*;
 use crate::{script_num_size, TranslateErr};
 
 pub mod analyzable;
 pub mod astelem;
+pub mod builder;
 pub(crate) mod context;
 pub mod decode;
 mod display;
+pub mod extensions;
+pub mod intern;
 pub mod iter;
 pub mod lex;
 pub mod limits;
@@ -39,12 +43,13 @@ use core::cmp;
 
 use sync::Arc;
 
-use self::lex::{lex, TokenIter};
+use self::lex::{lex_lenient, lex_with_positions, NonMinimalEncoding, TokenIter};
 use crate::expression::{FromTree, TreeIterItem};
 pub use crate::miniscript::context::ScriptContext;
 use crate::miniscript::decode::Terminal;
 use crate::{
-    expression, plan, Error, ForEachKey, FromStrKey, MiniscriptKey, ToPublicKey, Translator,
+    expression, plan, Error, ForEachKey, FromStrKey, MiniscriptKey, ParseMiniscriptError,
+    Threshold, ToPublicKey, Translator,
 };
 #[cfg(test)]
 mod ms_tests;
@@ -57,12 +62,13 @@ mod private {
     pub use crate::miniscript::context::ScriptContext;
     use crate::miniscript::types;
     use crate::prelude::sync::Arc;
-    use crate::{AbsLockTime, Error, MiniscriptKey, RelLockTime, Terminal, MAX_RECURSION_DEPTH};
+    use crate::prelude::Vec;
+    use crate::{AbsLockTime, Error, Extension, MiniscriptKey, NoExt, RelLockTime, Terminal};
 
     /// The top-level miniscript abstract syntax tree (AST).
-    pub struct Miniscript<Pk: MiniscriptKey, Ctx: ScriptContext> {
+    pub struct Miniscript<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension = NoExt> {
         /// A node in the AST.
-        pub node: Terminal<Pk, Ctx>,
+        pub node: Terminal<Pk, Ctx, Ext>,
         /// The correctness and malleability type information for the AST node.
         pub ty: types::Type,
         /// Additional information helpful for extra analysis.
@@ -71,7 +77,7 @@ mod private {
         phantom: PhantomData<Ctx>,
     }
 
-    impl<Pk: MiniscriptKey, Ctx: ScriptContext> Clone for Miniscript<Pk, Ctx> {
+    impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> Clone for Miniscript<Pk, Ctx, Ext> {
         /// We implement clone as a "deep clone" which reconstructs the entire tree.
         ///
         /// If users just want to clone Arcs they can use Arc::clone themselves.
@@ -126,6 +132,7 @@ mod private {
                     }
                     Terminal::Multi(ref thresh) => Terminal::Multi(thresh.clone()),
                     Terminal::MultiA(ref thresh) => Terminal::MultiA(thresh.clone()),
+                    Terminal::Ext(ref ext) => Terminal::Ext(ext.clone()),
                 };
 
                 stack.push(Arc::new(Miniscript {
@@ -141,7 +148,56 @@ mod private {
         }
     }
 
-    impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
+    impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> Drop for Miniscript<Pk, Ctx, Ext> {
+        /// We implement drop without recursion, so that dropping a deep tree
+        /// cannot overflow the stack.
+        ///
+        /// Children shared with other Miniscripts are left for their last owner
+        /// to drop.
+        fn drop(&mut self) {
+            let mut stack = vec![];
+            take_children(&mut self.node, &mut stack);
+            while let Some(child) = stack.pop() {
+                if let Ok(mut child) = Arc::try_unwrap(child) {
+                    take_children(&mut child.node, &mut stack);
+                }
+            }
+        }
+    }
+
+    /// Replaces the node with a leaf, moving its children onto the stack.
+    fn take_children<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension>(
+        node: &mut Terminal<Pk, Ctx, Ext>,
+        stack: &mut Vec<Arc<Miniscript<Pk, Ctx, Ext>>>,
+    ) {
+        match core::mem::replace(node, Terminal::True) {
+            Terminal::Alt(sub)
+            | Terminal::Swap(sub)
+            | Terminal::Check(sub)
+            | Terminal::DupIf(sub)
+            | Terminal::Verify(sub)
+            | Terminal::NonZero(sub)
+            | Terminal::ZeroNotEqual(sub) => stack.push(sub),
+            Terminal::AndV(left, right)
+            | Terminal::AndB(left, right)
+            | Terminal::OrB(left, right)
+            | Terminal::OrD(left, right)
+            | Terminal::OrC(left, right)
+            | Terminal::OrI(left, right) => {
+                stack.push(left);
+                stack.push(right);
+            }
+            Terminal::AndOr(a, b, c) => {
+                stack.push(a);
+                stack.push(b);
+                stack.push(c);
+            }
+            Terminal::Thresh(thresh) => stack.extend(thresh.into_data()),
+            leaf => *node = leaf,
+        }
+    }
+
+    impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> Miniscript<Pk, Ctx, Ext> {
         /// The `1` combinator.
         pub const TRUE: Self = Miniscript {
             node: Terminal::True,
@@ -273,20 +329,13 @@ mod private {
         /// Add type information(Type and Extdata) to Miniscript based on
         /// `AstElem` fragment. Dependent on display and clone because of Error
         /// Display code of type_check.
-        pub fn from_ast(t: Terminal<Pk, Ctx>) -> Result<Miniscript<Pk, Ctx>, Error> {
+        pub fn from_ast(t: Terminal<Pk, Ctx, Ext>) -> Result<Miniscript<Pk, Ctx, Ext>, Error> {
             let res = Miniscript {
                 ty: Type::type_check(&t)?,
                 ext: ExtData::type_check(&t),
                 node: t,
                 phantom: PhantomData,
             };
-            // TODO: This recursion depth is based on segwitv0.
-            // We can relax this in tapscript, but this should be good for almost
-            // all practical cases and we can revisit this if needed.
-            // casting to u32 is safe because tree_height will never go more than u32::MAX
-            if (res.ext.tree_height as u32) > MAX_RECURSION_DEPTH {
-                return Err(Error::MaxRecursiveDepthExceeded);
-            }
             Ctx::check_global_validity(&res)?;
             Ok(res)
         }
@@ -297,10 +346,10 @@ mod private {
         ///
         /// You should almost always use `Miniscript::from_ast` instead of this function.
         pub fn from_components_unchecked(
-            node: Terminal<Pk, Ctx>,
+            node: Terminal<Pk, Ctx, Ext>,
             ty: types::Type,
             ext: types::extra_props::ExtData,
-        ) -> Miniscript<Pk, Ctx> {
+        ) -> Miniscript<Pk, Ctx, Ext> {
             Miniscript { node, ty, ext, phantom: PhantomData }
         }
     }
@@ -308,12 +357,103 @@ mod private {
 
 pub use private::Miniscript;
 
-impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
+/// Typed constructors for combinators and wrappers.
+///
+/// These typecheck each new node using only the (already computed) types of its
+/// children, so building a Miniscript bottom-up costs no more than parsing it, and
+/// avoids formatting and reparsing a string. Like [`Miniscript::from_ast`], they
+/// return an error if the node is ill-typed or violates the rules of the context.
+impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> Miniscript<Pk, Ctx, Ext> {
+    /// The `a:` wrapper.
+    pub fn alt(sub: Arc<Self>) -> Result<Self, Error> { Self::from_ast(Terminal::Alt(sub)) }
+
+    /// The `s:` wrapper.
+    pub fn swap(sub: Arc<Self>) -> Result<Self, Error> { Self::from_ast(Terminal::Swap(sub)) }
+
+    /// The `c:` wrapper.
+    pub fn check(sub: Arc<Self>) -> Result<Self, Error> { Self::from_ast(Terminal::Check(sub)) }
+
+    /// The `d:` wrapper.
+    pub fn dupif(sub: Arc<Self>) -> Result<Self, Error> { Self::from_ast(Terminal::DupIf(sub)) }
+
+    /// The `v:` wrapper.
+    pub fn verify(sub: Arc<Self>) -> Result<Self, Error> { Self::from_ast(Terminal::Verify(sub)) }
+
+    /// The `j:` wrapper.
+    pub fn nonzero(sub: Arc<Self>) -> Result<Self, Error> { Self::from_ast(Terminal::NonZero(sub)) }
+
+    /// The `n:` wrapper.
+    pub fn zero_not_equal(sub: Arc<Self>) -> Result<Self, Error> {
+        Self::from_ast(Terminal::ZeroNotEqual(sub))
+    }
+
+    /// The `and_v` combinator.
+    pub fn and_v(left: Arc<Self>, right: Arc<Self>) -> Result<Self, Error> {
+        Self::from_ast(Terminal::AndV(left, right))
+    }
+
+    /// The `and_b` combinator.
+    pub fn and_b(left: Arc<Self>, right: Arc<Self>) -> Result<Self, Error> {
+        Self::from_ast(Terminal::AndB(left, right))
+    }
+
+    /// The `and_n` combinator, which is an alias for `andor(left,right,0)`.
+    pub fn and_n(left: Arc<Self>, right: Arc<Self>) -> Result<Self, Error> {
+        Self::from_ast(Terminal::AndOr(left, right, Arc::new(Self::FALSE)))
+    }
+
+    /// The `andor` combinator.
+    pub fn andor(cond: Arc<Self>, then: Arc<Self>, els: Arc<Self>) -> Result<Self, Error> {
+        Self::from_ast(Terminal::AndOr(cond, then, els))
+    }
+
+    /// The `or_b` combinator.
+    pub fn or_b(left: Arc<Self>, right: Arc<Self>) -> Result<Self, Error> {
+        Self::from_ast(Terminal::OrB(left, right))
+    }
+
+    /// The `or_d` combinator.
+    pub fn or_d(left: Arc<Self>, right: Arc<Self>) -> Result<Self, Error> {
+        Self::from_ast(Terminal::OrD(left, right))
+    }
+
+    /// The `or_c` combinator.
+    pub fn or_c(left: Arc<Self>, right: Arc<Self>) -> Result<Self, Error> {
+        Self::from_ast(Terminal::OrC(left, right))
+    }
+
+    /// The `or_i` combinator.
+    pub fn or_i(left: Arc<Self>, right: Arc<Self>) -> Result<Self, Error> {
+        Self::from_ast(Terminal::OrI(left, right))
+    }
+
+    /// The `thresh` combinator.
+    pub fn thresh(k: usize, subs: Vec<Arc<Self>>) -> Result<Self, Error> {
+        let thresh = Threshold::new(k, subs).map_err(Error::Threshold)?;
+        Self::from_ast(Terminal::Thresh(thresh))
+    }
+
+    /// The `multi` combinator, which is only available outside of Taproot.
+    pub fn multi(k: usize, keys: Vec<Pk>) -> Result<Self, Error> {
+        let thresh = Threshold::new(k, keys).map_err(Error::Threshold)?;
+        Self::from_ast(Terminal::Multi(thresh))
+    }
+
+    /// The `multi_a` combinator, which is only available in Taproot.
+    pub fn multi_a(k: usize, keys: Vec<Pk>) -> Result<Self, Error> {
+        let thresh = Threshold::new(k, keys).map_err(Error::Threshold)?;
+        Self::from_ast(Terminal::MultiA(thresh))
+    }
+}
+
+impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> Miniscript<Pk, Ctx, Ext> {
     /// Extracts the `AstElem` representing the root of the miniscript
-    pub fn into_inner(self) -> Terminal<Pk, Ctx> { self.node }
+    pub fn into_inner(mut self) -> Terminal<Pk, Ctx, Ext> {
+        core::mem::replace(&mut self.node, Terminal::True)
+    }
 
     /// Get a reference to the inner `AstElem` representing the root of miniscript
-    pub fn as_inner(&self) -> &Terminal<Pk, Ctx> { &self.node }
+    pub fn as_inner(&self) -> &Terminal<Pk, Ctx, Ext> { &self.node }
 
     /// Encode as a Bitcoin script
     pub fn encode(&self) -> script::ScriptBuf
@@ -323,6 +463,19 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
         self.node.encode(script::Builder::new()).into_script()
     }
 
+    /// Encode as a Bitcoin script, appending it to `script`.
+    ///
+    /// Unlike [`Miniscript::encode`], this reuses the allocation of `script`,
+    /// which avoids allocating a new script for every encoding when the same
+    /// buffer is cleared and reused.
+    pub fn encode_to(&self, script: &mut script::ScriptBuf)
+    where
+        Pk: ToPublicKey,
+    {
+        let builder = script::Builder::from(core::mem::take(script).into_bytes());
+        *script = self.node.encode(builder).into_script();
+    }
+
     /// Size, in bytes of the script-pubkey. If this Miniscript is used outside
     /// of segwit (e.g. in a bare or P2SH descriptor), this quantity should be
     /// multiplied by 4 to compute the weight.
@@ -367,6 +520,7 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
                         + thresh.iter().map(|pk| Ctx::pk_len(pk)).sum::<usize>() // n keys
                         + thresh.n() // n times CHECKSIGADD
                 }
+                Terminal::Ext(..) => ms.ext.pk_cost,
             }
         }
         len
@@ -402,6 +556,58 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
         Ctx::max_satisfaction_size(self).ok_or(Error::ImpossibleSatisfaction)
     }
 
+    /// Maximum size, in bytes, of a satisfying witness, as given by
+    /// [`Self::max_satisfaction_size`], but assuming signatures of the given
+    /// sizes.
+    pub fn max_satisfaction_size_with_sig_sizes(
+        &self,
+        sig_sizes: &types::SigSizes,
+    ) -> Result<usize, Error> {
+        Ctx::max_satisfaction_size_with_sig_sizes(self, sig_sizes)
+            .ok_or(Error::ImpossibleSatisfaction)
+    }
+
+    /// Maximum weight of a satisfying witness, assuming signatures of the
+    /// given sizes. Unlike [`Self::max_satisfaction_size`], this accounts
+    /// for whether the satisfaction is placed in the witness or, outside of
+    /// segwit, in the scriptSig.
+    ///
+    /// This does not include the script itself, nor the lengths of the
+    /// scriptSig or witness stack; for these call the corresponding function
+    /// on a `Descriptor`.
+    pub fn max_satisfaction_weight(
+        &self,
+        sig_sizes: &types::SigSizes,
+    ) -> Result<crate::descriptor::SatisfactionWeight, Error> {
+        Ctx::max_satisfaction_weight(self, sig_sizes).ok_or(Error::ImpossibleSatisfaction)
+    }
+
+    /// Maximum number of non-push opcodes counted towards the 201-opcode
+    /// limit when satisfying the Miniscript, as counted by consensus. This
+    /// includes the opcodes executed by `CHECKMULTISIG` for each key.
+    ///
+    /// The limit does not apply in Taproot, but the count is still
+    /// available for informational purposes.
+    ///
+    /// This function may returns Error when the Miniscript is
+    /// impossible to satisfy
+    pub fn max_satisfaction_op_count(&self) -> Result<usize, Error> {
+        self.ext.ops.op_count().ok_or(Error::ImpossibleSatisfaction)
+    }
+
+    /// Maximum number of elements on the stack and alt stack at any point while
+    /// executing the script during satisfaction, including the initial witness
+    /// elements. In Taproot this is limited to 1000 elements by consensus.
+    ///
+    /// This function may returns Error when the Miniscript is
+    /// impossible to satisfy
+    pub fn max_satisfaction_exec_stack_elements(&self) -> Result<usize, Error> {
+        match (self.ext.exec_stack_elem_count_sat, self.ext.stack_elem_count_sat) {
+            (Some(exec), Some(initial)) => Ok(exec + initial),
+            _ => Err(Error::ImpossibleSatisfaction),
+        }
+    }
+
     /// Helper function to produce Taproot leaf hashes
     fn leaf_hash_internal(&self) -> TapLeafHash
     where
@@ -418,7 +624,7 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
     {
         // Only satisfactions for default versions (0xc0) are allowed.
         let satisfaction = satisfy::Satisfaction::satisfy(
-            &self.node,
+            self,
             &satisfier,
             self.ty.mall.safe,
             &self.leaf_hash_internal(),
@@ -436,7 +642,7 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
         Pk: ToPublicKey,
     {
         let satisfaction = satisfy::Satisfaction::satisfy_mall(
-            &self.node,
+            self,
             &satisfier,
             self.ty.mall.safe,
             &self.leaf_hash_internal(),
@@ -444,6 +650,26 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
         self._satisfy(satisfaction)
     }
 
+    /// Attempt to produce the canonical dissatisfying witness for the
+    /// witness script represented by the parse tree
+    ///
+    /// This is the witness which makes the fragment leave a zero on the stack
+    /// without any signature. Fragments which cannot be dissatisfied, such as
+    /// `v:` wrapped ones, return [`Error::CouldNotSatisfy`]. The satisfier is
+    /// only needed for the public keys of raw public key hashes.
+    pub fn dissatisfy<S: satisfy::Satisfier<Pk>>(&self, satisfier: S) -> Result<Vec<Vec<u8>>, Error>
+    where
+        Pk: ToPublicKey,
+    {
+        let dissatisfaction = satisfy::Satisfaction::dissatisfy(
+            self,
+            &satisfier,
+            self.ty.mall.safe,
+            &self.leaf_hash_internal(),
+        );
+        self._satisfy(dissatisfaction)
+    }
+
     fn _satisfy(&self, satisfaction: satisfy::Satisfaction<Vec<u8>>) -> Result<Vec<Vec<u8>>, Error>
     where
         Pk: ToPublicKey,
@@ -468,7 +694,7 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
         Pk: ToPublicKey,
     {
         satisfy::Satisfaction::build_template(
-            &self.node,
+            self,
             provider,
             self.ty.mall.safe,
             &self.leaf_hash_internal(),
@@ -484,7 +710,7 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
         Pk: ToPublicKey,
     {
         satisfy::Satisfaction::build_template_mall(
-            &self.node,
+            self,
             provider,
             self.ty.mall.safe,
             &self.leaf_hash_internal(),
@@ -499,7 +725,7 @@ impl Miniscript<<Tap as ScriptContext>::Key, Tap> {
     pub fn leaf_hash(&self) -> TapLeafHash { self.leaf_hash_internal() }
 }
 
-impl<Ctx: ScriptContext> Miniscript<Ctx::Key, Ctx> {
+impl<Ctx: ScriptContext, Ext: Extension> Miniscript<Ctx::Key, Ctx, Ext> {
     /// Attempt to parse an insane(scripts don't clear sanity checks)
     /// script into a Miniscript representation.
     /// Use this to parse scripts with repeated pubkeys, timelock mixing, malleable
@@ -507,7 +733,7 @@ impl<Ctx: ScriptContext> Miniscript<Ctx::Key, Ctx> {
     /// Some of the analysis guarantees of miniscript are lost when dealing with
     /// insane scripts. In general, in a multi-party setting users should only
     /// accept sane scripts.
-    pub fn parse_insane(script: &script::Script) -> Result<Miniscript<Ctx::Key, Ctx>, Error> {
+    pub fn parse_insane(script: &script::Script) -> Result<Miniscript<Ctx::Key, Ctx, Ext>, Error> {
         Miniscript::parse_with_ext(script, &ExtParams::insane())
     }
 
@@ -521,10 +747,65 @@ impl<Ctx: ScriptContext> Miniscript<Ctx::Key, Ctx> {
     pub fn parse_with_ext(
         script: &script::Script,
         ext: &ExtParams,
-    ) -> Result<Miniscript<Ctx::Key, Ctx>, Error> {
-        let tokens = lex(script)?;
-        let mut iter = TokenIter::new(tokens);
+    ) -> Result<Miniscript<Ctx::Key, Ctx, Ext>, Error> {
+        Self::check_not_op_return(script)?;
+        let (tokens, positions) = lex_with_positions(script)?;
+        Self::parse_tokens(TokenIter::with_positions(tokens, positions), ext)
+    }
+
+    /// Attempt to parse a Script into Miniscript representation, tolerating
+    /// consensus-valid encodings which Miniscript would not produce.
+    ///
+    /// Scripts which use non-minimal pushes or numbers, or a separate
+    /// `OP_VERIFY` where a `VERIFY` opcode exists, are accepted rather than
+    /// rejected, and each such encoding is returned along with the Miniscript.
+    /// As Miniscript always encodes minimally, [`Miniscript::encode`] will not
+    /// reproduce `script` whenever any are returned.
+    ///
+    /// Otherwise this behaves as [`Miniscript::parse_with_ext`], and is meant
+    /// for analysis of existing scripts, e.g. historic ones found on chain.
+    ///
+    /// ```rust
+    /// use miniscript::bitcoin::hex::FromHex;
+    /// use miniscript::miniscript::lex::NonMinimalKind;
+    /// use miniscript::{ExtParams, Miniscript, Segwitv0};
+    ///
+    /// // OP_CSV with the number 1 pushed as data rather than with OP_1
+    /// let script = bitcoin::ScriptBuf::from_hex("0101b2").unwrap();
+    /// assert!(Miniscript::<bitcoin::PublicKey, Segwitv0>::parse_insane(&script).is_err());
+    ///
+    /// let (ms, non_minimal) =
+    ///     Miniscript::<bitcoin::PublicKey, Segwitv0>::parse_lenient(&script, &ExtParams::insane())
+    ///         .unwrap();
+    /// assert_eq!(ms.to_string(), "older(1)");
+    /// assert_eq!(non_minimal.len(), 1);
+    /// assert_eq!(non_minimal[0].kind, NonMinimalKind::Push);
+    /// ```
+    #[allow(clippy::type_complexity)]
+    pub fn parse_lenient(
+        script: &script::Script,
+        ext: &ExtParams,
+    ) -> Result<(Miniscript<Ctx::Key, Ctx, Ext>, Vec<NonMinimalEncoding>), Error> {
+        Self::check_not_op_return(script)?;
+        let (tokens, positions, non_minimal) = lex_lenient(script)?;
+        let ms = Self::parse_tokens(TokenIter::with_positions(tokens, positions), ext)?;
+        Ok((ms, non_minimal))
+    }
 
+    /// Fails if the script starts with `OP_RETURN`, so that data outputs are
+    /// reported as such rather than as an unexpected opcode.
+    fn check_not_op_return(script: &script::Script) -> Result<(), Error> {
+        match decode::UnspendableScript::from_op_return(script) {
+            Some(unspendable) => Err(Error::Decode(decode::DecodeError::Unspendable(unspendable))),
+            None => Ok(()),
+        }
+    }
+
+    /// Decodes a Miniscript from all of the given tokens.
+    fn parse_tokens(
+        mut iter: TokenIter,
+        ext: &ExtParams,
+    ) -> Result<Miniscript<Ctx::Key, Ctx, Ext>, Error> {
         let top = decode::parse(&mut iter)?;
         Ctx::check_global_validity(&top)?;
         let type_check = types::Type::type_check(&top.node)?;
@@ -532,7 +813,7 @@ impl<Ctx: ScriptContext> Miniscript<Ctx::Key, Ctx> {
             return Err(Error::NonTopLevel(format!("{:?}", top)));
         };
         if let Some(leading) = iter.next() {
-            Err(Error::Trailing(leading.to_string()))
+            Err(iter.error_at_position(Error::Trailing(leading.to_string())))
         } else {
             top.ext_check(ext)?;
             Ok(top)
@@ -572,7 +853,7 @@ impl<Ctx: ScriptContext> Miniscript<Ctx::Key, Ctx> {
     ///     .expect("Compressed keys are allowed in Segwit context");
     ///
     /// ```
-    pub fn parse(script: &script::Script) -> Result<Miniscript<Ctx::Key, Ctx>, Error> {
+    pub fn parse(script: &script::Script) -> Result<Miniscript<Ctx::Key, Ctx, Ext>, Error> {
         let ms = Self::parse_with_ext(script, &ExtParams::sane())?;
         Ok(ms)
     }
@@ -581,8 +862,10 @@ impl<Ctx: ScriptContext> Miniscript<Ctx::Key, Ctx> {
 /// `PartialOrd` of `Miniscript` must depend only on node and not the type information.
 ///
 /// The type information and extra properties are implied by the AST.
-impl<Pk: MiniscriptKey, Ctx: ScriptContext> PartialOrd for Miniscript<Pk, Ctx> {
-    fn partial_cmp(&self, other: &Miniscript<Pk, Ctx>) -> Option<cmp::Ordering> {
+impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> PartialOrd
+    for Miniscript<Pk, Ctx, Ext>
+{
+    fn partial_cmp(&self, other: &Miniscript<Pk, Ctx, Ext>) -> Option<cmp::Ordering> {
         Some(self.node.cmp(&other.node))
     }
 }
@@ -590,30 +873,37 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> PartialOrd for Miniscript<Pk, Ctx> {
 /// `Ord` of `Miniscript` must depend only on node and not the type information.
 ///
 /// The type information and extra properties are implied by the AST.
-impl<Pk: MiniscriptKey, Ctx: ScriptContext> Ord for Miniscript<Pk, Ctx> {
-    fn cmp(&self, other: &Miniscript<Pk, Ctx>) -> cmp::Ordering { self.node.cmp(&other.node) }
+impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> Ord for Miniscript<Pk, Ctx, Ext> {
+    fn cmp(&self, other: &Miniscript<Pk, Ctx, Ext>) -> cmp::Ordering { self.node.cmp(&other.node) }
 }
 
 /// `PartialEq` of `Miniscript` must depend only on node and not the type information.
 ///
 /// The type information and extra properties are implied by the AST.
-impl<Pk: MiniscriptKey, Ctx: ScriptContext> PartialEq for Miniscript<Pk, Ctx> {
-    fn eq(&self, other: &Miniscript<Pk, Ctx>) -> bool { self.node.eq(&other.node) }
+impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> PartialEq for Miniscript<Pk, Ctx, Ext> {
+    fn eq(&self, other: &Miniscript<Pk, Ctx, Ext>) -> bool {
+        // Fast path for shared (e.g. interned) subtrees.
+        core::ptr::eq(self, other) || self.node.eq(&other.node)
+    }
 }
 
 /// `Eq` of `Miniscript` must depend only on node and not the type information.
 ///
 /// The type information and extra properties are implied by the AST.
-impl<Pk: MiniscriptKey, Ctx: ScriptContext> Eq for Miniscript<Pk, Ctx> {}
+impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> Eq for Miniscript<Pk, Ctx, Ext> {}
 
 /// `Hash` of `Miniscript` must depend only on node and not the type information.
 ///
 /// The type information and extra properties are implied by the AST.
-impl<Pk: MiniscriptKey, Ctx: ScriptContext> hash::Hash for Miniscript<Pk, Ctx> {
+impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> hash::Hash
+    for Miniscript<Pk, Ctx, Ext>
+{
     fn hash<H: hash::Hasher>(&self, state: &mut H) { self.node.hash(state); }
 }
 
-impl<Pk: MiniscriptKey, Ctx: ScriptContext> ForEachKey<Pk> for Miniscript<Pk, Ctx> {
+impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> ForEachKey<Pk>
+    for Miniscript<Pk, Ctx, Ext>
+{
     fn for_each_key<'a, F: FnMut(&'a Pk) -> bool>(&'a self, mut pred: F) -> bool {
         for ms in self.pre_order_iter() {
             match ms.node {
@@ -646,13 +936,13 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> ForEachKey<Pk> for Miniscript<Pk, Ct
     }
 }
 
-impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
+impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> Miniscript<Pk, Ctx, Ext> {
     /// Translates a struct from one generic to another where the translation
     /// for Pk is provided by [`Translator`]
     pub fn translate_pk<T>(
         &self,
         t: &mut T,
-    ) -> Result<Miniscript<T::TargetPk, Ctx>, TranslateErr<T::Error>>
+    ) -> Result<Miniscript<T::TargetPk, Ctx, Ext>, TranslateErr<T::Error>>
     where
         T: Translator<Pk>,
     {
@@ -662,7 +952,7 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
     pub(super) fn translate_pk_ctx<CtxQ, T>(
         &self,
         t: &mut T,
-    ) -> Result<Miniscript<T::TargetPk, CtxQ>, TranslateErr<T::Error>>
+    ) -> Result<Miniscript<T::TargetPk, CtxQ, Ext>, TranslateErr<T::Error>>
     where
         CtxQ: ScriptContext,
         T: Translator<Pk>,
@@ -718,6 +1008,7 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
                 Terminal::MultiA(ref thresh) => {
                     Terminal::MultiA(thresh.translate_ref(|k| t.pk(k))?)
                 }
+                Terminal::Ext(ref ext) => Terminal::Ext(ext.clone()),
             };
             let new_ms = Miniscript::from_ast(new_term).map_err(TranslateErr::OuterError)?;
             translated.push(Arc::new(new_ms));
@@ -727,7 +1018,10 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
     }
 
     /// Substitutes raw public keys hashes with the public keys as provided by map.
-    pub fn substitute_raw_pkh(&self, pk_map: &BTreeMap<hash160::Hash, Pk>) -> Miniscript<Pk, Ctx> {
+    pub fn substitute_raw_pkh(
+        &self,
+        pk_map: &BTreeMap<hash160::Hash, Pk>,
+    ) -> Miniscript<Pk, Ctx, Ext> {
         let mut stack = vec![];
         for item in self.rtl_post_order_iter() {
             let new_term = match item.node.node {
@@ -769,6 +1063,7 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
                 }
                 Terminal::Multi(ref thresh) => Terminal::Multi(thresh.clone()),
                 Terminal::MultiA(ref thresh) => Terminal::MultiA(thresh.clone()),
+                Terminal::Ext(ref ext) => Terminal::Ext(ext.clone()),
             };
 
             stack.push(Arc::new(Miniscript::from_components_unchecked(
@@ -781,9 +1076,80 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
         assert_eq!(stack.len(), 1);
         Arc::try_unwrap(stack.pop().unwrap()).unwrap()
     }
+
+    /// Replaces the subtree at `path` with `replacement`, returning the new Miniscript.
+    ///
+    /// The path is a list of child indices, as used by [`Miniscript::get_nth_child`],
+    /// leading from the root to the node to be replaced; the empty path denotes the
+    /// root itself. Only the nodes along the path are re-typechecked, while all other
+    /// subtrees are shared with `self` rather than copied, so this is much cheaper
+    /// than rebuilding the whole tree when editing large scripts.
+    ///
+    /// Returns an error if the path does not exist or if any node along it fails to
+    /// typecheck with the new subtree.
+    pub fn replace_subtree(
+        &self,
+        path: &[usize],
+        replacement: Arc<Miniscript<Pk, Ctx, Ext>>,
+    ) -> Result<Miniscript<Pk, Ctx, Ext>, Error> {
+        let mut spine = Vec::with_capacity(path.len());
+        let mut node = self;
+        for (depth, &n) in path.iter().enumerate() {
+            spine.push(node);
+            node = node
+                .get_nth_child(n)
+                .ok_or_else(|| Error::NoSuchChild(path[..=depth].to_vec()))?;
+        }
+
+        let mut new_node = replacement;
+        for (parent, &n) in spine.into_iter().zip(path).rev() {
+            let new_term = parent
+                .node
+                .with_nth_child(n, new_node)
+                .expect("child exists, checked above");
+            new_node = Arc::new(Miniscript::from_ast(new_term)?);
+        }
+        Ok(Arc::try_unwrap(new_node).unwrap_or_else(|arc| Miniscript::clone(&arc)))
+    }
+
+    /// Rewrites the Miniscript by applying `f` to every node, from the leaves up.
+    ///
+    /// For each node, `f` is called after its children have been rewritten, and
+    /// may return a replacement for the node, or `None` to keep it. Any node
+    /// whose children changed is re-typechecked, along with the consensus rules
+    /// of the script context, so an error is returned if a replacement does not
+    /// fit where it was placed. Subtrees which are unchanged are shared with
+    /// `self` rather than copied.
+    ///
+    /// The result is not checked for sanity; use [`Miniscript::sanity_check`]
+    /// if required.
+    pub fn rewrite<F>(&self, mut f: F) -> Result<Miniscript<Pk, Ctx, Ext>, Error>
+    where
+        F: FnMut(&Miniscript<Pk, Ctx, Ext>) -> Option<Miniscript<Pk, Ctx, Ext>>,
+    {
+        // For each node in post-order, its rewritten form, or None if unchanged.
+        let mut rewritten: Vec<Option<Arc<Miniscript<Pk, Ctx, Ext>>>> = vec![];
+        for item in self.post_order_iter() {
+            let mut new_term = None;
+            for (n, &idx) in item.child_indices.iter().enumerate() {
+                if let Some(child) = rewritten[idx].take() {
+                    let term = new_term.as_ref().unwrap_or(&item.node.node);
+                    new_term = Some(term.with_nth_child(n, child).expect("child exists"));
+                }
+            }
+            let rebuilt = new_term.map(Miniscript::from_ast).transpose()?;
+            let replacement = f(rebuilt.as_ref().unwrap_or(item.node));
+            rewritten.push(replacement.or(rebuilt).map(Arc::new));
+        }
+
+        match rewritten.pop().expect("at least one node") {
+            Some(ms) => Ok(Arc::try_unwrap(ms).unwrap_or_else(|arc| Miniscript::clone(&arc))),
+            None => Ok(self.clone()),
+        }
+    }
 }
 
-impl<Pk: FromStrKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
+impl<Pk: FromStrKey, Ctx: ScriptContext, Ext: Extension> Miniscript<Pk, Ctx, Ext> {
     /// Attempt to parse an insane(scripts don't clear sanity checks)
     /// from string into a Miniscript representation.
     /// Use this to parse scripts with repeated pubkeys, timelock mixing, malleable
@@ -791,7 +1157,7 @@ impl<Pk: FromStrKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
     /// Some of the analysis guarantees of miniscript are lost when dealing with
     /// insane scripts. In general, in a multi-party setting users should only
     /// accept sane scripts.
-    pub fn from_str_insane(s: &str) -> Result<Miniscript<Pk, Ctx>, Error> {
+    pub fn from_str_insane(s: &str) -> Result<Miniscript<Pk, Ctx, Ext>, Error> {
         Miniscript::from_str_ext(s, &ExtParams::insane())
     }
 
@@ -800,10 +1166,10 @@ impl<Pk: FromStrKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
     /// scripts, raw pubkey hashes without sig or scripts that can exceed resource limits.
     ///
     /// Use [`ExtParams`] builder to specify the types of non-sane rules to allow while parsing.
-    pub fn from_str_ext(s: &str, ext: &ExtParams) -> Result<Miniscript<Pk, Ctx>, Error> {
+    pub fn from_str_ext(s: &str, ext: &ExtParams) -> Result<Miniscript<Pk, Ctx, Ext>, Error> {
         // This checks for invalid ASCII chars
         let top = expression::Tree::from_str(s)?;
-        let ms: Miniscript<Pk, Ctx> = expression::FromTree::from_tree(top.root())?;
+        let ms: Miniscript<Pk, Ctx, Ext> = expression::FromTree::from_tree(top.root())?;
         ms.ext_check(ext)?;
 
         if ms.ty.corr.base != types::Base::B {
@@ -814,168 +1180,227 @@ impl<Pk: FromStrKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
     }
 }
 
-impl<Pk: FromStrKey, Ctx: ScriptContext> FromTree for Arc<Miniscript<Pk, Ctx>> {
-    fn from_tree(root: TreeIterItem) -> Result<Self, Error> {
+impl<Pk: FromStrKey, Ctx: ScriptContext, Ext: Extension> FromTree
+    for Arc<Miniscript<Pk, Ctx, Ext>>
+{
+    type Error = ParseMiniscriptError;
+    fn from_tree(root: TreeIterItem) -> Result<Self, ParseMiniscriptError> {
         Miniscript::from_tree(root).map(Arc::new)
     }
 }
 
-impl<Pk: FromStrKey, Ctx: ScriptContext> FromTree for Miniscript<Pk, Ctx> {
-    fn from_tree(root: TreeIterItem) -> Result<Self, Error> {
+// Check whether a node is the inner value of a terminal. In that case, it is
+// not a fragment. Conveniently, there are no combinators in Miniscript that
+// have a single child that these might be confused with. (Well, there are, but
+// they're all serialized as wrappers.)
+//
+// This also holds for all the children of multi/multi_a and the first child of
+// thresh (which will be the k value, not a real child).
+//
+// We do not do this check on the root node, because its parent might be wsh or
+// sh or something, and actually these ARE single-child combinators, but we don't
+// want to skip their children.
+fn is_terminal_value(n: usize, node: TreeIterItem) -> Result<bool, crate::ParseTreeError> {
+    if n == 0 || node.n_children() > 0 {
+        return Ok(false);
+    }
+    let parent = node.parent().unwrap();
+    if parent.n_children() == 1 {
+        return Ok(true);
+    }
+
+    let (_, parent_name) = parent.name_separated(':')?;
+
+    Ok(parent_name == "multi"
+        || parent_name == "multi_a"
+        || (parent_name == "thresh" && node.is_first_child()))
+}
+
+impl<Pk: FromStrKey, Ctx: ScriptContext, Ext: Extension> FromTree for Miniscript<Pk, Ctx, Ext> {
+    type Error = ParseMiniscriptError;
+    fn from_tree(root: TreeIterItem) -> Result<Self, ParseMiniscriptError> {
+        // `Miniscript::from_ast`, failing with the errors of the parser
+        fn from_ast<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension>(
+            t: Terminal<Pk, Ctx, Ext>,
+        ) -> Result<Miniscript<Pk, Ctx, Ext>, ParseMiniscriptError> {
+            let ty = types::Type::type_check(&t).map_err(ParseMiniscriptError::TypeCheck)?;
+            let ext = types::ExtData::type_check(&t);
+            let ms = Miniscript::from_components_unchecked(t, ty, ext);
+            Ctx::check_global_validity(&ms).map_err(ParseMiniscriptError::Context)?;
+            Ok(ms)
+        }
+
         #[allow(clippy::type_complexity)]
-        fn binary<Pk: MiniscriptKey, Ctx: ScriptContext>(
+        fn binary<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension>(
             node: expression::TreeIterItem,
-            stack: &mut Vec<Arc<Miniscript<Pk, Ctx>>>,
+            stack: &mut Vec<Arc<Miniscript<Pk, Ctx, Ext>>>,
             name: &'static str,
-            termfn: fn(Arc<Miniscript<Pk, Ctx>>, Arc<Miniscript<Pk, Ctx>>) -> Terminal<Pk, Ctx>,
-        ) -> Result<Miniscript<Pk, Ctx>, Error> {
+            termfn: fn(
+                Arc<Miniscript<Pk, Ctx, Ext>>,
+                Arc<Miniscript<Pk, Ctx, Ext>>,
+            ) -> Terminal<Pk, Ctx, Ext>,
+        ) -> Result<Miniscript<Pk, Ctx, Ext>, ParseMiniscriptError> {
             node.verify_n_children(name, 2..=2)
                 .map_err(From::from)
-                .map_err(Error::Parse)?;
-            Miniscript::from_ast(termfn(stack.pop().unwrap(), stack.pop().unwrap()))
+                .map_err(ParseMiniscriptError::Parse)?;
+            from_ast(termfn(stack.pop().unwrap(), stack.pop().unwrap()))
         }
         root.verify_no_curly_braces()
             .map_err(From::from)
-            .map_err(Error::Parse)?;
-
-        let mut stack = Vec::with_capacity(128);
-        for (n, node) in root.pre_order_iter().enumerate().rev() {
-            // Before doing anything else, check if this is the inner value of a terminal.
-            // In that case, just skip the node. Conveniently, there are no combinators
-            // in Miniscript that have a single child that these might be confused with.
-            // (Well, there are, but they're all serialized as wrappers.)
-            //
-            // We also skip all the children of multi/multi_a and the first child of thresh
-            // (which will be the k value, not a real child).
-            //
-            // We do not do this check on the root node, because its parent might be wsh or
-            // sh or something, and actually these ARE single-child combinators, but we don't
-            // want to skip their children.
-            if n > 0 && node.n_children() == 0 {
-                let parent = node.parent().unwrap();
-                if parent.n_children() == 1 {
+            .map_err(ParseMiniscriptError::Parse)?;
+
+        // Extension fragments are recognized from the root down, since their
+        // children are arguments rather than fragments, and must not be parsed.
+        let mut exts = BTreeMap::new();
+        let mut in_ext = vec![false; root.pre_order_iter().len()];
+        for (n, node) in root.pre_order_iter().enumerate() {
+            if n > 0 {
+                let parent = node.parent().unwrap().index() - root.index();
+                if in_ext[parent] || exts.contains_key(&parent) {
+                    in_ext[n] = true;
                     continue;
                 }
+            }
+            if is_terminal_value(n, node)
+                .map_err(From::from)
+                .map_err(ParseMiniscriptError::Parse)?
+            {
+                continue;
+            }
+            if let Ok((_, frag_name)) = node.name_separated(':') {
+                if let Some(ext) = Ext::from_name_tree(frag_name, node) {
+                    exts.insert(n, ext.map_err(|e| ParseMiniscriptError::Extension(Box::new(e)))?);
+                }
+            }
+        }
 
-                let (_, parent_name) = parent
-                    .name_separated(':')
+        let mut stack = Vec::with_capacity(128);
+        for (n, node) in root.pre_order_iter().enumerate().rev() {
+            // Before doing anything else, check if this is the inner value of a terminal
+            // or an argument of an extension fragment. In that case, just skip the node.
+            if in_ext[n] {
+                continue;
+            }
+            let ext = exts.remove(&n);
+            if ext.is_none()
+                && is_terminal_value(n, node)
                     .map_err(From::from)
-                    .map_err(Error::Parse)?;
-
-                if parent_name == "multi" || parent_name == "multi_a" {
-                    continue;
-                }
-                if parent_name == "thresh" && node.is_first_child() {
-                    continue;
-                }
+                    .map_err(ParseMiniscriptError::Parse)?
+            {
+                continue;
             }
 
             let (frag_wrap, frag_name) = node
                 .name_separated(':')
                 .map_err(From::from)
-                .map_err(Error::Parse)?;
+                .map_err(ParseMiniscriptError::Parse)?;
 
             // "pk" and "pkh" are aliases for "c:pk_k" and "c:pk_h" respectively.
-            let new = match frag_name {
-                "expr_raw_pkh" => node
-                    .verify_terminal_parent("expr_raw_pkh", "public key hash")
-                    .map(Miniscript::expr_raw_pkh)
-                    .map_err(Error::Parse),
-                "pk" => node
-                    .verify_terminal_parent("pk", "public key")
-                    .map(Miniscript::pk)
-                    .map_err(Error::Parse),
-                "pkh" => node
-                    .verify_terminal_parent("pkh", "public key")
-                    .map(Miniscript::pkh)
-                    .map_err(Error::Parse),
-                "pk_k" => node
-                    .verify_terminal_parent("pk_k", "public key")
-                    .map(Miniscript::pk_k)
-                    .map_err(Error::Parse),
-                "pk_h" => node
-                    .verify_terminal_parent("pk_h", "public key")
-                    .map(Miniscript::pk_h)
-                    .map_err(Error::Parse),
-                "after" => node
-                    .verify_after()
-                    .map(Miniscript::after)
-                    .map_err(Error::Parse),
-                "older" => node
-                    .verify_older()
-                    .map(Miniscript::older)
-                    .map_err(Error::Parse),
-                "sha256" => node
-                    .verify_terminal_parent("sha256", "hash")
-                    .map(Miniscript::sha256)
-                    .map_err(Error::Parse),
-                "hash256" => node
-                    .verify_terminal_parent("hash256", "hash")
-                    .map(Miniscript::hash256)
-                    .map_err(Error::Parse),
-                "ripemd160" => node
-                    .verify_terminal_parent("ripemd160", "hash")
-                    .map(Miniscript::ripemd160)
-                    .map_err(Error::Parse),
-                "hash160" => node
-                    .verify_terminal_parent("hash160", "hash")
-                    .map(Miniscript::hash160)
-                    .map_err(Error::Parse),
-                "1" => {
-                    node.verify_n_children("1", 0..=0)
-                        .map_err(From::from)
-                        .map_err(Error::Parse)?;
-                    Ok(Miniscript::TRUE)
-                }
-                "0" => {
-                    node.verify_n_children("0", 0..=0)
-                        .map_err(From::from)
-                        .map_err(Error::Parse)?;
-                    Ok(Miniscript::FALSE)
-                }
-                "and_v" => binary(node, &mut stack, "and_v", Terminal::AndV),
-                "and_b" => binary(node, &mut stack, "and_b", Terminal::AndB),
-                "and_n" => binary(node, &mut stack, "and_n", |x, y| {
-                    Terminal::AndOr(x, y, Arc::new(Miniscript::FALSE))
-                }),
-                "andor" => {
-                    node.verify_n_children("andor", 3..=3)
-                        .map_err(From::from)
-                        .map_err(Error::Parse)?;
-                    Miniscript::from_ast(Terminal::AndOr(
-                        stack.pop().unwrap(),
-                        stack.pop().unwrap(),
-                        stack.pop().unwrap(),
-                    ))
-                }
-                "or_b" => binary(node, &mut stack, "or_b", Terminal::OrB),
-                "or_d" => binary(node, &mut stack, "or_d", Terminal::OrD),
-                "or_c" => binary(node, &mut stack, "or_c", Terminal::OrC),
-                "or_i" => binary(node, &mut stack, "or_i", Terminal::OrI),
-                "thresh" => node
-                    .verify_threshold(|_| Ok(stack.pop().unwrap()))
-                    .map(Terminal::Thresh)
-                    .and_then(Miniscript::from_ast),
-                "multi" => node
-                    .verify_threshold(|sub| sub.verify_terminal("public_key").map_err(Error::Parse))
-                    .map(Terminal::Multi)
-                    .and_then(Miniscript::from_ast),
-                "multi_a" => node
-                    .verify_threshold(|sub| sub.verify_terminal("public_key").map_err(Error::Parse))
-                    .map(Terminal::MultiA)
-                    .and_then(Miniscript::from_ast),
-                x => {
-                    Err(Error::Parse(crate::ParseError::Tree(crate::ParseTreeError::UnknownName {
-                        name: x.to_owned(),
-                    })))
-                }
+            let new = match ext {
+                Some(ext) => from_ast(Terminal::Ext(ext)),
+                None => match frag_name {
+                    "expr_raw_pkh" => node
+                        .verify_terminal_parent("expr_raw_pkh", "public key hash")
+                        .map(Miniscript::expr_raw_pkh)
+                        .map_err(ParseMiniscriptError::Parse),
+                    "pk" => node
+                        .verify_terminal_parent("pk", "public key")
+                        .map(Miniscript::pk)
+                        .map_err(ParseMiniscriptError::Parse),
+                    "pkh" => node
+                        .verify_terminal_parent("pkh", "public key")
+                        .map(Miniscript::pkh)
+                        .map_err(ParseMiniscriptError::Parse),
+                    "pk_k" => node
+                        .verify_terminal_parent("pk_k", "public key")
+                        .map(Miniscript::pk_k)
+                        .map_err(ParseMiniscriptError::Parse),
+                    "pk_h" => node
+                        .verify_terminal_parent("pk_h", "public key")
+                        .map(Miniscript::pk_h)
+                        .map_err(ParseMiniscriptError::Parse),
+                    "after" => node
+                        .verify_after()
+                        .map(Miniscript::after)
+                        .map_err(ParseMiniscriptError::Parse),
+                    "older" => node
+                        .verify_older()
+                        .map(Miniscript::older)
+                        .map_err(ParseMiniscriptError::Parse),
+                    "sha256" => node
+                        .verify_terminal_parent("sha256", "hash")
+                        .map(Miniscript::sha256)
+                        .map_err(ParseMiniscriptError::Parse),
+                    "hash256" => node
+                        .verify_terminal_parent("hash256", "hash")
+                        .map(Miniscript::hash256)
+                        .map_err(ParseMiniscriptError::Parse),
+                    "ripemd160" => node
+                        .verify_terminal_parent("ripemd160", "hash")
+                        .map(Miniscript::ripemd160)
+                        .map_err(ParseMiniscriptError::Parse),
+                    "hash160" => node
+                        .verify_terminal_parent("hash160", "hash")
+                        .map(Miniscript::hash160)
+                        .map_err(ParseMiniscriptError::Parse),
+                    "1" => {
+                        node.verify_n_children("1", 0..=0)
+                            .map_err(From::from)
+                            .map_err(ParseMiniscriptError::Parse)?;
+                        Ok(Miniscript::TRUE)
+                    }
+                    "0" => {
+                        node.verify_n_children("0", 0..=0)
+                            .map_err(From::from)
+                            .map_err(ParseMiniscriptError::Parse)?;
+                        Ok(Miniscript::FALSE)
+                    }
+                    "and_v" => binary(node, &mut stack, "and_v", Terminal::AndV),
+                    "and_b" => binary(node, &mut stack, "and_b", Terminal::AndB),
+                    "and_n" => binary(node, &mut stack, "and_n", |x, y| {
+                        Terminal::AndOr(x, y, Arc::new(Miniscript::FALSE))
+                    }),
+                    "andor" => {
+                        node.verify_n_children("andor", 3..=3)
+                            .map_err(From::from)
+                            .map_err(ParseMiniscriptError::Parse)?;
+                        from_ast(Terminal::AndOr(
+                            stack.pop().unwrap(),
+                            stack.pop().unwrap(),
+                            stack.pop().unwrap(),
+                        ))
+                    }
+                    "or_b" => binary(node, &mut stack, "or_b", Terminal::OrB),
+                    "or_d" => binary(node, &mut stack, "or_d", Terminal::OrD),
+                    "or_c" => binary(node, &mut stack, "or_c", Terminal::OrC),
+                    "or_i" => binary(node, &mut stack, "or_i", Terminal::OrI),
+                    "thresh" => node
+                        .verify_threshold(|_| Ok(stack.pop().unwrap()))
+                        .map(Terminal::Thresh)
+                        .and_then(from_ast),
+                    "multi" => node
+                        .verify_threshold(|sub| {
+                            sub.verify_terminal("public_key").map_err(ParseMiniscriptError::Parse)
+                        })
+                        .map(Terminal::Multi)
+                        .and_then(from_ast),
+                    "multi_a" => node
+                        .verify_threshold(|sub| {
+                            sub.verify_terminal("public_key").map_err(ParseMiniscriptError::Parse)
+                        })
+                        .map(Terminal::MultiA)
+                        .and_then(from_ast),
+                    x => Err(ParseMiniscriptError::Parse(crate::ParseError::Tree(
+                        crate::ParseTreeError::UnknownName { name: x.to_owned() },
+                    ))),
+                },
             }?;
 
             let mut new = Arc::new(new);
             if let Some(frag_wrap) = frag_wrap {
                 // ":node()" is not valid syntax
                 if frag_wrap.is_empty() {
-                    return Err(Error::Parse(crate::ParseError::Tree(
+                    return Err(ParseMiniscriptError::Parse(crate::ParseError::Tree(
                         crate::ParseTreeError::UnknownName { name: node.name().to_owned() },
                     )));
                 }
@@ -992,9 +1417,9 @@ impl<Pk: FromStrKey, Ctx: ScriptContext> FromTree for Miniscript<Pk, Ctx> {
                         b't' => Terminal::AndV(new, Arc::new(Miniscript::TRUE)),
                         b'u' => Terminal::OrI(new, Arc::new(Miniscript::FALSE)),
                         b'l' => Terminal::OrI(Arc::new(Miniscript::FALSE), new),
-                        x => return Err(Error::UnknownWrapper(x.into())),
+                        x => return Err(ParseMiniscriptError::UnknownWrapper(x.into())),
                     };
-                    new = Arc::new(Miniscript::from_ast(term)?);
+                    new = Arc::new(from_ast(term)?);
                 }
             }
 
@@ -1008,18 +1433,18 @@ impl<Pk: FromStrKey, Ctx: ScriptContext> FromTree for Miniscript<Pk, Ctx> {
         // leaves. But probably checking every single node is overkill. This may be worth
         // optimizing.
         for node in ret.pre_order_iter() {
-            Ctx::check_global_validity(node)?;
+            Ctx::check_global_validity(node).map_err(ParseMiniscriptError::Context)?;
         }
         Ok(Arc::try_unwrap(ret).unwrap())
     }
 }
 
-impl<Pk: FromStrKey, Ctx: ScriptContext> str::FromStr for Miniscript<Pk, Ctx> {
+impl<Pk: FromStrKey, Ctx: ScriptContext, Ext: Extension> str::FromStr for Miniscript<Pk, Ctx, Ext> {
     type Err = Error;
     /// Parse a Miniscript from string and perform sanity checks
     /// See [Miniscript::from_str_insane] to parse scripts from string that
     /// do not clear the [Miniscript::sanity_check] checks.
-    fn from_str(s: &str) -> Result<Miniscript<Pk, Ctx>, Error> {
+    fn from_str(s: &str) -> Result<Miniscript<Pk, Ctx, Ext>, Error> {
         let ms = Self::from_str_ext(s, &ExtParams::sane())?;
         Ok(ms)
     }
@@ -1049,13 +1474,15 @@ mod tests {
     use bitcoin::taproot::TapLeafHash;
     use sync::Arc;
 
+    use super::decode::{DecodeError, ScriptPosition, UnspendableScript};
     use super::{Miniscript, ScriptContext, Segwitv0, Tap};
     use crate::miniscript::{types, Terminal};
     use crate::policy::Liftable;
     use crate::prelude::*;
     use crate::test_utils::{StrKeyTranslator, StrXOnlyKeyTranslator};
     use crate::{
-        hex_script, BareCtx, Error, ExtParams, Legacy, RelLockTime, Satisfier, ToPublicKey,
+        hex_script, AbsLockTime, BareCtx, Error, ExtParams, Legacy, RelLockTime, Satisfier,
+        ToPublicKey,
     };
 
     type Segwitv0Script = Miniscript<bitcoin::PublicKey, Segwitv0>;
@@ -1515,6 +1942,183 @@ mod tests {
             "2102ffffffffffffffefefefefefefefefefefef394c0fe5b711179e124008584753ac6900"
         ))
         .is_err());
+
+        // bare SHA256 not followed by the hashlock EQUAL/SIZE pattern
+        let err = Segwitv0Script::parse_insane(&hex_script("00a8")).unwrap_err();
+        match err {
+            Error::AtScriptPosition(pos, ref e) => {
+                assert_eq!(pos, ScriptPosition { byte_offset: 1, opcode_index: 1 });
+                assert!(matches!(
+                    **e,
+                    Error::Decode(crate::miniscript::decode::DecodeError::UnexpectedToken(
+                        crate::miniscript::lex::OwnedToken::Sha256
+                    )),
+                ));
+            }
+            ref e => panic!("unexpected error {:?}", e),
+        }
+        assert_eq!(err.to_string(), "unexpected token «Sha256» (at opcode 1, byte 1)");
+    }
+
+    #[test]
+    fn parse_lenient() {
+        use crate::miniscript::lex::NonMinimalKind;
+
+        let key = "028c28a97bf8298bc0d23d8c749452a32e694b65e30a9472a3954ab30fe5324caa";
+        let lenient = |hex: &str| {
+            let script = hex_script(hex);
+            assert!(Segwitv0Script::parse_insane(&script).is_err());
+            let (ms, non_minimal) =
+                Segwitv0Script::parse_lenient(&script, &ExtParams::insane()).unwrap();
+            assert_ne!(ms.encode(), script);
+            let non_minimal: Vec<_> = non_minimal
+                .iter()
+                .map(|nm| (nm.position.byte_offset, nm.kind))
+                .collect();
+            (ms.to_string(), non_minimal)
+        };
+
+        // OP_PUSHDATA1 for a key
+        assert_eq!(
+            lenient(&format!("4c21{}ac", key)),
+            (format!("pk({})", key), vec![(0, NonMinimalKind::Push)]),
+        );
+        // OP_CHECKSIG OP_VERIFY instead of OP_CHECKSIGVERIFY
+        assert_eq!(
+            lenient(&format!("21{}ac6951", key)),
+            (format!("tv:pk({})", key), vec![(35, NonMinimalKind::Verify)]),
+        );
+        // Number 1 encoded on two bytes, and pushed as data
+        assert_eq!(lenient("020100b2"), ("older(1)".to_owned(), vec![(0, NonMinimalKind::Number)]));
+        assert_eq!(lenient("0101b2"), ("older(1)".to_owned(), vec![(0, NonMinimalKind::Push)]));
+        assert_eq!(
+            lenient("4c020100b2"),
+            (
+                "older(1)".to_owned(),
+                vec![(0, NonMinimalKind::Push), (0, NonMinimalKind::Number)]
+            ),
+        );
+
+        // Minimal scripts are parsed as usual, and invalid ones still rejected.
+        let script = hex_script(&format!("21{}ad51", key));
+        let (ms, non_minimal) =
+            Segwitv0Script::parse_lenient(&script, &ExtParams::insane()).unwrap();
+        assert_eq!(ms, Segwitv0Script::parse_insane(&script).unwrap());
+        assert!(non_minimal.is_empty());
+        assert!(Segwitv0Script::parse_lenient(&hex_script("516a"), &ExtParams::insane()).is_err());
+    }
+
+    #[test]
+    fn decode_error_positions() {
+        let position = |hex: &str| match Segwitv0Script::parse_insane(&hex_script(hex)) {
+            Err(Error::AtScriptPosition(pos, e)) => (pos.byte_offset, pos.opcode_index, *e),
+            res => panic!("unexpected result {:?}", res),
+        };
+
+        // Lexing stops at the first opcode outside of Miniscript.
+        let (byte, opcode, e) = position("51516a");
+        assert_eq!((byte, opcode), (2, 2));
+        assert!(matches!(e, Error::InvalidOpcode(bitcoin::opcodes::all::OP_RETURN)));
+        // Pushes count as a single opcode.
+        let (byte, opcode, e) = position("020001516a");
+        assert_eq!((byte, opcode), (4, 2));
+        assert!(matches!(e, Error::InvalidOpcode(_)));
+        // Non-minimal push.
+        let (byte, opcode, e) = position("514c0169b2");
+        assert_eq!((byte, opcode), (1, 1));
+        assert!(matches!(e, Error::Script(_)));
+
+        // Decoding reads the script backward, and reports the leftmost opcode
+        // read before failing.
+        let (byte, opcode, e) =
+            position("21020000000000000000000000000000000000000000000000000000000000000000ac");
+        assert_eq!((byte, opcode), (0, 0));
+        assert!(matches!(e, Error::PubKeyCtxError(..)));
+        let (byte, opcode, e) = position("5100");
+        assert_eq!((byte, opcode), (0, 0));
+        assert!(matches!(e, Error::TypeCheck(_)));
+    }
+
+    #[test]
+    fn lexer() {
+        use crate::miniscript::lex::{Lexer, NonMinimalKind, Token};
+
+        fn tokens(lexer: Lexer) -> Vec<(Token, usize, &'static [NonMinimalKind])> {
+            lexer
+                .map(|lexed| {
+                    let lexed = lexed.unwrap();
+                    (lexed.token, lexed.position.byte_offset, lexed.non_minimal)
+                })
+                .collect()
+        }
+
+        // Opcodes with a verify form are lexed into two tokens sharing a position.
+        let script = hex_script("51b26951ad");
+        assert_eq!(
+            tokens(Lexer::new(&script)),
+            vec![
+                (Token::Num(1), 0, &[][..]),
+                (Token::CheckSequenceVerify, 1, &[]),
+                (Token::Verify, 2, &[]),
+                (Token::Num(1), 3, &[]),
+                (Token::CheckSig, 4, &[]),
+                (Token::Verify, 4, &[]),
+            ]
+        );
+
+        // Non-minimal encodings are only accepted, and reported, when lenient.
+        let script = hex_script("4c0101ac69");
+        assert!(Lexer::new(&script).any(|lexed| lexed.is_err()));
+        assert_eq!(
+            tokens(Lexer::lenient(&script)),
+            vec![
+                (Token::Num(1), 0, &[NonMinimalKind::Push][..]),
+                (Token::CheckSig, 3, &[]),
+                (Token::Verify, 4, &[NonMinimalKind::Verify]),
+            ]
+        );
+
+        // Lexing stops after the first error.
+        let script = hex_script("516a51");
+        let mut lexer = Lexer::new(&script);
+        assert_eq!(lexer.next().unwrap().unwrap().token, Token::Num(1));
+        match lexer.next() {
+            Some(Err(Error::AtScriptPosition(pos, _))) => assert_eq!(pos.byte_offset, 1),
+            res => panic!("unexpected result {:?}", res),
+        }
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn unspendable_scripts() {
+        let unspendable = |hex: &str| match Segwitv0Script::parse_insane(&hex_script(hex)) {
+            Err(Error::Decode(DecodeError::Unspendable(script))) => script,
+            res => panic!("unexpected result {:?}", res),
+        };
+
+        assert_eq!(unspendable("6a"), UnspendableScript::NullData(vec![]));
+        assert_eq!(
+            unspendable("6a02abcd004f60"),
+            UnspendableScript::NullData(vec![vec![0xab, 0xcd], vec![], vec![0x81], vec![16]])
+        );
+        assert_eq!(unspendable("6a0101"), UnspendableScript::NullData(vec![vec![1]]));
+        assert_eq!(unspendable("6a51b2"), UnspendableScript::OpReturn);
+        assert_eq!(unspendable("6a02ab"), UnspendableScript::OpReturn);
+        assert!(matches!(
+            Segwitv0Script::parse_lenient(&hex_script("6a"), &ExtParams::insane()),
+            Err(Error::Decode(DecodeError::Unspendable(UnspendableScript::NullData(_))))
+        ));
+
+        let spk = bitcoin::ScriptBuf::from_bytes(vec![0x61; 10_001]);
+        assert_eq!(
+            UnspendableScript::from_script_pubkey(&spk),
+            Some(UnspendableScript::Oversized(10_001))
+        );
+        assert_eq!(UnspendableScript::from_script_pubkey(&hex_script("516a")), None);
+        assert_eq!(
+            UnspendableScript::NullData(vec![vec![0xab, 0xcd], vec![]]).to_string(),
+            "null data output abcd <empty>"
+        );
     }
 
     #[test]
@@ -1635,6 +2239,385 @@ mod tests {
         assert_eq!(wit, vec![schnorr_sig.as_ref().to_vec(), vec![], vec![]]);
     }
 
+    #[test]
+    fn satisfier_chain() {
+        use crate::miniscript::satisfy::Preimage32;
+        use crate::SatisfierChain;
+
+        struct Preimages(Vec<Preimage32>);
+        impl<Pk: ToPublicKey> Satisfier<Pk> for Preimages {
+            fn lookup_sha256(&self, h: &Pk::Sha256) -> Option<Preimage32> {
+                let h = Pk::to_sha256(h);
+                self.0
+                    .iter()
+                    .find(|preimage| sha256::Hash::hash(&preimage[..]) == h)
+                    .copied()
+            }
+        }
+
+        let keys = pubkeys(2);
+        let preimage = [0xab; 32];
+        let ms = Segwitv0Script::from_str_insane(&format!(
+            "and_v(v:pk({}),and_v(v:sha256({}),older(10)))",
+            keys[0],
+            sha256::Hash::hash(&preimage)
+        ))
+        .unwrap();
+        let sig = bitcoin::ecdsa::Signature::sighash_all(
+            secp256k1::ecdsa::Signature::from_compact(&[1; 64]).unwrap(),
+        );
+        let sigs: BTreeMap<_, _> = [(keys[0], sig)].into_iter().collect();
+        let preimages = Preimages(vec![[0xcd; 32], preimage]);
+
+        let mut chain = SatisfierChain::new();
+        assert!(chain.is_empty());
+        assert!(ms.satisfy(&chain).is_err());
+        chain.push(&sigs);
+        chain.push(Preimages(vec![]));
+        chain.push(&preimages);
+        assert!(ms.satisfy(&chain).is_err());
+        let chain = chain.with(RelLockTime::from_height(10));
+        assert_eq!(chain.len(), 4);
+        assert_eq!(ms.satisfy(&chain).unwrap(), vec![preimage.to_vec(), sig.to_vec()]);
+    }
+
+    #[test]
+    fn tx_satisfier() {
+        use bitcoin::{
+            absolute, transaction, Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut,
+        };
+
+        use crate::TxSatisfier;
+
+        let keys = pubkeys(1);
+        let ms = Segwitv0Script::from_str_insane(&format!(
+            "and_v(v:pk({}),and_v(v:after(500000),older(10)))",
+            keys[0]
+        ))
+        .unwrap();
+        let sig = bitcoin::ecdsa::Signature::sighash_all(
+            secp256k1::ecdsa::Signature::from_compact(&[1; 64]).unwrap(),
+        );
+        let sigs: BTreeMap<_, _> = [(keys[0], sig)].into_iter().collect();
+        let mut tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::from_height(500000).unwrap(),
+            input: vec![TxIn { previous_output: OutPoint::null(), ..TxIn::default() }],
+            output: vec![],
+        };
+        let prevouts = [TxOut { value: Amount::from_sat(1000), script_pubkey: ScriptBuf::new() }];
+
+        // The default sequence enables neither the locktime nor relative locktimes
+        let satisfier = TxSatisfier::new(&sigs, &tx, 0, &prevouts);
+        assert_eq!(satisfier.spent_output(), Some(&prevouts[0]));
+        assert!(ms.satisfy(&satisfier).is_err());
+        tx.input[0].sequence = Sequence::from_height(10);
+        assert_eq!(
+            ms.satisfy(TxSatisfier::new(&sigs, &tx, 0, &prevouts)).unwrap(),
+            vec![sig.to_vec()]
+        );
+        tx.lock_time = absolute::LockTime::from_height(499999).unwrap();
+        assert!(ms.satisfy(TxSatisfier::new(&sigs, &tx, 0, &prevouts)).is_err());
+        tx.lock_time = absolute::LockTime::from_height(500000).unwrap();
+        tx.version = transaction::Version::ONE;
+        assert!(ms.satisfy(TxSatisfier::new(&sigs, &tx, 0, &prevouts)).is_err());
+    }
+
+    #[test]
+    fn lazy_preimage_satisfier() {
+        use core::cell::RefCell;
+
+        use crate::{LazyPreimageSatisfier, Preimage32, PreimageFetcher, PreimageHash};
+
+        struct Fetcher<'a> {
+            preimages: BTreeMap<PreimageHash, Preimage32>,
+            fetched: &'a RefCell<Vec<PreimageHash>>,
+        }
+
+        impl PreimageFetcher for Fetcher<'_> {
+            type Error = &'static str;
+
+            fn can_fetch(&self, _: &PreimageHash) -> bool { true }
+
+            fn fetch(&self, hash: &PreimageHash) -> Result<Preimage32, &'static str> {
+                self.fetched.borrow_mut().push(*hash);
+                self.preimages.get(hash).copied().ok_or("offline")
+            }
+        }
+
+        let keys = pubkeys(1);
+        let hashes = [[1; 32], [2; 32]].map(|preimage| sha256::Hash::hash(&preimage));
+        let ms = Segwitv0Script::from_str_insane(&format!(
+            "or_i(and_v(v:pk({}),sha256({})),and_v(v:pk({}),sha256({})))",
+            keys[0], hashes[0], keys[0], hashes[1]
+        ))
+        .unwrap();
+        let sig = bitcoin::ecdsa::Signature::sighash_all(
+            secp256k1::ecdsa::Signature::from_compact(&[1; 64]).unwrap(),
+        );
+        let sigs: BTreeMap<_, _> = [(keys[0], sig)].into_iter().collect();
+        let witness = vec![vec![2; 32], sig.to_vec(), vec![]];
+
+        // Only the preimage of the chosen branch is fetched, and only once
+        let fetched = RefCell::new(vec![]);
+        let satisfier = LazyPreimageSatisfier::new(Fetcher {
+            preimages: [(PreimageHash::Sha256(hashes[1]), [2; 32])]
+                .into_iter()
+                .collect(),
+            fetched: &fetched,
+        });
+        assert_eq!(ms.satisfy((&sigs, &satisfier)).unwrap(), witness);
+        assert_eq!(ms.satisfy((&sigs, &satisfier)).unwrap(), witness);
+        assert_eq!(*fetched.borrow(), vec![PreimageHash::Sha256(hashes[1])]);
+        assert!(satisfier.take_errors().is_empty());
+
+        // A preimage which cannot be fetched after all makes the satisfaction fail
+        let satisfier =
+            LazyPreimageSatisfier::new(Fetcher { preimages: BTreeMap::new(), fetched: &fetched });
+        assert!(ms.satisfy((&sigs, &satisfier)).is_err());
+        assert_eq!(satisfier.take_errors(), vec![(PreimageHash::Sha256(hashes[1]), "offline")]);
+    }
+
+    #[test]
+    fn map_satisfier() {
+        use bitcoin::taproot::LeafVersion;
+
+        use crate::MapSatisfier;
+
+        let keys = pubkeys(2);
+        let preimage = [0xab; 32];
+        let hash = sha256::Hash::hash(&preimage);
+        let ms = Segwitv0Script::from_str_insane(&format!(
+            "and_v(v:pkh({}),and_v(v:pk({}),sha256({})))",
+            keys[0], keys[1], hash
+        ))
+        .unwrap();
+        let sig = bitcoin::ecdsa::Signature::sighash_all(
+            secp256k1::ecdsa::Signature::from_compact(&[1; 64]).unwrap(),
+        );
+        let mut satisfier = MapSatisfier::new();
+        satisfier.add_ecdsa_sig(keys[0], sig);
+        satisfier.add_ecdsa_sig(keys[1], sig);
+        assert!(ms.satisfy(&satisfier).is_err());
+        satisfier.sha256_preimages.insert(hash, preimage);
+        assert_eq!(
+            ms.satisfy(&satisfier).unwrap(),
+            vec![preimage.to_vec(), sig.to_vec(), sig.to_vec(), keys[0].to_bytes()]
+        );
+
+        let xonly = keys
+            .iter()
+            .map(|pk| pk.to_x_only_pubkey())
+            .collect::<Vec<_>>();
+        let ms = Miniscript::<XOnlyPublicKey, Tap>::from_str(&format!(
+            "and_v(v:pk({}),pk({}))",
+            xonly[0], xonly[1]
+        ))
+        .unwrap();
+        let leaf_hash = TapLeafHash::from_script(&ms.encode(), LeafVersion::TapScript);
+        let other_leaf_hash =
+            TapLeafHash::from_script(bitcoin::Script::new(), LeafVersion::TapScript);
+        let schnorr_sig = |b: u8| bitcoin::taproot::Signature {
+            signature: secp256k1::schnorr::Signature::from_slice(&[b; 64]).unwrap(),
+            sighash_type: bitcoin::sighash::TapSighashType::Default,
+        };
+        let mut satisfier = MapSatisfier::new();
+        // Signatures are looked up under the leaf hash of the script only.
+        satisfier.add_tap_leaf_script_sig(xonly[0], leaf_hash, schnorr_sig(1));
+        satisfier.add_tap_leaf_script_sig(xonly[1], other_leaf_hash, schnorr_sig(2));
+        satisfier.add_tap_key_spend_sig(xonly[1], schnorr_sig(3));
+        assert!(ms.satisfy(&satisfier).is_err());
+        assert_eq!(
+            Satisfier::<XOnlyPublicKey>::lookup_tap_key_spend_sig(&satisfier),
+            Some(schnorr_sig(3))
+        );
+        satisfier.add_tap_leaf_script_sig(xonly[1], leaf_hash, schnorr_sig(2));
+        assert_eq!(
+            ms.satisfy(&satisfier).unwrap(),
+            vec![schnorr_sig(2).to_vec(), schnorr_sig(1).to_vec()]
+        );
+    }
+
+    #[test]
+    fn dissatisfy() {
+        let keys = pubkeys(2);
+        let dissat = |s: &str| {
+            let ms = Segwitv0Script::from_str_insane(
+                &s.replace("A", &keys[0].to_string())
+                    .replace("B", &keys[1].to_string()),
+            )
+            .unwrap();
+            ms.dissatisfy(())
+        };
+
+        assert_eq!(dissat("and_b(pk(A),s:pk(B))").unwrap(), vec![vec![], vec![]]);
+        assert_eq!(dissat("or_i(pk(A),pk(B))").unwrap(), vec![vec![], vec![]]);
+        assert_eq!(dissat("multi(1,A,B)").unwrap(), vec![vec![], vec![]]);
+        assert_eq!(dissat("pkh(A)").unwrap(), vec![vec![], keys[0].to_bytes()]);
+        assert_eq!(dissat("thresh(1,pk(A),s:pk(B))").unwrap(), vec![vec![], vec![]]);
+        assert!(matches!(dissat("and_v(v:pk(A),pk(B))"), Err(Error::CouldNotSatisfy)));
+        assert!(matches!(dissat("older(10)"), Err(Error::CouldNotSatisfy)));
+    }
+
+    #[test]
+    fn thresh_satisfaction_selection() {
+        use crate::MapSatisfier;
+
+        let keys = pubkeys(10);
+        let children = keys
+            .iter()
+            .enumerate()
+            .map(|(i, pk)| if i == 0 { format!("pk({})", pk) } else { format!("s:pk({})", pk) })
+            .collect::<Vec<_>>();
+        let ms = Segwitv0Script::from_str(&format!("thresh(3,{})", children.join(","))).unwrap();
+        let sig = |b: u8| {
+            bitcoin::ecdsa::Signature::sighash_all(
+                secp256k1::ecdsa::Signature::from_compact(&[b; 64]).unwrap(),
+            )
+        };
+
+        let mut satisfier = MapSatisfier::new();
+        for i in [2, 5] {
+            satisfier.add_ecdsa_sig(keys[i], sig(i as u8));
+        }
+        assert!(ms.satisfy(&satisfier).is_err());
+        // With more signatures than needed, the first available ones are used.
+        for i in [7, 8] {
+            satisfier.add_ecdsa_sig(keys[i], sig(i as u8));
+        }
+        let mut expected = vec![vec![]; 10];
+        for i in [2, 5, 7] {
+            expected[9 - i] = sig(i as u8).to_vec();
+        }
+        assert_eq!(ms.satisfy(&satisfier).unwrap(), expected);
+        assert_eq!(ms.satisfy_malleable(&satisfier).unwrap(), expected);
+    }
+
+    #[test]
+    fn canonical_satisfaction() {
+        use crate::MapSatisfier;
+
+        let keys = pubkeys(4);
+        let sig = |b: u8| {
+            bitcoin::ecdsa::Signature::sighash_all(
+                secp256k1::ecdsa::Signature::from_compact(&[b; 64]).unwrap(),
+            )
+        };
+        let mut satisfier = MapSatisfier::new();
+        for (i, pk) in keys.iter().enumerate() {
+            satisfier.add_ecdsa_sig(*pk, sig(i as u8 + 1));
+        }
+        let satisfy = |s: &str| {
+            Segwitv0Script::from_str(
+                &s.replace("A", &keys[0].to_string())
+                    .replace("B", &keys[1].to_string())
+                    .replace("C", &keys[2].to_string())
+                    .replace("D", &keys[3].to_string()),
+            )
+            .unwrap()
+            .satisfy(&satisfier)
+            .unwrap()
+        };
+
+        // Disjunctions between equally expensive branches use the left one
+        assert_eq!(satisfy("or_b(pk(A),s:pk(B))"), vec![vec![], sig(1).to_vec()]);
+        // Thresholds use their leftmost children
+        assert_eq!(
+            satisfy("thresh(2,pk(A),s:pk(B),s:pk(C))"),
+            vec![vec![], sig(2).to_vec(), sig(1).to_vec()]
+        );
+        // Multisigs use the first keys
+        assert_eq!(
+            satisfy("multi(2,A,B,C,D)"),
+            vec![vec![], sig(1).to_vec(), sig(2).to_vec()]
+        );
+    }
+
+    #[test]
+    fn preferred_satisfaction() {
+        use crate::{KeyPreferences, MapSatisfier};
+
+        let keys = pubkeys(3);
+        let preimage = [0xab; 32];
+        let hash = sha256::Hash::hash(&preimage);
+        let sig = |b: u8| {
+            bitcoin::ecdsa::Signature::sighash_all(
+                secp256k1::ecdsa::Signature::from_compact(&[b; 64]).unwrap(),
+            )
+        };
+        let mut satisfier = MapSatisfier::new();
+        for (i, pk) in keys.iter().enumerate() {
+            satisfier.add_ecdsa_sig(*pk, sig(i as u8 + 1));
+        }
+        satisfier.sha256_preimages.insert(hash, preimage);
+        let ms = |s: &str| {
+            Segwitv0Script::from_str_insane(
+                &s.replace("A", &keys[0].to_string())
+                    .replace("B", &keys[1].to_string())
+                    .replace("C", &keys[2].to_string())
+                    .replace("H", &hash.to_string()),
+            )
+            .unwrap()
+        };
+
+        // Avoiding a key selects other keys of the same weight
+        let avoid_a = (&satisfier, KeyPreferences::new().prefer(keys[0], -1));
+        assert_eq!(
+            ms("thresh(2,pk(A),s:pk(B),s:pk(C))")
+                .satisfy(&avoid_a)
+                .unwrap(),
+            vec![sig(3).to_vec(), sig(2).to_vec(), vec![]]
+        );
+        assert_eq!(
+            ms("multi(2,A,B,C)").satisfy(&avoid_a).unwrap(),
+            vec![vec![], sig(2).to_vec(), sig(3).to_vec()]
+        );
+
+        // Preferring keys over a cheaper preimage needs enough tolerance
+        let thresh = ms("thresh(2,pk(A),s:pk(B),a:sha256(H))");
+        let prefer_keys = KeyPreferences::new().prefer(keys[0], 1).prefer(keys[1], 1);
+        assert_eq!(
+            thresh
+                .satisfy_malleable((&satisfier, prefer_keys.clone().tolerance(71)))
+                .unwrap(),
+            vec![preimage.to_vec(), vec![], sig(1).to_vec()]
+        );
+        assert_eq!(
+            thresh
+                .satisfy_malleable((&satisfier, prefer_keys.tolerance(72)))
+                .unwrap(),
+            vec![vec![0; 32], sig(2).to_vec(), sig(1).to_vec()]
+        );
+    }
+
+    #[test]
+    fn sigops_budget() {
+        use crate::miniscript::context::ScriptContextError;
+
+        type TapMs = Miniscript<String, Tap>;
+        let budget = |s: &str| Tap::sigops_budget(&TapMs::from_str(s).unwrap());
+
+        let b = budget("thresh(1,pk(A),s:pk(B),s:pk(C))");
+        assert_eq!((b.sigops, b.used), (1, 50));
+        assert!(b.fits());
+        let b = budget("and_v(v:pk(A),or_d(multi_a(2,B,C,D),pk(E)))");
+        assert_eq!((b.sigops, b.used), (3, 150));
+        assert_eq!(b.remaining(), Some(b.available - 150));
+
+        let keys = |n: usize| (0..n).map(|i| format!(",K{}", i)).collect::<String>();
+        let b = budget(&format!("multi_a(400{})", keys(500)));
+        assert_eq!(b.sigops, 400);
+        assert!(b.available >= b.used + 400 * 14);
+
+        // A large multi_a fits the sigops budget, but not the stack
+        let ms = TapMs::from_str_insane(&format!("multi_a(999{})", keys(999))).unwrap();
+        assert!(Tap::sigops_budget(&ms).fits());
+        assert!(matches!(
+            Tap::check_local_consensus_validity(&ms),
+            Err(ScriptContextError::StackSizeLimitExceeded { .. })
+        ));
+    }
+
     #[test]
     fn decode_bug_cpp_review() {
         let ms = Miniscript::<String, Segwitv0>::from_str_insane(
@@ -1751,6 +2734,487 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn resource_usage() {
+        let ms = Miniscript::<String, Segwitv0>::from_str("multi(2,A,B,C)").unwrap();
+        assert_eq!(ms.script_size(), 105);
+        assert_eq!(ms.max_satisfaction_witness_elements().unwrap(), 4);
+        assert_eq!(ms.max_satisfaction_size().unwrap(), 147);
+        assert_eq!(ms.max_satisfaction_op_count().unwrap(), 4);
+        assert_eq!(ms.max_satisfaction_exec_stack_elements().unwrap(), 6);
+
+        let ms =
+            Miniscript::<String, Tap>::from_str("and_v(v:pk(A),or_d(pk(B),older(10)))").unwrap();
+        assert_eq!(ms.script_size(), 73);
+        assert_eq!(ms.max_satisfaction_witness_elements().unwrap(), 3);
+        assert_eq!(ms.max_satisfaction_size().unwrap(), 132);
+        assert_eq!(ms.max_satisfaction_op_count().unwrap(), 6);
+        assert_eq!(ms.max_satisfaction_exec_stack_elements().unwrap(), 3);
+
+        // Unsatisfiable scripts have no satisfaction costs.
+        let ms = Miniscript::<String, Segwitv0>::from_str_insane("and_b(pk(A),a:0)").unwrap();
+        assert!(matches!(ms.max_satisfaction_size(), Err(Error::ImpossibleSatisfaction)));
+        assert!(matches!(ms.max_satisfaction_op_count(), Err(Error::ImpossibleSatisfaction)));
+        assert!(matches!(
+            ms.max_satisfaction_exec_stack_elements(),
+            Err(Error::ImpossibleSatisfaction)
+        ));
+    }
+
+    #[test]
+    fn custom_resource_limits() {
+        use crate::miniscript::analyzable::ExtParams;
+        use crate::miniscript::context::ScriptContextError;
+        use crate::miniscript::limits::Limits;
+
+        let subs = (1..80)
+            .map(|i| format!(",s:pk(K{})", i))
+            .collect::<String>();
+        let ms = Miniscript::<String, Segwitv0>::from_str_ext(
+            &format!("thresh(1,pk(K0){})", subs),
+            &ExtParams::insane(),
+        )
+        .unwrap();
+        assert!(!ms.within_resource_limits());
+        assert!(matches!(
+            ms.check_resource_limits(&Limits::BITCOIN),
+            Err(ScriptContextError::MaxOpCountExceeded { actual: 239, limit: 201 })
+        ));
+        let limits = Limits { max_ops_per_script: 300, ..Limits::BITCOIN };
+        ms.check_resource_limits(&limits).unwrap();
+
+        let witness = vec![vec![]; 101];
+        assert!(Segwitv0::check_witness(&witness).is_err());
+        let limits = Limits { max_standard_p2wsh_stack_items: 101, ..Limits::BITCOIN };
+        Segwitv0::check_witness_with_limits(&witness, &limits).unwrap();
+    }
+
+    #[test]
+    fn resource_limit_usage() {
+        use crate::miniscript::analyzable::ExtParams;
+        use crate::miniscript::limits::{LimitUsage, Limits};
+
+        let subs = (1..80)
+            .map(|i| format!(",s:pk(K{})", i))
+            .collect::<String>();
+        let ms = Miniscript::<String, Segwitv0>::from_str_ext(
+            &format!("thresh(1,pk(K0){})", subs),
+            &ExtParams::insane(),
+        )
+        .unwrap();
+        let usage = ms.resource_usage();
+        assert_eq!(usage.op_count, Some(LimitUsage { used: 239, limit: 201 }));
+        assert_eq!(usage.op_count.unwrap().margin(), None);
+        assert_eq!(usage.witness_items, Some(LimitUsage { used: 81, limit: 100 }));
+        assert_eq!(usage.script_size.unwrap().limit, 3600);
+        assert_eq!(usage.stack_size, None);
+        assert_eq!(usage.scriptsig_size, None);
+        assert!(!usage.fits());
+        assert_eq!(usage.tightest().unwrap().0, "op_count");
+
+        // The report follows the given limits.
+        let limits = Limits { max_ops_per_script: 300, ..Limits::BITCOIN };
+        let usage = ms.resource_usage_with_limits(&limits);
+        assert_eq!(usage.op_count.unwrap().margin(), Some(61));
+        assert!(usage.fits());
+        assert_eq!(usage.tightest().unwrap().0, "witness_items");
+
+        let ms = Miniscript::<String, Legacy>::from_str("and_v(v:pk(A),pk(B))").unwrap();
+        let usage = ms.resource_usage();
+        assert_eq!(usage.script_size, Some(LimitUsage { used: 70, limit: 520 }));
+        assert_eq!(usage.scriptsig_size.unwrap().limit, 1650);
+        assert_eq!(usage.witness_items, None);
+
+        let ms = Miniscript::<String, Tap>::from_str("and_v(v:pk(A),pk(B))").unwrap();
+        let usage = ms.resource_usage();
+        assert_eq!(usage.op_count, None);
+        assert_eq!(usage.stack_size.unwrap().limit, 1000);
+        assert!(usage.fits());
+
+        // Satisfaction limits are not reported for unsatisfiable Miniscripts.
+        let ms = Miniscript::<String, Segwitv0>::from_str_insane("and_v(v:pk(A),0)").unwrap();
+        let usage = ms.resource_usage();
+        assert!(usage.script_size.is_some());
+        assert_eq!(usage.op_count, None);
+        assert_eq!(usage.witness_items, None);
+    }
+
+    #[test]
+    fn satisfaction_size_with_sig_sizes() {
+        use crate::miniscript::types::ExtData;
+        use crate::SigSizes;
+
+        let ms = Miniscript::<String, Segwitv0>::from_str(
+            "or_d(multi(2,A,B,C),and_v(v:pkh(D),older(10)))",
+        )
+        .unwrap();
+        assert_eq!(ExtData::for_sig_sizes(&ms, &SigSizes::WORST_CASE), ms.ext);
+        assert_eq!(
+            ms.max_satisfaction_size_with_sig_sizes(&SigSizes::WORST_CASE)
+                .unwrap(),
+            ms.max_satisfaction_size().unwrap(),
+        );
+        assert_eq!(ms.max_satisfaction_size().unwrap(), 147);
+        assert_eq!(
+            ms.max_satisfaction_size_with_sig_sizes(&SigSizes::LOW_R)
+                .unwrap(),
+            145
+        );
+
+        // The scriptSig size is used outside of segwit.
+        let ms = Miniscript::<String, Legacy>::from_str("pk(A)").unwrap();
+        assert_eq!(ms.max_satisfaction_size().unwrap(), 73);
+        assert_eq!(
+            ms.max_satisfaction_size_with_sig_sizes(&SigSizes::LOW_R)
+                .unwrap(),
+            72
+        );
+
+        let ms = Miniscript::<String, Tap>::from_str("multi_a(2,A,B,C)").unwrap();
+        let sighash_default = SigSizes { schnorr: 65, ..SigSizes::WORST_CASE };
+        assert_eq!(ms.max_satisfaction_size().unwrap(), 133);
+        assert_eq!(
+            ms.max_satisfaction_size_with_sig_sizes(&sighash_default)
+                .unwrap(),
+            131
+        );
+    }
+
+    #[test]
+    fn satisfaction_weight() {
+        use bitcoin::Weight;
+
+        use crate::SigSizes;
+
+        let ms = Miniscript::<String, Legacy>::from_str("pk(A)").unwrap();
+        let weight = ms.max_satisfaction_weight(&SigSizes::WORST_CASE).unwrap();
+        assert_eq!(weight.scriptsig, Weight::from_vb(73).unwrap());
+        assert_eq!(weight.witness, Weight::ZERO);
+
+        let ms = Miniscript::<String, Segwitv0>::from_str("pk(A)").unwrap();
+        let weight = ms.max_satisfaction_weight(&SigSizes::LOW_R).unwrap();
+        assert_eq!(weight.scriptsig, Weight::ZERO);
+        assert_eq!(weight.witness, Weight::from_wu(72));
+        assert_eq!(weight.total(), Weight::from_wu(72));
+
+        let ms = Miniscript::<String, Segwitv0>::from_str_insane("and_v(v:pk(A),0)").unwrap();
+        assert!(matches!(
+            ms.max_satisfaction_weight(&SigSizes::WORST_CASE),
+            Err(Error::ImpossibleSatisfaction)
+        ));
+    }
+
+    #[test]
+    fn malleability_report() {
+        use crate::miniscript::analyzable::{AnalysisError, MalleabilityCause, PathCondition};
+        use crate::miniscript::context::ScriptContextError;
+
+        let key = |k: &str| PathCondition::Key(String::from(k));
+
+        let ms = Miniscript::<String, Segwitv0>::from_str("or_d(pk(A),pk(B))").unwrap();
+        let report = ms.malleability_report(10).unwrap();
+        assert!(report.is_non_malleable());
+        assert!(report.fragments.is_empty());
+        let conditions = report
+            .paths
+            .iter()
+            .map(|p| p.conditions.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(conditions, vec![vec![key("A")], vec![key("B")]]);
+        assert!(matches!(ms.malleability_report(1), Err(AnalysisError::TooManyPaths)));
+
+        // or_i is only malleable where MINIMALIF is not a consensus rule.
+        let ms = Miniscript::<String, Segwitv0>::from_str("c:or_i(pk_k(A),pk_k(B))").unwrap();
+        assert!(ms.malleability_report(10).unwrap().is_non_malleable());
+        let ms = Miniscript::<String, Legacy>::from_str("c:or_i(pk_k(A),pk_k(B))").unwrap();
+        let report = ms.malleability_report(10).unwrap();
+        assert_eq!(report.fragments.len(), 1);
+        assert_eq!(
+            report.fragments[0].cause,
+            MalleabilityCause::Context(ScriptContextError::MalleableOrI)
+        );
+        assert_eq!(report.fragments[0].fragment.to_string(), "or_i(pk_k(A),pk_k(B))");
+        assert_eq!(report.malleable_paths().count(), 2);
+        assert!(report
+            .paths
+            .iter()
+            .all(|p| p.non_malleable_alternative.is_none()));
+
+        // Neither timelock branch requires a signature, so a third party may
+        // switch between them; a signature by A alone is a non-malleable path.
+        let ms = Miniscript::<String, Segwitv0>::from_str_insane(
+            "or_d(multi(1,A,B),and_v(v:pk(A),or_i(older(10),after(20))))",
+        )
+        .unwrap();
+        let report = ms.malleability_report(10).unwrap();
+        assert_eq!(report.fragments.len(), 1);
+        assert_eq!(report.fragments[0].cause, MalleabilityCause::Type);
+        assert_eq!(report.fragments[0].fragment.to_string(), "or_i(older(10),after(20))");
+        assert_eq!(report.paths.len(), 4);
+        assert_eq!(
+            report.paths[2].conditions,
+            vec![key("A"), PathCondition::Older(RelLockTime::from_height(10))]
+        );
+        for path in &report.paths[..2] {
+            assert!(!path.is_malleable());
+        }
+        for path in &report.paths[2..] {
+            assert_eq!(path.malleable_fragments, vec![0]);
+            assert_eq!(path.non_malleable_alternative, Some(0));
+        }
+
+        // Thresholds choose any k of their subexpressions.
+        let ms = Miniscript::<String, Segwitv0>::from_str("thresh(2,pk(A),s:pk(B),sln:after(100))")
+            .unwrap();
+        let report = ms.malleability_report(10).unwrap();
+        assert!(report.is_non_malleable());
+        assert_eq!(report.paths.len(), 3);
+        assert_eq!(report.paths[0].conditions, vec![key("A"), key("B")]);
+    }
+
+    #[test]
+    fn type_check_expression() {
+        use crate::expression::Tree;
+        use crate::miniscript::types::{Base, ErrorKind, ExpressionError, Type};
+
+        for s in [
+            "pk(A)",
+            "and_v(v:pk(A),pk(B))",
+            "or_b(pk(A),s:pk(B))",
+            "andor(pk(A),older(10),pkh(B))",
+            "and_n(pk(A),sha256(H))",
+            "thresh(2,pk(A),s:pk(B),sln:after(100))",
+            "c:or_i(pk_k(A),pk_h(B))",
+            "t:or_c(pk(A),v:multi(1,B,C))",
+            "or_d(multi(1,A,B),and_v(v:pk(C),or_i(older(10),after(20))))",
+            "n:and_b(0,a:1)",
+        ] {
+            let ms = Miniscript::<String, Segwitv0>::from_str_insane(s).unwrap();
+            let tree = Tree::from_str(s).unwrap();
+            assert_eq!(Type::type_check_expression(tree.root()).unwrap(), ms.ty, "{}", s);
+        }
+
+        let tree = Tree::from_str("thresh(2,pk(A),pk(B))").unwrap();
+        assert_eq!(
+            Type::type_check_expression(tree.root()),
+            Err(ExpressionError::Type {
+                pos: 0,
+                child_pos: Some(15),
+                error: types::Error {
+                    fragment_string: "thresh".to_owned(),
+                    error: ErrorKind::ThresholdBase(1, Base::B),
+                },
+            })
+        );
+        let tree = Tree::from_str("and_v(pk(A),s:pk(B))").unwrap();
+        assert!(matches!(
+            Type::type_check_expression(tree.root()),
+            Err(ExpressionError::Type { pos: 0, child_pos: None, .. })
+        ));
+        let tree = Tree::from_str("and_v(v:pk(A),x:pk(B))").unwrap();
+        assert_eq!(
+            Type::type_check_expression(tree.root()),
+            Err(ExpressionError::UnknownWrapper('x'))
+        );
+        let tree = Tree::from_str("or_q(pk(A),pk(B))").unwrap();
+        assert!(matches!(
+            Type::type_check_expression(tree.root()),
+            Err(ExpressionError::Tree(_))
+        ));
+    }
+
+    #[test]
+    fn custom_context() {
+        use crate::miniscript::context::{ContextParams, CustomCtx};
+        use crate::miniscript::limits::Limits;
+
+        #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        enum Relaxed {}
+        impl ContextParams for Relaxed {
+            type Key = bitcoin::PublicKey;
+            const NAME: &'static str = "Relaxed";
+            const LIMITS: Limits = Limits { max_ops_per_script: 300, ..Limits::BITCOIN };
+        }
+
+        #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        enum Schnorr {}
+        impl ContextParams for Schnorr {
+            type Key = bitcoin::secp256k1::XOnlyPublicKey;
+            const NAME: &'static str = "Schnorr";
+        }
+
+        let subs = (1..80)
+            .map(|i| format!(",s:pk(K{})", i))
+            .collect::<String>();
+        let s = format!("thresh(1,pk(K0){})", subs);
+        assert!(Miniscript::<String, Segwitv0>::from_str(&s).is_err());
+        let ms = Miniscript::<String, CustomCtx<Relaxed>>::from_str(&s).unwrap();
+        ms.sanity_check().unwrap();
+        assert_eq!(CustomCtx::<Relaxed>::sig_type(), crate::SigType::Ecdsa);
+        assert_eq!(CustomCtx::<Relaxed>::name_str(), "Relaxed");
+
+        // The remaining rules follow the signature type of the key.
+        let witness = vec![vec![]; 101];
+        assert!(CustomCtx::<Relaxed>::check_witness(&witness).is_err());
+        CustomCtx::<Schnorr>::check_witness(&witness).unwrap();
+        assert!(Miniscript::<String, CustomCtx<Relaxed>>::from_str("multi_a(1,A,B)").is_err());
+        assert!(Miniscript::<String, CustomCtx<Schnorr>>::from_str("multi(1,A,B)").is_err());
+        Miniscript::<String, CustomCtx<Schnorr>>::from_str("multi_a(1,A,B)").unwrap();
+
+        // Scripts are encoded as in Tapscript.
+        let xonly = "cc8a4bc64d897bddc5fbc2f670f7a8ba0b386779106cf1223c6fc5d7cd6fc115";
+        let ms =
+            Miniscript::<XOnlyPublicKey, CustomCtx<Schnorr>>::from_str(&format!("pk({})", xonly))
+                .unwrap();
+        let tap = Miniscript::<XOnlyPublicKey, Tap>::from_str(&format!("pk({})", xonly)).unwrap();
+        assert_eq!(ms.encode(), tap.encode());
+        assert_eq!(Miniscript::<_, CustomCtx<Schnorr>>::parse(&ms.encode()).unwrap(), ms);
+    }
+
+    #[test]
+    fn replace_subtree() {
+        type Ms = Miniscript<String, Segwitv0>;
+        let ms = Ms::from_str("and_v(v:pk(A),or_d(pk(B),older(10)))").unwrap();
+
+        let pkh = Arc::new(Ms::from_str("pkh(C)").unwrap());
+        let new = ms.replace_subtree(&[1, 0], Arc::clone(&pkh)).unwrap();
+        let expected = Ms::from_str("and_v(v:pk(A),or_d(pkh(C),older(10)))").unwrap();
+        assert_eq!(new, expected);
+        assert_eq!(new.ty, expected.ty);
+        assert_eq!(new.ext, expected.ext);
+        // Subtrees off the path are shared, not copied.
+        match (&ms.node, &new.node) {
+            (Terminal::AndV(old_l, _), Terminal::AndV(new_l, _)) => {
+                assert!(Arc::ptr_eq(old_l, new_l))
+            }
+            _ => unreachable!(),
+        }
+
+        // The types along the path are recomputed.
+        let new = ms
+            .replace_subtree(&[1], Arc::new(Ms::from_str_insane("older(20)").unwrap()))
+            .unwrap();
+        let expected = Ms::from_str("and_v(v:pk(A),older(20))").unwrap();
+        assert_eq!(new, expected);
+        assert_eq!(new.ty, expected.ty);
+        assert_eq!(new.ext, expected.ext);
+        assert_ne!(new.ext, ms.ext);
+
+        // Replacing the root just returns the replacement.
+        assert_eq!(ms.replace_subtree(&[], Arc::clone(&pkh)).unwrap(), *pkh);
+
+        // Errors
+        assert!(matches!(
+            ms.replace_subtree(&[1, 2], Arc::clone(&pkh)),
+            Err(Error::NoSuchChild(ref path)) if path == &[1, 2]
+        ));
+        assert!(matches!(
+            ms.replace_subtree(&[0, 0, 0, 0], Arc::clone(&pkh)),
+            Err(Error::NoSuchChild(ref path)) if path == &[0, 0, 0, 0]
+        ));
+        assert!(matches!(ms.replace_subtree(&[0], pkh), Err(Error::TypeCheck(_))));
+    }
+
+    #[test]
+    fn typed_constructors() {
+        type Ms = Miniscript<String, Segwitv0>;
+        type TapMs = Miniscript<String, Tap>;
+        let pk = |k: &str| Arc::new(Ms::pk(k.to_owned()));
+
+        let ms = Ms::and_v(
+            Arc::new(Ms::verify(pk("A")).unwrap()),
+            Arc::new(Ms::or_d(pk("B"), Arc::new(Ms::older(RelLockTime::from_height(10)))).unwrap()),
+        )
+        .unwrap();
+        let expected = Ms::from_str("and_v(v:pk(A),or_d(pk(B),older(10)))").unwrap();
+        assert_eq!(ms, expected);
+        assert_eq!(ms.ty, expected.ty);
+        assert_eq!(ms.ext, expected.ext);
+
+        let ms = Ms::thresh(
+            2,
+            vec![
+                pk("A"),
+                Arc::new(Ms::swap(pk("B")).unwrap()),
+                Arc::new(Ms::alt(Arc::new(Ms::pkh("C".to_owned()))).unwrap()),
+            ],
+        )
+        .unwrap();
+        let expected = Ms::from_str("thresh(2,pk(A),s:pk(B),a:pkh(C))").unwrap();
+        assert_eq!(ms, expected);
+        assert_eq!(ms.ext, expected.ext);
+
+        let ms = Ms::and_n(pk("A"), pk("B")).unwrap();
+        assert_eq!(ms, Ms::from_str_insane("and_n(pk(A),pk(B))").unwrap());
+
+        // Type errors
+        assert!(matches!(Ms::and_v(pk("A"), pk("B")), Err(Error::TypeCheck(_))));
+        assert!(matches!(
+            Ms::verify(Arc::new(Ms::verify(pk("A")).unwrap())),
+            Err(Error::TypeCheck(_))
+        ));
+        assert!(matches!(Ms::thresh(0, vec![pk("A")]), Err(Error::Threshold(_))));
+        // Context errors
+        let keys = vec!["A".to_owned(), "B".to_owned()];
+        Ms::multi(1, keys.clone()).unwrap();
+        assert!(matches!(Ms::multi_a(1, keys.clone()), Err(Error::ContextError(_))));
+        TapMs::multi_a(1, keys.clone()).unwrap();
+        assert!(matches!(TapMs::multi(1, keys), Err(Error::ContextError(_))));
+    }
+
+    #[test]
+    fn rewrite() {
+        type Ms = Miniscript<String, Segwitv0>;
+        let ms = Ms::from_str("or_d(pk(A),and_v(v:pk(B),after(10)))").unwrap();
+
+        // Unchanged
+        assert_eq!(ms.rewrite(|_| None).unwrap(), ms);
+
+        // Replace a key with a multisig.
+        let pk_a = Ms::from_str("pk(A)").unwrap();
+        let new = ms
+            .rewrite(|node| {
+                if *node == pk_a {
+                    Some(Ms::from_str("multi(2,A,C)").unwrap())
+                } else {
+                    None
+                }
+            })
+            .unwrap();
+        let expected = Ms::from_str("or_d(multi(2,A,C),and_v(v:pk(B),after(10)))").unwrap();
+        assert_eq!(new, expected);
+        assert_eq!(new.ty, expected.ty);
+        assert_eq!(new.ext, expected.ext);
+
+        // Replace all timelocks; replacements of children are seen by parents.
+        let mut seen = vec![];
+        let new = ms
+            .rewrite(|node| {
+                seen.push(node.to_string());
+                match node.node {
+                    Terminal::After(t) => Some(Ms::after(
+                        AbsLockTime::from_consensus(t.to_consensus_u32() + 90).unwrap(),
+                    )),
+                    _ => None,
+                }
+            })
+            .unwrap();
+        assert_eq!(new, Ms::from_str("or_d(pk(A),and_v(v:pk(B),after(100)))").unwrap());
+        assert_eq!(seen.last().unwrap(), "or_d(pk(A),and_v(v:pk(B),after(100)))");
+
+        // Replacements which do not typecheck are rejected.
+        let err = ms
+            .rewrite(|node| {
+                if *node == pk_a {
+                    Some(Ms::from_str_insane("older(10)").unwrap())
+                } else {
+                    None
+                }
+            })
+            .unwrap_err();
+        assert!(matches!(err, Error::TypeCheck(_)));
+    }
+
     #[test]
     fn template_timelocks() {
         use crate::{AbsLockTime, RelLockTime};
@@ -1847,10 +3311,35 @@ mod tests {
     #[test]
     fn test_dos() {
         let ms = "slnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnn:0";
-        matches!(
-            Miniscript::<String, Tap>::from_str_insane(ms),
-            Err(Error::MaxRecursiveDepthExceeded)
+        let _ = Miniscript::<String, Tap>::from_str_insane(ms);
+    }
+
+    #[test]
+    fn deep_miniscript() {
+        use crate::miniscript::satisfy::Witness;
+        use crate::policy::semantic::Policy as Semantic;
+
+        // Deep Miniscripts can be handled without overflowing the stack.
+        const DEPTH: usize = 20_000;
+        let s = "and_v(v:1,".repeat(DEPTH) + "1" + &")".repeat(DEPTH);
+        let ms = Miniscript::<String, Tap>::from_str_insane(&s).unwrap();
+        // The innermost `and_v(v:1,1)` is displayed as `tv:1`.
+        let expected = "and_v(v:1,".repeat(DEPTH - 1) + "tv:1" + &")".repeat(DEPTH - 1);
+        assert_eq!(ms.to_string(), expected);
+        assert_eq!(ms.ext.tree_height, DEPTH + 1);
+        assert_eq!(ms.clone(), ms);
+        assert_eq!(ms.lift().unwrap().normalized(), Semantic::Trivial);
+
+        let ms = ms.translate_pk(&mut StrXOnlyKeyTranslator::new()).unwrap();
+        let script = ms.encode();
+        assert_eq!(script.len(), ms.script_size());
+        assert_eq!(
+            Miniscript::<XOnlyPublicKey, Tap>::parse_insane(&script)
+                .unwrap()
+                .encode(),
+            script
         );
+        assert_eq!(ms.build_template(&()).stack, Witness::Stack(vec![]));
     }
 
     #[test]
@@ -1859,7 +3348,11 @@ mod tests {
         for _ in 0..10000 {
             script = script.push_opcode(bitcoin::opcodes::all::OP_0NOTEQUAL);
         }
-        Tapscript::parse_insane(&script.into_script()).unwrap_err();
+        // Deeply nested scripts are no longer rejected, and do not overflow the stack.
+        let script = script.into_script();
+        let ms = Tapscript::parse_insane(&script).unwrap();
+        assert_eq!(ms.ext.tree_height, 10000);
+        assert_eq!(ms.encode(), script);
     }
 
     #[test]
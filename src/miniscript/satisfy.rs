@@ -6,21 +6,25 @@
 //! scriptpubkeys.
 //!
 
-use core::{cmp, fmt, mem};
+use core::cell::RefCell;
+use core::{cmp, fmt, mem, ops};
 
-use bitcoin::hashes::hash160;
+use bitcoin::hashes::{hash160, ripemd160, sha256};
+use bitcoin::hex::DisplayHex;
 use bitcoin::key::XOnlyPublicKey;
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::sighash::TapSighashType;
 use bitcoin::taproot::{ControlBlock, LeafVersion, TapLeafHash, TapNodeHash};
-use bitcoin::{absolute, relative, ScriptBuf, Sequence};
-use sync::Arc;
+use bitcoin::{absolute, relative, transaction, ScriptBuf, Sequence, Transaction, TxOut};
 
 use super::context::SigType;
+use crate::iter::TreeLike;
 use crate::plan::AssetProvider;
 use crate::prelude::*;
 use crate::util::witness_size;
 use crate::{
-    AbsLockTime, Miniscript, MiniscriptKey, RelLockTime, ScriptContext, Terminal, Threshold,
-    ToPublicKey,
+    hash256, AbsLockTime, Extension, Miniscript, MiniscriptKey, RelLockTime, ScriptContext,
+    Terminal, ToPublicKey,
 };
 
 /// Type alias for 32 byte Preimage.
@@ -30,6 +34,26 @@ pub type Preimage32 = [u8; 32];
 /// Every method has a default implementation that simply returns `None`
 /// on every query. Users are expected to override the methods that they
 /// have data for.
+///
+/// # Canonical satisfactions
+///
+/// The satisfaction of a script only depends on the script and on which
+/// lookups succeed, so two satisfiers with the same data always produce the
+/// same witness. Among the satisfactions allowed by the data, the one with
+/// the smallest witness is used, preferring (for non-malleable satisfactions)
+/// the ones which do not leave a third party the choice of a cheaper witness.
+/// Remaining ties are broken as follows:
+///
+/// * disjunctions satisfy their left branch,
+/// * `thresh` satisfies its leftmost possible children,
+/// * `multi` uses the signatures of the first available keys, in script order,
+/// * `multi_a` uses the signatures of the last available keys, in script order.
+///
+/// Key priorities, see [`Satisfier::lookup_key_priority`], take precedence over
+/// these tie-breaking rules, and may even select a slightly larger witness.
+///
+/// [`crate::interpreter::canonicalize_witness`] rebuilds this canonical witness
+/// from any valid spend.
 pub trait Satisfier<Pk: MiniscriptKey + ToPublicKey> {
     /// Given a public key, look up an ECDSA signature with that key
     fn lookup_ecdsa_sig(&self, _: &Pk) -> Option<bitcoin::ecdsa::Signature> { None }
@@ -46,6 +70,11 @@ pub trait Satisfier<Pk: MiniscriptKey + ToPublicKey> {
         None
     }
 
+    /// Given a BIP 118 public key and a associated leaf hash, look up a
+    /// `SIGHASH_ANYPREVOUT` signature with that key
+    #[cfg(feature = "apo")]
+    fn lookup_apo_sig(&self, _: &Pk, _: &TapLeafHash) -> Option<crate::apo::ApoSignature> { None }
+
     /// Obtain a reference to the control block for a ver and script
     fn lookup_tap_control_block_map(
         &self,
@@ -95,6 +124,24 @@ pub trait Satisfier<Pk: MiniscriptKey + ToPublicKey> {
     /// Given a HASH160 hash, look up its preimage
     fn lookup_hash160(&self, _: &Pk::Hash160) -> Option<Preimage32> { None }
 
+    /// Whether the preimage of a SHA256 hash can be looked up
+    ///
+    /// The satisfaction is chosen using these checks, after which only the preimages
+    /// it uses are looked up. Satisfiers fetching preimages on demand, such as
+    /// [`LazyPreimageSatisfier`], override them to avoid fetching the other ones.
+    fn has_sha256_preimage(&self, h: &Pk::Sha256) -> bool { self.lookup_sha256(h).is_some() }
+
+    /// Whether the preimage of a HASH256 hash can be looked up
+    fn has_hash256_preimage(&self, h: &Pk::Hash256) -> bool { self.lookup_hash256(h).is_some() }
+
+    /// Whether the preimage of a RIPEMD160 hash can be looked up
+    fn has_ripemd160_preimage(&self, h: &Pk::Ripemd160) -> bool {
+        self.lookup_ripemd160(h).is_some()
+    }
+
+    /// Whether the preimage of a HASH160 hash can be looked up
+    fn has_hash160_preimage(&self, h: &Pk::Hash160) -> bool { self.lookup_hash160(h).is_some() }
+
     /// Assert whether an relative locktime is satisfied
     ///
     /// NOTE: If a descriptor mixes time-based and height-based timelocks, the implementation of
@@ -108,6 +155,25 @@ pub trait Satisfier<Pk: MiniscriptKey + ToPublicKey> {
     /// this method MUST only allow timelocks of either unit, but not both. Allowing both could cause
     /// miniscript to construct an invalid witness.
     fn check_after(&self, _: absolute::LockTime) -> bool { false }
+
+    /// Given a public key, look up how much its signature should be preferred
+    ///
+    /// When several sets of keys can satisfy a `thresh`, `multi` or `multi_a`, the
+    /// one with the highest sum of priorities is used among those whose witness is
+    /// at most [`Satisfier::preference_tolerance`] bytes larger than the smallest
+    /// one. Keys without a priority have a priority of 0, so negative priorities
+    /// can be used to avoid keys, e.g. cold storage ones.
+    fn lookup_key_priority(&self, _: &Pk) -> Option<i32> { None }
+
+    /// How many extra witness bytes the key priorities may cost
+    fn preference_tolerance(&self) -> usize { 0 }
+
+    /// The sighash type of the taproot signatures this satisfier provides, if they all use one
+    ///
+    /// Taproot signatures are 65 bytes long unless they use `SIGHASH_DEFAULT`. This is used to
+    /// size the signatures which are assumed rather than looked up, e.g. by
+    /// [`crate::Descriptor::missing_assets`].
+    fn lookup_tap_sighash_type(&self) -> Option<TapSighashType> { None }
 }
 
 // Allow use of `()` as a "no conditions available" satisfier
@@ -275,6 +341,11 @@ impl<Pk: MiniscriptKey + ToPublicKey, S: Satisfier<Pk>> Satisfier<Pk> for &S {
         (**self).lookup_tap_leaf_script_sig(p, h)
     }
 
+    #[cfg(feature = "apo")]
+    fn lookup_apo_sig(&self, p: &Pk, h: &TapLeafHash) -> Option<crate::apo::ApoSignature> {
+        (**self).lookup_apo_sig(p, h)
+    }
+
     fn lookup_raw_pkh_pk(&self, pkh: &hash160::Hash) -> Option<bitcoin::PublicKey> {
         (**self).lookup_raw_pkh_pk(pkh)
     }
@@ -317,9 +388,27 @@ impl<Pk: MiniscriptKey + ToPublicKey, S: Satisfier<Pk>> Satisfier<Pk> for &S {
 
     fn lookup_hash160(&self, h: &Pk::Hash160) -> Option<Preimage32> { (**self).lookup_hash160(h) }
 
+    fn has_sha256_preimage(&self, h: &Pk::Sha256) -> bool { (**self).has_sha256_preimage(h) }
+
+    fn has_hash256_preimage(&self, h: &Pk::Hash256) -> bool { (**self).has_hash256_preimage(h) }
+
+    fn has_ripemd160_preimage(&self, h: &Pk::Ripemd160) -> bool {
+        (**self).has_ripemd160_preimage(h)
+    }
+
+    fn has_hash160_preimage(&self, h: &Pk::Hash160) -> bool { (**self).has_hash160_preimage(h) }
+
     fn check_older(&self, t: relative::LockTime) -> bool { (**self).check_older(t) }
 
     fn check_after(&self, n: absolute::LockTime) -> bool { (**self).check_after(n) }
+
+    fn lookup_key_priority(&self, p: &Pk) -> Option<i32> { (**self).lookup_key_priority(p) }
+
+    fn preference_tolerance(&self) -> usize { (**self).preference_tolerance() }
+
+    fn lookup_tap_sighash_type(&self) -> Option<TapSighashType> {
+        (**self).lookup_tap_sighash_type()
+    }
 }
 
 impl<Pk: MiniscriptKey + ToPublicKey, S: Satisfier<Pk>> Satisfier<Pk> for &mut S {
@@ -335,6 +424,11 @@ impl<Pk: MiniscriptKey + ToPublicKey, S: Satisfier<Pk>> Satisfier<Pk> for &mut S
         (**self).lookup_tap_leaf_script_sig(p, h)
     }
 
+    #[cfg(feature = "apo")]
+    fn lookup_apo_sig(&self, p: &Pk, h: &TapLeafHash) -> Option<crate::apo::ApoSignature> {
+        (**self).lookup_apo_sig(p, h)
+    }
+
     fn lookup_tap_key_spend_sig(&self) -> Option<bitcoin::taproot::Signature> {
         (**self).lookup_tap_key_spend_sig()
     }
@@ -377,9 +471,27 @@ impl<Pk: MiniscriptKey + ToPublicKey, S: Satisfier<Pk>> Satisfier<Pk> for &mut S
 
     fn lookup_hash160(&self, h: &Pk::Hash160) -> Option<Preimage32> { (**self).lookup_hash160(h) }
 
+    fn has_sha256_preimage(&self, h: &Pk::Sha256) -> bool { (**self).has_sha256_preimage(h) }
+
+    fn has_hash256_preimage(&self, h: &Pk::Hash256) -> bool { (**self).has_hash256_preimage(h) }
+
+    fn has_ripemd160_preimage(&self, h: &Pk::Ripemd160) -> bool {
+        (**self).has_ripemd160_preimage(h)
+    }
+
+    fn has_hash160_preimage(&self, h: &Pk::Hash160) -> bool { (**self).has_hash160_preimage(h) }
+
     fn check_older(&self, t: relative::LockTime) -> bool { (**self).check_older(t) }
 
     fn check_after(&self, n: absolute::LockTime) -> bool { (**self).check_after(n) }
+
+    fn lookup_key_priority(&self, p: &Pk) -> Option<i32> { (**self).lookup_key_priority(p) }
+
+    fn preference_tolerance(&self) -> usize { (**self).preference_tolerance() }
+
+    fn lookup_tap_sighash_type(&self) -> Option<TapSighashType> {
+        (**self).lookup_tap_sighash_type()
+    }
 }
 
 macro_rules! impl_tuple_satisfier {
@@ -420,6 +532,17 @@ macro_rules! impl_tuple_satisfier {
                 None
             }
 
+            #[cfg(feature = "apo")]
+            fn lookup_apo_sig(&self, key: &Pk, h: &TapLeafHash) -> Option<crate::apo::ApoSignature> {
+                let &($(ref $ty,)*) = self;
+                $(
+                    if let Some(result) = $ty.lookup_apo_sig(key, h) {
+                        return Some(result);
+                    }
+                )*
+                None
+            }
+
             fn lookup_raw_pkh_ecdsa_sig(
                 &self,
                 key_hash: &hash160::Hash,
@@ -523,6 +646,46 @@ macro_rules! impl_tuple_satisfier {
                 None
             }
 
+            fn has_sha256_preimage(&self, h: &Pk::Sha256) -> bool {
+                let &($(ref $ty,)*) = self;
+                $(
+                    if $ty.has_sha256_preimage(h) {
+                        return true;
+                    }
+                )*
+                false
+            }
+
+            fn has_hash256_preimage(&self, h: &Pk::Hash256) -> bool {
+                let &($(ref $ty,)*) = self;
+                $(
+                    if $ty.has_hash256_preimage(h) {
+                        return true;
+                    }
+                )*
+                false
+            }
+
+            fn has_ripemd160_preimage(&self, h: &Pk::Ripemd160) -> bool {
+                let &($(ref $ty,)*) = self;
+                $(
+                    if $ty.has_ripemd160_preimage(h) {
+                        return true;
+                    }
+                )*
+                false
+            }
+
+            fn has_hash160_preimage(&self, h: &Pk::Hash160) -> bool {
+                let &($(ref $ty,)*) = self;
+                $(
+                    if $ty.has_hash160_preimage(h) {
+                        return true;
+                    }
+                )*
+                false
+            }
+
             fn check_older(&self, n: relative::LockTime) -> bool {
                 let &($(ref $ty,)*) = self;
                 $(
@@ -542,6 +705,35 @@ macro_rules! impl_tuple_satisfier {
                 )*
                 false
             }
+
+            fn lookup_key_priority(&self, key: &Pk) -> Option<i32> {
+                let &($(ref $ty,)*) = self;
+                $(
+                    if let Some(result) = $ty.lookup_key_priority(key) {
+                        return Some(result);
+                    }
+                )*
+                None
+            }
+
+            fn preference_tolerance(&self) -> usize {
+                let &($(ref $ty,)*) = self;
+                let mut tolerance = 0;
+                $(
+                    tolerance = cmp::max(tolerance, $ty.preference_tolerance());
+                )*
+                tolerance
+            }
+
+            fn lookup_tap_sighash_type(&self) -> Option<TapSighashType> {
+                let &($(ref $ty,)*) = self;
+                $(
+                    if let Some(result) = $ty.lookup_tap_sighash_type() {
+                        return Some(result);
+                    }
+                )*
+                None
+            }
         }
     }
 }
@@ -555,170 +747,856 @@ impl_tuple_satisfier!(A, B, C, D, E, F);
 impl_tuple_satisfier!(A, B, C, D, E, F, G);
 impl_tuple_satisfier!(A, B, C, D, E, F, G, H);
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-/// Type of schnorr signature to produce
-pub enum SchnorrSigType {
-    /// Key spend signature
-    KeySpend {
-        /// Merkle root to tweak the key, if present
-        merkle_root: Option<TapNodeHash>,
-    },
-    /// Script spend signature
-    ScriptSpend {
-        /// Leaf hash of the script
-        leaf_hash: TapLeafHash,
-    },
-}
-
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-/// Placeholder for some data in a [`Plan`]
+/// A satisfier merging several satisfiers, queried in order
 ///
-/// [`Plan`]: crate::plan::Plan
-pub enum Placeholder<Pk: MiniscriptKey> {
-    /// Public key and its size
-    Pubkey(Pk, usize),
-    /// Public key hash and public key size
-    PubkeyHash(hash160::Hash, usize),
-    /// ECDSA signature given the raw pubkey
-    EcdsaSigPk(Pk),
-    /// ECDSA signature given the pubkey hash
-    EcdsaSigPkHash(hash160::Hash),
-    /// Schnorr signature and its size
-    SchnorrSigPk(Pk, SchnorrSigType, usize),
-    /// Schnorr signature given the pubkey hash, the tapleafhash, and the sig size
-    SchnorrSigPkHash(hash160::Hash, TapLeafHash, usize),
-    /// SHA-256 preimage
-    Sha256Preimage(Pk::Sha256),
-    /// HASH256 preimage
-    Hash256Preimage(Pk::Hash256),
-    /// RIPEMD160 preimage
-    Ripemd160Preimage(Pk::Ripemd160),
-    /// HASH160 preimage
-    Hash160Preimage(Pk::Hash160),
-    /// Hash dissatisfaction (32 bytes of 0x00)
-    HashDissatisfaction,
-    /// OP_1
-    PushOne,
-    /// \<empty item\>
-    PushZero,
-    /// Taproot leaf script
-    TapScript(ScriptBuf),
-    /// Taproot control block
-    TapControlBlock(ControlBlock),
+/// Lookups return the result of the first satisfier which has one, and
+/// timelocks are satisfied if any satisfier says so. This is the same as
+/// using a tuple of satisfiers, but the satisfiers may be chosen at runtime,
+/// e.g. to combine local keys, the partial signatures of a PSBT and a store of
+/// hash preimages depending on what is available.
+pub struct SatisfierChain<'a, Pk: MiniscriptKey + ToPublicKey> {
+    satisfiers: Vec<Box<dyn Satisfier<Pk> + 'a>>,
 }
 
-impl<Pk: MiniscriptKey> fmt::Display for Placeholder<Pk> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        use Placeholder::*;
-        match self {
-            Pubkey(pk, size) => write!(f, "Pubkey(pk: {}, size: {})", pk, size),
-            PubkeyHash(hash, size) => write!(f, "PubkeyHash(hash: {}, size: {})", hash, size),
-            EcdsaSigPk(pk) => write!(f, "EcdsaSigPk(pk: {})", pk),
-            EcdsaSigPkHash(hash) => write!(f, "EcdsaSigPkHash(pkh: {})", hash),
-            SchnorrSigPk(pk, tap_leaf_hash, size) => write!(
-                f,
-                "SchnorrSig(pk: {}, tap_leaf_hash: {:?}, size: {})",
-                pk, tap_leaf_hash, size
-            ),
-            SchnorrSigPkHash(pkh, tap_leaf_hash, size) => write!(
-                f,
-                "SchnorrSigPkHash(pkh: {}, tap_leaf_hash: {:?}, size: {})",
-                pkh, tap_leaf_hash, size
-            ),
-            Sha256Preimage(hash) => write!(f, "Sha256Preimage(hash: {})", hash),
-            Hash256Preimage(hash) => write!(f, "Hash256Preimage(hash: {})", hash),
-            Ripemd160Preimage(hash) => write!(f, "Ripemd160Preimage(hash: {})", hash),
-            Hash160Preimage(hash) => write!(f, "Hash160Preimage(hash: {})", hash),
-            HashDissatisfaction => write!(f, "HashDissatisfaction"),
-            PushOne => write!(f, "PushOne"),
-            PushZero => write!(f, "PushZero"),
-            TapScript(script) => write!(f, "TapScript(script: {})", script),
-            TapControlBlock(control_block) => write!(
-                f,
-                "TapControlBlock(control_block: {})",
-                bitcoin::consensus::encode::serialize_hex(&control_block.serialize())
-            ),
-        }
+impl<'a, Pk: MiniscriptKey + ToPublicKey> SatisfierChain<'a, Pk> {
+    /// Creates an empty chain, which satisfies nothing
+    pub fn new() -> Self { SatisfierChain { satisfiers: vec![] } }
+
+    /// Appends a satisfier to the chain, to be queried after the ones already
+    /// in it
+    pub fn push<S: Satisfier<Pk> + 'a>(&mut self, satisfier: S) {
+        self.satisfiers.push(Box::new(satisfier));
     }
-}
 
-impl<Pk: MiniscriptKey + ToPublicKey> Placeholder<Pk> {
-    /// Replaces the placeholders with the information given by the satisfier
-    pub fn satisfy_self<Sat: Satisfier<Pk>>(&self, sat: &Sat) -> Option<Vec<u8>> {
-        match self {
-            Placeholder::Pubkey(pk, size) => {
-                if *size == 33 {
-                    Some(pk.to_x_only_pubkey().serialize().to_vec())
-                } else {
-                    Some(pk.to_public_key().to_bytes())
-                }
-            }
-            Placeholder::PubkeyHash(pkh, size) => sat
-                .lookup_raw_pkh_pk(pkh)
-                .map(|p| p.to_public_key())
-                .or(sat.lookup_raw_pkh_ecdsa_sig(pkh).map(|(p, _)| p))
-                .map(|pk| {
-                    let pk = pk.to_bytes();
-                    // We have to add a 1-byte OP_PUSH
-                    debug_assert!(1 + pk.len() == *size);
-                    pk
-                }),
-            Placeholder::Hash256Preimage(h) => sat.lookup_hash256(h).map(|p| p.to_vec()),
-            Placeholder::Sha256Preimage(h) => sat.lookup_sha256(h).map(|p| p.to_vec()),
-            Placeholder::Hash160Preimage(h) => sat.lookup_hash160(h).map(|p| p.to_vec()),
-            Placeholder::Ripemd160Preimage(h) => sat.lookup_ripemd160(h).map(|p| p.to_vec()),
-            Placeholder::EcdsaSigPk(pk) => sat.lookup_ecdsa_sig(pk).map(|s| s.to_vec()),
-            Placeholder::EcdsaSigPkHash(pkh) => {
-                sat.lookup_raw_pkh_ecdsa_sig(pkh).map(|(_, s)| s.to_vec())
-            }
-            Placeholder::SchnorrSigPk(pk, SchnorrSigType::ScriptSpend { leaf_hash }, size) => sat
-                .lookup_tap_leaf_script_sig(pk, leaf_hash)
-                .map(|s| s.to_vec())
-                .map(|s| {
-                    debug_assert!(s.len() == *size);
-                    s
-                }),
-            Placeholder::SchnorrSigPk(_, _, size) => {
-                sat.lookup_tap_key_spend_sig().map(|s| s.to_vec()).map(|s| {
-                    debug_assert!(s.len() == *size);
-                    s
-                })
-            }
-            Placeholder::SchnorrSigPkHash(pkh, tap_leaf_hash, size) => sat
-                .lookup_raw_pkh_tap_leaf_script_sig(&(*pkh, *tap_leaf_hash))
-                .map(|(_, s)| {
-                    let sig = s.to_vec();
-                    debug_assert!(sig.len() == *size);
-                    sig
-                }),
-            Placeholder::HashDissatisfaction => Some(vec![0; 32]),
-            Placeholder::PushZero => Some(vec![]),
-            Placeholder::PushOne => Some(vec![1]),
-            Placeholder::TapScript(s) => Some(s.to_bytes()),
-            Placeholder::TapControlBlock(cb) => Some(cb.serialize()),
-        }
+    /// Appends a satisfier to the chain, returning it
+    pub fn with<S: Satisfier<Pk> + 'a>(mut self, satisfier: S) -> Self {
+        self.push(satisfier);
+        self
     }
-}
 
-/// A witness, if available, for a Miniscript fragment
-#[derive(Clone, PartialEq, Eq, Debug, Hash)]
-pub enum Witness<T> {
-    /// Witness Available and the value of the witness
-    Stack(Vec<T>),
-    /// Third party can possibly satisfy the fragment but we cannot
-    /// Witness Unavailable
-    Unavailable,
-    /// No third party can produce a satisfaction without private key
-    /// Witness Impossible
-    Impossible,
+    /// The number of satisfiers in the chain
+    pub fn len(&self) -> usize { self.satisfiers.len() }
+
+    /// Whether the chain has no satisfier
+    pub fn is_empty(&self) -> bool { self.satisfiers.is_empty() }
 }
 
-impl<Pk: MiniscriptKey> PartialOrd for Witness<Placeholder<Pk>> {
-    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> { Some(self.cmp(other)) }
+impl<'a, Pk: MiniscriptKey + ToPublicKey> Default for SatisfierChain<'a, Pk> {
+    fn default() -> Self { SatisfierChain::new() }
 }
 
-impl<Pk: MiniscriptKey> Ord for Witness<Placeholder<Pk>> {
-    fn cmp(&self, other: &Self) -> cmp::Ordering {
+impl<'a, Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for SatisfierChain<'a, Pk> {
+    fn lookup_ecdsa_sig(&self, key: &Pk) -> Option<bitcoin::ecdsa::Signature> {
+        self.satisfiers.iter().find_map(|s| s.lookup_ecdsa_sig(key))
+    }
+
+    fn lookup_tap_key_spend_sig(&self) -> Option<bitcoin::taproot::Signature> {
+        self.satisfiers.iter().find_map(|s| s.lookup_tap_key_spend_sig())
+    }
+
+    fn lookup_tap_leaf_script_sig(
+        &self,
+        key: &Pk,
+        h: &TapLeafHash,
+    ) -> Option<bitcoin::taproot::Signature> {
+        self.satisfiers
+            .iter()
+            .find_map(|s| s.lookup_tap_leaf_script_sig(key, h))
+    }
+
+    #[cfg(feature = "apo")]
+    fn lookup_apo_sig(&self, key: &Pk, h: &TapLeafHash) -> Option<crate::apo::ApoSignature> {
+        self.satisfiers.iter().find_map(|s| s.lookup_apo_sig(key, h))
+    }
+
+    fn lookup_tap_control_block_map(
+        &self,
+    ) -> Option<&BTreeMap<ControlBlock, (bitcoin::ScriptBuf, LeafVersion)>> {
+        self.satisfiers
+            .iter()
+            .find_map(|s| s.lookup_tap_control_block_map())
+    }
+
+    fn lookup_raw_pkh_pk(&self, key_hash: &hash160::Hash) -> Option<bitcoin::PublicKey> {
+        self.satisfiers.iter().find_map(|s| s.lookup_raw_pkh_pk(key_hash))
+    }
+
+    fn lookup_raw_pkh_x_only_pk(&self, key_hash: &hash160::Hash) -> Option<XOnlyPublicKey> {
+        self.satisfiers
+            .iter()
+            .find_map(|s| s.lookup_raw_pkh_x_only_pk(key_hash))
+    }
+
+    fn lookup_raw_pkh_ecdsa_sig(
+        &self,
+        key_hash: &hash160::Hash,
+    ) -> Option<(bitcoin::PublicKey, bitcoin::ecdsa::Signature)> {
+        self.satisfiers
+            .iter()
+            .find_map(|s| s.lookup_raw_pkh_ecdsa_sig(key_hash))
+    }
+
+    fn lookup_raw_pkh_tap_leaf_script_sig(
+        &self,
+        key_hash: &(hash160::Hash, TapLeafHash),
+    ) -> Option<(XOnlyPublicKey, bitcoin::taproot::Signature)> {
+        self.satisfiers
+            .iter()
+            .find_map(|s| s.lookup_raw_pkh_tap_leaf_script_sig(key_hash))
+    }
+
+    fn lookup_sha256(&self, h: &Pk::Sha256) -> Option<Preimage32> {
+        self.satisfiers.iter().find_map(|s| s.lookup_sha256(h))
+    }
+
+    fn lookup_hash256(&self, h: &Pk::Hash256) -> Option<Preimage32> {
+        self.satisfiers.iter().find_map(|s| s.lookup_hash256(h))
+    }
+
+    fn lookup_ripemd160(&self, h: &Pk::Ripemd160) -> Option<Preimage32> {
+        self.satisfiers.iter().find_map(|s| s.lookup_ripemd160(h))
+    }
+
+    fn lookup_hash160(&self, h: &Pk::Hash160) -> Option<Preimage32> {
+        self.satisfiers.iter().find_map(|s| s.lookup_hash160(h))
+    }
+
+    fn has_sha256_preimage(&self, h: &Pk::Sha256) -> bool {
+        self.satisfiers.iter().any(|s| s.has_sha256_preimage(h))
+    }
+
+    fn has_hash256_preimage(&self, h: &Pk::Hash256) -> bool {
+        self.satisfiers.iter().any(|s| s.has_hash256_preimage(h))
+    }
+
+    fn has_ripemd160_preimage(&self, h: &Pk::Ripemd160) -> bool {
+        self.satisfiers.iter().any(|s| s.has_ripemd160_preimage(h))
+    }
+
+    fn has_hash160_preimage(&self, h: &Pk::Hash160) -> bool {
+        self.satisfiers.iter().any(|s| s.has_hash160_preimage(h))
+    }
+
+    fn check_older(&self, n: relative::LockTime) -> bool {
+        self.satisfiers.iter().any(|s| s.check_older(n))
+    }
+
+    fn check_after(&self, n: absolute::LockTime) -> bool {
+        self.satisfiers.iter().any(|s| s.check_after(n))
+    }
+
+    fn lookup_key_priority(&self, key: &Pk) -> Option<i32> {
+        self.satisfiers.iter().find_map(|s| s.lookup_key_priority(key))
+    }
+
+    fn preference_tolerance(&self) -> usize {
+        self.satisfiers
+            .iter()
+            .map(|s| s.preference_tolerance())
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn lookup_tap_sighash_type(&self) -> Option<TapSighashType> {
+        self.satisfiers.iter().find_map(|s| s.lookup_tap_sighash_type())
+    }
+}
+
+/// A satisfier answering timelock queries from the transaction spending an input
+///
+/// Signatures and hash preimages are looked up in the inner satisfier, while
+/// `after` and `older` are satisfied according to the locktime of the transaction
+/// and the sequence of the input. As for consensus, `after` requires the input to
+/// enable the locktime, and `older` requires a version 2 transaction and a
+/// sequence enabling relative locktimes.
+///
+/// All lookups panic if the input index is out of bounds.
+pub struct TxSatisfier<'tx, S> {
+    /// The satisfier for signatures and hash preimages
+    pub inner: S,
+    /// The spending transaction
+    pub tx: &'tx Transaction,
+    /// The index of the input to satisfy
+    pub index: usize,
+    /// The outputs spent by the inputs of the transaction, in order
+    pub prevouts: &'tx [TxOut],
+}
+
+impl<'tx, S> TxSatisfier<'tx, S> {
+    /// Create a satisfier for input `index` of `tx`, which spends `prevouts`
+    pub fn new(inner: S, tx: &'tx Transaction, index: usize, prevouts: &'tx [TxOut]) -> Self {
+        TxSatisfier { inner, tx, index, prevouts }
+    }
+
+    /// The output spent by the input to satisfy, if the prevouts include it
+    pub fn spent_output(&self) -> Option<&'tx TxOut> { self.prevouts.get(self.index) }
+}
+
+/// Whether input `index` of `tx` satisfies `after(n)`
+pub(crate) fn tx_check_after(tx: &Transaction, index: usize, n: absolute::LockTime) -> bool {
+    if !tx.input[index].enables_lock_time() {
+        return false;
+    }
+    <dyn Satisfier<bitcoin::PublicKey>>::check_after(&tx.lock_time, n)
+}
+
+/// Whether input `index` of `tx` satisfies `older(n)`
+pub(crate) fn tx_check_older(tx: &Transaction, index: usize, n: relative::LockTime) -> bool {
+    let seq = tx.input[index].sequence;
+    if tx.version < transaction::Version::TWO || !seq.is_relative_lock_time() {
+        return false;
+    }
+    <dyn Satisfier<bitcoin::PublicKey>>::check_older(&seq, n)
+}
+
+impl<Pk: MiniscriptKey + ToPublicKey, S: Satisfier<Pk>> Satisfier<Pk> for TxSatisfier<'_, S> {
+    fn lookup_ecdsa_sig(&self, p: &Pk) -> Option<bitcoin::ecdsa::Signature> {
+        self.inner.lookup_ecdsa_sig(p)
+    }
+
+    fn lookup_tap_leaf_script_sig(
+        &self,
+        p: &Pk,
+        h: &TapLeafHash,
+    ) -> Option<bitcoin::taproot::Signature> {
+        self.inner.lookup_tap_leaf_script_sig(p, h)
+    }
+
+    #[cfg(feature = "apo")]
+    fn lookup_apo_sig(&self, p: &Pk, h: &TapLeafHash) -> Option<crate::apo::ApoSignature> {
+        self.inner.lookup_apo_sig(p, h)
+    }
+
+    fn lookup_raw_pkh_pk(&self, pkh: &hash160::Hash) -> Option<bitcoin::PublicKey> {
+        self.inner.lookup_raw_pkh_pk(pkh)
+    }
+
+    fn lookup_raw_pkh_x_only_pk(&self, pkh: &hash160::Hash) -> Option<XOnlyPublicKey> {
+        self.inner.lookup_raw_pkh_x_only_pk(pkh)
+    }
+
+    fn lookup_raw_pkh_ecdsa_sig(
+        &self,
+        pkh: &hash160::Hash,
+    ) -> Option<(bitcoin::PublicKey, bitcoin::ecdsa::Signature)> {
+        self.inner.lookup_raw_pkh_ecdsa_sig(pkh)
+    }
+
+    fn lookup_tap_key_spend_sig(&self) -> Option<bitcoin::taproot::Signature> {
+        self.inner.lookup_tap_key_spend_sig()
+    }
+
+    fn lookup_raw_pkh_tap_leaf_script_sig(
+        &self,
+        pkh: &(hash160::Hash, TapLeafHash),
+    ) -> Option<(XOnlyPublicKey, bitcoin::taproot::Signature)> {
+        self.inner.lookup_raw_pkh_tap_leaf_script_sig(pkh)
+    }
+
+    fn lookup_tap_control_block_map(
+        &self,
+    ) -> Option<&BTreeMap<ControlBlock, (bitcoin::ScriptBuf, LeafVersion)>> {
+        self.inner.lookup_tap_control_block_map()
+    }
+
+    fn lookup_sha256(&self, h: &Pk::Sha256) -> Option<Preimage32> { self.inner.lookup_sha256(h) }
+
+    fn lookup_hash256(&self, h: &Pk::Hash256) -> Option<Preimage32> {
+        self.inner.lookup_hash256(h)
+    }
+
+    fn lookup_ripemd160(&self, h: &Pk::Ripemd160) -> Option<Preimage32> {
+        self.inner.lookup_ripemd160(h)
+    }
+
+    fn lookup_hash160(&self, h: &Pk::Hash160) -> Option<Preimage32> {
+        self.inner.lookup_hash160(h)
+    }
+
+    fn has_sha256_preimage(&self, h: &Pk::Sha256) -> bool { self.inner.has_sha256_preimage(h) }
+
+    fn has_hash256_preimage(&self, h: &Pk::Hash256) -> bool { self.inner.has_hash256_preimage(h) }
+
+    fn has_ripemd160_preimage(&self, h: &Pk::Ripemd160) -> bool {
+        self.inner.has_ripemd160_preimage(h)
+    }
+
+    fn has_hash160_preimage(&self, h: &Pk::Hash160) -> bool { self.inner.has_hash160_preimage(h) }
+
+    fn check_older(&self, t: relative::LockTime) -> bool { tx_check_older(self.tx, self.index, t) }
+
+    fn check_after(&self, n: absolute::LockTime) -> bool { tx_check_after(self.tx, self.index, n) }
+
+    fn lookup_key_priority(&self, p: &Pk) -> Option<i32> { self.inner.lookup_key_priority(p) }
+
+    fn preference_tolerance(&self) -> usize { self.inner.preference_tolerance() }
+
+    fn lookup_tap_sighash_type(&self) -> Option<TapSighashType> {
+        self.inner.lookup_tap_sighash_type()
+    }
+}
+
+/// A hash whose preimage is fetched by a [`PreimageFetcher`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PreimageHash {
+    /// A SHA256 hash
+    Sha256(sha256::Hash),
+    /// A HASH256 hash
+    Hash256(hash256::Hash),
+    /// A RIPEMD160 hash
+    Ripemd160(ripemd160::Hash),
+    /// A HASH160 hash
+    Hash160(hash160::Hash),
+}
+
+/// Fallible callbacks fetching hash preimages on demand, e.g. from a Lightning node
+pub trait PreimageFetcher {
+    /// Error returned when a preimage cannot be fetched
+    type Error;
+
+    /// Whether the preimage of `hash` can be fetched, without fetching it
+    fn can_fetch(&self, hash: &PreimageHash) -> bool;
+
+    /// Fetches the preimage of `hash`
+    fn fetch(&self, hash: &PreimageHash) -> Result<Preimage32, Self::Error>;
+}
+
+/// A satisfier fetching hash preimages only once they are known to be needed
+///
+/// The satisfaction is chosen among those using the preimages which
+/// [`PreimageFetcher::can_fetch`], then only the preimages it uses are fetched. If
+/// one of them cannot be fetched after all, the satisfaction fails and the error
+/// can be retrieved with [`LazyPreimageSatisfier::take_errors`]. Fetched preimages
+/// are cached. Signatures can be provided by another satisfier, using a tuple.
+pub struct LazyPreimageSatisfier<F: PreimageFetcher> {
+    fetcher: F,
+    fetched: RefCell<BTreeMap<PreimageHash, Preimage32>>,
+    errors: RefCell<Vec<(PreimageHash, F::Error)>>,
+}
+
+impl<F: PreimageFetcher> LazyPreimageSatisfier<F> {
+    /// Creates a satisfier fetching the preimages with `fetcher`
+    pub fn new(fetcher: F) -> Self {
+        LazyPreimageSatisfier {
+            fetcher,
+            fetched: RefCell::new(BTreeMap::new()),
+            errors: RefCell::new(vec![]),
+        }
+    }
+
+    /// Returns the errors of the failed fetches, and forgets them
+    pub fn take_errors(&self) -> Vec<(PreimageHash, F::Error)> {
+        mem::take(&mut *self.errors.borrow_mut())
+    }
+
+    fn lookup(&self, hash: PreimageHash) -> Option<Preimage32> {
+        if let Some(preimage) = self.fetched.borrow().get(&hash) {
+            return Some(*preimage);
+        }
+        match self.fetcher.fetch(&hash) {
+            Ok(preimage) => {
+                self.fetched.borrow_mut().insert(hash, preimage);
+                Some(preimage)
+            }
+            Err(e) => {
+                self.errors.borrow_mut().push((hash, e));
+                None
+            }
+        }
+    }
+}
+
+impl<Pk: MiniscriptKey + ToPublicKey, F: PreimageFetcher> Satisfier<Pk>
+    for LazyPreimageSatisfier<F>
+{
+    fn lookup_sha256(&self, h: &Pk::Sha256) -> Option<Preimage32> {
+        self.lookup(PreimageHash::Sha256(Pk::to_sha256(h)))
+    }
+
+    fn lookup_hash256(&self, h: &Pk::Hash256) -> Option<Preimage32> {
+        self.lookup(PreimageHash::Hash256(Pk::to_hash256(h)))
+    }
+
+    fn lookup_ripemd160(&self, h: &Pk::Ripemd160) -> Option<Preimage32> {
+        self.lookup(PreimageHash::Ripemd160(Pk::to_ripemd160(h)))
+    }
+
+    fn lookup_hash160(&self, h: &Pk::Hash160) -> Option<Preimage32> {
+        self.lookup(PreimageHash::Hash160(Pk::to_hash160(h)))
+    }
+
+    fn has_sha256_preimage(&self, h: &Pk::Sha256) -> bool {
+        self.fetcher.can_fetch(&PreimageHash::Sha256(Pk::to_sha256(h)))
+    }
+
+    fn has_hash256_preimage(&self, h: &Pk::Hash256) -> bool {
+        self.fetcher.can_fetch(&PreimageHash::Hash256(Pk::to_hash256(h)))
+    }
+
+    fn has_ripemd160_preimage(&self, h: &Pk::Ripemd160) -> bool {
+        self.fetcher.can_fetch(&PreimageHash::Ripemd160(Pk::to_ripemd160(h)))
+    }
+
+    fn has_hash160_preimage(&self, h: &Pk::Hash160) -> bool {
+        self.fetcher.can_fetch(&PreimageHash::Hash160(Pk::to_hash160(h)))
+    }
+}
+
+/// A signature stored in a [`MapSatisfier`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MapSignature {
+    /// An ECDSA signature
+    Ecdsa(bitcoin::ecdsa::Signature),
+    /// A Schnorr signature
+    Schnorr(bitcoin::taproot::Signature),
+}
+
+impl From<bitcoin::ecdsa::Signature> for MapSignature {
+    fn from(sig: bitcoin::ecdsa::Signature) -> Self { MapSignature::Ecdsa(sig) }
+}
+
+impl From<bitcoin::taproot::Signature> for MapSignature {
+    fn from(sig: bitcoin::taproot::Signature) -> Self { MapSignature::Schnorr(sig) }
+}
+
+/// A satisfier backed by signatures and hash preimages gathered beforehand
+///
+/// Signatures are keyed by their public key and, for Taproot script spends,
+/// the leaf hash of the script. ECDSA signatures and Taproot key spend
+/// signatures have no leaf hash. Public key hashes are resolved from the keys
+/// of the signatures. Timelocks are not satisfied, but the satisfier can be
+/// paired with a lock time in a tuple.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapSatisfier<Pk: MiniscriptKey> {
+    /// Signatures, by public key and leaf hash
+    pub signatures: BTreeMap<(Pk, Option<TapLeafHash>), MapSignature>,
+    /// SHA-256 preimages, by hash
+    pub sha256_preimages: BTreeMap<Pk::Sha256, Preimage32>,
+    /// HASH256 preimages, by hash
+    pub hash256_preimages: BTreeMap<Pk::Hash256, Preimage32>,
+    /// RIPEMD160 preimages, by hash
+    pub ripemd160_preimages: BTreeMap<Pk::Ripemd160, Preimage32>,
+    /// HASH160 preimages, by hash
+    pub hash160_preimages: BTreeMap<Pk::Hash160, Preimage32>,
+}
+
+impl<Pk: MiniscriptKey> MapSatisfier<Pk> {
+    /// Creates a satisfier without any signature or preimage
+    pub fn new() -> Self {
+        MapSatisfier {
+            signatures: BTreeMap::new(),
+            sha256_preimages: BTreeMap::new(),
+            hash256_preimages: BTreeMap::new(),
+            ripemd160_preimages: BTreeMap::new(),
+            hash160_preimages: BTreeMap::new(),
+        }
+    }
+
+    /// Adds an ECDSA signature by `pk`
+    pub fn add_ecdsa_sig(&mut self, pk: Pk, sig: bitcoin::ecdsa::Signature) {
+        self.signatures.insert((pk, None), sig.into());
+    }
+
+    /// Adds a Taproot key spend signature by `pk`
+    pub fn add_tap_key_spend_sig(&mut self, pk: Pk, sig: bitcoin::taproot::Signature) {
+        self.signatures.insert((pk, None), sig.into());
+    }
+
+    /// Adds a Taproot script spend signature by `pk` for the leaf `leaf_hash`
+    pub fn add_tap_leaf_script_sig(
+        &mut self,
+        pk: Pk,
+        leaf_hash: TapLeafHash,
+        sig: bitcoin::taproot::Signature,
+    ) {
+        self.signatures.insert((pk, Some(leaf_hash)), sig.into());
+    }
+
+    fn ecdsa_sigs(&self) -> impl Iterator<Item = (&Pk, bitcoin::ecdsa::Signature)> {
+        self.signatures.iter().filter_map(|((pk, _), sig)| match sig {
+            MapSignature::Ecdsa(sig) => Some((pk, *sig)),
+            MapSignature::Schnorr(_) => None,
+        })
+    }
+
+    fn schnorr_sigs(
+        &self,
+    ) -> impl Iterator<Item = (&Pk, Option<TapLeafHash>, bitcoin::taproot::Signature)> {
+        self.signatures
+            .iter()
+            .filter_map(|((pk, leaf_hash), sig)| match sig {
+                MapSignature::Ecdsa(_) => None,
+                MapSignature::Schnorr(sig) => Some((pk, *leaf_hash, *sig)),
+            })
+    }
+}
+
+impl<Pk: MiniscriptKey> Default for MapSatisfier<Pk> {
+    fn default() -> Self { MapSatisfier::new() }
+}
+
+impl<Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for MapSatisfier<Pk> {
+    fn lookup_ecdsa_sig(&self, key: &Pk) -> Option<bitcoin::ecdsa::Signature> {
+        match self.signatures.get(&(key.clone(), None)) {
+            Some(MapSignature::Ecdsa(sig)) => Some(*sig),
+            _ => None,
+        }
+    }
+
+    fn lookup_tap_key_spend_sig(&self) -> Option<bitcoin::taproot::Signature> {
+        self.schnorr_sigs()
+            .find(|(_, leaf_hash, _)| leaf_hash.is_none())
+            .map(|(_, _, sig)| sig)
+    }
+
+    fn lookup_tap_leaf_script_sig(
+        &self,
+        key: &Pk,
+        h: &TapLeafHash,
+    ) -> Option<bitcoin::taproot::Signature> {
+        match self.signatures.get(&(key.clone(), Some(*h))) {
+            Some(MapSignature::Schnorr(sig)) => Some(*sig),
+            _ => None,
+        }
+    }
+
+    fn lookup_raw_pkh_pk(&self, pk_hash: &hash160::Hash) -> Option<bitcoin::PublicKey> {
+        self.lookup_raw_pkh_ecdsa_sig(pk_hash).map(|(pk, _)| pk)
+    }
+
+    fn lookup_raw_pkh_x_only_pk(&self, pk_hash: &hash160::Hash) -> Option<XOnlyPublicKey> {
+        self.schnorr_sigs()
+            .find(|(pk, _, _)| pk.to_pubkeyhash(SigType::Schnorr) == *pk_hash)
+            .map(|(pk, _, _)| pk.to_x_only_pubkey())
+    }
+
+    fn lookup_raw_pkh_ecdsa_sig(
+        &self,
+        pk_hash: &hash160::Hash,
+    ) -> Option<(bitcoin::PublicKey, bitcoin::ecdsa::Signature)> {
+        self.ecdsa_sigs()
+            .find(|(pk, _)| pk.to_pubkeyhash(SigType::Ecdsa) == *pk_hash)
+            .map(|(pk, sig)| (pk.to_public_key(), sig))
+    }
+
+    fn lookup_raw_pkh_tap_leaf_script_sig(
+        &self,
+        pk_hash: &(hash160::Hash, TapLeafHash),
+    ) -> Option<(XOnlyPublicKey, bitcoin::taproot::Signature)> {
+        self.schnorr_sigs()
+            .find(|(pk, leaf_hash, _)| {
+                *leaf_hash == Some(pk_hash.1) && pk.to_pubkeyhash(SigType::Schnorr) == pk_hash.0
+            })
+            .map(|(pk, _, sig)| (pk.to_x_only_pubkey(), sig))
+    }
+
+    fn lookup_sha256(&self, h: &Pk::Sha256) -> Option<Preimage32> {
+        self.sha256_preimages.get(h).copied()
+    }
+
+    fn lookup_hash256(&self, h: &Pk::Hash256) -> Option<Preimage32> {
+        self.hash256_preimages.get(h).copied()
+    }
+
+    fn lookup_ripemd160(&self, h: &Pk::Ripemd160) -> Option<Preimage32> {
+        self.ripemd160_preimages.get(h).copied()
+    }
+
+    fn lookup_hash160(&self, h: &Pk::Hash160) -> Option<Preimage32> {
+        self.hash160_preimages.get(h).copied()
+    }
+}
+
+/// A satisfier which only provides key priorities
+///
+/// This is meant to be paired with another satisfier in a tuple or a
+/// [`SatisfierChain`], e.g. to prefer the signatures of online keys over the
+/// one of a cold key when several of them are available.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyPreferences<Pk: MiniscriptKey> {
+    /// Priorities, by public key
+    pub priorities: BTreeMap<Pk, i32>,
+    /// How many extra witness bytes the priorities may cost
+    pub tolerance: usize,
+}
+
+impl<Pk: MiniscriptKey> KeyPreferences<Pk> {
+    /// Creates preferences without any priority
+    pub fn new() -> Self { KeyPreferences { priorities: BTreeMap::new(), tolerance: 0 } }
+
+    /// Sets the priority of `pk`, higher priorities being preferred
+    pub fn prefer(mut self, pk: Pk, priority: i32) -> Self {
+        self.priorities.insert(pk, priority);
+        self
+    }
+
+    /// Sets how many extra witness bytes the priorities may cost
+    pub fn tolerance(mut self, tolerance: usize) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+}
+
+impl<Pk: MiniscriptKey> Default for KeyPreferences<Pk> {
+    fn default() -> Self { KeyPreferences::new() }
+}
+
+impl<Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for KeyPreferences<Pk> {
+    fn lookup_key_priority(&self, key: &Pk) -> Option<i32> { self.priorities.get(key).copied() }
+
+    fn preference_tolerance(&self) -> usize { self.tolerance }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Type of schnorr signature to produce
+pub enum SchnorrSigType {
+    /// Key spend signature
+    KeySpend {
+        /// Merkle root to tweak the key, if present
+        merkle_root: Option<TapNodeHash>,
+    },
+    /// Script spend signature
+    ScriptSpend {
+        /// Leaf hash of the script
+        leaf_hash: TapLeafHash,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Placeholder for some data in a [`Plan`]
+///
+/// [`Plan`]: crate::plan::Plan
+pub enum Placeholder<Pk: MiniscriptKey> {
+    /// Public key and its size
+    Pubkey(Pk, usize),
+    /// Public key hash and public key size
+    PubkeyHash(hash160::Hash, usize),
+    /// ECDSA signature given the raw pubkey
+    EcdsaSigPk(Pk),
+    /// ECDSA signature given the pubkey hash
+    EcdsaSigPkHash(hash160::Hash),
+    /// Schnorr signature and its size
+    SchnorrSigPk(Pk, SchnorrSigType, usize),
+    /// Schnorr signature given the pubkey hash, the tapleafhash, and the sig size
+    SchnorrSigPkHash(hash160::Hash, TapLeafHash, usize),
+    /// ECDSA signature to be adapted from an adaptor signature, given the raw pubkey and the
+    /// adaptor point
+    EcdsaAdaptorSigPk(Pk, PublicKey),
+    /// Schnorr signature to be adapted from an adaptor signature, given the raw pubkey, the
+    /// tapleafhash, the adaptor point, and the adapted sig size
+    SchnorrAdaptorSigPk(Pk, TapLeafHash, PublicKey, usize),
+    /// SHA-256 preimage
+    Sha256Preimage(Pk::Sha256),
+    /// HASH256 preimage
+    Hash256Preimage(Pk::Hash256),
+    /// RIPEMD160 preimage
+    Ripemd160Preimage(Pk::Ripemd160),
+    /// HASH160 preimage
+    Hash160Preimage(Pk::Hash160),
+    /// Hash dissatisfaction (32 bytes of 0x00)
+    HashDissatisfaction,
+    /// OP_1
+    PushOne,
+    /// \<empty item\>
+    PushZero,
+    /// Data fixed by the script, as used by extension fragments
+    Data(Vec<u8>),
+    /// Taproot leaf script
+    TapScript(ScriptBuf),
+    /// Taproot control block
+    TapControlBlock(ControlBlock),
+}
+
+impl<Pk: MiniscriptKey> fmt::Display for Placeholder<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Placeholder::*;
+        match self {
+            Pubkey(pk, size) => write!(f, "Pubkey(pk: {}, size: {})", pk, size),
+            PubkeyHash(hash, size) => write!(f, "PubkeyHash(hash: {}, size: {})", hash, size),
+            EcdsaSigPk(pk) => write!(f, "EcdsaSigPk(pk: {})", pk),
+            EcdsaSigPkHash(hash) => write!(f, "EcdsaSigPkHash(pkh: {})", hash),
+            SchnorrSigPk(pk, tap_leaf_hash, size) => write!(
+                f,
+                "SchnorrSig(pk: {}, tap_leaf_hash: {:?}, size: {})",
+                pk, tap_leaf_hash, size
+            ),
+            SchnorrSigPkHash(pkh, tap_leaf_hash, size) => write!(
+                f,
+                "SchnorrSigPkHash(pkh: {}, tap_leaf_hash: {:?}, size: {})",
+                pkh, tap_leaf_hash, size
+            ),
+            EcdsaAdaptorSigPk(pk, adaptor) => {
+                write!(f, "EcdsaAdaptorSigPk(pk: {}, adaptor: {})", pk, adaptor)
+            }
+            SchnorrAdaptorSigPk(pk, tap_leaf_hash, adaptor, size) => write!(
+                f,
+                "SchnorrAdaptorSigPk(pk: {}, tap_leaf_hash: {}, adaptor: {}, size: {})",
+                pk, tap_leaf_hash, adaptor, size
+            ),
+            Sha256Preimage(hash) => write!(f, "Sha256Preimage(hash: {})", hash),
+            Hash256Preimage(hash) => write!(f, "Hash256Preimage(hash: {})", hash),
+            Ripemd160Preimage(hash) => write!(f, "Ripemd160Preimage(hash: {})", hash),
+            Hash160Preimage(hash) => write!(f, "Hash160Preimage(hash: {})", hash),
+            HashDissatisfaction => write!(f, "HashDissatisfaction"),
+            PushOne => write!(f, "PushOne"),
+            Data(data) => write!(f, "Data({})", data.as_hex()),
+            PushZero => write!(f, "PushZero"),
+            TapScript(script) => write!(f, "TapScript(script: {})", script),
+            TapControlBlock(control_block) => write!(
+                f,
+                "TapControlBlock(control_block: {})",
+                bitcoin::consensus::encode::serialize_hex(&control_block.serialize())
+            ),
+        }
+    }
+}
+
+impl<Pk: MiniscriptKey> Placeholder<Pk> {
+    /// Whether the placeholder is a signature checked by a Tapscript, which
+    /// counts against the sigops budget of its input.
+    pub(crate) fn is_script_spend_sig(&self) -> bool {
+        matches!(
+            self,
+            Placeholder::SchnorrSigPk(_, SchnorrSigType::ScriptSpend { .. }, _)
+                | Placeholder::SchnorrSigPkHash(..)
+                | Placeholder::SchnorrAdaptorSigPk(..)
+        )
+    }
+}
+
+impl<Pk: MiniscriptKey + ToPublicKey> Placeholder<Pk> {
+    /// Replaces the placeholder with bytes of the size of the item it stands for
+    ///
+    /// Signatures and hash preimages are replaced by zero bytes, ECDSA signatures
+    /// being assumed to have their maximum size, as are public keys only known by
+    /// their hash. The other items, e.g. public keys and Taproot leaf scripts, are
+    /// the actual ones.
+    pub fn dummy_bytes(&self) -> Vec<u8> {
+        match self {
+            Placeholder::PubkeyHash(_, size) => vec![0; size - 1],
+            Placeholder::EcdsaSigPk(_)
+            | Placeholder::EcdsaSigPkHash(_)
+            | Placeholder::EcdsaAdaptorSigPk(..) => vec![0; 72],
+            Placeholder::SchnorrSigPk(_, _, size)
+            | Placeholder::SchnorrSigPkHash(_, _, size)
+            | Placeholder::SchnorrAdaptorSigPk(_, _, _, size) => vec![0; *size],
+            Placeholder::Sha256Preimage(_)
+            | Placeholder::Hash256Preimage(_)
+            | Placeholder::Ripemd160Preimage(_)
+            | Placeholder::Hash160Preimage(_) => vec![0; 32],
+            _ => self
+                .satisfy_self(&())
+                .expect("the other placeholders do not need a satisfier"),
+        }
+    }
+
+    /// Replaces the placeholders with the information given by the satisfier
+    pub fn satisfy_self<Sat: Satisfier<Pk>>(&self, sat: &Sat) -> Option<Vec<u8>> {
+        match self {
+            Placeholder::Pubkey(pk, size) => {
+                if *size == 33 {
+                    Some(pk.to_x_only_pubkey().serialize().to_vec())
+                } else {
+                    Some(pk.to_public_key().to_bytes())
+                }
+            }
+            Placeholder::PubkeyHash(pkh, 33) => sat
+                .lookup_raw_pkh_x_only_pk(pkh)
+                .map(|pk| pk.serialize().to_vec()),
+            Placeholder::PubkeyHash(pkh, size) => sat
+                .lookup_raw_pkh_pk(pkh)
+                .map(|p| p.to_public_key())
+                .or(sat.lookup_raw_pkh_ecdsa_sig(pkh).map(|(p, _)| p))
+                .map(|pk| {
+                    let pk = pk.to_bytes();
+                    // We have to add a 1-byte OP_PUSH
+                    debug_assert!(1 + pk.len() == *size);
+                    pk
+                }),
+            Placeholder::Hash256Preimage(h) => sat.lookup_hash256(h).map(|p| p.to_vec()),
+            Placeholder::Sha256Preimage(h) => sat.lookup_sha256(h).map(|p| p.to_vec()),
+            Placeholder::Hash160Preimage(h) => sat.lookup_hash160(h).map(|p| p.to_vec()),
+            Placeholder::Ripemd160Preimage(h) => sat.lookup_ripemd160(h).map(|p| p.to_vec()),
+            Placeholder::EcdsaSigPk(pk) => sat.lookup_ecdsa_sig(pk).map(|s| s.to_vec()),
+            Placeholder::EcdsaSigPkHash(pkh) => {
+                sat.lookup_raw_pkh_ecdsa_sig(pkh).map(|(_, s)| s.to_vec())
+            }
+            #[cfg(feature = "apo")]
+            Placeholder::SchnorrSigPk(pk, SchnorrSigType::ScriptSpend { leaf_hash }, size)
+                if pk.apo_kind().is_some() =>
+            {
+                sat.lookup_apo_sig(pk, leaf_hash)
+                    .map(|s| s.to_vec())
+                    .or_else(|| {
+                        sat.lookup_tap_leaf_script_sig(pk, leaf_hash)
+                            .map(|s| s.to_vec())
+                    })
+                    .map(|s| {
+                        debug_assert!(s.len() == *size);
+                        s
+                    })
+            }
+            Placeholder::SchnorrSigPk(pk, SchnorrSigType::ScriptSpend { leaf_hash }, size) => sat
+                .lookup_tap_leaf_script_sig(pk, leaf_hash)
+                .map(|s| s.to_vec())
+                .map(|s| {
+                    debug_assert!(s.len() == *size);
+                    s
+                }),
+            Placeholder::SchnorrSigPk(_, _, size) => {
+                sat.lookup_tap_key_spend_sig().map(|s| s.to_vec()).map(|s| {
+                    debug_assert!(s.len() == *size);
+                    s
+                })
+            }
+            Placeholder::SchnorrSigPkHash(pkh, tap_leaf_hash, size) => sat
+                .lookup_raw_pkh_tap_leaf_script_sig(&(*pkh, *tap_leaf_hash))
+                .map(|(_, s)| {
+                    let sig = s.to_vec();
+                    debug_assert!(sig.len() == *size);
+                    sig
+                }),
+            // Once adapted, the signatures are looked up like any other.
+            Placeholder::EcdsaAdaptorSigPk(pk, _) => sat.lookup_ecdsa_sig(pk).map(|s| s.to_vec()),
+            Placeholder::SchnorrAdaptorSigPk(pk, leaf_hash, _, size) => sat
+                .lookup_tap_leaf_script_sig(pk, leaf_hash)
+                .map(|s| s.to_vec())
+                .map(|s| {
+                    debug_assert!(s.len() == *size);
+                    s
+                }),
+            Placeholder::HashDissatisfaction => Some(vec![0; 32]),
+            Placeholder::PushZero => Some(vec![]),
+            Placeholder::PushOne => Some(vec![1]),
+            Placeholder::Data(data) => Some(data.clone()),
+            Placeholder::TapScript(s) => Some(s.to_bytes()),
+            Placeholder::TapControlBlock(cb) => Some(cb.serialize()),
+        }
+    }
+}
+
+/// A witness, if available, for a Miniscript fragment
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Witness<T> {
+    /// Witness Available and the value of the witness
+    Stack(Vec<T>),
+    /// Third party can possibly satisfy the fragment but we cannot
+    /// Witness Unavailable
+    Unavailable,
+    /// No third party can produce a satisfaction without private key
+    /// Witness Impossible
+    Impossible,
+}
+
+impl<Pk: MiniscriptKey> PartialOrd for Witness<Placeholder<Pk>> {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> { Some(self.cmp(other)) }
+}
+
+impl<Pk: MiniscriptKey> Ord for Witness<Placeholder<Pk>> {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
         match (self, other) {
             (Witness::Stack(v1), Witness::Stack(v2)) => {
                 let w1 = witness_size(v1);
@@ -746,6 +1624,8 @@ impl<Pk: MiniscriptKey + ToPublicKey> Witness<Placeholder<Pk>> {
             super::context::SigType::Ecdsa => {
                 if sat.provider_lookup_ecdsa_sig(pk) {
                     Witness::Stack(vec![Placeholder::EcdsaSigPk(pk.clone())])
+                } else if let Some(adaptor) = sat.provider_lookup_ecdsa_adaptor_sig(pk) {
+                    Witness::Stack(vec![Placeholder::EcdsaAdaptorSigPk(pk.clone(), adaptor)])
                 } else {
                     // Signatures cannot be forged
                     Witness::Impossible
@@ -758,8 +1638,13 @@ impl<Pk: MiniscriptKey + ToPublicKey> Witness<Placeholder<Pk>> {
                         SchnorrSigType::ScriptSpend { leaf_hash: *leaf_hash },
                         size,
                     )]),
-                    // Signatures cannot be forged
-                    None => Witness::Impossible,
+                    None => match sat.provider_lookup_tap_leaf_script_adaptor_sig(pk, leaf_hash) {
+                        Some((adaptor, size)) => Witness::Stack(vec![
+                            Placeholder::SchnorrAdaptorSigPk(pk.clone(), *leaf_hash, adaptor, size),
+                        ]),
+                        // Signatures cannot be forged
+                        None => Witness::Impossible,
+                    },
                 }
             }
         }
@@ -877,8 +1762,127 @@ impl<Pk: MiniscriptKey> Witness<Placeholder<Pk>> {
     }
 }
 
+/// The cost of satisfying a child of a `thresh` rather than dissatisfying it
+///
+/// Costs are compared field by field, so no amount of witness weight is worth
+/// an extra impossible satisfaction or an extra signature.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+struct ThreshCost {
+    impossible: usize,
+    sigs: usize,
+    weight: i128,
+}
+
+impl ThreshCost {
+    /// The weight of a satisfaction relative to the matching dissatisfaction.
+    fn weight<Pk: MiniscriptKey>(
+        sat: &Satisfaction<Placeholder<Pk>>,
+        dissat: &Satisfaction<Placeholder<Pk>>,
+    ) -> i128 {
+        match (&sat.stack, &dissat.stack) {
+            (&Witness::Unavailable, _) | (&Witness::Impossible, _) => i64::MAX.into(),
+            // This can only be the case when we have PkH without the corresponding
+            // Pubkey.
+            (_, &Witness::Unavailable) | (_, &Witness::Impossible) => i64::MIN.into(),
+            (Witness::Stack(s), Witness::Stack(d)) => {
+                witness_size(s) as i128 - witness_size(d) as i128
+            }
+        }
+    }
+}
+
+impl ops::Add for ThreshCost {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        ThreshCost {
+            impossible: self.impossible + other.impossible,
+            sigs: self.sigs + other.sigs,
+            weight: self.weight + other.weight,
+        }
+    }
+}
+
+/// Choose which `k` children of a `thresh` to satisfy, minimizing the sum of their costs.
+///
+/// Costs add up field by field and compare lexicographically, so the `k` cheapest children
+/// always have the cheapest sum. The sort is stable, so on ties earlier children are preferred.
+fn select_thresh(k: usize, costs: &[ThreshCost]) -> Vec<bool> {
+    let mut indices = (0..costs.len()).collect::<Vec<_>>();
+    indices.sort_by_key(|&i| costs[i]);
+
+    let mut chosen = vec![false; costs.len()];
+    for &i in indices.iter().take(k) {
+        chosen[i] = true;
+    }
+    chosen
+}
+
+/// Choose which `k` children of a `thresh` to satisfy, preferring the ones with the highest
+/// sum of `priorities`.
+///
+/// The selected children cost at most `tolerance` more witness weight than the cheapest ones,
+/// and never more impossible satisfactions or signatures. This is a dynamic program over the
+/// children which tracks the sum of the priorities: `best[j]` maps every reachable sum to the
+/// cheapest way of satisfying `j` of the children considered so far with that sum.
+fn select_thresh_preferring(
+    k: usize,
+    costs: &[ThreshCost],
+    priorities: &[i64],
+    tolerance: usize,
+) -> Vec<bool> {
+    if priorities.iter().all(|&p| p == 0) {
+        return select_thresh(k, costs);
+    }
+
+    let n = costs.len();
+    let mut best = vec![BTreeMap::new(); k + 1];
+    best[0].insert(0i64, ThreshCost::default());
+    let mut take = vec![BTreeSet::new(); n];
+    for (i, (&cost, &priority)) in costs.iter().zip(priorities).enumerate().rev() {
+        for j in (cmp::max(1, k.saturating_sub(i))..=cmp::min(k, n - i)).rev() {
+            let with = best[j - 1]
+                .iter()
+                .map(|(&sum, &rest)| (sum + priority, cost + rest))
+                .collect::<Vec<_>>();
+            for (sum, with) in with {
+                match best[j].get(&sum) {
+                    Some(&without) if with > without => {}
+                    _ => {
+                        best[j].insert(sum, with);
+                        take[i].insert((j, sum));
+                    }
+                }
+            }
+        }
+    }
+
+    let optimum = *best[k].values().min().expect("a thresh always has at least k children");
+    let (&(mut sum), _) = best[k]
+        .iter()
+        .rev()
+        .find(|(_, cost)| {
+            cost.impossible == optimum.impossible
+                && cost.sigs == optimum.sigs
+                && cost.weight <= optimum.weight + tolerance as i128
+        })
+        .expect("the optimum is acceptable");
+    let mut chosen = vec![false; n];
+    let mut j = k;
+    for (i, chosen) in chosen.iter_mut().enumerate() {
+        if j > 0 && take[i].contains(&(j, sum)) {
+            *chosen = true;
+            j -= 1;
+            sum -= priorities[i];
+        }
+    }
+    debug_assert_eq!(j, 0, "a thresh always has at least k children");
+    chosen
+}
+
 /// A (dis)satisfaction of a Miniscript fragment
 #[derive(Clone, PartialEq, Eq, Debug, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Satisfaction<T> {
     /// The actual witness stack
     pub stack: Witness<T>,
@@ -905,6 +1909,36 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfaction<Placeholder<Pk>> {
         }
     }
 
+    /// The satisfaction which is impossible to produce.
+    fn impossible() -> Self {
+        Satisfaction {
+            has_sig: false,
+            relative_timelock: None,
+            absolute_timelock: None,
+            stack: Witness::Impossible,
+        }
+    }
+
+    /// The satisfaction consisting of a single empty push.
+    fn push_0() -> Self {
+        Satisfaction {
+            has_sig: false,
+            relative_timelock: None,
+            absolute_timelock: None,
+            stack: Witness::push_0(),
+        }
+    }
+
+    /// The dissatisfaction of a hash fragment.
+    fn hash_dissatisfaction() -> Self {
+        Satisfaction {
+            has_sig: false,
+            relative_timelock: None,
+            absolute_timelock: None,
+            stack: Witness::hash_dissatisfaction(),
+        }
+    }
+
     /// Forms a satisfaction which is the concatenation of two satisfactions, with `other`'s
     /// stack before `self`'s.
     ///
@@ -920,18 +1954,38 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfaction<Placeholder<Pk>> {
         }
     }
 
-    pub(crate) fn build_template<P, Ctx>(
-        term: &Terminal<Pk, Ctx>,
+    /// The sum of the priorities of the keys whose signatures this satisfaction uses.
+    fn key_priority<P: AssetProvider<Pk>>(&self, provider: &P) -> i64 {
+        let stack = match self.stack {
+            Witness::Stack(ref stack) => stack,
+            Witness::Unavailable | Witness::Impossible => return 0,
+        };
+        stack
+            .iter()
+            .filter_map(|placeholder| match placeholder {
+                Placeholder::EcdsaSigPk(pk)
+                | Placeholder::SchnorrSigPk(pk, ..)
+                | Placeholder::EcdsaAdaptorSigPk(pk, _)
+                | Placeholder::SchnorrAdaptorSigPk(pk, ..) => Some(pk),
+                _ => None,
+            })
+            .map(|pk| i64::from(provider.provider_lookup_key_priority(pk).unwrap_or(0)))
+            .sum()
+    }
+
+    pub(crate) fn build_template<P, Ctx, Ext>(
+        ms: &Miniscript<Pk, Ctx, Ext>,
         provider: &P,
         root_has_sig: bool,
         leaf_hash: &TapLeafHash,
     ) -> Self
     where
         Ctx: ScriptContext,
+        Ext: Extension,
         P: AssetProvider<Pk>,
     {
         Self::satisfy_helper(
-            term,
+            ms,
             provider,
             root_has_sig,
             leaf_hash,
@@ -940,18 +1994,19 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfaction<Placeholder<Pk>> {
         )
     }
 
-    pub(crate) fn build_template_mall<P, Ctx>(
-        term: &Terminal<Pk, Ctx>,
+    pub(crate) fn build_template_mall<P, Ctx, Ext>(
+        ms: &Miniscript<Pk, Ctx, Ext>,
         provider: &P,
         root_has_sig: bool,
         leaf_hash: &TapLeafHash,
     ) -> Self
     where
         Ctx: ScriptContext,
+        Ext: Extension,
         P: AssetProvider<Pk>,
     {
         Self::satisfy_helper(
-            term,
+            ms,
             provider,
             root_has_sig,
             leaf_hash,
@@ -961,73 +2016,32 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfaction<Placeholder<Pk>> {
     }
 
     // produce a non-malleable satisafaction for thesh frag
-    fn thresh<Ctx, Sat, F>(
-        thresh: &Threshold<Arc<Miniscript<Pk, Ctx>>, 0>,
-        stfr: &Sat,
-        root_has_sig: bool,
-        leaf_hash: &TapLeafHash,
-        min_fn: &mut F,
-    ) -> Self
-    where
-        Ctx: ScriptContext,
-        Sat: AssetProvider<Pk>,
-        F: FnMut(
-            Satisfaction<Placeholder<Pk>>,
-            Satisfaction<Placeholder<Pk>>,
-        ) -> Satisfaction<Placeholder<Pk>>,
-    {
-        let mut sats = thresh
-            .iter()
-            .map(|s| {
-                Self::satisfy_helper(
-                    &s.node,
-                    stfr,
-                    root_has_sig,
-                    leaf_hash,
-                    min_fn,
-                    &mut Self::thresh,
-                )
-            })
-            .collect::<Vec<_>>();
-        // Start with the to-return stack set to all dissatisfactions
-        let mut ret_stack = thresh
+    fn thresh(
+        k: usize,
+        mut sats: Vec<Self>,
+        mut ret_stack: Vec<Self>,
+        priorities: &[i64],
+        tolerance: usize,
+    ) -> Self {
+        // Satisfactions without signatures beat satisfactions with
+        // signatures, and among those we choose by (sat cost - dissat cost)
+        let costs = sats
             .iter()
-            .map(|s| {
-                Self::dissatisfy_helper(
-                    &s.node,
-                    stfr,
-                    root_has_sig,
-                    leaf_hash,
-                    min_fn,
-                    &mut Self::thresh,
-                )
+            .zip(&ret_stack)
+            .map(|(sat, dissat)| ThreshCost {
+                // First consider the candidates that are not impossible to satisfy
+                // by any party. Among those first consider the ones that have no sig
+                // because third party can malleate them if they are not chosen.
+                // Lastly, choose by weight.
+                impossible: usize::from(sat.stack == Witness::Impossible),
+                sigs: usize::from(sat.has_sig),
+                weight: ThreshCost::weight(sat, dissat),
             })
             .collect::<Vec<_>>();
+        let chosen = select_thresh_preferring(k, &costs, priorities, tolerance);
 
-        // Sort everything by (sat cost - dissat cost), except that
-        // satisfactions without signatures beat satisfactions with
-        // signatures
-        let mut sat_indices = (0..thresh.n()).collect::<Vec<_>>();
-        sat_indices.sort_by_key(|&i| {
-            let stack_weight = match (&sats[i].stack, &ret_stack[i].stack) {
-                (&Witness::Unavailable, _) | (&Witness::Impossible, _) => i64::MAX,
-                // This can only be the case when we have PkH without the corresponding
-                // Pubkey.
-                (_, &Witness::Unavailable) | (_, &Witness::Impossible) => i64::MIN,
-                (Witness::Stack(s), Witness::Stack(d)) => {
-                    witness_size(s) as i64 - witness_size(d) as i64
-                }
-            };
-            let is_impossible = sats[i].stack == Witness::Impossible;
-            // First consider the candidates that are not impossible to satisfy
-            // by any party. Among those first consider the ones that have no sig
-            // because third party can malleate them if they are not chosen.
-            // Lastly, choose by weight.
-            (is_impossible, sats[i].has_sig, stack_weight)
-        });
-
-        for i in 0..thresh.k() {
-            mem::swap(&mut ret_stack[sat_indices[i]], &mut sats[sat_indices[i]]);
+        for (i, _) in chosen.iter().enumerate().filter(|(_, &c)| c) {
+            mem::swap(&mut ret_stack[i], &mut sats[i]);
         }
 
         // We preferably take satisfactions that are not impossible
@@ -1035,7 +2049,11 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfaction<Placeholder<Pk>> {
         // then the threshold branch is impossible to satisfy
         // For example, the fragment thresh(2, hash, 0, 0, 0)
         // is has an impossible witness
-        if sats[sat_indices[thresh.k() - 1]].stack == Witness::Impossible {
+        if chosen
+            .iter()
+            .zip(&ret_stack)
+            .any(|(&c, sat)| c && sat.stack == Witness::Impossible)
+        {
             Satisfaction {
                 stack: Witness::Impossible,
                 // If the witness is impossible, we don't care about the
@@ -1045,17 +2063,18 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfaction<Placeholder<Pk>> {
                 absolute_timelock: None,
             }
         }
-        // We are now guaranteed that all elements in `k` satisfactions
-        // are not impossible(we sort by is_impossible bool).
-        // The above loop should have taken everything without a sig
-        // (since those were sorted higher than non-sigs). If there
+        // We are now guaranteed that all `k` chosen satisfactions are
+        // not impossible. The selection should have taken everything
+        // without a sig (since those cost less than sigs). If there
         // are remaining non-sig satisfactions this indicates a
         // malleability vector
         // For example, the fragment thresh(2, hash, hash, 0, 0)
         // is uniquely satisfyiable because there is no satisfaction
         // for the 0 fragment
-        else if !sats[sat_indices[thresh.k()]].has_sig
-            && sats[sat_indices[thresh.k()]].stack != Witness::Impossible
+        else if chosen
+            .iter()
+            .zip(&sats)
+            .any(|(&c, sat)| !c && !sat.has_sig && sat.stack != Witness::Impossible)
         {
             // All arguments should be `d`, so dissatisfactions have no
             // signatures; and in this branch we assume too many weak
@@ -1079,68 +2098,28 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfaction<Placeholder<Pk>> {
     }
 
     // produce a possily malleable satisafaction for thesh frag
-    fn thresh_mall<Ctx, Sat, F>(
-        thresh: &Threshold<Arc<Miniscript<Pk, Ctx>>, 0>,
-        stfr: &Sat,
-        root_has_sig: bool,
-        leaf_hash: &TapLeafHash,
-        min_fn: &mut F,
-    ) -> Self
-    where
-        Ctx: ScriptContext,
-        Sat: AssetProvider<Pk>,
-        F: FnMut(
-            Satisfaction<Placeholder<Pk>>,
-            Satisfaction<Placeholder<Pk>>,
-        ) -> Satisfaction<Placeholder<Pk>>,
-    {
-        let mut sats = thresh
-            .iter()
-            .map(|s| {
-                Self::satisfy_helper(
-                    &s.node,
-                    stfr,
-                    root_has_sig,
-                    leaf_hash,
-                    min_fn,
-                    &mut Self::thresh_mall,
-                )
-            })
-            .collect::<Vec<_>>();
-        // Start with the to-return stack set to all dissatisfactions
-        let mut ret_stack = thresh
+    fn thresh_mall(
+        k: usize,
+        mut sats: Vec<Self>,
+        mut ret_stack: Vec<Self>,
+        priorities: &[i64],
+        tolerance: usize,
+    ) -> Self {
+        // For malleable satifactions, directly choose smallest weights
+        let costs = sats
             .iter()
-            .map(|s| {
-                Self::dissatisfy_helper(
-                    &s.node,
-                    stfr,
-                    root_has_sig,
-                    leaf_hash,
-                    min_fn,
-                    &mut Self::thresh_mall,
-                )
+            .zip(&ret_stack)
+            .map(|(sat, dissat)| ThreshCost {
+                impossible: 0,
+                sigs: 0,
+                weight: ThreshCost::weight(sat, dissat),
             })
             .collect::<Vec<_>>();
-
-        // Sort everything by (sat cost - dissat cost), except that
-        // satisfactions without signatures beat satisfactions with
-        // signatures
-        let mut sat_indices = (0..thresh.n()).collect::<Vec<_>>();
-        sat_indices.sort_by_key(|&i| {
-            // For malleable satifactions, directly choose smallest weights
-            match (&sats[i].stack, &ret_stack[i].stack) {
-                (&Witness::Unavailable, _) | (&Witness::Impossible, _) => i64::MAX,
-                // This is only possible when one of the branches has PkH
-                (_, &Witness::Unavailable) | (_, &Witness::Impossible) => i64::MIN,
-                (Witness::Stack(s), Witness::Stack(d)) => {
-                    witness_size(s) as i64 - witness_size(d) as i64
-                }
-            }
-        });
+        let chosen = select_thresh_preferring(k, &costs, priorities, tolerance);
 
         // swap the satisfactions
-        for i in 0..thresh.k() {
-            mem::swap(&mut ret_stack[sat_indices[i]], &mut sats[sat_indices[i]]);
+        for (i, _) in chosen.iter().enumerate().filter(|(_, &c)| c) {
+            mem::swap(&mut ret_stack[i], &mut sats[i]);
         }
 
         // combine the witness
@@ -1185,8 +2164,9 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfaction<Placeholder<Pk>> {
             },
             // If both have a signature associated with them, choose the
             // cheaper one (where "cheaper" is defined such that available
-            // things are cheaper than unavailable ones)
-            (true, true) if sat1.stack < sat2.stack => Satisfaction {
+            // things are cheaper than unavailable ones), or the first one
+            // on ties
+            (true, true) if sat1.stack <= sat2.stack => Satisfaction {
                 stack: sat1.stack,
                 has_sig: true,
                 relative_timelock: sat1.relative_timelock,
@@ -1210,7 +2190,8 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfaction<Placeholder<Pk>> {
             (_, &Witness::Impossible) | (_, &Witness::Unavailable) => return sat1,
             _ => {}
         }
-        let (stack, absolute_timelock, relative_timelock) = if sat1.stack < sat2.stack {
+        // On ties, choose the first one
+        let (stack, absolute_timelock, relative_timelock) = if sat1.stack <= sat2.stack {
             (sat1.stack, sat1.absolute_timelock, sat1.relative_timelock)
         } else {
             (sat2.stack, sat2.absolute_timelock, sat2.relative_timelock)
@@ -1225,55 +2206,191 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfaction<Placeholder<Pk>> {
         }
     }
 
-    // produce a non-malleable satisfaction
-    fn satisfy_helper<Ctx, Sat, F, G>(
-        term: &Terminal<Pk, Ctx>,
+    pub(crate) fn build_dissatisfaction_template<P, Ctx, Ext>(
+        ms: &Miniscript<Pk, Ctx, Ext>,
+        provider: &P,
+        root_has_sig: bool,
+        leaf_hash: &TapLeafHash,
+    ) -> Self
+    where
+        Ctx: ScriptContext,
+        Ext: Extension,
+        P: AssetProvider<Pk>,
+    {
+        let (_, dissat) = Self::satisfy_dissatisfy_helper(
+            ms,
+            provider,
+            root_has_sig,
+            leaf_hash,
+            &mut Satisfaction::minimum,
+            &mut Satisfaction::thresh,
+        );
+        dissat
+    }
+
+    // produce a satisfaction, using `min_fn` and `thresh_fn` to choose between
+    // the possible satisfactions of disjunctions and thresholds
+    fn satisfy_helper<Ctx, Ext, Sat, F, G>(
+        ms: &Miniscript<Pk, Ctx, Ext>,
+        stfr: &Sat,
+        root_has_sig: bool,
+        leaf_hash: &TapLeafHash,
+        min_fn: &mut F,
+        thresh_fn: &mut G,
+    ) -> Self
+    where
+        Ctx: ScriptContext,
+        Ext: Extension,
+        Sat: AssetProvider<Pk>,
+        F: FnMut(
+            Satisfaction<Placeholder<Pk>>,
+            Satisfaction<Placeholder<Pk>>,
+        ) -> Satisfaction<Placeholder<Pk>>,
+        G: FnMut(
+            usize,
+            Vec<Satisfaction<Placeholder<Pk>>>,
+            Vec<Satisfaction<Placeholder<Pk>>>,
+            &[i64],
+            usize,
+        ) -> Satisfaction<Placeholder<Pk>>,
+    {
+        let (sat, _) =
+            Self::satisfy_dissatisfy_helper(ms, stfr, root_has_sig, leaf_hash, min_fn, thresh_fn);
+        sat
+    }
+
+    // produce the satisfaction and dissatisfaction of the root
+    fn satisfy_dissatisfy_helper<Ctx, Ext, Sat, F, G>(
+        ms: &Miniscript<Pk, Ctx, Ext>,
+        stfr: &Sat,
+        root_has_sig: bool,
+        leaf_hash: &TapLeafHash,
+        min_fn: &mut F,
+        thresh_fn: &mut G,
+    ) -> (Self, Self)
+    where
+        Ctx: ScriptContext,
+        Ext: Extension,
+        Sat: AssetProvider<Pk>,
+        F: FnMut(
+            Satisfaction<Placeholder<Pk>>,
+            Satisfaction<Placeholder<Pk>>,
+        ) -> Satisfaction<Placeholder<Pk>>,
+        G: FnMut(
+            usize,
+            Vec<Satisfaction<Placeholder<Pk>>>,
+            Vec<Satisfaction<Placeholder<Pk>>>,
+            &[i64],
+            usize,
+        ) -> Satisfaction<Placeholder<Pk>>,
+    {
+        // The satisfaction and dissatisfaction of every node, computed
+        // bottom-up. Every node is the child of a single parent, which takes
+        // its entry.
+        let mut results: Vec<Option<(Self, Self)>> = vec![];
+        for item in ms.post_order_iter() {
+            let children = item
+                .child_indices
+                .iter()
+                .map(|&i| results[i].take().expect("each child has one parent"))
+                .collect();
+            results.push(Some(Self::satisfy_node(
+                &item.node.node,
+                children,
+                stfr,
+                root_has_sig,
+                leaf_hash,
+                min_fn,
+                thresh_fn,
+            )));
+        }
+        results.pop().flatten().expect("the root is the last node")
+    }
+
+    // produce the satisfaction and dissatisfaction of a single node, given
+    // the satisfactions and dissatisfactions of its children
+    fn satisfy_node<Ctx, Ext, Sat, F, G>(
+        term: &Terminal<Pk, Ctx, Ext>,
+        children: Vec<(Self, Self)>,
         stfr: &Sat,
         root_has_sig: bool,
         leaf_hash: &TapLeafHash,
         min_fn: &mut F,
         thresh_fn: &mut G,
-    ) -> Self
+    ) -> (Self, Self)
     where
         Ctx: ScriptContext,
+        Ext: Extension,
         Sat: AssetProvider<Pk>,
         F: FnMut(
             Satisfaction<Placeholder<Pk>>,
             Satisfaction<Placeholder<Pk>>,
         ) -> Satisfaction<Placeholder<Pk>>,
         G: FnMut(
-            &Threshold<Arc<Miniscript<Pk, Ctx>>, 0>,
-            &Sat,
-            bool,
-            &TapLeafHash,
-            &mut F,
+            usize,
+            Vec<Satisfaction<Placeholder<Pk>>>,
+            Vec<Satisfaction<Placeholder<Pk>>>,
+            &[i64],
+            usize,
         ) -> Satisfaction<Placeholder<Pk>>,
     {
+        let mut children = children.into_iter();
+        let mut child = || children.next().expect("child exists");
         match *term {
-            Terminal::PkK(ref pk) => Satisfaction {
-                stack: Witness::signature::<_, Ctx>(stfr, pk, leaf_hash),
-                has_sig: true,
-                relative_timelock: None,
-                absolute_timelock: None,
-            },
+            Terminal::PkK(ref pk) => (
+                Satisfaction {
+                    stack: Witness::signature::<_, Ctx>(stfr, pk, leaf_hash),
+                    has_sig: true,
+                    relative_timelock: None,
+                    absolute_timelock: None,
+                },
+                Satisfaction {
+                    stack: Witness::push_0(),
+                    has_sig: false,
+                    relative_timelock: None,
+                    absolute_timelock: None,
+                },
+            ),
             Terminal::PkH(ref pk) => {
                 let wit = Witness::signature::<_, Ctx>(stfr, pk, leaf_hash);
+                (
+                    Satisfaction {
+                        stack: Witness::combine(
+                            wit,
+                            Witness::Stack(vec![Placeholder::Pubkey(pk.clone(), Ctx::pk_len(pk))]),
+                        ),
+                        has_sig: true,
+                        relative_timelock: None,
+                        absolute_timelock: None,
+                    },
+                    Satisfaction {
+                        stack: Witness::combine(
+                            Witness::push_0(),
+                            Witness::Stack(vec![Placeholder::Pubkey(pk.clone(), Ctx::pk_len(pk))]),
+                        ),
+                        has_sig: false,
+                        relative_timelock: None,
+                        absolute_timelock: None,
+                    },
+                )
+            }
+            Terminal::RawPkH(ref pkh) => (
+                Satisfaction {
+                    stack: Witness::pkh_signature::<_, Ctx>(stfr, pkh, leaf_hash),
+                    has_sig: true,
+                    relative_timelock: None,
+                    absolute_timelock: None,
+                },
                 Satisfaction {
                     stack: Witness::combine(
-                        wit,
-                        Witness::Stack(vec![Placeholder::Pubkey(pk.clone(), Ctx::pk_len(pk))]),
+                        Witness::push_0(),
+                        Witness::pkh_public_key::<_, Ctx>(stfr, pkh),
                     ),
-                    has_sig: true,
+                    has_sig: false,
                     relative_timelock: None,
                     absolute_timelock: None,
-                }
-            }
-            Terminal::RawPkH(ref pkh) => Satisfaction {
-                stack: Witness::pkh_signature::<_, Ctx>(stfr, pkh, leaf_hash),
-                has_sig: true,
-                relative_timelock: None,
-                absolute_timelock: None,
-            },
+                },
+            ),
             Terminal::After(t) => {
                 let (stack, absolute_timelock) = if stfr.check_after(t.into()) {
                     (Witness::empty(), Some(t))
@@ -1287,7 +2404,15 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfaction<Placeholder<Pk>> {
                 } else {
                     (Witness::Unavailable, None)
                 };
-                Satisfaction { stack, has_sig: false, relative_timelock: None, absolute_timelock }
+                (
+                    Satisfaction {
+                        stack,
+                        has_sig: false,
+                        relative_timelock: None,
+                        absolute_timelock,
+                    },
+                    Satisfaction::impossible(),
+                )
             }
             Terminal::Older(t) => {
                 let (stack, relative_timelock) = if stfr.check_older(t.into()) {
@@ -1302,147 +2427,124 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfaction<Placeholder<Pk>> {
                 } else {
                     (Witness::Unavailable, None)
                 };
-                Satisfaction { stack, has_sig: false, relative_timelock, absolute_timelock: None }
-            }
-            Terminal::Ripemd160(ref h) => Satisfaction {
-                stack: Witness::ripemd160_preimage(stfr, h),
-                has_sig: false,
-                relative_timelock: None,
-                absolute_timelock: None,
-            },
-            Terminal::Hash160(ref h) => Satisfaction {
-                stack: Witness::hash160_preimage(stfr, h),
-                has_sig: false,
-                relative_timelock: None,
-                absolute_timelock: None,
-            },
-            Terminal::Sha256(ref h) => Satisfaction {
-                stack: Witness::sha256_preimage(stfr, h),
-                has_sig: false,
-                relative_timelock: None,
-                absolute_timelock: None,
-            },
-            Terminal::Hash256(ref h) => Satisfaction {
-                stack: Witness::hash256_preimage(stfr, h),
-                has_sig: false,
-                relative_timelock: None,
-                absolute_timelock: None,
-            },
-            Terminal::True => Satisfaction {
-                stack: Witness::empty(),
-                has_sig: false,
-                relative_timelock: None,
-                absolute_timelock: None,
-            },
-            Terminal::False => Satisfaction {
-                stack: Witness::Impossible,
-                has_sig: false,
-                relative_timelock: None,
-                absolute_timelock: None,
-            },
-            Terminal::Alt(ref sub)
-            | Terminal::Swap(ref sub)
-            | Terminal::Check(ref sub)
-            | Terminal::Verify(ref sub)
-            | Terminal::NonZero(ref sub)
-            | Terminal::ZeroNotEqual(ref sub) => {
-                Self::satisfy_helper(&sub.node, stfr, root_has_sig, leaf_hash, min_fn, thresh_fn)
+                (
+                    Satisfaction {
+                        stack,
+                        has_sig: false,
+                        relative_timelock,
+                        absolute_timelock: None,
+                    },
+                    Satisfaction::impossible(),
+                )
             }
-            Terminal::DupIf(ref sub) => {
-                let sat = Self::satisfy_helper(
-                    &sub.node,
-                    stfr,
-                    root_has_sig,
-                    leaf_hash,
-                    min_fn,
-                    thresh_fn,
-                );
+            Terminal::Ripemd160(ref h) => (
                 Satisfaction {
-                    stack: Witness::combine(sat.stack, Witness::push_1()),
-                    has_sig: sat.has_sig,
-                    relative_timelock: sat.relative_timelock,
-                    absolute_timelock: sat.absolute_timelock,
-                }
+                    stack: Witness::ripemd160_preimage(stfr, h),
+                    has_sig: false,
+                    relative_timelock: None,
+                    absolute_timelock: None,
+                },
+                Satisfaction::hash_dissatisfaction(),
+            ),
+            Terminal::Hash160(ref h) => (
+                Satisfaction {
+                    stack: Witness::hash160_preimage(stfr, h),
+                    has_sig: false,
+                    relative_timelock: None,
+                    absolute_timelock: None,
+                },
+                Satisfaction::hash_dissatisfaction(),
+            ),
+            Terminal::Sha256(ref h) => (
+                Satisfaction {
+                    stack: Witness::sha256_preimage(stfr, h),
+                    has_sig: false,
+                    relative_timelock: None,
+                    absolute_timelock: None,
+                },
+                Satisfaction::hash_dissatisfaction(),
+            ),
+            Terminal::Hash256(ref h) => (
+                Satisfaction {
+                    stack: Witness::hash256_preimage(stfr, h),
+                    has_sig: false,
+                    relative_timelock: None,
+                    absolute_timelock: None,
+                },
+                Satisfaction::hash_dissatisfaction(),
+            ),
+            Terminal::True => (Satisfaction::empty(), Satisfaction::impossible()),
+            Terminal::False => (Satisfaction::impossible(), Satisfaction::empty()),
+            Terminal::Alt(..)
+            | Terminal::Swap(..)
+            | Terminal::Check(..)
+            | Terminal::ZeroNotEqual(..) => child(),
+            Terminal::Verify(..) => (child().0, Satisfaction::impossible()),
+            Terminal::NonZero(..) => (child().0, Satisfaction::push_0()),
+            Terminal::DupIf(..) => {
+                let (sat, _) = child();
+                (
+                    Satisfaction {
+                        stack: Witness::combine(sat.stack, Witness::push_1()),
+                        has_sig: sat.has_sig,
+                        relative_timelock: sat.relative_timelock,
+                        absolute_timelock: sat.absolute_timelock,
+                    },
+                    Satisfaction::push_0(),
+                )
             }
-            Terminal::AndV(ref l, ref r) | Terminal::AndB(ref l, ref r) => {
-                let l_sat =
-                    Self::satisfy_helper(&l.node, stfr, root_has_sig, leaf_hash, min_fn, thresh_fn);
-                let r_sat =
-                    Self::satisfy_helper(&r.node, stfr, root_has_sig, leaf_hash, min_fn, thresh_fn);
-                l_sat.concatenate_rev(r_sat)
+            Terminal::AndV(..) => {
+                let ((v_sat, _), (o_sat, o_nsat)) = (child(), child());
+                (v_sat.clone().concatenate_rev(o_sat), v_sat.concatenate_rev(o_nsat))
             }
-            Terminal::AndOr(ref a, ref b, ref c) => {
-                let a_sat =
-                    Self::satisfy_helper(&a.node, stfr, root_has_sig, leaf_hash, min_fn, thresh_fn);
-                let a_nsat = Self::dissatisfy_helper(
-                    &a.node,
-                    stfr,
-                    root_has_sig,
-                    leaf_hash,
-                    min_fn,
-                    thresh_fn,
-                );
-                let b_sat =
-                    Self::satisfy_helper(&b.node, stfr, root_has_sig, leaf_hash, min_fn, thresh_fn);
-                let c_sat =
-                    Self::satisfy_helper(&c.node, stfr, root_has_sig, leaf_hash, min_fn, thresh_fn);
-
-                min_fn(a_sat.concatenate_rev(b_sat), a_nsat.concatenate_rev(c_sat))
+            Terminal::AndB(..) => {
+                let ((l_sat, l_nsat), (r_sat, r_nsat)) = (child(), child());
+                (l_sat.concatenate_rev(r_sat), l_nsat.concatenate_rev(r_nsat))
             }
-            Terminal::OrB(ref l, ref r) => {
-                let l_sat =
-                    Self::satisfy_helper(&l.node, stfr, root_has_sig, leaf_hash, min_fn, thresh_fn);
-                let r_sat =
-                    Self::satisfy_helper(&r.node, stfr, root_has_sig, leaf_hash, min_fn, thresh_fn);
-                let l_nsat = Self::dissatisfy_helper(
-                    &l.node,
-                    stfr,
-                    root_has_sig,
-                    leaf_hash,
-                    min_fn,
-                    thresh_fn,
-                );
-                let r_nsat = Self::dissatisfy_helper(
-                    &r.node,
-                    stfr,
-                    root_has_sig,
-                    leaf_hash,
-                    min_fn,
-                    thresh_fn,
-                );
+            Terminal::AndOr(..) => {
+                let ((a_sat, a_nsat), (b_sat, _), (c_sat, c_nsat)) = (child(), child(), child());
+                (
+                    min_fn(a_sat.concatenate_rev(b_sat), a_nsat.clone().concatenate_rev(c_sat)),
+                    a_nsat.concatenate_rev(c_nsat),
+                )
+            }
+            Terminal::OrB(..) => {
+                let ((l_sat, l_nsat), (r_sat, r_nsat)) = (child(), child());
 
                 assert!(!l_nsat.has_sig);
                 assert!(!r_nsat.has_sig);
 
-                min_fn(
-                    Satisfaction::concatenate_rev(l_nsat, r_sat),
-                    Satisfaction::concatenate_rev(l_sat, r_nsat),
+                (
+                    min_fn(
+                        Satisfaction::concatenate_rev(l_sat, r_nsat.clone()),
+                        Satisfaction::concatenate_rev(l_nsat.clone(), r_sat),
+                    ),
+                    l_nsat.concatenate_rev(r_nsat),
                 )
             }
-            Terminal::OrD(ref l, ref r) | Terminal::OrC(ref l, ref r) => {
-                let l_sat =
-                    Self::satisfy_helper(&l.node, stfr, root_has_sig, leaf_hash, min_fn, thresh_fn);
-                let r_sat =
-                    Self::satisfy_helper(&r.node, stfr, root_has_sig, leaf_hash, min_fn, thresh_fn);
-                let l_nsat = Self::dissatisfy_helper(
-                    &l.node,
-                    stfr,
-                    root_has_sig,
-                    leaf_hash,
-                    min_fn,
-                    thresh_fn,
-                );
+            Terminal::OrD(..) => {
+                let ((l_sat, l_nsat), (r_sat, r_nsat)) = (child(), child());
 
                 assert!(!l_nsat.has_sig);
 
-                min_fn(l_sat, Satisfaction::concatenate_rev(l_nsat, r_sat))
+                (
+                    min_fn(l_sat, Satisfaction::concatenate_rev(l_nsat.clone(), r_sat)),
+                    l_nsat.concatenate_rev(r_nsat),
+                )
+            }
+            Terminal::OrC(..) => {
+                let ((l_sat, l_nsat), (r_sat, _)) = (child(), child());
+
+                assert!(!l_nsat.has_sig);
+
+                (
+                    min_fn(l_sat, Satisfaction::concatenate_rev(l_nsat, r_sat)),
+                    Satisfaction::impossible(),
+                )
             }
-            Terminal::OrI(ref l, ref r) => {
-                let l_sat =
-                    Self::satisfy_helper(&l.node, stfr, root_has_sig, leaf_hash, min_fn, thresh_fn);
-                let r_sat =
-                    Self::satisfy_helper(&r.node, stfr, root_has_sig, leaf_hash, min_fn, thresh_fn);
-                min_fn(
+            Terminal::OrI(..) => {
+                let ((l_sat, l_nsat), (r_sat, r_nsat)) = (child(), child());
+                let sat = min_fn(
                     Satisfaction {
                         stack: Witness::combine(l_sat.stack, Witness::push_1()),
                         has_sig: l_sat.has_sig,
@@ -1455,27 +2557,41 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfaction<Placeholder<Pk>> {
                         relative_timelock: r_sat.relative_timelock,
                         absolute_timelock: r_sat.absolute_timelock,
                     },
-                )
+                );
+                let dissat_1 = Satisfaction {
+                    stack: Witness::combine(l_nsat.stack, Witness::push_1()),
+                    has_sig: l_nsat.has_sig,
+                    relative_timelock: None,
+                    absolute_timelock: None,
+                };
+                let dissat_2 = Satisfaction {
+                    stack: Witness::combine(r_nsat.stack, Witness::push_0()),
+                    has_sig: r_nsat.has_sig,
+                    relative_timelock: None,
+                    absolute_timelock: None,
+                };
+                // Dissatisfactions don't need to non-malleable. Use minimum_mall always
+                (sat, Satisfaction::minimum_mall(dissat_1, dissat_2))
             }
             Terminal::Thresh(ref thresh) => {
-                if thresh.k() == thresh.n() {
+                let (sats, nsats): (Vec<_>, Vec<_>) = children.by_ref().unzip();
+                let nsat = nsats
+                    .iter()
+                    .cloned()
+                    .fold(Satisfaction::empty(), Satisfaction::concatenate_rev);
+                let sat = if thresh.k() == thresh.n() {
                     // this is just an and
-                    thresh
-                        .iter()
-                        .map(|s| {
-                            Self::satisfy_helper(
-                                &s.node,
-                                stfr,
-                                root_has_sig,
-                                leaf_hash,
-                                min_fn,
-                                thresh_fn,
-                            )
-                        })
+                    sats.into_iter()
                         .fold(Satisfaction::empty(), Satisfaction::concatenate_rev)
                 } else {
-                    thresh_fn(thresh, stfr, root_has_sig, leaf_hash, min_fn)
-                }
+                    let priorities = sats
+                        .iter()
+                        .map(|sat| sat.key_priority(stfr))
+                        .collect::<Vec<_>>();
+                    let tolerance = stfr.provider_preference_tolerance();
+                    thresh_fn(thresh.k(), sats, nsats, &priorities, tolerance)
+                };
+                (sat, nsat)
             }
             Terminal::Multi(ref thresh) => {
                 // Collect all available signatures
@@ -1484,7 +2600,8 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfaction<Placeholder<Pk>> {
                 for pk in thresh.data() {
                     match Witness::signature::<_, Ctx>(stfr, pk, leaf_hash) {
                         Witness::Stack(sig) => {
-                            sigs.push(sig);
+                            let priority = stfr.provider_lookup_key_priority(pk).unwrap_or(0);
+                            sigs.push((sig_count, priority, sig));
                             sig_count += 1;
                         }
                         Witness::Impossible => {}
@@ -1494,24 +2611,16 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfaction<Placeholder<Pk>> {
                     }
                 }
 
-                if sig_count < thresh.k() {
-                    Satisfaction {
-                        stack: Witness::Impossible,
-                        has_sig: false,
-                        relative_timelock: None,
-                        absolute_timelock: None,
-                    }
+                let sat = if sig_count < thresh.k() {
+                    Satisfaction::impossible()
                 } else {
-                    // Throw away the most expensive ones
-                    for _ in 0..sig_count - thresh.k() {
-                        let max_idx = sigs
-                            .iter()
-                            .enumerate()
-                            .max_by_key(|&(_, v)| v.len())
-                            .unwrap()
-                            .0;
-                        sigs[max_idx] = vec![];
-                    }
+                    // All ECDSA signatures have the same estimated size, so use
+                    // the ones of the first available keys with the highest
+                    // priorities, in script order
+                    sigs.sort_by_key(|&(_, priority, _)| cmp::Reverse(priority));
+                    sigs.truncate(thresh.k());
+                    sigs.sort_by_key(|&(i, _, _)| i);
+                    let sigs = sigs.into_iter().map(|(_, _, sig)| sig);
 
                     Satisfaction {
                         stack: sigs.into_iter().fold(Witness::push_0(), |acc, sig| {
@@ -1521,24 +2630,23 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfaction<Placeholder<Pk>> {
                         relative_timelock: None,
                         absolute_timelock: None,
                     }
-                }
+                };
+                let nsat = Satisfaction {
+                    stack: Witness::Stack(vec![Placeholder::PushZero; thresh.k() + 1]),
+                    has_sig: false,
+                    relative_timelock: None,
+                    absolute_timelock: None,
+                };
+                (sat, nsat)
             }
             Terminal::MultiA(ref thresh) => {
                 // Collect all available signatures
-                let mut sig_count = 0;
-                let mut sigs = vec![vec![Placeholder::PushZero]; thresh.n()];
+                let mut available = vec![];
                 for (i, pk) in thresh.iter().rev().enumerate() {
                     match Witness::signature::<_, Ctx>(stfr, pk, leaf_hash) {
                         Witness::Stack(sig) => {
-                            sigs[i] = sig;
-                            sig_count += 1;
-                            // This a privacy issue, we are only selecting the first available
-                            // sigs. Incase pk at pos 1 is not selected, we know we did not have access to it
-                            // bitcoin core also implements the same logic for MULTISIG, so I am not bothering
-                            // permuting the sigs for now
-                            if sig_count == thresh.k() {
-                                break;
-                            }
+                            let priority = stfr.provider_lookup_key_priority(pk).unwrap_or(0);
+                            available.push((i, priority, sig));
                         }
                         Witness::Impossible => {}
                         Witness::Unavailable => unreachable!(
@@ -1547,14 +2655,18 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfaction<Placeholder<Pk>> {
                     }
                 }
 
-                if sig_count < thresh.k() {
-                    Satisfaction {
-                        stack: Witness::Impossible,
-                        has_sig: false,
-                        relative_timelock: None,
-                        absolute_timelock: None,
-                    }
+                let sat = if available.len() < thresh.k() {
+                    Satisfaction::impossible()
                 } else {
+                    // This a privacy issue, we are only selecting the first available
+                    // sigs. Incase pk at pos 1 is not selected, we know we did not have access to it
+                    // bitcoin core also implements the same logic for MULTISIG, so I am not bothering
+                    // permuting the sigs for now
+                    available.sort_by_key(|&(_, priority, _)| cmp::Reverse(priority));
+                    let mut sigs = vec![vec![Placeholder::PushZero]; thresh.n()];
+                    for (i, _, sig) in available.into_iter().take(thresh.k()) {
+                        sigs[i] = sig;
+                    }
                     Satisfaction {
                         stack: sigs.into_iter().fold(Witness::empty(), |acc, sig| {
                             Witness::combine(acc, Witness::Stack(sig))
@@ -1563,191 +2675,16 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfaction<Placeholder<Pk>> {
                         relative_timelock: None,
                         absolute_timelock: None,
                     }
-                }
-            }
-        }
-    }
-
-    // Helper function to produce a dissatisfaction
-    fn dissatisfy_helper<Ctx, Sat, F, G>(
-        term: &Terminal<Pk, Ctx>,
-        stfr: &Sat,
-        root_has_sig: bool,
-        leaf_hash: &TapLeafHash,
-        min_fn: &mut F,
-        thresh_fn: &mut G,
-    ) -> Self
-    where
-        Ctx: ScriptContext,
-        Sat: AssetProvider<Pk>,
-        F: FnMut(
-            Satisfaction<Placeholder<Pk>>,
-            Satisfaction<Placeholder<Pk>>,
-        ) -> Satisfaction<Placeholder<Pk>>,
-        G: FnMut(
-            &Threshold<Arc<Miniscript<Pk, Ctx>>, 0>,
-            &Sat,
-            bool,
-            &TapLeafHash,
-            &mut F,
-        ) -> Satisfaction<Placeholder<Pk>>,
-    {
-        match *term {
-            Terminal::PkK(..) => Satisfaction {
-                stack: Witness::push_0(),
-                has_sig: false,
-                relative_timelock: None,
-                absolute_timelock: None,
-            },
-            Terminal::PkH(ref pk) => Satisfaction {
-                stack: Witness::combine(
-                    Witness::push_0(),
-                    Witness::Stack(vec![Placeholder::Pubkey(pk.clone(), Ctx::pk_len(pk))]),
-                ),
-                has_sig: false,
-                relative_timelock: None,
-                absolute_timelock: None,
-            },
-            Terminal::RawPkH(ref pkh) => Satisfaction {
-                stack: Witness::combine(
-                    Witness::push_0(),
-                    Witness::pkh_public_key::<_, Ctx>(stfr, pkh),
-                ),
-                has_sig: false,
-                relative_timelock: None,
-                absolute_timelock: None,
-            },
-            Terminal::False => Satisfaction {
-                stack: Witness::empty(),
-                has_sig: false,
-                relative_timelock: None,
-                absolute_timelock: None,
-            },
-            Terminal::True
-            | Terminal::Older(_)
-            | Terminal::After(_)
-            | Terminal::Verify(_)
-            | Terminal::OrC(..) => Satisfaction {
-                stack: Witness::Impossible,
-                has_sig: false,
-                relative_timelock: None,
-                absolute_timelock: None,
-            },
-            Terminal::Sha256(_)
-            | Terminal::Hash256(_)
-            | Terminal::Ripemd160(_)
-            | Terminal::Hash160(_) => Satisfaction {
-                stack: Witness::hash_dissatisfaction(),
-                has_sig: false,
-                relative_timelock: None,
-                absolute_timelock: None,
-            },
-            Terminal::Alt(ref sub)
-            | Terminal::Swap(ref sub)
-            | Terminal::Check(ref sub)
-            | Terminal::ZeroNotEqual(ref sub) => {
-                Self::dissatisfy_helper(&sub.node, stfr, root_has_sig, leaf_hash, min_fn, thresh_fn)
-            }
-            Terminal::DupIf(_) | Terminal::NonZero(_) => Satisfaction {
-                stack: Witness::push_0(),
-                has_sig: false,
-                relative_timelock: None,
-                absolute_timelock: None,
-            },
-            Terminal::AndV(ref v, ref other) => {
-                let vsat =
-                    Self::satisfy_helper(&v.node, stfr, root_has_sig, leaf_hash, min_fn, thresh_fn);
-                let odissat = Self::dissatisfy_helper(
-                    &other.node,
-                    stfr,
-                    root_has_sig,
-                    leaf_hash,
-                    min_fn,
-                    thresh_fn,
-                );
-                vsat.concatenate_rev(odissat)
-            }
-            Terminal::AndB(ref l, ref r)
-            | Terminal::OrB(ref l, ref r)
-            | Terminal::OrD(ref l, ref r)
-            | Terminal::AndOr(ref l, _, ref r) => {
-                let lnsat = Self::dissatisfy_helper(
-                    &l.node,
-                    stfr,
-                    root_has_sig,
-                    leaf_hash,
-                    min_fn,
-                    thresh_fn,
-                );
-                let rnsat = Self::dissatisfy_helper(
-                    &r.node,
-                    stfr,
-                    root_has_sig,
-                    leaf_hash,
-                    min_fn,
-                    thresh_fn,
-                );
-                lnsat.concatenate_rev(rnsat)
-            }
-            Terminal::OrI(ref l, ref r) => {
-                let lnsat = Self::dissatisfy_helper(
-                    &l.node,
-                    stfr,
-                    root_has_sig,
-                    leaf_hash,
-                    min_fn,
-                    thresh_fn,
-                );
-                let dissat_1 = Satisfaction {
-                    stack: Witness::combine(lnsat.stack, Witness::push_1()),
-                    has_sig: lnsat.has_sig,
-                    relative_timelock: None,
-                    absolute_timelock: None,
                 };
-
-                let rnsat = Self::dissatisfy_helper(
-                    &r.node,
-                    stfr,
-                    root_has_sig,
-                    leaf_hash,
-                    min_fn,
-                    thresh_fn,
-                );
-                let dissat_2 = Satisfaction {
-                    stack: Witness::combine(rnsat.stack, Witness::push_0()),
-                    has_sig: rnsat.has_sig,
+                let nsat = Satisfaction {
+                    stack: Witness::Stack(vec![Placeholder::PushZero; thresh.n()]),
+                    has_sig: false,
                     relative_timelock: None,
                     absolute_timelock: None,
                 };
-
-                // Dissatisfactions don't need to non-malleable. Use minimum_mall always
-                Satisfaction::minimum_mall(dissat_1, dissat_2)
+                (sat, nsat)
             }
-            Terminal::Thresh(ref thresh) => thresh
-                .iter()
-                .map(|s| {
-                    Self::dissatisfy_helper(
-                        &s.node,
-                        stfr,
-                        root_has_sig,
-                        leaf_hash,
-                        min_fn,
-                        thresh_fn,
-                    )
-                })
-                .fold(Satisfaction::empty(), Satisfaction::concatenate_rev),
-            Terminal::Multi(ref thresh) => Satisfaction {
-                stack: Witness::Stack(vec![Placeholder::PushZero; thresh.k() + 1]),
-                has_sig: false,
-                relative_timelock: None,
-                absolute_timelock: None,
-            },
-            Terminal::MultiA(ref thresh) => Satisfaction {
-                stack: Witness::Stack(vec![Placeholder::PushZero; thresh.n()]),
-                has_sig: false,
-                relative_timelock: None,
-                absolute_timelock: None,
-            },
+            Terminal::Ext(ref ext) => (ext.satisfy(stfr), ext.dissatisfy(stfr)),
         }
     }
 
@@ -1771,40 +2708,75 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfaction<Placeholder<Pk>> {
             absolute_timelock: *absolute_timelock,
         })
     }
+
+    /// Create the final witness using the [`Satisfier`] which built the template
+    ///
+    /// The satisfier may still fail to look up an item it claimed to have, e.g. a
+    /// preimage it could not fetch, in which case the witness is unavailable.
+    pub(crate) fn complete<Sat: Satisfier<Pk>>(&self, stfr: &Sat) -> Satisfaction<Vec<u8>> {
+        self.try_completing(stfr).unwrap_or(Satisfaction {
+            stack: Witness::Unavailable,
+            has_sig: self.has_sig,
+            relative_timelock: self.relative_timelock,
+            absolute_timelock: self.absolute_timelock,
+        })
+    }
 }
 
 impl Satisfaction<Vec<u8>> {
     /// Produce a satisfaction non-malleable satisfaction
-    pub(super) fn satisfy<Ctx, Pk, Sat>(
-        term: &Terminal<Pk, Ctx>,
+    pub(super) fn satisfy<Ctx, Ext, Pk, Sat>(
+        ms: &Miniscript<Pk, Ctx, Ext>,
         stfr: &Sat,
         root_has_sig: bool,
         leaf_hash: &TapLeafHash,
     ) -> Self
     where
         Ctx: ScriptContext,
+        Ext: Extension,
         Pk: MiniscriptKey + ToPublicKey,
         Sat: Satisfier<Pk>,
     {
-        Satisfaction::<Placeholder<Pk>>::build_template(term, &stfr, root_has_sig, leaf_hash)
-            .try_completing(stfr)
-            .expect("the same satisfier should manage to complete the template")
+        Satisfaction::<Placeholder<Pk>>::build_template(ms, &stfr, root_has_sig, leaf_hash)
+            .complete(stfr)
     }
 
     /// Produce a satisfaction(possibly malleable)
-    pub(super) fn satisfy_mall<Ctx, Pk, Sat>(
-        term: &Terminal<Pk, Ctx>,
+    pub(super) fn satisfy_mall<Ctx, Ext, Pk, Sat>(
+        ms: &Miniscript<Pk, Ctx, Ext>,
+        stfr: &Sat,
+        root_has_sig: bool,
+        leaf_hash: &TapLeafHash,
+    ) -> Self
+    where
+        Ctx: ScriptContext,
+        Ext: Extension,
+        Pk: MiniscriptKey + ToPublicKey,
+        Sat: Satisfier<Pk>,
+    {
+        Satisfaction::<Placeholder<Pk>>::build_template_mall(ms, &stfr, root_has_sig, leaf_hash)
+            .complete(stfr)
+    }
+
+    /// Produce the canonical dissatisfaction
+    pub(super) fn dissatisfy<Ctx, Ext, Pk, Sat>(
+        ms: &Miniscript<Pk, Ctx, Ext>,
         stfr: &Sat,
         root_has_sig: bool,
         leaf_hash: &TapLeafHash,
     ) -> Self
     where
         Ctx: ScriptContext,
+        Ext: Extension,
         Pk: MiniscriptKey + ToPublicKey,
         Sat: Satisfier<Pk>,
     {
-        Satisfaction::<Placeholder<Pk>>::build_template_mall(term, &stfr, root_has_sig, leaf_hash)
-            .try_completing(stfr)
-            .expect("the same satisfier should manage to complete the template")
+        Satisfaction::<Placeholder<Pk>>::build_dissatisfaction_template(
+            ms,
+            &stfr,
+            root_has_sig,
+            leaf_hash,
+        )
+        .complete(stfr)
     }
 }
@@ -6,10 +6,74 @@
 use core::cmp;
 use core::iter::once;
 
+use bitcoin::sighash::TapSighashType;
+
 use super::ScriptContext;
+use crate::iter::TreeLike;
 use crate::miniscript::context::SigType;
 use crate::prelude::*;
-use crate::{script_num_size, AbsLockTime, MiniscriptKey, RelLockTime, Terminal};
+use crate::{
+    script_num_size, AbsLockTime, Extension, Miniscript, MiniscriptKey, RelLockTime, Terminal,
+};
+
+/// Sizes assumed for signatures when computing the size of satisfactions.
+///
+/// Each size is that of the signature as a witness element, including its
+/// length prefix (segwit) or push opcode (pre-segwit) and its sighash suffix.
+/// By default the worst case is assumed; signers which always produce smaller
+/// signatures can use smaller sizes to get exact fee estimates, e.g.
+///
+/// ```
+/// use miniscript::bitcoin::TapSighashType;
+/// use miniscript::SigSizes;
+///
+/// // Low-R ECDSA signatures, and Schnorr signatures using SIGHASH_DEFAULT.
+/// let sizes = SigSizes::LOW_R.with_tap_sighash_type(TapSighashType::Default);
+/// assert_eq!(sizes, SigSizes { schnorr: 65, ..SigSizes::LOW_R });
+/// assert!(sizes.ecdsa < SigSizes::WORST_CASE.ecdsa);
+/// ```
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub struct SigSizes {
+    /// Size of an ECDSA signature.
+    pub ecdsa: usize,
+    /// Size of a Schnorr signature.
+    pub schnorr: usize,
+}
+
+impl SigSizes {
+    /// The largest possible signatures: 72-byte DER-encoded ECDSA signatures,
+    /// and Schnorr signatures with an explicit sighash type.
+    pub const WORST_CASE: SigSizes = SigSizes { ecdsa: 73, schnorr: 66 };
+
+    /// ECDSA signatures from a signer grinding for a low R value, which are at
+    /// most 71 bytes with their sighash type, and Schnorr signatures with an
+    /// explicit sighash type.
+    pub const LOW_R: SigSizes = SigSizes { ecdsa: 72, schnorr: 66 };
+
+    /// These sizes, with Schnorr signatures using the given sighash type.
+    ///
+    /// Schnorr signatures using `SIGHASH_DEFAULT` have no sighash suffix, so
+    /// they are one byte smaller than those using any other sighash type.
+    pub fn with_tap_sighash_type(self, sighash_type: TapSighashType) -> SigSizes {
+        let schnorr = match sighash_type {
+            TapSighashType::Default => 65,
+            _ => 66,
+        };
+        SigSizes { schnorr, ..self }
+    }
+
+    /// The size of a signature of the given type.
+    pub fn size(&self, sig_type: SigType) -> usize {
+        match sig_type {
+            SigType::Ecdsa => self.ecdsa,
+            SigType::Schnorr => self.schnorr,
+        }
+    }
+}
+
+impl Default for SigSizes {
+    fn default() -> Self { SigSizes::WORST_CASE }
+}
 
 /// Timelock information for satisfaction of a fragment.
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Default, Hash)]
@@ -192,6 +256,10 @@ impl ExtData {
 
     /// Extra properties for the `pk_k` fragment.
     pub fn pk_k<Ctx: ScriptContext>() -> Self {
+        Self::pk_k_with_sig_size::<Ctx>(SigSizes::WORST_CASE.size(Ctx::sig_type()))
+    }
+
+    fn pk_k_with_sig_size<Ctx: ScriptContext>(sig_size: usize) -> Self {
         ExtData {
             pk_cost: match Ctx::sig_type() {
                 SigType::Ecdsa => 34,
@@ -201,10 +269,7 @@ impl ExtData {
             ops: OpLimits::new(0, Some(0), Some(0)),
             stack_elem_count_sat: Some(1),
             stack_elem_count_dissat: Some(1),
-            max_sat_size: match Ctx::sig_type() {
-                SigType::Ecdsa => Some((73, 73)),
-                SigType::Schnorr => Some((66, 66)),
-            },
+            max_sat_size: Some((sig_size, sig_size)),
             max_dissat_size: Some((1, 1)),
             timelock_info: TimelockInfo::default(),
             exec_stack_elem_count_sat: Some(1), // pushes the pk
@@ -215,6 +280,10 @@ impl ExtData {
 
     /// Extra properties for the `pk_h` fragment.
     pub fn pk_h<Ctx: ScriptContext>() -> Self {
+        Self::pk_h_with_sig_size::<Ctx>(SigSizes::WORST_CASE.size(Ctx::sig_type()))
+    }
+
+    fn pk_h_with_sig_size<Ctx: ScriptContext>(sig_size: usize) -> Self {
         ExtData {
             pk_cost: 24,
             has_free_verify: false,
@@ -222,8 +291,8 @@ impl ExtData {
             stack_elem_count_sat: Some(2),
             stack_elem_count_dissat: Some(2),
             max_sat_size: match Ctx::sig_type() {
-                SigType::Ecdsa => Some((34 + 73, 34 + 73)),
-                SigType::Schnorr => Some((66 + 33, 33 + 66)),
+                SigType::Ecdsa => Some((34 + sig_size, 34 + sig_size)),
+                SigType::Schnorr => Some((sig_size + 33, 33 + sig_size)),
             },
             max_dissat_size: match Ctx::sig_type() {
                 SigType::Ecdsa => Some((35, 35)),
@@ -238,6 +307,10 @@ impl ExtData {
 
     /// Extra properties for the `multi` fragment.
     pub fn multi(k: usize, n: usize) -> Self {
+        Self::multi_with_sig_size(k, n, SigSizes::WORST_CASE.ecdsa)
+    }
+
+    fn multi_with_sig_size(k: usize, n: usize, sig_size: usize) -> Self {
         let num_cost = match (k > 16, n > 16) {
             (true, true) => 4,
             (false, true) => 3,
@@ -252,7 +325,7 @@ impl ExtData {
             ops: OpLimits::new(1, Some(n), Some(n)),
             stack_elem_count_sat: Some(k + 1),
             stack_elem_count_dissat: Some(k + 1),
-            max_sat_size: Some((1 + 73 * k, 1 + 73 * k)),
+            max_sat_size: Some((1 + sig_size * k, 1 + sig_size * k)),
             max_dissat_size: Some((1 + k, 1 + k)),
             timelock_info: TimelockInfo::new(),
             exec_stack_elem_count_sat: Some(n), // n pks
@@ -263,6 +336,10 @@ impl ExtData {
 
     /// Extra properties for the `multi_a` fragment.
     pub fn multi_a(k: usize, n: usize) -> Self {
+        Self::multi_a_with_sig_size(k, n, SigSizes::WORST_CASE.schnorr)
+    }
+
+    fn multi_a_with_sig_size(k: usize, n: usize, sig_size: usize) -> Self {
         let num_cost = match (k > 16, n > 16) {
             (true, true) => 4,
             (false, true) => 3,
@@ -276,7 +353,7 @@ impl ExtData {
             ops: OpLimits::new(n, Some(0), Some(0)),
             stack_elem_count_sat: Some(n),
             stack_elem_count_dissat: Some(n),
-            max_sat_size: Some(((n - k) + 66 * k, (n - k) + 66 * k)),
+            max_sat_size: Some(((n - k) + sig_size * k, (n - k) + sig_size * k)),
             max_dissat_size: Some((n, n)),
             timelock_info: TimelockInfo::new(),
             exec_stack_elem_count_sat: Some(2), // the two nums before num equal verify
@@ -924,76 +1001,135 @@ impl ExtData {
 
     /// Compute the type of a fragment assuming all the children of
     /// Miniscript have been computed already.
-    pub fn type_check<Pk, Ctx>(fragment: &Terminal<Pk, Ctx>) -> Self
+    pub fn type_check<Pk, Ctx, Ext>(fragment: &Terminal<Pk, Ctx, Ext>) -> Self
     where
         Ctx: ScriptContext,
         Pk: MiniscriptKey,
+        Ext: Extension,
     {
+        Self::type_check_with_sig_sizes(fragment, &SigSizes::WORST_CASE, |n| child_ext(fragment, n))
+    }
+
+    /// Computes the extra properties of a Miniscript assuming signatures of
+    /// the given sizes, rather than the worst case assumed by the properties
+    /// stored in the Miniscript. Extension fragments keep their own sizes.
+    pub fn for_sig_sizes<Pk, Ctx, Ext>(ms: &Miniscript<Pk, Ctx, Ext>, sig_sizes: &SigSizes) -> Self
+    where
+        Ctx: ScriptContext,
+        Pk: MiniscriptKey,
+        Ext: Extension,
+    {
+        let mut exts: Vec<ExtData> = vec![];
+        for item in ms.post_order_iter() {
+            let ext = Self::type_check_with_sig_sizes(&item.node.node, sig_sizes, |n| {
+                exts[item.child_indices[n]]
+            });
+            exts.push(ext);
+        }
+        exts.pop().unwrap()
+    }
+
+    /// Compute the type of a fragment, given the properties of its children
+    /// and assuming signatures of the given sizes.
+    fn type_check_with_sig_sizes<Pk, Ctx, Ext, C>(
+        fragment: &Terminal<Pk, Ctx, Ext>,
+        sig_sizes: &SigSizes,
+        child: C,
+    ) -> Self
+    where
+        Ctx: ScriptContext,
+        Pk: MiniscriptKey,
+        Ext: Extension,
+        C: Fn(usize) -> Self,
+    {
+        let sig_size = sig_sizes.size(Ctx::sig_type());
         let ret = match *fragment {
             Terminal::True => Self::TRUE,
             Terminal::False => Self::FALSE,
-            Terminal::PkK(..) => Self::pk_k::<Ctx>(),
-            Terminal::PkH(..) | Terminal::RawPkH(..) => Self::pk_h::<Ctx>(),
-            Terminal::Multi(ref thresh) => Self::multi(thresh.k(), thresh.n()),
-            Terminal::MultiA(ref thresh) => Self::multi_a(thresh.k(), thresh.n()),
+            Terminal::PkK(..) => Self::pk_k_with_sig_size::<Ctx>(sig_size),
+            Terminal::PkH(..) | Terminal::RawPkH(..) => Self::pk_h_with_sig_size::<Ctx>(sig_size),
+            Terminal::Multi(ref thresh) => {
+                Self::multi_with_sig_size(thresh.k(), thresh.n(), sig_sizes.ecdsa)
+            }
+            Terminal::MultiA(ref thresh) => {
+                Self::multi_a_with_sig_size(thresh.k(), thresh.n(), sig_sizes.schnorr)
+            }
+            Terminal::Ext(ref ext) => ext.extra_props(),
             Terminal::After(t) => Self::after(t),
             Terminal::Older(t) => Self::older(t),
             Terminal::Sha256(..) => Self::sha256(),
             Terminal::Hash256(..) => Self::hash256(),
             Terminal::Ripemd160(..) => Self::ripemd160(),
             Terminal::Hash160(..) => Self::hash160(),
-            Terminal::Alt(ref sub) => Self::cast_alt(sub.ext),
-            Terminal::Swap(ref sub) => Self::cast_swap(sub.ext),
-            Terminal::Check(ref sub) => Self::cast_check(sub.ext),
-            Terminal::DupIf(ref sub) => Self::cast_dupif(sub.ext),
-            Terminal::Verify(ref sub) => Self::cast_verify(sub.ext),
-            Terminal::NonZero(ref sub) => Self::cast_nonzero(sub.ext),
-            Terminal::ZeroNotEqual(ref sub) => Self::cast_zeronotequal(sub.ext),
-            Terminal::AndB(ref l, ref r) => {
-                let ltype = l.ext;
-                let rtype = r.ext;
-                Self::and_b(ltype, rtype)
-            }
-            Terminal::AndV(ref l, ref r) => {
-                let ltype = l.ext;
-                let rtype = r.ext;
-                Self::and_v(ltype, rtype)
-            }
-            Terminal::OrB(ref l, ref r) => {
-                let ltype = l.ext;
-                let rtype = r.ext;
-                Self::or_b(ltype, rtype)
-            }
-            Terminal::OrD(ref l, ref r) => {
-                let ltype = l.ext;
-                let rtype = r.ext;
-                Self::or_d(ltype, rtype)
-            }
-            Terminal::OrC(ref l, ref r) => {
-                let ltype = l.ext;
-                let rtype = r.ext;
-                Self::or_c(ltype, rtype)
-            }
-            Terminal::OrI(ref l, ref r) => {
-                let ltype = l.ext;
-                let rtype = r.ext;
-                Self::or_i(ltype, rtype)
-            }
-            Terminal::AndOr(ref a, ref b, ref c) => {
-                let atype = a.ext;
-                let btype = b.ext;
-                let ctype = c.ext;
-                Self::and_or(atype, btype, ctype)
-            }
-            Terminal::Thresh(ref thresh) => {
-                Self::threshold(thresh.k(), thresh.n(), |n| thresh.data()[n].ext)
+            Terminal::Alt(..) => Self::cast_alt(child(0)),
+            Terminal::Swap(..) => Self::cast_swap(child(0)),
+            Terminal::Check(..) => Self::cast_check(child(0)),
+            Terminal::DupIf(..) => Self::cast_dupif(child(0)),
+            Terminal::Verify(..) => Self::cast_verify(child(0)),
+            Terminal::NonZero(..) => Self::cast_nonzero(child(0)),
+            Terminal::ZeroNotEqual(..) => Self::cast_zeronotequal(child(0)),
+            Terminal::AndB(..) => Self::and_b(child(0), child(1)),
+            Terminal::AndV(..) => Self::and_v(child(0), child(1)),
+            Terminal::OrB(..) => Self::or_b(child(0), child(1)),
+            Terminal::OrD(..) => Self::or_d(child(0), child(1)),
+            Terminal::OrC(..) => Self::or_c(child(0), child(1)),
+            Terminal::OrI(..) => Self::or_i(child(0), child(1)),
+            Terminal::AndOr(..) => Self::and_or(child(0), child(1), child(2)),
+            Terminal::Thresh(ref thresh) => Self::threshold(thresh.k(), thresh.n(), child),
+        };
+        // BIP 118 keys differ in size from ordinary x-only keys
+        #[cfg(feature = "apo")]
+        let ret = match *fragment {
+            Terminal::PkK(ref pk) if Ctx::sig_type() == SigType::Schnorr => {
+                ExtData { pk_cost: Ctx::pk_len(pk), ..ret }
             }
+            Terminal::MultiA(ref thresh) if Ctx::sig_type() == SigType::Schnorr => ExtData {
+                pk_cost: ret.pk_cost - 33 * thresh.n()
+                    + thresh.iter().map(|pk| Ctx::pk_len(pk)).sum::<usize>(),
+                ..ret
+            },
+            _ => ret,
         };
         ret.sanity_checks();
         ret
     }
 }
 
+/// The extra properties of the `n`th child of a fragment.
+fn child_ext<Pk, Ctx, Ext>(fragment: &Terminal<Pk, Ctx, Ext>, n: usize) -> ExtData
+where
+    Ctx: ScriptContext,
+    Pk: MiniscriptKey,
+    Ext: Extension,
+{
+    match (fragment, n) {
+        (Terminal::Alt(ref sub), 0)
+        | (Terminal::Swap(ref sub), 0)
+        | (Terminal::Check(ref sub), 0)
+        | (Terminal::DupIf(ref sub), 0)
+        | (Terminal::Verify(ref sub), 0)
+        | (Terminal::NonZero(ref sub), 0)
+        | (Terminal::ZeroNotEqual(ref sub), 0)
+        | (Terminal::AndB(ref sub, _), 0)
+        | (Terminal::AndV(ref sub, _), 0)
+        | (Terminal::OrB(ref sub, _), 0)
+        | (Terminal::OrD(ref sub, _), 0)
+        | (Terminal::OrC(ref sub, _), 0)
+        | (Terminal::OrI(ref sub, _), 0)
+        | (Terminal::AndOr(ref sub, _, _), 0)
+        | (Terminal::AndB(_, ref sub), 1)
+        | (Terminal::AndV(_, ref sub), 1)
+        | (Terminal::OrB(_, ref sub), 1)
+        | (Terminal::OrD(_, ref sub), 1)
+        | (Terminal::OrC(_, ref sub), 1)
+        | (Terminal::OrI(_, ref sub), 1)
+        | (Terminal::AndOr(_, ref sub, _), 1)
+        | (Terminal::AndOr(_, _, ref sub), 2) => sub.ext,
+        (Terminal::Thresh(ref thresh), n) => thresh.data()[n].ext,
+        _ => unreachable!("fragment has no child {}", n),
+    }
+}
+
 // Function to pass to sort_by. Sort by (satisfaction cost - dissatisfaction cost).
 //
 // We sort by (satisfaction cost - dissatisfaction cost) to make a worst-case (the most
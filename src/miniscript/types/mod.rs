@@ -10,16 +10,22 @@ pub mod extra_props;
 pub mod malleability;
 
 #[cfg(all(not(feature = "std"), not(test)))]
-use alloc::string::{String, ToString};
+use alloc::{
+    borrow::ToOwned,
+    string::{String, ToString},
+    vec::Vec,
+};
 use core::fmt;
 #[cfg(feature = "std")]
 use std::error;
 
 pub use self::correctness::{Base, Correctness, Input};
-pub use self::extra_props::ExtData;
+pub use self::extra_props::{ExtData, SigSizes};
 pub use self::malleability::{Dissat, Malleability};
+use super::limits::{MAX_PUBKEYS_IN_CHECKSIGADD, MAX_PUBKEYS_PER_MULTISIG};
 use super::ScriptContext;
-use crate::{MiniscriptKey, Terminal};
+use crate::expression::TreeIterItem;
+use crate::{Extension, MiniscriptKey, ParseThresholdError, ParseTreeError, Terminal};
 
 /// Detailed type of a typechecker error
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
@@ -59,6 +65,28 @@ pub enum ErrorKind {
     ThresholdNonUnit(usize),
 }
 
+impl ErrorKind {
+    /// The index of the child of the fragment which caused the error, if the
+    /// error is attributable to a single child.
+    ///
+    /// The child of a wrapper is the fragment it wraps.
+    pub fn child(&self) -> Option<usize> {
+        match *self {
+            ErrorKind::NonZeroDupIf
+            | ErrorKind::SwapNonOne
+            | ErrorKind::NonZeroZero
+            | ErrorKind::ChildBase1(_)
+            | ErrorKind::LeftNotDissatisfiable
+            | ErrorKind::LeftNotUnit => Some(0),
+            ErrorKind::RightNotDissatisfiable => Some(1),
+            ErrorKind::ThresholdBase(idx, _)
+            | ErrorKind::ThresholdDissat(idx)
+            | ErrorKind::ThresholdNonUnit(idx) => Some(idx),
+            ErrorKind::ChildBase2(..) | ErrorKind::ChildBase3(..) => None,
+        }
+    }
+}
+
 /// Error type for typechecking
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
 pub struct Error {
@@ -148,6 +176,60 @@ impl error::Error for Error {
     fn cause(&self) -> Option<&dyn error::Error> { None }
 }
 
+/// Error returned by [`Type::type_check_expression`]
+#[derive(Debug, PartialEq, Eq)]
+pub enum ExpressionError {
+    /// The expression is not a well-formed Miniscript expression.
+    Tree(ParseTreeError),
+    /// A threshold fragment was malformed.
+    Threshold(ParseThresholdError),
+    /// A fragment had an unknown wrapper.
+    UnknownWrapper(char),
+    /// A fragment failed to typecheck.
+    Type {
+        /// The byte-index into the string of the fragment.
+        pos: usize,
+        /// The byte-index into the string of the child of the fragment which
+        /// caused the error, if the error is attributable to a single child.
+        child_pos: Option<usize>,
+        /// The typechecking error.
+        error: Error,
+    },
+}
+
+impl From<ParseTreeError> for ExpressionError {
+    fn from(e: ParseTreeError) -> Self { ExpressionError::Tree(e) }
+}
+
+impl From<ParseThresholdError> for ExpressionError {
+    fn from(e: ParseThresholdError) -> Self { ExpressionError::Threshold(e) }
+}
+
+impl fmt::Display for ExpressionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ExpressionError::Tree(ref e) => e.fmt(f),
+            ExpressionError::Threshold(ref e) => e.fmt(f),
+            ExpressionError::UnknownWrapper(ch) => write!(f, "unknown wrapper {}:", ch),
+            ExpressionError::Type { pos, ref error, .. } => {
+                write!(f, "at position {}: {}", pos, error)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for ExpressionError {
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            ExpressionError::Tree(ref e) => Some(e),
+            ExpressionError::Threshold(ref e) => Some(e),
+            ExpressionError::UnknownWrapper(_) => None,
+            ExpressionError::Type { ref error, .. } => Some(error),
+        }
+    }
+}
+
 /// Structure representing the type of a Miniscript fragment, including all
 /// properties relevant to the main codebase
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
@@ -444,10 +526,11 @@ impl Type {
 impl Type {
     /// Compute the type of a fragment assuming all the children of
     /// Miniscript have been computed already.
-    pub fn type_check<Pk, Ctx>(fragment: &Terminal<Pk, Ctx>) -> Result<Self, Error>
+    pub fn type_check<Pk, Ctx, Ext>(fragment: &Terminal<Pk, Ctx, Ext>) -> Result<Self, Error>
     where
         Pk: MiniscriptKey,
         Ctx: ScriptContext,
+        Ext: Extension,
     {
         let wrap_err = |result: Result<Self, ErrorKind>| {
             result.map_err(|kind| Error { fragment_string: fragment.to_string(), error: kind })
@@ -460,6 +543,7 @@ impl Type {
             Terminal::PkH(..) | Terminal::RawPkH(..) => Ok(Self::pk_h()),
             Terminal::Multi(..) => Ok(Self::multi()),
             Terminal::MultiA(..) => Ok(Self::multi_a()),
+            Terminal::Ext(ref ext) => Ok(ext.type_check()),
             Terminal::After(_) => Ok(Self::time()),
             Terminal::Older(_) => Ok(Self::time()),
             Terminal::Sha256(..) => Ok(Self::hash()),
@@ -520,3 +604,175 @@ impl Type {
         ret
     }
 }
+
+impl Type {
+    /// Compute the type of a Miniscript expression, without parsing its keys,
+    /// hashes or timelocks and without constructing a [`crate::Miniscript`].
+    ///
+    /// The expression may therefore contain placeholders in place of keys and
+    /// hashes. On failure, the error locates the fragment which failed to
+    /// typecheck and, where possible, the child responsible, which makes this
+    /// suitable for fragment-level diagnostics in editors and linters. Types do
+    /// not depend on the script context. Extension fragments are not recognized.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use miniscript::expression::Tree;
+    /// use miniscript::miniscript::types::{Base, ErrorKind, ExpressionError, Type};
+    ///
+    /// let tree = Tree::from_str("or_b(pk(A),s:pk(B))").unwrap();
+    /// let ty = Type::type_check_expression(tree.root()).unwrap();
+    /// assert_eq!(ty.corr.base, Base::B);
+    ///
+    /// // `after` cannot be dissatisfied, so it cannot be the left child of `or_d`.
+    /// let tree = Tree::from_str("or_d(after(10),pk(A))").unwrap();
+    /// match Type::type_check_expression(tree.root()) {
+    ///     Err(ExpressionError::Type { pos, child_pos, error }) => {
+    ///         assert_eq!((pos, child_pos), (0, Some(5)));
+    ///         assert_eq!(error.error, ErrorKind::LeftNotDissatisfiable);
+    ///     }
+    ///     _ => unreachable!(),
+    /// }
+    /// ```
+    pub fn type_check_expression(root: TreeIterItem) -> Result<Self, ExpressionError> {
+        #[allow(clippy::type_complexity)]
+        fn binary(
+            node: TreeIterItem,
+            stack: &mut Vec<(Type, usize)>,
+            description: &'static str,
+            typefn: fn(Type, Type) -> Result<Type, ErrorKind>,
+        ) -> Result<(Vec<(Type, usize)>, Result<Type, ErrorKind>), ParseTreeError> {
+            node.verify_n_children(description, 2..=2)?;
+            let children = vec![stack.pop().unwrap(), stack.pop().unwrap()];
+            let ty = typefn(children[0].0, children[1].0);
+            Ok((children, ty))
+        }
+
+        root.verify_no_curly_braces()?;
+
+        // Types of the fragments which have not yet been consumed by their
+        // parents, with their positions in the string.
+        let mut stack: Vec<(Self, usize)> = Vec::with_capacity(128);
+        for (n, node) in root.pre_order_iter().enumerate().rev() {
+            if super::is_terminal_value(n, node)? {
+                continue;
+            }
+
+            let (frag_wrap, frag_name) = node.name_separated(':')?;
+            let pos = node.name_pos();
+            let terminal = |description| -> Result<(), ParseTreeError> {
+                node.verify_n_children(description, 1..=1)?;
+                node.first_child()
+                    .unwrap()
+                    .verify_n_children(description, 0..=0)
+            };
+
+            let (children, ty) = match frag_name {
+                "pk_k" | "pk" | "pk_h" | "pkh" | "expr_raw_pkh" => {
+                    terminal("public key")?;
+                    let ty = match frag_name {
+                        "pk_k" => Ok(Self::pk_k()),
+                        "pk" => Self::pk_k().cast_check(),
+                        "pkh" => Self::pk_h().cast_check(),
+                        _ => Ok(Self::pk_h()),
+                    };
+                    (vec![], ty)
+                }
+                "after" | "older" => {
+                    terminal("timelock")?;
+                    (vec![], Ok(Self::time()))
+                }
+                "sha256" | "hash256" | "ripemd160" | "hash160" => {
+                    terminal("hash")?;
+                    (vec![], Ok(Self::hash()))
+                }
+                "1" => {
+                    node.verify_n_children("1", 0..=0)?;
+                    (vec![], Ok(Self::TRUE))
+                }
+                "0" => {
+                    node.verify_n_children("0", 0..=0)?;
+                    (vec![], Ok(Self::FALSE))
+                }
+                "and_v" => binary(node, &mut stack, "and_v", Self::and_v)?,
+                "and_b" => binary(node, &mut stack, "and_b", Self::and_b)?,
+                "and_n" => {
+                    binary(node, &mut stack, "and_n", |x, y| Self::and_or(x, y, Self::FALSE))?
+                }
+                "or_b" => binary(node, &mut stack, "or_b", Self::or_b)?,
+                "or_d" => binary(node, &mut stack, "or_d", Self::or_d)?,
+                "or_c" => binary(node, &mut stack, "or_c", Self::or_c)?,
+                "or_i" => binary(node, &mut stack, "or_i", Self::or_i)?,
+                "andor" => {
+                    node.verify_n_children("andor", 3..=3)?;
+                    let children = vec![
+                        stack.pop().unwrap(),
+                        stack.pop().unwrap(),
+                        stack.pop().unwrap(),
+                    ];
+                    let ty = Self::and_or(children[0].0, children[1].0, children[2].0);
+                    (children, ty)
+                }
+                "thresh" => {
+                    let thresh = node.verify_threshold::<0, _, _, ExpressionError>(|_| {
+                        Ok(stack.pop().unwrap())
+                    })?;
+                    let ty = Self::threshold(thresh.k(), thresh.iter().map(|child| &child.0));
+                    (thresh.into_data(), ty)
+                }
+                "multi" => {
+                    node.verify_threshold::<MAX_PUBKEYS_PER_MULTISIG, _, _, ExpressionError>(
+                        |_| Ok(()),
+                    )?;
+                    (vec![], Ok(Self::multi()))
+                }
+                "multi_a" => {
+                    node.verify_threshold::<MAX_PUBKEYS_IN_CHECKSIGADD, _, _, ExpressionError>(
+                        |_| Ok(()),
+                    )?;
+                    (vec![], Ok(Self::multi_a()))
+                }
+                x => return Err(ParseTreeError::UnknownName { name: x.to_owned() }.into()),
+            };
+            let type_err = |error, child_pos| ExpressionError::Type {
+                pos,
+                child_pos,
+                error: Error { fragment_string: node.name().to_owned(), error },
+            };
+            let mut ty = ty.map_err(|kind: ErrorKind| {
+                let child_pos = kind.child().and_then(|i| children.get(i)).map(|c| c.1);
+                type_err(kind, child_pos)
+            })?;
+
+            if let Some(frag_wrap) = frag_wrap {
+                // ":node()" is not valid syntax
+                if frag_wrap.is_empty() {
+                    return Err(ParseTreeError::UnknownName { name: node.name().to_owned() }.into());
+                }
+                for ch in frag_wrap.chars().rev() {
+                    let cast = match ch {
+                        'a' => ty.cast_alt(),
+                        's' => ty.cast_swap(),
+                        'c' => ty.cast_check(),
+                        'd' => ty.cast_dupif(),
+                        'v' => ty.cast_verify(),
+                        'j' => ty.cast_nonzero(),
+                        'n' => ty.cast_zeronotequal(),
+                        't' => ty.cast_true(),
+                        'u' => ty.cast_unlikely(),
+                        'l' => ty.cast_likely(),
+                        x => return Err(ExpressionError::UnknownWrapper(x)),
+                    };
+                    ty = cast.map_err(|kind| type_err(kind, Some(pos)))?;
+                }
+            }
+
+            ty.sanity_checks();
+            stack.push((ty, pos));
+        }
+
+        assert_eq!(stack.len(), 1);
+        Ok(stack.pop().unwrap().0)
+    }
+}
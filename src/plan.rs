@@ -16,20 +16,32 @@
 //! Once you've obtained signatures, hash pre-images etc required by the plan, it can create a
 //! witness/script_sig for the input.
 
+use core::fmt;
 use core::iter::FromIterator;
 
 use bitcoin::hashes::{hash160, ripemd160, sha256};
 use bitcoin::key::XOnlyPublicKey;
+use bitcoin::secp256k1::PublicKey;
 use bitcoin::script::PushBytesBuf;
-use bitcoin::taproot::{ControlBlock, LeafVersion, TapLeafHash};
-use bitcoin::{absolute, bip32, psbt, relative, ScriptBuf, WitnessVersion};
-
-use crate::descriptor::{self, Descriptor, DescriptorType, KeyMap};
+use bitcoin::sighash::TapSighashType;
+use bitcoin::taproot::{
+    ControlBlock, LeafVersion, TapLeafHash, TapNodeHash, TaprootMerkleBranch,
+};
+use bitcoin::{
+    absolute, bip32, psbt, relative, Amount, FeeRate, ScriptBuf, Sequence, SignedAmount, Weight,
+    WitnessVersion,
+};
+
+use crate::descriptor::{self, Descriptor, DescriptorType, KeyMap, SatisfactionWeight};
 use crate::miniscript::hash256;
+use crate::miniscript::limits::SigopsBudget;
 use crate::miniscript::satisfy::{Placeholder, Satisfier, SchnorrSigType};
 use crate::prelude::*;
-use crate::util::witness_size;
-use crate::{DefiniteDescriptorKey, DescriptorPublicKey, Error, MiniscriptKey, ToPublicKey};
+use crate::util::{varint_len, witness_size, ItemSize};
+use crate::{
+    DefiniteDescriptorKey, DescriptorPublicKey, Error, ForEachKey, MiniscriptKey, SigType,
+    ToPublicKey,
+};
 
 /// Trait describing a present/missing lookup table for constructing witness templates
 ///
@@ -53,6 +65,26 @@ pub trait AssetProvider<Pk: MiniscriptKey> {
     /// and return its size
     fn provider_lookup_tap_leaf_script_sig(&self, _: &Pk, _: &TapLeafHash) -> Option<usize> { None }
 
+    /// Given a public key, look up an ECDSA adaptor signature with that key and return its
+    /// adaptor point
+    ///
+    /// This is not implemented for satisfiers, as the signature cannot be completed until it is
+    /// adapted.
+    fn provider_lookup_ecdsa_adaptor_sig(&self, _: &Pk) -> Option<PublicKey> { None }
+
+    /// Given a public key and a associated leaf hash, look up a schnorr adaptor signature with
+    /// that key and return its adaptor point and the size of the adapted signature
+    ///
+    /// This is not implemented for satisfiers, as the signature cannot be completed until it is
+    /// adapted.
+    fn provider_lookup_tap_leaf_script_adaptor_sig(
+        &self,
+        _: &Pk,
+        _: &TapLeafHash,
+    ) -> Option<(PublicKey, usize)> {
+        None
+    }
+
     /// Obtain a reference to the control block for a ver and script
     fn provider_lookup_tap_control_block_map(
         &self,
@@ -110,6 +142,24 @@ pub trait AssetProvider<Pk: MiniscriptKey> {
 
     /// Assert whether an absolute locktime is satisfied
     fn check_after(&self, _: absolute::LockTime) -> bool { false }
+
+    /// Whether a plan built from these assets may be used
+    ///
+    /// This allows restricting how the assets are combined, e.g. how many of them a plan
+    /// uses. [`Descriptor::plan`] and [`Descriptor::plans`] skip the plans which are not
+    /// accepted, looking for the cheapest accepted one instead.
+    fn provider_accepts_plan(&self, _: &Plan) -> bool { true }
+
+    /// Given a public key, look up how much its signature should be preferred
+    ///
+    /// See [`Satisfier::lookup_key_priority`].
+    fn provider_lookup_key_priority(&self, _: &Pk) -> Option<i32> { None }
+
+    /// How many extra witness bytes the key priorities may cost
+    fn provider_preference_tolerance(&self) -> usize { 0 }
+
+    /// The sighash type of the taproot signatures, if they all use one
+    fn provider_lookup_tap_sighash_type(&self) -> Option<TapSighashType> { None }
 }
 
 /// Wrapper around [`Assets`] that logs every query and value returned
@@ -133,6 +183,8 @@ impl AssetProvider<DefiniteDescriptorKey> for LoggerAssetProvider<'_> {
     impl_log_method!(provider_lookup_ecdsa_sig, pk: &DefiniteDescriptorKey, -> bool);
     impl_log_method!(provider_lookup_tap_key_spend_sig, pk: &DefiniteDescriptorKey, -> Option<usize>);
     impl_log_method!(provider_lookup_tap_leaf_script_sig, pk: &DefiniteDescriptorKey, leaf_hash: &TapLeafHash, -> Option<usize>);
+    impl_log_method!(provider_lookup_ecdsa_adaptor_sig, pk: &DefiniteDescriptorKey, -> Option<PublicKey>);
+    impl_log_method!(provider_lookup_tap_leaf_script_adaptor_sig, pk: &DefiniteDescriptorKey, leaf_hash: &TapLeafHash, -> Option<(PublicKey, usize)>);
     impl_log_method!(provider_lookup_tap_control_block_map, -> Option<&BTreeMap<ControlBlock, (bitcoin::ScriptBuf, LeafVersion)>>);
     impl_log_method!(provider_lookup_raw_pkh_pk, hash: &hash160::Hash, -> Option<bitcoin::PublicKey>);
     impl_log_method!(provider_lookup_raw_pkh_x_only_pk, hash: &hash160::Hash, -> Option<XOnlyPublicKey>);
@@ -144,6 +196,10 @@ impl AssetProvider<DefiniteDescriptorKey> for LoggerAssetProvider<'_> {
     impl_log_method!(provider_lookup_hash160, hash: &hash160::Hash, -> bool);
     impl_log_method!(check_older, s: relative::LockTime, -> bool);
     impl_log_method!(check_after, t: absolute::LockTime, -> bool);
+    impl_log_method!(provider_accepts_plan, plan: &Plan, -> bool);
+    impl_log_method!(provider_lookup_key_priority, pk: &DefiniteDescriptorKey, -> Option<i32>);
+    impl_log_method!(provider_preference_tolerance, -> usize);
+    impl_log_method!(provider_lookup_tap_sighash_type, -> Option<TapSighashType>);
 }
 
 impl<T, Pk> AssetProvider<Pk> for T
@@ -164,6 +220,12 @@ where
         pk: &Pk,
         leaf_hash: &TapLeafHash,
     ) -> Option<usize> {
+        #[cfg(feature = "apo")]
+        if pk.apo_kind().is_some() {
+            if let Some(sig) = Satisfier::lookup_apo_sig(self, pk, leaf_hash) {
+                return Some(sig.to_vec().len());
+            }
+        }
         Satisfier::lookup_tap_leaf_script_sig(self, pk, leaf_hash).map(|s| s.to_vec().len())
     }
 
@@ -197,24 +259,34 @@ where
     }
 
     fn provider_lookup_sha256(&self, hash: &Pk::Sha256) -> bool {
-        Satisfier::lookup_sha256(self, hash).is_some()
+        Satisfier::has_sha256_preimage(self, hash)
     }
 
     fn provider_lookup_hash256(&self, hash: &Pk::Hash256) -> bool {
-        Satisfier::lookup_hash256(self, hash).is_some()
+        Satisfier::has_hash256_preimage(self, hash)
     }
 
     fn provider_lookup_ripemd160(&self, hash: &Pk::Ripemd160) -> bool {
-        Satisfier::lookup_ripemd160(self, hash).is_some()
+        Satisfier::has_ripemd160_preimage(self, hash)
     }
 
     fn provider_lookup_hash160(&self, hash: &Pk::Hash160) -> bool {
-        Satisfier::lookup_hash160(self, hash).is_some()
+        Satisfier::has_hash160_preimage(self, hash)
     }
 
     fn check_older(&self, s: relative::LockTime) -> bool { Satisfier::check_older(self, s) }
 
     fn check_after(&self, l: absolute::LockTime) -> bool { Satisfier::check_after(self, l) }
+
+    fn provider_lookup_key_priority(&self, pk: &Pk) -> Option<i32> {
+        Satisfier::lookup_key_priority(self, pk)
+    }
+
+    fn provider_preference_tolerance(&self) -> usize { Satisfier::preference_tolerance(self) }
+
+    fn provider_lookup_tap_sighash_type(&self) -> Option<TapSighashType> {
+        Satisfier::lookup_tap_sighash_type(self)
+    }
 }
 
 /// Representation of a particular spending path on a descriptor.
@@ -223,7 +295,14 @@ where
 /// and the timelocks needed for satisfying the plan.
 /// Calling `plan` on a Descriptor will return this structure,
 /// containing the cheapest spending path possible (considering the `Assets` given)
-#[derive(Debug, Clone)]
+///
+/// With the `serde` feature, plans can be shipped to another process, e.g. an
+/// offline signer, alongside a PSBT. They serialize to a struct with the fields
+/// `template`, `absolute_timelock`, `relative_timelock` and `descriptor`, where
+/// the descriptor and its keys are strings and placeholders are externally tagged
+/// by their variant name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Plan {
     /// This plan's witness template
     pub(crate) template: Vec<Placeholder<DefiniteDescriptorKey>>,
@@ -244,9 +323,47 @@ impl Plan {
         self.descriptor.desc_type().segwit_version()
     }
 
-    /// The weight, in witness units, needed for satisfying this plan (includes both
-    /// the script sig weight and the witness weight)
-    pub fn satisfaction_weight(&self) -> usize { self.witness_size() + self.scriptsig_size() * 4 }
+    /// The weight needed for satisfying this plan (includes both the script sig
+    /// weight and the witness weight)
+    pub fn satisfaction_weight(&self) -> Weight { self.satisfaction_weight_parts().total() }
+
+    /// The weights of the script sig and of the witness needed for satisfying
+    /// this plan
+    pub fn satisfaction_weight_parts(&self) -> SatisfactionWeight {
+        SatisfactionWeight::from_scriptsig_size(self.scriptsig_size())
+            + SatisfactionWeight::from_witness_size(self.witness_size())
+    }
+
+    /// The weight of a transaction input spending this plan
+    ///
+    /// This is the [`satisfaction weight`](Self::satisfaction_weight) plus the weight of the
+    /// outpoint and sequence. Plans spending segwit outputs also include the two weight units of
+    /// the segwit marker and flag, which the transaction carries once for all of its inputs.
+    pub fn input_weight(&self) -> Weight {
+        // outpoint (32 + 4) + sequence (4)
+        let base = Weight::from_non_witness_data_size(32 + 4 + 4);
+        let marker = match self.witness_version() {
+            Some(_) => Weight::from_wu(2),
+            None => Weight::ZERO,
+        };
+        base + self.satisfaction_weight() + marker
+    }
+
+    /// The fee a transaction input spending this plan contributes at `fee_rate`
+    ///
+    /// Returns `None` if the fee overflows.
+    pub fn input_fee(&self, fee_rate: FeeRate) -> Option<Amount> {
+        fee_rate.fee_wu(self.input_weight())
+    }
+
+    /// The value of an output of `value` spent with this plan, net of its
+    /// [`input fee`](Self::input_fee) at `fee_rate`
+    ///
+    /// Returns `None` if the computation overflows.
+    pub fn effective_value(&self, fee_rate: FeeRate, value: Amount) -> Option<SignedAmount> {
+        let fee = self.input_fee(fee_rate)?.to_signed().ok()?;
+        value.to_signed().ok()?.checked_sub(fee)
+    }
 
     /// The size in bytes of the script sig that satisfies this plan
     pub fn scriptsig_size(&self) -> usize {
@@ -255,10 +372,14 @@ impl Plan {
             (None, _) => witness_size(self.template.as_ref()),
             // Taproot doesn't have a "wrapped" version (scriptSig len (1))
             (Some(WitnessVersion::V1), _) => 1,
-            // scriptSig len (1) + OP_0 (1) + OP_PUSHBYTES_20 (1) + <pk hash> (20)
-            (_, DescriptorType::ShWpkh) => 1 + 1 + 1 + 20,
-            // scriptSig len (1) + OP_0 (1) + OP_PUSHBYTES_32 (1) + <script hash> (32)
-            (_, DescriptorType::ShWsh) | (_, DescriptorType::ShWshSortedMulti) => 1 + 1 + 1 + 32,
+            // scriptSig len (1) + OP_PUSHBYTES_22 (1) + OP_0 (1) + OP_PUSHBYTES_20 (1)
+            // + <pk hash> (20)
+            (_, DescriptorType::ShWpkh) => 1 + 1 + 1 + 1 + 20,
+            // scriptSig len (1) + OP_PUSHBYTES_34 (1) + OP_0 (1) + OP_PUSHBYTES_32 (1)
+            // + <script hash> (32)
+            (_, DescriptorType::ShWsh) | (_, DescriptorType::ShWshSortedMulti) => {
+                1 + 1 + 1 + 1 + 32
+            }
             // Native Segwit v0 (scriptSig len (1))
             _ => 1,
         }
@@ -274,13 +395,50 @@ impl Plan {
         }
     }
 
+    /// The usage of the Tapscript sigops budget by this plan, if it spends a
+    /// Taproot script path
+    pub fn sigops_budget(&self) -> Option<SigopsBudget> {
+        if !self
+            .template
+            .iter()
+            .any(|item| matches!(item, Placeholder::TapScript(_)))
+        {
+            return None;
+        }
+        let sigops = self
+            .template
+            .iter()
+            .filter(|item| item.is_script_spend_sig())
+            .count();
+        let witness_size = varint_len(self.template.len())
+            + self.template.iter().map(|item| item.size()).sum::<usize>();
+        Some(SigopsBudget::new(sigops, witness_size))
+    }
+
+    /// The taproot leaf this plan spends, if it spends a Taproot script path
+    pub fn tap_leaf_spend(&self) -> Option<TapLeafSpend> {
+        let script = self.template.iter().find_map(|item| match item {
+            Placeholder::TapScript(script) => Some(script),
+            _ => None,
+        })?;
+        let control_block = self.template.iter().find_map(|item| match item {
+            Placeholder::TapControlBlock(cb) => Some(cb),
+            _ => None,
+        })?;
+        let leaf_version = control_block.leaf_version;
+        Some(TapLeafSpend {
+            script: script.clone(),
+            leaf_version,
+            leaf_hash: TapLeafHash::from_script(script, leaf_version),
+            control_block: control_block.clone(),
+        })
+    }
+
     /// Try creating the final script_sig and witness using a [`Satisfier`]
     pub fn satisfy<Sat: Satisfier<DefiniteDescriptorKey>>(
         &self,
         stfr: &Sat,
     ) -> Result<(Vec<Vec<u8>>, ScriptBuf), Error> {
-        use bitcoin::blockdata::script::Builder;
-
         let stack = self
             .template
             .iter()
@@ -288,7 +446,25 @@ impl Plan {
             .collect::<Option<Vec<Vec<u8>>>>()
             .ok_or(Error::CouldNotSatisfy)?;
 
-        Ok(match self.descriptor.desc_type() {
+        Ok(self.witness_and_script_sig(stack))
+    }
+
+    /// Create a witness and script_sig of the size of those satisfying this plan
+    ///
+    /// Signatures and hash preimages are replaced by dummy bytes, see
+    /// [`Placeholder::dummy_bytes`]. The result is not a valid spend, but a transaction using
+    /// it weighs as much as the signed one, so it can be used to estimate fees or test
+    /// transaction sizes before any signer is contacted.
+    pub fn dummy_satisfaction(&self) -> (Vec<Vec<u8>>, ScriptBuf) {
+        let stack = self.template.iter().map(Placeholder::dummy_bytes).collect();
+        self.witness_and_script_sig(stack)
+    }
+
+    /// Place the satisfying stack in the witness or script_sig, as the descriptor requires
+    fn witness_and_script_sig(&self, stack: Vec<Vec<u8>>) -> (Vec<Vec<u8>>, ScriptBuf) {
+        use bitcoin::blockdata::script::Builder;
+
+        match self.descriptor.desc_type() {
             DescriptorType::Bare
             | DescriptorType::Sh
             | DescriptorType::Pkh
@@ -310,7 +486,10 @@ impl Plan {
             DescriptorType::ShWsh | DescriptorType::ShWshSortedMulti | DescriptorType::ShWpkh => {
                 (stack, self.descriptor.unsigned_script_sig())
             }
-        })
+            DescriptorType::Addr | DescriptorType::Raw => {
+                unreachable!("addr() and raw() descriptors cannot be planned")
+            }
+        }
     }
 
     /// Update a PSBT input with the metadata required to complete this plan
@@ -327,8 +506,6 @@ impl Plan {
 
             #[derive(Default)]
             struct TrDescriptorData {
-                tap_script: Option<ScriptBuf>,
-                control_block: Option<ControlBlock>,
                 spend_type: Option<SpendType>,
                 key_origins: BTreeMap<XOnlyPublicKey, bip32::KeySource>,
             }
@@ -341,8 +518,6 @@ impl Plan {
                 .iter()
                 .fold(TrDescriptorData::default(), |mut data, item| {
                     match item {
-                        Placeholder::TapScript(script) => data.tap_script = Some(script.clone()),
-                        Placeholder::TapControlBlock(cb) => data.control_block = Some(cb.clone()),
                         Placeholder::SchnorrSigPk(pk, sig_type, _) => {
                             let raw_pk = pk.to_x_only_pubkey();
 
@@ -365,6 +540,12 @@ impl Plan {
                         Placeholder::SchnorrSigPkHash(_, tap_leaf_hash, _) => {
                             data.spend_type = Some(SpendType::ScriptSpend { leaf_hash: *tap_leaf_hash });
                         }
+                        Placeholder::SchnorrAdaptorSigPk(pk, tap_leaf_hash, _, _) => {
+                            data.spend_type = Some(SpendType::ScriptSpend { leaf_hash: *tap_leaf_hash });
+                            for path in pk.full_derivation_paths() {
+                                data.key_origins.insert(pk.to_x_only_pubkey(), (pk.master_fingerprint(), path));
+                            }
+                        }
                         _ => {}
                     }
 
@@ -394,14 +575,14 @@ impl Plan {
                     })
                     .or_insert_with(|| (vec![], key_source));
             }
-            if let (Some(tap_script), Some(control_block)) = (data.tap_script, data.control_block) {
+            if let Some(spend) = self.tap_leaf_spend() {
                 input
                     .tap_scripts
-                    .insert(control_block, (tap_script, LeafVersion::TapScript));
+                    .insert(spend.control_block, (spend.script, spend.leaf_version));
             }
         } else {
             for item in &self.template {
-                if let Placeholder::EcdsaSigPk(pk) = item {
+                if let Placeholder::EcdsaSigPk(pk) | Placeholder::EcdsaAdaptorSigPk(pk, _) = item {
                     let public_key = pk.to_public_key().inner;
                     let master_fingerprint = pk.master_fingerprint();
                     for derivation_path in pk.full_derivation_paths() {
@@ -412,26 +593,856 @@ impl Plan {
                 }
             }
 
-            match &self.descriptor {
-                Descriptor::Bare(_) | Descriptor::Pkh(_) | Descriptor::Wpkh(_) => {}
-                Descriptor::Sh(sh) => match sh.as_inner() {
-                    descriptor::ShInner::Wsh(wsh) => {
-                        input.witness_script = Some(wsh.inner_script());
-                        input.redeem_script = Some(wsh.inner_script().to_p2wsh());
-                    }
-                    descriptor::ShInner::Wpkh(..) => input.redeem_script = Some(sh.inner_script()),
-                    descriptor::ShInner::SortedMulti(_) | descriptor::ShInner::Ms(_) => {
-                        input.redeem_script = Some(sh.inner_script())
-                    }
-                },
-                Descriptor::Wsh(wsh) => input.witness_script = Some(wsh.inner_script()),
-                Descriptor::Tr(_) => unreachable!("Tr is dealt with separately"),
-            }
-        }
+            match &self.descriptor {
+                Descriptor::Bare(_)
+                | Descriptor::Pkh(_)
+                | Descriptor::Wpkh(_)
+                | Descriptor::Addr(_)
+                | Descriptor::Raw(_) => {}
+                Descriptor::Sh(sh) => match sh.as_inner() {
+                    descriptor::ShInner::Wsh(wsh) => {
+                        input.witness_script = Some(wsh.inner_script());
+                        input.redeem_script = Some(wsh.inner_script().to_p2wsh());
+                    }
+                    descriptor::ShInner::Wpkh(..) => input.redeem_script = Some(sh.inner_script()),
+                    descriptor::ShInner::SortedMulti(_) | descriptor::ShInner::Ms(_) => {
+                        input.redeem_script = Some(sh.inner_script())
+                    }
+                },
+                Descriptor::Wsh(wsh) => input.witness_script = Some(wsh.inner_script()),
+                Descriptor::Tr(_) => unreachable!("Tr is dealt with separately"),
+            }
+        }
+    }
+
+    /// Lists the items which must be gathered to complete this plan
+    ///
+    /// This can be used by a coordinator to dispatch requests to each signer
+    /// and to whoever knows the hash preimages, before calling [`Plan::satisfy`].
+    pub fn signing_requests(&self) -> SigningRequests {
+        let mut requests = SigningRequests {
+            signatures: vec![],
+            sha256_preimages: vec![],
+            hash256_preimages: vec![],
+            ripemd160_preimages: vec![],
+            hash160_preimages: vec![],
+            absolute_timelock: self.absolute_timelock,
+            relative_timelock: self.relative_timelock,
+        };
+        for item in &self.template {
+            match item {
+                Placeholder::EcdsaSigPk(pk) => requests.signatures.push(SignatureRequest::new(
+                    Some(pk.clone()),
+                    pk.to_pubkeyhash(SigType::Ecdsa),
+                    SignatureKind::Ecdsa,
+                    None,
+                )),
+                Placeholder::EcdsaSigPkHash(pkh) => {
+                    requests.signatures.push(SignatureRequest::new(
+                        self.key_for_hash(pkh, SigType::Ecdsa),
+                        *pkh,
+                        SignatureKind::Ecdsa,
+                        None,
+                    ))
+                }
+                Placeholder::EcdsaAdaptorSigPk(pk, adaptor) => {
+                    let mut request = SignatureRequest::new(
+                        Some(pk.clone()),
+                        pk.to_pubkeyhash(SigType::Ecdsa),
+                        SignatureKind::Ecdsa,
+                        None,
+                    );
+                    request.adaptor = Some(*adaptor);
+                    requests.signatures.push(request)
+                }
+                Placeholder::SchnorrAdaptorSigPk(pk, leaf_hash, adaptor, size) => {
+                    let mut request = SignatureRequest::new(
+                        Some(pk.clone()),
+                        pk.to_pubkeyhash(SigType::Schnorr),
+                        SignatureKind::TapScriptSpend { leaf_hash: *leaf_hash },
+                        Some(*size),
+                    );
+                    request.adaptor = Some(*adaptor);
+                    requests.signatures.push(request)
+                }
+                Placeholder::SchnorrSigPk(pk, sig_type, size) => {
+                    let kind = match *sig_type {
+                        SchnorrSigType::KeySpend { merkle_root } => {
+                            SignatureKind::TapKeySpend { merkle_root }
+                        }
+                        SchnorrSigType::ScriptSpend { leaf_hash } => {
+                            SignatureKind::TapScriptSpend { leaf_hash }
+                        }
+                    };
+                    requests.signatures.push(SignatureRequest::new(
+                        Some(pk.clone()),
+                        pk.to_pubkeyhash(SigType::Schnorr),
+                        kind,
+                        Some(*size),
+                    ))
+                }
+                Placeholder::SchnorrSigPkHash(pkh, leaf_hash, size) => {
+                    requests.signatures.push(SignatureRequest::new(
+                        self.key_for_hash(pkh, SigType::Schnorr),
+                        *pkh,
+                        SignatureKind::TapScriptSpend { leaf_hash: *leaf_hash },
+                        Some(*size),
+                    ))
+                }
+                Placeholder::Sha256Preimage(hash) => requests.sha256_preimages.push(*hash),
+                Placeholder::Hash256Preimage(hash) => requests.hash256_preimages.push(*hash),
+                Placeholder::Ripemd160Preimage(hash) => requests.ripemd160_preimages.push(*hash),
+                Placeholder::Hash160Preimage(hash) => requests.hash160_preimages.push(*hash),
+                _ => {}
+            }
+        }
+        requests
+    }
+
+    /// Finds the key of the descriptor with the given hash, if any
+    fn key_for_hash(
+        &self,
+        pkh: &hash160::Hash,
+        sig_type: SigType,
+    ) -> Option<DefiniteDescriptorKey> {
+        let mut found = None;
+        self.descriptor.for_any_key(|pk| {
+            if pk.to_pubkeyhash(sig_type) == *pkh {
+                found = Some(pk.clone());
+            }
+            found.is_some()
+        });
+        found
+    }
+
+    /// The signatures, hash preimages and timelocks used by this plan
+    pub fn used_assets(&self) -> Vec<PlanAsset> {
+        let mut assets = vec![];
+        for item in &self.template {
+            let asset = match item {
+                Placeholder::EcdsaSigPk(pk) | Placeholder::EcdsaAdaptorSigPk(pk, _) => {
+                    PlanAsset::Signature(pk.clone(), None)
+                }
+                Placeholder::EcdsaSigPkHash(pkh) => PlanAsset::SignatureByHash(*pkh, None),
+                Placeholder::SchnorrSigPk(pk, SchnorrSigType::KeySpend { .. }, _) => {
+                    PlanAsset::Signature(pk.clone(), None)
+                }
+                Placeholder::SchnorrSigPk(pk, SchnorrSigType::ScriptSpend { leaf_hash }, _)
+                | Placeholder::SchnorrAdaptorSigPk(pk, leaf_hash, _, _) => {
+                    PlanAsset::Signature(pk.clone(), Some(*leaf_hash))
+                }
+                Placeholder::SchnorrSigPkHash(pkh, leaf_hash, _) => {
+                    PlanAsset::SignatureByHash(*pkh, Some(*leaf_hash))
+                }
+                Placeholder::Sha256Preimage(hash) => PlanAsset::Sha256Preimage(*hash),
+                Placeholder::Hash256Preimage(hash) => PlanAsset::Hash256Preimage(*hash),
+                Placeholder::Ripemd160Preimage(hash) => PlanAsset::Ripemd160Preimage(*hash),
+                Placeholder::Hash160Preimage(hash) => PlanAsset::Hash160Preimage(*hash),
+                _ => continue,
+            };
+            assets.push(asset);
+        }
+        assets.extend(self.absolute_timelock.map(PlanAsset::AbsoluteTimelock));
+        assets.extend(self.relative_timelock.map(PlanAsset::RelativeTimelock));
+        assets
+    }
+
+    /// The nSequence of the input spent by this plan
+    ///
+    /// Plans using a relative timelock need it as their sequence, and the transaction
+    /// to be version 2. Plans using only an absolute timelock need a sequence enabling
+    /// nLockTime, `0xfffffffe` is returned. The other plans do not constrain the
+    /// sequence, `0xffffffff` is returned.
+    pub fn sequence(&self) -> Sequence {
+        match (self.relative_timelock, self.absolute_timelock) {
+            (Some(delta), _) => delta.to_sequence(),
+            (None, Some(_)) => Sequence::ENABLE_LOCKTIME_NO_RBF,
+            (None, None) => Sequence::MAX,
+        }
+    }
+
+    /// The lowest nLockTime of a transaction spending this plan
+    pub fn lock_time(&self) -> absolute::LockTime {
+        self.absolute_timelock.unwrap_or(absolute::LockTime::ZERO)
+    }
+}
+
+/// Describes the spend for humans, e.g. in logs
+///
+/// The description names the spent descriptor, the Taproot spending path if any, the
+/// signatures, hash preimages and timelocks used and the satisfaction weight, as in
+/// `tr(...) by the key path using signature by 03...; satisfaction weight 70 wu`.
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.descriptor)?;
+        if self.witness_version() == Some(WitnessVersion::V1) {
+            match self.tap_leaf_spend() {
+                Some(spend) => write!(
+                    f,
+                    " by the script path of leaf {} ({})",
+                    spend.leaf_hash, spend.script
+                )?,
+                None => f.write_str(" by the key path")?,
+            }
+        }
+        let assets = self.used_assets();
+        if assets.is_empty() {
+            f.write_str(" using no signature, preimage or timelock")?;
+        }
+        for (i, asset) in assets.iter().enumerate() {
+            f.write_str(if i == 0 { " using " } else { ", " })?;
+            fmt::Display::fmt(asset, f)?;
+        }
+        write!(f, "; satisfaction weight {} wu", self.satisfaction_weight().to_wu())
+    }
+}
+
+/// Which Taproot spending path [`Descriptor::plan_tap_spend`] should use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TapSpendPath {
+    /// Use the key path if possible, falling back to the cheapest script path
+    Auto {
+        /// The highest fee the script path may cost, if it is limited
+        max_script_path_fee: Option<Amount>,
+    },
+    /// Only use the key path
+    KeyPath,
+    /// Only use the cheapest script path, even if the key path is available
+    ScriptPath,
+}
+
+impl Default for TapSpendPath {
+    fn default() -> Self { TapSpendPath::Auto { max_script_path_fee: None } }
+}
+
+/// A Taproot spend selected by [`Descriptor::plan_tap_spend`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TapSpendPlan {
+    /// The plan of the selected spending path
+    pub plan: Plan,
+    /// The fee of satisfying the key path, if it is available
+    pub key_path_fee: Option<Amount>,
+    /// The fee of satisfying the cheapest script path, if any is available
+    pub script_path_fee: Option<Amount>,
+}
+
+/// The Taproot script path spent by a [`Plan`]
+///
+/// Returned by [`Plan::tap_leaf_spend`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TapLeafSpend {
+    /// The script of the leaf
+    pub script: ScriptBuf,
+    /// The version of the leaf
+    pub leaf_version: LeafVersion,
+    /// The hash of the leaf, which script path signatures commit to
+    pub leaf_hash: TapLeafHash,
+    /// The control block proving the output key commits to the leaf
+    pub control_block: ControlBlock,
+}
+
+impl TapLeafSpend {
+    /// The merkle branch from the leaf to the root of the tap tree
+    pub fn merkle_branch(&self) -> &TaprootMerkleBranch { &self.control_block.merkle_branch }
+
+    /// The control block, serialized as it is pushed on the witness
+    pub fn serialized_control_block(&self) -> Vec<u8> { self.control_block.serialize() }
+}
+
+/// The nLockTime and nSequences of a transaction spending the inputs of several plans
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxTimelocks {
+    /// The nLockTime of the transaction, the highest absolute timelock of the plans
+    pub lock_time: absolute::LockTime,
+    /// The nSequence of each input, see [`Plan::sequence`]
+    pub sequences: Vec<Sequence>,
+}
+
+impl TxTimelocks {
+    /// Computes the timelocks of a transaction whose inputs are spent by `plans`, in order
+    ///
+    /// A transaction has a single nLockTime, so this fails if the plans use absolute
+    /// timelocks of different units, i.e. block heights and times.
+    pub fn from_plans<'a, I>(plans: I) -> Result<Self, LockTimeConflict>
+    where
+        I: IntoIterator<Item = &'a Plan>,
+    {
+        let mut lock_time: Option<absolute::LockTime> = None;
+        let mut sequences = vec![];
+        for (index, plan) in plans.into_iter().enumerate() {
+            if let Some(lt) = plan.absolute_timelock {
+                lock_time = Some(match lock_time {
+                    None => lt,
+                    Some(max) if !max.is_same_unit(lt) => {
+                        return Err(LockTimeConflict { index, lock_time: lt, other: max })
+                    }
+                    Some(max) if max.to_consensus_u32() < lt.to_consensus_u32() => lt,
+                    Some(max) => max,
+                });
+            }
+            sequences.push(plan.sequence());
+        }
+        Ok(TxTimelocks { lock_time: lock_time.unwrap_or(absolute::LockTime::ZERO), sequences })
+    }
+}
+
+/// Error when the plans of a transaction use absolute timelocks of different units
+///
+/// Returned by [`TxTimelocks::from_plans`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockTimeConflict {
+    /// The index of the first plan whose timelock conflicts with the previous ones
+    pub index: usize,
+    /// The absolute timelock of this plan
+    pub lock_time: absolute::LockTime,
+    /// The absolute timelock required by the previous plans
+    pub other: absolute::LockTime,
+}
+
+impl fmt::Display for LockTimeConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "input {} needs the absolute timelock {}, whose unit differs from that of {}",
+            self.index, self.lock_time, self.other
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for LockTimeConflict {}
+
+/// The items which must be gathered to complete a [`Plan`]
+///
+/// Returned by [`Plan::signing_requests`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SigningRequests {
+    /// The signatures needed, in witness order
+    pub signatures: Vec<SignatureRequest>,
+    /// The SHA-256 preimages needed
+    pub sha256_preimages: Vec<sha256::Hash>,
+    /// The HASH256 preimages needed
+    pub hash256_preimages: Vec<hash256::Hash>,
+    /// The RIPEMD160 preimages needed
+    pub ripemd160_preimages: Vec<ripemd160::Hash>,
+    /// The HASH160 preimages needed
+    pub hash160_preimages: Vec<hash160::Hash>,
+    /// The absolute timelock which must be mature
+    pub absolute_timelock: Option<absolute::LockTime>,
+    /// The relative timelock which must be mature
+    pub relative_timelock: Option<relative::LockTime>,
+}
+
+/// A signature needed to complete a [`Plan`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureRequest {
+    /// The key which must sign, if it is part of the descriptor
+    ///
+    /// This is `None` only for raw public key hashes whose key is unknown.
+    pub key: Option<DefiniteDescriptorKey>,
+    /// The hash of the key, as found in `pkh` fragments
+    pub key_hash: hash160::Hash,
+    /// Where the key was derived from, for each of its derivation paths
+    pub key_origins: Vec<bip32::KeySource>,
+    /// The kind of signature to produce
+    pub kind: SignatureKind,
+    /// The sighash type the signature must commit to, if the plan fixes it
+    ///
+    /// Plans only fix it for taproot signatures assumed to use `SIGHASH_DEFAULT`.
+    pub sighash_type: Option<psbt::PsbtSighashType>,
+    /// The adaptor point, if the signature will be adapted from an adaptor signature
+    pub adaptor: Option<PublicKey>,
+}
+
+impl SignatureRequest {
+    fn new(
+        key: Option<DefiniteDescriptorKey>,
+        key_hash: hash160::Hash,
+        kind: SignatureKind,
+        sig_size: Option<usize>,
+    ) -> Self {
+        let key_origins = key
+            .as_ref()
+            .map(|pk| {
+                pk.full_derivation_paths()
+                    .into_iter()
+                    .map(|path| (pk.master_fingerprint(), path))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let sighash_type = match sig_size {
+            Some(64) => Some(bitcoin::TapSighashType::Default.into()),
+            _ => None,
+        };
+        SignatureRequest { key, key_hash, key_origins, kind, sighash_type, adaptor: None }
+    }
+}
+
+/// The kind of a requested signature
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SignatureKind {
+    /// An ECDSA signature
+    Ecdsa,
+    /// A taproot key spend signature
+    TapKeySpend {
+        /// Merkle root to tweak the key, if present
+        merkle_root: Option<TapNodeHash>,
+    },
+    /// A taproot script spend signature
+    TapScriptSpend {
+        /// Leaf hash of the script
+        leaf_hash: TapLeafHash,
+    },
+}
+
+/// An item used by a [`Plan`], as listed by [`Plan::used_assets`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PlanAsset {
+    /// A signature by a key, for the given taproot leaf if it is a script spend
+    Signature(DefiniteDescriptorKey, Option<TapLeafHash>),
+    /// A signature by the key with the given hash, for the given taproot leaf if any
+    SignatureByHash(hash160::Hash, Option<TapLeafHash>),
+    /// A SHA-256 preimage
+    Sha256Preimage(sha256::Hash),
+    /// A HASH256 preimage
+    Hash256Preimage(hash256::Hash),
+    /// A RIPEMD160 preimage
+    Ripemd160Preimage(ripemd160::Hash),
+    /// A HASH160 preimage
+    Hash160Preimage(hash160::Hash),
+    /// An absolute timelock
+    AbsoluteTimelock(absolute::LockTime),
+    /// A relative timelock
+    RelativeTimelock(relative::LockTime),
+}
+
+/// Iterator over the plans of a descriptor, from the cheapest
+///
+/// Returned by [`Descriptor::plans`].
+pub struct Plans<'a, P> {
+    descriptor: Descriptor<DefiniteDescriptorKey>,
+    provider: &'a P,
+    /// Plans yet to be yielded, with the assets excluded to find them
+    candidates: Vec<(Plan, Vec<PlanAsset>)>,
+    /// Sets of excluded assets already tried
+    tried: Vec<Vec<PlanAsset>>,
+    yielded: Vec<Vec<Placeholder<DefiniteDescriptorKey>>>,
+    remaining: usize,
+}
+
+impl<'a, P: AssetProvider<DefiniteDescriptorKey>> Plans<'a, P> {
+    pub(crate) fn new(
+        descriptor: Descriptor<DefiniteDescriptorKey>,
+        provider: &'a P,
+        limit: usize,
+    ) -> Self {
+        let mut plans = Plans {
+            descriptor,
+            provider,
+            candidates: vec![],
+            tried: vec![],
+            yielded: vec![],
+            remaining: limit,
+        };
+        plans.try_excluding(vec![]);
+        plans
+    }
+
+    /// Plans the descriptor without the `excluded` assets, unless it was already done
+    fn try_excluding(&mut self, excluded: Vec<PlanAsset>) {
+        let same_set = |other: &Vec<PlanAsset>| {
+            other.len() == excluded.len() && excluded.iter().all(|asset| other.contains(asset))
+        };
+        if self.tried.iter().any(same_set) {
+            return;
+        }
+        let provider = ExcludingProvider { inner: self.provider, excluded: &excluded };
+        if let Ok(plan) = self.descriptor.clone().plan_cheapest(&provider) {
+            self.candidates.push((plan, excluded.clone()));
+        }
+        self.tried.push(excluded);
+    }
+}
+
+impl<P: AssetProvider<DefiniteDescriptorKey>> Iterator for Plans<'_, P> {
+    type Item = Plan;
+
+    fn next(&mut self) -> Option<Plan> {
+        while self.remaining > 0 && !self.candidates.is_empty() {
+            let cheapest = self
+                .candidates
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, (plan, _))| plan.satisfaction_weight())
+                .map(|(i, _)| i)
+                .expect("non-empty candidates");
+            let (plan, excluded) = self.candidates.swap_remove(cheapest);
+            // Any other plan does without at least one of the assets of this one.
+            for asset in plan.used_assets() {
+                if !excluded.contains(&asset) {
+                    let mut excluded = excluded.clone();
+                    excluded.push(asset);
+                    self.try_excluding(excluded);
+                }
+            }
+            if !self.yielded.contains(&plan.template) && self.provider.provider_accepts_plan(&plan)
+            {
+                self.yielded.push(plan.template.clone());
+                self.remaining -= 1;
+                return Some(plan);
+            }
+        }
+        None
+    }
+}
+
+/// Asset provider hiding some of the assets of another one
+struct ExcludingProvider<'a, P> {
+    inner: &'a P,
+    excluded: &'a [PlanAsset],
+}
+
+impl<P> ExcludingProvider<'_, P> {
+    fn has_sig(&self, pk: &DefiniteDescriptorKey, leaf_hash: Option<&TapLeafHash>) -> bool {
+        !self
+            .excluded
+            .contains(&PlanAsset::Signature(pk.clone(), leaf_hash.copied()))
+    }
+
+    fn has(&self, asset: PlanAsset) -> bool { !self.excluded.contains(&asset) }
+}
+
+impl<P: AssetProvider<DefiniteDescriptorKey>> AssetProvider<DefiniteDescriptorKey>
+    for ExcludingProvider<'_, P>
+{
+    fn provider_lookup_ecdsa_sig(&self, pk: &DefiniteDescriptorKey) -> bool {
+        self.has_sig(pk, None) && self.inner.provider_lookup_ecdsa_sig(pk)
+    }
+
+    fn provider_lookup_tap_key_spend_sig(&self, pk: &DefiniteDescriptorKey) -> Option<usize> {
+        self.inner
+            .provider_lookup_tap_key_spend_sig(pk)
+            .filter(|_| self.has_sig(pk, None))
+    }
+
+    fn provider_lookup_tap_leaf_script_sig(
+        &self,
+        pk: &DefiniteDescriptorKey,
+        leaf_hash: &TapLeafHash,
+    ) -> Option<usize> {
+        self.inner
+            .provider_lookup_tap_leaf_script_sig(pk, leaf_hash)
+            .filter(|_| self.has_sig(pk, Some(leaf_hash)))
+    }
+
+    fn provider_lookup_ecdsa_adaptor_sig(&self, pk: &DefiniteDescriptorKey) -> Option<PublicKey> {
+        self.inner
+            .provider_lookup_ecdsa_adaptor_sig(pk)
+            .filter(|_| self.has_sig(pk, None))
+    }
+
+    fn provider_lookup_tap_leaf_script_adaptor_sig(
+        &self,
+        pk: &DefiniteDescriptorKey,
+        leaf_hash: &TapLeafHash,
+    ) -> Option<(PublicKey, usize)> {
+        self.inner
+            .provider_lookup_tap_leaf_script_adaptor_sig(pk, leaf_hash)
+            .filter(|_| self.has_sig(pk, Some(leaf_hash)))
+    }
+
+    fn provider_lookup_tap_control_block_map(
+        &self,
+    ) -> Option<&BTreeMap<ControlBlock, (bitcoin::ScriptBuf, LeafVersion)>> {
+        self.inner.provider_lookup_tap_control_block_map()
+    }
+
+    fn provider_lookup_raw_pkh_pk(&self, hash: &hash160::Hash) -> Option<bitcoin::PublicKey> {
+        self.inner.provider_lookup_raw_pkh_pk(hash)
+    }
+
+    fn provider_lookup_raw_pkh_x_only_pk(&self, hash: &hash160::Hash) -> Option<XOnlyPublicKey> {
+        self.inner.provider_lookup_raw_pkh_x_only_pk(hash)
+    }
+
+    fn provider_lookup_raw_pkh_ecdsa_sig(
+        &self,
+        hash: &hash160::Hash,
+    ) -> Option<bitcoin::PublicKey> {
+        self.inner
+            .provider_lookup_raw_pkh_ecdsa_sig(hash)
+            .filter(|_| self.has(PlanAsset::SignatureByHash(*hash, None)))
+    }
+
+    fn provider_lookup_raw_pkh_tap_leaf_script_sig(
+        &self,
+        hash: &(hash160::Hash, TapLeafHash),
+    ) -> Option<(XOnlyPublicKey, usize)> {
+        self.inner
+            .provider_lookup_raw_pkh_tap_leaf_script_sig(hash)
+            .filter(|_| self.has(PlanAsset::SignatureByHash(hash.0, Some(hash.1))))
+    }
+
+    fn provider_lookup_sha256(&self, hash: &sha256::Hash) -> bool {
+        self.has(PlanAsset::Sha256Preimage(*hash)) && self.inner.provider_lookup_sha256(hash)
+    }
+
+    fn provider_lookup_hash256(&self, hash: &hash256::Hash) -> bool {
+        self.has(PlanAsset::Hash256Preimage(*hash)) && self.inner.provider_lookup_hash256(hash)
+    }
+
+    fn provider_lookup_ripemd160(&self, hash: &ripemd160::Hash) -> bool {
+        self.has(PlanAsset::Ripemd160Preimage(*hash)) && self.inner.provider_lookup_ripemd160(hash)
+    }
+
+    fn provider_lookup_hash160(&self, hash: &hash160::Hash) -> bool {
+        self.has(PlanAsset::Hash160Preimage(*hash)) && self.inner.provider_lookup_hash160(hash)
+    }
+
+    fn check_older(&self, s: relative::LockTime) -> bool {
+        self.has(PlanAsset::RelativeTimelock(s)) && self.inner.check_older(s)
+    }
+
+    fn check_after(&self, l: absolute::LockTime) -> bool {
+        self.has(PlanAsset::AbsoluteTimelock(l)) && self.inner.check_after(l)
+    }
+
+    fn provider_lookup_key_priority(&self, pk: &DefiniteDescriptorKey) -> Option<i32> {
+        self.inner.provider_lookup_key_priority(pk)
+    }
+
+    fn provider_preference_tolerance(&self) -> usize { self.inner.provider_preference_tolerance() }
+
+    fn provider_lookup_tap_sighash_type(&self) -> Option<TapSighashType> {
+        self.inner.provider_lookup_tap_sighash_type()
+    }
+}
+
+/// The assets missing to spend a descriptor along one of its spending paths
+///
+/// Returned by [`Descriptor::missing_assets`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingAssets {
+    /// The plan which the missing assets would allow
+    pub plan: Plan,
+    /// The signatures, hash preimages and timelocks used by the plan but not available
+    pub missing: Vec<PlanAsset>,
+}
+
+impl MissingAssets {
+    /// Computes which of the assets used by `plan` are not available from `provider`
+    pub(crate) fn new<P: AssetProvider<DefiniteDescriptorKey>>(plan: Plan, provider: &P) -> Self {
+        let is_taproot = plan.descriptor.desc_type().segwit_version() == Some(WitnessVersion::V1);
+        let mut missing = vec![];
+        for asset in plan.used_assets() {
+            let available = match asset {
+                PlanAsset::Signature(ref pk, None) if is_taproot => {
+                    provider.provider_lookup_tap_key_spend_sig(pk).is_some()
+                }
+                PlanAsset::Signature(ref pk, None) => {
+                    provider.provider_lookup_ecdsa_sig(pk)
+                        || provider.provider_lookup_ecdsa_adaptor_sig(pk).is_some()
+                }
+                PlanAsset::Signature(ref pk, Some(ref leaf_hash)) => {
+                    provider
+                        .provider_lookup_tap_leaf_script_sig(pk, leaf_hash)
+                        .is_some()
+                        || provider
+                            .provider_lookup_tap_leaf_script_adaptor_sig(pk, leaf_hash)
+                            .is_some()
+                }
+                PlanAsset::SignatureByHash(ref hash, None) => {
+                    provider.provider_lookup_raw_pkh_ecdsa_sig(hash).is_some()
+                }
+                PlanAsset::SignatureByHash(ref hash, Some(leaf_hash)) => provider
+                    .provider_lookup_raw_pkh_tap_leaf_script_sig(&(*hash, leaf_hash))
+                    .is_some(),
+                PlanAsset::Sha256Preimage(ref hash) => provider.provider_lookup_sha256(hash),
+                PlanAsset::Hash256Preimage(ref hash) => provider.provider_lookup_hash256(hash),
+                PlanAsset::Ripemd160Preimage(ref hash) => provider.provider_lookup_ripemd160(hash),
+                PlanAsset::Hash160Preimage(ref hash) => provider.provider_lookup_hash160(hash),
+                PlanAsset::AbsoluteTimelock(lt) => provider.check_after(lt),
+                PlanAsset::RelativeTimelock(delta) => provider.check_older(delta),
+            };
+            if !available && !missing.contains(&asset) {
+                missing.push(asset);
+            }
+        }
+        MissingAssets { plan, missing }
+    }
+
+    /// Whether nothing is missing, i.e. the plan can be satisfied right away
+    pub fn is_empty(&self) -> bool { self.missing.is_empty() }
+
+    /// The missing signatures
+    pub fn signatures(&self) -> impl Iterator<Item = &PlanAsset> {
+        self.missing.iter().filter(|asset| {
+            matches!(asset, PlanAsset::Signature(..) | PlanAsset::SignatureByHash(..))
+        })
+    }
+
+    /// The missing hash preimages
+    pub fn preimages(&self) -> impl Iterator<Item = &PlanAsset> {
+        self.missing.iter().filter(|asset| {
+            matches!(
+                asset,
+                PlanAsset::Sha256Preimage(_)
+                    | PlanAsset::Hash256Preimage(_)
+                    | PlanAsset::Ripemd160Preimage(_)
+                    | PlanAsset::Hash160Preimage(_)
+            )
+        })
+    }
+
+    /// The absolute timelock the plan needs, if it is not mature yet
+    pub fn immature_absolute_timelock(&self) -> Option<absolute::LockTime> {
+        self.missing.iter().find_map(|asset| match asset {
+            PlanAsset::AbsoluteTimelock(lt) => Some(*lt),
+            _ => None,
+        })
+    }
+
+    /// The relative timelock the plan needs, if it is not mature yet
+    pub fn immature_relative_timelock(&self) -> Option<relative::LockTime> {
+        self.missing.iter().find_map(|asset| match asset {
+            PlanAsset::RelativeTimelock(delta) => Some(*delta),
+            _ => None,
+        })
+    }
+}
+
+impl fmt::Display for PlanAsset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlanAsset::Signature(pk, None) => write!(f, "signature by {}", pk),
+            PlanAsset::Signature(pk, Some(leaf_hash)) => {
+                write!(f, "signature by {} for leaf {}", pk, leaf_hash)
+            }
+            PlanAsset::SignatureByHash(hash, None) => write!(f, "signature by key hash {}", hash),
+            PlanAsset::SignatureByHash(hash, Some(leaf_hash)) => {
+                write!(f, "signature by key hash {} for leaf {}", hash, leaf_hash)
+            }
+            PlanAsset::Sha256Preimage(hash) => write!(f, "sha256 preimage of {}", hash),
+            PlanAsset::Hash256Preimage(hash) => write!(f, "hash256 preimage of {}", hash),
+            PlanAsset::Ripemd160Preimage(hash) => write!(f, "ripemd160 preimage of {}", hash),
+            PlanAsset::Hash160Preimage(hash) => write!(f, "hash160 preimage of {}", hash),
+            PlanAsset::AbsoluteTimelock(lt) => write!(f, "absolute timelock {}", lt),
+            PlanAsset::RelativeTimelock(delta) => write!(f, "relative timelock {}", delta),
+        }
+    }
+}
+
+impl fmt::Display for MissingAssets {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.missing.is_empty() {
+            return f.write_str("nothing missing");
+        }
+        f.write_str("missing ")?;
+        for (i, asset) in self.missing.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            fmt::Display::fmt(asset, f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Asset provider assuming every signature, preimage and timelock of another one is available
+///
+/// Public keys of raw key hashes cannot be made up, so they are only looked up from the inner
+/// provider. Made up Taproot signatures use `SIGHASH_DEFAULT`.
+pub(crate) struct AssumingProvider<'a, P> {
+    pub(crate) inner: &'a P,
+}
+
+impl<P: AssetProvider<DefiniteDescriptorKey>> AssumingProvider<'_, P> {
+    /// The size of the taproot signatures assumed to be available
+    fn tap_sig_len(&self) -> usize { tap_sig_len(self.inner.provider_lookup_tap_sighash_type()) }
+}
+
+impl<P: AssetProvider<DefiniteDescriptorKey>> AssetProvider<DefiniteDescriptorKey>
+    for AssumingProvider<'_, P>
+{
+    fn provider_lookup_ecdsa_sig(&self, _: &DefiniteDescriptorKey) -> bool { true }
+
+    fn provider_lookup_tap_key_spend_sig(&self, pk: &DefiniteDescriptorKey) -> Option<usize> {
+        self.inner
+            .provider_lookup_tap_key_spend_sig(pk)
+            .or_else(|| Some(self.tap_sig_len()))
+    }
+
+    fn provider_lookup_tap_leaf_script_sig(
+        &self,
+        pk: &DefiniteDescriptorKey,
+        leaf_hash: &TapLeafHash,
+    ) -> Option<usize> {
+        self.inner
+            .provider_lookup_tap_leaf_script_sig(pk, leaf_hash)
+            .or_else(|| Some(self.tap_sig_len()))
+    }
+
+    fn provider_lookup_tap_control_block_map(
+        &self,
+    ) -> Option<&BTreeMap<ControlBlock, (bitcoin::ScriptBuf, LeafVersion)>> {
+        self.inner.provider_lookup_tap_control_block_map()
+    }
+
+    fn provider_lookup_raw_pkh_pk(&self, hash: &hash160::Hash) -> Option<bitcoin::PublicKey> {
+        self.inner.provider_lookup_raw_pkh_pk(hash)
+    }
+
+    fn provider_lookup_raw_pkh_x_only_pk(&self, hash: &hash160::Hash) -> Option<XOnlyPublicKey> {
+        self.inner.provider_lookup_raw_pkh_x_only_pk(hash)
+    }
+
+    fn provider_lookup_raw_pkh_ecdsa_sig(
+        &self,
+        hash: &hash160::Hash,
+    ) -> Option<bitcoin::PublicKey> {
+        self.inner
+            .provider_lookup_raw_pkh_ecdsa_sig(hash)
+            .or_else(|| self.inner.provider_lookup_raw_pkh_pk(hash))
+    }
+
+    fn provider_lookup_raw_pkh_tap_leaf_script_sig(
+        &self,
+        hash: &(hash160::Hash, TapLeafHash),
+    ) -> Option<(XOnlyPublicKey, usize)> {
+        self.inner
+            .provider_lookup_raw_pkh_tap_leaf_script_sig(hash)
+            .or_else(|| {
+                self.inner
+                    .provider_lookup_raw_pkh_x_only_pk(&hash.0)
+                    .map(|pk| (pk, self.tap_sig_len()))
+            })
+    }
+
+    fn provider_lookup_sha256(&self, _: &sha256::Hash) -> bool { true }
+
+    fn provider_lookup_hash256(&self, _: &hash256::Hash) -> bool { true }
+
+    fn provider_lookup_ripemd160(&self, _: &ripemd160::Hash) -> bool { true }
+
+    fn provider_lookup_hash160(&self, _: &hash160::Hash) -> bool { true }
+
+    fn check_older(&self, _: relative::LockTime) -> bool { true }
+
+    fn check_after(&self, _: absolute::LockTime) -> bool { true }
+
+    fn provider_lookup_key_priority(&self, pk: &DefiniteDescriptorKey) -> Option<i32> {
+        self.inner.provider_lookup_key_priority(pk)
+    }
+
+    fn provider_preference_tolerance(&self) -> usize { self.inner.provider_preference_tolerance() }
+
+    fn provider_lookup_tap_sighash_type(&self) -> Option<TapSighashType> {
+        self.inner.provider_lookup_tap_sighash_type()
     }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Signatures which a key can produce
 ///
 /// Defaults to `ecdsa=true` and `taproot=TaprootCanSign::default()`
@@ -447,6 +1458,7 @@ impl Default for CanSign {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Signatures which a taproot key can produce
 ///
 /// Defaults to `key_spend=true`, `script_spend=Any` and `sighash_default=true`
@@ -468,6 +1480,14 @@ impl TaprootCanSign {
     }
 }
 
+/// The size of a taproot signature with the given sighash type, assuming `SIGHASH_DEFAULT`
+fn tap_sig_len(sighash_type: Option<TapSighashType>) -> usize {
+    match sighash_type {
+        None | Some(TapSighashType::Default) => 64,
+        Some(_) => 65,
+    }
+}
+
 impl Default for TaprootCanSign {
     fn default() -> Self {
         TaprootCanSign {
@@ -479,6 +1499,7 @@ impl Default for TaprootCanSign {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Which taproot leaves the key can sign for
 pub enum TaprootAvailableLeaves {
     /// Cannot sign for any leaf
@@ -505,7 +1526,8 @@ impl TaprootAvailableLeaves {
 }
 
 /// The Assets we can use to satisfy a particular spending path
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Assets {
     /// Keys the user can sign for, and how.
     ///
@@ -513,7 +1535,8 @@ pub struct Assets {
     /// provided, meaning that the user can sign using the key with `fingerprint`,
     /// derived with either `derivation_path` or a derivation path that extends `derivation_path`
     /// by exactly one child number. For example, if the derivation path `m/0/1` is provided, the
-    /// user can sign with either `m/0/1` or `m/0/1/*`.
+    /// user can sign with either `m/0/1` or `m/0/1/*`. Adding a ranged key such as `xpub/0/*`
+    /// thus allows signing at any index under its wildcard.
     pub keys: BTreeSet<(bip32::KeySource, CanSign)>,
     /// Set of available sha256 preimages
     pub sha256_preimages: BTreeSet<sha256::Hash>,
@@ -527,6 +1550,64 @@ pub struct Assets {
     pub absolute_timelock: Option<absolute::LockTime>,
     /// Maximum relative timelock allowed
     pub relative_timelock: Option<relative::LockTime>,
+    /// Absolute timelocks up to which any timelock of the same unit is assumed to be mature
+    ///
+    /// There is at most one bound per unit, so both block height and block time timelocks can be
+    /// allowed. See [`Assets::after_max`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub absolute_timelock_bounds: Vec<absolute::LockTime>,
+    /// Relative timelocks up to which any timelock of the same unit is assumed to be mature
+    ///
+    /// There is at most one bound per unit, so both block and time interval timelocks can be
+    /// allowed. See [`Assets::older_max`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub relative_timelock_bounds: Vec<relative::LockTime>,
+    /// Keys which produced an adaptor signature, and its adaptor point
+    ///
+    /// Keys are matched like in [`Assets::keys`]. Taproot adaptor signatures are assumed to
+    /// use `SIGHASH_DEFAULT` once adapted, unless [`Assets::tap_sighash_type`] is set.
+    #[cfg_attr(feature = "serde", serde(with = "serde_pairs"))]
+    pub adaptors: BTreeMap<bip32::KeySource, PublicKey>,
+    /// Limits on the number of signatures plans may use from some sets of keys
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub signature_limits: Vec<SignatureLimit>,
+    /// Number of preimages available for hashes which are not known in advance
+    ///
+    /// Any hash is assumed to have a preimage, but plans may use at most this many
+    /// preimages of hashes which are not in the preimage sets.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub unspecified_preimages: usize,
+    /// Priorities of some keys, matched like in [`Assets::keys`]
+    ///
+    /// See [`Assets::prefer`].
+    #[cfg_attr(feature = "serde", serde(default, with = "serde_pairs"))]
+    pub key_priorities: BTreeMap<bip32::KeySource, i32>,
+    /// How many extra witness bytes the key priorities may cost
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub preference_tolerance: usize,
+    /// The sighash type of all taproot signatures, if known
+    ///
+    /// When set, it overrides [`TaprootCanSign::sighash_default`] to size the signatures.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub tap_sighash_type: Option<TapSighashType>,
+}
+
+/// A limit on the number of signatures a plan may use from a set of keys
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SignatureLimit {
+    /// The keys, matched like in [`Assets::keys`]
+    pub keys: BTreeSet<bip32::KeySource>,
+    /// The maximum number of signatures from these keys
+    pub max: usize,
+}
+
+impl SignatureLimit {
+    fn contains(&self, pk: &DefiniteDescriptorKey) -> bool {
+        self.keys
+            .iter()
+            .any(|keysource| is_key_derived_from(pk, keysource))
+    }
 }
 
 // Checks if the `pk` is a "direct child" of the `derivation_path` provided.
@@ -544,7 +1625,9 @@ fn is_key_direct_child_of(
         }
 
         let definite_path_len = pk_derivation_path.len();
-        if derivation_path.as_ref() == &pk_derivation_path[..(definite_path_len - 1)] {
+        if definite_path_len > 0
+            && derivation_path.as_ref() == &pk_derivation_path[..(definite_path_len - 1)]
+        {
             return true;
         }
     }
@@ -552,6 +1635,11 @@ fn is_key_direct_child_of(
     false
 }
 
+// Checks if the `pk` is the key of `keysource` or one of its direct children.
+fn is_key_derived_from(pk: &DefiniteDescriptorKey, keysource: &bip32::KeySource) -> bool {
+    pk.master_fingerprint() == keysource.0 && is_key_direct_child_of(pk, &keysource.1)
+}
+
 impl Assets {
     pub(crate) fn has_ecdsa_key(&self, pk: &DefiniteDescriptorKey) -> bool {
         self.keys.iter().any(|(keysource, can_sign)| {
@@ -569,11 +1657,34 @@ impl Assets {
             {
                 None
             } else {
-                Some(can_sign.taproot.sig_len())
+                Some(self.tap_sig_len(&can_sign.taproot))
             }
         })
     }
 
+    fn tap_sig_len(&self, can_sign: &TaprootCanSign) -> usize {
+        match self.tap_sighash_type {
+            Some(_) => tap_sig_len(self.tap_sighash_type),
+            None => can_sign.sig_len(),
+        }
+    }
+
+    pub(crate) fn adaptor_for_key(&self, pk: &DefiniteDescriptorKey) -> Option<PublicKey> {
+        self.adaptors
+            .iter()
+            .find(|(keysource, _)| {
+                pk.master_fingerprint() == keysource.0 && is_key_direct_child_of(pk, &keysource.1)
+            })
+            .map(|(_, adaptor)| *adaptor)
+    }
+
+    pub(crate) fn key_priority(&self, pk: &DefiniteDescriptorKey) -> Option<i32> {
+        self.key_priorities
+            .iter()
+            .find(|(keysource, _)| is_key_derived_from(pk, keysource))
+            .map(|(_, priority)| *priority)
+    }
+
     pub(crate) fn has_taproot_script_key(
         &self,
         pk: &DefiniteDescriptorKey,
@@ -586,7 +1697,7 @@ impl Assets {
             {
                 None
             } else {
-                Some(can_sign.taproot.sig_len())
+                Some(self.tap_sig_len(&can_sign.taproot))
             }
         })
     }
@@ -609,37 +1720,85 @@ impl AssetProvider<DefiniteDescriptorKey> for Assets {
         self.has_taproot_script_key(pk, tap_leaf_hash)
     }
 
+    fn provider_lookup_ecdsa_adaptor_sig(&self, pk: &DefiniteDescriptorKey) -> Option<PublicKey> {
+        self.adaptor_for_key(pk)
+    }
+
+    fn provider_lookup_tap_leaf_script_adaptor_sig(
+        &self,
+        pk: &DefiniteDescriptorKey,
+        _: &TapLeafHash,
+    ) -> Option<(PublicKey, usize)> {
+        self.adaptor_for_key(pk)
+            .map(|adaptor| (adaptor, tap_sig_len(self.tap_sighash_type)))
+    }
+
     fn provider_lookup_sha256(&self, hash: &sha256::Hash) -> bool {
-        self.sha256_preimages.contains(hash)
+        self.sha256_preimages.contains(hash) || self.unspecified_preimages > 0
     }
 
     fn provider_lookup_hash256(&self, hash: &hash256::Hash) -> bool {
-        self.hash256_preimages.contains(hash)
+        self.hash256_preimages.contains(hash) || self.unspecified_preimages > 0
     }
 
     fn provider_lookup_ripemd160(&self, hash: &ripemd160::Hash) -> bool {
-        self.ripemd160_preimages.contains(hash)
+        self.ripemd160_preimages.contains(hash) || self.unspecified_preimages > 0
     }
 
     fn provider_lookup_hash160(&self, hash: &hash160::Hash) -> bool {
-        self.hash160_preimages.contains(hash)
+        self.hash160_preimages.contains(hash) || self.unspecified_preimages > 0
     }
 
     fn check_older(&self, s: relative::LockTime) -> bool {
-        if let Some(timelock) = self.relative_timelock {
-            s.is_implied_by(timelock)
-        } else {
-            false
-        }
+        self.relative_timelock
+            .iter()
+            .chain(&self.relative_timelock_bounds)
+            .any(|timelock| s.is_implied_by(*timelock))
     }
 
     fn check_after(&self, l: absolute::LockTime) -> bool {
-        if let Some(timelock) = self.absolute_timelock {
-            l.is_implied_by(timelock)
-        } else {
-            false
+        self.absolute_timelock
+            .iter()
+            .chain(&self.absolute_timelock_bounds)
+            .any(|timelock| l.is_implied_by(*timelock))
+    }
+
+    fn provider_accepts_plan(&self, plan: &Plan) -> bool {
+        let mut used = vec![];
+        for asset in plan.used_assets() {
+            if !used.contains(&asset) {
+                used.push(asset);
+            }
         }
+        let unspecified = used
+            .iter()
+            .filter(|asset| match asset {
+                PlanAsset::Sha256Preimage(hash) => !self.sha256_preimages.contains(hash),
+                PlanAsset::Hash256Preimage(hash) => !self.hash256_preimages.contains(hash),
+                PlanAsset::Ripemd160Preimage(hash) => !self.ripemd160_preimages.contains(hash),
+                PlanAsset::Hash160Preimage(hash) => !self.hash160_preimages.contains(hash),
+                _ => false,
+            })
+            .count();
+        unspecified <= self.unspecified_preimages
+            && self.signature_limits.iter().all(|limit| {
+                let sigs = used
+                    .iter()
+                    .filter(|asset| {
+                        matches!(asset, PlanAsset::Signature(pk, _) if limit.contains(pk))
+                    })
+                    .count();
+                sigs <= limit.max
+            })
+    }
+
+    fn provider_lookup_key_priority(&self, pk: &DefiniteDescriptorKey) -> Option<i32> {
+        self.key_priority(pk)
     }
+
+    fn provider_preference_tolerance(&self) -> usize { self.preference_tolerance }
+
+    fn provider_lookup_tap_sighash_type(&self) -> Option<TapSighashType> { self.tap_sighash_type }
 }
 
 impl FromIterator<DescriptorPublicKey> for Assets {
@@ -723,8 +1882,94 @@ impl Assets {
         self
     }
 
+    /// Assume any absolute timelock of the same unit as `lt`, up to `lt`, to be mature
+    ///
+    /// Unlike [`Assets::after`], this replaces only a previous bound of the same unit, so that
+    /// bounds can be set for both block heights and block times. This allows planning spends
+    /// which only become possible in the future.
+    pub fn after_max(mut self, lt: absolute::LockTime) -> Self {
+        self.absolute_timelock_bounds.retain(|bound| !bound.is_same_unit(lt));
+        self.absolute_timelock_bounds.push(lt);
+        self
+    }
+
+    /// Assume any relative timelock of the same unit as `delta`, up to `delta`, to be mature
+    ///
+    /// Unlike [`Assets::older`], this replaces only a previous bound of the same unit, so that
+    /// bounds can be set for both blocks and time intervals. This allows planning spends which
+    /// only become possible once the output is old enough, such as recovery paths.
+    pub fn older_max(mut self, delta: relative::LockTime) -> Self {
+        self.relative_timelock_bounds.retain(|bound| !bound.is_same_unit(delta));
+        self.relative_timelock_bounds.push(delta);
+        self
+    }
+
+    /// Add an adaptor signature produced by `key`, with the adaptor point `adaptor`
+    pub fn adaptor(mut self, key: DescriptorPublicKey, adaptor: PublicKey) -> Self {
+        for deriv_path in key.full_derivation_paths() {
+            self.adaptors
+                .insert((key.master_fingerprint(), deriv_path), adaptor);
+        }
+        self
+    }
+
+    /// Add keys of which at most `max` can sign
+    ///
+    /// Plans using signatures from more than `max` of the `keys` are skipped by
+    /// [`Descriptor::plan`] and [`Descriptor::plans`].
+    pub fn at_most(mut self, max: usize, keys: Vec<DescriptorPublicKey>) -> Self {
+        let keys = keys.into_assets().keys;
+        self.signature_limits.push(SignatureLimit {
+            keys: keys.iter().map(|(keysource, _)| keysource.clone()).collect(),
+            max,
+        });
+        self.keys.extend(keys);
+        self
+    }
+
+    /// Allow plans to use up to `count` preimages of hashes which are not known in advance
+    pub fn unspecified_preimages(mut self, count: usize) -> Self {
+        self.unspecified_preimages = count;
+        self
+    }
+
+    /// Set the priority of the signatures of `key`
+    ///
+    /// When several sets of keys can satisfy a threshold, plans use the one with the highest sum
+    /// of priorities among those costing at most [`Assets::preference_tolerance`] more witness
+    /// bytes than the cheapest one. Keys have a priority of 0 by default, so a negative priority
+    /// avoids a key, e.g. a cold storage one, whenever possible.
+    pub fn prefer(mut self, key: DescriptorPublicKey, priority: i32) -> Self {
+        for deriv_path in key.full_derivation_paths() {
+            self.key_priorities
+                .insert((key.master_fingerprint(), deriv_path), priority);
+        }
+        self
+    }
+
+    /// Set how many extra witness bytes the key priorities may cost
+    pub fn preference_tolerance(mut self, tolerance: usize) -> Self {
+        self.preference_tolerance = tolerance;
+        self
+    }
+
+    /// Set the sighash type of all taproot signatures
+    ///
+    /// Signatures using another sighash type than `SIGHASH_DEFAULT` are one byte longer, which
+    /// the satisfaction weight of plans accounts for.
+    pub fn tap_sighash_type(mut self, sighash_type: TapSighashType) -> Self {
+        self.tap_sighash_type = Some(sighash_type);
+        self
+    }
+
     fn append(&mut self, b: Self) {
         self.keys.extend(b.keys);
+        self.adaptors.extend(b.adaptors);
+        self.signature_limits.extend(b.signature_limits);
+        self.unspecified_preimages += b.unspecified_preimages;
+        self.key_priorities.extend(b.key_priorities);
+        self.preference_tolerance = self.preference_tolerance.max(b.preference_tolerance);
+        self.tap_sighash_type = b.tap_sighash_type.or(self.tap_sighash_type);
         self.sha256_preimages.extend(b.sha256_preimages);
         self.hash256_preimages.extend(b.hash256_preimages);
         self.ripemd160_preimages.extend(b.ripemd160_preimages);
@@ -732,6 +1977,40 @@ impl Assets {
 
         self.relative_timelock = b.relative_timelock.or(self.relative_timelock);
         self.absolute_timelock = b.absolute_timelock.or(self.absolute_timelock);
+        for lt in b.absolute_timelock_bounds {
+            self.absolute_timelock_bounds.retain(|bound| !bound.is_same_unit(lt));
+            self.absolute_timelock_bounds.push(lt);
+        }
+        for delta in b.relative_timelock_bounds {
+            self.relative_timelock_bounds.retain(|bound| !bound.is_same_unit(delta));
+            self.relative_timelock_bounds.push(delta);
+        }
+    }
+}
+
+/// Serializes a map as a sequence of pairs, for maps whose keys are not strings
+#[cfg(feature = "serde")]
+mod serde_pairs {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use crate::prelude::*;
+
+    pub fn serialize<K, V, S>(map: &BTreeMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        K: Serialize,
+        V: Serialize,
+        S: Serializer,
+    {
+        serializer.collect_seq(map)
+    }
+
+    pub fn deserialize<'de, K, V, D>(deserializer: D) -> Result<BTreeMap<K, V>, D::Error>
+    where
+        K: Deserialize<'de> + Ord,
+        V: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        Vec::<(K, V)>::deserialize(deserializer).map(|pairs| pairs.into_iter().collect())
     }
 }
 
@@ -777,7 +2056,10 @@ mod test {
 
             let result = desc.clone().plan(&assets);
             assert_eq!(
-                result.as_ref().ok().map(|plan| plan.satisfaction_weight()),
+                result
+                    .as_ref()
+                    .ok()
+                    .map(|plan| plan.satisfaction_weight().to_wu() as usize),
                 expected,
                 "{:#?}",
                 result
@@ -995,23 +2277,26 @@ mod test {
         // expected weight: 4 (scriptSig len) + 1 (witness len) + 1 (OP_PUSH) + 64 (sig)
         let internal_key_sat_weight = Some(70);
         // expected weight: 4 (scriptSig len) + 1 (witness len) + 1 (OP_PUSH) + 64 (sig)
-        // + 34 [script: 1 (OP_PUSHBYTES_32) + 32 (key) + 1 (OP_CHECKSIG)]
+        // + 1 (script len) + 34 [script: 1 (OP_PUSHBYTES_32) + 32 (key) + 1 (OP_CHECKSIG)]
+        // + 1 (control block len)
         // + 65 [control block: 1 (control byte) + 32 (internal key) + 32 (hash BC)]
-        let first_leaf_sat_weight = Some(169);
+        let first_leaf_sat_weight = Some(171);
         // expected weight: 4 (scriptSig len) + 1 (witness len) + 1 (OP_PUSH) + 64 (sig)
         // + 1 (OP_ZERO)
-        // + 70 [script: 1 (OP_PUSHBYTES_32) + 32 (key) + 1 (OP_CHECKSIG)
+        // + 1 (script len) + 70 [script: 1 (OP_PUSHBYTES_32) + 32 (key) + 1 (OP_CHECKSIG)
         //       + 1 (OP_PUSHBYTES_32) + 32 (key) + 1 (OP_CHECKSIGADD)
         //       + 1 (OP_PUSHNUM1) + 1 (OP_NUMEQUAL)]
+        // + 1 (control block len)
         // + 97 [control block: 1 (control byte) + 32 (internal key) + 32 (hash C) + 32 (hash
         //       A)]
-        let second_leaf_sat_weight = Some(238);
+        let second_leaf_sat_weight = Some(240);
         // expected weight: 4 (scriptSig len) + 1 (witness len) + 1 (OP_PUSH) + 64 (sig)
-        // + 36 [script: 1 (OP_PUSHBYTES_32) + 32 (key) + 1 (OP_CHECKSIGVERIFY)
+        // + 1 (script len) + 36 [script: 1 (OP_PUSHBYTES_32) + 32 (key) + 1 (OP_CHECKSIGVERIFY)
         //       + 1 (OP_PUSHNUM_10) + 1 (OP_CLTV)]
+        // + 1 (control block len)
         // + 97 [control block: 1 (control byte) + 32 (internal key) + 32 (hash B) + 32 (hash
         //       A)]
-        let third_leaf_sat_weight = Some(203);
+        let third_leaf_sat_weight = Some(205);
 
         let tests = vec![
             // Don't give assets
@@ -1066,6 +2351,52 @@ mod test {
         test_inner(&desc, keys, hashes, tests);
     }
 
+    #[test]
+    fn taproot_sigops_budget() {
+        let keys = [
+            "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+            "0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a",
+            "033ad2d191da4f39512adbaac320cae1f12f298386a4e9d43fd98dec7cf5db2ac9",
+            "023fc33527afab09fa97135f2180bcd22ce637b1d2fbcb2db748b1f2c33f45b2b4",
+        ]
+        .map(|k| DescriptorPublicKey::from_str(k).unwrap());
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "tr({},{{pk({}),multi_a(2,{},{})}})",
+            keys[0], keys[1], keys[2], keys[3]
+        ))
+        .unwrap();
+
+        // Key spends have no sigops budget.
+        let plan = desc
+            .clone()
+            .plan(&Assets::new().add(keys[0].clone()))
+            .unwrap();
+        assert_eq!(plan.sigops_budget(), None);
+
+        let assets = Assets::new().add(vec![keys[2].clone(), keys[3].clone()]);
+        let plan = desc.clone().plan(&assets).unwrap();
+        let budget = plan.sigops_budget().unwrap();
+        assert_eq!(budget.sigops, 2);
+        assert_eq!(budget.used, 100);
+        // witness: 1 (stack len) + 2 * 65 (sigs) + 71 (script) + 66 (control block)
+        assert_eq!(budget.available, 50 + 1 + 2 * 65 + 71 + 66);
+        assert_eq!(budget.remaining(), Some(218));
+
+        // The same budget is computed from the satisfaction of the leaf alone.
+        let leaf = desc
+            .tap_tree_iter()
+            .find(|leaf| matches!(leaf.miniscript().unwrap().node, Terminal::MultiA(..)))
+            .unwrap();
+        let satisfaction = leaf.miniscript().unwrap().build_template(&assets);
+        match satisfaction.stack {
+            crate::miniscript::satisfy::Witness::Stack(stack) => assert_eq!(
+                Tap::satisfaction_sigops_budget(leaf.miniscript().unwrap(), &stack, leaf.depth()),
+                budget
+            ),
+            _ => panic!("leaf should be satisfiable"),
+        }
+    }
+
     #[test]
     fn test_hash() {
         let keys = vec![DescriptorPublicKey::from_str(
@@ -1090,6 +2421,189 @@ mod test {
         test_inner(&desc, keys, hashes, tests);
     }
 
+    #[test]
+    fn test_signing_requests() {
+        use bitcoin::Sequence;
+
+        let root_xpub = Xpub::from_str("xpub661MyMwAqRbcFkPHucMnrGNzDwb6teAX1RbKQmqtEF8kK3Z7LZ59qafCjB9eCRLiTVG3uxBxgKvRgbubRhqSKXnGGb1aoaqLrpMBDrVxga8").unwrap();
+        let fingerprint = root_xpub.fingerprint();
+        let xpub = format!("[{}/86'/0'/0']xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ", fingerprint);
+        let key = DescriptorPublicKey::from_str(&format!("{}/0/0", xpub)).unwrap();
+        let hash = sha256::Hash::from_slice(&[1; 32]).unwrap();
+        let older = Sequence(144).to_relative_lock_time().unwrap();
+
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "wsh(and_v(v:pkh({}),and_v(v:sha256({}),older(144))))",
+            key, hash
+        ))
+        .unwrap();
+        let assets = Assets::new().add(key.clone()).add(hash).older(older);
+        let requests = desc.plan(&assets).unwrap().signing_requests();
+        assert_eq!(requests.signatures.len(), 1);
+        let request = &requests.signatures[0];
+        assert_eq!(request.key.as_ref().map(|pk| pk.as_descriptor_public_key()), Some(&key));
+        assert_eq!(request.kind, SignatureKind::Ecdsa);
+        assert_eq!(request.sighash_type, None);
+        assert_eq!(
+            request.key_origins,
+            vec![(fingerprint, bip32::DerivationPath::from_str("m/86'/0'/0'/0/0").unwrap())]
+        );
+        assert_eq!(requests.sha256_preimages, vec![hash]);
+        assert!(requests.hash160_preimages.is_empty());
+        assert_eq!(requests.relative_timelock, Some(older));
+        assert_eq!(requests.absolute_timelock, None);
+
+        let internal_key = DescriptorPublicKey::from_str(
+            "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+        )
+        .unwrap();
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "tr({},pk({}))",
+            internal_key, key
+        ))
+        .unwrap();
+        let leaf_hash = desc.tap_tree_iter().next().unwrap().compute_tap_leaf_hash();
+
+        let plan = desc.clone().plan(&Assets::new().add(key.clone())).unwrap();
+        let requests = plan.signing_requests();
+        assert_eq!(requests.signatures.len(), 1);
+        assert_eq!(requests.signatures[0].kind, SignatureKind::TapScriptSpend { leaf_hash });
+        assert_eq!(
+            requests.signatures[0].sighash_type,
+            Some(bitcoin::TapSighashType::Default.into())
+        );
+        assert_eq!(requests.signatures[0].key_origins.len(), 1);
+
+        let plan = desc.plan(&Assets::new().add(internal_key.clone())).unwrap();
+        let requests = plan.signing_requests();
+        assert_eq!(requests.signatures.len(), 1);
+        assert!(matches!(requests.signatures[0].kind, SignatureKind::TapKeySpend { .. }));
+        assert_eq!(
+            requests.signatures[0]
+                .key
+                .as_ref()
+                .map(|pk| pk.as_descriptor_public_key()),
+            Some(&internal_key)
+        );
+    }
+
+    #[test]
+    fn test_adaptor_signatures() {
+        let keys = [
+            "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+            "0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a",
+        ]
+        .map(|k| DescriptorPublicKey::from_str(k).unwrap());
+        let adaptor = PublicKey::from_str(
+            "03500a2b48b0f66c8183cc0d6645ab21cc19c7fad8a33ff04d41c3ece54b0bc1c5",
+        )
+        .unwrap();
+
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "wsh(and_v(v:pk({}),pk({})))",
+            keys[0], keys[1]
+        ))
+        .unwrap();
+        assert!(desc.clone().plan(&Assets::new().add(keys[0].clone())).is_err());
+        let assets = Assets::new()
+            .add(keys[0].clone())
+            .adaptor(keys[1].clone(), adaptor);
+        let plan = desc.plan(&assets).unwrap();
+        // The adapted signature is accounted for like any other.
+        // expected weight: 4 (scriptSig len) + 1 (witness len) + 73 (sig) * 2
+        assert_eq!(plan.satisfaction_weight().to_wu(), 4 + 1 + 73 * 2);
+        assert!(matches!(
+            plan.witness_template()[0],
+            Placeholder::EcdsaAdaptorSigPk(ref pk, a)
+                if pk.as_descriptor_public_key() == &keys[1] && a == adaptor
+        ));
+        let requests = plan.signing_requests();
+        assert_eq!(requests.signatures.len(), 2);
+        assert_eq!(requests.signatures[0].adaptor, Some(adaptor));
+        assert_eq!(requests.signatures[1].adaptor, None);
+        // The plan cannot be completed until the signature is adapted.
+        assert!(matches!(plan.satisfy(&()), Err(Error::CouldNotSatisfy)));
+
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "tr({},pk({}))",
+            keys[0], keys[1]
+        ))
+        .unwrap();
+        let leaf_hash = desc.tap_tree_iter().next().unwrap().compute_tap_leaf_hash();
+        let with_key = desc
+            .clone()
+            .plan(&Assets::new().add(keys[1].clone()))
+            .unwrap();
+        let plan = desc
+            .plan(&Assets::new().adaptor(keys[1].clone(), adaptor))
+            .unwrap();
+        assert_eq!(plan.satisfaction_weight(), with_key.satisfaction_weight());
+        assert!(matches!(
+            plan.witness_template()[0],
+            Placeholder::SchnorrAdaptorSigPk(_, lh, a, 64) if lh == leaf_hash && a == adaptor
+        ));
+        assert_eq!(plan.sigops_budget(), with_key.sigops_budget());
+    }
+
+    #[test]
+    fn test_plans() {
+        use bitcoin::Sequence;
+
+        let keys = [
+            "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+            "0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a",
+            "03500a2b48b0f66c8183cc0d6645ab21cc19c7fad8a33ff04d41c3ece54b0bc1c5",
+        ]
+        .map(|k| DescriptorPublicKey::from_str(k).unwrap());
+        let definite = |i: usize| DefiniteDescriptorKey::from_str(&keys[i].to_string()).unwrap();
+        let hash = sha256::Hash::from_slice(&[1; 32]).unwrap();
+        let older = Sequence(144).to_relative_lock_time().unwrap();
+        let assets = Assets::new().add(keys.to_vec()).add(hash).older(older);
+
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "wsh(or_d(pk({}),and_v(v:pk({}),and_v(v:sha256({}),older(144)))))",
+            keys[0], keys[1], hash
+        ))
+        .unwrap();
+        let plans = desc.plans(&assets, 10).collect::<Vec<_>>();
+        assert_eq!(plans.len(), 2);
+        assert!(plans[0].satisfaction_weight() < plans[1].satisfaction_weight());
+        assert_eq!(plans[0].used_assets(), vec![PlanAsset::Signature(definite(0), None)]);
+        assert_eq!(
+            plans[1].used_assets(),
+            vec![
+                PlanAsset::Sha256Preimage(hash),
+                PlanAsset::Signature(definite(1), None),
+                PlanAsset::RelativeTimelock(older),
+            ]
+        );
+        assert_eq!(desc.plans(&assets, 1).count(), 1);
+        assert_eq!(desc.plans(&Assets::new(), 10).count(), 0);
+
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "tr({},{{pk({}),{{pk({}),and_v(v:pk({}),pk({}))}}}})",
+            keys[0], keys[1], keys[2], keys[1], keys[2]
+        ))
+        .unwrap();
+        let plans = desc.plans(&assets, 10).collect::<Vec<_>>();
+        assert_eq!(plans.len(), 4);
+        assert!(plans
+            .windows(2)
+            .all(|w| w[0].satisfaction_weight() <= w[1].satisfaction_weight()));
+        assert_eq!(plans[0].used_assets(), vec![PlanAsset::Signature(definite(0), None)]);
+
+        // A plan which doesn't reveal the second key can be picked.
+        let assets = Assets::new().add(vec![keys[1].clone(), keys[2].clone()]);
+        let plans = desc.plans(&assets, 10).collect::<Vec<_>>();
+        assert_eq!(plans.len(), 3);
+        let reveals_key = |plan: &Plan| {
+            plan.used_assets()
+                .iter()
+                .any(|asset| matches!(asset, PlanAsset::Signature(pk, _) if *pk == definite(1)))
+        };
+        assert_eq!(plans.iter().map(reveals_key).collect::<Vec<_>>(), vec![true, false, true]);
+    }
+
     #[test]
     fn test_plan_update_psbt_tr() {
         // keys taken from: https://github.com/bitcoin/bips/blob/master/bip-0086.mediawiki#Specifications
@@ -1138,6 +2652,174 @@ mod test {
         assert_eq!(psbt_input.tap_scripts.len(), 1, "Unexpected number of tap_scripts");
     }
 
+    #[test]
+    fn test_tap_leaf_spend() {
+        let keys = [
+            "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+            "0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a",
+            "03500a2b48b0f66c8183cc0d6645ab21cc19c7fad8a33ff04d41c3ece54b0bc1c5",
+        ]
+        .map(|k| DescriptorPublicKey::from_str(k).unwrap());
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "tr({},{{pk({}),pk({})}})",
+            keys[0], keys[1], keys[2]
+        ))
+        .unwrap();
+        let tr = match desc {
+            Descriptor::Tr(ref tr) => tr.clone(),
+            _ => unreachable!(),
+        };
+        let spend_info = tr.spend_info();
+        let secp = secp256k1::Secp256k1::verification_only();
+
+        // Key spends don't use a leaf
+        let plan = desc.clone().plan(&Assets::new().add(keys[0].clone())).unwrap();
+        assert_eq!(plan.tap_leaf_spend(), None);
+
+        let plan = desc.plan(&Assets::new().add(keys[2].clone())).unwrap();
+        let spend = plan.tap_leaf_spend().unwrap();
+        let leaf = tr.leaves().nth(1).unwrap();
+        assert_eq!(spend.script, leaf.compute_script());
+        assert_eq!(spend.leaf_version, LeafVersion::TapScript);
+        assert_eq!(spend.leaf_hash, leaf.compute_tap_leaf_hash());
+        assert_eq!(
+            Some(&spend.control_block),
+            spend_info.control_block(&(spend.script.clone(), spend.leaf_version)).as_ref()
+        );
+        assert_eq!(spend.merkle_branch().len(), 1);
+        let output_key = spend_info.output_key().to_x_only_public_key();
+        assert!(spend
+            .control_block
+            .verify_taproot_commitment(&secp, output_key, &spend.script));
+        assert_eq!(
+            plan.witness_template().last(),
+            Some(&Placeholder::TapControlBlock(spend.control_block.clone()))
+        );
+        assert_eq!(spend.serialized_control_block().len(), 33 + 32);
+    }
+
+    #[test]
+    fn test_raw_tap_leaf() {
+        let keys = [
+            "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+            "0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a",
+        ]
+        .map(|k| DescriptorPublicKey::from_str(k).unwrap());
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "tr({},{{raw_leaf(51),pk({})}})",
+            keys[0], keys[1]
+        ))
+        .unwrap();
+
+        // The key path can still be planned
+        let plan = desc.clone().plan(&Assets::new().add(keys[0].clone())).unwrap();
+        assert_eq!(plan.tap_leaf_spend(), None);
+
+        // Only the Miniscript leaf can be spent by its script path
+        let plan = desc.clone().plan(&Assets::new().add(keys[1].clone())).unwrap();
+        let spend = plan.tap_leaf_spend().unwrap();
+        let leaf = desc.tap_tree_iter().nth(1).unwrap();
+        assert_eq!(spend.script, leaf.compute_script());
+        assert_eq!(spend.merkle_branch().len(), 1);
+        assert!(desc.plan(&Assets::new()).is_err());
+    }
+
+    #[test]
+    fn test_plan_tap_spend() {
+        let keys = [
+            "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+            "0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a",
+            "03500a2b48b0f66c8183cc0d6645ab21cc19c7fad8a33ff04d41c3ece54b0bc1c5",
+        ]
+        .map(|k| DescriptorPublicKey::from_str(k).unwrap());
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "tr({},{{pk({}),and_v(v:pk({}),older(10))}})",
+            keys[0], keys[1], keys[2]
+        ))
+        .unwrap();
+        let fee_rate = bitcoin::FeeRate::from_sat_per_vb_u32(10);
+        let fee = |plan: &Plan| fee_rate.fee_wu(plan.satisfaction_weight()).unwrap();
+        let auto = TapSpendPath::default();
+
+        // The key path is selected when available, unless the script path is forced
+        let assets = Assets::new().add(keys.to_vec());
+        let spend = desc.clone().plan_tap_spend(&assets, auto, fee_rate).unwrap();
+        assert!(spend.plan.tap_leaf_spend().is_none());
+        assert_eq!(spend.key_path_fee, Some(fee(&spend.plan)));
+        let script_path_fee = spend.script_path_fee.unwrap();
+        assert!(script_path_fee > spend.key_path_fee.unwrap());
+
+        let spend = desc
+            .clone()
+            .plan_tap_spend(&assets, TapSpendPath::ScriptPath, fee_rate)
+            .unwrap();
+        assert_eq!(spend.plan.used_assets().len(), 1);
+        assert!(spend.plan.tap_leaf_spend().is_some());
+        assert_eq!(fee(&spend.plan), script_path_fee);
+
+        // Without the internal key only the script path is available
+        let assets = Assets::new().add(keys[1..].to_vec());
+        assert!(desc
+            .clone()
+            .plan_tap_spend(&assets, TapSpendPath::KeyPath, fee_rate)
+            .is_err());
+        let spend = desc.clone().plan_tap_spend(&assets, auto, fee_rate).unwrap();
+        assert_eq!(spend.key_path_fee, None);
+        assert_eq!(spend.script_path_fee, Some(script_path_fee));
+
+        // Unless it costs more than allowed
+        let limited = |max| TapSpendPath::Auto { max_script_path_fee: Some(max) };
+        assert!(desc
+            .clone()
+            .plan_tap_spend(&assets, limited(script_path_fee), fee_rate)
+            .is_ok());
+        assert!(desc
+            .clone()
+            .plan_tap_spend(&assets, limited(script_path_fee - Amount::ONE_SAT), fee_rate)
+            .is_err());
+
+        // Only Taproot descriptors can be spent through either path
+        let desc =
+            Descriptor::<DefiniteDescriptorKey>::from_str(&format!("wpkh({})", keys[0])).unwrap();
+        assert!(desc.plan_tap_spend(&assets, auto, fee_rate).is_err());
+    }
+
+    #[test]
+    fn test_input_fee() {
+        let key = "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c";
+        let assets = Assets::new().add(DescriptorPublicKey::from_str(key).unwrap());
+        let plan = |desc: &str| {
+            Descriptor::<DefiniteDescriptorKey>::from_str(&desc.replace("K", key))
+                .unwrap()
+                .plan(&assets)
+                .unwrap()
+        };
+        let fee_rate = bitcoin::FeeRate::from_sat_per_kwu(1000);
+
+        // outpoint and sequence (160) + scriptSig
+        let pkh = plan("pkh(K)");
+        assert_eq!(pkh.input_weight(), Weight::from_wu(160) + pkh.satisfaction_weight());
+        assert_eq!(pkh.input_fee(fee_rate), Some(Amount::from_sat(592)));
+
+        // outpoint and sequence (160) + empty scriptSig (4) + witness + segwit marker and flag (2)
+        let wpkh = plan("wpkh(K)");
+        assert_eq!(wpkh.input_weight(), Weight::from_wu(160 + 4 + 108 + 2));
+        assert_eq!(
+            wpkh.effective_value(fee_rate, Amount::from_sat(10_000)),
+            Some(SignedAmount::from_sat(10_000 - 274))
+        );
+        assert_eq!(
+            wpkh.effective_value(fee_rate, Amount::from_sat(100)),
+            Some(SignedAmount::from_sat(100 - 274))
+        );
+
+        // Script path spends include the leaf script and control block in their witness
+        let tr = plan("tr(0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a,pk(K))");
+        assert!(tr.witness_size() > 33 + 34 + 64);
+        assert_eq!(tr.input_weight(), Weight::from_wu(160 + 2) + tr.satisfaction_weight());
+        assert_eq!(tr.input_fee(FeeRate::MAX), None);
+    }
+
     #[test]
     fn test_plan_update_psbt_segwit() {
         // keys taken from: https://github.com/bitcoin/bips/blob/master/bip-0086.mediawiki#Specifications
@@ -1159,4 +2841,528 @@ mod test {
         assert!(psbt_input.redeem_script.is_none(), "Redeem script present");
         assert_eq!(psbt_input.bip32_derivation.len(), 2, "Unexpected number of bip32_derivation");
     }
+
+    #[test]
+    fn test_assets_limits() {
+        let keys = [
+            "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+            "0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a",
+            "03500a2b48b0f66c8183cc0d6645ab21cc19c7fad8a33ff04d41c3ece54b0bc1c5",
+        ]
+        .map(|k| DescriptorPublicKey::from_str(k).unwrap());
+        let hashes = [1, 2].map(|b| sha256::Hash::from_slice(&[b; 32]).unwrap());
+        let older = relative::LockTime::from_height(10);
+        let plan = |desc: &str, assets: &Assets| {
+            Descriptor::<DefiniteDescriptorKey>::from_str(desc)
+                .unwrap()
+                .plan(assets)
+                .ok()
+                .map(|plan| plan.used_assets())
+        };
+        let sig = |i: usize| {
+            let key = DefiniteDescriptorKey::from_str(&keys[i].to_string()).unwrap();
+            PlanAsset::Signature(key, None)
+        };
+
+        // Ranged keys can sign at any index
+        let xpub = DescriptorPublicKey::from_str("[abcdef00/84'/0'/0']xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ/0/*").unwrap();
+        let desc = Descriptor::<DescriptorPublicKey>::from_str(&format!("wpkh({})", xpub)).unwrap();
+        let assets = Assets::new().add(xpub);
+        for index in [0, 7, 1000] {
+            assert!(desc.at_derivation_index(index).unwrap().plan(&assets).is_ok());
+        }
+
+        // At most one of the first two keys can sign, so the second branch must be used
+        let desc = format!(
+            "wsh(or_d(multi(2,{},{}),and_v(v:pk({}),older(10))))",
+            keys[0], keys[1], keys[2]
+        );
+        let assets = Assets::new().add(keys.to_vec());
+        assert_eq!(plan(&desc, &assets), Some(vec![sig(0), sig(1)]));
+        let assets = Assets::new()
+            .at_most(1, keys[..2].to_vec())
+            .add(keys[2].clone());
+        assert_eq!(plan(&desc, &assets), None);
+        assert_eq!(
+            plan(&desc, &assets.older(older)),
+            Some(vec![sig(2), PlanAsset::RelativeTimelock(older)])
+        );
+
+        // Preimages of unknown hashes
+        let desc = format!(
+            "wsh(and_v(v:sha256({}),and_v(v:sha256({}),pk({}))))",
+            hashes[0], hashes[1], keys[0]
+        );
+        let assets = Assets::new().add(keys[0].clone());
+        assert_eq!(plan(&desc, &assets), None);
+        assert_eq!(plan(&desc, &assets.clone().unspecified_preimages(1)), None);
+        assert!(plan(&desc, &assets.clone().unspecified_preimages(2)).is_some());
+        assert!(plan(&desc, &assets.add(hashes[0]).unspecified_preimages(1)).is_some());
+    }
+
+    #[test]
+    fn test_timelock_bounds() {
+        let key = "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c";
+        let recovery = "0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a";
+        let assets = Assets::new().add(DescriptorPublicKey::from_str(recovery).unwrap());
+        let plan = |desc: &str, assets: &Assets| {
+            Descriptor::<DefiniteDescriptorKey>::from_str(desc)
+                .unwrap()
+                .plan(assets)
+                .ok()
+        };
+
+        // A recovery path maturing after about six months
+        let six_months = relative::LockTime::from_height(26_280);
+        let desc = format!("wsh(or_d(pk({}),and_v(v:pk({}),older(26280))))", key, recovery);
+        assert!(plan(&desc, &assets).is_none());
+        let one_day = relative::LockTime::from_height(144);
+        assert!(plan(&desc, &assets.clone().older_max(one_day)).is_none());
+        let recover = plan(&desc, &assets.clone().older_max(six_months)).unwrap();
+        assert_eq!(recover.relative_timelock, Some(six_months));
+
+        // Bounds of both units can be set at the same time
+        let time = absolute::LockTime::from_time(1_900_000_000).unwrap();
+        let height = absolute::LockTime::from_height(1_000_000).unwrap();
+        let both = assets.clone().after_max(time).after_max(height);
+        assert_eq!(both.absolute_timelock_bounds, vec![time, height]);
+        let after_time = format!("wsh(and_v(v:pk({}),after(1800000000)))", recovery);
+        let after_height = format!("wsh(and_v(v:pk({}),after(900000)))", recovery);
+        assert!(plan(&after_time, &both).is_some());
+        assert!(plan(&after_height, &both).is_some());
+        assert!(plan(&after_time, &assets.clone().after(height)).is_none());
+
+        // Setting a bound replaces the one of the same unit
+        let lower = absolute::LockTime::from_height(800_000).unwrap();
+        let replaced = both.after_max(lower);
+        assert_eq!(replaced.absolute_timelock_bounds, vec![time, lower]);
+        assert!(plan(&after_height, &replaced).is_none());
+        assert!(plan(&after_time, &replaced).is_some());
+    }
+
+    #[test]
+    fn test_key_priorities() {
+        let keys = [
+            "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+            "0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a",
+            "03500a2b48b0f66c8183cc0d6645ab21cc19c7fad8a33ff04d41c3ece54b0bc1c5",
+        ]
+        .map(|key| DescriptorPublicKey::from_str(key).unwrap());
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "wsh(multi(2,{},{},{}))",
+            keys[0], keys[1], keys[2]
+        ))
+        .unwrap();
+        let signers = |plan: &Plan| {
+            plan.template
+                .iter()
+                .filter_map(|placeholder| match placeholder {
+                    Placeholder::EcdsaSigPk(pk) => Some(pk.as_descriptor_public_key().clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let assets = Assets::new().add(keys.to_vec());
+        let plan = desc.clone().plan(&assets).unwrap();
+        assert_eq!(signers(&plan), vec![keys[0].clone(), keys[1].clone()]);
+        // Prefer the two online keys over the cold one
+        let assets = assets.prefer(keys[0].clone(), -1).prefer(keys[2].clone(), 1);
+        assert_eq!(assets.key_priorities.len(), 2);
+        let plan = desc.plan(&assets).unwrap();
+        assert_eq!(signers(&plan), vec![keys[1].clone(), keys[2].clone()]);
+    }
+
+    #[test]
+    fn test_tap_sighash_type() {
+        let key = DescriptorPublicKey::from_str(
+            "03500a2b48b0f66c8183cc0d6645ab21cc19c7fad8a33ff04d41c3ece54b0bc1c5",
+        )
+        .unwrap();
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!("tr({})", key)).unwrap();
+        let max_weight = |sighash_type| {
+            let sizes = SigSizes::WORST_CASE.with_tap_sighash_type(sighash_type);
+            desc.max_weight_to_satisfy_with_sig_sizes(&sizes).unwrap()
+        };
+
+        let assets = Assets::new().add(key);
+        let plan = desc.clone().plan(&assets).unwrap();
+        assert_eq!(
+            plan.signing_requests().signatures[0].sighash_type,
+            Some(TapSighashType::Default.into())
+        );
+
+        // SIGHASH_SINGLE signatures carry their sighash type
+        let assets = assets.tap_sighash_type(TapSighashType::Single);
+        let single = desc.clone().plan(&assets).unwrap();
+        assert_eq!(single.satisfaction_weight(), plan.satisfaction_weight() + Weight::from_wu(1));
+        assert_eq!(
+            max_weight(TapSighashType::Single),
+            max_weight(TapSighashType::Default) + Weight::from_wu(1)
+        );
+        assert_eq!(single.signing_requests().signatures[0].sighash_type, None);
+    }
+
+    #[test]
+    fn test_dummy_satisfaction() {
+        let keys = [
+            "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+            "0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a",
+            "03500a2b48b0f66c8183cc0d6645ab21cc19c7fad8a33ff04d41c3ece54b0bc1c5",
+        ]
+        .map(|key| DescriptorPublicKey::from_str(key).unwrap());
+        let hash = sha256::Hash::hash(&[1; 32]);
+        let assets = Assets::new().add(keys[0].clone()).add(hash);
+        let descriptors = [
+            format!("pkh({})", keys[0]),
+            format!("sh(wpkh({}))", keys[0]),
+            format!("sh(and_v(v:pk({}),sha256({})))", keys[0], hash),
+            format!("wsh(or_d(pk({}),and_v(v:pkh({}),sha256({}))))", keys[1], keys[0], hash),
+            format!("tr({},{{pk({}),and_v(v:pk({}),pk({}))}})", keys[1], keys[0], keys[2], keys[0]),
+        ];
+        for desc in descriptors {
+            let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&desc).unwrap();
+            let plan = desc.clone().plan(&assets).unwrap();
+            let (witness, script_sig) = plan.dummy_satisfaction();
+            let txin = bitcoin::TxIn {
+                script_sig,
+                witness: bitcoin::Witness::from_slice(&witness),
+                ..Default::default()
+            };
+            match plan.witness_version() {
+                Some(_) => {
+                    assert_eq!(txin.segwit_weight() + Weight::from_wu(2), plan.input_weight())
+                }
+                None => assert_eq!(txin.legacy_weight(), plan.input_weight()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        let keys = [
+            "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+            "0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a",
+        ]
+        .map(|key| DescriptorPublicKey::from_str(key).unwrap());
+        let assets = Assets::new()
+            .add(keys[0].clone())
+            .after(absolute::LockTime::from_height(10).unwrap());
+
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "wsh(or_d(pk({}),and_v(v:pk({}),after(10))))",
+            keys[1], keys[0]
+        ))
+        .unwrap();
+        let plan = desc.clone().plan(&assets).unwrap();
+        assert_eq!(
+            plan.to_string(),
+            format!(
+                "{} using signature by {}, absolute timelock 10; satisfaction weight 79 wu",
+                desc, keys[0]
+            )
+        );
+
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "tr({},pk({}))",
+            keys[1], keys[0]
+        ))
+        .unwrap();
+        let plan = desc.clone().plan(&assets).unwrap();
+        let spend = plan.tap_leaf_spend().unwrap();
+        assert_eq!(
+            plan.to_string(),
+            format!(
+                "{} by the script path of leaf {} ({}) using signature by {} for leaf {}; \
+                 satisfaction weight 139 wu",
+                desc, spend.leaf_hash, spend.script, keys[0], spend.leaf_hash
+            )
+        );
+        let plan = desc.clone().plan(&Assets::new().add(keys[1].clone())).unwrap();
+        assert_eq!(
+            plan.to_string(),
+            format!(
+                "{} by the key path using signature by {}; satisfaction weight 70 wu",
+                desc, keys[1]
+            )
+        );
+    }
+
+    #[test]
+    fn test_tx_timelocks() {
+        let key = DescriptorPublicKey::from_str(
+            "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+        )
+        .unwrap();
+        let plan = |desc: String, assets: &Assets| {
+            Descriptor::<DefiniteDescriptorKey>::from_str(&desc)
+                .unwrap()
+                .plan(assets)
+                .unwrap()
+        };
+        let assets = Assets::new()
+            .add(key.clone())
+            .older(relative::LockTime::from_height(144))
+            .after(absolute::LockTime::from_height(1000).unwrap());
+        let plans = [
+            plan(format!("wsh(and_v(v:pk({}),older(144)))", key), &assets),
+            plan(format!("wsh(and_v(v:pk({}),after(1000)))", key), &assets),
+            plan(format!("wpkh({})", key), &assets),
+            plan(format!("wsh(and_v(v:pk({}),after(500)))", key), &assets),
+        ];
+        assert_eq!(plans[0].sequence(), Sequence::from_height(144));
+        assert_eq!(plans[0].lock_time(), absolute::LockTime::ZERO);
+        assert_eq!(plans[1].lock_time(), absolute::LockTime::from_height(1000).unwrap());
+        assert_eq!(
+            TxTimelocks::from_plans(&plans),
+            Ok(TxTimelocks {
+                lock_time: absolute::LockTime::from_height(1000).unwrap(),
+                sequences: vec![
+                    Sequence::from_height(144),
+                    Sequence::ENABLE_LOCKTIME_NO_RBF,
+                    Sequence::MAX,
+                    Sequence::ENABLE_LOCKTIME_NO_RBF,
+                ],
+            })
+        );
+
+        // A transaction cannot have both a height and a time as its locktime
+        let time = absolute::LockTime::from_time(1_700_000_000).unwrap();
+        let timed = plan(
+            format!("wsh(and_v(v:pk({}),after(1700000000)))", key),
+            &Assets::new().add(key.clone()).after(time),
+        );
+        assert_eq!(
+            TxTimelocks::from_plans(plans.iter().chain([&timed])),
+            Err(LockTimeConflict {
+                index: 4,
+                lock_time: time,
+                other: absolute::LockTime::from_height(1000).unwrap(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_missing_assets() {
+        let keys = [
+            "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+            "0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a",
+            "03500a2b48b0f66c8183cc0d6645ab21cc19c7fad8a33ff04d41c3ece54b0bc1c5",
+            "033ad2d191da4f39512adbaac320cae1f12f298386a4e9d43fd98dec7cf5db2ac9",
+        ]
+        .map(|key| DescriptorPublicKey::from_str(key).unwrap());
+        let definite = |i: usize| keys[i].clone().at_derivation_index(0).unwrap();
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "wsh(or_d(multi(2,{},{},{}),and_v(v:pk({}),older(144))))",
+            keys[0], keys[1], keys[2], keys[3]
+        ))
+        .unwrap();
+
+        // Either of the other cosigners can complete the multisig
+        let assets = Assets::new().add(keys[0].clone());
+        assert!(desc.clone().plan(&assets).is_err());
+        let missing = desc.missing_assets(&assets);
+        assert_eq!(missing.len(), 2);
+        assert_eq!(missing[0].missing, vec![PlanAsset::Signature(definite(1), None)]);
+        assert_eq!(missing[1].missing, vec![PlanAsset::Signature(definite(2), None)]);
+        assert_eq!(missing[0].to_string(), format!("missing signature by {}", keys[1]));
+
+        // The recovery key has to wait for its timelock
+        let assets = Assets::new().add(keys[3].clone());
+        let missing = desc.missing_assets(&assets);
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].signatures().count(), 0);
+        assert_eq!(
+            missing[0].immature_relative_timelock(),
+            Some(relative::LockTime::from_height(144))
+        );
+        let assets = assets.older(relative::LockTime::from_height(144));
+        let missing = desc.missing_assets(&assets);
+        assert!(missing[0].is_empty());
+        assert_eq!(missing[0].plan, desc.clone().plan(&assets).unwrap());
+
+        // Preimages are reported too
+        let hash = sha256::Hash::hash(&[0xab; 32]);
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "wsh(and_v(v:pk({}),sha256({})))",
+            keys[0], hash
+        ))
+        .unwrap();
+        let missing = desc.missing_assets(&Assets::new());
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].signatures().count(), 1);
+        assert_eq!(
+            missing[0].preimages().collect::<Vec<_>>(),
+            vec![&PlanAsset::Sha256Preimage(hash)]
+        );
+    }
+
+    #[test]
+    fn test_plan_at_indices() {
+        let xpub = "xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ";
+        let desc = Descriptor::<DescriptorPublicKey>::from_str(&format!(
+            "wsh(or_d(pk({}/0/*),and_v(v:pk({}/1/*),older(10))))",
+            xpub, xpub
+        ))
+        .unwrap();
+        let older = relative::LockTime::from_height(10);
+        let first = DescriptorPublicKey::from_str(&format!("{}/0/3", xpub)).unwrap();
+        let second = DescriptorPublicKey::from_str(&format!("{}/1/*", xpub)).unwrap();
+
+        // Only the index the derived key was given for can be planned
+        let assets = Assets::new().add(first.clone()).add(second.clone());
+        let plans = desc.plan_at_indices(&assets, [5, 3, 0, 3]).unwrap();
+        assert_eq!(plans.keys().copied().collect::<Vec<_>>(), vec![3]);
+        assert_eq!(plans[&3].descriptor, desc.at_derivation_index(3).unwrap());
+
+        // With the timelock every index can use the second branch
+        let assets = assets.older(older);
+        let plans = desc.plan_at_indices(&assets, 0..6).unwrap();
+        assert_eq!(plans.len(), 6);
+        for (index, plan) in &plans {
+            assert_eq!(plan.descriptor, desc.at_derivation_index(*index).unwrap());
+            let uses_timelock = plan.relative_timelock == Some(older);
+            assert_eq!(uses_timelock, *index != 3);
+        }
+
+        // Hardened indices can't be derived
+        assert_eq!(
+            desc.plan_at_indices(&assets, [0, 1 << 31]),
+            Err(descriptor::ConversionError::HardenedChild)
+        );
+
+        // Descriptors without wildcards share a single plan
+        let desc =
+            Descriptor::<DescriptorPublicKey>::from_str(&format!("wpkh({})", first)).unwrap();
+        let plans = desc.plan_at_indices(&assets, [1, 2]).unwrap();
+        assert_eq!(plans.len(), 2);
+        assert_eq!(plans[&1], plans[&2]);
+        assert!(desc.plan_at_indices(&Assets::new(), [1, 2]).unwrap().is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_plan_serde() {
+        use serde_test::{assert_tokens, Configure, Token};
+
+        use crate::miniscript::satisfy::{Satisfaction, Witness};
+        use crate::RelLockTime;
+
+        let key = "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c";
+        let desc = "wpkh(02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c)#eeywfh2q";
+        let plan = Descriptor::<DefiniteDescriptorKey>::from_str(desc)
+            .unwrap()
+            .plan(&Assets::new().add(DescriptorPublicKey::from_str(key).unwrap()))
+            .unwrap();
+        assert_tokens(
+            &plan,
+            &[
+                Token::Struct { name: "Plan", len: 4 },
+                Token::Str("template"),
+                Token::Seq { len: Some(2) },
+                Token::NewtypeVariant { name: "Placeholder", variant: "EcdsaSigPk" },
+                Token::Str(key),
+                Token::TupleVariant { name: "Placeholder", variant: "Pubkey", len: 2 },
+                Token::Str(key),
+                Token::U64(34),
+                Token::TupleVariantEnd,
+                Token::SeqEnd,
+                Token::Str("absolute_timelock"),
+                Token::None,
+                Token::Str("relative_timelock"),
+                Token::None,
+                Token::Str("descriptor"),
+                Token::Str(desc),
+                Token::StructEnd,
+            ],
+        );
+
+        let sat = Satisfaction::<Placeholder<DefiniteDescriptorKey>> {
+            stack: Witness::Stack(vec![Placeholder::PushOne]),
+            has_sig: false,
+            absolute_timelock: None,
+            relative_timelock: Some(RelLockTime::from_height(144)),
+        };
+        assert_tokens(
+            &sat,
+            &[
+                Token::Struct { name: "Satisfaction", len: 4 },
+                Token::Str("stack"),
+                Token::NewtypeVariant { name: "Witness", variant: "Stack" },
+                Token::Seq { len: Some(1) },
+                Token::UnitVariant { name: "Placeholder", variant: "PushOne" },
+                Token::SeqEnd,
+                Token::Str("has_sig"),
+                Token::Bool(false),
+                Token::Str("absolute_timelock"),
+                Token::None,
+                Token::Str("relative_timelock"),
+                Token::Some,
+                Token::U32(144),
+                Token::StructEnd,
+            ],
+        );
+
+        let adaptor = bitcoin::secp256k1::PublicKey::from_str(key).unwrap();
+        let origin = (
+            bip32::Fingerprint::from([1, 2, 3, 4]),
+            bip32::DerivationPath::from_str("m/84'/0'/0'").unwrap(),
+        );
+        let mut assets = Assets::new();
+        assets.adaptors.insert(origin, adaptor);
+        assert_tokens(
+            &assets.readable(),
+            &[
+                Token::Struct { name: "Assets", len: 15 },
+                Token::Str("keys"),
+                Token::Seq { len: Some(0) },
+                Token::SeqEnd,
+                Token::Str("sha256_preimages"),
+                Token::Seq { len: Some(0) },
+                Token::SeqEnd,
+                Token::Str("hash256_preimages"),
+                Token::Seq { len: Some(0) },
+                Token::SeqEnd,
+                Token::Str("ripemd160_preimages"),
+                Token::Seq { len: Some(0) },
+                Token::SeqEnd,
+                Token::Str("hash160_preimages"),
+                Token::Seq { len: Some(0) },
+                Token::SeqEnd,
+                Token::Str("absolute_timelock"),
+                Token::None,
+                Token::Str("relative_timelock"),
+                Token::None,
+                Token::Str("absolute_timelock_bounds"),
+                Token::Seq { len: Some(0) },
+                Token::SeqEnd,
+                Token::Str("relative_timelock_bounds"),
+                Token::Seq { len: Some(0) },
+                Token::SeqEnd,
+                Token::Str("adaptors"),
+                Token::Seq { len: Some(1) },
+                Token::Tuple { len: 2 },
+                Token::Tuple { len: 2 },
+                Token::Str("01020304"),
+                Token::Str("84'/0'/0'"),
+                Token::TupleEnd,
+                Token::Str(key),
+                Token::TupleEnd,
+                Token::SeqEnd,
+                Token::Str("signature_limits"),
+                Token::Seq { len: Some(0) },
+                Token::SeqEnd,
+                Token::Str("unspecified_preimages"),
+                Token::U64(0),
+                Token::Str("key_priorities"),
+                Token::Seq { len: Some(0) },
+                Token::SeqEnd,
+                Token::Str("preference_tolerance"),
+                Token::U64(0),
+                Token::Str("tap_sighash_type"),
+                Token::None,
+                Token::StructEnd,
+            ],
+        );
+    }
 }
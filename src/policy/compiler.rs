@@ -490,6 +490,7 @@ impl CompilerExtData {
             Terminal::Thresh(ref thresh) => {
                 Self::threshold(thresh.k(), thresh.n(), |n| get_child(&thresh.data()[n].node, n))
             }
+            Terminal::Ext(e) => match e {},
         }
     }
 }
@@ -27,13 +27,24 @@ use crate::sync::Arc;
 #[cfg(all(doc, not(feature = "compiler")))]
 use crate::Descriptor;
 use crate::{
-    AbsLockTime, Error, ForEachKey, FromStrKey, MiniscriptKey, RelLockTime, Threshold, Translator,
+    AbsLockTime, Error, ForEachKey, FromStrKey, MiniscriptKey, ParsePolicyError, RelLockTime,
+    Threshold, Translator,
 };
 
 /// Maximum TapLeafs allowed in a compiled TapTree
 #[cfg(feature = "compiler")]
 const MAX_COMPILATION_LEAVES: usize = 1024;
 
+/// Maximum nesting depth of a concrete policy parsed from a string.
+///
+/// Miniscripts, descriptors and semantic policies are handled without
+/// recursion, and have no depth limit. Concrete policies are still compiled
+/// and dropped recursively, so their parser keeps this limit. It only applies
+/// to parsing: a deeper policy constructed directly may still overflow the
+/// stack when compiled or dropped.
+// https://github.com/sipa/miniscript/pull/5 for discussion on this number
+const MAX_PARSE_DEPTH: u32 = 402;
+
 /// Concrete policy which corresponds directly to a miniscript structure,
 /// and whose disjunctions are annotated with satisfaction probabilities
 /// to assist the compiler.
@@ -124,7 +135,7 @@ impl error::Error for PolicyError {
 }
 
 #[cfg(feature = "compiler")]
-struct TapleafProbabilityIter<'p, Pk: MiniscriptKey> {
+pub(crate) struct TapleafProbabilityIter<'p, Pk: MiniscriptKey> {
     stack: Vec<(f64, &'p Policy<Pk>)>,
 }
 
@@ -180,7 +191,7 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
     /// Since this splitting might lead to exponential blow-up, we constrain the number of
     /// leaf-nodes to [`MAX_COMPILATION_LEAVES`].
     #[cfg(feature = "compiler")]
-    fn tapleaf_probability_iter(&self) -> TapleafProbabilityIter<Pk> {
+    pub(crate) fn tapleaf_probability_iter(&self) -> TapleafProbabilityIter<Pk> {
         TapleafProbabilityIter { stack: vec![(1.0, self)] }
     }
 
@@ -833,8 +844,11 @@ impl<Pk: MiniscriptKey> fmt::Display for Policy<Pk> {
 
 impl<Pk: FromStrKey> str::FromStr for Policy<Pk> {
     type Err = Error;
+    /// Parses a policy, rejecting policies nested more than 402 levels deep
+    /// with [`crate::ParseTreeError::MaxRecursionDepthExceeded`].
     fn from_str(s: &str) -> Result<Policy<Pk>, Error> {
-        let tree = expression::Tree::from_str(s)?;
+        let options = expression::ParseOptions::new().max_depth(MAX_PARSE_DEPTH);
+        let tree = expression::Tree::from_str_with_options(s, options)?;
         let policy: Policy<Pk> = FromTree::from_tree(tree.root())?;
         policy.check_timelocks().map_err(Error::ConcretePolicy)?;
         Ok(policy)
@@ -844,10 +858,11 @@ impl<Pk: FromStrKey> str::FromStr for Policy<Pk> {
 serde_string_impl_pk!(Policy, "a miniscript concrete policy");
 
 impl<Pk: FromStrKey> expression::FromTree for Policy<Pk> {
-    fn from_tree(root: expression::TreeIterItem) -> Result<Policy<Pk>, Error> {
+    type Error = ParsePolicyError;
+    fn from_tree(root: expression::TreeIterItem) -> Result<Policy<Pk>, ParsePolicyError> {
         root.verify_no_curly_braces()
             .map_err(From::from)
-            .map_err(Error::Parse)?;
+            .map_err(ParsePolicyError::Parse)?;
 
         let mut stack = Vec::<(usize, _)>::with_capacity(128);
         for node in root.pre_order_iter().rev() {
@@ -863,7 +878,7 @@ impl<Pk: FromStrKey> expression::FromTree for Policy<Pk> {
                 let (_, parent_name) = parent
                     .name_separated('@')
                     .map_err(From::from)
-                    .map_err(Error::Parse)?;
+                    .map_err(ParsePolicyError::Parse)?;
                 if node.is_first_child() && parent_name == "thresh" {
                     continue;
                 }
@@ -876,7 +891,7 @@ impl<Pk: FromStrKey> expression::FromTree for Policy<Pk> {
             let (frag_prob, frag_name) = if allow_prob {
                 node.name_separated('@')
                     .map_err(From::from)
-                    .map_err(Error::Parse)?
+                    .map_err(ParsePolicyError::Parse)?
             } else {
                 (None, node.name())
             };
@@ -885,7 +900,7 @@ impl<Pk: FromStrKey> expression::FromTree for Policy<Pk> {
                 None => 1,
                 Some(s) => expression::parse_num(s)
                     .map_err(From::from)
-                    .map_err(Error::Parse)? as usize,
+                    .map_err(ParsePolicyError::Parse)? as usize,
             };
 
             let new =
@@ -893,53 +908,59 @@ impl<Pk: FromStrKey> expression::FromTree for Policy<Pk> {
                     "UNSATISFIABLE" => {
                         node.verify_n_children("UNSATISFIABLE", 0..=0)
                             .map_err(From::from)
-                            .map_err(Error::Parse)?;
+                            .map_err(ParsePolicyError::Parse)?;
                         Ok(Policy::Unsatisfiable)
                     }
                     "TRIVIAL" => {
                         node.verify_n_children("TRIVIAL", 0..=0)
                             .map_err(From::from)
-                            .map_err(Error::Parse)?;
+                            .map_err(ParsePolicyError::Parse)?;
                         Ok(Policy::Trivial)
                     }
                     "pk" => node
                         .verify_terminal_parent("pk", "public key")
                         .map(Policy::Key)
-                        .map_err(Error::Parse),
-                    "after" => node.verify_after().map_err(Error::Parse).map(Policy::After),
-                    "older" => node.verify_older().map_err(Error::Parse).map(Policy::Older),
+                        .map_err(ParsePolicyError::Parse),
+                    "after" => node
+                        .verify_after()
+                        .map_err(ParsePolicyError::Parse)
+                        .map(Policy::After),
+                    "older" => node
+                        .verify_older()
+                        .map_err(ParsePolicyError::Parse)
+                        .map(Policy::Older),
                     "sha256" => node
                         .verify_terminal_parent("sha256", "hash")
                         .map(Policy::Sha256)
-                        .map_err(Error::Parse),
+                        .map_err(ParsePolicyError::Parse),
                     "hash256" => node
                         .verify_terminal_parent("hash256", "hash")
                         .map(Policy::Hash256)
-                        .map_err(Error::Parse),
+                        .map_err(ParsePolicyError::Parse),
                     "ripemd160" => node
                         .verify_terminal_parent("ripemd160", "hash")
                         .map(Policy::Ripemd160)
-                        .map_err(Error::Parse),
+                        .map_err(ParsePolicyError::Parse),
                     "hash160" => node
                         .verify_terminal_parent("hash160", "hash")
                         .map(Policy::Hash160)
-                        .map_err(Error::Parse),
+                        .map_err(ParsePolicyError::Parse),
                     "and" => {
                         node.verify_n_children("and", 2..=2)
                             .map_err(From::from)
-                            .map_err(Error::Parse)?;
+                            .map_err(ParsePolicyError::Parse)?;
                         Ok(Policy::And(vec![stack.pop().unwrap().1, stack.pop().unwrap().1]))
                     }
                     "or" => {
                         node.verify_n_children("or", 2..=2)
                             .map_err(From::from)
-                            .map_err(Error::Parse)?;
+                            .map_err(ParsePolicyError::Parse)?;
                         Ok(Policy::Or(vec![stack.pop().unwrap(), stack.pop().unwrap()]))
                     }
                     "thresh" => node
                         .verify_threshold(|_| Ok(stack.pop().unwrap().1))
                         .map(Self::Thresh),
-                    x => Err(Error::Parse(crate::ParseError::Tree(
+                    x => Err(ParsePolicyError::Parse(crate::ParseError::Tree(
                         crate::ParseTreeError::UnknownName { name: x.to_owned() },
                     ))),
                 }?;
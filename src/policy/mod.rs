@@ -16,6 +16,8 @@ use std::error;
 #[cfg(feature = "compiler")]
 pub mod compiler;
 pub mod concrete;
+#[cfg(feature = "compiler")]
+pub mod report;
 pub mod semantic;
 
 pub use self::concrete::Policy as Concrete;
@@ -166,6 +168,7 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Liftable<Pk> for Miniscript<Pk, Ctx>
                         .map_ref(|key| Arc::new(Semantic::Key(key.clone())))
                         .forget_maximum(),
                 )),
+                Terminal::Ext(e) => match e {},
             };
             stack.push(new_term)
         }
@@ -182,6 +185,8 @@ impl<Pk: MiniscriptKey> Liftable<Pk> for Descriptor<Pk> {
             Descriptor::Wsh(ref wsh) => wsh.lift(),
             Descriptor::Sh(ref sh) => sh.lift(),
             Descriptor::Tr(ref tr) => tr.lift(),
+            Descriptor::Addr(_) => Err(Error::AddrNoScript),
+            Descriptor::Raw(_) => Err(Error::RawNoScript),
         }
     }
 }
@@ -304,7 +309,7 @@ mod tests {
             ConcretePol::from_str("thresh(2,pk(),thresh(0,pk()))")
                 .unwrap_err()
                 .to_string(),
-            "thresholds in Miniscript must have k > 0",
+            "threshold value must be positive (position 21)",
         );
         assert_eq!(
             ConcretePol::from_str("and(pk())").unwrap_err().to_string(),
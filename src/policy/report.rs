@@ -0,0 +1,545 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Dual-Context Compilation Reports
+//!
+//! Compiles a single concrete policy to both `wsh` and `tr` descriptors and
+//! compares the results: script sizes, satisfaction weights for every
+//! root-level spending branch, and what each spend reveals on-chain.
+//!
+//! This is intended to support decisions about migrating existing segwit v0
+//! wallets to Taproot. The numbers are computed with the same worst-case
+//! signature size assumptions as [`Descriptor::max_weight_to_satisfy`].
+//!
+
+use core::fmt;
+
+use bitcoin::hashes::hash160;
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::taproot::{TapLeafHash, TAPROOT_CONTROL_BASE_SIZE, TAPROOT_CONTROL_NODE_SIZE};
+use bitcoin::{absolute, relative, secp256k1, Weight};
+
+use crate::descriptor::{Descriptor, WshInner};
+use crate::iter::TreeLike;
+use crate::miniscript::satisfy::{Placeholder, Preimage32, Satisfaction, Satisfier, Witness};
+use crate::policy::Concrete;
+use crate::prelude::*;
+use crate::util::{varint_len, ItemSize};
+use crate::{Error, MiniscriptKey, ScriptContext, Segwitv0, Tap, ToPublicKey};
+
+/// Side-by-side comparison of the `wsh` and `tr` compilations of a policy.
+///
+/// Constructed by [`Concrete::compile_report`]. The [`fmt::Display`]
+/// implementation renders the report as a plain-text table.
+#[derive(Clone, Debug)]
+pub struct CompilationReport<Pk: MiniscriptKey> {
+    /// Summary of the `wsh` compilation.
+    pub wsh: ContextSummary<Pk>,
+    /// Summary of the `tr` compilation.
+    pub tr: ContextSummary<Pk>,
+    /// Per-branch satisfaction weights, one entry for each root-level
+    /// disjunctive branch of the policy.
+    pub branches: Vec<BranchReport<Pk>>,
+    /// Notes about what spends from each output type reveal on-chain.
+    pub privacy_notes: Vec<PrivacyNote>,
+}
+
+/// Summary of a single compiled descriptor within a [`CompilationReport`].
+#[derive(Clone, Debug)]
+pub struct ContextSummary<Pk: MiniscriptKey> {
+    /// The compiled descriptor.
+    pub descriptor: Descriptor<Pk>,
+    /// Total size, in bytes, of all scripts committed to by the output.
+    ///
+    /// For `wsh` this is the witness script; for `tr` this is the sum of the
+    /// sizes of all leaf scripts.
+    pub total_script_size: usize,
+    /// Size, in bytes, of the largest script that a single spend may reveal.
+    pub max_revealed_script_size: usize,
+    /// Upper bound on the weight needed to satisfy the descriptor.
+    pub max_weight_to_satisfy: Weight,
+    /// Satisfaction weight averaged over all branches, weighted by the branch
+    /// probabilities of the policy.
+    ///
+    /// `None` if no branch could be satisfied in this context.
+    pub expected_weight_to_satisfy: Option<Weight>,
+}
+
+/// Satisfaction weights of a single root-level branch of the policy.
+#[derive(Clone, Debug)]
+pub struct BranchReport<Pk: MiniscriptKey> {
+    /// The sub-policy making up this branch.
+    pub policy: Concrete<Pk>,
+    /// The probability of this branch being used, derived from the policy odds.
+    pub probability: f64,
+    /// Weight of the witness needed to spend the `wsh` output using this branch.
+    pub wsh_weight: Option<Weight>,
+    /// Weight of the witness needed to spend the `tr` output using this branch.
+    pub tr_weight: Option<Weight>,
+    /// Whether the branch is spent using the Taproot key path.
+    pub tr_key_spend: bool,
+}
+
+/// A note about the on-chain privacy of a compiled descriptor.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum PrivacyNote {
+    /// Every `wsh` spend reveals the entire witness script, including all
+    /// unused branches.
+    WshRevealsFullScript,
+    /// The Taproot internal key is taken from the policy, so spends using it
+    /// are indistinguishable from single-key spends.
+    TrKeySpendAvailable,
+    /// The Taproot internal key is unspendable, so every spend reveals that the
+    /// output is a script.
+    TrUnspendableInternalKey,
+    /// Taproot script-path spends only reveal the leaf that was used.
+    TrRevealsSingleLeaf {
+        /// Number of leaves in the tap tree.
+        n_leaves: usize,
+    },
+}
+
+impl fmt::Display for PrivacyNote {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PrivacyNote::WshRevealsFullScript => {
+                f.write_str("wsh: every spend reveals the full witness script")
+            }
+            PrivacyNote::TrKeySpendAvailable => {
+                f.write_str("tr: key-path spends are indistinguishable from single-key outputs")
+            }
+            PrivacyNote::TrUnspendableInternalKey => {
+                f.write_str("tr: internal key is unspendable; every spend reveals a script")
+            }
+            PrivacyNote::TrRevealsSingleLeaf { n_leaves } => {
+                write!(f, "tr: script-path spends reveal only the used leaf out of {}", n_leaves)
+            }
+        }
+    }
+}
+
+impl<Pk: ToPublicKey> CompilationReport<Pk> {
+    /// Compiles `policy` to both `wsh` and `tr` and builds the comparison.
+    ///
+    /// `unspendable_key` is used as the Taproot internal key if none can be
+    /// inferred from the policy, as in [`Concrete::compile_tr`].
+    pub fn new(policy: &Concrete<Pk>, unspendable_key: Option<Pk>) -> Result<Self, Error> {
+        let wsh = Descriptor::new_wsh(policy.compile()?)?;
+        let tr = policy.compile_tr(unspendable_key)?;
+
+        let wsh_ms = match wsh {
+            Descriptor::Wsh(ref wsh) => match wsh.as_inner() {
+                WshInner::Ms(ms) => ms,
+                WshInner::SortedMulti(..) => unreachable!("compiler does not output sortedmulti"),
+            },
+            _ => unreachable!("new_wsh returns a wsh descriptor"),
+        };
+        let tr_inner = match tr {
+            Descriptor::Tr(ref tr) => tr,
+            _ => unreachable!("compile_tr returns a tr descriptor"),
+        };
+
+        let mut branches = vec![];
+        for (probability, branch) in policy.tapleaf_probability_iter() {
+            if *branch == Concrete::Unsatisfiable {
+                continue;
+            }
+            let key_spend = match branch {
+                Concrete::Key(ref pk) => pk == tr_inner.internal_key(),
+                _ => false,
+            };
+            let assets = BranchAssets { branch, key_spend };
+
+            let wsh_weight = segwit_weight(wsh_ms.build_template(&assets), wsh_ms.script_size());
+            let (tr_weight, tr_key_spend) = if key_spend {
+                // stack len varint diff + <sig(64)+sighash(1)> with its length prefix
+                (Some(Weight::from_wu((varint_len(1) - varint_len(0) + 1 + 65) as u64)), true)
+            } else {
+                let weight = tr_inner
+                    .leaves()
+                    .filter_map(|leaf| {
+                        let ms = leaf.miniscript()?;
+                        let cb_size = TAPROOT_CONTROL_BASE_SIZE
+                            + leaf.depth() as usize * TAPROOT_CONTROL_NODE_SIZE;
+                        // The control block is one extra stack element after the script.
+                        segwit_weight(ms.build_template(&assets), ms.script_size())
+                            .map(|w| w + Weight::from_wu((varint_len(cb_size) + cb_size) as u64))
+                    })
+                    .min();
+                (weight, false)
+            };
+
+            branches.push(BranchReport {
+                policy: branch.clone(),
+                probability,
+                wsh_weight,
+                tr_weight,
+                tr_key_spend,
+            });
+        }
+
+        let n_leaves = tr_inner.leaves().count();
+        let mut privacy_notes = vec![PrivacyNote::WshRevealsFullScript];
+        if policy.keys().contains(&tr_inner.internal_key()) {
+            privacy_notes.push(PrivacyNote::TrKeySpendAvailable);
+        } else {
+            privacy_notes.push(PrivacyNote::TrUnspendableInternalKey);
+        }
+        if n_leaves > 1 {
+            privacy_notes.push(PrivacyNote::TrRevealsSingleLeaf { n_leaves });
+        }
+
+        let wsh = ContextSummary {
+            total_script_size: wsh_ms.script_size(),
+            max_revealed_script_size: wsh_ms.script_size(),
+            max_weight_to_satisfy: wsh.max_weight_to_satisfy()?,
+            expected_weight_to_satisfy: expected_weight(
+                branches.iter().map(|b| (b.probability, b.wsh_weight)),
+            ),
+            descriptor: wsh,
+        };
+        let tr = ContextSummary {
+            total_script_size: tr_inner
+                .leaves()
+                .filter_map(|leaf| leaf.miniscript())
+                .map(|ms| ms.script_size())
+                .sum(),
+            max_revealed_script_size: tr_inner
+                .leaves()
+                .filter_map(|leaf| leaf.miniscript())
+                .map(|ms| ms.script_size())
+                .max()
+                .unwrap_or(0),
+            max_weight_to_satisfy: tr.max_weight_to_satisfy()?,
+            expected_weight_to_satisfy: expected_weight(
+                branches.iter().map(|b| (b.probability, b.tr_weight)),
+            ),
+            descriptor: tr,
+        };
+
+        Ok(CompilationReport { wsh, tr, branches, privacy_notes })
+    }
+}
+
+impl<Pk: MiniscriptKey> fmt::Display for CompilationReport<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn opt_wu(w: Option<Weight>) -> String {
+            w.map(|w| format!("{} WU", w.to_wu()))
+                .unwrap_or_else(|| "n/a".to_owned())
+        }
+
+        writeln!(f, "{:<28}{:>14}{:>14}", "", "wsh", "tr")?;
+        writeln!(
+            f,
+            "{:<28}{:>14}{:>14}",
+            "total script size",
+            format!("{} B", self.wsh.total_script_size),
+            format!("{} B", self.tr.total_script_size)
+        )?;
+        writeln!(
+            f,
+            "{:<28}{:>14}{:>14}",
+            "max revealed script size",
+            format!("{} B", self.wsh.max_revealed_script_size),
+            format!("{} B", self.tr.max_revealed_script_size)
+        )?;
+        writeln!(
+            f,
+            "{:<28}{:>14}{:>14}",
+            "max weight to satisfy",
+            opt_wu(Some(self.wsh.max_weight_to_satisfy)),
+            opt_wu(Some(self.tr.max_weight_to_satisfy))
+        )?;
+        writeln!(
+            f,
+            "{:<28}{:>14}{:>14}",
+            "expected weight to satisfy",
+            opt_wu(self.wsh.expected_weight_to_satisfy),
+            opt_wu(self.tr.expected_weight_to_satisfy)
+        )?;
+        for (i, branch) in self.branches.iter().enumerate() {
+            writeln!(
+                f,
+                "{:<28}{:>14}{:>14}{}",
+                format!("branch {} (p={:.3})", i, branch.probability),
+                opt_wu(branch.wsh_weight),
+                opt_wu(branch.tr_weight),
+                if branch.tr_key_spend {
+                    " (key path)"
+                } else {
+                    ""
+                }
+            )?;
+            writeln!(f, "    {}", branch.policy)?;
+        }
+        for note in &self.privacy_notes {
+            writeln!(f, "note: {}", note)?;
+        }
+        Ok(())
+    }
+}
+
+/// Computes the weight of a segwit witness consisting of the satisfaction
+/// stack followed by the script, as is done in `max_weight_to_satisfy`.
+fn segwit_weight<Pk: MiniscriptKey>(
+    satisfaction: Satisfaction<Placeholder<Pk>>,
+    script_size: usize,
+) -> Option<Weight> {
+    match satisfaction.stack {
+        Witness::Stack(stack) => {
+            let items_size = stack.iter().map(ItemSize::size).sum::<usize>();
+            // stack varint difference (+1 for the script element)
+            let stack_varint_diff = varint_len(stack.len() + 1) - varint_len(0);
+            Some(Weight::from_wu(
+                (stack_varint_diff + items_size + varint_len(script_size) + script_size) as u64,
+            ))
+        }
+        Witness::Unavailable | Witness::Impossible => None,
+    }
+}
+
+/// Probability-weighted average of the satisfiable branch weights.
+fn expected_weight<I: Iterator<Item = (f64, Option<Weight>)>>(iter: I) -> Option<Weight> {
+    let (total_prob, total_wu) = iter
+        .filter_map(|(prob, weight)| weight.map(|w| (prob, prob * w.to_wu() as f64)))
+        .fold((0.0, 0.0), |acc, (prob, wu)| (acc.0 + prob, acc.1 + wu));
+    if total_prob > 0.0 {
+        // The average is non-negative, so truncating after adding 0.5 rounds it
+        // to the nearest integer (`f64::round` is not available in `core`).
+        Some(Weight::from_wu((total_wu / total_prob + 0.5) as u64))
+    } else {
+        None
+    }
+}
+
+/// Satisfier which has exactly the assets required by a single branch of a
+/// policy.
+///
+/// Signatures and preimages are dummy values; this is only ever used to build
+/// witness templates, whose sizes do not depend on the actual values.
+struct BranchAssets<'p, Pk: MiniscriptKey> {
+    branch: &'p Concrete<Pk>,
+    key_spend: bool,
+}
+
+impl<Pk: MiniscriptKey> BranchAssets<'_, Pk> {
+    fn has_key(&self, pk: &Pk) -> bool {
+        self.branch
+            .pre_order_iter()
+            .any(|pol| matches!(pol, Concrete::Key(ref k) if k == pk))
+    }
+
+    fn has_key_hash<Ctx: ScriptContext>(&self, hash: &hash160::Hash) -> Option<Pk>
+    where
+        Pk: ToPublicKey,
+    {
+        self.branch.pre_order_iter().find_map(|pol| match pol {
+            Concrete::Key(ref k) if k.to_pubkeyhash(Ctx::sig_type()) == *hash => Some(k.clone()),
+            _ => None,
+        })
+    }
+
+    fn has_preimage(&self, pred: impl Fn(&Concrete<Pk>) -> bool) -> Option<Preimage32> {
+        self.branch
+            .pre_order_iter()
+            .find(|pol| pred(pol))
+            .map(|_| [0; 32])
+    }
+}
+
+fn dummy_ecdsa_sig() -> bitcoin::ecdsa::Signature {
+    bitcoin::ecdsa::Signature {
+        signature: secp256k1::ecdsa::Signature::from_compact(&[1; 64]).expect("valid signature"),
+        sighash_type: bitcoin::EcdsaSighashType::All,
+    }
+}
+
+// Uses a non-default sighash type so the signature has its worst-case size of 65 bytes.
+fn dummy_schnorr_sig() -> bitcoin::taproot::Signature {
+    bitcoin::taproot::Signature {
+        signature: secp256k1::schnorr::Signature::from_slice(&[1; 64]).expect("valid signature"),
+        sighash_type: bitcoin::TapSighashType::All,
+    }
+}
+
+impl<Pk: ToPublicKey> Satisfier<Pk> for BranchAssets<'_, Pk> {
+    fn lookup_ecdsa_sig(&self, pk: &Pk) -> Option<bitcoin::ecdsa::Signature> {
+        if self.has_key(pk) {
+            Some(dummy_ecdsa_sig())
+        } else {
+            None
+        }
+    }
+
+    fn lookup_tap_key_spend_sig(&self) -> Option<bitcoin::taproot::Signature> {
+        if self.key_spend {
+            Some(dummy_schnorr_sig())
+        } else {
+            None
+        }
+    }
+
+    fn lookup_tap_leaf_script_sig(
+        &self,
+        pk: &Pk,
+        _: &TapLeafHash,
+    ) -> Option<bitcoin::taproot::Signature> {
+        if self.has_key(pk) {
+            Some(dummy_schnorr_sig())
+        } else {
+            None
+        }
+    }
+
+    fn lookup_raw_pkh_pk(&self, hash: &hash160::Hash) -> Option<bitcoin::PublicKey> {
+        self.has_key_hash::<Segwitv0>(hash)
+            .map(|pk| pk.to_public_key())
+    }
+
+    fn lookup_raw_pkh_x_only_pk(&self, hash: &hash160::Hash) -> Option<XOnlyPublicKey> {
+        self.has_key_hash::<Tap>(hash)
+            .map(|pk| pk.to_x_only_pubkey())
+    }
+
+    fn lookup_raw_pkh_ecdsa_sig(
+        &self,
+        hash: &hash160::Hash,
+    ) -> Option<(bitcoin::PublicKey, bitcoin::ecdsa::Signature)> {
+        self.lookup_raw_pkh_pk(hash)
+            .map(|pk| (pk, dummy_ecdsa_sig()))
+    }
+
+    fn lookup_raw_pkh_tap_leaf_script_sig(
+        &self,
+        (hash, _): &(hash160::Hash, TapLeafHash),
+    ) -> Option<(XOnlyPublicKey, bitcoin::taproot::Signature)> {
+        self.lookup_raw_pkh_x_only_pk(hash)
+            .map(|pk| (pk, dummy_schnorr_sig()))
+    }
+
+    fn lookup_sha256(&self, hash: &Pk::Sha256) -> Option<Preimage32> {
+        self.has_preimage(|pol| matches!(pol, Concrete::Sha256(ref h) if h == hash))
+    }
+
+    fn lookup_hash256(&self, hash: &Pk::Hash256) -> Option<Preimage32> {
+        self.has_preimage(|pol| matches!(pol, Concrete::Hash256(ref h) if h == hash))
+    }
+
+    fn lookup_ripemd160(&self, hash: &Pk::Ripemd160) -> Option<Preimage32> {
+        self.has_preimage(|pol| matches!(pol, Concrete::Ripemd160(ref h) if h == hash))
+    }
+
+    fn lookup_hash160(&self, hash: &Pk::Hash160) -> Option<Preimage32> {
+        self.has_preimage(|pol| matches!(pol, Concrete::Hash160(ref h) if h == hash))
+    }
+
+    fn check_older(&self, s: relative::LockTime) -> bool {
+        self.branch.pre_order_iter().any(|pol| match pol {
+            Concrete::Older(t) => s.is_implied_by(relative::LockTime::from(*t)),
+            _ => false,
+        })
+    }
+
+    fn check_after(&self, l: absolute::LockTime) -> bool {
+        self.branch.pre_order_iter().any(|pol| match pol {
+            Concrete::After(t) => l.is_implied_by(absolute::LockTime::from(*t)),
+            _ => false,
+        })
+    }
+}
+
+impl<Pk: ToPublicKey> Concrete<Pk> {
+    /// Compiles the policy to both `wsh` and `tr` and returns a side-by-side
+    /// [`CompilationReport`] of the two.
+    ///
+    /// `unspendable_key` is used as the Taproot internal key if none can be
+    /// inferred from the policy, as in [`Concrete::compile_tr`].
+    pub fn compile_report(
+        &self,
+        unspendable_key: Option<Pk>,
+    ) -> Result<CompilationReport<Pk>, Error> {
+        CompilationReport::new(self, unspendable_key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use super::*;
+
+    fn keys(n: usize) -> Vec<bitcoin::PublicKey> {
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        (1..=n)
+            .map(|i| {
+                let mut sk = [0u8; 32];
+                sk[31] = i as u8;
+                let sk = bitcoin::secp256k1::SecretKey::from_slice(&sk).unwrap();
+                bitcoin::PublicKey::new(bitcoin::secp256k1::PublicKey::from_secret_key(&secp, &sk))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn report_key_and_timelocked_recovery() {
+        let k = keys(3);
+        let policy = Concrete::<bitcoin::PublicKey>::from_str(&format!(
+            "or(9@pk({}),1@and(thresh(1,pk({}),pk({})),older(1000)))",
+            k[0], k[1], k[2]
+        ))
+        .unwrap();
+        let report = policy.compile_report(None).unwrap();
+
+        assert_eq!(report.branches.len(), 2);
+        assert!((report.branches[0].probability - 0.9).abs() < 1e-9);
+        assert!(report.branches[0].tr_key_spend);
+        assert_eq!(report.branches[0].tr_weight, Some(Weight::from_wu(66)));
+        assert!(!report.branches[1].tr_key_spend);
+        for branch in &report.branches {
+            let wsh_weight = branch.wsh_weight.unwrap();
+            let tr_weight = branch.tr_weight.unwrap();
+            assert!(wsh_weight <= report.wsh.max_weight_to_satisfy);
+            assert!(tr_weight <= report.tr.max_weight_to_satisfy);
+            assert!(tr_weight < wsh_weight);
+        }
+        assert!(report.tr.expected_weight_to_satisfy < report.wsh.expected_weight_to_satisfy);
+        assert_eq!(
+            report.privacy_notes,
+            vec![
+                PrivacyNote::WshRevealsFullScript,
+                PrivacyNote::TrKeySpendAvailable
+            ]
+        );
+
+        let text = report.to_string();
+        assert!(text.contains("(key path)"));
+        assert!(text.contains("wsh: every spend reveals the full witness script"));
+    }
+
+    #[test]
+    fn report_unspendable_internal_key() {
+        let k = keys(4);
+        let policy = Concrete::<bitcoin::PublicKey>::from_str(&format!(
+            "or(and(pk({}),pk({})),and(pk({}),sha256({})))",
+            k[0], k[1], k[2], "1111111111111111111111111111111111111111111111111111111111111111",
+        ))
+        .unwrap();
+        let report = policy.compile_report(Some(k[3])).unwrap();
+
+        assert_eq!(report.branches.len(), 2);
+        assert!(report.branches.iter().all(|b| !b.tr_key_spend));
+        assert!(report
+            .branches
+            .iter()
+            .all(|b| b.wsh_weight.is_some() && b.tr_weight.is_some()));
+        // Each tr leaf only contains one branch, the wsh script contains both.
+        assert!(report.tr.max_revealed_script_size < report.wsh.max_revealed_script_size);
+        assert_eq!(
+            report.privacy_notes,
+            vec![
+                PrivacyNote::WshRevealsFullScript,
+                PrivacyNote::TrUnspendableInternalKey,
+                PrivacyNote::TrRevealsSingleLeaf { n_leaves: 2 },
+            ]
+        );
+    }
+}
@@ -14,8 +14,8 @@ use crate::iter::{Tree, TreeLike};
 use crate::prelude::*;
 use crate::sync::Arc;
 use crate::{
-    expression, AbsLockTime, Error, ForEachKey, FromStrKey, MiniscriptKey, RelLockTime, Threshold,
-    Translator,
+    expression, AbsLockTime, Error, ForEachKey, FromStrKey, MiniscriptKey, ParsePolicyError,
+    RelLockTime, Threshold, Translator,
 };
 
 /// Abstract policy which corresponds to the semantics of a miniscript and
@@ -48,6 +48,23 @@ pub enum Policy<Pk: MiniscriptKey> {
     Thresh(Threshold<Arc<Policy<Pk>>, 0>),
 }
 
+impl<Pk: MiniscriptKey> Drop for Policy<Pk> {
+    /// Drops the policy without recursion, so that dropping a deep policy
+    /// (e.g. one lifted from a deep Miniscript) cannot overflow the stack.
+    fn drop(&mut self) {
+        let mut stack = vec![];
+        if let Policy::Thresh(ref mut thresh) = *self {
+            stack.extend(thresh.take_data());
+        }
+        while let Some(child) = stack.pop() {
+            // Children shared with other policies are left for their last owner.
+            if let Ok(Policy::Thresh(ref mut thresh)) = Arc::try_unwrap(child) {
+                stack.extend(thresh.take_data());
+            }
+        }
+    }
+}
+
 impl<Pk: MiniscriptKey> ForEachKey<Pk> for Policy<Pk> {
     fn for_each_key<'a, F: FnMut(&'a Pk) -> bool>(&'a self, mut pred: F) -> bool {
         self.pre_order_iter().all(|policy| match policy {
@@ -205,20 +222,19 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
             panic!("should be unreachable")
         }
 
-        let ret =
-            match self {
-                Policy::Thresh(thresh) => Policy::Thresh(thresh.map(|sub| {
-                    Arc::new(sub.as_ref().clone().satisfy_constraint(witness, available))
-                })),
-                ref leaf if leaf == witness => {
-                    if available {
-                        Policy::Trivial
-                    } else {
-                        Policy::Unsatisfiable
-                    }
+        let ret = match self {
+            Policy::Thresh(ref thresh) => Policy::Thresh(thresh.map_ref(|sub| {
+                Arc::new(sub.as_ref().clone().satisfy_constraint(witness, available))
+            })),
+            ref leaf if leaf == witness => {
+                if available {
+                    Policy::Trivial
+                } else {
+                    Policy::Unsatisfiable
                 }
-                x => x,
-            };
+            }
+            x => x,
+        };
         ret.normalized()
     }
 }
@@ -277,17 +293,18 @@ impl<Pk: FromStrKey> str::FromStr for Policy<Pk> {
     type Err = Error;
     fn from_str(s: &str) -> Result<Policy<Pk>, Error> {
         let tree = expression::Tree::from_str(s)?;
-        expression::FromTree::from_tree(tree.root())
+        expression::FromTree::from_tree(tree.root()).map_err(Error::from)
     }
 }
 
 serde_string_impl_pk!(Policy, "a miniscript semantic policy");
 
 impl<Pk: FromStrKey> expression::FromTree for Policy<Pk> {
-    fn from_tree(root: expression::TreeIterItem) -> Result<Policy<Pk>, Error> {
+    type Error = ParsePolicyError;
+    fn from_tree(root: expression::TreeIterItem) -> Result<Policy<Pk>, ParsePolicyError> {
         root.verify_no_curly_braces()
             .map_err(From::from)
-            .map_err(Error::Parse)?;
+            .map_err(ParsePolicyError::Parse)?;
 
         let mut stack = Vec::with_capacity(128);
         for node in root.pre_order_iter().rev() {
@@ -308,73 +325,83 @@ impl<Pk: FromStrKey> expression::FromTree for Policy<Pk> {
                 "UNSATISFIABLE" => {
                     node.verify_n_children("UNSATISFIABLE", 0..=0)
                         .map_err(From::from)
-                        .map_err(Error::Parse)?;
+                        .map_err(ParsePolicyError::Parse)?;
                     Ok(Policy::Unsatisfiable)
                 }
                 "TRIVIAL" => {
                     node.verify_n_children("TRIVIAL", 0..=0)
                         .map_err(From::from)
-                        .map_err(Error::Parse)?;
+                        .map_err(ParsePolicyError::Parse)?;
                     Ok(Policy::Trivial)
                 }
                 "pk" => node
                     .verify_terminal_parent("pk", "public key")
                     .map(Policy::Key)
-                    .map_err(Error::Parse),
-                "after" => node.verify_after().map_err(Error::Parse).map(Policy::After),
-                "older" => node.verify_older().map_err(Error::Parse).map(Policy::Older),
+                    .map_err(ParsePolicyError::Parse),
+                "after" => node
+                    .verify_after()
+                    .map_err(ParsePolicyError::Parse)
+                    .map(Policy::After),
+                "older" => node
+                    .verify_older()
+                    .map_err(ParsePolicyError::Parse)
+                    .map(Policy::Older),
                 "sha256" => node
                     .verify_terminal_parent("sha256", "hash")
                     .map(Policy::Sha256)
-                    .map_err(Error::Parse),
+                    .map_err(ParsePolicyError::Parse),
                 "hash256" => node
                     .verify_terminal_parent("hash256", "hash")
                     .map(Policy::Hash256)
-                    .map_err(Error::Parse),
+                    .map_err(ParsePolicyError::Parse),
                 "ripemd160" => node
                     .verify_terminal_parent("ripemd160", "hash")
                     .map(Policy::Ripemd160)
-                    .map_err(Error::Parse),
+                    .map_err(ParsePolicyError::Parse),
                 "hash160" => node
                     .verify_terminal_parent("hash160", "hash")
                     .map(Policy::Hash160)
-                    .map_err(Error::Parse),
+                    .map_err(ParsePolicyError::Parse),
                 "and" => {
                     node.verify_n_children("and", 2..)
                         .map_err(From::from)
-                        .map_err(Error::Parse)?;
+                        .map_err(ParsePolicyError::Parse)?;
 
                     let child_iter = (0..node.n_children()).map(|_| stack.pop().unwrap());
                     let thresh = Threshold::from_iter(node.n_children(), child_iter)
-                        .map_err(Error::Threshold)?;
+                        .map_err(ParsePolicyError::Threshold)?;
                     Ok(Policy::Thresh(thresh))
                 }
                 "or" => {
                     node.verify_n_children("or", 2..)
                         .map_err(From::from)
-                        .map_err(Error::Parse)?;
+                        .map_err(ParsePolicyError::Parse)?;
                     let child_iter = (0..node.n_children()).map(|_| stack.pop().unwrap());
-                    let thresh = Threshold::from_iter(1, child_iter).map_err(Error::Threshold)?;
+                    let thresh = Threshold::from_iter(1, child_iter)
+                        .map_err(ParsePolicyError::Threshold)?;
                     Ok(Policy::Thresh(thresh))
                 }
                 "thresh" => {
-                    let thresh = node.verify_threshold(|_| Ok::<_, Error>(stack.pop().unwrap()))?;
+                    let thresh = node
+                        .verify_threshold(|_| Ok::<_, ParsePolicyError>(stack.pop().unwrap()))?;
 
                     // thresh(1) and thresh(n) are disallowed in semantic policies
                     if thresh.is_or() {
-                        return Err(Error::ParseThreshold(crate::ParseThresholdError::IllegalOr));
+                        return Err(ParsePolicyError::ParseThreshold(
+                            crate::ParseThresholdError::IllegalOr,
+                        ));
                     }
                     if thresh.is_and() {
-                        return Err(Error::ParseThreshold(crate::ParseThresholdError::IllegalAnd));
+                        return Err(ParsePolicyError::ParseThreshold(
+                            crate::ParseThresholdError::IllegalAnd,
+                        ));
                     }
 
                     Ok(Policy::Thresh(thresh))
                 }
-                x => {
-                    Err(Error::Parse(crate::ParseError::Tree(crate::ParseTreeError::UnknownName {
-                        name: x.to_owned(),
-                    })))
-                }
+                x => Err(ParsePolicyError::Parse(crate::ParseError::Tree(
+                    crate::ParseTreeError::UnknownName { name: x.to_owned() },
+                ))),
             }?;
 
             stack.push(Arc::new(new));
@@ -389,71 +416,88 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
     /// Flattens out trees of `And`s and `Or`s; eliminate `Trivial` and
     /// `Unsatisfiable`s. Does not reorder any branches; use `.sort`.
     pub fn normalized(self) -> Policy<Pk> {
-        match self {
-            Policy::Thresh(thresh) => {
-                let mut ret_subs = Vec::with_capacity(thresh.n());
-
-                let subs: Vec<_> = thresh
-                    .iter()
-                    .map(|sub| Arc::new(sub.as_ref().clone().normalized()))
-                    .collect();
-                let trivial_count = subs
-                    .iter()
-                    .filter(|&pol| *pol.as_ref() == Policy::Trivial)
-                    .count();
-                let unsatisfied_count = subs
-                    .iter()
-                    .filter(|&pol| *pol.as_ref() == Policy::Unsatisfiable)
-                    .count();
-
-                let n = subs.len() - unsatisfied_count - trivial_count; // remove all true/false
-                let m = thresh.k().saturating_sub(trivial_count); // satisfy all trivial
-
-                let is_and = m == n;
-                let is_or = m == 1;
-
-                for sub in subs {
-                    match sub.as_ref() {
-                        Policy::Trivial | Policy::Unsatisfiable => {}
-                        Policy::Thresh(ref subthresh) => {
-                            match (is_and, is_or) {
-                                (true, true) => {
-                                    // means m = n = 1, thresh(1,X) type thing.
-                                    ret_subs.push(Arc::new(Policy::Thresh(subthresh.clone())));
-                                }
-                                (true, false) if subthresh.k() == subthresh.n() => {
-                                    ret_subs.extend(subthresh.iter().cloned())
-                                } // and case
-                                (false, true) if subthresh.k() == 1 => {
-                                    ret_subs.extend(subthresh.iter().cloned())
-                                } // or case
-                                _ => ret_subs.push(Arc::new(Policy::Thresh(subthresh.clone()))),
-                            }
+        // The normalized form of every node, computed bottom-up. Every node
+        // is the child of a single parent, which takes its entry.
+        let mut normalized: Vec<Option<Arc<Policy<Pk>>>> = vec![];
+        for item in (&self).post_order_iter() {
+            let node = match item.node {
+                Policy::Thresh(ref thresh) => {
+                    let subs = item
+                        .child_indices
+                        .iter()
+                        .map(|&i| normalized[i].take().expect("each child has one parent"))
+                        .collect();
+                    Self::normalized_thresh(thresh.k(), subs)
+                }
+                x => x.clone(),
+            };
+            normalized.push(Some(Arc::new(node)));
+        }
+        let root = normalized
+            .pop()
+            .flatten()
+            .expect("the root is the last node");
+        Arc::try_unwrap(root).unwrap_or_else(|root| root.as_ref().clone())
+    }
+
+    /// Normalizes a threshold whose children are already normalized.
+    fn normalized_thresh(k: usize, subs: Vec<Arc<Policy<Pk>>>) -> Policy<Pk> {
+        let mut ret_subs = Vec::with_capacity(subs.len());
+
+        let trivial_count = subs
+            .iter()
+            .filter(|&pol| *pol.as_ref() == Policy::Trivial)
+            .count();
+        let unsatisfied_count = subs
+            .iter()
+            .filter(|&pol| *pol.as_ref() == Policy::Unsatisfiable)
+            .count();
+
+        let n = subs.len() - unsatisfied_count - trivial_count; // remove all true/false
+        let m = k.saturating_sub(trivial_count); // satisfy all trivial
+
+        let is_and = m == n;
+        let is_or = m == 1;
+
+        for sub in subs {
+            match sub.as_ref() {
+                Policy::Trivial | Policy::Unsatisfiable => {}
+                Policy::Thresh(ref subthresh) => {
+                    match (is_and, is_or) {
+                        (true, true) => {
+                            // means m = n = 1, thresh(1,X) type thing.
+                            ret_subs.push(Arc::new(Policy::Thresh(subthresh.clone())));
                         }
-                        x => ret_subs.push(Arc::new(x.clone())),
+                        (true, false) if subthresh.k() == subthresh.n() => {
+                            ret_subs.extend(subthresh.iter().cloned())
+                        } // and case
+                        (false, true) if subthresh.k() == 1 => {
+                            ret_subs.extend(subthresh.iter().cloned())
+                        } // or case
+                        _ => ret_subs.push(Arc::new(Policy::Thresh(subthresh.clone()))),
                     }
                 }
-                // Now reason about m of n threshold
-                if m == 0 {
-                    Policy::Trivial
-                } else if m > ret_subs.len() {
-                    Policy::Unsatisfiable
-                } else if ret_subs.len() == 1 {
-                    let policy = ret_subs.pop().unwrap();
-                    // Only one strong reference because we created the Arc when pushing to ret_subs.
-                    Arc::try_unwrap(policy).unwrap()
-                } else if is_and {
-                    // unwrap ok since ret_subs is nonempty
-                    Policy::Thresh(Threshold::new(ret_subs.len(), ret_subs).unwrap())
-                } else if is_or {
-                    // unwrap ok since ret_subs is nonempty
-                    Policy::Thresh(Threshold::new(1, ret_subs).unwrap())
-                } else {
-                    // unwrap ok since ret_subs is nonempty and we made sure m <= ret_subs.len
-                    Policy::Thresh(Threshold::new(m, ret_subs).unwrap())
-                }
+                x => ret_subs.push(Arc::new(x.clone())),
             }
-            x => x,
+        }
+        // Now reason about m of n threshold
+        if m == 0 {
+            Policy::Trivial
+        } else if m > ret_subs.len() {
+            Policy::Unsatisfiable
+        } else if ret_subs.len() == 1 {
+            let policy = ret_subs.pop().unwrap();
+            // Only one strong reference because we created the Arc when pushing to ret_subs.
+            Arc::try_unwrap(policy).unwrap()
+        } else if is_and {
+            // unwrap ok since ret_subs is nonempty
+            Policy::Thresh(Threshold::new(ret_subs.len(), ret_subs).unwrap())
+        } else if is_or {
+            // unwrap ok since ret_subs is nonempty
+            Policy::Thresh(Threshold::new(1, ret_subs).unwrap())
+        } else {
+            // unwrap ok since ret_subs is nonempty and we made sure m <= ret_subs.len
+            Policy::Thresh(Threshold::new(m, ret_subs).unwrap())
         }
     }
 
@@ -89,3 +89,18 @@ impl cmp::Ord for AbsLockTime {
 impl fmt::Display for AbsLockTime {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { fmt::Display::fmt(&self.0, f) }
 }
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for AbsLockTime {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(self.to_consensus_u32())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for AbsLockTime {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let n = u32::deserialize(deserializer)?;
+        AbsLockTime::from_consensus(n).map_err(serde::de::Error::custom)
+    }
+}
@@ -98,3 +98,18 @@ impl cmp::Ord for RelLockTime {
 impl fmt::Display for RelLockTime {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { fmt::Display::fmt(&self.0, f) }
 }
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for RelLockTime {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(self.to_consensus_u32())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for RelLockTime {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let n = u32::deserialize(deserializer)?;
+        RelLockTime::from_consensus(n).map_err(serde::de::Error::custom)
+    }
+}
@@ -216,6 +216,12 @@ impl<T, const MAX: usize> Threshold<T, MAX> {
     /// Accessor for the underlying data.
     pub fn into_data(self) -> Vec<T> { self.inner }
 
+    /// Moves the underlying data out, leaving the threshold empty.
+    ///
+    /// An empty threshold is invalid, so this should only be used when the
+    /// threshold is about to be dropped.
+    pub(crate) fn take_data(&mut self) -> Vec<T> { core::mem::take(&mut self.inner) }
+
     /// Passthrough to an iterator on the underlying vector.
     pub fn iter(&self) -> core::slice::Iter<T> { self.inner.iter() }
 }
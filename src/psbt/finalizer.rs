@@ -16,11 +16,14 @@ use bitcoin::key::XOnlyPublicKey;
 #[cfg(not(test))] // https://github.com/rust-lang/rust/issues/121684
 use bitcoin::secp256k1;
 use bitcoin::secp256k1::Secp256k1;
-use bitcoin::sighash::Prevouts;
-use bitcoin::taproot::LeafVersion;
-use bitcoin::{PublicKey, Script, ScriptBuf, TxOut, Witness};
+use bitcoin::sighash::{Prevouts, SighashCache};
+use bitcoin::taproot::{LeafVersion, TapLeafHash};
+use bitcoin::{PublicKey, Script, ScriptBuf, Transaction, TxOut, Witness};
 
-use super::{sanity_check, Error, InputError, Psbt, PsbtInputSatisfier};
+use super::{
+    sanity_check, Error, InputError, Psbt, PsbtInputSatisfier, PsbtSighashMsg, SighashError,
+};
+use crate::descriptor::ShInner;
 use crate::prelude::*;
 use crate::util::witness_size;
 use crate::{
@@ -36,20 +39,23 @@ fn construct_tap_witness(
     spk: &Script,
     sat: &PsbtInputSatisfier,
     allow_mall: bool,
+    tap_scripts: &mut BTreeMap<ScriptBuf, Option<Miniscript<XOnlyPublicKey, Tap>>>,
 ) -> Result<Vec<Vec<u8>>, InputError> {
     // When miniscript tries to finalize the PSBT, it doesn't have the full descriptor (which contained a pkh() fragment)
     // and instead resorts to parsing the raw script sig, which is translated into a "expr_raw_pkh" internally.
     let mut map: BTreeMap<hash160::Hash, bitcoin::key::XOnlyPublicKey> = BTreeMap::new();
-    let psbt_inputs = &sat.psbt.inputs;
-    for psbt_input in psbt_inputs {
-        // We need to satisfy or dissatisfy any given key. `tap_key_origin` is the only field of PSBT Input which consist of
-        // all the keys added on a descriptor and thus we get keys from it.
-        let public_keys = psbt_input.tap_key_origins.keys();
-        for key in public_keys {
-            let bitcoin_key = *key;
-            let hash = bitcoin_key.to_pubkeyhash(SigType::Schnorr);
-            map.insert(hash, bitcoin_key);
-        }
+    let psbt_input = &sat.psbt.inputs[sat.index];
+    // We need to satisfy or dissatisfy any given key. `tap_key_origin` is the only field of PSBT Input which consist of
+    // all the keys added on a descriptor and thus we get keys from it. Keys which signed are also
+    // found in `tap_script_sigs`, which may be the only field filled in by the signers.
+    let public_keys = psbt_input
+        .tap_key_origins
+        .keys()
+        .chain(psbt_input.tap_script_sigs.keys().map(|(key, _)| key));
+    for key in public_keys {
+        let bitcoin_key = *key;
+        let hash = bitcoin_key.to_pubkeyhash(SigType::Schnorr);
+        map.insert(hash, bitcoin_key);
     }
     assert!(spk.is_p2tr());
 
@@ -69,12 +75,13 @@ fn construct_tap_witness(
                 // We don't know how to satisfy non default version scripts yet
                 continue;
             }
-            let ms = match Miniscript::<XOnlyPublicKey, Tap>::parse_with_ext(
-                script,
-                &ExtParams::allow_all(),
-            ) {
-                Ok(ms) => ms.substitute_raw_pkh(&map),
-                Err(..) => continue, // try another script
+            let ms = tap_scripts.entry(script.clone()).or_insert_with(|| {
+                Miniscript::<XOnlyPublicKey, Tap>::parse_with_ext(script, &ExtParams::allow_all())
+                    .ok()
+            });
+            let ms = match ms {
+                Some(ms) => ms.substitute_raw_pkh(&map),
+                None => continue, // try another script
             };
             let mut wit = if allow_mall {
                 match ms.satisfy_malleable(sat) {
@@ -142,19 +149,12 @@ pub(super) fn prevouts(psbt: &Psbt) -> Result<Vec<&bitcoin::TxOut>, super::Error
 // We parse the insane version while satisfying because
 // we want to move the script is probably already created
 // and we want to satisfy it in any way possible.
-fn get_descriptor(psbt: &Psbt, index: usize) -> Result<Descriptor<PublicKey>, InputError> {
-    let mut map: BTreeMap<hash160::Hash, PublicKey> = BTreeMap::new();
-    let psbt_inputs = &psbt.inputs;
-    for psbt_input in psbt_inputs {
-        // Use BIP32 Derviation to get set of all possible keys.
-        let public_keys = psbt_input.bip32_derivation.keys();
-        for key in public_keys {
-            let bitcoin_key = bitcoin::PublicKey::new(*key);
-            let hash = bitcoin_key.pubkey_hash().to_raw_hash();
-            map.insert(hash, bitcoin_key);
-        }
-    }
-
+// The keys of `map` are used to substitute raw key hashes in the scripts.
+fn get_descriptor(
+    psbt: &Psbt,
+    index: usize,
+    map: &BTreeMap<hash160::Hash, PublicKey>,
+) -> Result<Descriptor<PublicKey>, InputError> {
     // Figure out Scriptpubkey
     let script_pubkey = get_scriptpubkey(psbt, index)?;
     let inp = &psbt.inputs[index];
@@ -215,7 +215,7 @@ fn get_descriptor(psbt: &Psbt, index: usize) -> Result<Descriptor<PublicKey>, In
                 witness_script,
                 &ExtParams::allow_all(),
             )?;
-            Ok(Descriptor::new_wsh(ms.substitute_raw_pkh(&map))?)
+            Ok(Descriptor::new_wsh(ms.substitute_raw_pkh(map))?)
         } else {
             Err(InputError::MissingWitnessScript)
         }
@@ -242,7 +242,7 @@ fn get_descriptor(psbt: &Psbt, index: usize) -> Result<Descriptor<PublicKey>, In
                             witness_script,
                             &ExtParams::allow_all(),
                         )?;
-                        Ok(Descriptor::new_sh_wsh(ms.substitute_raw_pkh(&map))?)
+                        Ok(Descriptor::new_sh_wsh(ms.substitute_raw_pkh(map))?)
                     } else {
                         Err(InputError::MissingWitnessScript)
                     }
@@ -293,8 +293,24 @@ fn get_descriptor(psbt: &Psbt, index: usize) -> Result<Descriptor<PublicKey>, In
             &script_pubkey,
             &ExtParams::allow_all(),
         )?;
-        Ok(Descriptor::new_bare(ms.substitute_raw_pkh(&map))?)
+        Ok(Descriptor::new_bare(ms.substitute_raw_pkh(map))?)
+    }
+}
+
+// Get the keys of the BIP32 derivations of all psbt inputs, by hash
+fn get_bip32_keys(psbt: &Psbt) -> BTreeMap<hash160::Hash, PublicKey> {
+    let mut map: BTreeMap<hash160::Hash, PublicKey> = BTreeMap::new();
+    let psbt_inputs = &psbt.inputs;
+    for psbt_input in psbt_inputs {
+        // Use BIP32 Derviation to get set of all possible keys.
+        let public_keys = psbt_input.bip32_derivation.keys();
+        for key in public_keys {
+            let bitcoin_key = bitcoin::PublicKey::new(*key);
+            let hash = bitcoin_key.pubkey_hash().to_raw_hash();
+            map.insert(hash, bitcoin_key);
+        }
     }
+    map
 }
 
 /// Interprets all psbt inputs and checks whether the
@@ -309,6 +325,7 @@ pub fn interpreter_check<C: secp256k1::Verification>(
 ) -> Result<(), Error> {
     let utxos = prevouts(psbt)?;
     let utxos = &Prevouts::All(&utxos);
+    let mut cache = SighashCache::new(&psbt.unsigned_tx);
     for (index, input) in psbt.inputs.iter().enumerate() {
         let empty_script_sig = ScriptBuf::new();
         let empty_witness = Witness::default();
@@ -319,7 +336,7 @@ pub fn interpreter_check<C: secp256k1::Verification>(
             .map(|wit_slice| Witness::from_slice(&wit_slice.to_vec())) // TODO: Update rust-bitcoin psbt API to use witness
             .unwrap_or(empty_witness);
 
-        interpreter_inp_check(psbt, secp, index, utxos, &witness, script_sig)?;
+        interpreter_inp_check(psbt, secp, &mut cache, index, utxos, &witness, script_sig)?;
     }
     Ok(())
 }
@@ -328,6 +345,7 @@ pub fn interpreter_check<C: secp256k1::Verification>(
 fn interpreter_inp_check<C: secp256k1::Verification, T: Borrow<TxOut>>(
     psbt: &Psbt,
     secp: &Secp256k1<C>,
+    cache: &mut SighashCache<&Transaction>,
     index: usize,
     utxos: &Prevouts<T>,
     witness: &Witness,
@@ -338,18 +356,31 @@ fn interpreter_inp_check<C: secp256k1::Verification, T: Borrow<TxOut>>(
     // Now look at all the satisfied constraints. If everything is filled in
     // corrected, there should be no errors
     // Interpreter check
-    {
-        let cltv = psbt.unsigned_tx.lock_time;
-        let csv = psbt.unsigned_tx.input[index].sequence;
-        let interpreter =
-            interpreter::Interpreter::from_txdata(&spk, script_sig, witness, csv, cltv)
-                .map_err(|e| Error::InputError(InputError::Interpreter(e), index))?;
-        let iter = interpreter.iter(secp, &psbt.unsigned_tx, index, utxos);
-        if let Some(error) = iter.filter_map(Result::err).next() {
-            return Err(Error::InputError(InputError::Interpreter(error), index));
-        };
-    }
-    Ok(())
+    let cltv = psbt.unsigned_tx.lock_time;
+    let csv = psbt.unsigned_tx.input[index].sequence;
+    let interpreted = interpreter::Interpreter::from_txdata(&spk, script_sig, witness, csv, cltv)
+        .and_then(|interpreter| {
+            let mut iter = interpreter.iter_with_cache(secp, cache, index, utxos);
+            match iter.find_map(Result::err) {
+                Some(error) => Err(error),
+                None => Ok(()),
+            }
+        });
+    // Cross-check the spend with libbitcoinconsensus, which cannot verify
+    // taproot spends
+    #[cfg(feature = "consensus-verify")]
+    let interpreted = if spk.is_p2tr() {
+        interpreted
+    } else {
+        let utxo = get_utxo(psbt, index).map_err(|e| Error::InputError(e, index))?;
+        let mut tx = psbt.unsigned_tx.clone();
+        tx.input[index].script_sig = script_sig.to_owned();
+        tx.input[index].witness = witness.clone();
+        let serialized_tx = bitcoin::consensus::encode::serialize(&tx);
+        let verified = spk.verify(index, utxo.value, &serialized_tx);
+        interpreter::cross_check_consensus(interpreted, verified)
+    };
+    interpreted.map_err(|e| Error::InputError(InputError::Interpreter(e), index))
 }
 
 /// Finalize the psbt.
@@ -394,44 +425,142 @@ pub fn finalize_helper<C: secp256k1::Verification>(
     Ok(())
 }
 
-// Helper function to obtain psbt final_witness/final_script_sig.
-// Does not add fields to the psbt, only returns the values.
-fn finalize_input_helper<C: secp256k1::Verification>(
-    psbt: &Psbt,
-    index: usize,
-    secp: &Secp256k1<C>,
-    allow_mall: bool,
-) -> Result<(Witness, ScriptBuf), super::Error> {
-    let (witness, script_sig) = {
-        let spk = get_scriptpubkey(psbt, index).map_err(|e| Error::InputError(e, index))?;
-        let sat = PsbtInputSatisfier::new(psbt, index);
-
-        if spk.is_p2tr() {
-            // Deal with tr case separately, unfortunately we cannot infer the full descriptor for Tr
-            let wit = construct_tap_witness(&spk, &sat, allow_mall)
-                .map_err(|e| Error::InputError(e, index))?;
-            (wit, ScriptBuf::new())
-        } else {
-            // Get a descriptor for this input.
-            let desc = get_descriptor(psbt, index).map_err(|e| Error::InputError(e, index))?;
+/// Data shared by the inputs of a PSBT, to finalize them or compute their sighashes
+///
+/// Finalizing inputs one at a time recomputes the data they share for each of
+/// them: the outputs they spend, the keys of the BIP32 derivations, the hashes
+/// the sighash algorithms precompute over the whole transaction and the scripts
+/// parsed from the inputs. A batch computes them once, so finalizing a
+/// transaction with many inputs spending the same descriptor is much faster.
+///
+/// The final fields of each input are returned rather than set, as the batch
+/// borrows the PSBT. [`crate::psbt::PsbtExt::finalize_mut`] uses a batch.
+pub struct PsbtBatch<'psbt> {
+    psbt: &'psbt Psbt,
+    prevouts: Option<Vec<&'psbt TxOut>>,
+    cache: SighashCache<&'psbt Transaction>,
+    keys: BTreeMap<hash160::Hash, PublicKey>,
+    /// Descriptors inferred from scripts, by script pubkey, redeem script and witness script
+    descriptors: BTreeMap<(ScriptBuf, Option<ScriptBuf>, Option<ScriptBuf>), Descriptor<PublicKey>>,
+    /// Tapscripts parsed as miniscripts, if they are
+    tap_scripts: BTreeMap<ScriptBuf, Option<Miniscript<XOnlyPublicKey, Tap>>>,
+}
 
-            //generate the satisfaction witness and scriptsig
+impl<'psbt> PsbtBatch<'psbt> {
+    /// Create a batch for the inputs of `psbt`
+    pub fn new(psbt: &'psbt Psbt) -> Self {
+        PsbtBatch {
+            psbt,
+            prevouts: None,
+            cache: SighashCache::new(&psbt.unsigned_tx),
+            keys: get_bip32_keys(psbt),
+            descriptors: BTreeMap::new(),
+            tap_scripts: BTreeMap::new(),
+        }
+    }
+
+    /// The PSBT of the batch
+    pub fn psbt(&self) -> &'psbt Psbt { self.psbt }
+
+    /// Compute the final witness and script sig of input `index`
+    ///
+    /// This is the same as [`crate::psbt::PsbtExt::finalize_inp`], but returns the final
+    /// fields rather than setting them. The spend is checked with the interpreter.
+    pub fn finalize_input<C: secp256k1::Verification>(
+        &mut self,
+        index: usize,
+        secp: &Secp256k1<C>,
+        allow_mall: bool,
+    ) -> Result<(Witness, ScriptBuf), Error> {
+        let psbt = self.psbt;
+        if index >= psbt.inputs.len() {
+            return Err(Error::InputIdxOutofBounds { psbt_inp: psbt.inputs.len(), index });
+        }
+        let (witness, script_sig) = {
+            let spk = get_scriptpubkey(psbt, index).map_err(|e| Error::InputError(e, index))?;
             let sat = PsbtInputSatisfier::new(psbt, index);
-            if !allow_mall {
-                desc.get_satisfaction(sat)
+
+            if spk.is_p2tr() {
+                // Deal with tr case separately, unfortunately we cannot infer the full descriptor for Tr
+                let wit = construct_tap_witness(&spk, &sat, allow_mall, &mut self.tap_scripts)
+                    .map_err(|e| Error::InputError(e, index))?;
+                (wit, ScriptBuf::new())
             } else {
-                desc.get_satisfaction_mall(sat)
+                // Get a descriptor for this input.
+                let desc = self
+                    .descriptor(index, spk)
+                    .map_err(|e| Error::InputError(e, index))?;
+
+                //generate the satisfaction witness and scriptsig
+                if !allow_mall {
+                    desc.get_satisfaction(sat)
+                } else {
+                    desc.get_satisfaction_mall(sat)
+                }
+                .map_err(|e| Error::InputError(InputError::MiniscriptError(e), index))?
             }
-            .map_err(|e| Error::InputError(InputError::MiniscriptError(e), index))?
+        };
+
+        let witness = bitcoin::Witness::from_slice(&witness);
+        let (utxos, cache) = self.sighash_data()?;
+        let utxos = &Prevouts::All(utxos);
+        interpreter_inp_check(psbt, secp, cache, index, utxos, &witness, &script_sig)?;
+
+        Ok((witness, script_sig))
+    }
+
+    /// Get the sighash message of input `idx`
+    ///
+    /// This is the same as [`crate::psbt::PsbtExt::sighash_msg`], using the sighash cache of
+    /// the batch.
+    pub fn sighash_msg(
+        &mut self,
+        idx: usize,
+        tapleaf_hash: Option<TapLeafHash>,
+    ) -> Result<PsbtSighashMsg, SighashError> {
+        let psbt = self.psbt;
+        if idx >= psbt.inputs.len() {
+            return Err(SighashError::IndexOutOfBounds(idx, psbt.inputs.len()));
         }
-    };
+        let (prevouts, cache) = self
+            .sighash_data()
+            .map_err(|_e| SighashError::MissingSpendUtxos)?;
+        super::sighash_msg_with_prevouts(psbt, idx, cache, prevouts, tapleaf_hash)
+    }
 
-    let witness = bitcoin::Witness::from_slice(&witness);
-    let utxos = prevouts(psbt)?;
-    let utxos = &Prevouts::All(&utxos);
-    interpreter_inp_check(psbt, secp, index, utxos, &witness, &script_sig)?;
+    // The spent outputs, computed on first use, and the sighash cache
+    fn sighash_data(
+        &mut self,
+    ) -> Result<(&[&'psbt TxOut], &mut SighashCache<&'psbt Transaction>), Error> {
+        if self.prevouts.is_none() {
+            self.prevouts = Some(prevouts(self.psbt)?);
+        }
+        let prevouts = self.prevouts.as_deref().expect("just computed");
+        Ok((prevouts, &mut self.cache))
+    }
 
-    Ok((witness, script_sig))
+    // Infer the descriptor of an input, reusing the one of any previous input with the same
+    // scripts. Descriptors inferred from signatures rather than scripts are not reused.
+    fn descriptor(
+        &mut self,
+        index: usize,
+        spk: ScriptBuf,
+    ) -> Result<Descriptor<PublicKey>, InputError> {
+        let inp = &self.psbt.inputs[index];
+        let scripts = (spk, inp.redeem_script.clone(), inp.witness_script.clone());
+        if let Some(desc) = self.descriptors.get(&scripts) {
+            return Ok(desc.clone());
+        }
+        let desc = get_descriptor(self.psbt, index, &self.keys)?;
+        match desc {
+            Descriptor::Pkh(..) | Descriptor::Wpkh(..) => {}
+            Descriptor::Sh(ref sh) if matches!(sh.as_inner(), ShInner::Wpkh(..)) => {}
+            _ => {
+                self.descriptors.insert(scripts, desc.clone());
+            }
+        }
+        Ok(desc)
+    }
 }
 
 pub(super) fn finalize_input<C: secp256k1::Verification>(
@@ -440,30 +569,34 @@ pub(super) fn finalize_input<C: secp256k1::Verification>(
     secp: &Secp256k1<C>,
     allow_mall: bool,
 ) -> Result<(), super::Error> {
-    let (witness, script_sig) = finalize_input_helper(psbt, index, secp, allow_mall)?;
-
-    // Now mutate the psbt input. Note that we cannot error after this point.
-    // If the input is mutated, it means that the finalization succeeded.
-    {
-        let original = mem::take(&mut psbt.inputs[index]);
-        let input = &mut psbt.inputs[index];
-        input.non_witness_utxo = original.non_witness_utxo;
-        input.witness_utxo = original.witness_utxo;
-        input.final_script_sig = if script_sig.is_empty() {
-            None
-        } else {
-            Some(script_sig)
-        };
-        input.final_script_witness = if witness.is_empty() {
-            None
-        } else {
-            Some(witness)
-        };
-    }
-
+    let (witness, script_sig) = PsbtBatch::new(psbt).finalize_input(index, secp, allow_mall)?;
+    set_final_fields(psbt, index, witness, script_sig);
     Ok(())
 }
 
+// Set the final fields of a psbt input, clearing the other ones
+pub(super) fn set_final_fields(
+    psbt: &mut Psbt,
+    index: usize,
+    witness: Witness,
+    script_sig: ScriptBuf,
+) {
+    let original = mem::take(&mut psbt.inputs[index]);
+    let input = &mut psbt.inputs[index];
+    input.non_witness_utxo = original.non_witness_utxo;
+    input.witness_utxo = original.witness_utxo;
+    input.final_script_sig = if script_sig.is_empty() {
+        None
+    } else {
+        Some(script_sig)
+    };
+    input.final_script_witness = if witness.is_empty() {
+        None
+    } else {
+        Some(witness)
+    };
+}
+
 #[cfg(test)]
 mod tests {
     use bitcoin::hashes::hex::FromHex;
@@ -476,7 +609,24 @@ mod tests {
         let mut psbt = Psbt::deserialize(&Vec::<u8>::from_hex("70736274ff01009a020000000258e87a21b56daf0c23be8e7070456c336f7cbaa5c8757924f545887bb2abdd750000000000ffffffff838d0427d0ec650a68aa46bb0b098aea4422c071b2ca78352a077959d07cea1d0100000000ffffffff0270aaf00800000000160014d85c2b71d0060b09c9886aeb815e50991dda124d00e1f5050000000016001400aea9a2e5f0f876a588df5546e8742d1d87008f00000000000100bb0200000001aad73931018bd25f84ae400b68848be09db706eac2ac18298babee71ab656f8b0000000048473044022058f6fc7c6a33e1b31548d481c826c015bd30135aad42cd67790dab66d2ad243b02204a1ced2604c6735b6393e5b41691dd78b00f0c5942fb9f751856faa938157dba01feffffff0280f0fa020000000017a9140fb9463421696b82c833af241c78c17ddbde493487d0f20a270100000017a91429ca74f8a08f81999428185c97b5d852e4063f6187650000002202029583bf39ae0a609747ad199addd634fa6108559d6c5cd39b4c2183f1ab96e07f473044022074018ad4180097b873323c0015720b3684cc8123891048e7dbcd9b55ad679c99022073d369b740e3eb53dcefa33823c8070514ca55a7dd9544f157c167913261118c01220202dab61ff49a14db6a7d02b0cd1fbb78fc4b18312b5b4e54dae4dba2fbfef536d7483045022100f61038b308dc1da865a34852746f015772934208c6d24454393cd99bdf2217770220056e675a675a6d0a02b85b14e5e29074d8a25a9b5760bea2816f661910a006ea01010304010000000104475221029583bf39ae0a609747ad199addd634fa6108559d6c5cd39b4c2183f1ab96e07f2102dab61ff49a14db6a7d02b0cd1fbb78fc4b18312b5b4e54dae4dba2fbfef536d752ae2206029583bf39ae0a609747ad199addd634fa6108559d6c5cd39b4c2183f1ab96e07f10d90c6a4f000000800000008000000080220602dab61ff49a14db6a7d02b0cd1fbb78fc4b18312b5b4e54dae4dba2fbfef536d710d90c6a4f0000008000000080010000800001012000c2eb0b0000000017a914b7f5faf40e3d40a5a459b1db3535f2b72fa921e887220203089dc10c7ac6db54f91329af617333db388cead0c231f723379d1b99030b02dc473044022062eb7a556107a7c73f45ac4ab5a1dddf6f7075fb1275969a7f383efff784bcb202200c05dbb7470dbf2f08557dd356c7325c1ed30913e996cd3840945db12228da5f012202023add904f3d6dcf59ddb906b0dee23529b7ffb9ed50e5e86151926860221f0e73473044022065f45ba5998b59a27ffe1a7bed016af1f1f90d54b3aa8f7450aa5f56a25103bd02207f724703ad1edb96680b284b56d4ffcb88f7fb759eabbe08aa30f29b851383d2010103040100000001042200208c2353173743b595dfb4a07b72ba8e42e3797da74e87fe7d9d7497e3b2028903010547522103089dc10c7ac6db54f91329af617333db388cead0c231f723379d1b99030b02dc21023add904f3d6dcf59ddb906b0dee23529b7ffb9ed50e5e86151926860221f0e7352ae2206023add904f3d6dcf59ddb906b0dee23529b7ffb9ed50e5e86151926860221f0e7310d90c6a4f000000800000008003000080220603089dc10c7ac6db54f91329af617333db388cead0c231f723379d1b99030b02dc10d90c6a4f00000080000000800200008000220203a9a4c37f5996d3aa25dbac6b570af0650394492942460b354753ed9eeca5877110d90c6a4f000000800000008004000080002202027f6399757d2eff55a136ad02c684b1838b6556e5f1b6b34282a94b6b5005109610d90c6a4f00000080000000800500008000").unwrap()).unwrap();
 
         let secp = Secp256k1::verification_only();
+        let mut batch = PsbtBatch::new(&psbt);
+        let finals = (0..2)
+            .map(|index| batch.finalize_input(index, &secp, false).unwrap())
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            batch.finalize_input(2, &secp, false),
+            Err(Error::InputIdxOutofBounds { psbt_inp: 2, index: 2 })
+        ));
+        let mut cache = SighashCache::new(&psbt.unsigned_tx);
+        assert_eq!(
+            batch.sighash_msg(1, None).unwrap(),
+            psbt.sighash_msg(1, &mut cache, None).unwrap()
+        );
         psbt.finalize_mut(&secp).unwrap();
+        for (input, (witness, script_sig)) in psbt.inputs.iter().zip(finals) {
+            assert_eq!(input.final_script_witness, Some(witness).filter(|w| !w.is_empty()));
+            assert_eq!(input.final_script_sig, Some(script_sig).filter(|s| !s.is_empty()));
+        }
 
         let expected = Psbt::deserialize(&Vec::<u8>::from_hex("70736274ff01009a020000000258e87a21b56daf0c23be8e7070456c336f7cbaa5c8757924f545887bb2abdd750000000000ffffffff838d0427d0ec650a68aa46bb0b098aea4422c071b2ca78352a077959d07cea1d0100000000ffffffff0270aaf00800000000160014d85c2b71d0060b09c9886aeb815e50991dda124d00e1f5050000000016001400aea9a2e5f0f876a588df5546e8742d1d87008f00000000000100bb0200000001aad73931018bd25f84ae400b68848be09db706eac2ac18298babee71ab656f8b0000000048473044022058f6fc7c6a33e1b31548d481c826c015bd30135aad42cd67790dab66d2ad243b02204a1ced2604c6735b6393e5b41691dd78b00f0c5942fb9f751856faa938157dba01feffffff0280f0fa020000000017a9140fb9463421696b82c833af241c78c17ddbde493487d0f20a270100000017a91429ca74f8a08f81999428185c97b5d852e4063f6187650000000107da00473044022074018ad4180097b873323c0015720b3684cc8123891048e7dbcd9b55ad679c99022073d369b740e3eb53dcefa33823c8070514ca55a7dd9544f157c167913261118c01483045022100f61038b308dc1da865a34852746f015772934208c6d24454393cd99bdf2217770220056e675a675a6d0a02b85b14e5e29074d8a25a9b5760bea2816f661910a006ea01475221029583bf39ae0a609747ad199addd634fa6108559d6c5cd39b4c2183f1ab96e07f2102dab61ff49a14db6a7d02b0cd1fbb78fc4b18312b5b4e54dae4dba2fbfef536d752ae0001012000c2eb0b0000000017a914b7f5faf40e3d40a5a459b1db3535f2b72fa921e8870107232200208c2353173743b595dfb4a07b72ba8e42e3797da74e87fe7d9d7497e3b20289030108da0400473044022062eb7a556107a7c73f45ac4ab5a1dddf6f7075fb1275969a7f383efff784bcb202200c05dbb7470dbf2f08557dd356c7325c1ed30913e996cd3840945db12228da5f01473044022065f45ba5998b59a27ffe1a7bed016af1f1f90d54b3aa8f7450aa5f56a25103bd02207f724703ad1edb96680b284b56d4ffcb88f7fb759eabbe08aa30f29b851383d20147522103089dc10c7ac6db54f91329af617333db388cead0c231f723379d1b99030b02dc21023add904f3d6dcf59ddb906b0dee23529b7ffb9ed50e5e86151926860221f0e7352ae00220203a9a4c37f5996d3aa25dbac6b570af0650394492942460b354753ed9eeca5877110d90c6a4f000000800000008004000080002202027f6399757d2eff55a136ad02c684b1838b6556e5f1b6b34282a94b6b5005109610d90c6a4f00000080000000800500008000").unwrap()).unwrap();
         assert_eq!(psbt, expected);
@@ -22,6 +22,7 @@ use bitcoin::taproot::{self, ControlBlock, LeafVersion, TapLeafHash};
 use bitcoin::{absolute, bip32, relative, transaction, Script, ScriptBuf};
 
 use crate::miniscript::context::SigType;
+use crate::miniscript::satisfy::{tx_check_after, tx_check_older};
 use crate::prelude::*;
 use crate::{
     descriptor, interpreter, DefiniteDescriptorKey, Descriptor, DescriptorPublicKey, MiniscriptKey,
@@ -29,9 +30,16 @@ use crate::{
 };
 
 mod finalizer;
+mod signer;
 
 #[allow(deprecated)]
-pub use self::finalizer::{finalize, finalize_mall, interpreter_check};
+pub use self::finalizer::{finalize, finalize_mall, interpreter_check, PsbtBatch};
+pub use self::signer::{
+    display_address, sign_and_finalize, sign_psbt, signer_assets, signer_key, ExternalSigner,
+    ExternalSignerError,
+};
+#[cfg(any(test, feature = "test-utils"))]
+pub use self::signer::{MockSigner, MockSignerError};
 
 /// Error type for entire Psbt
 #[derive(Debug)]
@@ -144,6 +152,17 @@ pub enum InputError {
         /// the corresponding publickey
         pubkey: bitcoin::PublicKey,
     },
+    /// Sighash type which cannot be used by taproot signatures
+    InvalidTapSighashType(sighash::InvalidSighashTypeError),
+    /// Taproot sighash did not match
+    WrongTapSighashFlag {
+        /// required sighash type
+        required: sighash::TapSighashType,
+        /// the sighash type we got
+        got: sighash::TapSighashType,
+        /// the corresponding publickey, unknown for key spend signatures without an internal key
+        pubkey: Option<bitcoin::key::XOnlyPublicKey>,
+    },
 }
 
 #[cfg(feature = "std")]
@@ -164,7 +183,9 @@ impl error::Error for InputError {
             | NonEmptyWitnessScript
             | NonEmptyRedeemScript
             | NonStandardSighashType(_)
-            | WrongSighashFlag { .. } => None,
+            | WrongSighashFlag { .. }
+            | WrongTapSighashFlag { .. } => None,
+            InvalidTapSighashType(e) => Some(e),
             SecpErr(e) => Some(e),
             KeyErr(e) => Some(e),
             Interpreter(e) => Some(e),
@@ -216,6 +237,15 @@ impl fmt::Display for InputError {
             InputError::NonStandardSighashType(ref e) => {
                 write!(f, "Non-standard sighash type {}", e)
             }
+            InputError::InvalidTapSighashType(ref e) => {
+                write!(f, "Invalid taproot sighash type {}", e)
+            }
+            InputError::WrongTapSighashFlag { required, got, pubkey } => write!(
+                f,
+                "PSBT: signature with key {:?} had \
+                 sighashflag {:?} rather than required {:?}",
+                pubkey, got, required
+            ),
         }
     }
 }
@@ -278,6 +308,19 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for PsbtInputSatisfier<'_> {
             .map(|(pubkey, _)| bitcoin::PublicKey::new(*pubkey))
     }
 
+    fn lookup_raw_pkh_x_only_pk(
+        &self,
+        pkh: &hash160::Hash,
+    ) -> Option<bitcoin::secp256k1::XOnlyPublicKey> {
+        let input = &self.psbt.inputs[self.index];
+        input
+            .tap_key_origins
+            .keys()
+            .chain(input.tap_script_sigs.keys().map(|(pubkey, _)| pubkey))
+            .find(|pubkey| pubkey.to_pubkeyhash(SigType::Schnorr) == *pkh)
+            .copied()
+    }
+
     fn lookup_tap_control_block_map(
         &self,
     ) -> Option<&BTreeMap<ControlBlock, (bitcoin::ScriptBuf, LeafVersion)>> {
@@ -316,24 +359,11 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for PsbtInputSatisfier<'_> {
     }
 
     fn check_after(&self, n: absolute::LockTime) -> bool {
-        if !self.psbt.unsigned_tx.input[self.index].enables_lock_time() {
-            return false;
-        }
-
-        let lock_time = self.psbt.unsigned_tx.lock_time;
-
-        <dyn Satisfier<Pk>>::check_after(&lock_time, n)
+        tx_check_after(&self.psbt.unsigned_tx, self.index, n)
     }
 
     fn check_older(&self, n: relative::LockTime) -> bool {
-        let seq = self.psbt.unsigned_tx.input[self.index].sequence;
-
-        if self.psbt.unsigned_tx.version < transaction::Version::TWO || !seq.is_relative_lock_time()
-        {
-            return false;
-        }
-
-        <dyn Satisfier<Pk>>::check_older(&seq, n)
+        tx_check_older(&self.psbt.unsigned_tx, self.index, n)
     }
 
     fn lookup_hash160(&self, h: &Pk::Hash160) -> Option<Preimage32> {
@@ -378,12 +408,16 @@ fn sanity_check(psbt: &Psbt) -> Result<(), Error> {
 
     // Check well-formedness of input data
     for (index, input) in psbt.inputs.iter().enumerate() {
+        // Signatures must use the sighash type of the input if there is one. Otherwise signers
+        // may use any type, and each signature can have its own (BIP 174).
         // TODO: fix this after https://github.com/rust-bitcoin/rust-bitcoin/issues/838
         let target_ecdsa_sighash_ty = match input.sighash_type {
-            Some(psbt_hash_ty) => psbt_hash_ty
-                .ecdsa_hash_ty()
-                .map_err(|e| Error::InputError(InputError::NonStandardSighashType(e), index))?,
-            None => sighash::EcdsaSighashType::All,
+            Some(psbt_hash_ty) if !input.partial_sigs.is_empty() => Some(
+                psbt_hash_ty
+                    .ecdsa_hash_ty()
+                    .map_err(|e| Error::InputError(InputError::NonStandardSighashType(e), index))?,
+            ),
+            _ => None,
         };
         for (key, ecdsa_sig) in &input.partial_sigs {
             let flag = sighash::EcdsaSighashType::from_standard(ecdsa_sig.sighash_type as u32)
@@ -395,18 +429,47 @@ fn sanity_check(psbt: &Psbt) -> Result<(), Error> {
                         index,
                     )
                 })?;
-            if target_ecdsa_sighash_ty != flag {
-                return Err(Error::InputError(
-                    InputError::WrongSighashFlag {
-                        required: target_ecdsa_sighash_ty,
-                        got: flag,
-                        pubkey: *key,
-                    },
-                    index,
-                ));
+            match target_ecdsa_sighash_ty {
+                Some(required) if required != flag => {
+                    return Err(Error::InputError(
+                        InputError::WrongSighashFlag { required, got: flag, pubkey: *key },
+                        index,
+                    ));
+                }
+                _ => {}
             }
             // Signatures are well-formed in psbt partial sigs
         }
+
+        let has_tap_sigs = input.tap_key_sig.is_some() || !input.tap_script_sigs.is_empty();
+        let target_tap_sighash_ty = match input.sighash_type {
+            Some(psbt_hash_ty) if has_tap_sigs => Some(
+                psbt_hash_ty
+                    .taproot_hash_ty()
+                    .map_err(|e| Error::InputError(InputError::InvalidTapSighashType(e), index))?,
+            ),
+            _ => None,
+        };
+        let tap_sigs = input
+            .tap_key_sig
+            .iter()
+            .map(|sig| (input.tap_internal_key, sig))
+            .chain(input.tap_script_sigs.iter().map(|((key, _), sig)| (Some(*key), sig)));
+        for (key, tap_sig) in tap_sigs {
+            match target_tap_sighash_ty {
+                Some(required) if required != tap_sig.sighash_type => {
+                    return Err(Error::InputError(
+                        InputError::WrongTapSighashFlag {
+                            required,
+                            got: tap_sig.sighash_type,
+                            pubkey: key,
+                        },
+                        index,
+                    ));
+                }
+                _ => {}
+            }
+        }
     }
 
     Ok(())
@@ -423,6 +486,9 @@ pub trait PsbtExt {
     /// Finalizes all inputs that it can finalize, and returns an error for each input
     /// that it cannot finalize. Also performs a sanity interpreter check on the
     /// finalized psbt which involves checking the signatures/ preimages/timelocks.
+    /// With the `consensus-verify` feature, the finalized non-taproot inputs are
+    /// additionally verified with libbitcoinconsensus, and fail if exactly one of
+    /// it and the interpreter rejects them.
     ///
     /// Input finalization also fails if it is not possible to satisfy any of the inputs non-malleably
     /// See [finalizer::finalize_mall] if you want to allow malleable satisfactions
@@ -589,14 +655,19 @@ impl PsbtExt for Psbt {
     ) -> Result<(), Vec<Error>> {
         // Actually construct the witnesses
         let mut errors = vec![];
+        let mut finalized = vec![];
+        let mut batch = PsbtBatch::new(self);
         for index in 0..self.inputs.len() {
-            match finalizer::finalize_input(self, index, secp, /*allow_mall*/ false) {
-                Ok(..) => {}
+            match batch.finalize_input(index, secp, /*allow_mall*/ false) {
+                Ok((witness, script_sig)) => finalized.push((index, witness, script_sig)),
                 Err(e) => {
                     errors.push(e);
                 }
             }
         }
+        for (index, witness, script_sig) in finalized {
+            finalizer::set_final_fields(self, index, witness, script_sig);
+        }
         if errors.is_empty() {
             Ok(())
         } else {
@@ -619,14 +690,19 @@ impl PsbtExt for Psbt {
         secp: &secp256k1::Secp256k1<C>,
     ) -> Result<(), Vec<Error>> {
         let mut errors = vec![];
+        let mut finalized = vec![];
+        let mut batch = PsbtBatch::new(self);
         for index in 0..self.inputs.len() {
-            match finalizer::finalize_input(self, index, secp, /*allow_mall*/ true) {
-                Ok(..) => {}
+            match batch.finalize_input(index, secp, /*allow_mall*/ true) {
+                Ok((witness, script_sig)) => finalized.push((index, witness, script_sig)),
                 Err(e) => {
                     errors.push(e);
                 }
             }
         }
+        for (index, witness, script_sig) in finalized {
+            finalizer::set_final_fields(self, index, witness, script_sig);
+        }
         if errors.is_empty() {
             Ok(())
         } else {
@@ -810,87 +886,205 @@ impl PsbtExt for Psbt {
         cache: &mut SighashCache<T>,
         tapleaf_hash: Option<TapLeafHash>,
     ) -> Result<PsbtSighashMsg, SighashError> {
-        // Infer a descriptor at idx
         if idx >= self.inputs.len() {
             return Err(SighashError::IndexOutOfBounds(idx, self.inputs.len()));
         }
-        let inp = &self.inputs[idx];
         let prevouts = finalizer::prevouts(self).map_err(|_e| SighashError::MissingSpendUtxos)?;
-        // Note that as per Psbt spec we should have access to spent_utxos for the transaction
-        // Even if the transaction does not require SighashAll, we create `Prevouts::All` for code simplicity
-        let prevouts = bitcoin::sighash::Prevouts::All(&prevouts);
-        let inp_spk =
-            finalizer::get_scriptpubkey(self, idx).map_err(|_e| SighashError::MissingInputUtxo)?;
-        if inp_spk.is_p2tr() {
-            let hash_ty = inp
-                .sighash_type
-                .map(|sighash_type| sighash_type.taproot_hash_ty())
-                .unwrap_or(Ok(sighash::TapSighashType::Default))
-                .map_err(|_e| SighashError::InvalidSighashType)?;
-            match tapleaf_hash {
-                Some(leaf_hash) => {
-                    let tap_sighash_msg = cache
-                        .taproot_script_spend_signature_hash(idx, &prevouts, leaf_hash, hash_ty)?;
-                    Ok(PsbtSighashMsg::TapSighash(tap_sighash_msg))
-                }
-                None => {
-                    let tap_sighash_msg =
-                        cache.taproot_key_spend_signature_hash(idx, &prevouts, hash_ty)?;
-                    Ok(PsbtSighashMsg::TapSighash(tap_sighash_msg))
-                }
+        sighash_msg_with_prevouts(self, idx, cache, &prevouts, tapleaf_hash)
+    }
+}
+
+/// Computes the sighash message of input `idx` of `psbt`, which spends `prevouts`
+///
+/// The input index must be in bounds.
+fn sighash_msg_with_prevouts<T: Borrow<bitcoin::Transaction>>(
+    psbt: &Psbt,
+    idx: usize,
+    cache: &mut SighashCache<T>,
+    prevouts: &[&bitcoin::TxOut],
+    tapleaf_hash: Option<TapLeafHash>,
+) -> Result<PsbtSighashMsg, SighashError> {
+    let inp = &psbt.inputs[idx];
+    // Note that as per Psbt spec we should have access to spent_utxos for the transaction
+    // Even if the transaction does not require SighashAll, we create `Prevouts::All` for code simplicity
+    let prevouts = bitcoin::sighash::Prevouts::All(prevouts);
+    let inp_spk =
+        finalizer::get_scriptpubkey(psbt, idx).map_err(|_e| SighashError::MissingInputUtxo)?;
+    if inp_spk.is_p2tr() {
+        let hash_ty = inp
+            .sighash_type
+            .map(|sighash_type| sighash_type.taproot_hash_ty())
+            .unwrap_or(Ok(sighash::TapSighashType::Default))
+            .map_err(|_e| SighashError::InvalidSighashType)?;
+        match tapleaf_hash {
+            Some(leaf_hash) => {
+                let tap_sighash_msg = cache
+                    .taproot_script_spend_signature_hash(idx, &prevouts, leaf_hash, hash_ty)?;
+                Ok(PsbtSighashMsg::TapSighash(tap_sighash_msg))
             }
-        } else {
-            let hash_ty = inp
-                .sighash_type
-                .map(|sighash_type| sighash_type.ecdsa_hash_ty())
-                .unwrap_or(Ok(sighash::EcdsaSighashType::All))
-                .map_err(|_e| SighashError::InvalidSighashType)?;
-            let amt = finalizer::get_utxo(self, idx)
-                .map_err(|_e| SighashError::MissingInputUtxo)?
-                .value;
-            let is_nested_wpkh = inp_spk.is_p2sh()
-                && inp
+            None => {
+                let tap_sighash_msg =
+                    cache.taproot_key_spend_signature_hash(idx, &prevouts, hash_ty)?;
+                Ok(PsbtSighashMsg::TapSighash(tap_sighash_msg))
+            }
+        }
+    } else {
+        let hash_ty = inp
+            .sighash_type
+            .map(|sighash_type| sighash_type.ecdsa_hash_ty())
+            .unwrap_or(Ok(sighash::EcdsaSighashType::All))
+            .map_err(|_e| SighashError::InvalidSighashType)?;
+        let amt = finalizer::get_utxo(psbt, idx)
+            .map_err(|_e| SighashError::MissingInputUtxo)?
+            .value;
+        let is_nested_wpkh = inp_spk.is_p2sh()
+            && inp
+                .redeem_script
+                .as_ref()
+                .map(|x| x.is_p2wpkh())
+                .unwrap_or(false);
+        let is_nested_wsh = inp_spk.is_p2sh()
+            && inp
+                .redeem_script
+                .as_ref()
+                .map(|x| x.is_p2wsh())
+                .unwrap_or(false);
+        if inp_spk.is_p2wpkh() || inp_spk.is_p2wsh() || is_nested_wpkh || is_nested_wsh {
+            let msg = if inp_spk.is_p2wpkh() {
+                cache.p2wpkh_signature_hash(idx, &inp_spk, amt, hash_ty)?
+            } else if is_nested_wpkh {
+                let script_code = inp
                     .redeem_script
                     .as_ref()
-                    .map(|x| x.is_p2wpkh())
-                    .unwrap_or(false);
-            let is_nested_wsh = inp_spk.is_p2sh()
-                && inp
-                    .redeem_script
+                    .expect("redeem script non-empty checked earlier");
+                cache.p2wpkh_signature_hash(idx, script_code, amt, hash_ty)?
+            } else {
+                let witness_script = inp
+                    .witness_script
                     .as_ref()
-                    .map(|x| x.is_p2wsh())
-                    .unwrap_or(false);
-            if inp_spk.is_p2wpkh() || inp_spk.is_p2wsh() || is_nested_wpkh || is_nested_wsh {
-                let msg = if inp_spk.is_p2wpkh() {
-                    cache.p2wpkh_signature_hash(idx, &inp_spk, amt, hash_ty)?
-                } else if is_nested_wpkh {
-                    let script_code = inp
-                        .redeem_script
-                        .as_ref()
-                        .expect("redeem script non-empty checked earlier");
-                    cache.p2wpkh_signature_hash(idx, script_code, amt, hash_ty)?
-                } else {
-                    let witness_script = inp
-                        .witness_script
-                        .as_ref()
-                        .ok_or(SighashError::MissingWitnessScript)?;
-                    cache.p2wsh_signature_hash(idx, witness_script, amt, hash_ty)?
-                };
-                Ok(PsbtSighashMsg::SegwitV0Sighash(msg))
+                    .ok_or(SighashError::MissingWitnessScript)?;
+                cache.p2wsh_signature_hash(idx, witness_script, amt, hash_ty)?
+            };
+            Ok(PsbtSighashMsg::SegwitV0Sighash(msg))
+        } else {
+            // legacy sighash case
+            let script_code = if inp_spk.is_p2sh() {
+                inp.redeem_script
+                    .as_ref()
+                    .ok_or(SighashError::MissingRedeemScript)?
             } else {
-                // legacy sighash case
-                let script_code = if inp_spk.is_p2sh() {
-                    inp.redeem_script
-                        .as_ref()
-                        .ok_or(SighashError::MissingRedeemScript)?
-                } else {
-                    &inp_spk
-                };
-                let msg = cache.legacy_signature_hash(idx, script_code, hash_ty.to_u32())?;
-                Ok(PsbtSighashMsg::LegacySighash(msg))
+                &inp_spk
+            };
+            let msg = cache.legacy_signature_hash(idx, script_code, hash_ty.to_u32())?;
+            Ok(PsbtSighashMsg::LegacySighash(msg))
+        }
+    }
+}
+
+/// Reconstructs a PSBT from a transaction whose inputs are already signed.
+///
+/// Each input is run through the [`Interpreter`](interpreter::Interpreter) and the signatures
+/// and hash preimages revealed by its spend are placed into the corresponding PSBT input,
+/// alongside the scripts and key origins obtained from the descriptor it spends. This is
+/// useful to recover the signing data of a broadcast transaction, for instance to re-sign
+/// parts of it when bumping its fee.
+///
+/// `prevouts` and `descriptors` must contain the spent output and its descriptor for every
+/// input of `tx`, in order. The returned PSBT has empty final scripts; call
+/// [`PsbtExt::finalize_mut`] to fill them in again.
+pub fn from_signed_tx<C: secp256k1::Verification>(
+    secp: &Secp256k1<C>,
+    tx: &bitcoin::Transaction,
+    prevouts: &[bitcoin::TxOut],
+    descriptors: &[Descriptor<DefiniteDescriptorKey>],
+) -> Result<Psbt, FromSignedTxError> {
+    let n_inputs = tx.input.len();
+    if prevouts.len() != n_inputs || descriptors.len() != n_inputs {
+        return Err(FromSignedTxError::WrongInputCount {
+            in_tx: n_inputs,
+            prevouts: prevouts.len(),
+            descriptors: descriptors.len(),
+        });
+    }
+
+    let mut unsigned_tx = tx.clone();
+    for txin in &mut unsigned_tx.input {
+        txin.script_sig = ScriptBuf::new();
+        txin.witness = bitcoin::Witness::new();
+    }
+    let mut psbt =
+        Psbt::from_unsigned_tx(unsigned_tx).expect("script_sig and witness were just cleared");
+
+    let all_prevouts = sighash::Prevouts::All(prevouts);
+    for (index, ((txin, prevout), desc)) in tx
+        .input
+        .iter()
+        .zip(prevouts)
+        .zip(descriptors)
+        .enumerate()
+    {
+        let input = &mut psbt.inputs[index];
+        if desc.desc_type().segwit_version().is_some() {
+            input.witness_utxo = Some(prevout.clone());
+        }
+        let (_, spk_check_passed) =
+            update_item_with_descriptor_helper(input, desc, Some(&prevout.script_pubkey))
+                .map_err(|e| {
+                    FromSignedTxError::Update(UtxoUpdateError::DerivationError(e), index)
+                })?;
+        if !spk_check_passed {
+            return Err(FromSignedTxError::Update(UtxoUpdateError::MismatchedScriptPubkey, index));
+        }
+
+        let interpreter = interpreter::Interpreter::from_txdata(
+            &prevout.script_pubkey,
+            &txin.script_sig,
+            &txin.witness,
+            txin.sequence,
+            tx.lock_time,
+        )
+        .map_err(|e| FromSignedTxError::Interpreter(e, index))?;
+        let revealed = interpreter
+            .iter(secp, tx, index, &all_prevouts)
+            .collect::<Result<interpreter::RevealedData, _>>()
+            .map_err(|e| FromSignedTxError::Interpreter(e, index))?;
+
+        for key_sig in revealed.signatures {
+            match key_sig {
+                interpreter::KeySigPair::Ecdsa(pk, sig) => {
+                    input.partial_sigs.insert(pk, sig);
+                }
+                interpreter::KeySigPair::Schnorr(xpk, sig) => {
+                    if interpreter.is_taproot_v1_key_spend() {
+                        input.tap_key_sig = Some(sig);
+                    } else if let Some(leaf) = interpreter.tap_leaf() {
+                        input.tap_script_sigs.insert((xpk, leaf.leaf_hash()), sig);
+                    }
+                }
+            }
+        }
+        for (hash, preimage) in revealed.preimages {
+            let preimage = preimage.to_vec();
+            match hash {
+                interpreter::HashLockType::Sha256(h) => {
+                    input.sha256_preimages.insert(h, preimage);
+                }
+                interpreter::HashLockType::Hash256(h) => {
+                    // upstream psbt operates on hash256
+                    let h = sha256d::Hash::from_byte_array(h.to_byte_array());
+                    input.hash256_preimages.insert(h, preimage);
+                }
+                interpreter::HashLockType::Hash160(h) => {
+                    input.hash160_preimages.insert(h, preimage);
+                }
+                interpreter::HashLockType::Ripemd160(h) => {
+                    input.ripemd160_preimages.insert(h, preimage);
+                }
             }
         }
     }
+
+    Ok(psbt)
 }
 
 /// Extension trait for PSBT inputs
@@ -1117,7 +1311,7 @@ fn update_item_with_descriptor_helper<F: PsbtFields>(
                 let leaf_script = (leaf_derived.compute_script(), leaf_derived.leaf_version());
                 let tapleaf_hash = TapLeafHash::from_script(&leaf_script.0, leaf_script.1);
                 builder = builder
-                    .add_leaf(leaf_derived.depth(), leaf_script.0.clone())
+                    .add_leaf_with_ver(leaf_derived.depth(), leaf_script.0.clone(), leaf_script.1)
                     .expect("Computing spend data on a valid tree should always succeed");
                 if let Some(tap_scripts) = item.tap_scripts() {
                     let control_block = spend_info
@@ -1126,11 +1320,12 @@ fn update_item_with_descriptor_helper<F: PsbtFields>(
                     tap_scripts.insert(control_block, leaf_script);
                 }
 
-                for (pk_pkh_derived, pk_pkh_xpk) in leaf_derived
-                    .miniscript()
-                    .iter_pk()
-                    .zip(leaf.miniscript().iter_pk())
-                {
+                // Raw leaves have no keys to record origins for
+                let (ms_derived, ms) = match (leaf_derived.miniscript(), leaf.miniscript()) {
+                    (Some(ms_derived), Some(ms)) => (ms_derived, ms),
+                    _ => continue,
+                };
+                for (pk_pkh_derived, pk_pkh_xpk) in ms_derived.iter_pk().zip(ms.iter_pk()) {
                     let (xonly, xpk) = (pk_pkh_derived.to_x_only_pubkey(), pk_pkh_xpk);
 
                     let xpk_full_derivation_path = xpk
@@ -1189,7 +1384,11 @@ fn update_item_with_descriptor_helper<F: PsbtFields>(
         item.bip32_derivation().append(&mut bip32_derivation.0);
 
         match &derived {
-            Descriptor::Bare(_) | Descriptor::Pkh(_) | Descriptor::Wpkh(_) => {}
+            Descriptor::Bare(_)
+            | Descriptor::Pkh(_)
+            | Descriptor::Wpkh(_)
+            | Descriptor::Addr(_)
+            | Descriptor::Raw(_) => {}
             Descriptor::Sh(sh) => match sh.as_inner() {
                 descriptor::ShInner::Wsh(wsh) => {
                     *item.witness_script() = Some(wsh.inner_script());
@@ -1301,6 +1500,51 @@ impl error::Error for OutputUpdateError {
     }
 }
 
+/// Return error type for [`from_signed_tx`]
+#[derive(Debug)]
+pub enum FromSignedTxError {
+    /// The number of prevouts or descriptors did not match the transaction's inputs
+    WrongInputCount {
+        /// Input count in tx
+        in_tx: usize,
+        /// Number of prevouts given
+        prevouts: usize,
+        /// Number of descriptors given
+        descriptors: usize,
+    },
+    /// The descriptor for the input at this index could not be applied to it
+    Update(UtxoUpdateError, usize),
+    /// The spend of the input at this index could not be interpreted
+    Interpreter(interpreter::Error, usize),
+}
+
+impl fmt::Display for FromSignedTxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FromSignedTxError::WrongInputCount { in_tx, prevouts, descriptors } => write!(
+                f,
+                "transaction had {} inputs but {} prevouts and {} descriptors were given",
+                in_tx, prevouts, descriptors
+            ),
+            FromSignedTxError::Update(e, index) => write!(f, "{} at index {}", e, index),
+            FromSignedTxError::Interpreter(e, index) => write!(f, "{} at index {}", e, index),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for FromSignedTxError {
+    fn cause(&self) -> Option<&dyn error::Error> {
+        use self::FromSignedTxError::*;
+
+        match self {
+            WrongInputCount { .. } => None,
+            Update(e, _) => Some(e),
+            Interpreter(e, _) => Some(e),
+        }
+    }
+}
+
 /// Return error type for [`PsbtExt::sighash_msg`]
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum SighashError {
@@ -1523,6 +1767,37 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_update_item_tr_with_raw_leaf() {
+        // keys taken from: https://github.com/bitcoin/bips/blob/master/bip-0086.mediawiki#Specifications
+        let root_xpub = Xpub::from_str("xpub661MyMwAqRbcFkPHucMnrGNzDwb6teAX1RbKQmqtEF8kK3Z7LZ59qafCjB9eCRLiTVG3uxBxgKvRgbubRhqSKXnGGb1aoaqLrpMBDrVxga8").unwrap();
+        let fingerprint = root_xpub.fingerprint();
+        let xpub = format!("[{}/86'/0'/0']xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ", fingerprint);
+        let desc = format!("tr({}/0/0,{{raw_leaf(51,c2),pk({}/0/1)}})", xpub, xpub);
+
+        let desc = Descriptor::from_str(&desc).unwrap();
+        let mut psbt_input = psbt::Input::default();
+        psbt_input.update_with_descriptor_unchecked(&desc).unwrap();
+        let mut psbt_output = psbt::Output::default();
+        psbt_output.update_with_descriptor_unchecked(&desc).unwrap();
+
+        // The raw leaf is committed to with its version, but has no keys.
+        let raw_leaf = (ScriptBuf::from(vec![0x51]), LeafVersion::from_consensus(0xc2).unwrap());
+        assert_eq!(psbt_input.tap_scripts.len(), 2);
+        assert!(psbt_input.tap_scripts.values().any(|leaf| *leaf == raw_leaf));
+        assert_eq!(psbt_input.tap_key_origins.len(), 2);
+
+        let merkle_root = match desc {
+            Descriptor::Tr(ref tr) => tr.spend_info().merkle_root(),
+            _ => unreachable!(),
+        };
+        assert_eq!(psbt_input.tap_merkle_root, merkle_root);
+        let tap_tree = psbt_output.tap_tree.unwrap();
+        assert!(tap_tree
+            .script_leaves()
+            .any(|leaf| (leaf.script().to_owned(), leaf.version()) == raw_leaf));
+    }
+
     #[test]
     fn test_update_item_non_tr_multi() {
         // values taken from https://github.com/bitcoin/bips/blob/master/bip-0084.mediawiki (after removing zpub thingy)
@@ -1690,4 +1965,209 @@ mod tests {
             "output script_pubkey no longer matches"
         );
     }
+
+    #[test]
+    fn test_from_signed_tx() {
+        let secp = Secp256k1::new();
+        let sk = bitcoin::secp256k1::SecretKey::from_slice(&[1; 32]).unwrap();
+        let pk = bitcoin::PublicKey::new(PublicKey::from_secret_key(&secp, &sk));
+        let preimage = [0xab; 32];
+        let hash = bitcoin::hashes::sha256::Hash::hash(&preimage);
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "wsh(and_v(v:pk({}),sha256({})))",
+            pk, hash
+        ))
+        .unwrap();
+        let witness_script = desc.explicit_script().unwrap();
+        let prevout = TxOut { value: Amount::from_sat(10_000), script_pubkey: desc.script_pubkey() };
+
+        let mut tx = bitcoin::Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: vec![TxOut { value: Amount::from_sat(9_000), script_pubkey: ScriptBuf::new() }],
+        };
+        let msg = SighashCache::new(&tx)
+            .p2wsh_signature_hash(
+                0,
+                &witness_script,
+                prevout.value,
+                bitcoin::EcdsaSighashType::All,
+            )
+            .unwrap();
+        let sig = bitcoin::ecdsa::Signature::sighash_all(
+            secp.sign_ecdsa(&bitcoin::secp256k1::Message::from(msg), &sk),
+        );
+        tx.input[0].witness = bitcoin::Witness::from_slice(&[
+            preimage.to_vec(),
+            sig.to_vec(),
+            witness_script.to_bytes(),
+        ]);
+
+        assert!(matches!(
+            from_signed_tx(&secp, &tx, &[], core::slice::from_ref(&desc)),
+            Err(FromSignedTxError::WrongInputCount { in_tx: 1, prevouts: 0, descriptors: 1 })
+        ));
+
+        let mut psbt = from_signed_tx(
+            &secp,
+            &tx,
+            core::slice::from_ref(&prevout),
+            core::slice::from_ref(&desc),
+        )
+        .unwrap();
+        assert!(psbt.unsigned_tx.input[0].witness.is_empty());
+        assert_eq!(psbt.inputs[0].witness_utxo, Some(prevout));
+        assert_eq!(psbt.inputs[0].witness_script, Some(witness_script));
+        assert_eq!(psbt.inputs[0].partial_sigs.get(&pk), Some(&sig));
+        assert_eq!(psbt.inputs[0].sha256_preimages.get(&hash), Some(&preimage.to_vec()));
+
+        psbt.finalize_mut(&secp).unwrap();
+        assert_eq!(psbt.extract(&secp).unwrap(), tx);
+    }
+
+    #[test]
+    fn test_finalize_tr_multi_leaf() {
+        let secp = Secp256k1::new();
+        let keypair = |b: u8| bitcoin::key::Keypair::from_seckey_slice(&secp, &[b; 32]).unwrap();
+        let (key_a, key_b, internal) = (keypair(1), keypair(2), keypair(3));
+        let (pk_a, pk_b) = (key_a.x_only_public_key().0, key_b.x_only_public_key().0);
+        // Both leaves share a key, which is only known by its hash in the second one.
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "tr({},{{pk({}),and_v(v:pkh({}),pk({}))}})",
+            internal.x_only_public_key().0,
+            pk_a,
+            pk_a,
+            pk_b
+        ))
+        .unwrap();
+        let leaf_hashes = match desc {
+            Descriptor::Tr(ref tr) => tr
+                .leaves()
+                .map(|leaf| {
+                    TapLeafHash::from_script(&leaf.compute_script(), LeafVersion::TapScript)
+                })
+                .collect::<Vec<_>>(),
+            _ => unreachable!(),
+        };
+        let utxo = TxOut { value: Amount::from_sat(10_000), script_pubkey: desc.script_pubkey() };
+        let tx = bitcoin::Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: vec![TxOut { value: Amount::from_sat(9_000), script_pubkey: ScriptBuf::new() }],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo = Some(utxo.clone());
+        psbt.update_input_with_descriptor(0, &desc).unwrap();
+        let sign = |psbt: &mut Psbt, keypair: &bitcoin::key::Keypair, leaf_hash: TapLeafHash| {
+            let msg = SighashCache::new(&psbt.unsigned_tx)
+                .taproot_script_spend_signature_hash(
+                    0,
+                    &sighash::Prevouts::All(core::slice::from_ref(&utxo)),
+                    leaf_hash,
+                    sighash::TapSighashType::Default,
+                )
+                .unwrap();
+            let sig = taproot::Signature {
+                signature: secp.sign_schnorr(&secp256k1::Message::from(msg), keypair),
+                sighash_type: sighash::TapSighashType::Default,
+            };
+            let pk = keypair.x_only_public_key().0;
+            psbt.inputs[0].tap_script_sigs.insert((pk, leaf_hash), sig);
+        };
+        let finalized_leaf = |psbt: &Psbt| {
+            let mut psbt = psbt.clone();
+            psbt.finalize_mut(&secp).unwrap();
+            let witness = psbt.inputs[0].final_script_witness.as_ref().unwrap();
+            let script = witness.taproot_leaf_script().unwrap().script;
+            TapLeafHash::from_script(script, LeafVersion::TapScript)
+        };
+
+        // Only the second leaf is signed for, so the signature of the shared
+        // key is looked up under its leaf hash.
+        sign(&mut psbt, &key_a, leaf_hashes[1]);
+        sign(&mut psbt, &key_b, leaf_hashes[1]);
+        assert_eq!(finalized_leaf(&psbt), leaf_hashes[1]);
+        // The key of the `pkh` fragment is also found from the signatures.
+        let mut no_origins = psbt.clone();
+        no_origins.inputs[0].tap_key_origins.clear();
+        assert_eq!(finalized_leaf(&no_origins), leaf_hashes[1]);
+
+        // With both leaves signed for, the cheapest is used.
+        sign(&mut psbt, &key_a, leaf_hashes[0]);
+        assert_eq!(finalized_leaf(&psbt), leaf_hashes[0]);
+    }
+
+    #[test]
+    fn test_finalize_mixed_sighash_types() {
+        let secp = Secp256k1::new();
+        let keypair = |b: u8| bitcoin::key::Keypair::from_seckey_slice(&secp, &[b; 32]).unwrap();
+        let (key_a, key_b, internal) = (keypair(1), keypair(2), keypair(3));
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "tr({},multi_a(2,{},{}))",
+            internal.x_only_public_key().0,
+            key_a.x_only_public_key().0,
+            key_b.x_only_public_key().0
+        ))
+        .unwrap();
+        let leaf_hash = desc.tap_tree_iter().next().unwrap().compute_tap_leaf_hash();
+        let utxo = TxOut { value: Amount::from_sat(10_000), script_pubkey: desc.script_pubkey() };
+        let tx = bitcoin::Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: vec![TxOut { value: Amount::from_sat(9_000), script_pubkey: ScriptBuf::new() }],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo = Some(utxo.clone());
+        psbt.update_input_with_descriptor(0, &desc).unwrap();
+        // The second signer only commits to its own input and output.
+        for (keypair, sighash_type) in [
+            (&key_a, sighash::TapSighashType::Default),
+            (&key_b, sighash::TapSighashType::SinglePlusAnyoneCanPay),
+        ] {
+            let msg = SighashCache::new(&psbt.unsigned_tx)
+                .taproot_script_spend_signature_hash(
+                    0,
+                    &sighash::Prevouts::All(core::slice::from_ref(&utxo)),
+                    leaf_hash,
+                    sighash_type,
+                )
+                .unwrap();
+            let sig = taproot::Signature {
+                signature: secp.sign_schnorr(&secp256k1::Message::from(msg), keypair),
+                sighash_type,
+            };
+            let pk = keypair.x_only_public_key().0;
+            psbt.inputs[0].tap_script_sigs.insert((pk, leaf_hash), sig);
+        }
+
+        // The explicit sighash type takes an extra byte.
+        let plan = desc.plan(&PsbtInputSatisfier::new(&psbt, 0)).unwrap();
+        let sigs = plan
+            .witness_template()
+            .iter()
+            .filter_map(|item| match item {
+                crate::miniscript::satisfy::Placeholder::SchnorrSigPk(_, _, size) => Some(*size),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(sigs, vec![65, 64]);
+
+        let mut finalized = psbt.clone();
+        finalized.finalize_mut(&secp).unwrap();
+        let witness = finalized.inputs[0].final_script_witness.as_ref().unwrap();
+        assert_eq!(witness.iter().take(2).map(<[u8]>::len).collect::<Vec<_>>(), sigs);
+
+        // Signatures must all use the sighash type of the input if it has one.
+        psbt.inputs[0].sighash_type = Some(sighash::TapSighashType::Default.into());
+        match finalize_mall(&mut psbt, &secp) {
+            Err(Error::InputError(InputError::WrongTapSighashFlag { got, pubkey, .. }, 0)) => {
+                assert_eq!(got, sighash::TapSighashType::SinglePlusAnyoneCanPay);
+                assert_eq!(pubkey, Some(key_b.x_only_public_key().0));
+            }
+            e => panic!("unexpected result {:?}", e),
+        }
+    }
 }
@@ -0,0 +1,448 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! External Signers
+//!
+//! This module lets signers which hold their keys outside of the wallet, such
+//! as hardware wallets bridged through HWI, take part in signing and
+//! finalizing PSBTs. Such signers are identified by the fingerprint of their
+//! master key, and sign the inputs spending keys derived from it.
+//!
+
+use core::fmt;
+#[cfg(feature = "std")]
+use std::error;
+
+#[cfg(any(test, feature = "test-utils"))]
+use bitcoin::bip32::{self, Xpriv};
+use bitcoin::bip32::{DerivationPath, Fingerprint, KeySource, Xpub};
+use bitcoin::psbt::Psbt;
+#[cfg(any(test, feature = "test-utils"))]
+use bitcoin::psbt::SignError;
+use bitcoin::secp256k1::{self, Secp256k1};
+use bitcoin::{Address, Network};
+
+use super::{Error, PsbtExt};
+use crate::descriptor::{DescriptorXKey, Wildcard};
+use crate::plan::Assets;
+use crate::prelude::*;
+#[cfg(any(test, feature = "test-utils"))]
+use crate::ToPublicKey;
+use crate::{DefiniteDescriptorKey, Descriptor, DescriptorPublicKey, ForEachKey};
+
+/// A signer holding its keys outside of the wallet, such as a hardware wallet
+///
+/// The methods mirror the commands of hardware wallet interfaces such as HWI,
+/// so that a bridge to a device only has to implement them to be used with
+/// [`sign_psbt`], [`sign_and_finalize`], [`display_address`],
+/// [`signer_key`] and [`signer_assets`].
+pub trait ExternalSigner {
+    /// The error returned by the signer
+    type Error;
+
+    /// The fingerprint of the signer's master key
+    fn fingerprint(&self) -> Fingerprint;
+
+    /// Whether the signer holds the key derived at `origin`
+    ///
+    /// By default, this is the case for all the keys derived from the master
+    /// key of the signer.
+    fn owns_key(&self, origin: &KeySource) -> bool { origin.0 == self.fingerprint() }
+
+    /// The extended public key derived from the signer's master key at `path`
+    fn xpub(&self, path: &DerivationPath) -> Result<Xpub, Self::Error>;
+
+    /// Adds the signatures of the signer's keys to the input of `psbt` at `index`
+    fn sign_input(&self, psbt: &mut Psbt, index: usize) -> Result<(), Self::Error>;
+
+    /// Displays the address of `descriptor` on the signer for the user to
+    /// check it, and returns the displayed address
+    fn display_address(
+        &self,
+        descriptor: &Descriptor<DefiniteDescriptorKey>,
+        network: Network,
+    ) -> Result<Address, Self::Error>;
+}
+
+/// Error when using an [`ExternalSigner`]
+#[derive(Debug)]
+pub enum ExternalSignerError<E> {
+    /// The signer returned an error
+    Signer(E),
+    /// The descriptor has no address
+    Descriptor(crate::Error),
+    /// The signer displayed another address than the one of the descriptor
+    AddressMismatch {
+        /// The address of the descriptor
+        expected: Address,
+        /// The address displayed by the signer
+        displayed: Address,
+    },
+    /// The signed PSBT could not be finalized
+    Finalize(Vec<Error>),
+}
+
+impl<E: fmt::Display> fmt::Display for ExternalSignerError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ExternalSignerError::Signer(ref e) => write!(f, "external signer error: {}", e),
+            ExternalSignerError::Descriptor(ref e) => e.fmt(f),
+            ExternalSignerError::AddressMismatch { ref expected, ref displayed } => write!(
+                f,
+                "signer displayed address {} instead of {}",
+                displayed, expected
+            ),
+            ExternalSignerError::Finalize(ref errs) => {
+                f.write_str("could not finalize the signed psbt")?;
+                for e in errs {
+                    write!(f, ": {}", e)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: fmt::Debug + fmt::Display> error::Error for ExternalSignerError<E> {
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match self {
+            ExternalSignerError::Descriptor(e) => Some(e),
+            ExternalSignerError::Signer(_)
+            | ExternalSignerError::AddressMismatch { .. }
+            | ExternalSignerError::Finalize(_) => None,
+        }
+    }
+}
+
+/// Signs all the inputs of `psbt` spending a key owned by `signer`
+///
+/// Inputs are recognized from the key origins in their `bip32_derivation` and
+/// `tap_key_origins`, as filled by [`PsbtExt::update_input_with_descriptor`].
+/// Returns the indices of the inputs passed to the signer.
+pub fn sign_psbt<S: ExternalSigner>(
+    psbt: &mut Psbt,
+    signer: &S,
+) -> Result<Vec<usize>, ExternalSignerError<S::Error>> {
+    let mut signed = vec![];
+    for index in 0..psbt.inputs.len() {
+        let input = &psbt.inputs[index];
+        let owned = input
+            .bip32_derivation
+            .values()
+            .chain(input.tap_key_origins.values().map(|(_, origin)| origin))
+            .any(|origin| signer.owns_key(origin));
+        if owned {
+            signer
+                .sign_input(psbt, index)
+                .map_err(ExternalSignerError::Signer)?;
+            signed.push(index);
+        }
+    }
+    Ok(signed)
+}
+
+/// Signs `psbt` with `signer` and finalizes it
+///
+/// See [`sign_psbt`] for the inputs passed to the signer.
+pub fn sign_and_finalize<S, C>(
+    psbt: &mut Psbt,
+    signer: &S,
+    secp: &Secp256k1<C>,
+) -> Result<(), ExternalSignerError<S::Error>>
+where
+    S: ExternalSigner,
+    C: secp256k1::Verification,
+{
+    sign_psbt(psbt, signer)?;
+    psbt.finalize_mut(secp)
+        .map_err(ExternalSignerError::Finalize)
+}
+
+/// Displays the address of `descriptor` on `signer`, checking that it is the
+/// address of the descriptor
+pub fn display_address<S: ExternalSigner>(
+    signer: &S,
+    descriptor: &Descriptor<DefiniteDescriptorKey>,
+    network: Network,
+) -> Result<Address, ExternalSignerError<S::Error>> {
+    let expected = descriptor
+        .address(network)
+        .map_err(ExternalSignerError::Descriptor)?;
+    let displayed = signer
+        .display_address(descriptor, network)
+        .map_err(ExternalSignerError::Signer)?;
+    if displayed == expected {
+        Ok(displayed)
+    } else {
+        Err(ExternalSignerError::AddressMismatch { expected, displayed })
+    }
+}
+
+/// The key of `signer` derived at `path`, with its origin, ready to be used
+/// in a descriptor
+///
+/// The key has no wildcard; use [`DescriptorPublicKey::XPub`] directly to
+/// build ranged keys.
+pub fn signer_key<S: ExternalSigner>(
+    signer: &S,
+    path: &DerivationPath,
+) -> Result<DescriptorPublicKey, ExternalSignerError<S::Error>> {
+    let xkey = signer.xpub(path).map_err(ExternalSignerError::Signer)?;
+    Ok(DescriptorPublicKey::XPub(DescriptorXKey {
+        origin: Some((signer.fingerprint(), path.clone())),
+        xkey,
+        derivation_path: DerivationPath::master(),
+        wildcard: Wildcard::None,
+    }))
+}
+
+/// The keys of `descriptor` owned by `signer`, as [`Assets`] for planning the
+/// spends the signer can sign for
+pub fn signer_assets<S: ExternalSigner>(
+    signer: &S,
+    descriptor: &Descriptor<DescriptorPublicKey>,
+) -> Assets {
+    let mut keys = vec![];
+    descriptor.for_each_key(|key| {
+        let fingerprint = key.master_fingerprint();
+        if key
+            .full_derivation_paths()
+            .into_iter()
+            .any(|path| signer.owns_key(&(fingerprint, path)))
+        {
+            keys.push(key.clone());
+        }
+        true
+    });
+    Assets::new().add(keys)
+}
+
+#[cfg(any(test, feature = "test-utils"))]
+/// An [`ExternalSigner`] signing with an extended private key, for tests
+///
+/// It behaves like a hardware wallet: it signs with any key derived from its
+/// master key, and refuses to display addresses of descriptors whose keys
+/// claim to be derived from its master key but are not.
+#[derive(Debug, Clone)]
+pub struct MockSigner {
+    xpriv: Xpriv,
+    secp: Secp256k1<secp256k1::All>,
+}
+
+#[cfg(any(test, feature = "test-utils"))]
+impl MockSigner {
+    /// Creates a signer with the master key `xpriv`
+    pub fn new(xpriv: Xpriv) -> Self { MockSigner { xpriv, secp: Secp256k1::new() } }
+}
+
+#[cfg(any(test, feature = "test-utils"))]
+/// Error returned by a [`MockSigner`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MockSignerError {
+    /// Deriving a key failed
+    Bip32(bip32::Error),
+    /// Signing the input failed
+    Sign(SignError),
+    /// The PSBT has no input at this index
+    MissingInput(usize),
+    /// A key claims to be derived from the signer's master key, but isn't
+    UnknownKey(DefiniteDescriptorKey),
+    /// The descriptor has no address
+    NoAddress,
+}
+
+#[cfg(any(test, feature = "test-utils"))]
+impl fmt::Display for MockSignerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MockSignerError::Bip32(ref e) => e.fmt(f),
+            MockSignerError::Sign(ref e) => e.fmt(f),
+            MockSignerError::MissingInput(index) => write!(f, "psbt has no input {}", index),
+            MockSignerError::UnknownKey(ref key) => write!(f, "key {} is not the signer's", key),
+            MockSignerError::NoAddress => f.write_str("descriptor has no address"),
+        }
+    }
+}
+
+#[cfg(all(feature = "std", any(test, feature = "test-utils")))]
+impl error::Error for MockSignerError {
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match self {
+            MockSignerError::Bip32(e) => Some(e),
+            MockSignerError::Sign(e) => Some(e),
+            MockSignerError::MissingInput(_)
+            | MockSignerError::UnknownKey(_)
+            | MockSignerError::NoAddress => None,
+        }
+    }
+}
+
+#[cfg(any(test, feature = "test-utils"))]
+impl ExternalSigner for MockSigner {
+    type Error = MockSignerError;
+
+    fn fingerprint(&self) -> Fingerprint { self.xpriv.fingerprint(&self.secp) }
+
+    fn xpub(&self, path: &DerivationPath) -> Result<Xpub, Self::Error> {
+        let xpriv = self
+            .xpriv
+            .derive_priv(&self.secp, path)
+            .map_err(MockSignerError::Bip32)?;
+        Ok(Xpub::from_priv(&self.secp, &xpriv))
+    }
+
+    fn sign_input(&self, psbt: &mut Psbt, index: usize) -> Result<(), Self::Error> {
+        if index >= psbt.inputs.len() {
+            return Err(MockSignerError::MissingInput(index));
+        }
+        // Sign a copy, so that only the requested input is modified
+        let mut signed = psbt.clone();
+        if let Err((_, mut errors)) = signed.sign(&self.xpriv, &self.secp) {
+            if let Some(e) = errors.remove(&index) {
+                return Err(MockSignerError::Sign(e));
+            }
+        }
+        psbt.inputs[index] = signed.inputs.swap_remove(index);
+        Ok(())
+    }
+
+    fn display_address(
+        &self,
+        descriptor: &Descriptor<DefiniteDescriptorKey>,
+        network: Network,
+    ) -> Result<Address, Self::Error> {
+        let fingerprint = self.fingerprint();
+        let mut unknown = None;
+        descriptor.for_each_key(|key| {
+            if key.master_fingerprint() != fingerprint {
+                return true;
+            }
+            let owned = key.full_derivation_paths().iter().all(|path| {
+                self.xpub(path).map_or(false, |xpub| {
+                    xpub.public_key.x_only_public_key().0 == key.to_x_only_pubkey()
+                })
+            });
+            if !owned {
+                unknown = Some(key.clone());
+            }
+            owned
+        });
+        match unknown {
+            Some(key) => Err(MockSignerError::UnknownKey(key)),
+            None => descriptor
+                .address(network)
+                .map_err(|_| MockSignerError::NoAddress),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use bitcoin::{absolute, transaction, Amount, ScriptBuf, Transaction, TxIn, TxOut};
+
+    use super::*;
+
+    fn signer(seed: u8) -> MockSigner {
+        MockSigner::new(Xpriv::new_master(Network::Testnet, &[seed; 32]).unwrap())
+    }
+
+    fn psbt_spending(descriptors: &[&Descriptor<DefiniteDescriptorKey>]) -> Psbt {
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn::default(); descriptors.len()],
+            output: vec![TxOut { value: Amount::from_sat(9_000), script_pubkey: ScriptBuf::new() }],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        for (index, desc) in descriptors.iter().enumerate() {
+            let script_pubkey = desc.script_pubkey();
+            let utxo = TxOut { value: Amount::from_sat(10_000), script_pubkey };
+            psbt.inputs[index].witness_utxo = Some(utxo);
+            psbt.update_input_with_descriptor(index, desc).unwrap();
+        }
+        psbt
+    }
+
+    #[test]
+    fn sign_with_external_signer() {
+        let secp = Secp256k1::verification_only();
+        let (ours, theirs) = (signer(1), signer(2));
+        let path = DerivationPath::from_str("m/86'/1'/0'").unwrap();
+        let key = signer_key(&ours, &path).unwrap();
+        let other = signer_key(&theirs, &path).unwrap();
+        assert_eq!(key.master_fingerprint(), ours.fingerprint());
+
+        let ranged = |desc: &str| {
+            Descriptor::<DescriptorPublicKey>::from_str(&desc.replace("K", &key.to_string()))
+                .unwrap()
+        };
+        let wpkh = ranged("wpkh(K/0/*)").at_derivation_index(3).unwrap();
+        let tr = ranged("tr(K/1/*)").at_derivation_index(5).unwrap();
+        let foreign = Descriptor::<DefiniteDescriptorKey>::from_str(&format!("wpkh({})", other))
+            .unwrap();
+
+        // Only the inputs spending keys of the signer are signed
+        let mut psbt = psbt_spending(&[&wpkh, &foreign, &tr]);
+        assert_eq!(sign_psbt(&mut psbt, &ours).unwrap(), vec![0, 2]);
+        assert_eq!(psbt.inputs[0].partial_sigs.len(), 1);
+        assert!(psbt.inputs[1].partial_sigs.is_empty());
+        assert!(psbt.inputs[2].tap_key_sig.is_some());
+        assert!(matches!(
+            sign_and_finalize(&mut psbt.clone(), &ours, &secp),
+            Err(ExternalSignerError::Finalize(_))
+        ));
+        sign_and_finalize(&mut psbt, &theirs, &secp).unwrap();
+        assert!(psbt
+            .inputs
+            .iter()
+            .all(|input| input.final_script_witness.is_some()));
+
+        // Its keys are available for planning
+        let shared = ranged(&format!("wsh(or_d(pk({}),pk(K/1/*)))", other));
+        let assets = signer_assets(&ours, &shared);
+        assert_eq!(assets.keys.len(), 1);
+        let plan = shared.at_derivation_index(0).unwrap().plan(&assets).unwrap();
+        assert_eq!(plan.witness_template().len(), 2);
+    }
+
+    #[test]
+    fn display_address_on_external_signer() {
+        let ours = signer(1);
+        let path = DerivationPath::from_str("m/84'/1'/0'").unwrap();
+        let key = signer_key(&ours, &path).unwrap();
+        let desc = Descriptor::<DescriptorPublicKey>::from_str(&format!("wpkh({}/0/*)", key))
+            .unwrap()
+            .at_derivation_index(7)
+            .unwrap();
+        assert_eq!(
+            display_address(&ours, &desc, Network::Testnet).unwrap(),
+            desc.address(Network::Testnet).unwrap()
+        );
+
+        // A key pretending to be derived from the signer's master key is refused
+        let fake = match signer_key(&signer(2), &path).unwrap() {
+            DescriptorPublicKey::XPub(xkey) => DescriptorPublicKey::XPub(DescriptorXKey {
+                origin: Some((ours.fingerprint(), path.clone())),
+                ..xkey
+            }),
+            _ => unreachable!(),
+        };
+        let fake = Descriptor::<DescriptorPublicKey>::from_str(&format!("wpkh({}/0/*)", fake))
+            .unwrap()
+            .at_derivation_index(7)
+            .unwrap();
+        assert!(matches!(
+            display_address(&ours, &fake, Network::Testnet),
+            Err(ExternalSignerError::Signer(MockSignerError::UnknownKey(_)))
+        ));
+
+        // Not all descriptors have an address
+        let bare = Descriptor::<DefiniteDescriptorKey>::from_str(&format!("pk({})", key)).unwrap();
+        assert!(matches!(
+            display_address(&ours, &bare, Network::Testnet),
+            Err(ExternalSignerError::Descriptor(_))
+        ));
+    }
+}
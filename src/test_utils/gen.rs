@@ -0,0 +1,474 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Grammar-based generators and shrinkers
+
+use core::fmt;
+use core::ops::Range;
+
+use bitcoin::hex::DisplayHex as _;
+use bitcoin::secp256k1::XOnlyPublicKey;
+
+use crate::expression::{Tree, TreeIterItem};
+use crate::miniscript::context::SigType;
+use crate::prelude::*;
+use crate::{Descriptor, FromStrKey, Miniscript, ScriptContext, Segwitv0, Tap};
+
+/// Number of attempts made to generate a value satisfying a constraint
+/// before falling back to a trivial value.
+const MAX_ATTEMPTS: usize = 100;
+
+/// A small, seedable pseudorandom number generator (SplitMix64).
+///
+/// This is not suitable for generating secrets. It exists so that generated
+/// test cases are reproducible from a single `u64` seed without depending on
+/// any particular version of an external RNG crate.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TestRng(u64);
+
+impl TestRng {
+    /// Constructs a new generator from a seed.
+    pub fn new(seed: u64) -> Self { TestRng(seed) }
+
+    /// Returns the next 64 random bits.
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number uniformly-ish distributed in the given range, which must be nonempty.
+    pub fn range(&mut self, range: Range<u64>) -> u64 {
+        assert!(range.start < range.end, "empty range");
+        range.start + self.next_u64() % (range.end - range.start)
+    }
+
+    /// Returns an index less than `n`, which must be nonzero.
+    pub fn below(&mut self, n: usize) -> usize { self.range(0..n as u64) as usize }
+
+    fn bytes32(&mut self) -> [u8; 32] {
+        let mut ret = [0; 32];
+        for chunk in ret.chunks_mut(8) {
+            chunk.copy_from_slice(&self.next_u64().to_le_bytes());
+        }
+        ret
+    }
+}
+
+/// Parameters controlling the size of generated values.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct GenParams {
+    /// The maximum nesting depth of generated fragments.
+    pub max_depth: usize,
+    /// The maximum number of children of a generated node, or keys in a multisig.
+    pub max_children: usize,
+}
+
+impl Default for GenParams {
+    fn default() -> Self { GenParams { max_depth: 3, max_children: 3 } }
+}
+
+/// Generates a random well-formed expression string, which can be parsed
+/// by [`Tree::from_str`] but is not necessarily a valid descriptor.
+pub fn expression(rng: &mut TestRng, params: GenParams) -> String {
+    fn gen(rng: &mut TestRng, depth: usize, params: GenParams, out: &mut String) {
+        const NAME_CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789_";
+        for _ in 0..1 + rng.below(6) {
+            out.push(char::from(NAME_CHARS[rng.below(NAME_CHARS.len())]));
+        }
+        if depth < params.max_depth && rng.below(2) == 0 {
+            let (open, close) = if rng.below(4) == 0 {
+                ('{', '}')
+            } else {
+                ('(', ')')
+            };
+            out.push(open);
+            for i in 0..1 + rng.below(params.max_children.max(1)) {
+                if i > 0 {
+                    out.push(',');
+                }
+                gen(rng, depth + 1, params, out);
+            }
+            out.push(close);
+        }
+    }
+
+    let mut ret = String::new();
+    gen(rng, 0, params, &mut ret);
+    ret
+}
+
+/// Generates fragments of a Miniscript according to its type system.
+///
+/// The grammar used here only produces well-typed Miniscripts: `b` produces a
+/// fragment of type B, and `du` one of type B which also has the properties d
+/// and u, which several combinators require of their children.
+struct MsGen<'r, Ctx> {
+    rng: &'r mut TestRng,
+    params: GenParams,
+    keys: Vec<String>,
+    _ctx: core::marker::PhantomData<Ctx>,
+}
+
+impl<'r, Ctx: ScriptContext> MsGen<'r, Ctx> {
+    fn new(rng: &'r mut TestRng, params: GenParams) -> Self {
+        MsGen { rng, params, keys: vec![], _ctx: core::marker::PhantomData }
+    }
+
+    /// Generates a fresh compressed public key, distinct from all previous ones.
+    fn key(&mut self) -> String {
+        loop {
+            let bytes = self.rng.bytes32();
+            if XOnlyPublicKey::from_slice(&bytes).is_ok() {
+                let key = format!("02{}", bytes[..].to_lower_hex_string());
+                if !self.keys.contains(&key) {
+                    self.keys.push(key.clone());
+                    return key;
+                }
+            }
+        }
+    }
+
+    fn hash(&mut self, len: usize) -> String { self.rng.bytes32()[..len].to_lower_hex_string() }
+
+    fn n_children(&mut self) -> usize { 1 + self.rng.below(self.params.max_children.max(1)) }
+
+    fn du_leaf(&mut self) -> String {
+        match self.rng.below(6) {
+            0 => format!("pk({})", self.key()),
+            1 => format!("pkh({})", self.key()),
+            2 => {
+                let n = self.n_children();
+                let k = 1 + self.rng.below(n);
+                let name = if Ctx::sig_type() == SigType::Schnorr {
+                    "multi_a"
+                } else {
+                    "multi"
+                };
+                let mut ret = format!("{}({}", name, k);
+                for _ in 0..n {
+                    ret.push(',');
+                    ret.push_str(&self.key());
+                }
+                ret.push(')');
+                ret
+            }
+            3 => format!("sha256({})", self.hash(32)),
+            4 => format!("hash256({})", self.hash(32)),
+            _ => format!("hash160({})", self.hash(20)),
+        }
+    }
+
+    fn du(&mut self, depth: usize) -> String {
+        if depth >= self.params.max_depth {
+            return self.du_leaf();
+        }
+        match self.rng.below(6) {
+            0 => format!("or_d({},{})", self.du(depth + 1), self.du(depth + 1)),
+            1 => format!("and_b({},a:{})", self.du(depth + 1), self.du(depth + 1)),
+            2 => format!("or_b({},a:{})", self.du(depth + 1), self.du(depth + 1)),
+            3 => format!("or_i({},{})", self.du(depth + 1), self.du(depth + 1)),
+            4 => {
+                let n = 1 + self.n_children();
+                let k = 1 + self.rng.below(n);
+                let mut ret = format!("thresh({},{}", k, self.du(depth + 1));
+                for _ in 1..n {
+                    ret.push_str(",a:");
+                    ret.push_str(&self.du(depth + 1));
+                }
+                ret.push(')');
+                ret
+            }
+            _ => self.du_leaf(),
+        }
+    }
+
+    fn b(&mut self, depth: usize) -> String {
+        if depth >= self.params.max_depth {
+            return match self.rng.below(4) {
+                0 => format!("older({})", self.rng.range(1..0x10000)),
+                1 => format!("after({})", self.rng.range(1..500_000_000)),
+                _ => self.du_leaf(),
+            };
+        }
+        match self.rng.below(6) {
+            0 => format!("and_v(v:{},{})", self.b(depth + 1), self.b(depth + 1)),
+            1 => format!("or_i({},{})", self.b(depth + 1), self.b(depth + 1)),
+            2 => {
+                format!("andor({},{},{})", self.du(depth + 1), self.b(depth + 1), self.b(depth + 1))
+            }
+            3 => format!("or_d({},{})", self.du(depth + 1), self.b(depth + 1)),
+            4 => self.du(depth),
+            _ => self.b(self.params.max_depth),
+        }
+    }
+}
+
+/// Generates a random well-typed Miniscript, which may not pass the sanity
+/// checks applied by [`core::str::FromStr`].
+///
+/// Keys are generated as hex-encoded compressed public keys, so `Pk` must be
+/// parseable from those.
+pub fn miniscript<Pk: FromStrKey, Ctx: ScriptContext>(
+    rng: &mut TestRng,
+    params: GenParams,
+) -> Miniscript<Pk, Ctx> {
+    let mut gen = MsGen::<Ctx>::new(rng, params);
+    for _ in 0..MAX_ATTEMPTS {
+        // The grammar is well-typed, but the result may still exceed the
+        // resource limits of the context.
+        if let Ok(ms) = Miniscript::from_str_insane(&gen.b(0)) {
+            return ms;
+        }
+    }
+    let key = gen.key();
+    Miniscript::from_str_insane(&format!("pk({})", key)).expect("valid miniscript")
+}
+
+/// Generates a random Miniscript which passes all sanity checks.
+///
+/// Keys are generated as hex-encoded compressed public keys, so `Pk` must be
+/// parseable from those.
+pub fn sane_miniscript<Pk: FromStrKey, Ctx: ScriptContext>(
+    rng: &mut TestRng,
+    params: GenParams,
+) -> Miniscript<Pk, Ctx> {
+    sane_miniscript_str::<Pk, Ctx>(&mut MsGen::new(rng, params))
+        .parse()
+        .expect("checked by sane_miniscript_str")
+}
+
+fn sane_miniscript_str<Pk: FromStrKey, Ctx: ScriptContext>(gen: &mut MsGen<Ctx>) -> String {
+    for _ in 0..MAX_ATTEMPTS {
+        let s = gen.b(0);
+        if s.parse::<Miniscript<Pk, Ctx>>().is_ok() {
+            return s;
+        }
+    }
+    format!("pk({})", gen.key())
+}
+
+/// Generates a random descriptor, of any type other than bare.
+///
+/// Keys are generated as hex-encoded compressed public keys, so `Pk` must be
+/// parseable from those.
+pub fn descriptor<Pk: FromStrKey>(rng: &mut TestRng, params: GenParams) -> Descriptor<Pk> {
+    fn tap_tree<Pk: FromStrKey>(gen: &mut MsGen<Tap>, depth: usize) -> String {
+        if depth < gen.params.max_depth && gen.rng.below(2) == 0 {
+            let left = tap_tree::<Pk>(gen, depth + 1);
+            let right = tap_tree::<Pk>(gen, depth + 1);
+            format!("{{{},{}}}", left, right)
+        } else {
+            sane_miniscript_str::<Pk, Tap>(gen)
+        }
+    }
+
+    let choice = rng.below(7);
+    let s = if choice < 5 {
+        let mut gen = MsGen::<Segwitv0>::new(rng, params);
+        match choice {
+            0 => format!("pkh({})", gen.key()),
+            1 => format!("wpkh({})", gen.key()),
+            2 => format!("sh(wpkh({}))", gen.key()),
+            3 => format!("wsh({})", sane_miniscript_str::<Pk, Segwitv0>(&mut gen)),
+            _ => format!("sh(wsh({}))", sane_miniscript_str::<Pk, Segwitv0>(&mut gen)),
+        }
+    } else {
+        let mut gen = MsGen::<Tap>::new(rng, params);
+        let internal_key = gen.key();
+        if choice == 5 {
+            format!("tr({})", internal_key)
+        } else {
+            format!("tr({},{})", internal_key, tap_tree::<Pk>(&mut gen, 0))
+        }
+    };
+    s.parse().expect("generated descriptor is valid")
+}
+
+/// Returns the byte range of a node, including its children, in the string
+/// it was parsed from.
+fn node_span(node: TreeIterItem) -> Range<usize> {
+    let start = node.name_pos();
+    let mut end_node = node;
+    let mut n_close_parens = 0;
+    while let Some(last) = end_node.children().last() {
+        end_node = last;
+        n_close_parens += 1;
+    }
+    start..end_node.name_pos() + end_node.name().len() + n_close_parens
+}
+
+/// Returns simpler variants of an expression string.
+///
+/// Each candidate is formed either by replacing a node with one of its
+/// children, or by deleting one child of a node with several. The result
+/// is always a well-formed expression, though it may no longer be a valid
+/// descriptor; the typed shrinkers filter these out.
+pub fn shrink_expression(s: &str) -> Vec<String> {
+    // Ignore any checksum, which would be invalidated anyway.
+    let s = s.split('#').next().unwrap_or(s);
+    let tree = match Tree::from_str(s) {
+        Ok(tree) => tree,
+        Err(_) => return vec![],
+    };
+
+    let mut ret = vec![];
+    for node in tree.root().pre_order_iter() {
+        let span = node_span(node);
+        let children = node.children().collect::<Vec<_>>();
+        for child in &children {
+            let child_span = node_span(*child);
+            ret.push(format!("{}{}{}", &s[..span.start], &s[child_span], &s[span.end..]));
+        }
+        if children.len() > 1 {
+            for (i, child) in children.iter().enumerate() {
+                let child_span = node_span(*child);
+                // Delete the child along with one adjacent comma.
+                let cut = if i == 0 {
+                    child_span.start..child_span.end + 1
+                } else {
+                    child_span.start - 1..child_span.end
+                };
+                ret.push(format!("{}{}", &s[..cut.start], &s[cut.end..]));
+            }
+        }
+    }
+    ret
+}
+
+/// Returns simpler Miniscripts derived from `ms`, which remain well-typed
+/// (but which may not pass the sanity checks).
+pub fn shrink_miniscript<Pk: FromStrKey, Ctx: ScriptContext>(
+    ms: &Miniscript<Pk, Ctx>,
+) -> Vec<Miniscript<Pk, Ctx>> {
+    shrink_expression(&ms.to_string())
+        .iter()
+        .filter_map(|s| Miniscript::from_str_insane(s).ok())
+        .collect()
+}
+
+/// Returns simpler descriptors derived from `desc`.
+pub fn shrink_descriptor<Pk: FromStrKey>(desc: &Descriptor<Pk>) -> Vec<Descriptor<Pk>> {
+    // The alternate format omits the checksum, which would be invalidated.
+    shrink_expression(&format!("{:#}", desc))
+        .iter()
+        .filter_map(|s| s.parse().ok())
+        .collect()
+}
+
+/// Checks that a property holds for randomly-generated values.
+///
+/// Generates `cases` values from `seed` using `generate`, and checks that
+/// `property` holds for each. If it fails, the failing value is repeatedly
+/// replaced by the first of its `shrink` candidates which still fails, and
+/// the function panics with the smallest failing value found, along with the
+/// seed needed to reproduce it.
+pub fn check<T, G, S, P>(seed: u64, cases: usize, mut generate: G, shrink: S, property: P)
+where
+    T: fmt::Display,
+    G: FnMut(&mut TestRng) -> T,
+    S: Fn(&T) -> Vec<T>,
+    P: Fn(&T) -> bool,
+{
+    let mut rng = TestRng::new(seed);
+    for case in 0..cases {
+        let value = generate(&mut rng);
+        if property(&value) {
+            continue;
+        }
+
+        let mut minimal = value;
+        while let Some(smaller) = shrink(&minimal).into_iter().find(|x| !property(x)) {
+            minimal = smaller;
+        }
+        panic!(
+            "property failed (seed {}, case {}); minimal failing value: {}",
+            seed, case, minimal
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Legacy;
+
+    #[test]
+    fn generated_values_are_valid() {
+        let mut rng = TestRng::new(0);
+        let params = GenParams::default();
+        for _ in 0..50 {
+            let s = expression(&mut rng, params);
+            Tree::from_str(&s).unwrap();
+
+            let ms = miniscript::<bitcoin::PublicKey, Segwitv0>(&mut rng, params);
+            assert!(ms.ty.corr.base == crate::miniscript::types::Base::B);
+            miniscript::<bitcoin::PublicKey, Tap>(&mut rng, params);
+            miniscript::<bitcoin::PublicKey, Legacy>(&mut rng, params);
+
+            sane_miniscript::<bitcoin::PublicKey, Segwitv0>(&mut rng, params)
+                .sanity_check()
+                .unwrap();
+            sane_miniscript::<String, Tap>(&mut rng, params)
+                .sanity_check()
+                .unwrap();
+
+            let desc = descriptor::<crate::DescriptorPublicKey>(&mut rng, params);
+            desc.sanity_check().unwrap();
+        }
+    }
+
+    #[test]
+    fn shrink() {
+        assert_eq!(
+            shrink_expression("a(b(c),d)#checksum"),
+            vec!["b(c)", "d", "a(d)", "a(b(c))", "a(c,d)"],
+        );
+
+        let ms = "and_v(v:pk(A),or_d(pk(B),older(10)))"
+            .parse::<Miniscript<String, Segwitv0>>()
+            .unwrap();
+        let shrunk = shrink_miniscript(&ms)
+            .iter()
+            .map(|ms| ms.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            shrunk,
+            vec![
+                "or_d(pk(B),older(10))",
+                "and_v(v:pk(A),pk(B))",
+                "and_v(v:pk(A),older(10))"
+            ]
+        );
+    }
+
+    #[test]
+    fn check_shrinks_to_minimal() {
+        // Holds for everything.
+        check(
+            1,
+            100,
+            |rng| descriptor::<String>(rng, GenParams::default()),
+            shrink_descriptor,
+            |desc| desc.sanity_check().is_ok(),
+        );
+
+        // Fails, and can be shrunk down to a single fragment.
+        let result = std::panic::catch_unwind(|| {
+            check(
+                1,
+                1,
+                |_| {
+                    "or_d(pk(A),and_v(v:pk(B),sha256(H)))"
+                        .parse::<Miniscript<String, Segwitv0>>()
+                        .unwrap()
+                },
+                shrink_miniscript,
+                |ms| !ms.to_string().contains("sha256"),
+            )
+        });
+        let msg = *result.unwrap_err().downcast::<String>().unwrap();
+        assert_eq!(msg, "property failed (seed 1, case 0); minimal failing value: sha256(H)");
+    }
+}
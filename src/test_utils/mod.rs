@@ -0,0 +1,23 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Testing Utilities
+//!
+//! Random generators for expression trees, Miniscripts and descriptors, along
+//! with a small property-testing driver which shrinks failing cases. These are
+//! intended for downstream crates which want to property-test their handling
+//! of descriptors, and are available with the `test-utils` feature.
+//!
+//! All generation is driven by a seedable [`TestRng`], so failures can be
+//! reproduced exactly.
+//!
+
+mod gen;
+#[cfg(test)]
+mod translate;
+
+pub use self::gen::{
+    check, descriptor, expression, miniscript, sane_miniscript, shrink_descriptor,
+    shrink_expression, shrink_miniscript, GenParams, TestRng,
+};
+#[cfg(test)]
+pub(crate) use self::translate::{StrKeyTranslator, StrXOnlyKeyTranslator};
@@ -1,6 +1,6 @@
 // SPDX-License-Identifier: CC0-1.0
 
-//! Generally useful utilities for test scripts
+//! Key translators for unit tests
 
 use core::convert::Infallible;
 use std::collections::HashMap;
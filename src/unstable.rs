@@ -0,0 +1,279 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Unstable Fragments
+//!
+//! Experimental Miniscript fragments using the opcodes of proposed soft forks,
+//! intended for research only. **None of these opcodes are part of the Bitcoin
+//! consensus rules**: in Tapscript, `OP_CAT` is an `OP_SUCCESS` opcode and
+//! `OP_CHECKTEMPLATEVERIFY` is `OP_NOP4`, so outputs using these fragments can
+//! be spent by anyone. Their syntax, semantics and encoding may change, or be
+//! removed, in any release.
+//!
+//! The fragments are provided by the [`Unstable`] extension, and are meant to
+//! be used with the [`Experimental`] context, which follows the rules of
+//! [`crate::Tap`]:
+//!
+//! * `cat_verify(X)` is `OP_CAT <X> OP_EQUALVERIFY`, where `X` is 32 bytes of hex,
+//!   and requires two stack elements which concatenate to `X`.
+//! * `ctv(H)` is `<H> OP_CHECKTEMPLATEVERIFY`, which requires the spending
+//!   transaction to have the BIP 119 template hash `H`.
+//!
+//! Since any split of `X` satisfies `cat_verify`, its satisfactions are
+//! malleable, so scripts using it must be parsed as insane. The
+//! [`crate::interpreter`] does not support these fragments.
+//!
+
+use core::fmt;
+
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::hex::{DisplayHex, FromHex};
+use bitcoin::secp256k1::XOnlyPublicKey;
+use bitcoin::{opcodes, script};
+
+use crate::expression::TreeIterItem;
+use crate::miniscript::lex::{Token, TokenIter};
+use crate::miniscript::satisfy::{Placeholder, Satisfaction, Witness};
+use crate::miniscript::types::extra_props::{OpLimits, TimelockInfo};
+use crate::miniscript::types::{Base, Correctness, Dissat, ExtData, Input, Malleability, Type};
+use crate::plan::AssetProvider;
+use crate::prelude::*;
+use crate::{ContextParams, CustomCtx, Error, Extension, ParseError, ToPublicKey};
+
+/// The parameters of the [`Experimental`] context.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ExperimentalParams {}
+
+impl ContextParams for ExperimentalParams {
+    type Key = XOnlyPublicKey;
+    const NAME: &'static str = "Experimental";
+}
+
+/// An experimental Tapscript context for the [`Unstable`] fragments.
+///
+/// This is not a consensus context; see the [module documentation](self).
+pub type Experimental = CustomCtx<ExperimentalParams>;
+
+/// The unstable fragments.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum Unstable {
+    /// `cat_verify(X)`
+    CatVerify([u8; 32]),
+    /// `ctv(H)`
+    Ctv(sha256::Hash),
+}
+
+impl fmt::Display for Unstable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Unstable::CatVerify(ref x) => write!(f, "cat_verify({})", x.as_hex()),
+            Unstable::Ctv(ref h) => write!(f, "ctv({})", h),
+        }
+    }
+}
+
+/// Takes the given tokens from the end of the stream, returning the data of
+/// the `Bytes32` token among them, or `None` without consuming anything if
+/// they do not match.
+fn take_tokens<'s>(tokens: &mut TokenIter<'s>, expected: &[Token]) -> Option<&'s [u8]> {
+    let mut taken = Vec::with_capacity(expected.len());
+    let mut data = None;
+    for exp in expected {
+        match (tokens.next(), exp) {
+            (Some(tok @ Token::Bytes32(bytes)), Token::Bytes32(..)) => {
+                data = Some(bytes);
+                taken.push(tok);
+            }
+            (Some(tok), exp) if tok == *exp => taken.push(tok),
+            (tok, _) => {
+                for tok in tok.into_iter().chain(taken.into_iter().rev()) {
+                    tokens.un_next(tok);
+                }
+                return None;
+            }
+        }
+    }
+    data
+}
+
+impl Extension for Unstable {
+    fn type_check(&self) -> Type {
+        match *self {
+            Unstable::CatVerify(..) => Type {
+                corr: Correctness {
+                    base: Base::V,
+                    input: Input::Any,
+                    dissatisfiable: false,
+                    unit: false,
+                },
+                mall: Malleability { dissat: Dissat::None, safe: false, non_malleable: false },
+            },
+            Unstable::Ctv(..) => Type {
+                corr: Correctness {
+                    base: Base::B,
+                    input: Input::Zero,
+                    dissatisfiable: false,
+                    unit: false,
+                },
+                mall: Malleability { dissat: Dissat::None, safe: false, non_malleable: true },
+            },
+        }
+    }
+
+    fn extra_props(&self) -> ExtData {
+        match *self {
+            Unstable::CatVerify(..) => ExtData {
+                pk_cost: 1 + 33 + 1,
+                has_free_verify: false,
+                ops: OpLimits::new(2, Some(0), None),
+                stack_elem_count_sat: Some(2),
+                stack_elem_count_dissat: None,
+                max_sat_size: Some((34, 34)),
+                max_dissat_size: None,
+                timelock_info: TimelockInfo::new(),
+                exec_stack_elem_count_sat: Some(2), // <a> <b>, or <a||b> <X>
+                exec_stack_elem_count_dissat: None,
+                tree_height: 0,
+            },
+            Unstable::Ctv(..) => ExtData {
+                pk_cost: 33 + 1,
+                has_free_verify: false,
+                ops: OpLimits::new(1, Some(0), None),
+                stack_elem_count_sat: Some(0),
+                stack_elem_count_dissat: None,
+                max_sat_size: Some((0, 0)),
+                max_dissat_size: None,
+                timelock_info: TimelockInfo::new(),
+                exec_stack_elem_count_sat: Some(1),
+                exec_stack_elem_count_dissat: None,
+                tree_height: 0,
+            },
+        }
+    }
+
+    fn from_name_tree(name: &str, node: TreeIterItem) -> Option<Result<Self, Error>> {
+        let ret = match name {
+            "cat_verify" => node
+                .verify_n_children("cat_verify", 1..=1)
+                .map_err(From::from)
+                .and_then(|_| {
+                    <[u8; 32]>::from_hex(node.first_child().unwrap().name())
+                        .map_err(ParseError::box_from_str)
+                })
+                .map(Unstable::CatVerify),
+            "ctv" => node
+                .verify_terminal_parent("ctv", "template hash")
+                .map(Unstable::Ctv),
+            _ => return None,
+        };
+        Some(ret.map_err(Error::Parse))
+    }
+
+    fn push_to_builder(&self, builder: script::Builder) -> script::Builder {
+        match *self {
+            Unstable::CatVerify(ref x) => builder
+                .push_opcode(opcodes::all::OP_CAT)
+                .push_slice(x)
+                .push_opcode(opcodes::all::OP_EQUALVERIFY),
+            Unstable::Ctv(ref h) => builder
+                .push_slice(h.as_byte_array())
+                .push_opcode(opcodes::all::OP_NOP4),
+        }
+    }
+
+    fn from_token_iter(tokens: &mut TokenIter) -> Option<Self> {
+        let data = [0; 32];
+        if let Some(x) = take_tokens(
+            tokens,
+            &[
+                Token::Verify,
+                Token::Equal,
+                Token::Bytes32(&data),
+                Token::Cat,
+            ],
+        ) {
+            return Some(Unstable::CatVerify(x.try_into().expect("32 bytes")));
+        }
+        take_tokens(tokens, &[Token::CheckTemplateVerify, Token::Bytes32(&data)])
+            .map(|h| Unstable::Ctv(sha256::Hash::from_slice(h).expect("32 bytes")))
+    }
+
+    fn satisfy<Pk, P>(&self, _: &P) -> Satisfaction<Placeholder<Pk>>
+    where
+        Pk: ToPublicKey,
+        P: AssetProvider<Pk>,
+    {
+        let stack = match *self {
+            Unstable::CatVerify(ref x) => {
+                Witness::Stack(vec![Placeholder::Data(x.to_vec()), Placeholder::PushZero])
+            }
+            Unstable::Ctv(..) => Witness::Stack(vec![]),
+        };
+        Satisfaction { stack, has_sig: false, relative_timelock: None, absolute_timelock: None }
+    }
+
+    fn dissatisfy<Pk, P>(&self, _: &P) -> Satisfaction<Placeholder<Pk>>
+    where
+        Pk: ToPublicKey,
+        P: AssetProvider<Pk>,
+    {
+        Satisfaction {
+            stack: Witness::Impossible,
+            has_sig: false,
+            relative_timelock: None,
+            absolute_timelock: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use super::*;
+    use crate::Miniscript;
+
+    type ExpMs = Miniscript<XOnlyPublicKey, Experimental, Unstable>;
+
+    const X: &str = "cc8a4bc64d897bddc5fbc2f670f7a8ba0b386779106cf1223c6fc5d7cd6fc115";
+    const H: &str = "0101010101010101010101010101010101010101010101010101010101010101";
+
+    #[test]
+    fn parse_display() {
+        let s = format!("and_v(cat_verify({}),or_i(ctv({}),pk({})))", H, H, X);
+        let ms = ExpMs::from_str_insane(&s).unwrap();
+        assert_eq!(ms.to_string(), s);
+        // cat_verify is malleable
+        assert!(ExpMs::from_str(&s).is_err());
+        let s = format!("and_v(v:pk({}),ctv({}))", X, H);
+        ExpMs::from_str(&s).unwrap().sanity_check().unwrap();
+
+        assert!(ExpMs::from_str_insane("cat_verify(00)").is_err());
+        assert!(ExpMs::from_str_insane(&format!("ctv({},{})", H, H)).is_err());
+        assert!(ExpMs::from_str_insane(&format!("v:ctv({})", H)).is_err());
+    }
+
+    #[test]
+    fn encode_decode() {
+        let ms = ExpMs::from_str_insane(&format!("ctv({})", H)).unwrap();
+        assert_eq!(ms.encode().to_hex_string(), format!("20{}b3", H));
+
+        let ms = ExpMs::from_str_insane(&format!("and_v(cat_verify({}),pk({}))", H, X)).unwrap();
+        let script = ms.encode();
+        assert_eq!(&script.to_hex_string()[..70], format!("7e20{}88", H));
+        assert_eq!(script.len(), ms.script_size());
+        assert_eq!(ExpMs::parse_insane(&script).unwrap(), ms);
+
+        let ms =
+            ExpMs::from_str_insane(&format!("or_d(pk({}),and_v(cat_verify({}),ctv({})))", X, H, H))
+                .unwrap();
+        assert_eq!(ExpMs::parse_insane(&ms.encode()).unwrap(), ms);
+    }
+
+    #[test]
+    fn satisfy() {
+        let ms = ExpMs::from_str_insane(&format!("and_v(cat_verify({}),ctv({}))", H, H)).unwrap();
+        let witness = ms.satisfy(()).unwrap();
+        assert_eq!(witness, vec![Vec::<u8>::from_hex(H).unwrap(), vec![]]);
+        assert_eq!(ms.max_satisfaction_size().unwrap(), 34);
+    }
+}
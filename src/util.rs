@@ -1,16 +1,24 @@
 // SPDX-License-Identifier: CC0-1.0
 
+//! Utilities
+//!
+//! Conversions between witness stacks and the push-only scriptSigs carrying
+//! the same stack in legacy spends.
+//!
+
 use core::convert::TryFrom;
+use core::mem;
 
-use bitcoin::constants::MAX_SCRIPT_ELEMENT_SIZE;
 use bitcoin::hashes::Hash;
-use bitcoin::script::{self, PushBytes, ScriptBuf};
-use bitcoin::PubkeyHash;
+use bitcoin::script::{self, PushBytes, Script, ScriptBuf};
+use bitcoin::opcodes::{Class, ClassifyContext};
+use bitcoin::{opcodes, Opcode, PubkeyHash};
 
 use crate::miniscript::context;
 use crate::miniscript::satisfy::Placeholder;
 use crate::prelude::*;
-use crate::{MiniscriptKey, ScriptContext, ToPublicKey};
+use crate::{Error, MiniscriptKey, ScriptContext, ToPublicKey};
+
 pub(crate) fn varint_len(n: usize) -> usize { bitcoin::VarInt(n as u64).size() }
 
 pub(crate) trait ItemSize {
@@ -22,8 +30,12 @@ impl<Pk: MiniscriptKey> ItemSize for Placeholder<Pk> {
         match self {
             Placeholder::Pubkey(_, size) => *size,
             Placeholder::PubkeyHash(_, size) => *size,
-            Placeholder::EcdsaSigPk(_) | Placeholder::EcdsaSigPkHash(_) => 73,
-            Placeholder::SchnorrSigPk(_, _, size) | Placeholder::SchnorrSigPkHash(_, _, size) => {
+            Placeholder::EcdsaSigPk(_)
+            | Placeholder::EcdsaSigPkHash(_)
+            | Placeholder::EcdsaAdaptorSigPk(..) => 73,
+            Placeholder::SchnorrSigPk(_, _, size)
+            | Placeholder::SchnorrSigPkHash(_, _, size)
+            | Placeholder::SchnorrAdaptorSigPk(_, _, _, size) => {
                 size + 1
             } // +1 for the OP_PUSH
             Placeholder::HashDissatisfaction
@@ -33,8 +45,12 @@ impl<Pk: MiniscriptKey> ItemSize for Placeholder<Pk> {
             | Placeholder::Hash160Preimage(_) => 33,
             Placeholder::PushOne => 2, // On legacy this should be 1 ?
             Placeholder::PushZero => 1,
-            Placeholder::TapScript(s) => s.len(),
-            Placeholder::TapControlBlock(cb) => cb.serialize().len(),
+            Placeholder::Data(data) => varint_len(data.len()) + data.len(),
+            Placeholder::TapScript(s) => varint_len(s.len()) + s.len(),
+            Placeholder::TapControlBlock(cb) => {
+                let size = cb.serialize().len();
+                varint_len(size) + size
+            }
         }
     }
 }
@@ -48,24 +64,84 @@ pub(crate) fn witness_size<T: ItemSize>(wit: &[T]) -> usize {
     wit.iter().map(T::size).sum::<usize>() + varint_len(wit.len())
 }
 
-pub(crate) fn witness_to_scriptsig(witness: &[Vec<u8>]) -> ScriptBuf {
+/// Replaces the script appended to `script` from byte `start` onward with
+/// its P2SH script pubkey.
+pub(crate) fn wrap_in_p2sh(script: &mut ScriptBuf, start: usize) {
+    let hash = Script::from_bytes(&script.as_bytes()[start..]).script_hash();
+    truncate_script(script, start);
+    script.push_opcode(opcodes::all::OP_HASH160);
+    script.push_slice(hash);
+    script.push_opcode(opcodes::all::OP_EQUAL);
+}
+
+/// Replaces the script appended to `script` from byte `start` onward with
+/// its P2WSH script pubkey.
+pub(crate) fn wrap_in_p2wsh(script: &mut ScriptBuf, start: usize) {
+    let hash = Script::from_bytes(&script.as_bytes()[start..]).wscript_hash();
+    truncate_script(script, start);
+    script.push_opcode(opcodes::OP_0);
+    script.push_slice(hash);
+}
+
+fn truncate_script(script: &mut ScriptBuf, len: usize) {
+    let mut bytes = mem::take(script).into_bytes();
+    bytes.truncate(len);
+    *script = ScriptBuf::from_bytes(bytes);
+}
+
+/// Converts a stack, given bottom element first as in a witness, into the
+/// push-only scriptSig leaving it on the stack
+///
+/// Every element is pushed minimally, as required by the `MINIMALDATA`
+/// standardness rule: elements which are minimally-encoded numbers, including
+/// the empty element, are pushed with the number opcodes when possible, and
+/// other elements with the smallest push opcode for their length.
+///
+/// The conversion does not check consensus limits on the size of the
+/// elements, so elements larger than 520 bytes result in an invalid script.
+///
+/// This is the inverse of [`scriptsig_to_witness`].
+pub fn witness_to_scriptsig(witness: &[Vec<u8>]) -> ScriptBuf {
     let mut b = script::Builder::new();
-    for (i, wit) in witness.iter().enumerate() {
+    for wit in witness {
         if let Ok(n) = script::read_scriptint(wit) {
             b = b.push_int(n);
         } else {
-            if i != witness.len() - 1 {
-                assert!(wit.len() < 73, "All pushes in miniscript are < 73 bytes");
-            } else {
-                assert!(wit.len() <= MAX_SCRIPT_ELEMENT_SIZE, "P2SH redeem script is <= 520 bytes");
-            }
-            let push = <&PushBytes>::try_from(wit.as_slice()).expect("checked above");
+            let push = <&PushBytes>::try_from(wit.as_slice())
+                .expect("script elements are less than 4GiB");
             b = b.push_slice(push)
         }
     }
     b.into_script()
 }
 
+/// Converts a push-only scriptSig into the stack it leaves, bottom element
+/// first as in a witness
+///
+/// Number opcodes are converted to the minimal encoding of their number, as
+/// when executing the script. Fails if the scriptSig contains an opcode
+/// which is not a push, or a push which is not minimal.
+///
+/// This is the inverse of [`witness_to_scriptsig`].
+pub fn scriptsig_to_witness(script_sig: &Script) -> Result<Vec<Vec<u8>>, Error> {
+    script_sig
+        .instructions_minimal()
+        .map(|ins| match ins.map_err(Error::Script)? {
+            script::Instruction::PushBytes(push) => Ok(push.as_bytes().to_vec()),
+            script::Instruction::Op(op) => pushed_number(op).ok_or(Error::InvalidOpcode(op)),
+        })
+        .collect()
+}
+
+/// The minimal encoding of the number pushed by `op`, if it is a number opcode
+pub(crate) fn pushed_number(op: Opcode) -> Option<Vec<u8>> {
+    match op.classify(ClassifyContext::Legacy) {
+        Class::PushNum(-1) => Some(vec![0x81]),
+        Class::PushNum(n) => Some(vec![n as u8]),
+        _ => None,
+    }
+}
+
 // trait for pushing key that depend on context
 pub(crate) trait MsKeyBuilder {
     /// Serialize the key as bytes based on script context. Used when encoding miniscript into bitcoin script
@@ -89,7 +165,21 @@ impl MsKeyBuilder for script::Builder {
     {
         match Ctx::sig_type() {
             context::SigType::Ecdsa => self.push_key(&key.to_public_key()),
-            context::SigType::Schnorr => self.push_slice(key.to_x_only_pubkey().serialize()),
+            context::SigType::Schnorr => {
+                #[cfg(feature = "apo")]
+                match key.apo_kind() {
+                    Some(crate::apo::ApoKind::Explicit) => {
+                        let mut ser = [0x01; 33];
+                        ser[1..].copy_from_slice(&key.to_x_only_pubkey().serialize());
+                        return self.push_slice(ser);
+                    }
+                    Some(crate::apo::ApoKind::Internal) => {
+                        return self.push_opcode(bitcoin::opcodes::all::OP_PUSHNUM_1)
+                    }
+                    None => {}
+                }
+                self.push_slice(key.to_x_only_pubkey().serialize())
+            }
         }
     }
 
@@ -106,3 +196,49 @@ impl MsKeyBuilder for script::Builder {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scriptsig_witness_roundtrip() {
+        let witness = vec![
+            vec![],
+            vec![0x01],
+            vec![0x10],
+            vec![0x81],
+            vec![0x11],
+            vec![0x00],
+            vec![0x01, 0x00],
+            vec![0xab; 72],
+            vec![0xcd; 300],
+        ];
+        let script_sig = witness_to_scriptsig(&witness);
+        let expected = script::Builder::new()
+            .push_opcode(opcodes::OP_0)
+            .push_opcode(opcodes::all::OP_PUSHNUM_1)
+            .push_opcode(opcodes::all::OP_PUSHNUM_16)
+            .push_opcode(opcodes::all::OP_PUSHNUM_NEG1)
+            .push_slice([0x11])
+            .push_slice([0x00])
+            .push_slice([0x01, 0x00])
+            .push_slice([0xab; 72])
+            .push_slice(<&PushBytes>::try_from(&[0xcd; 300][..]).unwrap())
+            .into_script();
+        assert_eq!(script_sig, expected);
+        assert_eq!(scriptsig_to_witness(&script_sig).unwrap(), witness);
+
+        // Only minimal pushes are accepted
+        let script_sig = ScriptBuf::from_bytes(vec![0x01, 0x01]);
+        assert!(matches!(scriptsig_to_witness(&script_sig), Err(Error::Script(_))));
+        let script_sig = script::Builder::new()
+            .push_slice([0xaa])
+            .push_opcode(opcodes::all::OP_DROP)
+            .into_script();
+        assert!(matches!(
+            scriptsig_to_witness(&script_sig),
+            Err(Error::InvalidOpcode(opcodes::all::OP_DROP))
+        ));
+    }
+}